@@ -0,0 +1,146 @@
+use typst_ts_core::vector::{
+    diff::{diff, DiffOptions, PageDiff},
+    stats::stats,
+};
+
+#[cfg(feature = "pdf")]
+use crate::ArtifactToPdfArgs;
+use crate::{
+    utils::{self, UnwrapOrExit},
+    ArtifactDiffArgs, ArtifactDiffFormat, ArtifactStatsArgs, ArtifactStatsFormat,
+};
+
+/// Compare two artifacts and print the result.
+///
+/// Exits with failure if the artifacts differ.
+pub fn diff_artifacts(args: ArtifactDiffArgs) -> ! {
+    let left = std::fs::read(&args.left).unwrap_or_exit();
+    let right = std::fs::read(&args.right).unwrap_or_exit();
+
+    let opts = DiffOptions {
+        ignore_metadata: args.ignore_metadata,
+    };
+    let res = diff(&left, &right, &opts).unwrap_or_exit();
+
+    match args.format {
+        ArtifactDiffFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&res).unwrap_or_exit());
+        }
+        ArtifactDiffFormat::Summary => {
+            for (i, page) in res.pages.iter().enumerate() {
+                let page_no = i + 1;
+                match page {
+                    PageDiff::Identical => println!("page {page_no}: identical"),
+                    PageDiff::Differing {
+                        added,
+                        removed,
+                        changed,
+                        regions,
+                    } => println!(
+                        "page {page_no}: differing, {added} added, {removed} removed, {changed} changed in {} regions",
+                        regions.len()
+                    ),
+                    PageDiff::OnlyInLeft => println!("page {page_no}: only in {}", args.left.display()),
+                    PageDiff::OnlyInRight => {
+                        println!("page {page_no}: only in {}", args.right.display())
+                    }
+                }
+            }
+        }
+    }
+
+    utils::logical_exit(res.is_identical())
+}
+
+/// Print a size breakdown of an artifact.
+pub fn artifact_stats(args: ArtifactStatsArgs) -> ! {
+    let artifact = std::fs::read(&args.file).unwrap_or_exit();
+    let res = stats(&artifact, args.top).unwrap_or_exit();
+
+    match args.format {
+        ArtifactStatsFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&res).unwrap_or_exit());
+        }
+        ArtifactStatsFormat::Table => {
+            println!("{:<40} {:>12}", "total", res.total_bytes);
+
+            println!("\n{:<28} {:>11} {:>12}", "section", "count", "bytes");
+            for section in &res.sections {
+                println!(
+                    "{:<28} {:>11} {:>12}",
+                    section.name, section.count, section.bytes
+                );
+            }
+
+            if !res.fonts.is_empty() {
+                println!("\n{:<28} {:>11} {:>12}", "font", "glyphs", "bytes");
+                for font in &res.fonts {
+                    println!("{:<28} {:>11} {:>12}", font.family, font.glyphs, font.bytes);
+                }
+            }
+
+            if !res.images.is_empty() {
+                println!(
+                    "\n{:<28} {:>11} {:>7} {:>12}",
+                    "image", "size", "pages", "bytes"
+                );
+                for image in &res.images {
+                    let size = format!("{}x{} {}", image.width, image.height, image.format);
+                    println!(
+                        "{:<28} {:>11} {:>7} {:>12}",
+                        image.id, size, image.pages, image.bytes
+                    );
+                }
+            }
+
+            println!("\n{:<28} {:>11} {:>12}", "page", "items", "bytes");
+            for page in &res.pages {
+                println!(
+                    "{:<28} {:>11} {:>12}",
+                    page.index + 1,
+                    page.items,
+                    page.bytes
+                );
+            }
+            println!("{:<40} {:>12}", "text", res.text_bytes);
+
+            if !res.largest.is_empty() {
+                println!("\n{:<28} {:>11} {:>12}", "largest", "kind", "bytes");
+                for resource in &res.largest {
+                    let kind = format!("{:?}", resource.kind).to_lowercase();
+                    println!("{:<28} {:>11} {:>12}", resource.id, kind, resource.bytes);
+                }
+            }
+        }
+    }
+
+    utils::logical_exit(true)
+}
+
+/// Convert an artifact to PDF, embedding the fonts found among the embedded,
+/// the system and the given fonts, and printing the features dropped by the
+/// conversion.
+#[cfg(feature = "pdf")]
+pub fn artifact_to_pdf(args: ArtifactToPdfArgs) -> ! {
+    use std::{borrow::Cow, path::Path};
+
+    use typst_ts_compiler::TypstSystemWorld;
+    use typst_ts_core::config::{compiler::EntryOpts, CompileOpts};
+    use typst_ts_pdf_exporter::artifact::{artifact_to_pdf_with_fonts, world_fonts};
+
+    let artifact = std::fs::read(&args.input).unwrap_or_exit();
+    let world = TypstSystemWorld::new(CompileOpts {
+        entry: EntryOpts::new_workspace(Path::new("-").into()),
+        font_paths: args.font.paths,
+        with_embedded_fonts: crate::font::fonts().map(Cow::Borrowed).collect(),
+        ..CompileOpts::default()
+    })
+    .unwrap_or_exit();
+    let res = artifact_to_pdf_with_fonts(&artifact, world_fonts(&world)).unwrap_or_exit();
+    for warning in &res.warnings {
+        eprintln!("warning: {warning}");
+    }
+    std::fs::write(&args.output, res.pdf).unwrap_or_exit();
+
+    utils::logical_exit(true)
+}
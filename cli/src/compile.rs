@@ -1,6 +1,9 @@
 use std::borrow::Cow;
 use std::io::{self, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::FixedOffset;
 
 use typst::diag::{FileError, FileResult};
 use typst::foundations::{Bytes, Dict, IntoValue};
@@ -8,57 +11,41 @@ use typst::model::Document;
 use typst_ts_compiler::service::EntryManager;
 use typst_ts_compiler::ShadowApi;
 use typst_ts_compiler::{
+    cache::DiskCache,
+    font::system::LazyFontResolver,
+    images::ImageLimits,
     service::{
-        features::{FeatureSet, DIAG_FMT_FEATURE},
-        CompileActor, CompileDriver, CompileExporter, DynamicLayoutCompiler,
+        deps::{DepGraphExporter, DepGraphFormat},
+        features::{FeatureSet, DIAG_FMT_FEATURE, FAIL_ON_WARNINGS_FEATURE},
+        limits::CompileLimits,
+        manifest::ManifestExporter,
+        observer::{ActorObserver, CompileEnd},
+        project::{ProjectConfig, ProjectState},
+        session_log::SessionLog,
+        CompileActor, CompileDriver, CompileDriverBuilder, CompileExporter, DynamicLayoutCompiler,
     },
-    TypstSystemWorld,
 };
-use typst_ts_core::config::compiler::{EntryOpts, MEMORY_MAIN_ENTRY};
-use typst_ts_core::{config::CompileOpts, exporter_builtins::GroupExporter, path::PathClean};
+use typst_ts_core::config::compiler::MEMORY_MAIN_ENTRY;
+use typst_ts_core::exporter_builtins::GroupExporter;
 
 use crate::font::fonts;
-use crate::utils::current_dir;
+use crate::utils::{current_dir, make_absolute_from};
 use crate::{
-    tracing::TraceGuard,
-    utils::{self, UnwrapOrExit},
-    CompileArgs, CompileOnceArgs,
+    export::output_files, tracing::TraceGuard, utils, CompileArgs, CompileOnceArgs, DepsFormat,
 };
 
 pub fn create_driver(args: CompileOnceArgs) -> CompileDriver {
-    let workspace_dir = Path::new(args.workspace.as_str()).clean();
-    let entry = args.entry;
-    let entry_file_path = Path::new(entry.as_str()).clean();
-
-    let workspace_dir = if workspace_dir.is_absolute() {
-        workspace_dir
-    } else {
-        let cwd = std::env::current_dir().unwrap_or_exit();
-        cwd.join(workspace_dir)
-    };
-
-    let workspace_dir = workspace_dir.clean();
-
-    let is_stdin = entry == "-";
-    let entry_file_path = if is_stdin || entry_file_path.is_absolute() {
-        entry_file_path
-    } else {
-        let cwd = std::env::current_dir().unwrap_or_exit();
-        cwd.join(entry_file_path)
-    };
-
-    let entry_file_path = entry_file_path.clean();
+    create_project_driver(args).0
+}
 
-    if !is_stdin && !entry_file_path.starts_with(&workspace_dir) {
-        clap::Error::raw(
-            clap::error::ErrorKind::InvalidValue,
-            format!(
-                "entry file path must be in workspace directory: {workspace_dir}\n",
-                workspace_dir = workspace_dir.display()
-            ),
-        )
-        .exit()
-    }
+/// Create a driver along with the project file of the workspace, whose
+/// options are overridden by those of the command line, see
+/// [`typst_ts_compiler::service::project`].
+pub fn create_project_driver(args: CompileOnceArgs) -> (CompileDriver, Option<ProjectState>) {
+    let is_stdin = args.entry == "-";
+    // The entry file is relative to the current directory, while the builder
+    // resolves relative entry files against the workspace.
+    let entry_file_path = make_absolute_from(Path::new(args.entry.as_str()), current_dir);
 
     // Convert the input pairs to a dictionary.
     let inputs: Dict = args
@@ -67,21 +54,48 @@ pub fn create_driver(args: CompileOnceArgs) -> CompileDriver {
         .map(|(k, v)| (k.as_str().into(), v.as_str().into_value()))
         .collect();
 
-    let world = TypstSystemWorld::new(CompileOpts {
-        entry: EntryOpts::new_workspace(workspace_dir.clone()),
-        inputs,
-        font_paths: args.font.paths.clone(),
-        with_embedded_fonts: fonts()
-            .map(Cow::Borrowed)
-            .chain(args.extra_embedded_fonts)
-            .collect(),
-        ..CompileOpts::default()
-    })
-    .unwrap_or_exit();
+    // Keep the date independent of the local timezone for reproducible builds.
+    let utc = args
+        .creation_timestamp
+        .map(|_| FixedOffset::east_opt(0).unwrap());
 
-    if is_stdin {
-        let mut driver = CompileDriver::new(world);
+    let limits = CompileLimits::default()
+        .with_time_budget(args.time_budget)
+        .with_max_elements(args.max_elements)
+        .with_max_file_bytes(args.max_file_bytes);
+    let image_limits = ImageLimits::default()
+        .with_max_pixels(args.max_image_pixels)
+        .with_max_dimension(args.max_image_dimension)
+        .with_animated(args.animated_images.into());
+
+    let builder = CompileDriverBuilder::new()
+        .root(args.workspace.as_str())
+        .extra_roots(args.extra_roots.iter().map(PathBuf::from))
+        .inputs(inputs)
+        .font_paths(args.font.paths.clone())
+        .with_embedded_fonts(fonts().map(Cow::Borrowed).chain(args.extra_embedded_fonts))
+        .cache_dir(match (args.cache_dir, args.cache && !args.no_cache) {
+            (Some(dir), _) => Some(dir),
+            (None, true) => DiskCache::default_dir(),
+            (None, false) => None,
+        })
+        .creation_timestamp(args.creation_timestamp)
+        .timezone(args.timezone_offset.or(utc))
+        .limits(limits)
+        .image_limits(image_limits)
+        .discover_project();
+    let builder = match is_stdin {
+        true => builder,
+        false => builder.entry(entry_file_path),
+    };
+    let (mut driver, project) = builder
+        .build_project_driver()
+        .map_err(|err| {
+            clap::Error::raw(clap::error::ErrorKind::InvalidValue, format!("{err}\n")).exit()
+        })
+        .unwrap();
 
+    if is_stdin {
         let entry = driver.world.entry.select_in_workspace(*MEMORY_MAIN_ENTRY);
         driver.world.mutate_entry(entry).unwrap();
 
@@ -105,11 +119,18 @@ pub fn create_driver(args: CompileOnceArgs) -> CompileDriver {
                 .exit()
             })
             .unwrap();
-
-        driver
-    } else {
-        CompileDriver::new(world).with_entry_file(entry_file_path)
     }
+
+    (driver, project)
+}
+
+/// Get the output directory of the project file of the workspace, which is
+/// used if the command line doesn't specify one.
+pub fn project_output_dir(args: &CompileOnceArgs) -> Option<String> {
+    let root = make_absolute_from(Path::new(args.workspace.as_str()), current_dir);
+    // An invalid project file is reported on creating the driver.
+    let project = ProjectConfig::discover(&root).ok()??;
+    Some(project.export.output?.to_string_lossy().into_owned())
 }
 
 pub fn compile_export(args: CompileArgs, exporter: GroupExporter<Document>) -> ! {
@@ -122,7 +143,12 @@ pub fn compile_export(args: CompileArgs, exporter: GroupExporter<Document>) -> !
     }
 
     let is_stdin = args.compile.entry == "-";
-    let driver = create_driver(args.compile.clone());
+    let begin = Instant::now();
+    let (driver, project) = create_project_driver(args.compile.clone());
+    let timings = args.timings.then(|| Timings {
+        startup: begin.elapsed(),
+        fonts: driver.world.font_resolver.clone(),
+    });
 
     let _trace_guard = {
         let guard = args.trace.clone().map(TraceGuard::new);
@@ -164,19 +190,91 @@ pub fn compile_export(args: CompileArgs, exporter: GroupExporter<Document>) -> !
         }
     };
 
-    let feature_set =
-        FeatureSet::default().configure(&DIAG_FMT_FEATURE, args.diagnostic_format.into());
+    let feature_set = FeatureSet::default()
+        .configure(&DIAG_FMT_FEATURE, args.diagnostic_format.into())
+        .configure(&FAIL_ON_WARNINGS_FEATURE, args.fail_on_warnings);
 
-    // CompileExporter + DynamicLayoutCompiler + WatchDriver
+    // CompileExporter + DynamicLayoutCompiler + DepGraphExporter +
+    // ManifestExporter + WatchDriver
     let driver = CompileExporter::new(driver).with_exporter(exporter);
     let driver = DynamicLayoutCompiler::new(driver, output_dir).with_enable(args.dynamic_layout);
+
+    let entry_file = (!is_stdin).then(|| driver.compiler.compiler.entry_file().to_owned());
+    let mut outputs = output_files(&args, entry_file.as_deref());
+    if args.dynamic_layout {
+        outputs.push(("dynamic_layout".to_owned(), driver.module_dest_path()));
+    }
+
+    let deps_format = match args.deps_format {
+        DepsFormat::Make => {
+            let targets = outputs.iter().map(|(_, path)| path.clone()).collect();
+            DepGraphFormat::Make { targets }
+        }
+        DepsFormat::Json => DepGraphFormat::Json,
+        DepsFormat::Dot => DepGraphFormat::Dot,
+    };
+    let driver = DepGraphExporter::new(driver, args.make_deps.clone(), deps_format);
+    let driver = ManifestExporter::new(driver, args.manifest.clone(), outputs);
     let actor = CompileActor::new_with_features(driver, feature_set).with_watch(args.watch);
+    let actor = match project {
+        Some(project) => actor.with_project(project),
+        None => actor,
+    };
+    let actor = match &args.session_log {
+        Some(path) => actor.with_session_log(open_session_log(&args, path)),
+        None => actor,
+    };
+    let actor = match timings {
+        Some(timings) => actor.with_observer(timings),
+        None => actor,
+    };
 
     utils::async_continue(async move {
         utils::logical_exit(actor.run());
     })
 }
 
+/// The size of the session log before it is rotated.
+const SESSION_LOG_MAX_BYTES: u64 = 16 * 1024 * 1024;
+/// The number of the rotated session logs kept.
+const SESSION_LOG_MAX_FILES: usize = 2;
+
+/// Open the log given by `--session-log`.
+fn open_session_log(args: &CompileArgs, path: &Path) -> SessionLog {
+    let log = SessionLog::to_file(path, SESSION_LOG_MAX_BYTES, SESSION_LOG_MAX_FILES);
+    let log = log.unwrap_or_else(|err| {
+        clap::Error::raw(
+            clap::error::ErrorKind::Io,
+            format!("failed to open the session log: {err}\n"),
+        )
+        .exit()
+    });
+    let root = make_absolute_from(Path::new(args.compile.workspace.as_str()), current_dir);
+    log.with_root(root.into())
+        .with_anonymized_paths(args.anonymize_session_log)
+}
+
+/// Print the timings of `--timings` after each compilation.
+struct Timings {
+    /// The time spent on creating the driver, which doesn't wait for fonts.
+    startup: Duration,
+    fonts: LazyFontResolver,
+}
+
+impl ActorObserver for Timings {
+    fn on_compile_end(&self, end: &CompileEnd) {
+        // The fonts are searched in the background until the first lookup.
+        let fonts = match self.fonts.search_time() {
+            Some(elapsed) => format!("{elapsed:?}"),
+            None => "not needed".to_owned(),
+        };
+        eprintln!(
+            "timings of revision {}: startup {:?}, font search {fonts}, compile {:?}",
+            end.revision, self.startup, end.duration
+        );
+    }
+}
+
 /// Read from stdin.
 fn read_from_stdin() -> FileResult<Vec<u8>> {
     let mut buf = Vec::new();
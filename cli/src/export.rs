@@ -2,8 +2,11 @@ use std::path::{Path, PathBuf};
 
 use typst_ts_core::{
     exporter_builtins::{FsPathExporter, GroupExporter},
+    output::OutputRevisions,
     program_meta::REPORT_BUG_MESSAGE,
 };
+#[cfg(feature = "html")]
+use typst_ts_html_exporter::HtmlImages;
 use typst_ts_svg_exporter::DefaultExportFeature;
 
 use crate::{utils::current_dir, CompileArgs, ExportArgs};
@@ -15,6 +18,7 @@ type GroupDocExporter = GroupExporter<typst::model::Document>;
 pub static AVAILABLE_FORMATS: &[(/* format name */ &str, /* feature hint */ &str)] = &[
     ("ast", REPORT_BUG_MESSAGE),
     ("nothing", REPORT_BUG_MESSAGE),
+    ("html", "html"),
     ("pdf", "pdf"),
     ("svg", "svg"),
     ("svg_html", "svg"),
@@ -51,6 +55,34 @@ fn exit_by_unknown_format(f: &str) -> ! {
     }
 }
 
+/// Get the extension of the files written by a format, or `None` if the
+/// format writes nothing.
+fn format_extension(format: &str) -> Option<&'static str> {
+    Some(match format {
+        "ast" => "ast.ansi.text",
+        "html" => "html",
+        "pdf" => "pdf",
+        "svg" => "artifact.svg",
+        "svg_html" => "artifact.svg.html",
+        "sir" | "vector" => "artifact.sir.in",
+        "text" => "txt",
+        _ => return None,
+    })
+}
+
+/// Write the exports to the revisions of the output if requested.
+fn with_revisions<W, E>(
+    args: &ExportArgs,
+    extension: &str,
+    exporter: FsPathExporter<W, E>,
+) -> FsPathExporter<W, E> {
+    if !args.output_per_revision {
+        return exporter;
+    }
+    let revisions = OutputRevisions::new(args.output_retention).with_extension(extension);
+    exporter.with_revisions(revisions)
+}
+
 /// With the given arguments, prepare exporters for the compilation.
 fn prepare_exporters_impl(
     args: ExportArgs,
@@ -71,19 +103,21 @@ fn prepare_exporters_impl(
 
     /// write $exporters as $exporter to path `$output_dir @@ $extension`
     macro_rules! sink_path {
-        ($exporter:ty as $ser:ty as $exporters:ident, $output_dir:ident @@ $extension:literal) => {{
+        ($exporter:ty as $ser:ty as $exporters:ident, $output_dir:ident @@ $extension:expr) => {{
             let output_path = $output_dir.with_extension($extension);
-            $exporters.push(Box::new(FsPathExporter::<$ser, _>::new(
-                output_path,
-                <$exporter>::default(),
+            $exporters.push(Box::new(with_revisions(
+                &args,
+                $extension,
+                FsPathExporter::<$ser, _>::new(output_path, <$exporter>::default()),
             )));
         }};
-        (|| $exporter:tt as $ser:ty as $exporters:ident, $output_dir:ident @@ $extension:literal) => {{
+        (|| $exporter:tt as $ser:ty as $exporters:ident, $output_dir:ident @@ $extension:expr) => {{
             let output_path = $output_dir.with_extension($extension);
             let exporter = $exporter;
-            $exporters.push(Box::new(FsPathExporter::<$ser, _>::new(
-                output_path,
-                exporter,
+            $exporters.push(Box::new(with_revisions(
+                &args,
+                $extension,
+                FsPathExporter::<$ser, _>::new(output_path, exporter),
             )));
         }};
     }
@@ -92,25 +126,40 @@ fn prepare_exporters_impl(
     {
         formats.sort();
         formats.dedup();
-        #[rustfmt::skip]
-        formats.iter().map(String::as_str).for_each(|f| match f {
-            "nothing"     => (),
-            "ast"         => sink_path!(WithAst as _ as doc, out @@ "ast.ansi.text"),
-            #[cfg(feature = "pdf")]
-            "pdf"         => sink_path!(|| {
-                WithPdf::default().with_timestamp(args.pdf_timestamp)
-            } as _ as doc, out @@ "pdf"),
-            #[cfg(feature = "svg")]
-            "svg"         => sink_path!(WithSvg as _ as doc, out @@ "artifact.svg"),
-            #[cfg(feature = "svg")]
-            "svg_html"         => sink_path!(WithSvgHtml as _ as doc, out @@ "artifact.svg.html"),
-            #[cfg(feature = "svg")]
-            "sir"         => sink_path!(WithSIR as _ as doc, out @@ "artifact.sir.in"),
-            #[cfg(feature = "svg")]
-            "vector"      => sink_path!(WithSIR as _ as doc, out @@ "artifact.sir.in"),
-            #[cfg(feature = "text")]
-            "text"      => sink_path!(WithText as _ as doc, out @@ "txt"),
-            _             => exit_by_unknown_format(f),
+        formats.iter().map(String::as_str).for_each(|f| {
+            let ext = format_extension(f).unwrap_or_default();
+            #[rustfmt::skip]
+            match f {
+                "nothing"     => (),
+                "ast"         => sink_path!(WithAst as _ as doc, out @@ ext),
+                #[cfg(feature = "html")]
+                "html"        => sink_path!(|| {
+                    let images = match &args.html_image_dir {
+                        Some(dir) => HtmlImages::Files(dir.clone()),
+                        None => HtmlImages::DataUri,
+                    };
+                    WithHtml::default().with_images(images)
+                } as _ as doc, out @@ ext),
+                #[cfg(feature = "pdf")]
+                "pdf"         => sink_path!(|| {
+                    WithPdf::default().with_timestamp(args.pdf_timestamp)
+                } as _ as doc, out @@ ext),
+                #[cfg(feature = "svg")]
+                "svg"         => sink_path!(WithSvg as _ as doc, out @@ ext),
+                #[cfg(feature = "svg")]
+                "svg_html"    => sink_path!(WithSvgHtml as _ as doc, out @@ ext),
+                #[cfg(feature = "svg")]
+                "sir"         => sink_path!(|| {
+                    WithSIR::default().with_paged(args.vector_paged)
+                } as _ as doc, out @@ ext),
+                #[cfg(feature = "svg")]
+                "vector"      => sink_path!(|| {
+                    WithSIR::default().with_paged(args.vector_paged)
+                } as _ as doc, out @@ ext),
+                #[cfg(feature = "text")]
+                "text"        => sink_path!(WithText as _ as doc, out @@ ext),
+                _             => exit_by_unknown_format(f),
+            };
         });
     }
     return GroupExporter::new(doc);
@@ -118,6 +167,7 @@ fn prepare_exporters_impl(
     type Doc = typst::model::Document;
 
     type WithAst = typst_ts_ast_exporter::AstExporter;
+    type WithHtml = typst_ts_html_exporter::HtmlExporter;
     // type WithJson<T> = typst_ts_serde_exporter::JsonExporter<T>;
     type WithPdf = typst_ts_pdf_exporter::PdfDocExporter;
     // type WithRmp<T> = typst_ts_serde_exporter::RmpExporter<T>;
@@ -131,34 +181,49 @@ fn prepare_exporters_impl(
 
 /// Prepare exporters from command line arguments.
 pub fn prepare_exporters(args: &CompileArgs, entry_file: Option<&Path>) -> GroupDocExporter {
-    let output_dir = {
-        // If output is specified, use it.
-        let dir = (!args.compile.output.is_empty()).then(|| Path::new(&args.compile.output));
-        // Otherwise, use the parent directory of the entry file.
-        let dir = dir.map(Path::to_owned).unwrap_or_else(|| match entry_file {
-            Some(entry_file) => entry_file
-                .parent()
-                .expect("entry_file has no parent")
-                .to_owned(),
-            None => current_dir(),
-        });
-        match entry_file {
-            Some(entry_file) => {
-                dir.join(entry_file.file_name().expect("entry_file has no file name"))
-            }
-            None => dir.join("main"),
-        }
-    };
-
-    let formats = {
-        // If formats are specified, use them.
-        let mut formats = args.format.clone();
-        // Otherwise, use default formats.
-        if formats.is_empty() {
-            formats.extend(["pdf", "vector"].map(str::to_owned));
-        }
-        formats
-    };
+    let output_dir = output_base(args, entry_file);
+    prepare_exporters_impl(args.export.clone(), output_dir, export_formats(args))
+}
+
+/// Get the formats and the paths of the files written by the exporters, see
+/// [`prepare_exporters`].
+pub fn output_files(args: &CompileArgs, entry_file: Option<&Path>) -> Vec<(String, PathBuf)> {
+    let output_dir = output_base(args, entry_file);
+    let mut formats = export_formats(args);
+    formats.sort();
+    formats.dedup();
+    (formats.into_iter())
+        .filter_map(|f| {
+            let path = output_dir.with_extension(format_extension(&f)?);
+            Some((f, path))
+        })
+        .collect()
+}
 
-    prepare_exporters_impl(args.export.clone(), output_dir, formats)
+/// Get the path of output files without extension.
+fn output_base(args: &CompileArgs, entry_file: Option<&Path>) -> PathBuf {
+    // If output is specified, use it.
+    let dir = (!args.compile.output.is_empty()).then(|| Path::new(&args.compile.output));
+    // Otherwise, use the parent directory of the entry file.
+    let dir = dir.map(Path::to_owned).unwrap_or_else(|| match entry_file {
+        Some(entry_file) => entry_file
+            .parent()
+            .expect("entry_file has no parent")
+            .to_owned(),
+        None => current_dir(),
+    });
+    match entry_file {
+        Some(entry_file) => dir.join(entry_file.file_name().expect("entry_file has no file name")),
+        None => dir.join("main"),
+    }
+}
+
+fn export_formats(args: &CompileArgs) -> Vec<String> {
+    // If formats are specified, use them.
+    let mut formats = args.format.clone();
+    // Otherwise, use default formats.
+    if formats.is_empty() {
+        formats.extend(["pdf", "vector"].map(str::to_owned));
+    }
+    formats
 }
@@ -1,6 +1,7 @@
 use core::fmt;
-use std::{borrow::Cow, path::PathBuf};
+use std::{borrow::Cow, path::PathBuf, time::Duration};
 
+pub mod artifact;
 pub mod compile;
 pub mod export;
 pub mod font;
@@ -12,6 +13,7 @@ pub mod tracing;
 pub mod utils;
 pub mod version;
 
+use chrono::{DateTime, FixedOffset, Utc};
 use clap::{builder::ValueParser, ArgAction, Args, Command, Parser, Subcommand, ValueEnum};
 use typst_ts_core::build_info::VERSION;
 use version::VersionFormat;
@@ -54,6 +56,9 @@ pub enum Subcommands {
     /// Runs repl for query
     QueryRepl(QueryReplArgs),
 
+    /// Evaluates an entry file without layout and prints its bindings
+    Eval(EvalArgs),
+
     /// Generates a shell completion script for CLI.
     Completion(CompletionArgs),
 
@@ -70,6 +75,10 @@ pub enum Subcommands {
     /// Package commands
     #[clap(subcommand)]
     Package(PackageSubCommands),
+
+    /// Artifact commands
+    #[clap(subcommand)]
+    Artifact(ArtifactSubCommands),
 }
 
 #[derive(Debug, Subcommand)]
@@ -104,6 +113,23 @@ pub enum PackageSubCommands {
     Doc(GenPackagesDocArgs),
 }
 
+#[derive(Debug, Subcommand)]
+#[clap(
+    about = "Artifact commands about exported vector artifacts.",
+    after_help = "",
+    next_display_order = None
+)]
+#[allow(clippy::large_enum_variant)]
+pub enum ArtifactSubCommands {
+    /// Compares two artifacts page by page
+    Diff(ArtifactDiffArgs),
+    /// Shows a size breakdown of an artifact
+    Stats(ArtifactStatsArgs),
+    /// Converts an artifact to PDF without recompiling
+    #[cfg(feature = "pdf")]
+    ToPdf(ArtifactToPdfArgs),
+}
+
 /// Shared arguments for font related commands
 #[derive(Default, Debug, Clone, Parser)]
 pub struct FontArgs {
@@ -128,6 +154,31 @@ pub struct CompileOnceArgs {
     #[clap(long, short, default_value = ".")]
     pub workspace: String,
 
+    /// Path to an extra root of the workspace, e.g. a directory of shared
+    /// templates, which can be repeated.
+    #[clap(long = "extra-root", value_name = "DIR", action = ArgAction::Append)]
+    pub extra_roots: Vec<String>,
+
+    /// The UNIX timestamp in seconds to compile with instead of the current
+    /// time, for reproducible builds
+    #[clap(
+        long = "creation-timestamp",
+        env = "SOURCE_DATE_EPOCH",
+        value_name = "UNIX_TIMESTAMP",
+        value_parser = parse_creation_timestamp
+    )]
+    pub creation_timestamp: Option<DateTime<Utc>>,
+
+    /// The timezone offset in hours of the current date. Defaults to UTC if
+    /// the creation timestamp is set, otherwise to the local timezone
+    #[clap(
+        long = "timezone-offset",
+        value_name = "HOURS",
+        allow_hyphen_values = true,
+        value_parser = parse_timezone_offset
+    )]
+    pub timezone_offset: Option<FixedOffset>,
+
     /// Path to input Typst file, use `-` to read input from stdin
     #[clap(long, short, required = true)]
     pub entry: String,
@@ -145,6 +196,47 @@ pub struct CompileOnceArgs {
     #[clap(long, short, default_value = "")]
     pub output: String,
 
+    /// Aborts a compilation taking longer than the budget in seconds.
+    #[clap(long = "time-budget", value_name = "SECS", value_parser = parse_time_budget)]
+    pub time_budget: Option<Duration>,
+
+    /// Aborts a compilation whose document has more elements than the limit.
+    #[clap(long = "max-elements", value_name = "COUNT")]
+    pub max_elements: Option<usize>,
+
+    /// Aborts a compilation loading files, e.g. images, of more bytes in
+    /// total than the limit.
+    #[clap(long = "max-file-bytes", value_name = "BYTES")]
+    pub max_file_bytes: Option<usize>,
+
+    /// Rejects an image decoding to more pixels than the limit.
+    #[clap(long = "max-image-pixels", value_name = "PIXELS")]
+    pub max_image_pixels: Option<u64>,
+
+    /// Downscales an image whose width or height exceeds the limit in pixels
+    /// to fit, before it enters the document.
+    #[clap(long = "max-image-dimension", value_name = "PIXELS")]
+    pub max_image_dimension: Option<u32>,
+
+    /// How to handle animated images.
+    #[clap(long = "animated-images", default_value_t = AnimatedImages::Keep)]
+    pub animated_images: AnimatedImages,
+
+    /// Persist derived data across runs, e.g. the metadata of fonts, in
+    /// `typst-ts/cache` in the cache directory of the user unless `--cache-dir`
+    /// is given
+    #[clap(long = "cache", overrides_with = "no_cache")]
+    pub cache: bool,
+
+    /// Directory to persist derived data across runs, which implies `--cache`
+    #[clap(long = "cache-dir", value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Don't persist derived data across runs, which is the default, e.g. to
+    /// override an earlier `--cache`
+    #[clap(long = "no-cache", overrides_with = "cache", conflicts_with = "cache_dir")]
+    pub no_cache: bool,
+
     #[clap(skip)]
     pub extra_embedded_fonts: Vec<Cow<'static, [u8]>>,
 }
@@ -165,12 +257,60 @@ fn parse_input_pair(raw: &str) -> Result<(String, String), String> {
     Ok((key, val))
 }
 
+/// Parses a UNIX timestamp, e.g. from `SOURCE_DATE_EPOCH`.
+fn parse_creation_timestamp(raw: &str) -> Result<DateTime<Utc>, String> {
+    let timestamp: i64 = raw
+        .trim()
+        .parse()
+        .map_err(|err| format!("timestamp is not an integer: {err}"))?;
+    DateTime::from_timestamp(timestamp, 0).ok_or_else(|| "timestamp is out of range".to_owned())
+}
+
+/// Parses a time budget in seconds, e.g. `1.5`.
+fn parse_time_budget(raw: &str) -> Result<Duration, String> {
+    let secs: f64 = raw
+        .trim()
+        .parse()
+        .map_err(|err| format!("budget is not a number: {err}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|err| format!("budget is out of range: {err}"))
+}
+
+/// Parses a timezone offset in hours.
+fn parse_timezone_offset(raw: &str) -> Result<FixedOffset, String> {
+    let hours: i32 = raw
+        .trim()
+        .parse()
+        .map_err(|err| format!("offset is not an integer: {err}"))?;
+    (hours.checked_mul(3600))
+        .and_then(FixedOffset::east_opt)
+        .ok_or_else(|| "offset is out of range".to_owned())
+}
+
 #[derive(Default, Debug, Clone, Parser)]
 #[clap(next_help_heading = "Export options")]
 pub struct ExportArgs {
     /// Export pdf with timestamp.
     #[clap(long, default_value_t = false)]
     pub pdf_timestamp: bool,
+
+    /// Writes the images of html export into the directory, instead of
+    /// embedding them as data URIs.
+    #[clap(long, value_name = "DIR")]
+    pub html_image_dir: Option<PathBuf>,
+
+    /// Writes vector artifacts in the page-wise format, so that viewers can
+    /// render the first pages before the whole artifact arrives.
+    #[clap(long, default_value_t = false)]
+    pub vector_paged: bool,
+
+    /// Writes each export to a new revision of the output, e.g.
+    /// `main.r42.pdf`, to which the output links.
+    #[clap(long, default_value_t = false)]
+    pub output_per_revision: bool,
+
+    /// The number of the revisions kept by `--output-per-revision`.
+    #[clap(long, value_name = "COUNT", default_value_t = 5)]
+    pub output_retention: usize,
 }
 
 #[derive(Default, Debug, Clone, Parser)]
@@ -193,7 +333,7 @@ pub struct CompileArgs {
     #[clap(long)]
     pub dynamic_layout: bool,
 
-    /// Outputs format(s), possible values: `ast`, `pdf`, `svg`, and,
+    /// Outputs format(s), possible values: `ast`, `html`, `pdf`, `svg`, and,
     /// `svg_html`.
     #[clap(long)]
     pub format: Vec<String>,
@@ -206,6 +346,38 @@ pub struct CompileArgs {
     )]
     pub diagnostic_format: DiagnosticFormat,
 
+    /// Fails the compilation if there are warnings.
+    #[clap(long)]
+    pub fail_on_warnings: bool,
+
+    /// Writes the dependencies of the document to a file after each
+    /// compilation, e.g. a Makefile depfile.
+    #[clap(long, value_name = "PATH")]
+    pub make_deps: Option<PathBuf>,
+
+    /// The format of the dependencies written by `--make-deps`
+    #[clap(long, default_value_t = DepsFormat::Make)]
+    pub deps_format: DepsFormat,
+
+    /// Writes a JSON manifest of the outputs, the fonts and the dependencies
+    /// of the document to a file after each compilation.
+    #[clap(long, value_name = "PATH")]
+    pub manifest: Option<PathBuf>,
+
+    /// Records the events of the watch session to a file as JSON lines, e.g.
+    /// to attach to a bug report. The contents of the files are not recorded.
+    #[clap(long, value_name = "PATH")]
+    pub session_log: Option<PathBuf>,
+
+    /// Records the paths of the files by their hashes in `--session-log`.
+    #[clap(long)]
+    pub anonymize_session_log: bool,
+
+    /// Prints the time spent on the startup, i.e. creating the world and
+    /// searching fonts, and on each compilation.
+    #[clap(long)]
+    pub timings: bool,
+
     /// Enable tracing.
     /// Possible usage: --trace=verbosity={0..3}
     ///   where verbosity: {0..3} -> {warning, info, debug, trace}
@@ -251,6 +423,31 @@ pub struct QueryReplArgs {
     pub compile: CompileOnceArgs,
 }
 
+/// Evaluates an entry file without layout and prints the values of its
+/// top-level bindings, e.g. for data extraction
+///
+/// Examples:
+/// ```shell
+/// # print the bindings of main.typ in json
+/// eval --entry main.typ --format json
+/// # print the bindings whenever main.typ or its dependencies change
+/// eval --entry main.typ --watch
+/// ```
+#[derive(Debug, Clone, Parser)]
+pub struct EvalArgs {
+    /// compile arguments before evaluation.
+    #[clap(flatten)]
+    pub compile: CompileOnceArgs,
+
+    /// Evaluates again when the entry file or its dependencies change.
+    #[clap(long)]
+    pub watch: bool,
+
+    /// The format of the bindings, possible values: `json`.
+    #[clap(long, default_value = "json", value_parser = ["json"])]
+    pub format: String,
+}
+
 /// List all discovered fonts in system and custom font paths
 #[derive(Debug, Clone, Parser)]
 pub struct ListFontsArgs {
@@ -307,6 +504,85 @@ pub struct EnvArgs {
     pub key: EnvKey,
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactDiffFormat {
+    Json,
+    Summary,
+}
+
+impl fmt::Display for ArtifactDiffFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Compare two artifacts page by page.
+#[derive(Debug, Clone, Parser)]
+pub struct ArtifactDiffArgs {
+    /// Path to the left artifact
+    pub left: PathBuf,
+
+    /// Path to the right artifact
+    pub right: PathBuf,
+
+    /// The format of the diff result
+    #[clap(long, default_value_t = ArtifactDiffFormat::Summary)]
+    pub format: ArtifactDiffFormat,
+
+    /// Ignore differences in non-visual items, e.g. links and content hints
+    #[clap(long)]
+    pub ignore_metadata: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactStatsFormat {
+    Json,
+    Table,
+}
+
+impl fmt::Display for ArtifactStatsFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Show a size breakdown of an artifact.
+#[derive(Debug, Clone, Parser)]
+pub struct ArtifactStatsArgs {
+    /// Path to the artifact
+    pub file: PathBuf,
+
+    /// The format of the statistics
+    #[clap(long, default_value_t = ArtifactStatsFormat::Table)]
+    pub format: ArtifactStatsFormat,
+
+    /// The number of largest resources to show
+    #[clap(long, default_value_t = 10)]
+    pub top: usize,
+}
+
+/// Convert an artifact to PDF without recompiling.
+#[derive(Debug, Clone, Parser)]
+pub struct ArtifactToPdfArgs {
+    /// Path to the artifact
+    pub input: PathBuf,
+
+    /// Path to the output PDF
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// Shared arguments for font related commands, where the fonts of the
+    /// artifact found among them are embedded.
+    #[clap(flatten)]
+    pub font: FontArgs,
+}
+
 #[derive(Debug, Clone, Parser)]
 pub struct ListPackagesArgs {
     /// Also list other information of each package
@@ -338,6 +614,56 @@ pub struct GenPackagesDocArgs {
     pub dynamic_layout: bool,
 }
 
+/// Which format to use for dependencies.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, ValueEnum)]
+pub enum DepsFormat {
+    /// A Makefile depfile, as generated by `gcc -M`.
+    #[default]
+    Make,
+    Json,
+    Dot,
+}
+
+impl fmt::Display for DepsFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// How to handle animated images.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, ValueEnum)]
+pub enum AnimatedImages {
+    /// Keep all the frames.
+    #[default]
+    Keep,
+    /// Only keep the first frame, with a warning.
+    FirstFrame,
+    /// Reject animated images.
+    Reject,
+}
+
+impl fmt::Display for AnimatedImages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl From<AnimatedImages> for typst_ts_compiler::images::AnimatedImagePolicy {
+    fn from(policy: AnimatedImages) -> Self {
+        match policy {
+            AnimatedImages::Keep => Self::Keep,
+            AnimatedImages::FirstFrame => Self::FirstFrame,
+            AnimatedImages::Reject => Self::Reject,
+        }
+    }
+}
+
 /// Which format to use for diagnostics.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, ValueEnum)]
 pub enum DiagnosticFormat {
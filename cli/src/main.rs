@@ -10,15 +10,16 @@ use typst::{model::Document, text::FontVariant, World};
 
 use typst_assets::fonts;
 use typst_ts_cli::{
-    compile::compile_export,
+    artifact::{artifact_stats, diff_artifacts},
+    compile::{compile_export, create_project_driver, project_output_dir},
     get_cli,
     manual::generate_manual,
     query::serialize,
     utils::{self, make_absolute, UnwrapOrExit},
     version::intercept_version,
-    CompileArgs, CompileOnceArgs, CompletionArgs, EnvKey, FontSubCommands, GenPackagesDocArgs,
-    LinkPackagesArgs, ListFontsArgs, ListPackagesArgs, MeasureFontsArgs, Opts, PackageSubCommands,
-    QueryArgs, QueryReplArgs, Subcommands,
+    ArtifactSubCommands, CompileArgs, CompileOnceArgs, CompletionArgs, EnvKey, EvalArgs,
+    FontSubCommands, GenPackagesDocArgs, LinkPackagesArgs, ListFontsArgs, ListPackagesArgs,
+    MeasureFontsArgs, Opts, PackageSubCommands, QueryArgs, QueryReplArgs, Subcommands,
 };
 use typst_ts_compiler::TypstSystemWorld;
 use typst_ts_core::{config::compiler::EntryOpts, exporter_builtins::GroupExporter};
@@ -65,6 +66,7 @@ fn main() {
         Some(Subcommands::Compile(args)) => compile(args),
         Some(Subcommands::Query(args)) => query(args),
         Some(Subcommands::QueryRepl(args)) => query_repl(args),
+        Some(Subcommands::Eval(args)) => eval(args),
         Some(Subcommands::Completion(args)) => generate_completion(args),
         #[cfg(feature = "gen-manual")]
         Some(Subcommands::Manual(args)) => {
@@ -89,6 +91,12 @@ fn main() {
             PackageSubCommands::Unlink(args) => link_packages(args, true),
             PackageSubCommands::Doc(args) => doc_packages(args),
         },
+        Some(Subcommands::Artifact(artifact_sub)) => match artifact_sub {
+            ArtifactSubCommands::Diff(args) => diff_artifacts(args),
+            ArtifactSubCommands::Stats(args) => artifact_stats(args),
+            #[cfg(feature = "pdf")]
+            ArtifactSubCommands::ToPdf(args) => typst_ts_cli::artifact::artifact_to_pdf(args),
+        },
         None => help_sub_command(),
     };
 
@@ -114,6 +122,13 @@ fn compile(args: CompileArgs) -> ! {
             args.format.push("nothing".to_owned());
         }
 
+        // The output directory of the project file is a default.
+        if args.compile.output.is_empty() {
+            if let Some(output) = project_output_dir(&args.compile) {
+                args.compile.output = output;
+            }
+        }
+
         args
     };
 
@@ -157,6 +172,32 @@ fn query_repl(args: QueryReplArgs) -> ! {
     exit(0)
 }
 
+/// Execute an eval command.
+fn eval(args: EvalArgs) -> ! {
+    use typst_ts_compiler::service::{eval::module_to_json, CompileActor};
+
+    let format = args.format.clone();
+    let (driver, project) = create_project_driver(args.compile);
+    let actor = CompileActor::new(driver);
+    let actor = match project {
+        Some(project) => actor.with_project(project),
+        None => actor,
+    };
+    let actor = actor
+        .with_watch(args.watch)
+        .with_eval_only(true)
+        .on_evaluated(
+            move |module| match serialize(&module_to_json(module), &format) {
+                Ok(serialized) => println!("{serialized}"),
+                Err(err) => log::error!("failed to serialize the bindings: {err}"),
+            },
+        );
+
+    utils::async_continue(async move {
+        utils::logical_exit(actor.run());
+    })
+}
+
 fn generate_completion(CompletionArgs { shell }: CompletionArgs) -> ! {
     clap_complete::generate(
         shell,
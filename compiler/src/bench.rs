@@ -0,0 +1,290 @@
+//! Measure the compilations of a fixture directory.
+//!
+//! [`CompileBencher`] compiles `main.typ` of a fixture directory with the
+//! embedded fonts and a pinned creation timestamp, so that the measurements
+//! are comparable across machines. An incremental compilation is measured by
+//! applying a synthetic edit via the shadow files, recompiling and reverting
+//! the edit.
+//!
+//! Comemo doesn't expose the statistics of its caches, hence the speedup of
+//! incremental compilations over full compilations is reported instead of the
+//! hit rates of the caches.
+
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use typst_ts_core::{
+    config::compiler::EntryState, error::prelude::*, font::FontResolverImpl, Bytes,
+};
+
+use crate::{
+    font::pure::MemoryFontBuilder,
+    package::http::HttpRegistry,
+    service::{CompileDriverImpl, CompileEnv, Compiler},
+    vfs::{
+        system::SystemAccessModel,
+        trace::{AccessKind, TraceAccessModel, TraceStats},
+        Vfs,
+    },
+    world::{CompilerFeat, CompilerWorld},
+    ShadowApi,
+};
+
+/// type trait of [`BenchWorld`].
+#[derive(Debug, Clone, Copy)]
+pub struct BenchCompilerFeat;
+
+impl CompilerFeat for BenchCompilerFeat {
+    /// It uses the embedded fonts only.
+    type FontResolver = FontResolverImpl;
+    /// It counts the accesses to a physical file system.
+    type AccessModel = TraceAccessModel<SystemAccessModel>;
+    /// It performs native HTTP requests for fetching package data.
+    type Registry = HttpRegistry;
+}
+
+/// The compiler world measured by [`CompileBencher`].
+pub type BenchWorld = CompilerWorld<BenchCompilerFeat>;
+
+/// A synthetic edit of a source file, see
+/// [`CompileBencher::measure_incremental`].
+#[derive(Debug, Clone)]
+pub struct SourceEdit {
+    /// The path of the file relative to the fixture directory.
+    pub path: PathBuf,
+    /// The byte range to replace, which is clamped to the content.
+    pub range: Range<usize>,
+    /// The replacement text.
+    pub text: String,
+}
+
+impl SourceEdit {
+    /// Append text to the end of a file.
+    pub fn append(path: impl Into<PathBuf>, text: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            range: usize::MAX..usize::MAX,
+            text: text.into(),
+        }
+    }
+
+    fn apply(&self, content: &str) -> ZResult<String> {
+        let end = self.range.end.min(content.len());
+        let start = self.range.start.min(end);
+        if !content.is_char_boundary(start) || !content.is_char_boundary(end) {
+            return Err(error_once!("edit is not at char boundaries", start: start, end: end));
+        }
+
+        let mut edited = content.to_owned();
+        edited.replace_range(start..end, &self.text);
+        Ok(edited)
+    }
+}
+
+/// The measurement of an incremental compilation.
+#[derive(Debug, Clone, Serialize)]
+pub struct IterationReport {
+    /// The wall time of the compilation.
+    pub wall_time: Duration,
+    /// The number of accesses to the file system.
+    pub io_calls: u64,
+    /// The number of files read from the file system.
+    pub content_reads: u64,
+}
+
+/// The measurements of [`CompileBencher::measure_incremental`].
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    /// The name of the benchmark, e.g. `incremental/book/main.typ`.
+    pub name: String,
+    /// The median wall time of the full compilations, if warmed up.
+    pub full_compile: Option<Duration>,
+    pub iterations: Vec<IterationReport>,
+}
+
+impl BenchReport {
+    /// The median wall time of the incremental compilations.
+    pub fn median(&self) -> Duration {
+        median(self.iterations.iter().map(|it| it.wall_time).collect())
+    }
+
+    /// The speedup of the incremental compilations over the full
+    /// compilations.
+    pub fn speedup(&self) -> Option<f64> {
+        let median = self.median().as_secs_f64();
+        let full = self.full_compile?.as_secs_f64();
+        (median > 0.).then(|| full / median)
+    }
+
+    /// Format the report in the output format of `cargo bench`, which is also
+    /// printed by `criterion --output-format bencher`, so that CI can track
+    /// the trends by existing tools.
+    pub fn to_bencher(&self) -> String {
+        let times = self.iterations.iter().map(|it| it.wall_time);
+        let deviation = times.clone().max().unwrap_or_default() - times.min().unwrap_or_default();
+        format!(
+            "test {} ... bench: {:>11} ns/iter (+/- {})",
+            self.name,
+            thousands(self.median().as_nanos()),
+            thousands(deviation.as_nanos()),
+        )
+    }
+}
+
+/// Measure the compilations of a fixture directory.
+pub struct CompileBencher {
+    root: PathBuf,
+    driver: CompileDriverImpl<BenchWorld>,
+    stats: Arc<TraceStats>,
+    /// The wall times of the full compilations.
+    full_compiles: Vec<Duration>,
+}
+
+impl CompileBencher {
+    /// Create a bencher compiling `main.typ` in the fixture directory.
+    pub fn new(root: impl AsRef<Path>) -> ZResult<Self> {
+        let root = (root.as_ref().canonicalize())
+            .map_err(map_string_err("failed to find the fixture directory"))?;
+        let main = root.join("main.typ");
+        if !main.is_file() {
+            return Err(error_once!("fixture has no main.typ", root: root.display()));
+        }
+
+        let access = TraceAccessModel::counting(SystemAccessModel);
+        let stats = access.stats();
+
+        let mut fonts = MemoryFontBuilder::new();
+        for font in typst_assets::fonts() {
+            fonts.add_memory_font(Bytes::from_static(font));
+        }
+
+        let world = BenchWorld::new_raw(
+            EntryState::new_rooted(root.as_path().into(), None),
+            Vfs::new(access),
+            HttpRegistry::default(),
+            fonts.into(),
+        )
+        .with_creation_timestamp(pinned_timestamp());
+
+        Ok(Self {
+            driver: CompileDriverImpl::new(world).with_entry_file(main),
+            root,
+            stats,
+            full_compiles: vec![],
+        })
+    }
+
+    /// Run full compilations, evicting the caches of comemo before each.
+    pub fn warmup(&mut self, n: usize) -> ZResult<()> {
+        for _ in 0..n {
+            comemo::evict(0);
+            let elapsed = self.compile()?;
+            self.full_compiles.push(elapsed);
+        }
+        Ok(())
+    }
+
+    /// Measure the recompilations after applying the edit. The edit is
+    /// reverted and the document is recompiled after each iteration, so that
+    /// every iteration starts with the same caches.
+    pub fn measure_incremental(
+        &mut self,
+        edit: SourceEdit,
+        iterations: usize,
+    ) -> ZResult<BenchReport> {
+        let path = self.root.join(&edit.path);
+        let original = std::fs::read_to_string(&path)
+            .map_err(map_string_err("failed to read the edited file"))?;
+        let edited = Bytes::from(edit.apply(&original)?.into_bytes());
+
+        let fixture = self.root.file_name().unwrap_or_default().to_string_lossy();
+        let mut report = BenchReport {
+            name: format!("incremental/{fixture}/{}", edit.path.display()),
+            full_compile: (!self.full_compiles.is_empty())
+                .then(|| median(self.full_compiles.clone())),
+            iterations: Vec::with_capacity(iterations),
+        };
+
+        for _ in 0..iterations {
+            (self.driver.map_shadow(&path, edited.clone()))
+                .map_err(map_string_err("failed to apply the edit"))?;
+            self.stats.reset();
+            let wall_time = self.compile()?;
+            report.iterations.push(IterationReport {
+                wall_time,
+                io_calls: self.stats.total_calls(),
+                content_reads: self.stats.calls(AccessKind::Content),
+            });
+
+            (self.driver.unmap_shadow(&path))
+                .map_err(map_string_err("failed to revert the edit"))?;
+            self.compile()?;
+        }
+
+        Ok(report)
+    }
+
+    fn compile(&mut self) -> ZResult<Duration> {
+        let start = Instant::now();
+        let res = self.driver.compile(&mut CompileEnv::default());
+        let elapsed = start.elapsed();
+        res.map_err(|diags| error_once!("failed to compile the fixture", errors: diags.len()))?;
+        Ok(elapsed)
+    }
+}
+
+/// The creation timestamp of the documents, i.e. `2024-01-01T00:00:00Z`.
+fn pinned_timestamp() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+}
+
+fn median(mut times: Vec<Duration>) -> Duration {
+    times.sort();
+    times.get(times.len() / 2).copied().unwrap_or_default()
+}
+
+/// Format a number with thousands separators like `cargo bench`.
+fn thousands(n: u128) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (idx, ch) in digits.chars().enumerate() {
+        if idx > 0 && (digits.len() - idx).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(ch);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TestWorkspace;
+
+    #[test]
+    fn test_measure_incremental() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        ws.write("main.typ", "= Bench\n#lorem(50)\n");
+
+        let mut bencher = CompileBencher::new(&root).unwrap();
+        bencher.warmup(1).unwrap();
+        let edit = SourceEdit::append("main.typ", "\nAppended.");
+        let report = bencher.measure_incremental(edit, 2).unwrap();
+
+        assert_eq!(report.iterations.len(), 2);
+        assert!(report.full_compile.is_some());
+        let line = report.to_bencher();
+        let dir = root.file_name().unwrap().to_string_lossy();
+        let name = format!("test incremental/{dir}/main.typ ... bench:");
+        assert!(line.starts_with(&name), "{line}");
+        assert_eq!(thousands(1234567), "1,234,567");
+        assert_eq!(thousands(123), "123");
+    }
+}
@@ -0,0 +1,63 @@
+//! Measure the incremental compilations of a fixture directory.
+//!
+//! Usage: `cargo run -p typst-ts-compiler --features bench --bin bench --
+//! <fixture-dir> [iterations]`
+//!
+//! The measurement is printed to stdout in the output format of `cargo
+//! bench`, and a summary is printed to stderr.
+
+use typst_ts_compiler::bench::{BenchReport, CompileBencher, SourceEdit};
+use typst_ts_core::error::prelude::*;
+
+const WARMUP: usize = 3;
+const DEFAULT_ITERATIONS: usize = 10;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(fixture) = args.next() else {
+        eprintln!("usage: bench <fixture-dir> [iterations]");
+        std::process::exit(2);
+    };
+    let iterations = match args.next().map(|n| n.parse::<usize>()) {
+        Some(Ok(n)) => n,
+        Some(Err(err)) => {
+            eprintln!("invalid number of iterations: {err}");
+            std::process::exit(2);
+        }
+        None => DEFAULT_ITERATIONS,
+    };
+
+    match run(&fixture, iterations) {
+        Ok(report) => {
+            println!("{}", report.to_bencher());
+            summarize(&report);
+        }
+        Err(err) => {
+            eprintln!("failed to bench {fixture}: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run(fixture: &str, iterations: usize) -> ZResult<BenchReport> {
+    let mut bencher = CompileBencher::new(fixture)?;
+    bencher.warmup(WARMUP)?;
+    let edit = SourceEdit::append("main.typ", "\n\nAn appended paragraph.\n");
+    bencher.measure_incremental(edit, iterations)
+}
+
+fn summarize(report: &BenchReport) {
+    if let Some(full) = report.full_compile {
+        eprintln!("full compile:        {full:?}");
+    }
+    eprintln!("incremental compile: {:?}", report.median());
+    if let Some(speedup) = report.speedup() {
+        eprintln!("speedup:             {speedup:.1}x");
+    }
+    if let Some(last) = report.iterations.last() {
+        eprintln!(
+            "io calls:            {} ({} reads)",
+            last.io_calls, last.content_reads
+        );
+    }
+}
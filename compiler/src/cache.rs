@@ -0,0 +1,416 @@
+//! Persist derived data across process restarts.
+//!
+//! The caches of comemo live in memory, hence every process parses the same
+//! fonts again, e.g. each invocation of the CLI. A [`DiskCache`] stores the
+//! expensive data the crate derives itself in a directory, keyed by a hash of
+//! what it is derived from. A changed source gets a new key, so an entry is
+//! never stale, and entries of other versions of the library are never read.
+//! The cache is opt-in, e.g. by `--cache` of the CLI, see
+//! [`crate::service::CompileDriverBuilder::cache_dir`].
+//!
+//! The cache is shared by concurrent processes without locks. An entry is
+//! written to a temporary file and renamed into place, so that readers see
+//! either no entry or a complete one. Unreadable entries are treated as
+//! misses and overwritten.
+//!
+//! Currently the metadata of the font faces found by
+//! [`crate::font::system::SystemFontSearcher`] is cached, keyed by the paths
+//! and mtimes of the font files, so that a hit reads no font file. Its hits
+//! are reported by `CompileClient::cache_efficiency`. Images are decoded by
+//! typst itself and packages are already unpacked to a directory by
+//! [`crate::package::http::HttpRegistry`], so neither is cached here.
+//!
+//! The contents of the text files read by a compiler are persisted to a
+//! single file instead, see [`save_contents`], since they are keyed by their
+//! paths and mtimes rather than by themselves. Neither the caches of comemo
+//! nor the parsed sources are serializable, hence the restored files are
+//! still parsed again.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use typst_ts_core::{build_info, error::prelude::*, hash::hash128, Bytes};
+
+use crate::Time;
+
+/// The version of the layout of the cache directory, which is bumped on
+/// incompatible changes.
+const LAYOUT_VERSION: &str = "v1";
+
+/// The kinds of entries in a [`DiskCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheKind {
+    /// The [`typst::text::FontInfo`] of a font face.
+    FontInfo,
+}
+
+impl CacheKind {
+    const ALL: [CacheKind; 1] = [CacheKind::FontInfo];
+
+    fn dir_name(self) -> &'static str {
+        match self {
+            CacheKind::FontInfo => "font-info",
+        }
+    }
+}
+
+/// The number of hits and misses of a [`DiskCache`].
+///
+/// It is shared with the cache, so that it can be read after the cache is
+/// moved into a font searcher.
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    hits: [AtomicU64; CacheKind::ALL.len()],
+    misses: [AtomicU64; CacheKind::ALL.len()],
+}
+
+impl CacheStats {
+    /// Get the number of lookups of a kind served from the disk.
+    pub fn hits(&self, kind: CacheKind) -> u64 {
+        self.hits[kind as usize].load(Ordering::Relaxed)
+    }
+
+    /// Get the number of lookups of a kind which had to derive the data.
+    pub fn misses(&self, kind: CacheKind) -> u64 {
+        self.misses[kind as usize].load(Ordering::Relaxed)
+    }
+
+    /// Get the ratio of hits to lookups of a kind, if there were any lookups.
+    pub fn hit_rate(&self, kind: CacheKind) -> Option<f64> {
+        let hits = self.hits(kind);
+        let total = hits + self.misses(kind);
+        (total > 0).then(|| hits as f64 / total as f64)
+    }
+
+    /// Reset all the counters to zero.
+    pub fn reset(&self) {
+        for counter in self.hits.iter().chain(self.misses.iter()) {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Get the hits and misses of a kind.
+    pub fn snapshot(&self, kind: CacheKind) -> CacheHits {
+        CacheHits {
+            hits: self.hits(kind),
+            misses: self.misses(kind),
+        }
+    }
+
+    fn record(&self, kind: CacheKind, hit: bool) {
+        let counters = if hit { &self.hits } else { &self.misses };
+        counters[kind as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The hits and misses of a kind of entries, see [`CacheStats::snapshot`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheHits {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// An on-disk cache of derived data, see the [module docs](self).
+#[derive(Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+    stats: Arc<CacheStats>,
+}
+
+/// The caches opened by this process, so that the caches of the same
+/// directory share their statistics.
+static OPENED: Lazy<Mutex<HashMap<PathBuf, Arc<DiskCache>>>> = Lazy::new(Mutex::default);
+
+impl DiskCache {
+    /// The default directory of the cache, i.e. `typst-ts/cache` in the cache
+    /// directory of the user.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("typst-ts").join("cache"))
+    }
+
+    /// Open the cache in a directory, which is created on the first write.
+    ///
+    /// The caches of the same directory are shared in a process.
+    pub fn open(dir: impl AsRef<Path>) -> Arc<Self> {
+        let dir = dir.as_ref().to_owned();
+        (OPENED.lock().entry(dir.clone()))
+            .or_insert_with(|| {
+                Arc::new(Self {
+                    dir,
+                    stats: Arc::default(),
+                })
+            })
+            .clone()
+    }
+
+    /// Get the directory of the cache.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get the statistics of the cache.
+    pub fn stats(&self) -> Arc<CacheStats> {
+        self.stats.clone()
+    }
+
+    /// Compute the key of an entry from what the entry is derived from, e.g.
+    /// a content, or the path and mtime of a file.
+    ///
+    /// The key covers the version of the library, since the derived data may
+    /// change with it.
+    pub fn key(content: &impl Hash) -> u128 {
+        hash128(&(build_info::VERSION, content))
+    }
+
+    /// Get an entry, or derive and store it on a miss.
+    pub fn get_or_insert_with<T: Serialize + DeserializeOwned>(
+        &self,
+        kind: CacheKind,
+        key: u128,
+        derive: impl FnOnce() -> T,
+    ) -> T {
+        if let Some(value) = self.get(kind, key) {
+            return value;
+        }
+
+        let value = derive();
+        self.put(kind, key, &value);
+        value
+    }
+
+    /// Get an entry, where an unreadable entry is a miss.
+    pub fn get<T: DeserializeOwned>(&self, kind: CacheKind, key: u128) -> Option<T> {
+        let value = std::fs::read(self.entry_path(kind, key))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok());
+        self.stats.record(kind, value.is_some());
+        value
+    }
+
+    /// Store an entry atomically.
+    ///
+    /// The cache is an optimization, hence a failed write is only logged.
+    pub fn put<T: Serialize>(&self, kind: CacheKind, key: u128, value: &T) {
+        if let Err(err) = self.write(kind, key, value) {
+            log::warn!("DiskCache: failed to write {kind:?} entry {key:032x}: {err}");
+        }
+    }
+
+    fn write<T: Serialize>(&self, kind: CacheKind, key: u128, value: &T) -> std::io::Result<()> {
+        static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let path = self.entry_path(kind, key);
+        let dir = path.parent().unwrap();
+        std::fs::create_dir_all(dir)?;
+
+        let data = serde_json::to_vec(value)?;
+        let temp = dir.join(format!(
+            ".{key:032x}.{}.{}.tmp",
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&temp, data)?;
+        std::fs::rename(&temp, &path).inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp);
+        })
+    }
+
+    fn entry_path(&self, kind: CacheKind, key: u128) -> PathBuf {
+        (self.dir.join(LAYOUT_VERSION).join(kind.dir_name())).join(format!("{key:032x}.json"))
+    }
+}
+
+/// A text file persisted by [`save_contents`].
+#[derive(Serialize, Deserialize)]
+struct PersistedFile {
+    path: PathBuf,
+    mtime: Time,
+    content: String,
+}
+
+/// The contents persisted by [`save_contents`], which are only read by the
+/// same version of the library.
+#[derive(Serialize, Deserialize)]
+struct PersistedContents {
+    version: String,
+    files: Vec<PersistedFile>,
+}
+
+/// Persist the contents of the text files cached by a vfs to a file, see
+/// [`crate::vfs::Vfs::cached_contents`].
+///
+/// The binary files, e.g. images, are skipped. The file is written
+/// atomically like the entries of a [`DiskCache`].
+pub fn save_contents(path: &Path, contents: Vec<(PathBuf, Time, Bytes)>) -> ZResult<()> {
+    let files = (contents.into_iter())
+        .filter_map(|(path, mtime, content)| {
+            let content = std::str::from_utf8(&content).ok()?.to_owned();
+            Some(PersistedFile {
+                path,
+                mtime,
+                content,
+            })
+        })
+        .collect();
+    let persisted = PersistedContents {
+        version: build_info::VERSION.to_owned(),
+        files,
+    };
+
+    let data = serde_json::to_vec(&persisted).map_err(map_string_err("failed to serialize"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(map_string_err("failed to create the directory"))?;
+    }
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&temp, data).map_err(map_string_err("failed to write the contents"))?;
+    std::fs::rename(&temp, path).map_err(|err| {
+        let _ = std::fs::remove_file(&temp);
+        error_once!("failed to write the contents", err: err)
+    })
+}
+
+/// Load the contents persisted by [`save_contents`], to be restored by
+/// [`crate::vfs::Vfs::restore_contents`].
+///
+/// The contents persisted by another version of the library are ignored.
+pub fn load_contents(path: &Path) -> ZResult<Vec<(PathBuf, Time, Bytes)>> {
+    let data = std::fs::read(path).map_err(map_string_err("failed to read the contents"))?;
+    let persisted: PersistedContents =
+        serde_json::from_slice(&data).map_err(map_string_err("invalid persisted contents"))?;
+    if persisted.version != build_info::VERSION {
+        return Ok(vec![]);
+    }
+
+    Ok((persisted.files.into_iter())
+        .map(|file| {
+            (
+                file.path,
+                file.mtime,
+                Bytes::from(file.content.into_bytes()),
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::open(dir.path());
+        let stats = cache.stats();
+        assert!(Arc::ptr_eq(&stats, &DiskCache::open(dir.path()).stats()));
+
+        let kind = CacheKind::FontInfo;
+        let key = DiskCache::key(&b"content".as_slice());
+        assert_eq!(cache.get_or_insert_with(kind, key, || 1), 1);
+        assert_eq!(cache.get_or_insert_with(kind, key, || 2), 1);
+        assert_eq!((stats.hits(kind), stats.misses(kind)), (1, 1));
+        assert_eq!(stats.hit_rate(kind), Some(0.5));
+
+        // A changed content is a different entry.
+        let changed = DiskCache::key(&b"changed".as_slice());
+        assert_ne!(key, changed);
+        assert_eq!(cache.get::<i32>(kind, changed), None);
+
+        // A corrupted entry is a miss and is overwritten.
+        std::fs::write(cache.entry_path(kind, key), b"{").unwrap();
+        assert_eq!(cache.get_or_insert_with(kind, key, || 3), 3);
+        assert_eq!(cache.get::<i32>(kind, key), Some(3));
+
+        // Concurrent writers never expose a partial entry.
+        std::thread::scope(|s| {
+            for value in 0..8 {
+                let cache = &cache;
+                s.spawn(move || {
+                    for _ in 0..20 {
+                        cache.put(kind, changed, &vec![value; 256]);
+                        let read = cache.get::<Vec<i32>>(kind, changed).unwrap();
+                        assert!(read.len() == 256 && read.iter().all(|v| *v == read[0]));
+                    }
+                });
+            }
+        });
+        let entries = std::fs::read_dir(cache.entry_path(kind, key).parent().unwrap()).unwrap();
+        assert_eq!(entries.count(), 2, "temporary files are left behind");
+
+        stats.reset();
+        assert_eq!(stats.hit_rate(kind), None);
+    }
+
+    #[cfg(not(feature = "lazy-fontdb"))]
+    #[test]
+    fn test_font_info_cache() {
+        use typst_ts_core::config::CompileFontOpts;
+
+        use crate::font::system::SystemFontSearcher;
+
+        let dir = tempfile::tempdir().unwrap();
+        let font = dir.path().join("font.otf");
+        std::fs::write(&font, typst_assets::fonts().next().unwrap()).unwrap();
+
+        let cache_dir = dir.path().join("cache");
+        let search = || {
+            let mut searcher = SystemFontSearcher::new();
+            let opts = CompileFontOpts {
+                font_paths: vec![font.clone()],
+                no_system_fonts: true,
+                cache_dir: Some(cache_dir.clone()),
+                ..CompileFontOpts::default()
+            };
+            searcher.resolve_opts(opts).unwrap();
+            assert!(searcher.load_errors.is_empty());
+            searcher.fonts.len()
+        };
+        let stats = DiskCache::open(&cache_dir).stats();
+        let kind = CacheKind::FontInfo;
+
+        let faces = search() as u64;
+        assert!(faces > 0);
+        assert_eq!(
+            stats.snapshot(kind),
+            CacheHits {
+                hits: 0,
+                misses: faces
+            }
+        );
+        search();
+        assert_eq!(
+            stats.snapshot(kind),
+            CacheHits {
+                hits: faces,
+                misses: faces
+            }
+        );
+
+        // A touched file is parsed again.
+        let mtime = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&font)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        search();
+        assert_eq!(
+            stats.snapshot(kind),
+            CacheHits {
+                hits: faces,
+                misses: 2 * faces
+            }
+        );
+    }
+}
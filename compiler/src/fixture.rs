@@ -0,0 +1,74 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use tempfile::TempDir;
+use typst::foundations::Bytes;
+use typst_ts_core::config::{compiler::EntryOpts, CompileOpts};
+
+use crate::{service::CompileDriver, ShadowApi, TypstSystemWorld};
+
+/// A workspace in a unique temporary directory, so that the tests running in
+/// parallel don't share files. The directory is removed on drop.
+pub(crate) struct TestWorkspace {
+    dir: TempDir,
+}
+
+impl TestWorkspace {
+    pub fn new() -> Self {
+        let dir = tempfile::Builder::new().prefix("typst-ts-").tempdir();
+        Self { dir: dir.unwrap() }
+    }
+
+    pub fn root(&self) -> PathBuf {
+        self.dir.path().to_owned()
+    }
+
+    /// Get the path of a file in the workspace.
+    pub fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.dir.path().join(path)
+    }
+
+    /// Write a file in the workspace, creating its parent directories.
+    pub fn write(&self, path: impl AsRef<Path>, content: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// The options of a world rooted at the workspace, whose entry is
+    /// `main.typ` and which only has the embedded fonts.
+    pub fn opts(&self) -> CompileOpts {
+        Self::opts_at(self.root())
+    }
+
+    /// The same options as [`Self::opts`], but for a workspace owned by
+    /// someone else, e.g. by the process spawning the test.
+    pub fn opts_at(root: PathBuf) -> CompileOpts {
+        CompileOpts {
+            entry: EntryOpts::new_rooted(root, Some("main.typ".into())),
+            no_system_fonts: true,
+            with_embedded_fonts: typst_assets::fonts().map(Cow::Borrowed).collect(),
+            ..CompileOpts::default()
+        }
+    }
+
+    pub fn world(&self) -> TypstSystemWorld {
+        TypstSystemWorld::new(self.opts()).unwrap()
+    }
+
+    /// Create a driver compiling `main.typ`.
+    pub fn driver(&self) -> CompileDriver {
+        CompileDriver::new(self.world()).with_entry_file(self.path("main.typ"))
+    }
+
+    /// Create a driver compiling `main.typ`, which only exists in memory.
+    pub fn shadow_driver(&self, content: impl AsRef<[u8]>) -> CompileDriver {
+        let driver = self.driver();
+        let content = Bytes::from(content.as_ref());
+        driver.map_shadow(&self.path("main.typ"), content).unwrap();
+        driver
+    }
+}
@@ -1,16 +1,22 @@
+use core::fmt;
 use std::{
     borrow::Cow,
     collections::HashMap,
     fs::File,
+    ops::{Deref, DerefMut},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
+use comemo::Prehashed;
 use fontdb::Database;
+use once_cell::sync::OnceCell;
 use sha2::{Digest, Sha256};
 use typst::{
     diag::{FileError, FileResult},
-    text::{FontBook, FontInfo},
+    text::{Font, FontBook, FontInfo},
 };
 
 use typst_ts_core::{
@@ -19,13 +25,16 @@ use typst_ts_core::{
     debug_loc::{DataSource, MemoryDataSource},
     error::prelude::ZResult,
     font::{
-        BufferFontLoader, FontProfile, FontProfileItem, FontResolverImpl, LazyBufferFontLoader,
-        PartialFontBook,
+        BufferFontLoader, FontLoadError, FontOrigin, FontProfile, FontProfileItem,
+        FontResolverImpl, LazyBufferFontLoader, PartialFontBook,
     },
-    Bytes, FontSlot,
+    Bytes, FontResolver, FontSlot,
 };
 
-use crate::vfs::system::LazyFile;
+use crate::{
+    cache::{CacheKind, DiskCache},
+    vfs::system::LazyFile,
+};
 
 #[derive(Debug, Default)]
 struct FontProfileRebuilder {
@@ -87,7 +96,13 @@ pub struct SystemFontSearcher {
 
     pub book: FontBook,
     pub fonts: Vec<FontSlot>,
+    /// The fonts skipped since they fail to load, which doesn't fail the
+    /// search of the other fonts.
+    pub load_errors: Vec<FontLoadError>,
     profile_rebuilder: FontProfileRebuilder,
+    /// The cache of the metadata of the font faces.
+    #[cfg_attr(feature = "lazy-fontdb", allow(dead_code))]
+    cache: Option<Arc<DiskCache>>,
 }
 
 impl SystemFontSearcher {
@@ -102,12 +117,18 @@ impl SystemFontSearcher {
             db,
             book: FontBook::new(),
             fonts: vec![],
+            load_errors: vec![],
             profile_rebuilder,
+            cache: None,
         }
     }
 
     /// Resolve fonts from given options.
     pub fn resolve_opts(&mut self, opts: CompileFontOpts) -> ZResult<()> {
+        if let Some(dir) = &opts.cache_dir {
+            self.set_cache(Some(DiskCache::open(dir)));
+        }
+
         if opts
             .font_profile_cache_path
             .to_str()
@@ -119,12 +140,19 @@ impl SystemFontSearcher {
 
         // Note: the order of adding fonts is important.
         // See: https://github.com/typst/typst/blob/9c7f31870b4e1bf37df79ebbe1df9a56df83d878/src/font/book.rs#L151-L154
+        // Fonts are sorted by path on each flush, so we flush after each
+        // path to keep the priority of paths.
         // Source1: add the fonts specified by the user.
         for path in opts.font_paths {
+            let start = self.fonts.len();
             if path.is_dir() {
                 self.search_dir(&path);
-            } else {
-                let _ = self.search_file(&path);
+            } else if let Err(err) = self.search_file(&path) {
+                self.skip_font(path.display().to_string(), err.to_string());
+            }
+            self.flush();
+            for slot in &mut self.fonts[start..] {
+                slot.origin = FontOrigin::User;
             }
         }
         // Source2: add the fonts from system paths.
@@ -146,6 +174,12 @@ impl SystemFontSearcher {
         Ok(())
     }
 
+    /// Set the cache of the metadata of the font faces, which is looked up by
+    /// the paths and mtimes of the font files on [`Self::flush`].
+    pub fn set_cache(&mut self, cache: Option<Arc<DiskCache>>) {
+        self.cache = cache;
+    }
+
     pub fn set_can_profile(&mut self, can_profile: bool) {
         self.profile_rebuilder.can_profile = can_profile;
     }
@@ -184,10 +218,15 @@ impl SystemFontSearcher {
         // println!("profile_rebuilder init took {:?}", end - begin);
     }
 
+    /// Parse the faces in the database and add them to the book.
+    ///
+    /// The faces are parsed in parallel and sorted by path and index, so
+    /// that the font indices are deterministic.
     #[cfg(feature = "lazy-fontdb")]
     pub fn flush(&mut self) {
         use rayon::prelude::*;
-        self.db
+        let results = self
+            .db
             .lazy_faces()
             .enumerate()
             .par_bridge()
@@ -237,9 +276,11 @@ impl SystemFontSearcher {
                 let info = match cache_state {
                     Some(cache_state) => cache_state.info,
                     None => {
-                        let info = face
-                            .with_data(|data| FontInfo::new(data, face.index()))
-                            .expect("database must contain this font");
+                        let Some(info) = face.with_data(|data| FontInfo::new(data, face.index()))
+                        else {
+                            let message = "failed to read the font file".to_owned();
+                            return Some(Err((path.display().to_string(), message)));
+                        };
                         std::fs::create_dir_all(cache_state_path.parent().unwrap()).unwrap();
 
                         let info = CacheStateValue { info, mtime };
@@ -252,56 +293,110 @@ impl SystemFontSearcher {
 
                 // println!("searched font: {idx} {:?}", path);
 
-                Some((
-                    info?,
+                let Some(info) = info else {
+                    let message = format!("failed to parse the face at index {}", face.index());
+                    return Some(Err((path.display().to_string(), message)));
+                };
+                Some(Ok((
+                    (path.to_owned(), face.index()),
+                    info,
                     FontSlot::new_boxed(LazyBufferFontLoader::new(
                         LazyFile::new(path.to_owned()),
                         face.index(),
-                    )),
-                ))
+                    ))
+                    .with_origin(FontOrigin::System),
+                )))
             })
-            .collect::<Vec<_>>()
-            .into_iter()
-            .for_each(|(info, font)| {
-                self.book.push(info);
-                self.fonts.push(font);
-            });
+            .collect::<Vec<_>>();
+
+        let (mut faces, mut skipped) = (vec![], vec![]);
+        for result in results {
+            match result {
+                Ok(face) => faces.push(face),
+                Err(err) => skipped.push(err),
+            }
+        }
+
+        // The skipped fonts are sorted as well to report them deterministically.
+        skipped.sort();
+        for (source, message) in skipped {
+            self.skip_font(source, message);
+        }
+        faces.sort_by(|(x, ..), (y, ..)| x.cmp(y));
+        for (_, info, font) in faces {
+            self.book.push(info);
+            self.fonts.push(font);
+        }
 
         self.db = Database::new();
     }
 
+    /// Parse the faces in the database and add them to the book.
+    ///
+    /// The faces are parsed in parallel and sorted by path and index, so
+    /// that the font indices are deterministic.
     #[cfg(not(feature = "lazy-fontdb"))]
     pub fn flush(&mut self) {
         use fontdb::Source;
         use typst_ts_core::debug_loc::FsDataSource;
 
-        for face in self.db.faces() {
-            let path = match &face.source {
-                Source::File(path) | Source::SharedFile(path, _) => path,
-                // We never add binary sources to the database, so there
-                // shouln't be any.
-                Source::Binary(_) => unreachable!(),
+        let mut faces: Vec<_> = (self.db.faces())
+            .map(|face| {
+                let path = match &face.source {
+                    Source::File(path) | Source::SharedFile(path, _) => path,
+                    // We never add binary sources to the database, so there
+                    // shouln't be any.
+                    Source::Binary(_) => unreachable!(),
+                };
+                (path.clone(), face.index, face.id)
+            })
+            .collect();
+        faces.sort_by(|(x, x_idx, _), (y, y_idx, _)| (x, x_idx).cmp(&(y, y_idx)));
+
+        let (db, cache) = (&self.db, self.cache.as_deref());
+        let infos = par_map(&faces, |(path, index, id)| {
+            let parse = || db.with_face_data(*id, FontInfo::new);
+            // The faces are keyed by their files rather than their data, so
+            // that a hit reads no font file.
+            let mtime = std::fs::metadata(path).and_then(|meta| meta.modified());
+            let (Some(cache), Ok(mtime)) = (cache, mtime) else {
+                return parse();
             };
-
-            let info = self
-                .db
-                .with_face_data(face.id, FontInfo::new)
-                .expect("database must contain this font");
-
-            // println!("searched font: {idx} {:?}", path);
-
-            if let Some(info) = info {
-                self.book.push(info);
-                self.fonts.push(
-                    FontSlot::new_boxed(LazyBufferFontLoader::new(
-                        LazyFile::new(path.clone()),
-                        face.index,
-                    ))
-                    .describe(DataSource::Fs(FsDataSource {
-                        path: path.to_str().unwrap_or_default().to_owned(),
-                    })),
-                );
+            let key = DiskCache::key(&(path, mtime, index));
+            if let Some(info) = cache.get(CacheKind::FontInfo, key) {
+                return Some(info);
             }
+            // An unreadable file is not cached, unlike an unparsable face.
+            let info = parse()?;
+            cache.put(CacheKind::FontInfo, key, &info);
+            Some(info)
+        });
+
+        for ((path, index, _), info) in faces.into_iter().zip(infos) {
+            // A file may be removed or changed since it is indexed.
+            let info = match info {
+                Some(Some(info)) => info,
+                Some(None) => {
+                    let message = format!("failed to parse the face at index {index}");
+                    self.skip_font(path.display().to_string(), message);
+                    continue;
+                }
+                None => {
+                    let message = "failed to read the font file".to_owned();
+                    self.skip_font(path.display().to_string(), message);
+                    continue;
+                }
+            };
+
+            let description = DataSource::Fs(FsDataSource {
+                path: path.to_str().unwrap_or_default().to_owned(),
+            });
+            self.book.push(info);
+            self.fonts.push(
+                FontSlot::new_boxed(LazyBufferFontLoader::new(LazyFile::new(path), index))
+                    .describe(description)
+                    .with_origin(FontOrigin::System),
+            );
         }
 
         self.db = Database::new();
@@ -313,6 +408,7 @@ impl SystemFontSearcher {
             panic!("dirty font search state, please flush the searcher before adding memory fonts");
         }
 
+        let start = self.fonts.len();
         for (index, info) in FontInfo::iter(&data).enumerate() {
             self.book.push(info.clone());
             self.fonts.push(
@@ -325,6 +421,16 @@ impl SystemFontSearcher {
                 })),
             );
         }
+        if self.fonts.len() == start {
+            let message = format!("no font face is parsed from {} bytes", data.len());
+            self.skip_font("<memory>".to_owned(), message);
+        }
+    }
+
+    /// Skip a font which fails to load, see [`Self::load_errors`].
+    fn skip_font(&mut self, source: String, message: String) {
+        log::warn!("SystemFontSearcher: skipped font {source}: {message}");
+        self.load_errors.push(FontLoadError { source, message });
     }
 
     pub fn search_system(&mut self) {
@@ -350,6 +456,32 @@ impl Default for SystemFontSearcher {
     }
 }
 
+/// Map items with scoped threads, keeping their order.
+///
+/// It runs on the current thread if the platform has no threads, e.g. on
+/// wasm.
+#[cfg(not(feature = "lazy-fontdb"))]
+fn par_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let chunk_size = items.len().div_ceil(threads);
+    let f = &f;
+    std::thread::scope(|s| {
+        let handles: Vec<_> = (items.chunks(chunk_size))
+            .map(|chunk| s.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+            .collect();
+        (handles.into_iter())
+            .flat_map(|h| {
+                h.join()
+                    .unwrap_or_else(|err| std::panic::resume_unwind(err))
+            })
+            .collect()
+    })
+}
+
 impl From<SystemFontSearcher> for FontResolverImpl {
     fn from(searcher: SystemFontSearcher) -> Self {
         // let profile_item = match
@@ -372,5 +504,174 @@ impl From<SystemFontSearcher> for FontResolverImpl {
             searcher.fonts,
             searcher.profile_rebuilder.profile,
         )
+        .with_load_errors(searcher.load_errors)
+    }
+}
+
+/// A font resolver which searches fonts in a background thread.
+///
+/// Searching system fonts takes a while, so the world doesn't wait for it on
+/// creation. The first font lookup, usually in layout, waits for the search
+/// instead, hence sources are read and evaluated in the meantime.
+///
+/// Cloning the resolver is cheap and the clones share the same fonts, which
+/// are searched and loaded only once, e.g. by the worlds of several
+/// [`crate::service::CompileActor`]s.
+#[derive(Clone)]
+pub struct LazyFontResolver {
+    inner: Arc<LazyFonts>,
+}
+
+struct LazyFonts {
+    /// The options of the search, if the fonts are searched from options.
+    opts: Option<CompileFontOpts>,
+    search: Mutex<Option<JoinHandle<(FontResolverImpl, Duration)>>>,
+    resolved: OnceCell<(FontResolverImpl, Duration)>,
+}
+
+// The fonts are shared by worlds in different threads.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<LazyFontResolver>();
+};
+
+impl LazyFontResolver {
+    /// Start searching fonts from the given options.
+    pub fn spawn(opts: CompileFontOpts) -> Self {
+        let search_opts = opts.clone();
+        let search = std::thread::spawn(move || {
+            let opts = search_opts;
+            let begin = Instant::now();
+            let mut searcher = SystemFontSearcher::new();
+            if let Err(err) = searcher.resolve_opts(opts) {
+                searcher.skip_font("<options>".to_owned(), err.to_string());
+            }
+            let elapsed = begin.elapsed();
+            log::debug!("LazyFontResolver: resolved fonts in {elapsed:?}");
+
+            (FontResolverImpl::from(searcher), elapsed)
+        });
+
+        Self {
+            inner: Arc::new(LazyFonts {
+                opts: Some(opts),
+                search: Mutex::new(Some(search)),
+                resolved: OnceCell::new(),
+            }),
+        }
+    }
+
+    /// Start searching fonts again with other font paths, e.g. when the font
+    /// paths of a project change.
+    ///
+    /// Returns `None` if the fonts are not searched from options.
+    pub fn respawn_with_font_paths(&self, font_paths: Vec<PathBuf>) -> Option<Self> {
+        let opts = self.inner.opts.clone()?;
+        Some(Self::spawn(CompileFontOpts { font_paths, ..opts }))
+    }
+
+    fn resolved(&self) -> &(FontResolverImpl, Duration) {
+        self.inner.resolved.get_or_init(|| {
+            let search = self.inner.search.lock().unwrap().take();
+            let search = search.expect("font search is either pending or resolved");
+            search
+                .join()
+                .unwrap_or_else(|err| std::panic::resume_unwind(err))
+        })
+    }
+
+    /// Get the resolved fonts, waiting for the search if necessary.
+    pub fn get(&self) -> &FontResolverImpl {
+        &self.resolved().0
+    }
+
+    /// Get the resolved fonts mutably, waiting for the search if necessary.
+    ///
+    /// Panics if the fonts are shared with a clone of the resolver.
+    pub fn get_mut(&mut self) -> &mut FontResolverImpl {
+        self.resolved();
+        let inner = Arc::get_mut(&mut self.inner).expect("cannot mutate shared fonts");
+        &mut inner.resolved.get_mut().unwrap().0
+    }
+
+    /// Get the time spent on searching fonts, or `None` if no font has been
+    /// looked up yet.
+    pub fn search_time(&self) -> Option<Duration> {
+        self.inner.resolved.get().map(|(_, elapsed)| *elapsed)
+    }
+}
+
+impl From<FontResolverImpl> for LazyFontResolver {
+    fn from(resolver: FontResolverImpl) -> Self {
+        Self {
+            inner: Arc::new(LazyFonts {
+                opts: None,
+                search: Mutex::new(None),
+                resolved: OnceCell::with_value((resolver, Duration::ZERO)),
+            }),
+        }
+    }
+}
+
+impl From<SystemFontSearcher> for LazyFontResolver {
+    fn from(searcher: SystemFontSearcher) -> Self {
+        FontResolverImpl::from(searcher).into()
+    }
+}
+
+impl Deref for LazyFontResolver {
+    type Target = FontResolverImpl;
+
+    fn deref(&self) -> &Self::Target {
+        self.get()
+    }
+}
+
+impl DerefMut for LazyFontResolver {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.get_mut()
+    }
+}
+
+impl FontResolver for LazyFontResolver {
+    fn font_book(&self) -> &Prehashed<FontBook> {
+        self.get().font_book()
+    }
+
+    fn font(&self, idx: usize) -> Option<Font> {
+        self.get().font(idx)
+    }
+
+    fn get_by_info(&self, info: &FontInfo) -> Option<Font> {
+        self.get().get_by_info(info)
+    }
+
+    fn loaded_font_bytes(&self) -> usize {
+        // Don't wait for the search, since no font is loaded before it.
+        (self.inner.resolved.get()).map_or(0, |(resolver, _)| resolver.loaded_font_bytes())
+    }
+
+    fn describe_font(&self, font: &Font) -> Option<Arc<DataSource>> {
+        let (resolver, _) = self.inner.resolved.get()?;
+        FontResolverImpl::describe_font(resolver, font)
+    }
+
+    fn font_origin(&self, idx: usize) -> Option<FontOrigin> {
+        self.get().font_origin(idx)
+    }
+
+    fn load_errors(&self) -> Vec<FontLoadError> {
+        self.get().load_errors()
+    }
+}
+
+impl fmt::Debug for LazyFontResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LazyFontResolver")
+            .field(
+                "resolved",
+                &self.inner.resolved.get().map(|(resolver, _)| resolver),
+            )
+            .finish_non_exhaustive()
     }
 }
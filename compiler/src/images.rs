@@ -0,0 +1,411 @@
+//! Guard the resources used by images.
+//!
+//! A document embedding a huge image, e.g. a 200-megapixel photo, makes typst
+//! allocate gigabytes to decode it, and the exported artifact is too large to
+//! be loaded by browsers. The world applies the [`ImageLimits`] when it loads
+//! the image files, which are recognized by their extensions:
+//!
+//! - An image with more pixels than [`ImageLimits::max_pixels`] is rejected,
+//!   which typst reports at the span of the image. Only the header of the
+//!   image is read to check it.
+//! - An image whose width or height exceeds [`ImageLimits::max_dimension`] is
+//!   downscaled to fit, before it enters the document and the artifacts.
+//! - An animated image is handled by [`ImageLimits::animated`].
+//!
+//! The guarded images are cached by the hashes of their content and the
+//! limits, see [`crate::vfs::cached::CachedAccessModel::guard_image`], so
+//! that recompilations don't decode them again. The downscaled images and the
+//! first frames of animated images are reported as warnings of the
+//! compilations, and all the decisions are reported by [`ImageDecision`]s.
+//!
+//! All the limits are unset by default, which serves the images as is. The
+//! limits are only applied with the `image-limits` feature, which pulls the
+//! decoders of the `image` crate, otherwise the images are served as is
+//! whatever the limits.
+
+use std::collections::HashMap;
+#[cfg(feature = "image-limits")]
+use std::{io::Cursor, path::Path};
+
+#[cfg(feature = "image-limits")]
+use image::{
+    codecs::gif::GifDecoder, imageops::FilterType, io::Reader, AnimationDecoder, ImageFormat,
+    ImageOutputFormat,
+};
+use typst::{
+    diag::{eco_format, SourceDiagnostic},
+    eval::Tracer,
+    layout::{Frame, FrameItem},
+    model::Document,
+    syntax::Span,
+};
+use typst_ts_core::{
+    hash::hash128,
+    vector::stats::{ImageAction, ImageDecision},
+    Bytes, TypstFileId as FileId,
+};
+
+/// The quality of the downscaled JPEG images.
+#[cfg(feature = "image-limits")]
+const JPEG_QUALITY: u8 = 90;
+
+/// The handling of animated images, i.e. GIFs with multiple frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AnimatedImagePolicy {
+    /// Serve animated images as is. Typst only draws their first frames, but
+    /// the artifacts embed all the frames.
+    #[default]
+    Keep,
+    /// Only serve the first frames of animated images, with a warning.
+    FirstFrame,
+    /// Reject animated images.
+    Reject,
+}
+
+/// The limits of the images loaded by the world, see the
+/// [module docs](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ImageLimits {
+    /// The maximum number of decoded pixels of an image.
+    pub max_pixels: Option<u64>,
+    /// The maximum width and height of an image in pixels, beyond which the
+    /// image is downscaled to fit.
+    pub max_dimension: Option<u32>,
+    /// The handling of animated images.
+    pub animated: AnimatedImagePolicy,
+}
+
+impl ImageLimits {
+    /// Whether none of the limits is set.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn with_max_pixels(mut self, max: Option<u64>) -> Self {
+        self.max_pixels = max;
+        self
+    }
+
+    pub fn with_max_dimension(mut self, max: Option<u32>) -> Self {
+        self.max_dimension = max;
+        self
+    }
+
+    pub fn with_animated(mut self, policy: AnimatedImagePolicy) -> Self {
+        self.animated = policy;
+        self
+    }
+}
+
+/// An image guarded by the limits.
+#[derive(Debug, Clone)]
+pub struct GuardedImage {
+    /// The data served to typst, which is the original data unless the image
+    /// is transformed.
+    pub data: Bytes,
+    /// The width of the original image in pixels.
+    pub width: u32,
+    /// The height of the original image in pixels.
+    pub height: u32,
+    pub action: ImageAction,
+}
+
+impl GuardedImage {
+    /// Describe the decision on the image of a file.
+    pub fn decision(&self, id: FileId) -> ImageDecision {
+        ImageDecision {
+            path: display_path(id),
+            width: self.width,
+            height: self.height,
+            action: self.action.clone(),
+        }
+    }
+}
+
+/// Get the format of an image file by its extension.
+#[cfg(feature = "image-limits")]
+pub fn image_format(path: &Path) -> Option<ImageFormat> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "png" => Some(ImageFormat::Png),
+        "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+        "gif" => Some(ImageFormat::Gif),
+        _ => None,
+    }
+}
+
+/// Apply the limits to the data of an image, see the [module docs](self).
+///
+/// Data which isn't an image of the format is not guarded, hence gets `None`,
+/// and typst reports the errors of decoding it.
+#[cfg(feature = "image-limits")]
+pub fn guard_image(
+    data: &Bytes,
+    format: ImageFormat,
+    limits: &ImageLimits,
+) -> Option<GuardedImage> {
+    let reader = || Reader::with_format(Cursor::new(data.as_slice()), format);
+    let (width, height) = reader().into_dimensions().ok()?;
+    let guarded = |data: Bytes, action| GuardedImage {
+        data,
+        width,
+        height,
+        action,
+    };
+    let reject = |reason: String| Some(guarded(data.clone(), ImageAction::Rejected { reason }));
+
+    let pixels = u64::from(width) * u64::from(height);
+    if let Some(max) = limits.max_pixels.filter(|max| pixels > *max) {
+        return reject(format!(
+            "image of {width}x{height} pixels exceeds the configured limit of {max} pixels"
+        ));
+    }
+
+    let animated = format == ImageFormat::Gif && is_animated(data);
+    if animated && limits.animated == AnimatedImagePolicy::Reject {
+        return reject("animated images are rejected by the configured policy".to_owned());
+    }
+
+    let first_frame = animated && limits.animated == AnimatedImagePolicy::FirstFrame;
+    let target = limits.max_dimension.filter(|max| width.max(height) > *max);
+    if !first_frame && target.is_none() {
+        return Some(guarded(data.clone(), ImageAction::Kept));
+    }
+
+    // Decoding an animated image decodes its first frame. The image is
+    // encoded in the same format again, since typst decodes images by the
+    // extensions of their files.
+    let transformed = reader().decode().ok().and_then(|mut image| {
+        if let Some(max) = target {
+            image = image.resize(max, max, FilterType::Lanczos3);
+        }
+        let output = match format {
+            ImageFormat::Jpeg => ImageOutputFormat::Jpeg(JPEG_QUALITY),
+            ImageFormat::Gif => ImageOutputFormat::Gif,
+            _ => ImageOutputFormat::Png,
+        };
+        let mut buf = Cursor::new(vec![]);
+        image.write_to(&mut buf, output).ok()?;
+        Some((buf.into_inner(), image.width(), image.height()))
+    });
+    let Some((transformed, to_width, to_height)) = transformed else {
+        return Some(guarded(data.clone(), ImageAction::Kept));
+    };
+
+    let action = if first_frame {
+        ImageAction::FirstFrame {
+            width: to_width,
+            height: to_height,
+        }
+    } else {
+        ImageAction::Downscaled {
+            width: to_width,
+            height: to_height,
+        }
+    };
+    Some(guarded(Bytes::from(transformed), action))
+}
+
+/// Whether a GIF has multiple frames.
+#[cfg(feature = "image-limits")]
+fn is_animated(data: &Bytes) -> bool {
+    let Ok(decoder) = GifDecoder::new(Cursor::new(data.as_slice())) else {
+        return false;
+    };
+    decoder.into_frames().take(2).count() > 1
+}
+
+/// Report the transformed images of a compilation as warnings at the spans
+/// of the images in the document.
+pub fn warn_guarded_images(
+    document: &Document,
+    images: &[(FileId, GuardedImage)],
+    tracer: &mut Tracer,
+) {
+    let transformed: Vec<_> = (images.iter())
+        .filter(|(_, image)| {
+            matches!(
+                image.action,
+                ImageAction::Downscaled { .. } | ImageAction::FirstFrame { .. }
+            )
+        })
+        .collect();
+    if transformed.is_empty() {
+        return;
+    }
+
+    let mut spans = HashMap::new();
+    for page in &document.pages {
+        image_spans(&page.frame, &mut spans);
+    }
+
+    for (id, image) in transformed {
+        let span = (spans.get(&hash128(&image.data)).copied()).unwrap_or_else(Span::detached);
+        let path = display_path(*id);
+        let (width, height) = (image.width, image.height);
+        let warning = match image.action {
+            ImageAction::Downscaled {
+                width: to_width,
+                height: to_height,
+            } => SourceDiagnostic::warning(
+                span,
+                eco_format!(
+                    "image {path} of {width}x{height} pixels is downscaled to \
+                     {to_width}x{to_height} pixels"
+                ),
+            )
+            .with_hint("the image exceeds the configured maximum dimension"),
+            _ => SourceDiagnostic::warning(
+                span,
+                eco_format!("only the first frame of the animated image {path} is used"),
+            ),
+        };
+        tracer.warn(warning);
+    }
+}
+
+/// Collect the spans of the images in a frame by the hashes of their data.
+fn image_spans(frame: &Frame, spans: &mut HashMap<u128, Span>) {
+    for (_, item) in frame.items() {
+        match item {
+            FrameItem::Group(group) => image_spans(&group.frame, spans),
+            FrameItem::Image(image, _, span) if !span.is_detached() => {
+                spans.entry(hash128(image.data())).or_insert(*span);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn display_path(id: FileId) -> String {
+    id.vpath().as_rootless_path().display().to_string()
+}
+
+#[cfg(all(test, feature = "image-limits"))]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Bytes {
+        let image = RgbImage::from_pixel(width, height, Rgb([200, 100, 50]));
+        let mut buf = Cursor::new(vec![]);
+        image.write_to(&mut buf, ImageOutputFormat::Png).unwrap();
+        Bytes::from(buf.into_inner())
+    }
+
+    #[test]
+    fn test_guard_image() {
+        let data = png(400, 100);
+        let unlimited = ImageLimits::default();
+        let kept = guard_image(&data, ImageFormat::Png, &unlimited).unwrap();
+        assert_eq!(kept.action, ImageAction::Kept);
+        assert_eq!(kept.data, data);
+
+        let limits = ImageLimits::default().with_max_pixels(Some(10_000));
+        let rejected = guard_image(&data, ImageFormat::Png, &limits).unwrap();
+        assert!(matches!(rejected.action, ImageAction::Rejected { .. }));
+
+        // The aspect ratio is kept by downscaling.
+        let limits = ImageLimits::default().with_max_dimension(Some(200));
+        let downscaled = guard_image(&data, ImageFormat::Png, &limits).unwrap();
+        assert_eq!(
+            downscaled.action,
+            ImageAction::Downscaled {
+                width: 200,
+                height: 50
+            }
+        );
+        assert_eq!((downscaled.width, downscaled.height), (400, 100));
+        let dimensions =
+            Reader::with_format(Cursor::new(downscaled.data.as_slice()), ImageFormat::Png)
+                .into_dimensions()
+                .unwrap();
+        assert_eq!(dimensions, (200, 50));
+
+        assert!(
+            guard_image(&Bytes::from_static(b"not a png"), ImageFormat::Png, &limits).is_none()
+        );
+        assert_eq!(
+            image_format(Path::new("figures/photo.JPG")),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(image_format(Path::new("data.csv")), None);
+    }
+
+    #[cfg(feature = "system-compile")]
+    #[test]
+    fn test_guard_images_of_world() {
+        use crate::{
+            fixture::TestWorkspace,
+            service::{CompileDriver, CompileEnv, Compiler, EnvWorld},
+            ShadowApi,
+        };
+
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let main = ws.path("main.typ");
+        let mut world = ws.world();
+        world.set_image_limits(ImageLimits::default().with_max_dimension(Some(64)));
+        let mut driver = CompileDriver::new(world).with_entry_file(main.clone());
+
+        driver
+            .map_shadow(&root.join("wide.png"), png(256, 128))
+            .unwrap();
+        let content = Bytes::from_static(b"#image(\"wide.png\")");
+        driver.map_shadow(&main, content).unwrap();
+
+        let mut env = CompileEnv {
+            tracer: Some(Default::default()),
+            ..Default::default()
+        };
+        let doc = driver.compile(&mut env).unwrap();
+        let warnings = env.tracer.unwrap().warnings();
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert!(
+            warnings[0].message.contains("downscaled to 64x32"),
+            "{warnings:?}"
+        );
+        assert!(!warnings[0].span.is_detached());
+
+        // The laid out image is the downscaled one.
+        let mut spans = HashMap::new();
+        image_spans(&doc.pages[0].frame, &mut spans);
+        assert_eq!(spans.len(), 1);
+        let guarded = &driver.world.guarded_images()[0].1;
+        assert!(spans.contains_key(&hash128(&guarded.data)));
+        let decisions = driver.world.image_decisions();
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].path, "wide.png");
+
+        // The images identical after being downscaled share their data in
+        // the artifacts.
+        driver
+            .map_shadow(&root.join("wider.png"), png(512, 256))
+            .unwrap();
+        let content = Bytes::from_static(
+            b"#image(\"wide.png\", width: 2cm)\n#image(\"wider.png\", width: 4cm)",
+        );
+        driver.map_shadow(&main, content).unwrap();
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+        let stats = crate::service::vector_artifact_stats(&driver.world, &doc, 0).unwrap();
+        assert_eq!(stats.images.len(), 1, "{:?}", stats.images);
+        assert_eq!(stats.images[0].items, 2);
+        assert_eq!((stats.images[0].width, stats.images[0].height), (64, 32));
+        let paths: Vec<_> = (stats.image_decisions.iter())
+            .map(|decision| decision.path.as_str())
+            .collect();
+        assert_eq!(paths, ["wide.png", "wider.png"]);
+
+        // A rejected image fails the compilation at the span of the image.
+        driver
+            .world
+            .set_image_limits(ImageLimits::default().with_max_pixels(Some(1000)));
+        let errors = driver.compile(&mut CompileEnv::default()).unwrap_err();
+        assert!(
+            errors[0].message.contains("exceeds the configured limit"),
+            "{errors:?}"
+        );
+        assert!(!errors[0].span.is_detached());
+    }
+}
@@ -10,6 +10,7 @@
 //!   - [`font::FontSlot`]: the way to load a font.
 //!   - [`vfs::AccessModel`]: how the compiler accesses a storage.
 //!   - [`package::Registry`]: how the compiler obtains data about a package.
+//!   - [`package::PackageResolver`]: how the compiler overrides the registry.
 //!
 //! - [`world`]: The world is the core part of the library, which maintains all
 //!   the data for typst compilation.
@@ -42,10 +43,15 @@ pub(crate) mod macros;
 /// font things about compiler.
 pub mod font;
 
+/// Guard the resources used by images.
+pub mod images;
+
 /// Dependency things about compiler
 pub mod dependency;
 /// package things about compiler.
 pub mod package;
+/// Map between the ids, the paths and the URIs of files.
+pub mod path_mapper;
 /// time things about compiler.
 pub mod time;
 /// A vfs implementation for compiler.
@@ -61,16 +67,39 @@ mod utils;
 /// Convenient services over [`world::CompilerWorld`].
 pub mod service;
 
+/// Measure the performance of compilations.
+///
+/// It is enabled by the `bench` feature, which the `bench` binary requires,
+/// and in tests.
+#[cfg(any(feature = "bench", all(test, feature = "system-compile")))]
+pub mod bench;
+
+/// Persist derived data across process restarts.
+#[cfg(feature = "system-compile")]
+pub mod cache;
+
 /// Run the compiler in the system environment.
 #[cfg(feature = "system-compile")]
 pub(crate) mod system;
+
+/// The workspaces of the documents compiled by the tests.
+#[cfg(all(test, feature = "system-compile"))]
+pub(crate) mod fixture;
+
 use std::{
+    ops::Range,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 #[cfg(feature = "system-compile")]
-pub use system::TypstSystemWorld;
+pub use system::{
+    SystemCompilerFeat, TracedSystemCompilerFeat, TracedSystemWorld, TypstSystemWorld,
+};
+
+/// Run the compiler over in-memory sources.
+pub(crate) mod memory;
+pub use memory::{InMemoryCompilerFeat, InMemoryWorldBuilder, TypstMemoryWorld};
 
 /// Run the compiler in the browser environment.
 #[cfg(feature = "browser-compile")]
@@ -108,6 +137,11 @@ pub trait ShadowApi {
     /// Add a shadow file to the driver.
     fn unmap_shadow(&self, path: &Path) -> FileResult<()>;
 
+    /// Replace a byte range of a shadow file with the given text.
+    ///
+    /// Returns the length of the edited content in bytes.
+    fn edit_shadow(&self, path: &Path, range: Range<usize>, text: &str) -> FileResult<usize>;
+
     /// Add a shadow file to the driver by file id.
     /// Note: to enable this function, `ShadowApi` must implement
     /// `_shadow_map_id`.
@@ -180,4 +214,10 @@ pub trait NotifyApi {
     fn iter_dependencies<'a>(&'a self, f: &mut dyn FnMut(&'a ImmutPath, FileResult<&crate::Time>));
 
     fn notify_fs_event(&mut self, event: FilesystemEvent);
+
+    /// Drop the caches of the given files, which are no longer depended on.
+    fn evict_files(&mut self, paths: &[ImmutPath]);
+
+    /// Drop the caches of the files which are not accessed within the age.
+    fn evict_stale_files(&mut self, age: std::time::Duration);
 }
@@ -6,3 +6,30 @@ macro_rules! static_assert {
 }
 
 pub(crate) use static_assert;
+
+/// Enter a span of the compile pipeline, which is a no-op unless the `tracing`
+/// feature is enabled. The arguments are passed to [`tracing::info_span`].
+macro_rules! pipeline_span {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!($($arg)*).entered();
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::macros::NoopSpan;
+        span
+    }};
+}
+
+/// The span entered by [`pipeline_span`] without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoopSpan;
+
+/// Record a field of a span entered by [`pipeline_span`].
+macro_rules! pipeline_record {
+    ($span:expr, $field:literal, $value:expr) => {
+        #[cfg(feature = "tracing")]
+        $span.record($field, $value);
+    };
+}
+
+pub(crate) use pipeline_record;
+pub(crate) use pipeline_span;
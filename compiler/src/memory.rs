@@ -0,0 +1,205 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use comemo::Prehashed;
+use typst::{foundations::Dict, syntax::VirtualPath};
+use typst_ts_core::{
+    config::compiler::EntryState, error::prelude::*, font::FontResolverImpl,
+    package::dummy::DummyRegistry, Bytes, ImmutPath, TypstFileId,
+};
+
+use crate::{
+    font::pure::MemoryFontBuilder,
+    package::PackageResolver,
+    service::CompileDriverImpl,
+    vfs::{archive::ArchiveAccessModel, Vfs},
+    world::{CompilerFeat, CompilerWorld},
+};
+
+/// The directory where the in-memory files are mounted.
+const MEMORY_ROOT: &str = "/@workspace";
+
+#[derive(Debug, Clone, Copy)]
+pub struct InMemoryCompilerFeat;
+
+impl CompilerFeat for InMemoryCompilerFeat {
+    /// Uses [`FontResolverImpl`] directly.
+    type FontResolver = FontResolverImpl;
+    /// Serves the in-memory files, see [`ArchiveAccessModel::from_files`].
+    type AccessModel = ArchiveAccessModel;
+    type Registry = DummyRegistry;
+}
+
+/// A world that compiles in-memory sources, see [`InMemoryWorldBuilder`].
+pub type TypstMemoryWorld = CompilerWorld<InMemoryCompilerFeat>;
+
+/// Build a world from in-memory sources, without access to the file system or
+/// the network.
+///
+/// The paths of the files are virtual paths, e.g. `main.typ` or `/lib/a.typ`,
+/// which are resolved against the root of the workspace. The files are still
+/// editable by the [`crate::ShadowApi`] of the built world.
+///
+/// ```ignore
+/// let driver = InMemoryWorldBuilder::new()
+///     .with_file("main.typ", b"#import \"lib.typ\": title\n= #title".as_slice())
+///     .with_file("lib.typ", b"#let title = [Hello]".as_slice())
+///     .with_main("main.typ")
+///     .with_fonts(typst_assets::fonts().map(Bytes::from_static))
+///     .build()?;
+/// let (actor, client) = CompileActor::new(driver).split();
+/// ```
+#[derive(Default)]
+pub struct InMemoryWorldBuilder {
+    files: Vec<(PathBuf, Bytes)>,
+    main: Option<PathBuf>,
+    fonts: Vec<Bytes>,
+    inputs: Dict,
+    package_resolver: Option<Arc<dyn PackageResolver>>,
+}
+
+impl InMemoryWorldBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, which replaces a file added at the same path.
+    pub fn with_file(mut self, path: impl AsRef<Path>, content: impl Into<Bytes>) -> Self {
+        self.files.push((vpath(path.as_ref()), content.into()));
+        self
+    }
+
+    /// Add files from a map of virtual paths to their contents.
+    pub fn with_files<P: AsRef<Path>, C: Into<Bytes>>(
+        mut self,
+        files: impl IntoIterator<Item = (P, C)>,
+    ) -> Self {
+        (self.files).extend(
+            (files.into_iter()).map(|(path, content)| (vpath(path.as_ref()), content.into())),
+        );
+        self
+    }
+
+    /// Set the main file, which must be one of the added files.
+    pub fn with_main(mut self, path: impl AsRef<Path>) -> Self {
+        self.main = Some(vpath(path.as_ref()));
+        self
+    }
+
+    /// Add the data of font files, where each file may contain several faces.
+    ///
+    /// Without any fonts, the text of the document is not rendered.
+    pub fn with_fonts(mut self, fonts: impl IntoIterator<Item = Bytes>) -> Self {
+        self.fonts.extend(fonts);
+        self
+    }
+
+    /// Set the inputs visible through `sys.inputs`.
+    pub fn with_inputs(mut self, inputs: Dict) -> Self {
+        self.inputs = inputs;
+        self
+    }
+
+    /// Resolve packages, e.g. by a
+    /// [`crate::package::memory::MemoryPackageResolver`]. Otherwise, importing
+    /// a package fails.
+    pub fn with_package_resolver(mut self, resolver: Arc<dyn PackageResolver>) -> Self {
+        self.package_resolver = Some(resolver);
+        self
+    }
+
+    /// Build the world, where the main file is the entry.
+    pub fn build_world(self) -> ZResult<TypstMemoryWorld> {
+        let main = self
+            .main
+            .ok_or_else(|| error_once!("the main file of the in-memory world is not set"))?;
+        if !self.files.iter().any(|(path, _)| *path == main) {
+            return Err(error_once!(
+                "the main file is not one of the in-memory files",
+                main: main.display()
+            ));
+        }
+
+        let root: ImmutPath = Path::new(MEMORY_ROOT).into();
+        let main = TypstFileId::new(None, VirtualPath::new(&main));
+        let mut fonts = MemoryFontBuilder::new();
+        for font in self.fonts {
+            fonts.add_memory_font(font);
+        }
+
+        let mut world = TypstMemoryWorld::new_raw(
+            EntryState::new_rooted(root.clone(), Some(main)),
+            Vfs::new(ArchiveAccessModel::from_files(root, self.files)),
+            DummyRegistry,
+            fonts.into(),
+        );
+        world.set_inputs(Arc::new(Prehashed::new(self.inputs)));
+        world.set_package_resolver(self.package_resolver);
+        Ok(world)
+    }
+
+    /// Build a driver of the world, which is ready for
+    /// `CompileActor::new`.
+    pub fn build(self) -> ZResult<CompileDriverImpl<TypstMemoryWorld>> {
+        // The main file is checked by `build_world`.
+        let main = Path::new(MEMORY_ROOT).join(self.main.clone().unwrap_or_default());
+        Ok(CompileDriverImpl::new(self.build_world()?).with_entry_file(main))
+    }
+}
+
+/// Normalize a virtual path to a path relative to the root.
+fn vpath(path: &Path) -> PathBuf {
+    VirtualPath::new(path).as_rootless_path().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        service::{CompileEnv, Compiler},
+        ShadowApi,
+    };
+
+    #[test]
+    fn test_build_in_memory_world() {
+        let err = InMemoryWorldBuilder::new()
+            .with_file("lib.typ", b"".as_slice())
+            .with_main("main.typ")
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("not one of the in-memory files"));
+
+        let mut driver = InMemoryWorldBuilder::new()
+            .with_files([
+                (
+                    "/main.typ",
+                    b"#import \"lib/util.typ\": greet\n#greet(\"memory\")".as_slice(),
+                ),
+                (
+                    "lib/util.typ",
+                    b"#let greet(name) = [Hello, #name!]".as_slice(),
+                ),
+            ])
+            .with_main("main.typ")
+            .with_fonts(typst_assets::fonts().map(Bytes::from_static))
+            .build()
+            .unwrap();
+
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+        assert_eq!(doc.pages.len(), 1);
+
+        // The in-memory files can be edited like the files on disk.
+        let lib = Path::new(MEMORY_ROOT).join("lib/util.typ");
+        (driver.world)
+            .map_shadow(
+                &lib,
+                Bytes::from(b"#let greet(name) = [#name #pagebreak() #name]".as_slice()),
+            )
+            .unwrap();
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+        assert_eq!(doc.pages.len(), 2);
+    }
+}
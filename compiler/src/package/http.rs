@@ -1,4 +1,9 @@
-use std::{cell::OnceCell, path::Path, sync::Arc};
+use std::{
+    cell::OnceCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use log::error;
 use parking_lot::Mutex;
@@ -14,6 +19,11 @@ pub struct HttpRegistry {
     notifier: Arc<Mutex<dyn Notifier + Send>>,
 
     packages: OnceCell<Vec<(PackageSpec, Option<EcoString>)>>,
+    /// Whether to only use the packages available on disk.
+    offline: bool,
+    /// The local directories of packages, which take precedence over the
+    /// downloaded packages.
+    overrides: HashMap<PackageSpec, Arc<Path>>,
 }
 
 impl Default for HttpRegistry {
@@ -23,11 +33,37 @@ impl Default for HttpRegistry {
 
             // todo: reset cache
             packages: OnceCell::new(),
+            offline: false,
+            overrides: HashMap::new(),
         }
     }
 }
 
 impl HttpRegistry {
+    /// Only use the packages available on disk, never downloading packages or
+    /// the package index.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Resolve packages to local directories, e.g. to develop a package along
+    /// with a document using it.
+    pub fn with_overrides(
+        mut self,
+        overrides: impl IntoIterator<Item = (PackageSpec, PathBuf)>,
+    ) -> Self {
+        self.set_overrides(overrides);
+        self
+    }
+
+    /// Replace the local directories of packages, see [`Self::with_overrides`].
+    pub fn set_overrides(&mut self, overrides: impl IntoIterator<Item = (PackageSpec, PathBuf)>) {
+        self.overrides = (overrides.into_iter())
+            .map(|(spec, dir)| (spec, dir.into()))
+            .collect();
+    }
+
     pub fn local_path(&self) -> Option<Box<Path>> {
         if let Some(data_dir) = dirs::data_dir() {
             if data_dir.exists() {
@@ -59,6 +95,10 @@ impl HttpRegistry {
 
     /// Make a package available in the on-disk cache.
     pub fn prepare_package(&self, spec: &PackageSpec) -> Result<Arc<Path>, PackageError> {
+        if let Some(dir) = self.overrides.get(spec) {
+            return Ok(dir.clone());
+        }
+
         let subdir = format!(
             "typst/packages/{}/{}/{}",
             spec.namespace, spec.name, spec.version
@@ -75,7 +115,7 @@ impl HttpRegistry {
             let dir = cache_dir.join(&subdir);
 
             // Download from network if it doesn't exist yet.
-            if spec.namespace == "preview" && !dir.exists() {
+            if spec.namespace == "preview" && !dir.exists() && !self.offline {
                 self.download_package(spec, &dir)?;
             }
 
@@ -122,6 +162,10 @@ impl Registry for HttpRegistry {
     }
 
     fn packages(&self) -> &[(PackageSpec, Option<EcoString>)] {
+        if self.offline {
+            return &[];
+        }
+
         self.packages.get_or_init(|| {
             let url = "https://packages.typst.org/preview/index.json";
 
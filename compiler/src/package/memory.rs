@@ -0,0 +1,123 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use typst::{
+    diag::{FileError, FileResult},
+    syntax::VirtualPath,
+};
+use typst_ts_core::Bytes;
+
+use super::{PackageError, PackageResolver, PackageSpec};
+
+/// Resolves packages from an in-memory store, which is useful in sandboxed or
+/// offline environments.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryPackageResolver {
+    packages: HashMap<PackageSpec, HashMap<PathBuf, Bytes>>,
+}
+
+impl MemoryPackageResolver {
+    /// Add a file to a package, the path is relative to the package root.
+    pub fn add_file(&mut self, spec: PackageSpec, path: impl AsRef<Path>, content: Bytes) {
+        let path = VirtualPath::new(path).as_rootless_path().to_owned();
+        self.packages.entry(spec).or_default().insert(path, content);
+    }
+
+    /// Builder variant of [`Self::add_file`].
+    pub fn with_file(mut self, spec: PackageSpec, path: impl AsRef<Path>, content: Bytes) -> Self {
+        self.add_file(spec, path, content);
+        self
+    }
+}
+
+impl PackageResolver for MemoryPackageResolver {
+    fn resolve(&self, spec: &PackageSpec) -> Result<Arc<Path>, PackageError> {
+        if !self.packages.contains_key(spec) {
+            return Err(PackageError::NotFound(spec.clone()));
+        }
+
+        let root = Path::new("/@memory")
+            .join(spec.namespace.as_str())
+            .join(spec.name.as_str())
+            .join(spec.version.to_string());
+        Ok(root.into())
+    }
+
+    fn file(&self, spec: &PackageSpec, path: &VirtualPath) -> Option<FileResult<Bytes>> {
+        let Some(files) = self.packages.get(spec) else {
+            return Some(Err(PackageError::NotFound(spec.clone()).into()));
+        };
+
+        let path = path.as_rootless_path();
+        Some(
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| FileError::NotFound(path.to_owned())),
+        )
+    }
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use std::sync::Arc;
+
+    use typst::{eval::Tracer, syntax::VirtualPath};
+    use typst_ts_core::TypstFileId;
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileEnv, EnvWorld},
+        ShadowApi,
+    };
+
+    #[test]
+    fn test_import_memory_package() {
+        let spec: PackageSpec = "@preview/hello:0.1.0".parse().unwrap();
+        let resolver = MemoryPackageResolver::default()
+            .with_file(
+                spec.clone(),
+                "typst.toml",
+                Bytes::from_static(
+                    br#"[package]
+name = "hello"
+version = "0.1.0"
+entrypoint = "lib.typ"
+authors = ["typst.ts"]
+license = "Apache-2.0"
+description = "A package in memory."
+"#,
+                ),
+            )
+            .with_file(
+                spec.clone(),
+                "lib.typ",
+                Bytes::from_static(b"#let greet() = [Hello]"),
+            );
+
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let main = TypstFileId::new(None, VirtualPath::new("main.typ"));
+        let mut world = ws.world();
+        world.set_package_resolver(Some(Arc::new(resolver)));
+        world
+            .map_shadow(
+                &root.join("main.typ"),
+                Bytes::from_static(b"#import \"@preview/hello:0.1.0\": greet\n#greet()"),
+            )
+            .unwrap();
+        world.prepare_env(&mut CompileEnv::default()).unwrap();
+
+        let lib_id = TypstFileId::new(Some(spec), VirtualPath::new("lib.typ"));
+        let lib = typst::World::source(&world, lib_id).unwrap();
+        assert_eq!(lib.text(), "#let greet() = [Hello]");
+
+        assert_eq!(typst::World::main(&world).id(), main);
+        let res = typst::compile(&world, &mut Tracer::new());
+        assert!(res.is_ok(), "{:?}", res.err());
+    }
+}
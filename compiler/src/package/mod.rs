@@ -1,4 +1,8 @@
+use std::{fmt, path::Path, sync::Arc};
+
+use typst::{diag::FileResult, syntax::VirtualPath};
 pub use typst_ts_core::package::{PackageError, PackageSpec, Registry};
+use typst_ts_core::Bytes;
 
 #[cfg(feature = "browser-compile")]
 pub mod browser;
@@ -6,6 +10,29 @@ pub mod browser;
 #[cfg(feature = "system-compile")]
 pub mod http;
 
+pub mod memory;
+
+/// Resolves `@namespace/name:version` packages for
+/// [`crate::world::CompilerWorld`].
+///
+/// When no resolver is set to the world, packages are resolved by the
+/// [`Registry`] of the world, which reads package files from the file system.
+pub trait PackageResolver: fmt::Debug + Send + Sync {
+    /// Resolve the root directory of a package.
+    ///
+    /// The path is also used to identify the package files in diagnostics and
+    /// shadow files, so it doesn't need to exist when [`Self::file`] serves
+    /// the files.
+    fn resolve(&self, spec: &PackageSpec) -> Result<Arc<Path>, PackageError>;
+
+    /// Read a file of a package without touching the file system.
+    ///
+    /// Returns `None` to read the file under the resolved root instead.
+    fn file(&self, _spec: &PackageSpec, _path: &VirtualPath) -> Option<FileResult<Bytes>> {
+        None
+    }
+}
+
 pub trait Notifier {
     fn downloading(&self, _spec: &PackageSpec) {}
 }
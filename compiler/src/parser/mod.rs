@@ -3,6 +3,8 @@ mod semantic_tokens;
 // mod token_encode;
 mod typst_tokens;
 
+use std::ops::Range;
+
 use typst::{diag::FileResult, syntax::Source};
 
 use typst_ts_core::TypstFileId;
@@ -22,6 +24,60 @@ pub fn reparse(source_id: TypstFileId, prev: Option<Source>, next: String) -> Fi
     }
 }
 
+/// How much of the syntax tree of a source is reused by an incremental
+/// reparse, see [`reparse_with_edits`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReparseStats {
+    /// The bytes of the new text whose nodes are reused.
+    pub reused_bytes: usize,
+    /// The bytes of the new text which are reparsed.
+    pub reparsed_bytes: usize,
+}
+
+impl ReparseStats {
+    fn new(len: usize, reparsed_bytes: usize) -> Self {
+        let reparsed_bytes = reparsed_bytes.min(len);
+        Self {
+            reused_bytes: len - reparsed_bytes,
+            reparsed_bytes,
+        }
+    }
+}
+
+/// Reparse a source by replaying the byte-range edits which turn the text of
+/// `prev` into `next` with [`Source::edit`], along with the extent of the
+/// reparse.
+///
+/// Falls back to [`reparse`] if the edits don't produce `next`, which is
+/// counted as reparsing the whole text.
+pub fn reparse_with_edits(
+    source_id: TypstFileId,
+    prev: Option<Source>,
+    next: String,
+    edits: &[(Range<usize>, String)],
+) -> FileResult<(Source, ReparseStats)> {
+    if let Some(prev) = &prev {
+        let mut source = prev.clone();
+        let mut reparsed_bytes = 0;
+        let applied = edits.iter().all(|(range, text)| {
+            // `Source::edit` panics on invalid ranges.
+            if source.text().get(range.clone()).is_none() {
+                return false;
+            }
+            reparsed_bytes += source.edit(range.clone(), text).len();
+            true
+        });
+        if applied && source.text() == next {
+            let stats = ReparseStats::new(next.len(), reparsed_bytes);
+            return Ok((source, stats));
+        }
+    }
+
+    let len = next.len();
+    let source = reparse(source_id, prev, next)?;
+    Ok((source, ReparseStats::new(len, len)))
+}
+
 #[cfg(test)]
 mod tests {
     use typst::syntax::VirtualPath;
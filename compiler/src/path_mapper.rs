@@ -0,0 +1,416 @@
+//! Map between the ids of files, their paths and their URIs consistently, e.g.
+//! to resolve the file of a cursor sent by an editor, and to report back the
+//! file of a diagnostic or a jump.
+//!
+//! A [`PathMapper`] of a world, see [`CompilerWorld::path_mapper`], handles
+//! the kinds of files explicitly:
+//! - a file under a root of the workspace maps to its path and its `file:`
+//!   URI, including the files under the extra roots and the files under no
+//!   root, see [`PathMapper::id_for_path`],
+//! - a file of a package maps to its path in the package cache, and to a
+//!   `typst-package:` URI, e.g. `typst-package:@preview/cetz:0.2.0/lib.typ`,
+//!   or the `file:` URI of its path, see [`PackageUris`],
+//! - an untitled buffer maps to its URI, e.g. `untitled:Untitled-1`, but to
+//!   no path, see [`CompilerWorld::register_untitled`],
+//! - the detached file maps to neither a path nor a URI.
+//!
+//! The `file:` URIs are percent-encoded, so that the paths with spaces and
+//! non-ASCII characters survive, including the paths which aren't valid
+//! UTF-8 on unix, and the paths with drive letters on windows, e.g.
+//! `file:///C:/My%20Files/main.typ`.
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use typst::{diag::FileResult, syntax::VirtualPath};
+use typst_ts_core::{
+    config::compiler::DETACHED_ENTRY, error::prelude::*, package::PackageSpec, TypstFileId,
+};
+
+use crate::world::{is_pseudo_package, CompilerFeat, CompilerWorld};
+
+/// The scheme of the URIs of package files, see [`PackageUris::Scheme`].
+pub const PACKAGE_SCHEME: &str = "typst-package:";
+
+/// How the files of packages map to URIs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PackageUris {
+    /// Map to `typst-package:` URIs of the specs of the packages, which are
+    /// independent of where the packages are cached.
+    #[default]
+    Scheme,
+    /// Map to the `file:` URIs of the paths in the package cache, which an
+    /// editor is able to open. Such a URI maps back to the id of the path,
+    /// which isn't that of the package.
+    Location,
+}
+
+/// A view of a world mapping between the ids, the paths and the URIs of
+/// files, see the [module docs](self).
+pub struct PathMapper<'w, F: CompilerFeat> {
+    world: &'w CompilerWorld<F>,
+    package_uris: PackageUris,
+}
+
+impl<F: CompilerFeat> CompilerWorld<F> {
+    /// Get a mapper of the files of the world, see [`PathMapper`].
+    pub fn path_mapper(&self) -> PathMapper<'_, F> {
+        PathMapper {
+            world: self,
+            package_uris: PackageUris::default(),
+        }
+    }
+}
+
+impl<F: CompilerFeat> PathMapper<'_, F> {
+    pub fn with_package_uris(mut self, package_uris: PackageUris) -> Self {
+        self.package_uris = package_uris;
+        self
+    }
+
+    /// Get the id of a file by its path, or by the URI of an untitled buffer.
+    ///
+    /// A relative path is resolved against the primary root, and an absolute
+    /// path against the longest root containing it. The files under the
+    /// extra roots and under no root are identified by pseudo packages, see
+    /// [`crate::world::EXTRA_ROOT_NAMESPACE`] and
+    /// [`crate::world::ABSOLUTE_PATH_NAMESPACE`].
+    pub fn id_for_path(&self, path: &Path) -> ZResult<TypstFileId> {
+        (self.lookup_id(path))
+            .ok_or_else(|| error_once!("the path has no file id", path: path.display()))
+    }
+
+    /// Get the id of a file by its path like [`Self::id_for_path`].
+    pub(crate) fn lookup_id(&self, path: &Path) -> Option<TypstFileId> {
+        self.world.resolve_id(path)
+    }
+
+    /// Get the path by which the vfs keys a file, which the untitled buffers
+    /// and the detached file have as well, unlike [`Self::path_for_id`].
+    pub(crate) fn vfs_path(&self, id: TypstFileId) -> FileResult<PathBuf> {
+        self.world.resolve_path(id)
+    }
+
+    /// Get the path of a file, which is the inverse of [`Self::id_for_path`].
+    ///
+    /// The detached file and the untitled buffers have no paths.
+    pub fn path_for_id(&self, id: TypstFileId) -> ZResult<PathBuf> {
+        if id == *DETACHED_ENTRY {
+            return Err(error_once!("the detached file has no path"));
+        }
+        if let Some(uri) = self.world.untitled_uri(id) {
+            return Err(error_once!("the untitled buffer has no path", uri: uri));
+        }
+        (self.vfs_path(id)).map_err(
+            |err| error_once!("failed to resolve the path", id: format!("{id:?}"), err: err),
+        )
+    }
+
+    /// Get the path of a file to display, e.g. in diagnostics, which is the
+    /// URI of an untitled buffer and the virtual path of the detached file.
+    pub fn display_path(&self, id: TypstFileId) -> ZResult<String> {
+        if id == *DETACHED_ENTRY {
+            return Ok(id.vpath().as_rooted_path().to_string_lossy().into_owned());
+        }
+        match self.world.untitled_uri(id) {
+            Some(uri) => Ok(uri.to_owned()),
+            None => Ok(self.path_for_id(id)?.to_string_lossy().into_owned()),
+        }
+    }
+
+    /// Get the URI of a file.
+    ///
+    /// The detached file has no URI.
+    pub fn uri_for_id(&self, id: TypstFileId) -> ZResult<String> {
+        if id == *DETACHED_ENTRY {
+            return Err(error_once!("the detached file has no uri"));
+        }
+        if let Some(uri) = self.world.untitled_uri(id) {
+            return Ok(uri.to_owned());
+        }
+        match id.package() {
+            Some(spec) if !is_pseudo_package(spec) && self.package_uris == PackageUris::Scheme => {
+                let path = id.vpath().as_rooted_path().to_string_lossy();
+                Ok(format!("{PACKAGE_SCHEME}{spec}{}", encode(path.as_bytes())))
+            }
+            _ => path_to_uri(&self.path_for_id(id)?),
+        }
+    }
+
+    /// Get the id of a file by its URI, which is the inverse of
+    /// [`Self::uri_for_id`].
+    pub fn id_for_uri(&self, uri: &str) -> ZResult<TypstFileId> {
+        if let Some(id) = self.world.untitled_id(uri) {
+            return Ok(id);
+        }
+        if let Some(rest) = uri.strip_prefix(PACKAGE_SCHEME) {
+            // The spec contains the first slash, e.g. `@preview/cetz:0.2.0`.
+            let end = (rest.match_indices('/').nth(1)).map_or(rest.len(), |(idx, _)| idx);
+            let spec = PackageSpec::from_str(&rest[..end])
+                .map_err(|err| error_once!("invalid package uri", uri: uri, err: err))?;
+            let path = String::from_utf8(decode(&rest[end..])?)
+                .map_err(|_| error_once!("invalid package uri", uri: uri))?;
+            return Ok(TypstFileId::new(Some(spec), VirtualPath::new(path)));
+        }
+        if uri.starts_with("file:") {
+            return self.id_for_path(&uri_to_path(uri)?);
+        }
+
+        Err(error_once!("unsupported uri", uri: uri))
+    }
+}
+
+/// Convert an absolute path to a `file:` URI.
+pub fn path_to_uri(path: &Path) -> ZResult<String> {
+    if !path.is_absolute() {
+        return Err(error_once!("the path is not absolute", path: path.display()));
+    }
+    Ok(path_bytes_to_uri(&path_bytes(path)?, cfg!(windows)))
+}
+
+/// Convert a `file:` URI to an absolute path.
+///
+/// A URI with a host other than `localhost`, e.g. of a network share, isn't
+/// supported.
+pub fn uri_to_path(uri: &str) -> ZResult<PathBuf> {
+    bytes_to_path(uri_to_path_bytes(uri, cfg!(windows))?)
+}
+
+fn path_bytes_to_uri(path: &[u8], windows: bool) -> String {
+    if !windows {
+        return format!("file://{}", encode(path));
+    }
+
+    // The drive of a path, e.g. `C:\`, is led by a slash in a URI.
+    let path: Vec<u8> = (path.iter())
+        .map(|&b| if b == b'\\' { b'/' } else { b })
+        .collect();
+    let slash = if path.starts_with(b"/") { "" } else { "/" };
+    format!("file://{slash}{}", encode(&path))
+}
+
+fn uri_to_path_bytes(uri: &str, windows: bool) -> ZResult<Vec<u8>> {
+    let rest =
+        (uri.strip_prefix("file://")).ok_or_else(|| error_once!("not a file uri", uri: uri))?;
+    let path = match rest.find('/') {
+        Some(0) => rest,
+        Some(idx) if &rest[..idx] == "localhost" => &rest[idx..],
+        _ => return Err(error_once!("unsupported host of a file uri", uri: uri)),
+    };
+    // The query and the fragment aren't a part of the path.
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let path = decode(path)?;
+    if !windows {
+        return Ok(path);
+    }
+
+    // Strip the slash leading the drive, e.g. of `/C:/`.
+    let has_drive = path.len() >= 3 && path[1].is_ascii_alphabetic() && path[2] == b':';
+    let path = if has_drive { &path[1..] } else { &path[..] };
+    Ok((path.iter())
+        .map(|&b| if b == b'/' { b'\\' } else { b })
+        .collect())
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> ZResult<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(path.as_os_str().as_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> ZResult<Vec<u8>> {
+    (path.to_str())
+        .map(|path| path.as_bytes().to_vec())
+        .ok_or_else(|| error_once!("the path is not valid unicode", path: path.display()))
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: Vec<u8>) -> ZResult<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(std::ffi::OsString::from_vec(bytes).into())
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: Vec<u8>) -> ZResult<PathBuf> {
+    (String::from_utf8(bytes))
+        .map(PathBuf::from)
+        .map_err(|_| error_once!("the path of the uri is not valid unicode"))
+}
+
+/// Percent-encode the bytes of a path, keeping the unreserved characters,
+/// the slashes and the colons of drives.
+fn encode(path: &[u8]) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for &b in path {
+        if b.is_ascii_alphanumeric() || b"-._~/:".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+fn decode(encoded: &str) -> ZResult<Vec<u8>> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let hex = (encoded.get(i + 1..i + 3))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| error_once!("invalid percent-encoding", uri: encoded))?;
+        decoded.push(hex);
+        i += 3;
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The number of generated cases of each property.
+    const CASES: usize = 512;
+
+    /// A generator of random paths, whose segments mix spaces, unicode,
+    /// reserved characters of URIs and percent signs. It is seeded, so that
+    /// a failing case reproduces.
+    struct PathGen(u64);
+
+    impl PathGen {
+        fn next(&mut self) -> u64 {
+            // xorshift64
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        /// Generate a relative path of one to four segments.
+        fn path(&mut self, sep: char) -> String {
+            const CHARS: &[char] = &[
+                'a', 'Z', '0', '.', '-', '_', '~', ' ', '%', '#', '?', '&', '+', '=', ':', '@',
+                '[', ']', 'ü', 'é', '日', '本', '🦀',
+            ];
+            let segments = 1 + self.below(4);
+            let mut path = String::new();
+            for i in 0..segments {
+                if i > 0 {
+                    path.push(sep);
+                }
+                // A segment starts with a letter, hence it is never `.` or `..`.
+                path.push('m');
+                for _ in 0..self.below(8) {
+                    path.push(CHARS[self.below(CHARS.len())]);
+                }
+            }
+            path
+        }
+
+        /// Generate a drive letter of windows, in either case.
+        fn drive(&mut self) -> char {
+            let letter = (b'A' + self.below(26) as u8) as char;
+            match self.below(2) {
+                0 => letter,
+                _ => letter.to_ascii_lowercase(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_file_uri_round_trip() {
+        let mut gen = PathGen(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..CASES {
+            let path = format!("/{}", gen.path('/'));
+            let uri = path_bytes_to_uri(path.as_bytes(), false);
+            assert!(uri.starts_with("file:///"), "{uri}");
+            assert!(!uri.contains([' ', '#', '?', '[', ']']), "{uri}");
+            assert_eq!(uri_to_path_bytes(&uri, false).unwrap(), path.as_bytes());
+        }
+
+        for _ in 0..CASES {
+            let drive = gen.drive();
+            let path = format!("{drive}:\\{}", gen.path('\\'));
+            let uri = path_bytes_to_uri(path.as_bytes(), true);
+            assert!(uri.starts_with(&format!("file:///{drive}:/")), "{uri}");
+            assert_eq!(uri_to_path_bytes(&uri, true).unwrap(), path.as_bytes());
+        }
+
+        // The encodings of other tools are accepted.
+        let path = uri_to_path_bytes("file:///c%3A/My%20Files/main.typ", true).unwrap();
+        assert_eq!(path, b"c:\\My Files\\main.typ");
+        let path = uri_to_path_bytes("file://localhost/a%20b", false).unwrap();
+        assert_eq!(path, b"/a b");
+        assert!(uri_to_path_bytes("file://server/share/a.typ", true).is_err());
+        assert!(uri_to_path_bytes("file:///a%2", false).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_path_uri() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let path = Path::new(OsStr::from_bytes(b"/invalid \xff.typ"));
+        let uri = path_to_uri(path).unwrap();
+        assert_eq!(uri, "file:///invalid%20%FF.typ");
+        assert_eq!(uri_to_path(&uri).unwrap(), path);
+    }
+
+    #[cfg(feature = "system-compile")]
+    #[test]
+    fn test_path_mapper_round_trip() {
+        use crate::fixture::TestWorkspace;
+
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let mut world = ws.world();
+        let untitled = world.register_untitled("untitled:Untitled-1", root.as_path().into());
+        let mapper = world.path_mapper();
+
+        // The files under the root and under no root.
+        let mut gen = PathGen(0x2545_f491_4f6c_dd1d);
+        let outside = root.parent().unwrap().join("outside");
+        for _ in 0..CASES {
+            let path = gen.path('/');
+            for path in [root.join(&path), outside.join(&path)] {
+                let id = mapper.id_for_path(&path).unwrap();
+                assert_eq!(mapper.path_for_id(id).unwrap(), path);
+                let uri = mapper.uri_for_id(id).unwrap();
+                assert!(uri.starts_with("file:///"), "{uri}");
+                assert_eq!(mapper.id_for_uri(&uri).unwrap(), id, "{uri}");
+            }
+        }
+
+        // The files of packages.
+        let spec = PackageSpec::from_str("@preview/cetz:0.2.0").unwrap();
+        for _ in 0..CASES {
+            let id = TypstFileId::new(Some(spec.clone()), VirtualPath::new(gen.path('/')));
+            let uri = mapper.uri_for_id(id).unwrap();
+            assert!(
+                uri.starts_with("typst-package:@preview/cetz:0.2.0/"),
+                "{uri}"
+            );
+            assert_eq!(mapper.id_for_uri(&uri).unwrap(), id, "{uri}");
+        }
+
+        // The untitled buffers and the detached file.
+        assert_eq!(mapper.uri_for_id(untitled).unwrap(), "untitled:Untitled-1");
+        assert_eq!(mapper.id_for_uri("untitled:Untitled-1").unwrap(), untitled);
+        assert!(mapper.path_for_id(untitled).is_err());
+        assert!(mapper.id_for_uri("untitled:Untitled-2").is_err());
+        assert!(mapper.uri_for_id(*DETACHED_ENTRY).is_err());
+        assert!(mapper.path_for_id(*DETACHED_ENTRY).is_err());
+    }
+}
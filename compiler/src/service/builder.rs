@@ -0,0 +1,532 @@
+//! Assemble compile drivers of the system environment with less boilerplate.
+//!
+//! [`CompileDriverBuilder`] wires the access model, the fonts, the package
+//! registry, the root and the entry file of a world, and validates the
+//! combination before building it. All the problems are reported by a single
+//! error, so that a misconfiguration is fixed at once instead of one option at
+//! a time.
+//!
+//! The options of the builder take precedence over those of the project file,
+//! see [`super::project`].
+//!
+//! The low-level constructors, e.g. [`crate::world::CompilerWorld::new_raw`]
+//! and [`crate::TypstSystemWorld::new`], remain public for other setups.
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use comemo::Prehashed;
+use typst::foundations::Dict;
+use typst_ts_core::{
+    config::{compiler::EntryState, CompileFontOpts},
+    error::prelude::*,
+    path::PathClean,
+};
+
+use super::{
+    library::LibraryHook,
+    limits::CompileLimits,
+    project::{ProjectConfig, ProjectState, PROJECT_FILE},
+    CompileDriverImpl,
+};
+use crate::{
+    cache::DiskCache,
+    font::system::LazyFontResolver,
+    images::ImageLimits,
+    package::http::HttpRegistry,
+    system::{SystemCompilerFeat, TracedSystemCompilerFeat},
+    vfs::{
+        system::SystemAccessModel,
+        trace::{TraceAccessModel, TraceStats},
+        Vfs,
+    },
+    world::{CompilerFeat, CompilerWorld},
+};
+
+/// Build a [`CompileDriverImpl`] of the system environment, see the
+/// [module docs](self).
+///
+/// The entry file is resolved against the root, which defaults to the
+/// directory of the entry file. The system fonts are searched by default.
+pub struct CompileDriverBuilder<F: CompilerFeat = SystemCompilerFeat> {
+    access_model: F::AccessModel,
+    root: Option<PathBuf>,
+    entry: Option<PathBuf>,
+    extra_roots: Vec<PathBuf>,
+    font_paths: Vec<PathBuf>,
+    system_fonts: bool,
+    embedded_fonts: Vec<Cow<'static, [u8]>>,
+    registry: Option<HttpRegistry>,
+    offline: bool,
+    inputs: Dict,
+    creation_timestamp: Option<DateTime<Utc>>,
+    timezone: Option<FixedOffset>,
+    limits: CompileLimits,
+    image_limits: ImageLimits,
+    library_hook: Option<LibraryHook>,
+    cache_dir: Option<PathBuf>,
+    project_file: Option<PathBuf>,
+    discover_project: bool,
+}
+
+impl Default for CompileDriverBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompileDriverBuilder {
+    /// Create a builder of a [`super::CompileDriver`].
+    pub fn new() -> Self {
+        Self {
+            access_model: SystemAccessModel,
+            root: None,
+            entry: None,
+            extra_roots: vec![],
+            font_paths: vec![],
+            system_fonts: true,
+            embedded_fonts: vec![],
+            registry: None,
+            offline: false,
+            inputs: Dict::new(),
+            creation_timestamp: None,
+            timezone: None,
+            limits: CompileLimits::default(),
+            image_limits: ImageLimits::default(),
+            library_hook: None,
+            cache_dir: None,
+            project_file: None,
+            discover_project: false,
+        }
+    }
+
+    /// Count the accesses to the file system, which are read by `trace_stats`
+    /// of the returned builder.
+    pub fn with_trace_access(self) -> CompileDriverBuilder<TracedSystemCompilerFeat> {
+        CompileDriverBuilder {
+            access_model: TraceAccessModel::counting(self.access_model),
+            root: self.root,
+            entry: self.entry,
+            extra_roots: self.extra_roots,
+            font_paths: self.font_paths,
+            system_fonts: self.system_fonts,
+            embedded_fonts: self.embedded_fonts,
+            registry: self.registry,
+            offline: self.offline,
+            inputs: self.inputs,
+            creation_timestamp: self.creation_timestamp,
+            timezone: self.timezone,
+            limits: self.limits,
+            image_limits: self.image_limits,
+            library_hook: self.library_hook,
+            cache_dir: self.cache_dir,
+            project_file: self.project_file,
+            discover_project: self.discover_project,
+        }
+    }
+}
+
+impl CompileDriverBuilder<TracedSystemCompilerFeat> {
+    /// Get the statistics of the accesses to the file system, which are shared
+    /// with the built driver.
+    pub fn trace_stats(&self) -> Arc<TraceStats> {
+        self.access_model.stats()
+    }
+}
+
+impl<F> CompileDriverBuilder<F>
+where
+    F: CompilerFeat<FontResolver = LazyFontResolver, Registry = HttpRegistry>,
+{
+    /// Set the root of the workspace, which defaults to the directory of the
+    /// entry file.
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Set the entry file, which is relative to the root if it is relative.
+    ///
+    /// A workspace without an entry file can still be compiled by mutating
+    /// the entry of the world later.
+    pub fn entry(mut self, entry: impl Into<PathBuf>) -> Self {
+        self.entry = Some(entry.into());
+        self
+    }
+
+    /// Add extra roots of the workspace besides the root.
+    pub fn extra_roots(mut self, roots: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.extra_roots.extend(roots);
+        self
+    }
+
+    /// Add files or directories of fonts to search.
+    pub fn font_paths(mut self, paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.font_paths.extend(paths);
+        self
+    }
+
+    /// Set whether to search the fonts installed in the system.
+    pub fn with_system_fonts(mut self, system_fonts: bool) -> Self {
+        self.system_fonts = system_fonts;
+        self
+    }
+
+    /// Add fonts by their data.
+    pub fn with_embedded_fonts(
+        mut self,
+        fonts: impl IntoIterator<Item = Cow<'static, [u8]>>,
+    ) -> Self {
+        self.embedded_fonts.extend(fonts);
+        self
+    }
+
+    /// Set the package registry, which defaults to [`HttpRegistry::default`].
+    pub fn with_package_registry(mut self, registry: HttpRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Only use the packages available on disk, see
+    /// [`HttpRegistry::with_offline`].
+    pub fn offline(mut self) -> Self {
+        self.offline = true;
+        self
+    }
+
+    /// Set the inputs visible through `sys.inputs`.
+    pub fn inputs(mut self, inputs: Dict) -> Self {
+        self.inputs = inputs;
+        self
+    }
+
+    /// Compile with a fixed datetime instead of the system clock, see
+    /// [`CompilerWorld::with_creation_timestamp`].
+    pub fn creation_timestamp(mut self, timestamp: Option<DateTime<Utc>>) -> Self {
+        self.creation_timestamp = timestamp;
+        self
+    }
+
+    /// Set the timezone of the current date, see
+    /// [`CompilerWorld::with_timezone`].
+    pub fn timezone(mut self, timezone: Option<FixedOffset>) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Set the limits applied to the compilations, see
+    /// [`CompileDriverImpl::with_limits`].
+    pub fn limits(mut self, limits: CompileLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the limits of the loaded images, see [`crate::images`].
+    pub fn image_limits(mut self, limits: ImageLimits) -> Self {
+        self.image_limits = limits;
+        self
+    }
+
+    /// Customize the library of the world by a hook, see
+    /// [`super::library`].
+    pub fn with_library_hook(mut self, hook: LibraryHook) -> Self {
+        self.library_hook = Some(hook);
+        self
+    }
+
+    /// Set the directory to persist derived data across processes, see
+    /// [`crate::cache::DiskCache`]. Nothing is persisted by default.
+    pub fn cache_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.cache_dir = dir;
+        self
+    }
+
+    /// Read the options of the project file at the root if it exists, see
+    /// [`super::project`].
+    pub fn discover_project(mut self) -> Self {
+        self.discover_project = true;
+        self
+    }
+
+    /// Read the options of the given project file instead of discovering it,
+    /// which is relative to the current directory if it is relative.
+    pub fn project_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.project_file = Some(path.into());
+        self
+    }
+
+    /// Validate the options and build the driver.
+    pub fn build_driver(self) -> ZResult<CompileDriverImpl<CompilerWorld<F>>> {
+        self.build_project_driver().map(|(driver, _)| driver)
+    }
+
+    /// Validate the options and build the driver along with the project file,
+    /// which is reloaded by [`super::CompileActor::with_project`] in watch
+    /// mode.
+    ///
+    /// The project file is kept even if it doesn't exist when discovered, so
+    /// that creating it later takes effect.
+    #[allow(clippy::type_complexity)]
+    pub fn build_project_driver(
+        self,
+    ) -> ZResult<(CompileDriverImpl<CompilerWorld<F>>, Option<ProjectState>)> {
+        let mut errors = vec![];
+        let mut absolute = |path: &Path| -> Option<PathBuf> {
+            if path.is_absolute() {
+                return Some(path.clean());
+            }
+            match std::env::current_dir() {
+                Ok(cwd) => Some(cwd.join(path).clean()),
+                Err(err) => {
+                    errors.push(format!("cannot resolve {}: {err}", path.display()));
+                    None
+                }
+            }
+        };
+
+        let root = self.root.as_deref().and_then(&mut absolute);
+        let entry = match (&self.entry, &root) {
+            (Some(entry), Some(root)) if entry.is_relative() => Some(root.join(entry).clean()),
+            (Some(entry), _) => absolute(entry),
+            (None, _) => None,
+        };
+        let extra_roots: Vec<_> = (self.extra_roots.iter())
+            .filter_map(|root| absolute(root))
+            .collect();
+        let project_file = self.project_file.as_deref().and_then(&mut absolute);
+
+        let root = root.or_else(|| entry.as_deref()?.parent().map(Path::to_owned));
+
+        // Layer the options of the builder over the project file.
+        let project_path = match (project_file, &root) {
+            (Some(path), _) => Some(path),
+            (None, Some(root)) if self.discover_project => Some(root.join(PROJECT_FILE)),
+            _ => None,
+        };
+        let overrides = ProjectConfig {
+            entry,
+            font_paths: self.font_paths,
+            ..ProjectConfig::default()
+        };
+        let project = project_path.map(|path| {
+            let explicit = self.project_file.is_some();
+            let file = match ProjectConfig::load(&path) {
+                Ok(file) => Some(file),
+                Err(_) if !explicit && !path.exists() => None,
+                Err(err) => {
+                    errors.push(format!("{}: {err}", path.display()));
+                    None
+                }
+            };
+            ProjectState::new(path.into(), overrides.clone(), file)
+        });
+        let config = project
+            .as_ref()
+            .map_or(overrides, |project| project.config.clone());
+        let entry = config.entry.clone();
+
+        match (&root, &entry) {
+            (None, _) => errors.push("either a root or an entry file is required".to_owned()),
+            (Some(root), _) if root.exists() && !root.is_dir() => {
+                errors.push(format!("root is not a directory: {}", root.display()))
+            }
+            (Some(root), Some(entry)) if !entry.starts_with(root) => errors.push(format!(
+                "entry file {} is not in the root {}",
+                entry.display(),
+                root.display()
+            )),
+            _ => {}
+        }
+        // A missing font path doesn't fail the build, like the font resolver
+        // skipping it.
+        for path in config.font_paths.iter().filter(|path| !path.exists()) {
+            log::warn!("font path does not exist: {}", path.display());
+        }
+        if !self.system_fonts && config.font_paths.is_empty() && self.embedded_fonts.is_empty() {
+            errors.push(
+                "no font source, add font paths or embedded fonts or enable the system fonts"
+                    .to_owned(),
+            );
+        }
+
+        let root = match root {
+            Some(root) if errors.is_empty() => root,
+            _ => {
+                return Err(
+                    error_once!("invalid compile driver options", errors: errors.join("; ")),
+                )
+            }
+        };
+
+        let fonts = LazyFontResolver::spawn(CompileFontOpts {
+            font_paths: config.font_paths.clone(),
+            no_system_fonts: !self.system_fonts,
+            with_embedded_fonts: self.embedded_fonts,
+            cache_dir: self.cache_dir.clone(),
+            ..CompileFontOpts::default()
+        });
+        let registry = self.registry.unwrap_or_default();
+        let registry = if self.offline {
+            registry.with_offline(true)
+        } else {
+            registry
+        };
+        let registry = match config.packages.is_empty() {
+            true => registry,
+            false => registry.with_overrides(config.package_overrides()),
+        };
+
+        let mut world = CompilerWorld::<F>::new_raw(
+            EntryState::new_workspace(root.as_path().into()),
+            Vfs::new(self.access_model),
+            registry,
+            fonts,
+        );
+        world.set_inputs(Arc::new(Prehashed::new(self.inputs)));
+        world.set_extra_roots(
+            extra_roots
+                .iter()
+                .map(|root| root.as_path().into())
+                .collect(),
+        );
+        world.set_creation_timestamp(self.creation_timestamp);
+        world.set_image_limits(self.image_limits);
+        world.set_disk_cache(self.cache_dir.map(DiskCache::open));
+        world.set_library_hook(self.library_hook);
+        if let Some(timezone) = self.timezone {
+            world = world.with_timezone(timezone);
+        }
+
+        let driver = CompileDriverImpl::new(world).with_limits(self.limits);
+        let driver = match entry {
+            Some(entry) => driver.with_entry_file(entry),
+            None => driver,
+        };
+        Ok((driver, project))
+    }
+}
+
+#[cfg(feature = "system-watch")]
+impl<F> CompileDriverBuilder<F>
+where
+    F: CompilerFeat<FontResolver = LazyFontResolver, Registry = HttpRegistry> + 'static,
+    CompileDriverImpl<CompilerWorld<F>>:
+        super::Compiler<World = CompilerWorld<F>> + crate::ShadowApi + super::WorldExporter + Send,
+{
+    /// Validate the options and build a compiler thread with its client.
+    #[allow(clippy::type_complexity)]
+    pub fn build(
+        self,
+    ) -> ZResult<(
+        super::CompileActor<CompileDriverImpl<CompilerWorld<F>>>,
+        super::CompileClient<super::CompileActor<CompileDriverImpl<CompilerWorld<F>>>>,
+    )> {
+        let (driver, project) = self.build_project_driver()?;
+        let actor = super::CompileActor::new(driver);
+        let actor = match project {
+            Some(project) => actor.with_project(project),
+            None => actor,
+        };
+        Ok(actor.split())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::{fixture::TestWorkspace, service::Compiler};
+
+    #[test]
+    fn test_build_driver() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        ws.write("main.typ", "= Built");
+
+        // All the problems are reported together.
+        let err = CompileDriverBuilder::new()
+            .root(&root)
+            .entry(root.parent().unwrap().join("elsewhere.typ"))
+            .with_system_fonts(false)
+            .build_driver()
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("is not in the root"), "{err}");
+        assert!(err.contains("no font source"), "{err}");
+
+        let err = CompileDriverBuilder::new()
+            .with_system_fonts(false)
+            .build_driver()
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("either a root or an entry file"), "{err}");
+        assert!(err.contains("no font source"), "{err}");
+
+        // A missing font path is only warned about.
+        let builder = CompileDriverBuilder::new()
+            .entry(root.join("main.typ"))
+            .font_paths([root.join("missing-fonts")])
+            .with_system_fonts(false)
+            .with_embedded_fonts(typst_assets::fonts().map(Cow::Borrowed))
+            .offline()
+            .with_trace_access();
+        let stats = builder.trace_stats();
+        let mut driver = builder.build_driver().unwrap();
+        assert_eq!(driver.entry_file(), root.join("main.typ"));
+
+        let doc = driver.compile(&mut Default::default()).unwrap();
+        assert_eq!(doc.pages.len(), 1);
+        assert!(stats.total_calls() > 0);
+    }
+
+    #[test]
+    fn test_project_precedence() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let (fonts, other_fonts) = (root.join("fonts"), root.join("other-fonts"));
+        std::fs::create_dir_all(&fonts).unwrap();
+        std::fs::create_dir_all(&other_fonts).unwrap();
+        ws.write("main.typ", "= Main");
+        ws.write("doc.typ", "= Doc");
+        let project = "entry = \"doc.typ\"\nfont-paths = [\"fonts\"]\nunknown = 1\n";
+        std::fs::write(root.join(PROJECT_FILE), project).unwrap();
+
+        let builder = || {
+            CompileDriverBuilder::new()
+                .root(&root)
+                .with_system_fonts(false)
+                .with_embedded_fonts(typst_assets::fonts().map(Cow::Borrowed))
+                .offline()
+        };
+
+        // The project file is only read if requested.
+        let (driver, project) = builder().build_project_driver().unwrap();
+        assert!(project.is_none());
+        assert_ne!(driver.entry_file(), root.join("doc.typ"));
+
+        // The project file takes precedence over the defaults, and an unknown
+        // key doesn't fail the build.
+        let (driver, project) = builder().discover_project().build_project_driver().unwrap();
+        let project = project.unwrap();
+        assert_eq!(driver.entry_file(), root.join("doc.typ"));
+        assert_eq!(project.path.as_ref(), root.join(PROJECT_FILE));
+        assert_eq!(project.config.font_paths, [fonts]);
+
+        // The options of the builder take precedence over the project file.
+        let (driver, project) = builder()
+            .discover_project()
+            .entry("main.typ")
+            .font_paths([other_fonts.clone()])
+            .build_project_driver()
+            .unwrap();
+        assert_eq!(driver.entry_file(), root.join("main.typ"));
+        assert_eq!(project.unwrap().config.font_paths, [other_fonts]);
+    }
+}
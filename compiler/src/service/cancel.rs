@@ -0,0 +1,107 @@
+//! Cancel the requests of clients, e.g. once an editor closes the panel
+//! waiting for a slow query.
+//!
+//! A request cancelled before it runs is dropped from the queue of the
+//! actor, see `CompileClient::request`. A running request is given a
+//! [`CancellationToken`], which it polls at reasonable intervals, e.g. once
+//! per page, to stop early with [`Cancelled`]. Either way, the request
+//! resolves with the error of [`Cancelled`] rather than hanging.
+//!
+//! Only the requests returning a `RequestHandle` are cancellable, i.e.
+//! `CompileClient::request` and the `request_*` methods, along with their
+//! counterparts of `AsyncCompileClient`. The other async methods, e.g.
+//! `resolve_src_to_doc_jump`, run to completion. A single call into Typst
+//! between two polls, e.g. a query of the introspector, cannot be
+//! interrupted, hence the cancellation takes effect once it returns.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use typst_ts_core::error::{prelude::*, ErrKind, ErrKindExt, Error};
+
+/// The location of the errors of cancelled requests.
+const CANCELLED_LOC: &str = "cancelled request";
+
+/// The error of a cancelled request, see the [module docs](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Cancelled {
+    /// Whether an error is of a cancelled request.
+    pub fn is(err: &Error) -> bool {
+        err.loc() == CANCELLED_LOC
+    }
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the request is cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl ErrKindExt for Cancelled {
+    fn to_error_kind(self) -> ErrKind {
+        ErrKind::Msg(self.to_string())
+    }
+}
+
+impl From<Cancelled> for Error {
+    fn from(err: Cancelled) -> Self {
+        Error::new(CANCELLED_LOC, err.to_error_kind(), Box::new([]))
+    }
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    started: AtomicBool,
+}
+
+/// A flag shared by a request and its caller, which is set once the request
+/// is cancelled.
+///
+/// The clones of a token share the flag. A default token is never cancelled
+/// unless [`CancellationToken::cancel`] is called.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<TokenState>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fail with [`Cancelled`] once the token is cancelled.
+    pub fn check(&self) -> ZResult<()> {
+        match self.is_cancelled() {
+            true => Err(Cancelled.into()),
+            false => Ok(()),
+        }
+    }
+
+    /// Whether the request has started to run.
+    pub fn is_started(&self) -> bool {
+        self.0.started.load(Ordering::SeqCst)
+    }
+
+    /// Mark the request as started, returning whether it should run, i.e.
+    /// it isn't cancelled yet.
+    pub(crate) fn start(&self) -> bool {
+        self.0.started.store(true, Ordering::SeqCst);
+        !self.is_cancelled()
+    }
+}
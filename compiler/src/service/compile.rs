@@ -1,35 +1,102 @@
 use std::{
-    collections::HashSet,
-    num::NonZeroUsize,
-    ops::Deref,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    future::Future,
+    num::NonZeroU64,
+    ops::{Deref, Range},
     path::{Path, PathBuf},
+    pin::Pin,
     sync::Arc,
+    task::{ready, Context, Poll},
     thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
+use chrono::{DateTime, Local, Utc};
+use comemo::Prehashed;
+use parking_lot::Mutex;
 use serde::Serialize;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 use typst::{
-    layout::{Frame, FrameItem, Point, Position},
+    diag::{FileError, Severity, SourceDiagnostic, SourceResult},
+    foundations::{Bytes, Content, Dict, Module},
+    layout::{Frame, FrameItem, Page, Point, Position},
     syntax::{LinkedNode, Source, Span, SyntaxKind, VirtualPath},
+    text::Glyph,
     World,
 };
 
 use crate::{
-    service::features::WITH_COMPILING_STATUS_FEATURE,
-    vfs::notify::{FilesystemEvent, MemoryEvent, NotifyMessage},
-    world::{CompilerFeat, CompilerWorld},
+    cache::{CacheHits, CacheKind},
+    font::system::LazyFontResolver,
+    macros::{pipeline_record, pipeline_span},
+    package::http::HttpRegistry,
+    parser::ReparseStats,
+    service::features::{DIFF_DIAGNOSTICS_FEATURE, WITH_COMPILING_STATUS_FEATURE},
+    vfs::{
+        cached::ReadStats,
+        notify::{
+            FileChangeSet, FileEdit, FileSnapshot, FilesystemEvent, MemoryEvent, NotifyMessage,
+        },
+        InvalidationStrategy, SourcePreprocessor,
+    },
+    world::{is_pseudo_package, CompilerFeat, CompilerWorld},
     ShadowApi,
 };
 use typst_ts_core::{
+    config::compiler::EntryState,
+    content_hash::DocumentHash,
     debug_loc::{SourceLocation, SourceSpanOffset},
-    error::prelude::*,
-    TypstDocument, TypstFileId,
+    error::{prelude::*, ErrKind, ErrKindExt, Error},
+    font::{FontLoadError, FontResolver},
+    typst::prelude::EcoVec,
+    vector::{incr::IncrDocServer, stats::ArtifactStats},
+    ImmutPath, TypstDocument, TypstFileId,
 };
 
+#[cfg(debug_assertions)]
+use super::jump::{dump_spans, SpanDump};
 use super::{
-    features::FeatureSet, CompileEnv, CompileReporter, Compiler, ConsoleDiagReporter, EntryManager,
-    WorldExporter,
+    cancel::{CancellationToken, Cancelled},
+    context::{context_at, counter_at, ContextInfo},
+    coverage::{document_coverage, document_coverage_cancellable, CoverageReport},
+    deps::{self, dep_graph, DepGraphFormat},
+    diff::{changed_pages, page_fingerprints},
+    document::{
+        document_info, document_labels, document_stats, document_text, plain_text, DocumentInfo,
+        DocumentStats, LabelInfo, TextRun, PAGE_DELIMITER,
+    },
+    eval::module_to_json,
+    features::FeatureSet,
+    fonts::{font_coverage, list_fonts, CoverageResult, FontFamilyInfo},
+    format::{format_source, FormatOptions, SourceFormatter, TextEdit, WhitespaceFormatter},
+    fragment::compile_fragment,
+    jump::{
+        caret_from_cursor, jump_all_from_cursor, jump_from_cursor, pages_for_file, span_at_offset,
+        span_from_point, CaretPosition,
+    },
+    layout::PageOverride,
+    library::LibraryHook,
+    limits::CompileLimits,
+    links::{document_links, LinkInfo, LinkSource},
+    lint::{lint_document, LintFinding},
+    metadata::{document_metadata, MetadataAnchor, MetadataSource},
+    observer::{ActorObserver, CompileEnd, NoopObserver},
+    outline::document_outline,
+    pages::{document_page_metadata, PageMeta},
+    position::{to_lsp_range, to_offset},
+    progress::{CompileProgress, CompileStage, ProgressCallback},
+    project::{ProjectConfig, ProjectState, PROJECT_FILE},
+    query::retrieve_cancellable,
+    queue::{TaskCategory, TaskQueue, TaskTag},
+    regions::{clickable_regions, word_regions, ClickRegion, WordRegion},
+    session_log::{InterruptKind, SessionEvent, SessionLog, SessionRecord},
+    standby::{self, BuiltRoot, BuiltWorld, Standby, WorldChange},
+    syntax::{syntax_path, syntax_tree, SyntaxAncestor, SyntaxTreeFormat},
+    vector_artifact_stats, vector_artifact_with, ArtifactOptions, CompileEnv, CompileMeta,
+    CompileReporter, Compiler, ConsoleDiagReporter, DiagnosticLocation, DiagnosticPosition,
+    DiagnosticSeverity, EntryManager, EntryNotFound, EnvWorld, PositionEncoding, RootUnavailable,
+    SerializableDiagnostic, WorldExporter,
 };
 
 /// A task that can be sent to the context (compiler thread)
@@ -37,27 +104,94 @@ use super::{
 /// The internal function will be dereferenced and called on the context.
 type BorrowTask<Ctx> = Box<dyn FnOnce(&mut Ctx) + Send + 'static>;
 
+/// A task queued by its tag, along with the token of its request if any, see
+/// [`CompileClient::request`].
+type QueuedTask<Ctx> = (BorrowTask<Ctx>, Option<CancellationToken>);
+
 /// Interrupts for the compiler thread.
 enum CompilerInterrupt<Ctx> {
     /// Interrupted by task.
     ///
     /// See [`CompileClient<Ctx>::steal`] for more information.
     Task(BorrowTask<Ctx>),
-    /// Interrupted by memory file changes.
-    Memory(MemoryEvent),
+    /// Interrupted by tasks pushed to the task queue.
+    ///
+    /// See [`CompileClient<Ctx>::with_task_tag`] for more information.
+    Queued,
+    /// Interrupted by a batch of memory file changes.
+    Memory(Vec<MemoryEvent>),
     /// Interrupted by file system event.
     ///
     /// If the event is `None`, it means the initial file system scan is done.
     /// Otherwise, it means a file system event is received.
     Fs(Option<FilesystemEvent>),
+    /// Interrupted periodically while the root of the workspace is
+    /// unavailable, see [`ActorHealth::root_unavailable`].
+    ProbeRoot,
 }
 
+/// A callback to observe the dependencies of compilations.
+type DependencyObserver = Box<dyn Fn(&[PathBuf]) + Send + 'static>;
+
+/// A callback to observe the modules evaluated in the eval-only mode.
+type EvalObserver = Box<dyn Fn(&Module) + Send + 'static>;
+
+/// A callback to apply the changed options of a project, which takes the
+/// previous and the new options.
+type ProjectReloader<C> =
+    Box<dyn Fn(&mut CompileReporter<C>, &ProjectConfig, &ProjectConfig) + Send + 'static>;
+
 /// Responses from the compiler thread.
 enum CompilerResponse {
     /// Response to the file watcher
     Notify(NotifyMessage),
 }
 
+/// Errors of the channels between a [`CompileActor`], its clients and its
+/// file watcher.
+///
+/// The client methods return them as [`ZResult`] errors, while the actor logs
+/// them and keeps running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompileServiceError {
+    /// The actor has exited, so it doesn't accept requests any more.
+    ActorGone,
+    /// The file watcher has exited, so file changes are not tracked.
+    WatcherGone,
+    /// The actor dropped a task without responding, e.g. the task panicked.
+    TaskDropped,
+    /// A message between the actor and the watcher is of an unexpected type.
+    ProtocolMismatch,
+    /// The task queue is full, see [`CompileActor::with_task_queue_depth`].
+    QueueFull,
+}
+
+impl fmt::Display for CompileServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ActorGone => write!(f, "the compile actor has exited"),
+            Self::WatcherGone => write!(f, "the file watcher has exited"),
+            Self::TaskDropped => write!(f, "the task is dropped by the compile actor"),
+            Self::ProtocolMismatch => write!(f, "unexpected message from the file watcher"),
+            Self::QueueFull => write!(f, "the task queue of the compile actor is full"),
+        }
+    }
+}
+
+impl std::error::Error for CompileServiceError {}
+
+impl ErrKindExt for CompileServiceError {
+    fn to_error_kind(self) -> ErrKind {
+        ErrKind::Msg(self.to_string())
+    }
+}
+
+impl From<CompileServiceError> for Error {
+    fn from(err: CompileServiceError) -> Self {
+        Error::new("compile service", err.to_error_kind(), Box::new([]))
+    }
+}
+
 /// A tagged memory event with logical tick.
 struct TaggedMemoryEvent {
     /// The logical tick when the event is received.
@@ -66,34 +200,203 @@ struct TaggedMemoryEvent {
     event: MemoryEvent,
 }
 
+/// The maximum number of pending interrupts merged into a request, see
+/// [`CompileActor::handle`].
+const MAX_MERGED_INTERRUPTS: usize = 256;
+
+/// The number of compiled documents remembered by [`CompileActor`] for
+/// [`CompileClient::changed_pages_since`].
+const DOC_HISTORY_SIZE: usize = 16;
+
+/// The interval of probing the root of the workspace while it is
+/// unavailable, see [`CompileActor::probe_root`].
+const ROOT_PROBE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The number of changes buffered for a receiver of
+/// [`CompileClient::watch_file`], beyond which a slow receiver lags.
+const FILE_WATCH_CAPACITY: usize = 16;
+
+/// The number of compilations between the full syncs of the dependencies to
+/// the file watcher, which otherwise receives the changes of the dependencies
+/// only, see [`NotifyMessage::UpdateDependency`].
+const DEPENDENCY_SYNC_INTERVAL: usize = 64;
+
+/// The names of the experimental features of typst, see
+/// [`CompileActor::with_features`]. Typst 0.11.1 has none.
+pub const TYPST_FEATURES: &[&str] = &[];
+
+/// A change of a watched file, see [`CompileClient::watch_file`].
+#[derive(Debug, Clone)]
+pub enum FileChange {
+    /// The file is created or updated, along with its new content or the
+    /// error reading it.
+    Updated(FileSnapshot),
+    Removed,
+}
+
+/// The senders of the changes of the watched files by their paths.
+type FileWatches = Arc<Mutex<HashMap<ImmutPath, broadcast::Sender<FileChange>>>>;
+
+/// The file watcher of a spawned actor, see [`CompileActor::restart_watcher`].
+struct WatcherHandle {
+    /// The sender to the running watcher, which is swapped on restarts.
+    dep_tx: Arc<Mutex<mpsc::UnboundedSender<NotifyMessage>>>,
+    /// The sender of the file system events to the compiler thread, which
+    /// doesn't keep the channel open by itself.
+    fs_tx: mpsc::WeakUnboundedSender<Option<FilesystemEvent>>,
+    /// The runtime where the watchers run.
+    runtime: tokio::runtime::Handle,
+}
+
 /// The compiler thread.
 pub struct CompileActor<C: Compiler> {
     /// The underlying compiler.
     pub compiler: CompileReporter<C>,
     /// Whether to enable file system watching.
     pub enable_watch: bool,
+    /// The name of the compiler thread.
+    thread_name: String,
+    /// The stack size of the compiler thread, in bytes.
+    stack_size: Option<usize>,
 
     /// The current logical tick.
     logical_tick: usize,
-    /// Last logical tick when invalidation is caused by shadow update.
+    /// The id of the latest request, see [`Self::handle`].
+    request_id: u64,
+    /// Last logical tick when invalidation is caused by a sync of the shadow
+    /// files, which delays all the memory events until it is applied.
     dirty_shadow_logical_tick: usize,
+    /// The files affected by the pending invalidations, i.e. the invalidated
+    /// files and their dependents, with the logical tick of the invalidation.
+    dirty_shadows: HashMap<ImmutPath, usize>,
+    /// Whether a stolen task asks for a compilation, e.g. after
+    /// [`Self::set_root`].
+    recompile_requested: bool,
+    /// Whether a compilation of the latest state is pending.
+    ///
+    /// It is a slot rather than a queue: a request needing a compilation
+    /// while one is pending is merged into it, so that only the latest state
+    /// is compiled. The merged requests are counted by `compiles_skipped`.
+    pending_compile: bool,
+    /// The number of compilations skipped since their states are superseded,
+    /// see [`ActorHealth::compiles_skipped`].
+    compiles_skipped: u64,
+    /// Whether to buffer the fs events until the initial scan completes, see
+    /// [`Self::with_initial_scan_buffering`].
+    buffer_initial_scan: bool,
+    /// Whether the watcher has sent the scan-complete sentinel, i.e.
+    /// `CompilerInterrupt::Fs(None)`.
+    initial_scan_completed: bool,
+    /// The fs events received before the initial scan completes.
+    pending_fs_events: Vec<FilesystemEvent>,
 
     /// Estimated latest set of shadow files.
     estimated_shadow_files: HashSet<Arc<Path>>,
     /// The latest compiled document.
     latest_doc: Option<Arc<TypstDocument>>,
+    /// The time when the latest compilation finished, and whether it
+    /// succeeded.
+    latest_compile: Option<(Instant, bool)>,
+    /// The entry file missing in the latest compilation, which is watched
+    /// until it is created, see [`ActorHealth::waiting_for_entry`].
+    missing_entry: Option<EntryNotFound>,
+    /// Whether the root of the workspace has been available, since a
+    /// workspace of shadow files may have no root on disk.
+    root_seen: bool,
+    /// The root of the workspace which has become unavailable, which stops
+    /// the compilations until it is available again, see
+    /// [`Self::probe_root`].
+    unavailable_root: Option<RootUnavailable>,
+    /// The cache efficiency of the latest compilation, if the world tracks
+    /// its reads.
+    latest_cache_efficiency: Option<CacheEfficiency>,
+    /// The number of successful compilations.
+    generation: u64,
+    /// Page fingerprints of recently compiled documents by generation.
+    doc_history: VecDeque<(u64, Vec<u128>)>,
+    /// The maximum number of retained documents, see
+    /// [`Self::with_doc_history`].
+    doc_revisions_size: usize,
+    /// Recently compiled documents, the oldest first.
+    doc_revisions: VecDeque<DocumentRevision>,
+    /// Diagnostics of the latest compilation.
+    latest_diagnostics: Vec<SerializableDiagnostic>,
+    /// The minimum severity of the published diagnostics, see
+    /// [`Self::with_diagnostic_filter`].
+    min_severity: DiagnosticSeverity,
+    /// The unit of columns in diagnostics.
+    position_encoding: PositionEncoding,
+    /// The fixed current datetime for compilations.
+    now: Option<DateTime<Local>>,
+    /// The maximum number of layout iterations for compilations.
+    layout_iteration_limit: Option<usize>,
+    /// The page size override for compilations.
+    page_override: Option<PageOverride>,
+    /// The maximum number of pages of the compiled documents.
+    max_pages: Option<usize>,
+    /// The metadata passed to the exporters, see [`CompileClient::set_meta`].
+    meta: Arc<CompileMeta>,
+    /// The age of the file caches to evict, see [`Self::with_cache_eviction`].
+    cache_eviction: Option<Duration>,
+    /// The time when the file caches are evicted last.
+    last_eviction: Instant,
+    /// Whether to fail compilations with warnings, see
+    /// [`Self::with_warnings_as_errors`].
+    warnings_as_errors: bool,
+    /// Whether to report the coverage of compilations, see
+    /// [`Self::with_coverage`].
+    coverage: bool,
+    /// The coverage of the latest compilation.
+    latest_coverage: Option<CoverageReport>,
+    /// The dependencies of the latest compilation.
+    latest_deps: HashSet<ImmutPath>,
+    /// The compilations since the latest full sync of the dependencies to the
+    /// file watcher, see [`DEPENDENCY_SYNC_INTERVAL`].
+    deps_sync_age: usize,
+    /// The callback to observe changes of dependencies.
+    deps_observer: Option<DependencyObserver>,
+    /// The project file reloaded on changes, see [`Self::with_project`].
+    project: Option<ProjectState>,
+    /// The callback to apply the reloaded options of the project.
+    project_reloader: Option<ProjectReloader<C>>,
+    /// The standby builds of the world, see [`Self::rebuild_world`].
+    standby: Standby,
+    /// The log of the watch session, see [`Self::with_session_log`].
+    session_log: Option<Arc<SessionLog>>,
+    /// The file watcher, which is only set once the actor is spawned in watch
+    /// mode.
+    watcher: Option<WatcherHandle>,
+    /// Whether to only evaluate the entry instead of compiling the document,
+    /// see [`Self::with_eval_only`].
+    eval_only: bool,
+    /// The callback to observe the evaluated modules.
+    eval_observer: Option<EvalObserver>,
+    /// The formatter of [`CompileClient::format`].
+    formatter: Box<dyn SourceFormatter>,
+    /// The observer of the lifecycle, see [`Self::with_observer`].
+    observer: Box<dyn ActorObserver>,
     /// feature set for compile_once mode.
     once_feature_set: Arc<FeatureSet>,
     /// Shared feature set for watch mode.
     watch_feature_set: Arc<FeatureSet>,
+    /// The latest progress of compilations, see [`CompileClient::progress`].
+    progress: Arc<watch::Sender<Option<CompileProgress>>>,
 
     /// Internal channel for stealing the compiler thread.
     steal_send: mpsc::UnboundedSender<BorrowTask<Self>>,
     steal_recv: mpsc::UnboundedReceiver<BorrowTask<Self>>,
+    /// The tagged tasks, which are run by their priorities.
+    task_queue: Arc<Mutex<TaskQueue<QueuedTask<Self>>>>,
+    /// Internal channel for waking up the actor on tagged tasks.
+    queue_send: mpsc::UnboundedSender<()>,
+    queue_recv: mpsc::UnboundedReceiver<()>,
 
     /// Internal channel for memory events.
-    memory_send: mpsc::UnboundedSender<MemoryEvent>,
-    memory_recv: mpsc::UnboundedReceiver<MemoryEvent>,
+    memory_send: mpsc::UnboundedSender<Vec<MemoryEvent>>,
+    memory_recv: mpsc::UnboundedReceiver<Vec<MemoryEvent>>,
+
+    /// The watched files, see [`CompileClient::watch_file`].
+    file_watches: FileWatches,
 }
 
 impl<C: Compiler + ShadowApi + WorldExporter + Send + 'static> CompileActor<C>
@@ -102,12 +405,14 @@ where
 {
     pub fn new_with_features(compiler: C, feature_set: FeatureSet) -> Self {
         let (steal_send, steal_recv) = mpsc::unbounded_channel();
+        let (queue_send, queue_recv) = mpsc::unbounded_channel();
         let (memory_send, memory_recv) = mpsc::unbounded_channel();
 
         let watch_feature_set = Arc::new(
             feature_set
                 .clone()
-                .configure(&WITH_COMPILING_STATUS_FEATURE, true),
+                .configure(&WITH_COMPILING_STATUS_FEATURE, true)
+                .configure(&DIFF_DIAGNOSTICS_FEATURE, true),
         );
 
         Self {
@@ -115,19 +420,69 @@ where
                 .with_generic_reporter(ConsoleDiagReporter::default()),
 
             logical_tick: 1,
+            request_id: 0,
             enable_watch: false,
+            thread_name: "typst-compiler".to_owned(),
+            stack_size: None,
             dirty_shadow_logical_tick: 0,
+            dirty_shadows: HashMap::new(),
+            recompile_requested: false,
+            pending_compile: false,
+            compiles_skipped: 0,
+            buffer_initial_scan: true,
+            initial_scan_completed: false,
+            pending_fs_events: Vec::new(),
 
             estimated_shadow_files: Default::default(),
             latest_doc: None,
+            latest_compile: None,
+            missing_entry: None,
+            root_seen: false,
+            unavailable_root: None,
+            latest_cache_efficiency: None,
+            generation: 0,
+            doc_history: VecDeque::new(),
+            doc_revisions_size: 0,
+            doc_revisions: VecDeque::new(),
+            latest_diagnostics: Vec::new(),
+            min_severity: DiagnosticSeverity::Warning,
+            position_encoding: PositionEncoding::default(),
+            now: None,
+            layout_iteration_limit: None,
+            page_override: None,
+            max_pages: None,
+            meta: Arc::default(),
+            cache_eviction: None,
+            last_eviction: Instant::now(),
+            warnings_as_errors: false,
+            coverage: false,
+            latest_coverage: None,
+            latest_deps: Default::default(),
+            deps_sync_age: 0,
+            deps_observer: None,
+            project: None,
+            project_reloader: None,
+            standby: Standby::default(),
+            session_log: None,
+            watcher: None,
+            eval_only: false,
+            eval_observer: None,
+            formatter: Box::new(WhitespaceFormatter),
+            observer: Box::new(NoopObserver),
             once_feature_set: Arc::new(feature_set),
             watch_feature_set,
+            progress: Arc::new(watch::channel(None).0),
 
             steal_send,
             steal_recv,
+            task_queue: Arc::default(),
+            queue_send,
+            queue_recv,
 
             memory_send,
             memory_recv,
+
+            file_watches: Arc::default(),
         }
     }
 
@@ -137,7 +492,81 @@ where
     }
 
     fn make_env(&self, feature_set: Arc<FeatureSet>) -> CompileEnv {
-        CompileEnv::default().configure_shared(feature_set)
+        // The reporter counts the compilation once it starts. The stages are
+        // only reported to the subscribers, while the end of a compilation is
+        // always kept for the later ones, see [`Self::report_done`].
+        let revision = self.compiler.revision() as u64 + 1;
+        let progress = self.progress.clone();
+        let report = (progress.receiver_count() > 0).then(|| {
+            Arc::new(move |stage: CompileStage| report_progress(&progress, revision, stage))
+                as ProgressCallback
+        });
+
+        CompileEnv::default()
+            .configure_shared(feature_set)
+            .with_now(self.now)
+            .with_layout_iteration_limit(self.layout_iteration_limit)
+            .with_page_override(self.page_override)
+            .with_limits(CompileLimits::default().with_max_pages(self.max_pages))
+            .with_meta(self.meta.clone())
+            .with_progress(report)
+    }
+
+    /// Report the end of the latest compilation, see
+    /// [`CompileClient::progress`].
+    fn report_done(&self, success: bool) {
+        let revision = self.compiler.revision() as u64;
+        report_progress(&self.progress, revision, CompileStage::Done { success });
+    }
+
+    /// Compile the document once for the compile_once mode.
+    ///
+    /// The reporter keeps the diagnostics of a failure, which are returned
+    /// as the errors.
+    fn compile_once(&mut self) -> SourceResult<Arc<TypstDocument>> {
+        let mut env = self.make_env(self.once_feature_set.clone());
+        let doc =
+            (self.compiler.compile(&mut env)).map_err(|_| self.compiler.diagnostics().clone())?;
+        match self.promoted_warnings() {
+            Some(warnings) => Err(warnings),
+            None => Ok(doc),
+        }
+    }
+
+    /// Run once for the compile_once mode, which only evaluates the entry in
+    /// the eval-only mode.
+    fn run_once(&mut self) -> bool {
+        let ok = if self.eval_only {
+            let mut env = self.make_env(self.once_feature_set.clone());
+            self.evaluate(&mut env)
+        } else {
+            self.compile_once().is_ok()
+        };
+        self.report_done(ok);
+        ok
+    }
+
+    /// Evaluate the entry, passing the module to the observer.
+    fn evaluate(&mut self, env: &mut CompileEnv) -> bool {
+        let module = self.compiler.evaluate(env);
+        if let (Ok(module), Some(observer)) = (&module, &self.eval_observer) {
+            observer(module);
+        }
+        module.is_ok() && self.promoted_warnings().is_none()
+    }
+
+    /// Get the warnings of the latest compilation if they are treated as
+    /// errors, see [`Self::with_warnings_as_errors`].
+    fn promoted_warnings(&self) -> Option<EcoVec<SourceDiagnostic>> {
+        if !self.warnings_as_errors {
+            return None;
+        }
+
+        let warnings: EcoVec<_> = (self.compiler.diagnostics().iter())
+            .filter(|diag| diag.severity == Severity::Warning)
+            .cloned()
+            .collect();
+        (!warnings.is_empty()).then_some(warnings)
     }
 
     /// Run the compiler thread synchronously.
@@ -156,15 +585,16 @@ where
     /// until it exits.
     async fn block_run_inner(mut self) -> bool {
         if !self.enable_watch {
-            let mut env = self.make_env(self.once_feature_set.clone());
-            let compiled = self.compiler.compile(&mut env);
-            return compiled.is_ok();
+            return self.run_once();
         }
 
         if let Some(h) = self.spawn().await {
             // Note: this is blocking the current thread.
             // Note: the block safety is ensured by `run` function.
-            h.join().unwrap();
+            if h.join().is_err() {
+                log::error!("CompileActor: compiler thread panicked");
+                return false;
+            }
         }
 
         true
@@ -173,107 +603,534 @@ where
     /// Spawn the compiler thread.
     pub async fn spawn(mut self) -> Option<JoinHandle<()>> {
         if !self.enable_watch {
-            let mut env = self.make_env(self.once_feature_set.clone());
-            self.compiler.compile(&mut env).ok();
+            self.run_once();
             return None;
         }
 
         // Setup internal channels.
         let (dep_tx, dep_rx) = tokio::sync::mpsc::unbounded_channel();
         let (fs_tx, mut fs_rx) = tokio::sync::mpsc::unbounded_channel();
+        let dep_tx = Arc::new(Mutex::new(dep_tx));
+        self.watcher = Some(WatcherHandle {
+            dep_tx: dep_tx.clone(),
+            fs_tx: fs_tx.downgrade(),
+            runtime: tokio::runtime::Handle::current(),
+        });
 
         let settle_notify_tx = dep_tx.clone();
         let settle_notify = move || {
             log_send_error(
                 "settle_notify",
-                settle_notify_tx.send(NotifyMessage::Settle),
+                CompileServiceError::WatcherGone,
+                settle_notify_tx.lock().send(NotifyMessage::Settle),
             )
         };
 
         // Wrap sender to send compiler response.
+        let session_log = self.session_log.clone();
         let compiler_ack = move |res: CompilerResponse| match res {
             CompilerResponse::Notify(msg) => {
-                log_send_error("compile_deps", dep_tx.send(msg));
+                let err = CompileServiceError::WatcherGone;
+                if !log_send_error("compile_deps", err, dep_tx.lock().send(msg)) {
+                    if let Some(log) = &session_log {
+                        let message = err.to_string();
+                        log.record(SessionEvent::WatcherError { message });
+                    }
+                }
             }
         };
 
         // Spawn file system watcher.
-        log_send_error("fs_event", fs_tx.send(None));
-        tokio::spawn(super::watch_deps(dep_rx, move |event| {
-            log_send_error("fs_event", fs_tx.send(Some(event)));
-        }));
+        let err = CompileServiceError::ActorGone;
+        log_send_error("fs_event", err, fs_tx.send(None));
+        spawn_watcher(dep_rx, fs_tx);
 
         // Spawn compiler thread.
-        let compile_thread = ensure_single_thread("typst-compiler", async move {
+        let thread_name = self.thread_name.clone();
+        let stack_size = self.stack_size;
+        let compile_thread = ensure_single_thread(&thread_name, stack_size, async move {
             log::debug!("CompileActor: initialized");
 
             // Wait for first events.
+            //
+            // The channels are polled in order, so that the interrupts are
+            // taken in the order of sending as long as they are sent by the
+            // same thread, e.g. a memory event sent before a task is always
+            // applied before the task runs.
             while let Some(event) = tokio::select! {
+                biased;
                 Some(it) = fs_rx.recv() => Some(CompilerInterrupt::Fs(it)),
                 Some(it) = self.memory_recv.recv() => Some(CompilerInterrupt::Memory(it)),
                 Some(it) = self.steal_recv.recv() => Some(CompilerInterrupt::Task(it)),
-            } {
-                // Small step to warp the logical clock.
-                self.logical_tick += 1;
-
-                // Accumulate events.
-                let mut need_recompile = false;
-                need_recompile = self.process(event, &compiler_ack) || need_recompile;
-                while let Some(event) = fs_rx
-                    .try_recv()
-                    .ok()
-                    .map(CompilerInterrupt::Fs)
-                    .or_else(|| {
-                        self.memory_recv
-                            .try_recv()
-                            .ok()
-                            .map(CompilerInterrupt::Memory)
-                    })
-                    .or_else(|| self.steal_recv.try_recv().ok().map(CompilerInterrupt::Task))
-                {
-                    need_recompile = self.process(event, &compiler_ack) || need_recompile;
-                }
-
-                // Compile if needed.
-                if need_recompile {
-                    self.compile(&compiler_ack);
+                Some(()) = self.queue_recv.recv() => Some(CompilerInterrupt::Queued),
+                _ = tokio::time::sleep(ROOT_PROBE_INTERVAL), if self.unavailable_root.is_some() => {
+                    Some(CompilerInterrupt::ProbeRoot)
                 }
+            } {
+                // Accumulate the pending events.
+                let next = |this: &mut Self| {
+                    (fs_rx.try_recv().ok().map(CompilerInterrupt::Fs))
+                        .or_else(|| {
+                            (this.memory_recv.try_recv().ok()).map(CompilerInterrupt::Memory)
+                        })
+                        .or_else(|| this.steal_recv.try_recv().ok().map(CompilerInterrupt::Task))
+                        .or_else(|| {
+                            (this.queue_recv.try_recv().ok()).map(|()| CompilerInterrupt::Queued)
+                        })
+                };
+                self.handle(event, next, &compiler_ack);
             }
 
             settle_notify();
+            self.observer.on_shutdown();
             log::debug!("CompileActor: exited");
-        })
-        .unwrap();
+        });
+        let compile_thread = match compile_thread {
+            Ok(compile_thread) => compile_thread,
+            Err(err) => {
+                log::error!("CompileActor: failed to spawn compiler thread: {err}");
+                return None;
+            }
+        };
 
         // Return the thread handle.
         Some(compile_thread)
     }
 
+    /// Handle a request, which starts with an interrupt and takes the pending
+    /// interrupts by `next`, compiling the latest state once if any of them
+    /// needs it.
+    ///
+    /// At most [`MAX_MERGED_INTERRUPTS`] pending interrupts are taken, so that
+    /// the actor keeps compiling when requests arrive faster than they are
+    /// compiled. The rest are handled by the next request, hence the final
+    /// state is always compiled.
+    ///
+    /// The shadow files are only mutated by the interrupts, which are handled
+    /// one by one on the compiler thread along with the compilations and the
+    /// exports, hence a compilation never observes a partially applied
+    /// memory event, e.g. the shadows cleared by a [`MemoryEvent::Sync`] but
+    /// not yet inserted. A task observes the effects of the interrupts taken
+    /// before it, since the pending compilation is done before the task runs.
+    ///
+    /// A new request id is assigned to the request, which is recorded by the
+    /// spans of the pipeline if the `tracing` feature is enabled.
+    fn handle(
+        &mut self,
+        first: CompilerInterrupt<Self>,
+        mut next: impl FnMut(&mut Self) -> Option<CompilerInterrupt<Self>>,
+        send: impl Fn(CompilerResponse),
+    ) {
+        // Small step to warp the logical clock.
+        self.logical_tick += 1;
+        self.request_id += 1;
+        let _span = pipeline_span!("request", id = self.request_id);
+
+        // Record the messages to the file watcher.
+        let session_log = self.session_log.clone();
+        let send = move |res: CompilerResponse| {
+            match (&session_log, &res) {
+                (Some(log), CompilerResponse::Notify(msg)) => log.notify(msg),
+                (None, _) => {}
+            }
+            send(res)
+        };
+
+        self.handle_interrupt(first, &send);
+        for _ in 0..MAX_MERGED_INTERRUPTS {
+            let Some(event) = next(self) else {
+                break;
+            };
+            self.handle_interrupt(event, &send);
+        }
+
+        // Compile the latest state if needed.
+        if std::mem::take(&mut self.pending_compile) {
+            self.compile(&send);
+        }
+    }
+
+    /// Process an interrupt of a request, see [`Self::handle`].
+    fn handle_interrupt(
+        &mut self,
+        event: CompilerInterrupt<Self>,
+        send: &impl Fn(CompilerResponse),
+    ) {
+        // A task may read the document, which must reflect the preceding
+        // changes.
+        if matches!(event, CompilerInterrupt::Task(..)) && std::mem::take(&mut self.pending_compile)
+        {
+            self.compile(send);
+        }
+        if self.process(event, send) {
+            self.request_compile();
+        }
+    }
+
+    /// Put a compilation into the pending slot, merging it into the pending
+    /// one if any.
+    fn request_compile(&mut self) {
+        if self.pending_compile {
+            self.compiles_skipped += 1;
+        }
+        self.pending_compile = true;
+    }
+
+    /// Replay the interrupts of a recorded session over a workspace, see
+    /// [`super::session_log`].
+    ///
+    /// The interrupts of a request are handled together, as they were in the
+    /// session. The recorded paths are resolved against `root`, and the
+    /// changed files are read from it, since their contents aren't recorded.
+    /// The recorded tasks run as no-ops, which still compile the changes
+    /// before them. A session recorded with anonymized paths can't be
+    /// replayed.
+    pub fn replay(&mut self, records: &[SessionRecord], root: &Path) {
+        let mut interrupts = (records.iter())
+            .filter_map(|record| match &record.event {
+                SessionEvent::Interrupt {
+                    request,
+                    kind,
+                    inserts,
+                    removes,
+                    ..
+                } => Some((*request, *kind, inserts, removes)),
+                _ => None,
+            })
+            .peekable();
+        while let Some((request, kind, inserts, removes)) = interrupts.next() {
+            let first = replayed_interrupt(root, kind, inserts, removes);
+            let next = |_: &mut Self| {
+                let (_, kind, inserts, removes) =
+                    interrupts.next_if(|(next, ..)| *next == request)?;
+                Some(replayed_interrupt(root, kind, inserts, removes))
+            };
+            self.handle(first, next, |_| {});
+        }
+    }
+
+    /// Report an interrupt to the observer, and record it to the session log,
+    /// if any.
+    fn log_interrupt(&self, event: &CompilerInterrupt<Self>) {
+        let empty = FileChangeSet::default();
+        let (kind, changeset) = match event {
+            CompilerInterrupt::Task(..) => (InterruptKind::Task, &empty),
+            CompilerInterrupt::Queued => (InterruptKind::Queued, &empty),
+            CompilerInterrupt::Memory(events) => return self.log_memory_events(events),
+            CompilerInterrupt::Fs(None) => (InterruptKind::ScanComplete, &empty),
+            CompilerInterrupt::Fs(Some(FilesystemEvent::Update(changeset))) => {
+                (InterruptKind::Fs, changeset)
+            }
+            CompilerInterrupt::Fs(Some(FilesystemEvent::UpstreamUpdate { changeset, .. })) => {
+                (InterruptKind::FsUpstream, changeset)
+            }
+            CompilerInterrupt::ProbeRoot => (InterruptKind::ProbeRoot, &empty),
+        };
+        self.observer.on_event_received(kind);
+        if let Some(log) = &self.session_log {
+            self.record_interrupt(log, kind, log.changes(changeset));
+        }
+    }
+
+    /// Report the memory events of an interrupt to the observer, and record
+    /// them to the session log, if any.
+    fn log_memory_events(&self, events: &[MemoryEvent]) {
+        for event in events {
+            let kind = match event {
+                MemoryEvent::Sync(..) => InterruptKind::MemorySync,
+                MemoryEvent::Update(..) => InterruptKind::MemoryUpdate,
+                MemoryEvent::Edit(..) => InterruptKind::MemoryEdit,
+            };
+            self.observer.on_event_received(kind);
+
+            let Some(log) = &self.session_log else {
+                continue;
+            };
+            let changes = match event {
+                MemoryEvent::Sync(changeset) | MemoryEvent::Update(changeset) => {
+                    log.changes(changeset)
+                }
+                MemoryEvent::Edit(edit) => (vec![log.path(&edit.path)], vec![]),
+            };
+            self.record_interrupt(log, kind, changes);
+        }
+    }
+
+    fn record_interrupt(
+        &self,
+        log: &SessionLog,
+        kind: InterruptKind,
+        (inserts, removes): (Vec<String>, Vec<String>),
+    ) {
+        log.record(SessionEvent::Interrupt {
+            request: self.request_id,
+            tick: self.logical_tick,
+            kind,
+            inserts,
+            removes,
+        });
+    }
+
+    /// Restart the file watcher without restarting the compiler thread, e.g.
+    /// after the watcher fails.
+    ///
+    /// The new watcher is synced with the latest dependencies before the old
+    /// one is settled, hence the changes during the swap are reported by
+    /// either of them, if not both.
+    pub fn restart_watcher(&mut self) -> ZResult<()> {
+        let watcher = (self.watcher.as_ref())
+            .ok_or_else(|| error_once!("the file watcher is not spawned"))?;
+        let fs_tx = (watcher.fs_tx.upgrade()).ok_or(CompileServiceError::ActorGone)?;
+
+        let (dep_tx, dep_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut deps: Vec<_> = self.latest_deps.iter().cloned().collect();
+        deps.sort();
+        dep_tx
+            .send(NotifyMessage::SyncDependency(deps))
+            .map_err(|_| CompileServiceError::WatcherGone)?;
+        {
+            let _guard = watcher.runtime.enter();
+            spawn_watcher(dep_rx, fs_tx);
+        }
+
+        let old = std::mem::replace(&mut *watcher.dep_tx.lock(), dep_tx);
+        log_send_error(
+            "settle_notify",
+            CompileServiceError::WatcherGone,
+            old.send(NotifyMessage::Settle),
+        );
+        Ok(())
+    }
+
+    /// Update the dependencies of the latest compilation, which are sorted by
+    /// their paths, returning the message notifying them to the file watcher.
+    ///
+    /// Only the changes since the latest notification are notified, if any,
+    /// which the watcher applies to the watched files. The full syncs once in
+    /// a while, see [`DEPENDENCY_SYNC_INTERVAL`], recover the watcher from
+    /// missed changes.
+    fn notify_dependencies(&mut self, deps: Vec<ImmutPath>) -> Option<NotifyMessage> {
+        let current: HashSet<_> = deps.iter().cloned().collect();
+        let inserts: Vec<_> = (deps.iter())
+            .filter(|dep| !self.latest_deps.contains(*dep))
+            .cloned()
+            .collect();
+        let mut removes: Vec<_> = self.latest_deps.difference(&current).cloned().collect();
+        removes.sort();
+        if !inserts.is_empty() || !removes.is_empty() {
+            // Release the files which are no longer depended on.
+            self.compiler.evict_files(&removes);
+
+            if let Some(observer) = &self.deps_observer {
+                let paths: Vec<_> = deps.iter().map(|dep| dep.to_path_buf()).collect();
+                observer(&paths);
+            }
+            self.latest_deps = current;
+        }
+
+        let age = self.deps_sync_age;
+        self.deps_sync_age = (age + 1) % DEPENDENCY_SYNC_INTERVAL;
+        if age == 0 {
+            Some(NotifyMessage::SyncDependency(deps))
+        } else if !inserts.is_empty() || !removes.is_empty() {
+            Some(NotifyMessage::UpdateDependency { inserts, removes })
+        } else {
+            None
+        }
+    }
+
     /// Compile the document.
     fn compile(&mut self, send: impl Fn(CompilerResponse)) {
         use CompilerResponse::*;
 
-        // Compile the document.
-        self.latest_doc = self
+        let _span = pipeline_span!(
+            "compile",
+            request = self.request_id,
+            revision = tracing::field::Empty,
+            success = tracing::field::Empty,
+            elapsed_ms = tracing::field::Empty,
+        );
+        let start = Instant::now();
+
+        if self.probe_root() {
+            log::debug!("CompileActor: skip compiling since the root is unavailable");
+            return;
+        }
+        self.observer.on_compile_start(self.request_id);
+
+        // Compile the document, or only evaluate the entry in the eval-only
+        // mode.
+        let mut env = self.make_env(self.watch_feature_set.clone());
+        let ok = if self.eval_only {
+            self.evaluate(&mut env)
+        } else {
+            self.latest_doc = self.compiler.compile(&mut env).ok();
+            // The document is kept even if its warnings fail the compilation,
+            // so that the warnings can still be displayed along with it.
+            self.latest_doc.is_some() && self.promoted_warnings().is_none()
+        };
+        // The reads are taken before any task reads the files.
+        let world = self.compiler.world();
+        let font_info = (world.disk_cache_stats()).map(|stats| stats.snapshot(CacheKind::FontInfo));
+        self.latest_cache_efficiency = world.read_stats().map(|stats| CacheEfficiency {
+            font_info,
+            ..stats.into()
+        });
+        self.latest_compile = Some((Instant::now(), ok));
+        self.missing_entry = match ok {
+            true => None,
+            false => EntryNotFound::check(self.compiler.world(), self.compiler.main_id()),
+        };
+        self.report_done(ok);
+        pipeline_record!(_span, "revision", self.compiler.revision());
+        pipeline_record!(_span, "success", ok);
+        pipeline_record!(_span, "elapsed_ms", start.elapsed().as_millis() as u64);
+        if let Some(doc) = &self.latest_doc {
+            self.generation += 1;
+            if self.doc_history.len() >= DOC_HISTORY_SIZE {
+                self.doc_history.pop_front();
+            }
+            let fingerprints = page_fingerprints(doc);
+            self.doc_history.push_back((self.generation, fingerprints));
+
+            if self.doc_revisions_size > 0 {
+                while self.doc_revisions.len() >= self.doc_revisions_size {
+                    self.doc_revisions.pop_front();
+                }
+                self.doc_revisions.push_back(DocumentRevision {
+                    revision: self.generation,
+                    document: doc.clone(),
+                    timestamp: crate::time::now(),
+                });
+            }
+        }
+
+        // Resolve diagnostics and coverage before the sources change.
+        let world = self.compiler.world();
+        self.latest_coverage = match &self.latest_doc {
+            Some(doc) if self.coverage => Some(document_coverage(world, world.source_ids(), doc)),
+            _ => None,
+        };
+        let revision = self.compiler.revision();
+        self.latest_diagnostics = self
             .compiler
-            .compile(&mut CompileEnv::default().configure_shared(self.watch_feature_set.clone()))
-            .ok();
+            .diagnostics()
+            .iter()
+            .filter(|diag| DiagnosticSeverity::from(diag.severity).is_at_least(self.min_severity))
+            .map(|diag| {
+                SerializableDiagnostic::from_source(world, diag, revision, self.position_encoding)
+            })
+            .collect();
 
         // Evict compilation cache.
         comemo::evict(30);
+        if let Some(age) = self.cache_eviction {
+            if self.last_eviction.elapsed() >= age {
+                self.compiler.evict_stale_files(age);
+                self.last_eviction = Instant::now();
+            }
+        }
 
         // Notify the new file dependencies.
+        let _span = pipeline_span!("sync_deps", files = tracing::field::Empty);
         let mut deps = vec![];
         self.compiler
             .iter_dependencies(&mut |dep, _| deps.push(dep.clone()));
-        send(Notify(NotifyMessage::SyncDependency(deps)));
+        // Watch the project file unlike the ignored files, so that the
+        // changes of its options take effect.
+        if let Some(project) = &self.project {
+            deps.retain(|dep| !project.config.is_watch_ignored(dep));
+            if !deps.contains(&project.path) {
+                deps.push(project.path.clone());
+            }
+        }
+        // Watch the missing entry file, so that its creation triggers a
+        // compilation.
+        if let Some(missing) = &self.missing_entry {
+            let path: ImmutPath = missing.root.join(&missing.entry).into();
+            if !deps.contains(&path) {
+                deps.push(path);
+            }
+        }
+        // The dependencies are iterated in the order of the file slots, hence
+        // they are sorted for reproducible notifications.
+        deps.sort();
+        deps.dedup();
+        pipeline_record!(_span, "files", deps.len());
+        if let Some(msg) = self.notify_dependencies(deps) {
+            send(Notify(msg));
+        }
+
+        self.observer.on_compile_end(&CompileEnd {
+            request: self.request_id,
+            revision,
+            ok,
+            duration: start.elapsed(),
+        });
+        if let Some(log) = &self.session_log {
+            let diags = self.compiler.diagnostics();
+            let count = |severity| {
+                diags
+                    .iter()
+                    .filter(|diag| diag.severity == severity)
+                    .count()
+            };
+            log.record(SessionEvent::Compile {
+                revision,
+                duration_ms: start.elapsed().as_millis() as u64,
+                ok,
+                errors: count(Severity::Error),
+                warnings: count(Severity::Warning),
+            });
+        }
+    }
+
+    /// Probe the root of the workspace before compiling, returning whether it
+    /// is unavailable, in which case the compilation is skipped.
+    ///
+    /// Once the root is unavailable, the errors of reading the files are
+    /// collapsed into a single diagnostic of the root, which is reported
+    /// once. The files are validated again once the root is available,
+    /// since the file events of the meantime are unreliable.
+    fn probe_root(&mut self) -> bool {
+        let Some(unavailable) = self.compiler.world().check_root() else {
+            self.root_seen = true;
+            if let Some(unavailable) = self.unavailable_root.take() {
+                log::info!(
+                    "CompileActor: workspace root '{}' is available again",
+                    unavailable.root.display()
+                );
+                self.compiler.world_mut().revalidate_files();
+            }
+            return false;
+        };
+        if !self.root_seen {
+            return false;
+        }
+
+        if self.unavailable_root.as_ref() != Some(&unavailable) {
+            log::error!("CompileActor: {unavailable}");
+            let world = self.compiler.world();
+            let revision = self.compiler.revision();
+            let diag = unavailable.diagnostic();
+            self.latest_diagnostics = vec![SerializableDiagnostic::from_source(
+                world,
+                &diag,
+                revision,
+                self.position_encoding,
+            )];
+            self.latest_compile = Some((Instant::now(), false));
+            self.report_done(false);
+        }
+        self.unavailable_root = Some(unavailable);
+        true
     }
 
     /// Process some interrupt.
     fn process(&mut self, event: CompilerInterrupt<Self>, send: impl Fn(CompilerResponse)) -> bool {
-        use CompilerResponse::*;
         // warp the logical clock by one.
         self.logical_tick += 1;
+        self.log_interrupt(&event);
 
         match event {
             // Borrow the compiler thread and run the task.
@@ -281,78 +1138,236 @@ where
             // See [`CompileClient::steal`] for more information.
             CompilerInterrupt::Task(task) => {
                 log::debug!("CompileActor: execute task");
+                let _span = pipeline_span!("task", request = self.request_id);
 
                 task(self);
 
-                // Will never trigger compilation
-                false
+                // Only triggers compilation if the task requests it.
+                std::mem::take(&mut self.recompile_requested)
             }
-            // Handle memory events.
-            CompilerInterrupt::Memory(event) => {
-                log::debug!("CompileActor: memory event incoming");
+            // Run the queued tasks by their priorities.
+            //
+            // See [`CompileClient::with_task_tag`] for more information.
+            CompilerInterrupt::Queued => {
+                loop {
+                    // The queue is unlocked while the task runs.
+                    let next = self.task_queue.lock().pop();
+                    let Some((tag, (task, _))) = next else {
+                        break;
+                    };
+                    log::debug!("CompileActor: execute queued task {tag:?}");
 
-                // Emulate memory changes.
-                let mut files = HashSet::new();
-                if matches!(event, MemoryEvent::Sync(..)) {
-                    files.clone_from(&self.estimated_shadow_files);
-                    self.estimated_shadow_files.clear();
-                }
-                match &event {
-                    MemoryEvent::Sync(event) | MemoryEvent::Update(event) => {
-                        for path in event.removes.iter().map(Deref::deref) {
-                            self.estimated_shadow_files.remove(path);
-                            files.insert(path.into());
-                        }
-                        for path in event.inserts.iter().map(|e| e.0.deref()) {
-                            self.estimated_shadow_files.insert(path.into());
-                            files.remove(path);
-                        }
+                    // Interactive tasks preempt the pending compilation,
+                    // while the others read the document of the latest state.
+                    if tag.category != TaskCategory::Interactive
+                        && std::mem::take(&mut self.pending_compile)
+                    {
+                        self.compile(&send);
+                    }
+                    let _span = pipeline_span!("task", request = self.request_id);
+                    task(self);
+                    if std::mem::take(&mut self.recompile_requested) {
+                        self.request_compile();
                     }
                 }
 
-                // If there is no invalidation happening, apply memory changes directly.
-                if files.is_empty() && self.dirty_shadow_logical_tick == 0 {
-                    self.apply_memory_changes(event);
+                false
+            }
+            // Handle memory events.
+            CompilerInterrupt::Memory(mut events) => {
+                log::debug!("CompileActor: memory event incoming");
 
-                    // Will trigger compilation
-                    return true;
+                // Take all the pending memory events, so that updates queued
+                // up by rapid typing are applied at once.
+                let mut merged = 0;
+                while let Ok(pending) = self.memory_recv.try_recv() {
+                    self.log_memory_events(&pending);
+                    events.extend(pending);
+                    merged += 1;
                 }
+                let _span =
+                    pipeline_span!("memory", request = self.request_id, events = events.len());
 
-                // Otherwise, send upstream update event.
-                // Also, record the logical tick when shadow is dirty.
-                self.dirty_shadow_logical_tick = self.logical_tick;
-                send(Notify(NotifyMessage::UpstreamUpdate(
-                    crate::vfs::notify::UpstreamUpdateEvent {
-                        invalidates: files.into_iter().collect(),
-                        opaque: Box::new(TaggedMemoryEvent {
-                            logical_tick: self.logical_tick,
-                            event,
-                        }),
-                    },
-                )));
-
-                // Delayed trigger compilation
-                false
+                let mut need_recompile = false;
+                for event in MemoryEvent::coalesce(events) {
+                    need_recompile = self.process_memory(event, &send) || need_recompile;
+                }
+                // Each merged batch would have been compiled on its own.
+                if need_recompile {
+                    self.compiles_skipped += merged;
+                }
+                need_recompile
             }
             // Handle file system events.
             CompilerInterrupt::Fs(event) => {
                 log::debug!("CompileActor: fs event incoming {:?}", event);
+                let _span =
+                    pipeline_span!("fs", request = self.request_id, initial = event.is_none());
 
-                // Handle file system event if any.
-                if let Some(mut event) = event {
-                    // Handle delayed upstream update event before applying file system changes
-                    if self.apply_delayed_memory_changes(&mut event).is_none() {
-                        log::warn!("CompileActor: unknown upstream update event");
+                let scanning =
+                    self.enable_watch && self.buffer_initial_scan && !self.initial_scan_completed;
+                match event {
+                    // Compiling before the scan completes would see a partial
+                    // state and compile again on the sentinel.
+                    Some(event) if scanning => {
+                        log::debug!("CompileActor: buffer fs event until the initial scan");
+                        self.pending_fs_events.push(event);
+                        return false;
+                    }
+                    Some(event) => self.apply_fs_event(event),
+                    None => {
+                        self.initial_scan_completed = true;
+                        for event in std::mem::take(&mut self.pending_fs_events) {
+                            self.apply_fs_event(event);
+                        }
                     }
-
-                    // Apply file system changes.
-                    self.compiler.notify_fs_event(event);
                 }
 
                 // Will trigger compilation
                 true
             }
+            // Compile again if the root is available, see
+            // [`Self::probe_root`].
+            CompilerInterrupt::ProbeRoot => true,
+        }
+    }
+
+    /// Apply a file system event to the compiler.
+    fn apply_fs_event(&mut self, mut event: FilesystemEvent) {
+        // Handle delayed upstream update event before applying file system changes
+        if self.apply_delayed_memory_changes(&mut event).is_none() {
+            log::warn!("CompileActor: unknown upstream update event");
+        }
+
+        // Reload the project before compiling with the changes.
+        self.reload_project(&event);
+        self.notify_file_watches(&event);
+
+        // Apply file system changes.
+        self.compiler.notify_fs_event(event);
+    }
+
+    /// Reload the project file if it is changed by a file system event.
+    fn reload_project(&mut self, event: &FilesystemEvent) {
+        let Some(project) = &mut self.project else {
+            return;
+        };
+        let changeset = event.changeset();
+
+        let inserted = (changeset.inserts.iter()).find(|(path, _)| *path == project.path);
+        let content = match inserted {
+            Some((_, snapshot)) => match snapshot.content() {
+                Ok(content) => Some(content.clone()),
+                Err(err) => {
+                    log::error!("CompileActor: failed to read the project file: {err}");
+                    return;
+                }
+            },
+            None if changeset.removes.contains(&project.path) => None,
+            None => return,
+        };
+
+        let previous = match project.reload(content.as_deref()) {
+            Ok(previous) => previous,
+            Err(err) => {
+                log::error!("CompileActor: failed to reload the project file: {err}");
+                return;
+            }
+        };
+        log::info!("CompileActor: reloaded the project file");
+        if let Some(reloader) = &self.project_reloader {
+            reloader(&mut self.compiler, &previous, &project.config);
+        }
+    }
+
+    /// Send the changes of the watched files in a file system event, see
+    /// [`CompileClient::watch_file`].
+    fn notify_file_watches(&self, event: &FilesystemEvent) {
+        let mut watches = self.file_watches.lock();
+        if watches.is_empty() {
+            return;
+        }
+
+        let changeset = event.changeset();
+        for path in &changeset.removes {
+            if let Some(send) = watches.get(path) {
+                let _ = send.send(FileChange::Removed);
+            }
+        }
+        for (path, snapshot) in &changeset.inserts {
+            if let Some(send) = watches.get(path) {
+                let _ = send.send(FileChange::Updated(snapshot.clone()));
+            }
+        }
+        // Forget the files which no receiver watches anymore.
+        watches.retain(|_, send| send.receiver_count() > 0);
+    }
+
+    /// Process a memory event, returning whether it triggers compilation.
+    fn process_memory(&mut self, event: MemoryEvent, send: impl Fn(CompilerResponse)) -> bool {
+        use CompilerResponse::*;
+        // Tag each event of a batch with a different logical tick.
+        self.logical_tick += 1;
+
+        // Emulate memory changes.
+        let mut files = HashSet::new();
+        if matches!(event, MemoryEvent::Sync(..)) {
+            files.clone_from(&self.estimated_shadow_files);
+            self.estimated_shadow_files.clear();
+        }
+        match &event {
+            MemoryEvent::Sync(event) | MemoryEvent::Update(event) => {
+                for path in event.removes.iter().map(Deref::deref) {
+                    self.estimated_shadow_files.remove(path);
+                    files.insert(path.into());
+                }
+                for path in event.inserts.iter().map(|e| e.0.deref()) {
+                    self.estimated_shadow_files.insert(path.into());
+                    files.remove(path);
+                }
+            }
+            MemoryEvent::Edit(..) => {}
+        }
+
+        // If there is no invalidation happening to the touched files, apply
+        // memory changes directly.
+        if files.is_empty() && !self.touches_dirty_shadow(&event) {
+            self.apply_memory_changes(event);
+
+            // Will trigger compilation
+            return true;
+        }
+
+        // Otherwise, send upstream update event.
+        // Also, record the files affected by the invalidation, so that the
+        // successive events touching them are delayed as well, while the
+        // events touching unrelated files are still applied directly.
+        let mut affected = self.dependents(&files);
+        match &event {
+            MemoryEvent::Sync(..) => self.dirty_shadow_logical_tick = self.logical_tick,
+            MemoryEvent::Update(event) => {
+                affected.extend(event.removes.iter().cloned());
+                affected.extend(event.inserts.iter().map(|e| e.0.clone()));
+            }
+            MemoryEvent::Edit(edit) => {
+                affected.insert(edit.path.clone());
+            }
         }
+        for path in affected {
+            self.dirty_shadows.insert(path, self.logical_tick);
+        }
+        send(Notify(NotifyMessage::UpstreamUpdate(
+            crate::vfs::notify::UpstreamUpdateEvent {
+                invalidates: files.into_iter().collect(),
+                opaque: Box::new(TaggedMemoryEvent {
+                    logical_tick: self.logical_tick,
+                    event,
+                }),
+            },
+        )));
+
+        // Delayed trigger compilation
+        false
     }
 
     /// Apply delayed memory changes to underlying compiler.
@@ -360,15 +1375,21 @@ where
         // Handle delayed upstream update event before applying file system changes
         if let FilesystemEvent::UpstreamUpdate { upstream_event, .. } = event {
             let event = upstream_event.take()?.opaque;
+            let Ok(event) = event.downcast::<TaggedMemoryEvent>() else {
+                let err = CompileServiceError::ProtocolMismatch;
+                log::error!("CompileActor: skipped upstream update: {err}");
+                return None;
+            };
             let TaggedMemoryEvent {
                 logical_tick,
                 event,
-            } = *event.downcast().ok()?;
+            } = *event;
 
             // Recovery from dirty shadow state.
             if logical_tick == self.dirty_shadow_logical_tick {
                 self.dirty_shadow_logical_tick = 0;
             }
+            self.dirty_shadows.retain(|_, tick| *tick != logical_tick);
 
             self.apply_memory_changes(event);
         }
@@ -376,6 +1397,45 @@ where
         Some(())
     }
 
+    /// Whether a memory event touches a file affected by a pending
+    /// invalidation, which must be applied before the event.
+    fn touches_dirty_shadow(&self, event: &MemoryEvent) -> bool {
+        if self.dirty_shadow_logical_tick != 0 {
+            return true;
+        }
+        match event {
+            MemoryEvent::Sync(..) => !self.dirty_shadows.is_empty(),
+            MemoryEvent::Update(event) => (event.removes.iter())
+                .chain(event.inserts.iter().map(|e| &e.0))
+                .any(|path| self.dirty_shadows.contains_key(path)),
+            MemoryEvent::Edit(edit) => self.dirty_shadows.contains_key(&edit.path),
+        }
+    }
+
+    /// Get the files affected by changes of the given files, i.e. the files
+    /// and their importers, transitively, in the latest compiled sources.
+    fn dependents(&self, files: &HashSet<ImmutPath>) -> HashSet<ImmutPath> {
+        let mut affected = files.clone();
+        if files.is_empty() {
+            return affected;
+        }
+
+        let compiler = &self.compiler;
+        let importers = deps::importers(compiler.world(), compiler.main_id(), |id| {
+            compiler._shadow_map_id(id).ok()
+        });
+        let mut queue: Vec<_> = files.iter().cloned().collect();
+        while let Some(path) = queue.pop() {
+            for importer in importers.get(path.deref()).into_iter().flatten() {
+                let importer: ImmutPath = importer.as_path().into();
+                if affected.insert(importer.clone()) {
+                    queue.push(importer);
+                }
+            }
+        }
+        affected
+    }
+
     /// Apply memory changes to underlying compiler.
     fn apply_memory_changes(&mut self, event: MemoryEvent) {
         if matches!(event, MemoryEvent::Sync(..)) {
@@ -402,142 +1462,2299 @@ where
                     let _ = self.compiler.map_shadow(&p, insert_file);
                 }
             }
+            MemoryEvent::Edit(edit) => {
+                let res = self
+                    .compiler
+                    .edit_shadow(&edit.path, edit.range, &edit.text);
+                let err = match (res, &edit.fallback) {
+                    (Ok(len), Some(fallback)) if len != fallback.len() => {
+                        format!("length mismatch, {len} != {}", fallback.len())
+                    }
+                    (Ok(..), _) => return,
+                    (Err(err), _) => err.to_string(),
+                };
+
+                match edit.fallback {
+                    Some(fallback) => {
+                        log::warn!(
+                            "CompileActor: edit memory file at {}: {}, use full content instead",
+                            edit.path.display(),
+                            err,
+                        );
+                        let _ = self.compiler.map_shadow(&edit.path, fallback);
+                    }
+                    None => log::error!(
+                        "CompileActor: edit memory file at {}: {}",
+                        edit.path.display(),
+                        err,
+                    ),
+                }
+            }
         }
     }
 }
 
+impl<F: CompilerFeat, C> CompileActor<C>
+where
+    C: Compiler<World = CompilerWorld<F>> + ShadowApi + WorldExporter + Send + 'static,
+{
+    /// Create a new compiler thread for the workspace at `root`, whose world
+    /// uses the given fonts instead of its own.
+    ///
+    /// Servers compiling many documents can pass clones of the same fonts to
+    /// all the actors, so that they are loaded only once, see
+    /// [`crate::font::system::LazyFontResolver`].
+    pub fn new_with_shared_fonts(mut compiler: C, root: ImmutPath, fonts: F::FontResolver) -> Self {
+        let world = compiler.world_mut();
+        world.font_resolver = fonts;
+        let entry = EntryState::new_rooted(root, world.entry_state().main());
+        if let Err(err) = world.mutate_entry(entry) {
+            log::error!("CompileActor: failed to set the workspace root: {err:?}");
+        }
+
+        Self::new(compiler)
+    }
+
+    /// Move the workspace to another root, keeping the main file at the same
+    /// path relative to the root.
+    ///
+    /// The shadows of files in the old root are unmapped, since they belong to
+    /// the old workspace, except for those also in the new root. The document
+    /// is compiled again after the task.
+    pub fn set_root(&mut self, root: ImmutPath) -> ZResult<()> {
+        if !root.is_dir() {
+            return Err(error_once!("the root is not a directory", root: root.display()));
+        }
+
+        let entry = EntryState::new_rooted(root, self.compiler.world().entry_state().main());
+        self.move_root(entry)
+    }
+
+    /// Move the workspace to the root of an entry, see [`Self::set_root`].
+    fn move_root(&mut self, entry: EntryState) -> ZResult<()> {
+        let root = (entry.root()).ok_or_else(|| error_once!("the entry has no root"))?;
+        let world = self.compiler.world_mut();
+        let old = world.workspace_root();
+        world.mutate_entry(entry).map_err(
+            |err| error_once!("failed to set the workspace root", err: format!("{err:?}")),
+        )?;
+
+        if let Some(old) = old.filter(|old| *old != root) {
+            for path in self.compiler.shadow_paths() {
+                if path.starts_with(&old) && !path.starts_with(&root) {
+                    self.estimated_shadow_files.remove(&path);
+                    if let Err(err) = self.compiler.unmap_shadow(&path) {
+                        log::error!("CompileActor: unmap shadow {}: {err}", path.display());
+                    }
+                }
+            }
+        }
+
+        // Watch the files by a new watcher, whose dependencies are updated by
+        // the compilation.
+        if self.watcher.is_some() {
+            self.restart_watcher()?;
+        }
+
+        self.recompile_requested = true;
+        Ok(())
+    }
+}
+
+impl<F, C> CompileActor<C>
+where
+    F: CompilerFeat<FontResolver = LazyFontResolver>,
+    C: Compiler<World = CompilerWorld<F>> + ShadowApi + WorldExporter + Send + 'static,
+{
+    /// Rebuild the world by a change on a standby thread, see
+    /// [`super::standby`].
+    ///
+    /// A change requested while a build is in flight is amended to the build.
+    pub fn rebuild_world(&mut self, change: WorldChange) {
+        if self.standby.building {
+            (self.standby.pending.get_or_insert_with(Default::default)).amend(change);
+            return;
+        }
+        self.standby.building = true;
+
+        let world = self.compiler.world();
+        let fonts = world.font_resolver.clone();
+        let inputs = world.inputs.clone();
+        let main = world.entry_state().main();
+        let steal_send = self.steal_send.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("{}-standby", self.thread_name))
+            .spawn(move || {
+                let built = standby::build(&fonts, inputs, main, change);
+                let task: BorrowTask<Self> =
+                    Box::new(move |this: &mut Self| this.swap_world(built));
+                // The actor may have exited in the meantime.
+                let _ = steal_send.send(task);
+            });
+        if let Err(err) = spawned {
+            log::error!("CompileActor: failed to spawn the standby thread: {err}");
+            self.standby.building = false;
+        }
+    }
+
+    /// Swap the built parts into the world and compile again, or build again
+    /// if changes are amended in the meantime.
+    fn swap_world(&mut self, built: ZResult<BuiltWorld>) {
+        self.standby.building = false;
+        let pending = self.standby.pending.take();
+        let built = match built {
+            Ok(built) => built,
+            Err(err) => {
+                log::error!("CompileActor: failed to rebuild the world: {err}");
+                if let Some(pending) = pending {
+                    self.rebuild_world(pending);
+                }
+                return;
+            }
+        };
+        if let Some(pending) = pending {
+            let mut change = built.change;
+            change.amend(pending);
+            self.rebuild_world(change);
+            return;
+        }
+
+        let BuiltWorld {
+            change,
+            fonts,
+            root,
+        } = built;
+        let world = self.compiler.world_mut();
+        if let Some(fonts) = fonts {
+            world.font_resolver = fonts;
+        }
+        if let Some(hook) = change.library_hook {
+            world.set_library_hook(hook);
+        }
+        if let Some(root) = root {
+            if let Err(err) = self.swap_root(root) {
+                log::error!("CompileActor: failed to rebuild the world: {err}");
+            }
+        }
+        self.recompile_requested = true;
+    }
+
+    /// Swap the state built for a new root into the world.
+    ///
+    /// The cached files of the old root are dropped for the sources read
+    /// ahead under the new one, and the project file of the new root replaces
+    /// the old one, reloading its options.
+    fn swap_root(&mut self, built: BuiltRoot) -> ZResult<()> {
+        let BuiltRoot {
+            entry,
+            contents,
+            project,
+        } = built;
+        let root = (entry.root()).ok_or_else(|| error_once!("the entry has no root"))?;
+        let old = self.compiler.world().workspace_root();
+        self.move_root(entry)?;
+
+        let vfs = &mut self.compiler.world_mut().vfs;
+        if let Some(old) = old.filter(|old| *old != root) {
+            let stale: Vec<ImmutPath> = (vfs.cached_contents().into_iter())
+                .map(|(path, ..)| path)
+                .filter(|path| path.starts_with(&old) && !path.starts_with(&root))
+                .map(ImmutPath::from)
+                .collect();
+            vfs.evict_files(&stale);
+        }
+        vfs.restore_contents(contents);
+
+        if let Some(state) = &mut self.project {
+            let path = root.join(PROJECT_FILE).into();
+            let next = ProjectState::new(path, state.overrides.clone(), project);
+            let previous = std::mem::replace(state, next);
+            if let Some(reloader) = &self.project_reloader {
+                reloader(&mut self.compiler, &previous.config, &state.config);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<F, C> CompileActor<C>
+where
+    F: CompilerFeat<FontResolver = LazyFontResolver, Registry = HttpRegistry>,
+    C: Compiler<World = CompilerWorld<F>> + ShadowApi + WorldExporter + Send + 'static,
+{
+    /// Reload the options of the project when its file changes in watch mode,
+    /// see [`super::project`].
+    ///
+    /// A change of the font paths searches the fonts again, and a change of
+    /// the entry file or of the package directories resolves the files again.
+    /// The export defaults are not reloaded, since the exporters take them on
+    /// creation.
+    pub fn with_project(mut self, project: ProjectState) -> Self {
+        self.project = Some(project);
+        self.project_reloader = Some(Box::new(
+            |compiler: &mut CompileReporter<C>,
+             previous: &ProjectConfig,
+             config: &ProjectConfig| {
+                apply_project_changes(compiler.world_mut(), previous, config)
+            },
+        ));
+        self
+    }
+}
+
+/// Apply the changed options of a project to a world, see
+/// [`CompileActor::with_project`].
+fn apply_project_changes<F>(
+    world: &mut CompilerWorld<F>,
+    previous: &ProjectConfig,
+    config: &ProjectConfig,
+) where
+    F: CompilerFeat<FontResolver = LazyFontResolver, Registry = HttpRegistry>,
+{
+    if previous.font_paths != config.font_paths {
+        let fonts = (world.font_resolver).respawn_with_font_paths(config.font_paths.clone());
+        match fonts {
+            Some(fonts) => world.font_resolver = fonts,
+            None => log::warn!("CompileActor: the fonts cannot be searched again"),
+        }
+    }
+    if previous.packages != config.packages {
+        world.registry.set_overrides(config.package_overrides());
+    }
+
+    let entry = match &config.entry {
+        Some(entry) if previous.entry != config.entry => entry,
+        _ => return,
+    };
+    let selected = world
+        .entry_state()
+        .try_select_path_in_workspace(entry, false);
+    let res = selected.and_then(|state| {
+        let state = state.ok_or_else(|| error_once!("the workspace has no root"))?;
+        (world.mutate_entry(state).map(|_| ()))
+            .map_err(|err| error_once!("failed to set the entry", err: format!("{err:?}")))
+    });
+    if let Err(err) = res {
+        log::error!("CompileActor: failed to set the entry of the project: {err}");
+    }
+}
+
 impl<C: Compiler> CompileActor<C> {
     pub fn with_watch(mut self, enable_watch: bool) -> Self {
         self.enable_watch = enable_watch;
         self
     }
 
-    pub fn split(self) -> (Self, CompileClient<Self>) {
-        let steal_send = self.steal_send.clone();
-        let memory_send = self.memory_send.clone();
-        (
-            self,
-            CompileClient {
-                steal_send,
-                memory_send,
-                _ctx: std::marker::PhantomData,
-            },
-        )
+    /// Record the events of the watch session to a log, see
+    /// [`super::session_log`].
+    pub fn with_session_log(mut self, log: SessionLog) -> Self {
+        self.session_log = Some(Arc::new(log));
+        self
+    }
+
+    /// Set whether to buffer the fs events received before the watcher
+    /// completes its initial scan, which is enabled by default.
+    ///
+    /// The watcher may report changes before the scan-complete sentinel.
+    /// Buffering them compiles once with the full picture on the sentinel,
+    /// instead of first compiling on a partial state. It only takes effect
+    /// with [`Self::with_watch`].
+    pub fn with_initial_scan_buffering(mut self, enabled: bool) -> Self {
+        self.buffer_initial_scan = enabled;
+        self
+    }
+
+    /// Set the name of the compiler thread, which is `typst-compiler` by
+    /// default.
+    ///
+    /// It helps to distinguish actors in profilers and logs when there are
+    /// multiple actors in a process.
+    pub fn with_thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread_name = name.into();
+        self
+    }
+
+    /// Set the unit of columns in diagnostics and in positions accepted or
+    /// returned by the [`CompileClient`], which is UTF-16 by default.
+    ///
+    /// Each client may override it with
+    /// [`CompileClient::with_position_encoding`].
+    pub fn with_position_encoding(mut self, encoding: PositionEncoding) -> Self {
+        self.position_encoding = encoding;
+        self
+    }
+
+    /// Compile with a fixed current datetime instead of the system clock, so
+    /// that `datetime.today()` and document timestamps are reproducible.
+    pub fn with_now(mut self, now: DateTime<Local>) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    /// Enable the experimental features of typst by their names, failing on
+    /// the names unknown to the typst in use, see [`TYPST_FEATURES`].
+    ///
+    /// Typst 0.11.1 gates no behavior behind features, so there is nothing
+    /// to thread into the world yet, and only the empty set is accepted.
+    pub fn with_features(self, features: Vec<String>) -> ZResult<Self> {
+        let unknown = features
+            .iter()
+            .find(|name| !TYPST_FEATURES.contains(&name.as_str()));
+        if let Some(name) = unknown {
+            let known = TYPST_FEATURES.join(", ");
+            return Err(error_once!("unknown typst feature", feature: name, known: known));
+        }
+        Ok(self)
+    }
+
+    /// Limit the number of layout iterations for documents whose
+    /// introspections don't converge, reporting a warning when the limit is
+    /// hit.
+    ///
+    /// By default, the limit of Typst is used, which is five iterations.
+    pub fn with_layout_iteration_limit(mut self, limit: usize) -> Self {
+        self.layout_iteration_limit = Some(limit);
+        self
+    }
+
+    /// Lay out documents with another page size, e.g. a single page growing
+    /// with the content for continuous previews, see [`PageOverride`].
+    pub fn with_page_override(mut self, page_override: PageOverride) -> Self {
+        self.page_override = Some(page_override);
+        self
+    }
+
+    /// Fail the compilations of documents with more than `max` pages, e.g. to
+    /// protect a server from a document generating pages in a loop.
+    ///
+    /// The limit is checked after each layout iteration and reported by a
+    /// diagnostic, hence such a document is never exported. The other limits
+    /// of the compiler are kept, see [`CompileLimits`].
+    pub fn with_max_pages(mut self, max: usize) -> Self {
+        self.max_pages = Some(max);
+        self
+    }
+
+    /// Pass the metadata to the exporters of compilations, see
+    /// [`CompileClient::set_meta`].
+    pub fn with_meta(mut self, meta: CompileMeta) -> Self {
+        self.meta = Arc::new(meta);
+        self
+    }
+
+    /// Evict the caches of the files which are not accessed within the age,
+    /// e.g. of files no longer referenced by a long-running server. It is
+    /// checked after compilations, at most once per the age.
+    pub fn with_cache_eviction(mut self, age: Duration) -> Self {
+        self.cache_eviction = Some(age);
+        self
+    }
+
+    /// Treat the warnings of compilations as errors, e.g. to fail CI builds.
+    ///
+    /// Unlike the [`FAIL_ON_WARNINGS_FEATURE`], the document is still kept,
+    /// so that the warnings can be displayed along with it, while the
+    /// compilation is reported as failed.
+    ///
+    /// [`FAIL_ON_WARNINGS_FEATURE`]: super::features::FAIL_ON_WARNINGS_FEATURE
+    pub fn with_warnings_as_errors(mut self, enabled: bool) -> Self {
+        self.warnings_as_errors = enabled;
+        self
+    }
+
+    /// Report the lines of the sources exercised by each compilation, see
+    /// [`CompileClient::coverage`]. It is disabled by default because of its
+    /// overhead.
+    pub fn with_coverage(mut self, enabled: bool) -> Self {
+        self.coverage = enabled;
+        self
+    }
+
+    /// Only publish the diagnostics at or above the severity, e.g. to surface
+    /// the errors but not the warnings, see [`CompileClient::diagnostics`].
+    ///
+    /// The filter doesn't affect the outcome of compilations, e.g. with
+    /// [`Self::with_warnings_as_errors`].
+    pub fn with_diagnostic_filter(mut self, min_severity: DiagnosticSeverity) -> Self {
+        self.min_severity = min_severity;
+        self
+    }
+
+    /// Call the given function with all the current dependencies, sorted by
+    /// their paths, whenever a compilation changes the set of dependencies,
+    /// e.g. when a file is newly imported.
+    ///
+    /// Unlike the [`NotifyMessage`]s sent to the file watcher, it is only
+    /// called when the set actually changes.
+    pub fn on_dependencies_changed(mut self, cb: impl Fn(&[PathBuf]) + Send + 'static) -> Self {
+        self.deps_observer = Some(Box::new(cb));
+        self
+    }
+
+    /// Format sources by the given formatter instead of the
+    /// [`WhitespaceFormatter`], see [`CompileClient::format`].
+    pub fn with_formatter(mut self, formatter: impl SourceFormatter + 'static) -> Self {
+        self.formatter = Box::new(formatter);
+        self
+    }
+
+    /// Observe the lifecycle of the actor, e.g. for metrics, see
+    /// [`super::observer`].
+    pub fn with_observer(mut self, observer: impl ActorObserver + 'static) -> Self {
+        self.observer = Box::new(observer);
+        self
+    }
+
+    /// Only evaluate the entry instead of compiling the document, e.g. for
+    /// pipelines extracting data which never need pages, see
+    /// [`Self::on_evaluated`].
+    ///
+    /// No document is produced in the eval-only mode, while the diagnostics
+    /// are reported as usual.
+    pub fn with_eval_only(mut self, enabled: bool) -> Self {
+        self.eval_only = enabled;
+        self
+    }
+
+    /// Call the given function with the module of each successful evaluation
+    /// in the eval-only mode, see [`super::eval::module_to_json`].
+    pub fn on_evaluated(mut self, cb: impl Fn(&Module) + Send + 'static) -> Self {
+        self.eval_observer = Some(Box::new(cb));
+        self
+    }
+
+    /// Retain the latest `n` successfully compiled documents, see
+    /// [`CompileClient::document_history`].
+    ///
+    /// No document is retained except the latest one by default. The oldest
+    /// documents are dropped first.
+    pub fn with_doc_history(mut self, n: usize) -> Self {
+        self.doc_revisions_size = n;
+        self
+    }
+
+    /// Set the stack size of the compiler thread, in bytes.
+    ///
+    /// Large documents with deep recursion may need a larger stack than the
+    /// platform default.
+    pub fn with_stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = Some(stack_size);
+        self
+    }
+
+    /// Set the number of tagged tasks waiting to run, beyond which the tasks
+    /// are rejected, see [`CompileClient::with_task_tag`].
+    ///
+    /// Defaults to [`super::queue::DEFAULT_QUEUE_DEPTH`].
+    pub fn with_task_queue_depth(self, depth: usize) -> Self {
+        self.task_queue.lock().set_depth(depth);
+        self
+    }
+
+    pub fn split(self) -> (Self, CompileClient<Self>) {
+        let steal_send = self.steal_send.clone();
+        let task_queue = self.task_queue.clone();
+        let queue_send = self.queue_send.clone();
+        let memory_send = self.memory_send.clone();
+        let position_encoding = self.position_encoding;
+        let progress = self.progress.clone();
+        let file_watches = self.file_watches.clone();
+        (
+            self,
+            CompileClient {
+                steal_send,
+                task_queue,
+                queue_send,
+                task_tag: None,
+                memory_send,
+                position_encoding,
+                progress,
+                file_watches,
+                _ctx: std::marker::PhantomData,
+            },
+        )
+    }
+
+    pub fn document(&self) -> Option<Arc<TypstDocument>> {
+        self.latest_doc.clone()
+    }
+
+    /// Get a retained document by its revision, see
+    /// [`Self::with_doc_history`].
+    pub fn document_at(&self, revision: u64) -> Option<Arc<TypstDocument>> {
+        (self.doc_revisions.iter())
+            .find(|rev| rev.revision == revision)
+            .map(|rev| rev.document.clone())
+    }
+
+    /// Get the health of the actor, which is alive since it is called.
+    fn health(&self) -> ActorHealth {
+        let (last_compile_ms_ago, last_ok) = match self.latest_compile {
+            Some((at, ok)) => (Some(at.elapsed().as_millis() as u64), ok),
+            None => (None, false),
+        };
+
+        ActorHealth {
+            alive: true,
+            last_compile_ms_ago,
+            last_ok,
+            pending_events: self.memory_recv.len()
+                + self.steal_recv.len()
+                + self.task_queue.lock().len(),
+            compiles_skipped: self.compiles_skipped,
+            waiting_for_entry: self.missing_entry.is_some(),
+            root_unavailable: self.unavailable_root.is_some(),
+            rebuilding_world: self.standby.building,
+        }
+    }
+
+    /// Get the diagnostics of the latest compilation, whose positions are in
+    /// the given unit.
+    fn diagnostics(&self, encoding: PositionEncoding) -> Vec<SerializableDiagnostic> {
+        let world = self.compiler.world();
+        let mut diags = self.latest_diagnostics.clone();
+        for diag in &mut diags {
+            diag.reencode(world, encoding);
+        }
+        diags
+    }
+}
+
+impl<F: CompilerFeat, C: Compiler<World = CompilerWorld<F>>> CompileActor<C> {
+    /// Evaluate a prelude before the main source of each compilation, whose
+    /// bindings and rules apply to the whole document, see
+    /// [`super::prelude`].
+    pub fn with_prelude(mut self, content: String) -> Self {
+        self.compiler.world_mut().set_prelude(Some(content));
+        self
+    }
+
+    /// Compile untrusted documents without access to the disk and the
+    /// network, where only the shadow files are readable, see
+    /// [`CompilerWorld::set_safe_mode`].
+    ///
+    /// The denied reads are reported as diagnostics of the documents.
+    pub fn with_safe_mode(mut self, enabled: bool) -> Self {
+        self.compiler.world_mut().set_safe_mode(enabled);
+        self
+    }
+
+    /// Get a snapshot of the configuration, see [`CompilerConfig`].
+    pub fn config(&self) -> CompilerConfig {
+        let world = self.compiler.world();
+        CompilerConfig {
+            root: world.entry.root(),
+            extra_roots: world.extra_roots.clone(),
+            main: world.entry.main(),
+            inputs: world.inputs.deref().deref().clone(),
+            features: self.watch_feature_set.deref().clone(),
+            enable_watch: self.enable_watch,
+            eval_only: self.eval_only,
+            safe_mode: world.safe_mode(),
+            page_override: self.page_override,
+            position_encoding: self.position_encoding,
+            min_severity: self.min_severity,
+            warnings_as_errors: self.warnings_as_errors,
+            coverage: self.coverage,
+            now: self.now,
+            layout_iteration_limit: self.layout_iteration_limit,
+            max_pages: self.max_pages,
+            cache_eviction: self.cache_eviction,
+            doc_history: self.doc_revisions_size,
+            thread_name: self.thread_name.clone(),
+        }
+    }
+
+    /// Persist the contents of the files read by the compilations to a file,
+    /// so that a restarted compiler doesn't read them again, see
+    /// [`crate::cache::save_contents`].
+    ///
+    /// The restored files are still parsed on the first compilation, i.e.
+    /// only the reads are spared, see `test_restore_cache`.
+    #[cfg(feature = "system-compile")]
+    pub fn save_cache(&self, path: &Path) -> ZResult<()> {
+        let contents = self.compiler.world().vfs.cached_contents();
+        crate::cache::save_contents(path, contents)
+    }
+
+    /// Restore the contents persisted by [`Self::save_cache`], returning the
+    /// number of restored files, without accessing the files.
+    ///
+    /// The files are stat'ed on their first access, where the files changed
+    /// since are read again, see [`crate::vfs::Vfs::restore_contents`].
+    #[cfg(feature = "system-compile")]
+    pub fn load_cache(&mut self, path: &Path) -> ZResult<usize> {
+        let contents = crate::cache::load_contents(path)?;
+        Ok(self.compiler.world_mut().vfs.restore_contents(contents))
+    }
+
+    /// Compile the document once with temporary overrides, see
+    /// [`CompileClient::compile_with_overrides`].
+    ///
+    /// The overridden state of the world is restored afterwards, and the
+    /// caches of the compilation are bounded by an eviction.
+    fn compile_with_overrides(
+        &mut self,
+        overrides: &CompileOverrides,
+    ) -> ZResult<Arc<TypstDocument>> {
+        let world = self.compiler.world();
+        let inputs = world.inputs.clone();
+        let entry = world.entry_state();
+        let timestamp = world.creation_timestamp;
+
+        let doc = self.compile_overridden(overrides);
+
+        let world = self.compiler.world_mut();
+        if overrides.inputs.is_some() {
+            world.set_inputs(inputs);
+            // Rebuild the library with the original inputs.
+            if let Err(err) = world.prepare_env(&mut CompileEnv::default()) {
+                log::error!("CompileActor: failed to restore the inputs: {err:?}");
+            }
+        }
+        if overrides.entry.is_some() {
+            if let Err(err) = world.mutate_entry(entry) {
+                log::error!("CompileActor: failed to restore the entry: {err:?}");
+            }
+        }
+        // Also drop the datetime cached by the compilation.
+        world.set_creation_timestamp(timestamp);
+        comemo::evict(30);
+
+        doc
+    }
+
+    /// Apply the overrides and compile, without recording the compilation.
+    fn compile_overridden(&mut self, overrides: &CompileOverrides) -> ZResult<Arc<TypstDocument>> {
+        let world = self.compiler.world_mut();
+        if let Some(inputs) = &overrides.inputs {
+            world.set_inputs(Arc::new(Prehashed::new(inputs.clone())));
+        }
+        if let Some(entry) = &overrides.entry {
+            let state = world
+                .entry_state()
+                .try_select_path_in_workspace(entry, true)?;
+            let state = state.ok_or_else(|| error_once!("failed to determine root"))?;
+            world.mutate_entry(state).map_err(
+                |err| error_once!("failed to set the entry file", err: format!("{err:?}")),
+            )?;
+        }
+
+        let now = overrides
+            .creation_timestamp
+            .map(|timestamp| timestamp.with_timezone(&Local));
+        let mut env = self.unrecorded_env().with_now(now.or(self.now));
+        // Compile by the inner compiler, so that neither the revision nor the
+        // diagnostics of the reporter change.
+        let doc = self.compiler.pure_compile(&mut env).map_err(|diags| {
+            let messages: Vec<_> = diags.iter().map(|diag| diag.message.as_str()).collect();
+            error_once!("failed to compile with overrides", diagnostics: messages.join("; "))
+        })?;
+
+        let Some(pages) = &overrides.pages else {
+            return Ok(doc);
+        };
+        let mut selected = (*doc).clone();
+        selected.pages = (pages.iter())
+            .map(|&page| {
+                let found = page.checked_sub(1).and_then(|idx| doc.pages.get(idx));
+                found
+                    .cloned()
+                    .ok_or_else(|| error_once!("page not found", page: page))
+            })
+            .collect::<ZResult<_>>()?;
+        selected.introspector.rebuild(&selected.pages);
+        Ok(Arc::new(selected))
+    }
+
+    /// Make the environment of a compilation which isn't recorded, i.e.
+    /// compiled by the inner compiler.
+    fn unrecorded_env(&self) -> CompileEnv {
+        CompileEnv::default()
+            .configure_shared(self.once_feature_set.clone())
+            .with_now(self.now)
+            .with_layout_iteration_limit(self.layout_iteration_limit)
+            .with_page_override(self.page_override)
+            .with_limits(CompileLimits::default().with_max_pages(self.max_pages))
+            .with_meta(self.meta.clone())
+    }
+
+    /// Compile each file under the root matching the glob as the entry, see
+    /// [`CompileClient::compile_all`].
+    fn compile_all(&mut self, glob: &str) -> ZResult<Vec<(VirtualPath, CompileResult)>> {
+        let world = self.compiler.world();
+        let entry = world.entry_state();
+        let root = (entry.root()).ok_or_else(|| error_once!("no root to compile the files of"))?;
+        let files = (world.vfs.list_files(&root))
+            .map_err(|err| error_once!("failed to list the files", err: format!("{err:?}")))?;
+        let files: Vec<_> = (files.iter())
+            .filter_map(|path| path.strip_prefix(&root).ok())
+            .filter(|path| glob_match(glob, &path.to_string_lossy().replace('\\', "/")))
+            .map(VirtualPath::new)
+            .collect();
+
+        let mut results = vec![];
+        for file in files {
+            let state = entry.select_in_workspace(TypstFileId::new(None, file.clone()));
+            let mut env = self.unrecorded_env();
+            let doc = match self.compiler.world_mut().mutate_entry(state) {
+                Ok(_) => self.compiler.pure_compile(&mut env),
+                Err(err) => Err(err),
+            };
+            results.push((file, doc));
+        }
+
+        if let Err(err) = self.compiler.world_mut().mutate_entry(entry) {
+            log::error!("CompileActor: failed to restore the entry: {err:?}");
+        }
+        comemo::evict(30);
+        Ok(results)
+    }
+
+    /// Estimate the memory usage of the actor.
+    fn memory_report(&self) -> MemoryReport {
+        let world = self.compiler.world();
+        let (shadow_files, shadow_bytes) = world.vfs.shadow_usage();
+
+        MemoryReport {
+            vfs_bytes: world.vfs.memory_usage(),
+            cache_bytes: world.vfs.cached_bytes(),
+            shadow_files,
+            shadow_bytes,
+            cached_files: world.vfs.cached_files(),
+            font_bytes: world.font_resolver.loaded_font_bytes(),
+            retained_documents: self.retained_documents(),
+            history_documents: self.doc_revisions.len(),
+            document_bytes: self.latest_doc.as_deref().map_or(0, document_bytes),
+        }
+    }
+
+    /// Count the distinct documents held by the actor.
+    fn retained_documents(&self) -> usize {
+        let history = self.doc_revisions.iter().map(|rev| &rev.document);
+        let is_latest = |doc: &Arc<TypstDocument>| {
+            (self.latest_doc.as_ref()).is_some_and(|latest| Arc::ptr_eq(latest, doc))
+        };
+        self.latest_doc.iter().count() + history.filter(|doc| !is_latest(doc)).count()
+    }
+
+    /// Get the source of a file in the workspace, which reflects its shadow
+    /// if any.
+    fn source_at(&self, filepath: &Path) -> ZResult<Source> {
+        let world = self.compiler.world();
+        let id = world.path_mapper().id_for_path(filepath)?;
+        (world.source(id)).map_err(map_string_err("failed to load the source"))
+    }
+}
+
+/// The memory usage of a [`CompileActor`], see [`CompileClient::memory_report`].
+///
+/// The sizes are estimated by the contents held, excluding the overhead of
+/// data structures. Comemo doesn't report the size of its caches, so they
+/// are not included, but they are evicted by [`CompileClient::trim`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryReport {
+    /// Bytes of the sources and files resolved by the latest compilation.
+    pub vfs_bytes: usize,
+    /// Bytes of the file contents cached by the access model.
+    pub cache_bytes: usize,
+    /// The number of shadow files.
+    pub shadow_files: usize,
+    /// Bytes of the contents of shadow files.
+    pub shadow_bytes: usize,
+    /// The number of files cached by the access model.
+    pub cached_files: usize,
+    /// Bytes of the loaded font data.
+    pub font_bytes: usize,
+    /// The number of compiled documents retained by the actor, excluding
+    /// those held by clients.
+    pub retained_documents: usize,
+    /// The number of documents in the history, see
+    /// [`CompileActor::with_doc_history`].
+    pub history_documents: usize,
+    /// Bytes of the frames of the latest document, excluding the fonts.
+    pub document_bytes: usize,
+}
+
+/// How much of the latest compilation is served from the caches, see
+/// [`CompileClient::cache_efficiency`].
+///
+/// Comemo doesn't report which of its memoized calls are validated or
+/// recomputed, so the efficiency is approximated by the reads of files. A read
+/// is cached if the file is unchanged since a previous compilation, in which
+/// case the memoized results depending on the file are likely reused too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEfficiency {
+    /// The number of reads of file contents and sources.
+    pub reads: u64,
+    /// The number of reads served by the cache.
+    pub cached_reads: u64,
+    /// The ratio of the cached reads, which is 1 if nothing is read.
+    pub ratio: f64,
+    /// The lookups of the metadata of fonts in the cache persisting derived
+    /// data since the start of the process, if it is enabled, see
+    /// [`crate::cache`].
+    pub font_info: Option<CacheHits>,
+}
+
+impl From<ReadStats> for CacheEfficiency {
+    fn from(stats: ReadStats) -> Self {
+        let ratio = match stats.reads {
+            0 => 1.,
+            reads => stats.cached as f64 / reads as f64,
+        };
+        Self {
+            reads: stats.reads,
+            cached_reads: stats.cached,
+            ratio,
+            font_info: None,
+        }
+    }
+}
+
+/// Estimate the bytes of the frames of a document, counting the items, the
+/// glyphs and the image data, but not the shared fonts.
+fn document_bytes(doc: &TypstDocument) -> usize {
+    fn frame_bytes(frame: &Frame) -> usize {
+        let items = frame.items().map(|(_, item)| match item {
+            FrameItem::Group(group) => frame_bytes(&group.frame),
+            FrameItem::Text(text) => {
+                text.text.len() + text.glyphs.len() * std::mem::size_of::<Glyph>()
+            }
+            FrameItem::Image(image, _, _) => image.data().len(),
+            FrameItem::Shape(..) | FrameItem::Meta(..) => 0,
+        });
+        let item_size = std::mem::size_of::<(Point, FrameItem)>();
+        items.map(|bytes| bytes + item_size).sum()
+    }
+
+    (doc.pages.iter())
+        .map(|page| std::mem::size_of::<Page>() + frame_bytes(&page.frame))
+        .sum()
+}
+
+/// A successfully compiled document retained by a [`CompileActor`], see
+/// [`CompileActor::with_doc_history`].
+#[derive(Debug, Clone)]
+pub struct DocumentRevision {
+    /// The generation of the document, see [`CompileClient::generation`].
+    pub revision: u64,
+    pub document: Arc<TypstDocument>,
+    /// The time when the compilation finished.
+    pub timestamp: crate::Time,
+}
+
+/// The temporary overrides of a compilation, see
+/// [`CompileClient::compile_with_overrides`].
+#[derive(Debug, Clone, Default)]
+pub struct CompileOverrides {
+    /// The `sys.inputs` of the compilation instead of those of the world.
+    pub inputs: Option<Dict>,
+    /// The entry file of the compilation, which must be in the workspace.
+    pub entry: Option<PathBuf>,
+    /// The 1-based page numbers kept in the compiled document, in the given
+    /// order, e.g. for exporting a selection of pages.
+    pub pages: Option<Vec<usize>>,
+    /// The datetime to compile with instead of that of the actor.
+    pub creation_timestamp: Option<DateTime<Utc>>,
+}
+
+/// The result of compiling a file, see [`CompileClient::compile_all`].
+pub type CompileResult = SourceResult<Arc<TypstDocument>>;
+
+/// Whether a path matches a glob, where `*` and `?` match within a component
+/// and `**` matches any number of components, e.g. `docs/**/*.typ`.
+fn glob_match(glob: &str, path: &str) -> bool {
+    fn components(glob: &[&str], path: &[&str]) -> bool {
+        match (glob.split_first(), path.split_first()) {
+            (None, _) => path.is_empty(),
+            (Some((&"**", rest)), _) => {
+                components(rest, path) || (!path.is_empty() && components(glob, &path[1..]))
+            }
+            (Some((pattern, rest)), Some((name, path))) => {
+                let pattern: Vec<_> = pattern.chars().collect();
+                let name: Vec<_> = name.chars().collect();
+                component(&pattern, &name) && components(rest, path)
+            }
+            (Some(_), None) => false,
+        }
+    }
+
+    fn component(pattern: &[char], name: &[char]) -> bool {
+        match (pattern.split_first(), name.split_first()) {
+            (None, _) => name.is_empty(),
+            (Some(('*', rest)), _) => {
+                component(rest, name) || (!name.is_empty() && component(pattern, &name[1..]))
+            }
+            (Some(('?', rest)), Some((_, name))) => component(rest, name),
+            (Some((c, rest)), Some((n, name))) => c == n && component(rest, name),
+            (Some(_), None) => false,
+        }
+    }
+
+    let glob: Vec<_> = glob.split('/').collect();
+    let path: Vec<_> = path.split('/').collect();
+    components(&glob, &path)
+}
+
+/// The health of a [`CompileActor`], see [`CompileClient::health`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActorHealth {
+    /// Whether the compiler thread responded in time.
+    pub alive: bool,
+    /// Milliseconds elapsed since the latest compilation finished.
+    pub last_compile_ms_ago: Option<u64>,
+    /// Whether the latest compilation succeeded.
+    pub last_ok: bool,
+    /// The number of events waiting to be processed by the compiler thread.
+    pub pending_events: usize,
+    /// The number of compilation requests merged into later ones, i.e. the
+    /// intermediate states skipped when requests arrive faster than they are
+    /// compiled.
+    pub compiles_skipped: u64,
+    /// Whether the latest compilation failed since the entry file doesn't
+    /// exist, in which case the document is compiled once the file is
+    /// created, either on disk or as a shadow file.
+    pub waiting_for_entry: bool,
+    /// Whether the root of the workspace is gone or unreadable, in which case
+    /// the actor stops compiling until the root is available again, which is
+    /// probed periodically and on the file changes.
+    pub root_unavailable: bool,
+    /// Whether the world is being rebuilt on a standby thread, while the old
+    /// world keeps serving the requests, see [`CompileClient::rebuild_world`].
+    pub rebuilding_world: bool,
+}
+
+/// A snapshot of the configuration of a [`CompileActor`], see
+/// [`CompileClient::config`].
+#[derive(Debug, Clone)]
+pub struct CompilerConfig {
+    /// The root of the workspace, if any.
+    pub root: Option<ImmutPath>,
+    /// The extra roots of the workspace, see [`CompilerWorld::extra_roots`].
+    pub extra_roots: Vec<ImmutPath>,
+    /// The main file, if any.
+    pub main: Option<TypstFileId>,
+    /// The inputs of the documents, i.e. `sys.inputs`.
+    pub inputs: Dict,
+    /// The features of the compilations in watch mode.
+    pub features: FeatureSet,
+    pub enable_watch: bool,
+    pub eval_only: bool,
+    /// Whether only the shadow files are readable, see
+    /// [`CompileActor::with_safe_mode`].
+    pub safe_mode: bool,
+    pub page_override: Option<PageOverride>,
+    pub position_encoding: PositionEncoding,
+    pub min_severity: DiagnosticSeverity,
+    pub warnings_as_errors: bool,
+    pub coverage: bool,
+    /// The fixed current datetime, see [`CompileActor::with_now`].
+    pub now: Option<DateTime<Local>>,
+    pub layout_iteration_limit: Option<usize>,
+    pub max_pages: Option<usize>,
+    pub cache_eviction: Option<Duration>,
+    /// The number of retained documents, see [`CompileActor::with_doc_history`].
+    pub doc_history: usize,
+    pub thread_name: String,
+}
+
+/// The pending result of a request, see [`CompileClient::request`].
+///
+/// The handle is a future resolving to the result of the request.
+pub struct RequestHandle<T> {
+    token: CancellationToken,
+    rx: oneshot::Receiver<ZResult<T>>,
+    /// Drop the cancelled tasks from the task queue.
+    purge: Arc<dyn Fn() + Send + Sync>,
+}
+
+impl<T> fmt::Debug for RequestHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestHandle")
+            .field("token", &self.token)
+            .finish()
+    }
+}
+
+impl<T> RequestHandle<T> {
+    /// Cancel the request, which then resolves to [`Cancelled`], see
+    /// [`super::cancel`].
+    pub fn cancel(&self) {
+        self.token.cancel();
+        (self.purge)();
+    }
+
+    /// Whether the request has started to run, after which cancelling it
+    /// relies on the request polling its token.
+    pub fn is_started(&self) -> bool {
+        self.token.is_started()
+    }
+}
+
+impl<T> Future for RequestHandle<T> {
+    type Output = ZResult<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = ready!(Pin::new(&mut self.rx).poll(cx));
+        Poll::Ready(match res {
+            _ if self.token.is_cancelled() => Err(Cancelled.into()),
+            Ok(res) => res,
+            Err(_) => Err(CompileServiceError::TaskDropped.into()),
+        })
+    }
+}
+
+pub struct CompileClient<Ctx> {
+    steal_send: mpsc::UnboundedSender<BorrowTask<Ctx>>,
+    task_queue: Arc<Mutex<TaskQueue<QueuedTask<Ctx>>>>,
+    queue_send: mpsc::UnboundedSender<()>,
+    /// The tag of the tasks sent by the client, see [`Self::with_task_tag`].
+    task_tag: Option<TaskTag>,
+    memory_send: mpsc::UnboundedSender<Vec<MemoryEvent>>,
+    /// The unit of columns in positions accepted or returned by the client.
+    position_encoding: PositionEncoding,
+    /// The latest progress of compilations, which is subscribed by
+    /// [`Self::progress`].
+    progress: Arc<watch::Sender<Option<CompileProgress>>>,
+    /// The watched files, see [`Self::watch_file`].
+    file_watches: FileWatches,
+
+    _ctx: std::marker::PhantomData<Ctx>,
+}
+
+// The clients are cloned and debugged regardless of their context, e.g. the
+// actor, which is neither `Clone` nor `Debug`.
+impl<Ctx> Clone for CompileClient<Ctx> {
+    fn clone(&self) -> Self {
+        Self {
+            steal_send: self.steal_send.clone(),
+            task_queue: self.task_queue.clone(),
+            queue_send: self.queue_send.clone(),
+            task_tag: self.task_tag.clone(),
+            memory_send: self.memory_send.clone(),
+            position_encoding: self.position_encoding,
+            progress: self.progress.clone(),
+            file_watches: self.file_watches.clone(),
+            _ctx: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<Ctx> std::fmt::Debug for CompileClient<Ctx> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompileClient")
+            .field("position_encoding", &self.position_encoding)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Ctx> CompileClient<Ctx> {
+    /// Set the unit of columns in positions accepted or returned by the
+    /// client, which is inherited from the actor by default.
+    ///
+    /// All the jump and diagnostic methods use the same encoding, so that
+    /// they agree with each other.
+    pub fn with_position_encoding(mut self, encoding: PositionEncoding) -> Self {
+        self.position_encoding = encoding;
+        self
+    }
+
+    /// Get the unit of columns in positions accepted or returned by the
+    /// client.
+    pub fn position_encoding(&self) -> PositionEncoding {
+        self.position_encoding
+    }
+
+    /// Tag the tasks sent by the client, which are then queued by their
+    /// priorities, see [`super::queue`].
+    ///
+    /// Untagged tasks are run in the order of sending along with the other
+    /// changes, which is the default. A tagged task replaced by a newer one
+    /// of the same tag is dropped, so that it resolves to an error, and a
+    /// tagged task is rejected with [`CompileServiceError::QueueFull`] if
+    /// the queue is full.
+    pub fn with_task_tag(mut self, tag: Option<TaskTag>) -> Self {
+        self.task_tag = tag;
+        self
+    }
+
+    /// Subscribe to the progress of compilations, see [`super::progress`].
+    ///
+    /// The channel only keeps the latest progress, hence the compiler thread
+    /// is never blocked by slow or absent readers. A progress of an older
+    /// revision than the latest seen one belongs to an abandoned compilation
+    /// and should be discarded.
+    pub fn progress(&self) -> watch::Receiver<Option<CompileProgress>> {
+        self.progress.subscribe()
+    }
+
+    /// Watch the changes of a file reported by the file system, e.g. to
+    /// observe the main file rather than all the dependencies.
+    ///
+    /// The path is matched against the absolute paths of the file system
+    /// events, which are delivered once the actor applies them, i.e. after
+    /// the initial scan in watch mode. The memory changes aren't delivered.
+    /// The file is forgotten once all its receivers are dropped.
+    pub fn watch_file(&self, path: PathBuf) -> broadcast::Receiver<FileChange> {
+        let mut watches = self.file_watches.lock();
+        (watches.entry(path.as_path().into()))
+            .or_insert_with(|| broadcast::channel(FILE_WATCH_CAPACITY).0)
+            .subscribe()
+    }
+
+    fn steal_inner<Ret: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Ctx) -> Ret + Send + 'static,
+    ) -> ZResult<oneshot::Receiver<Ret>> {
+        let (tx, rx) = oneshot::channel();
+
+        // Run the task in the span of the caller.
+        #[cfg(feature = "tracing")]
+        let caller = tracing::Span::current();
+
+        let task = Box::new(move |this: &mut Ctx| {
+            #[cfg(feature = "tracing")]
+            let _caller = (!caller.is_none()).then(|| caller.entered());
+
+            // The request has been cancelled before the task runs, so skip it.
+            if tx.is_closed() {
+                log::debug!("skipped a cancelled task on Typst thread");
+                return;
+            }
+
+            if tx.send(f(this)).is_err() {
+                // Receiver was dropped. The main thread may have exited, or the request may
+                // have been cancelled.
+                log::warn!("could not send back return value from Typst thread");
+            }
+        });
+
+        self.send_task(task, None)?;
+        Ok(rx)
+    }
+
+    /// Send a task to the actor, which is queued if the client is tagged, see
+    /// [`Self::with_task_tag`].
+    fn send_task(&self, task: BorrowTask<Ctx>, token: Option<CancellationToken>) -> ZResult<()> {
+        match &self.task_tag {
+            Some(tag) => {
+                if (self.task_queue.lock().push(tag.clone(), (task, token))).is_err() {
+                    return Err(CompileServiceError::QueueFull.into());
+                }
+                self.queue_send
+                    .send(())
+                    .map_err(|_| CompileServiceError::ActorGone)?;
+            }
+            None => self
+                .steal_send
+                .send(task)
+                .map_err(|_| CompileServiceError::ActorGone)?,
+        }
+        Ok(())
+    }
+
+    /// Run a cancellable request on the compiler thread, see
+    /// [`super::cancel`].
+    ///
+    /// The function is given the token of the request to poll. The returned
+    /// handle resolves to the result of the function, or to [`Cancelled`]
+    /// once the request is cancelled by [`RequestHandle::cancel`]. A request
+    /// cancelled before it runs is dropped from the task queue.
+    pub fn request<Ret: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Ctx, &CancellationToken) -> ZResult<Ret> + Send + 'static,
+    ) -> RequestHandle<Ret>
+    where
+        Ctx: 'static,
+    {
+        self.request_inner(move |this, token, tx| {
+            let _ = tx.send(f(this, &token));
+        })
+    }
+
+    /// Run a request like [`Self::request`], whose function sends the result
+    /// itself, e.g. from another thread after taking the document.
+    fn request_inner<Ret: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Ctx, CancellationToken, oneshot::Sender<ZResult<Ret>>) + Send + 'static,
+    ) -> RequestHandle<Ret>
+    where
+        Ctx: 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let token = CancellationToken::new();
+
+        // Run the task in the span of the caller.
+        #[cfg(feature = "tracing")]
+        let caller = tracing::Span::current();
+
+        let task_token = token.clone();
+        let task = Box::new(move |this: &mut Ctx| {
+            #[cfg(feature = "tracing")]
+            let _caller = (!caller.is_none()).then(|| caller.entered());
+
+            // Dropping the sender resolves a cancelled request, while a
+            // dropped handle skips the request like a dropped steal.
+            if task_token.start() && !tx.is_closed() {
+                f(this, task_token, tx);
+            }
+        });
+
+        let task_queue = self.task_queue.clone();
+        let mut handle = RequestHandle {
+            token: token.clone(),
+            rx,
+            purge: Arc::new(move || {
+                let cancelled = |token: &CancellationToken| token.is_cancelled();
+                (task_queue.lock()).retain(|(_, token)| !token.as_ref().is_some_and(cancelled));
+            }),
+        };
+        if let Err(err) = self.send_task(task, Some(token)) {
+            let (tx, rx) = oneshot::channel();
+            let _ = tx.send(Err(err));
+            handle.rx = rx;
+        }
+        handle
+    }
+
+    /// Steal the compiler thread and run the given function, blocking the
+    /// current thread until it returns.
+    ///
+    /// Within a multi-threaded tokio runtime, it blocks in place, see
+    /// [`tokio::task::block_in_place`], which stalls the calling task only.
+    /// A thread driving a current-thread runtime cannot block, where it
+    /// panics. See [`AsyncCompileClient`] for a client of async hosts.
+    pub fn steal<Ret: Send + 'static>(
+        &mut self,
+        f: impl FnOnce(&mut Ctx) -> Ret + Send + 'static,
+    ) -> ZResult<Ret> {
+        let rx = self.steal_inner(f)?;
+        Ok(blocking_recv(rx).map_err(|_| CompileServiceError::TaskDropped)?)
+    }
+
+    /// Steal the compiler thread and run the given function.
+    pub async fn steal_async<Ret: Send + 'static>(
+        &mut self,
+        f: impl FnOnce(&mut Ctx, tokio::runtime::Handle) -> Ret + Send + 'static,
+    ) -> ZResult<Ret> {
+        // get current async handle
+        let handle = tokio::runtime::Handle::current();
+        let rx = self.steal_inner(move |this: &mut Ctx| f(this, handle.clone()))?;
+        Ok(rx.await.map_err(|_| CompileServiceError::TaskDropped)?)
+    }
+
+    /// Turn into a client which only has async methods, see
+    /// [`AsyncCompileClient`].
+    pub fn into_async(self) -> AsyncCompileClient<Ctx> {
+        AsyncCompileClient(self)
+    }
+
+    /// Send memory changes to the actor, see [`MemoryEvent`].
+    ///
+    /// An error is returned if the actor has exited.
+    pub fn add_memory_changes(&self, event: MemoryEvent) -> ZResult<()> {
+        self.add_memory_changes_batch(vec![event])
+    }
+
+    /// Send a batch of memory changes, which are applied by the actor as a
+    /// unit and trigger at most one compilation.
+    ///
+    /// Consecutive updates are coalesced, see [`MemoryEvent::coalesce`].
+    pub fn add_memory_changes_batch(&self, events: Vec<MemoryEvent>) -> ZResult<()> {
+        let events = MemoryEvent::coalesce(events);
+        if !events.is_empty() {
+            self.memory_send
+                .send(events)
+                .map_err(|_| CompileServiceError::ActorGone)?;
+        }
+        Ok(())
+    }
+
+    /// Replace a byte range of a memory file with the given text, and trigger
+    /// a compilation.
+    ///
+    /// See [`MemoryEvent::Edit`] for more information.
+    pub fn apply_edit(
+        &self,
+        path: ImmutPath,
+        range: Range<usize>,
+        new_text: String,
+    ) -> ZResult<()> {
+        self.add_memory_changes(MemoryEvent::Edit(FileEdit {
+            path,
+            range,
+            text: new_text,
+            fallback: None,
+        }))
+    }
+}
+
+/// Wait for the result of a task in [`CompileClient::steal`].
+fn blocking_recv<Ret>(rx: oneshot::Receiver<Ret>) -> Result<Ret, oneshot::error::RecvError> {
+    use tokio::runtime::{Handle, RuntimeFlavor};
+
+    match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+        // The other tasks of the worker are handed over to another thread
+        // meanwhile, while the threads of `spawn_blocking` simply block.
+        Ok(RuntimeFlavor::MultiThread) => tokio::task::block_in_place(|| rx.blocking_recv()),
+        // Tokio refuses to block a thread driving a current-thread runtime,
+        // which would never run anything else meanwhile, but allows its
+        // threads of `spawn_blocking`.
+        _ => rx.blocking_recv(),
+    }
+}
+
+/// The time to wait for the compiler thread in [`CompileClient::health`].
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl<C: Compiler> CompileClient<CompileActor<C>> {
+    /// Check whether the compiler thread is alive by stealing it.
+    ///
+    /// If the compiler thread is busy for longer than five seconds or has
+    /// exited, the actor is reported as not alive.
+    pub fn health(&mut self) -> ZResult<ActorHealth> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let task = Box::new(move |this: &mut CompileActor<C>| {
+            // The receiver is dropped if the check has timed out.
+            let _ = tx.send(this.health());
+        });
+        if self.steal_send.send(task).is_err() {
+            return Ok(ActorHealth::default());
+        }
+
+        Ok(rx.recv_timeout(HEALTH_TIMEOUT).unwrap_or_default())
+    }
+
+    /// Compile the latest state of the files, resolving to the compiled
+    /// document, or `None` if the compilation has failed.
+    ///
+    /// The compilation is requested when the method is called rather than
+    /// when the future is polled. It is merged with the other pending
+    /// changes like the compilations triggered by them.
+    pub fn compile_async(
+        &self,
+    ) -> impl std::future::Future<Output = ZResult<Option<Arc<TypstDocument>>>> {
+        // A task observes the compilation requested by a preceding task, see
+        // [`CompileActor::handle`]. The receivers are kept until resolved, or
+        // the tasks are skipped as cancelled.
+        let requested = self.steal_inner(|this| this.recompile_requested = true);
+        let compiled = self.steal_inner(|this| this.document());
+        async move {
+            let (requested, compiled) = (requested?, compiled?);
+            requested
+                .await
+                .map_err(|_| CompileServiceError::TaskDropped)?;
+            Ok(compiled
+                .await
+                .map_err(|_| CompileServiceError::TaskDropped)?)
+        }
+    }
+
+    /// Get how much of the latest compilation is served from the caches, see
+    /// [`CacheEfficiency`].
+    ///
+    /// Fails if nothing is compiled yet or the world doesn't track its reads.
+    pub fn cache_efficiency(&mut self) -> ZResult<CacheEfficiency> {
+        let efficiency = self.steal(|this| this.latest_cache_efficiency)?;
+        efficiency.ok_or_else(|| error_once!("no cache efficiency of compilations"))
+    }
+
+    /// Render thumbnails of the pages of the latest compiled document, see
+    /// [`super::render::render_thumbnails`].
+    ///
+    /// The pages are rendered on the calling thread rather than the compiler
+    /// thread, so that the compilations are not blocked meanwhile.
+    #[cfg(feature = "render")]
+    pub fn render_thumbnails(
+        &mut self,
+        options: &super::render::ThumbnailOptions,
+    ) -> ZResult<Vec<super::render::Thumbnail>> {
+        let doc = self.steal(|this| this.document())?;
+        let doc = doc.ok_or_else(|| error_once!("no document is compiled"))?;
+        super::render::render_thumbnails(&doc, options)
+    }
+
+    /// Render a tile of a page of the latest compiled document into a PNG
+    /// image, e.g. for zoomable previews, see [`super::render::render_tile`].
+    ///
+    /// The page number is 1-based and the region is `(x, y, width, height)`
+    /// in pt. Like the thumbnails, the tile is rendered on the calling thread.
+    #[cfg(feature = "render")]
+    pub fn render_tile(
+        &mut self,
+        page: usize,
+        tile_rect: (f64, f64, f64, f64),
+        ppp: f32,
+    ) -> ZResult<Vec<u8>> {
+        let doc = self.steal(|this| this.document())?;
+        let doc = doc.ok_or_else(|| error_once!("no document is compiled"))?;
+        let fill = typst::visualize::Color::WHITE;
+        let tile = super::render::render_tile(&doc, page, tile_rect, ppp, fill)?;
+        tile.encode_png()
+            .map_err(map_string_err("failed to encode tile"))
+    }
+
+    /// Render thumbnails like [`Self::render_thumbnails`] as a cancellable
+    /// request, which stops before the next page once cancelled, see
+    /// [`Self::request`].
+    ///
+    /// The pages are rendered on a thread of their own.
+    #[cfg(feature = "render")]
+    pub fn request_thumbnails(
+        &self,
+        options: super::render::ThumbnailOptions,
+    ) -> RequestHandle<Vec<super::render::Thumbnail>>
+    where
+        C: 'static,
+    {
+        self.request_inner(move |this, token, tx| {
+            let Some(doc) = this.document() else {
+                let _ = tx.send(Err(error_once!("no document is compiled")));
+                return;
+            };
+            let spawned =
+                (std::thread::Builder::new().name("typst-thumbnails".into())).spawn(move || {
+                    let thumbnails =
+                        super::render::render_thumbnails_cancellable(&doc, &options, &token);
+                    let _ = tx.send(thumbnails);
+                });
+            if let Err(err) = spawned {
+                log::error!("CompileClient: failed to spawn thumbnail thread: {err}");
+            }
+        })
+    }
+
+    /// Get the diagnostics of the latest compilation, either errors or
+    /// warnings.
+    ///
+    /// The positions are in the unit of the
+    /// [`CompileClient::position_encoding`].
+    pub fn diagnostics(&mut self) -> ZResult<Vec<SerializableDiagnostic>> {
+        let encoding = self.position_encoding;
+        self.steal(move |this| this.diagnostics(encoding))
+    }
+
+    /// Get the text runs of the latest compiled document, see
+    /// [`document_text`].
+    pub fn document_text(&mut self) -> ZResult<Vec<TextRun>> {
+        let text = self.steal(|this| this.document().map(|doc| document_text(&doc)))?;
+        text.ok_or_else(|| error_once!("no document compiled"))
+    }
+
+    /// Dump the spans of the glyphs on a page of the latest compiled
+    /// document, see [`dump_spans`].
+    #[cfg(debug_assertions)]
+    pub fn dump_spans(&mut self, page: usize) -> ZResult<Vec<SpanDump>> {
+        self.steal(move |this| {
+            let doc = this
+                .document()
+                .ok_or_else(|| error_once!("no document compiled"))?;
+            dump_spans(this.compiler.world(), &doc, page)
+        })?
+    }
+
+    /// Extract the text of the latest compiled document, where the pages are
+    /// separated by form feeds, see [`plain_text`].
+    pub fn export_text(&mut self) -> ZResult<String> {
+        self.export_text_with_delimiter(PAGE_DELIMITER.to_owned())
+    }
+
+    /// Extract the text of the latest compiled document, where the pages are
+    /// separated by the delimiter, see [`plain_text`].
+    pub fn export_text_with_delimiter(&mut self, delimiter: String) -> ZResult<String> {
+        let text =
+            self.steal(move |this| this.document().map(|doc| plain_text(&doc, &delimiter)))?;
+        text.ok_or_else(|| error_once!("no document compiled"))
+    }
+
+    /// Write the vector artifact of the latest compiled document to a file,
+    /// see [`vector_artifact_with`].
+    ///
+    /// The artifact embeds the [`CompileClient::page_metadata`] and the
+    /// headings described by [`document_outline`].
+    pub fn export_artifact(&mut self, path: &Path) -> ZResult<()> {
+        let artifact = self.steal(|this| {
+            let doc = this.document()?;
+            let world = this.compiler.world();
+            let pages = document_page_metadata(world, &doc, this.page_override);
+            let options = ArtifactOptions {
+                page_metadata: Some(pages),
+                outline: Some(document_outline(world, &doc)),
+                ..ArtifactOptions::default()
+            };
+            Some(vector_artifact_with(&doc, &options))
+        })?;
+        let artifact = artifact.ok_or_else(|| error_once!("no document compiled"))?;
+        typst_ts_core::output::write_atomic(path, artifact)
+            .map_err(map_string_err("failed to write vector artifact"))
+    }
+
+    /// Get the statistics of the vector artifact of the latest compiled
+    /// document with the decisions of the image limits, see
+    /// [`vector_artifact_stats`].
+    pub fn artifact_stats(&mut self, top_n: usize) -> ZResult<ArtifactStats> {
+        let stats = self.steal(move |this| {
+            let doc = this.document()?;
+            Some(vector_artifact_stats(this.compiler.world(), &doc, top_n))
+        })?;
+        stats.ok_or_else(|| error_once!("no document compiled"))?
+    }
+
+    /// Get the geometry of the pages of the latest compiled document, see
+    /// [`document_page_metadata`].
+    pub fn page_metadata(&mut self) -> ZResult<Vec<PageMeta>> {
+        let pages = self.steal(|this| {
+            let doc = this.document()?;
+            Some(document_page_metadata(
+                this.compiler.world(),
+                &doc,
+                this.page_override,
+            ))
+        })?;
+        pages.ok_or_else(|| error_once!("no document compiled"))
+    }
+
+    /// Export each page of the latest compiled document into its own PDF
+    /// file in `dir`, see [`super::export_pdf_per_page`].
+    #[cfg(feature = "pdf")]
+    pub fn export_pdf_per_page(&mut self, dir: PathBuf) -> ZResult<Vec<PathBuf>> {
+        let doc = self.steal(|this| this.document())?;
+        let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+        super::export_pdf_per_page(&doc, &dir)
+    }
+
+    /// Export each page of the latest compiled document into its own SVG
+    /// file in `dir`, embedding the glyphs used by each page.
+    #[cfg(feature = "svg")]
+    pub fn export_svg_dir(&mut self, dir: PathBuf) -> ZResult<Vec<PathBuf>> {
+        self.export_svg_dir_with(dir, super::SvgFontEmbedding::default())
+    }
+
+    /// Export each page of the latest compiled document into its own SVG
+    /// file in `dir`, see [`super::export_svg_dir`].
+    #[cfg(feature = "svg")]
+    pub fn export_svg_dir_with(
+        &mut self,
+        dir: PathBuf,
+        fonts: super::SvgFontEmbedding,
+    ) -> ZResult<Vec<PathBuf>> {
+        let doc = self.steal(|this| this.document())?;
+        let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+        super::export_svg_dir(&doc, &dir, fonts)
+    }
+
+    /// Set a metadata entry passed to the exporters, e.g. the build id stamped
+    /// by a PDF exporter, see [`WorldExporter::export`].
+    ///
+    /// It doesn't trigger a compilation by itself, but takes effect from the
+    /// next compilation.
+    pub fn set_meta(&mut self, key: impl Into<String>, value: impl Into<String>) -> ZResult<()> {
+        let (key, value) = (key.into(), value.into());
+        self.steal(move |this| {
+            Arc::make_mut(&mut this.meta).insert(key, value);
+        })
+    }
+
+    /// Enable or disable the coverage report, which takes effect from the next
+    /// compilation, see [`CompileActor::with_coverage`].
+    pub fn set_coverage(&mut self, enabled: bool) -> ZResult<()> {
+        self.steal(move |this| {
+            this.coverage = enabled;
+            if !enabled {
+                this.latest_coverage = None;
+            }
+        })
+    }
+
+    /// Get the coverage of the sources by the latest compiled document, see
+    /// [`super::coverage`].
+    pub fn coverage(&mut self) -> ZResult<CoverageReport> {
+        self.steal(|this| {
+            if !this.coverage {
+                return Err(error_once!("coverage is disabled"));
+            }
+            (this.latest_coverage.clone()).ok_or_else(|| error_once!("no document compiled"))
+        })?
+    }
+
+    /// Compute the coverage of the sources by the latest compiled document
+    /// as a cancellable request, see [`Self::request`].
+    ///
+    /// Unlike [`Self::coverage`], the report is computed on demand, hence the
+    /// coverage doesn't have to be enabled.
+    pub fn request_coverage(&self) -> RequestHandle<CoverageReport>
+    where
+        C: 'static,
+    {
+        self.request(|this, token| {
+            let doc = this.document();
+            let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+            let world = this.compiler.world();
+            document_coverage_cancellable(world, world.source_ids(), &doc, token)
+        })
+    }
+
+    /// Query the latest compiled document by a selector as a cancellable
+    /// request, see [`super::query::retrieve_cancellable`] for when it stops.
+    pub fn request_query(&self, selector: String) -> RequestHandle<Vec<Content>>
+    where
+        C: 'static,
+    {
+        self.request(move |this, token| {
+            let doc = this.document();
+            let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+            retrieve_cancellable(this.compiler.world(), &selector, &doc, token)
+        })
+    }
+
+    /// Get the frame of a page of the latest compiled document, e.g. for
+    /// custom renderers.
+    ///
+    /// Unlike the other page methods, the page is a 0-based index, which gets
+    /// `None` if it is out of range.
+    pub fn page_frame(&mut self, page: usize) -> ZResult<Option<Arc<Frame>>> {
+        self.steal(move |this| {
+            let doc = this.document();
+            let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+            Ok(doc.pages.get(page).map(|page| Arc::new(page.frame.clone())))
+        })?
+    }
+
+    /// Get the clickable regions on a page of the latest compiled document,
+    /// see [`clickable_regions`].
+    ///
+    /// The page number is 1-based, as in [`Position`].
+    pub fn clickable_regions(&mut self, page: usize) -> ZResult<Vec<ClickRegion>> {
+        let regions = self.steal(move |this| {
+            let doc = this.document()?;
+            let page = doc.pages.get(page.checked_sub(1)?)?;
+            Some(clickable_regions(&page.frame))
+        })?;
+        regions.ok_or_else(|| error_once!("page not found", page: page))
+    }
+
+    /// Get the words on a page of the latest compiled document, see
+    /// [`word_regions`].
+    ///
+    /// The page number is 1-based, as in [`Position`].
+    pub fn word_regions(&mut self, page: usize) -> ZResult<Vec<WordRegion>> {
+        let words = self.steal(move |this| {
+            let doc = this.document()?;
+            let page = doc.pages.get(page.checked_sub(1)?)?;
+            Some(word_regions(&page.frame))
+        })?;
+        words.ok_or_else(|| error_once!("page not found", page: page))
+    }
+
+    /// Resolve the context of a position in the latest compiled document, see
+    /// [`context_at`].
+    ///
+    /// The page number is 1-based, as in [`Position`].
+    pub fn context_at(&mut self, page: usize, point: Point) -> ZResult<Option<ContextInfo>> {
+        self.steal(move |this| {
+            let doc = this.document();
+            let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+            Ok(context_at(this.compiler.world(), &doc, page, point))
+        })?
+    }
+
+    /// Get the generation of the latest compiled document, which increases by
+    /// one on each successful compilation.
+    pub fn generation(&mut self) -> ZResult<u64> {
+        self.steal(|this| this.generation)
+    }
+
+    /// Get indices of the pages of the latest compiled document which changed
+    /// since the document of the given generation.
+    ///
+    /// Only a few recent generations are remembered, and an error is returned
+    /// for a generation which is forgotten or not compiled yet.
+    pub fn changed_pages_since(&mut self, old_generation: u64) -> ZResult<Vec<usize>> {
+        self.steal(move |this| {
+            let history = &this.doc_history;
+            let old = history.iter().find(|(gen, _)| *gen == old_generation);
+            match (old, history.back()) {
+                (Some((_, old)), Some((_, new))) => Ok(changed_pages(old, new)),
+                _ => Err(error_once!("unknown document generation", generation: old_generation)),
+            }
+        })?
+    }
+
+    /// Get the content hashes of the latest compiled document, e.g. to skip
+    /// re-exporting an unchanged document, see [`typst_ts_core::content_hash`].
+    pub fn document_hash(&mut self) -> ZResult<DocumentHash> {
+        self.steal(|this| {
+            let doc = this.document();
+            let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+            Ok(DocumentHash::new(&doc))
+        })?
+    }
+
+    /// Get a retained document by its revision, see
+    /// [`CompileActor::with_doc_history`].
+    ///
+    /// An error is returned for a revision which is evicted, failed or not
+    /// compiled yet.
+    pub fn document_at(&mut self, revision: u64) -> ZResult<Arc<TypstDocument>> {
+        let doc = self.steal(move |this| this.document_at(revision))?;
+        doc.ok_or_else(|| error_once!("unknown document revision", revision: revision))
+    }
+
+    /// Get the retained documents, the oldest first, see
+    /// [`CompileActor::with_doc_history`].
+    pub fn document_history(&mut self) -> ZResult<Vec<DocumentRevision>> {
+        self.steal(|this| this.doc_revisions.iter().cloned().collect())
+    }
+
+    /// Pack the incremental delta from a retained document to another, see
+    /// [`IncrDocServer::pack_delta_between`].
+    ///
+    /// A renderer showing the document of the `from` revision applies the
+    /// delta to show that of the `to` revision.
+    pub fn artifact_delta(&mut self, from: u64, to: u64) -> ZResult<Vec<u8>> {
+        let base = self.document_at(from)?;
+        let doc = self.document_at(to)?;
+        Ok(IncrDocServer::pack_delta_between(base, doc))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DocToSrcJumpInfo {
+    pub filepath: String,
+    /// The 0-based (line, column), where the column is in the unit of the
+    /// [`CompileClient::position_encoding`].
+    pub start: Option<(usize, usize)>,
+    pub end: Option<(usize, usize)>,
+}
+
+impl<F: CompilerFeat, Ctx> CompileClient<CompileActor<Ctx>>
+where
+    Ctx: Compiler<World = CompilerWorld<F>> + ShadowApi + WorldExporter + Send + 'static,
+{
+    /// Move the workspace to another root and compile again, see
+    /// [`CompileActor::set_root`].
+    ///
+    /// The relative paths accepted by the client, e.g. by
+    /// [`Self::resolve_src_to_doc_jump`], are resolved against the new root
+    /// afterwards.
+    pub fn set_root(&mut self, root: PathBuf) -> ZResult<()> {
+        self.steal(move |this| this.set_root(root.as_path().into()))?
+    }
+
+    /// Restart the file watcher, see [`CompileActor::restart_watcher`].
+    pub fn restart_watcher(&mut self) -> ZResult<()> {
+        self.steal(|this| this.restart_watcher())?
+    }
+
+    /// Evaluate the entry without laying it out, returning the exported
+    /// bindings of the module in JSON, see [`module_to_json`].
+    ///
+    /// The diagnostics of the evaluation are reported like those of
+    /// compilations, and errors are also returned.
+    pub fn eval_entry(&mut self) -> ZResult<serde_json::Value> {
+        self.steal(|this| {
+            let mut env = this.make_env(this.once_feature_set.clone());
+            let module = this.compiler.evaluate(&mut env);
+            this.report_done(module.is_ok());
+            match module {
+                Ok(module) => Ok(module_to_json(&module)),
+                Err(_) => {
+                    let messages: Vec<_> = (this.compiler.diagnostics().iter())
+                        .map(|diag| diag.message.as_str())
+                        .collect();
+                    Err(error_once!(
+                        "failed to evaluate the entry",
+                        diagnostics: messages.join("; ")
+                    ))
+                }
+            }
+        })?
+    }
+
+    /// Compile the document once with temporary overrides, e.g. a draft
+    /// preview with other inputs, without disturbing the state of the watch
+    /// loop.
+    ///
+    /// The document is neither stored as the latest document nor counted as a
+    /// revision, and the world is restored exactly after the compilation.
+    pub fn compile_with_overrides(
+        &mut self,
+        overrides: CompileOverrides,
+    ) -> ZResult<Arc<TypstDocument>> {
+        self.steal(move |this| this.compile_with_overrides(&overrides))?
+    }
+
+    /// Compile each file under the root matching the glob as the main file,
+    /// e.g. `docs/**/*.typ`, returning the results in the order of the paths.
+    ///
+    /// The files are compiled one by one, and a failing file doesn't abort the
+    /// others. Like [`Self::compile_with_overrides`], the documents are not
+    /// stored as the latest document, and the main file is restored
+    /// afterwards.
+    pub fn compile_all(&mut self, glob: String) -> ZResult<Vec<(VirtualPath, CompileResult)>> {
+        self.steal(move |this| this.compile_all(&glob))?
+    }
+
+    /// List the font families known to the compiler, see [`list_fonts`].
+    pub fn list_fonts(&mut self) -> ZResult<Vec<FontFamilyInfo>> {
+        self.steal(|this| list_fonts(&this.compiler.world().font_resolver))
+    }
+
+    /// Get the fonts which are skipped since they fail to load, e.g. a
+    /// corrupt font uploaded by a user.
+    pub fn font_load_errors(&mut self) -> ZResult<Vec<FontLoadError>> {
+        self.steal(|this| this.compiler.world().font_resolver.load_errors())
+    }
+
+    /// Look up the characters of a text which a font family doesn't cover,
+    /// see [`font_coverage`].
+    pub fn font_coverage(&mut self, family: String, text: String) -> ZResult<CoverageResult> {
+        self.steal(move |this| {
+            let book = this.compiler.world().font_resolver.font_book();
+            font_coverage(book, &family, &text)
+        })?
+    }
+}
+
+impl<F, Ctx> CompileClient<CompileActor<Ctx>>
+where
+    F: CompilerFeat<FontResolver = LazyFontResolver>,
+    Ctx: Compiler<World = CompilerWorld<F>> + ShadowApi + WorldExporter + Send + 'static,
+{
+    /// Rebuild the world by a change, e.g. of the font paths, and compile
+    /// again, see [`super::standby`].
+    ///
+    /// The world is built on a standby thread, while the old world keeps
+    /// serving the requests until the new one is swapped in, see
+    /// [`ActorHealth::rebuilding_world`].
+    pub fn rebuild_world(&mut self, change: WorldChange) -> ZResult<()> {
+        self.steal(move |this| this.rebuild_world(change))
+    }
+}
+
+// todo: remove constraint to CompilerWorld
+impl<F: CompilerFeat, Ctx: Compiler<World = CompilerWorld<F>>> CompileClient<CompileActor<Ctx>>
+where
+    Ctx::World: EntryManager,
+{
+    /// Report the estimated memory usage of the actor, see [`MemoryReport`].
+    pub fn memory_report(&mut self) -> ZResult<MemoryReport> {
+        self.steal(|this| this.memory_report())
+    }
+
+    /// Report the memory usage of the actor.
+    #[deprecated(note = "use `CompileClient::memory_report` instead")]
+    pub fn memory_usage(&mut self) -> ZResult<MemoryReport> {
+        self.memory_report()
+    }
+
+    /// Drop the file caches which are not used by the latest compilation and
+    /// evict all the comemo caches, returning the memory usage afterwards.
+    pub fn trim(&mut self) -> ZResult<MemoryReport> {
+        self.steal(|this| {
+            this.compiler.world_mut().vfs.trim();
+            comemo::evict(0);
+            this.memory_report()
+        })
+    }
+
+    /// Export the dependency graph of the latest compilation, see
+    /// [`dep_graph`].
+    pub fn dep_graph(&mut self, format: DepGraphFormat) -> ZResult<String> {
+        self.steal(move |this| {
+            let doc = this.document();
+            dep_graph(this.compiler.world(), doc.as_deref()).render(&format)
+        })
+    }
+
+    /// Pin the datetime to compile with, or reset to the system clock by
+    /// `None`. The datetime is kept across recompilations, e.g. in watch mode,
+    /// until updated again.
+    pub fn set_creation_timestamp(&mut self, timestamp: Option<DateTime<Utc>>) -> ZResult<()> {
+        self.steal(move |this| this.compiler.world_mut().set_creation_timestamp(timestamp))
+    }
+
+    /// Set the hook customizing the library, or remove it by `None`, and
+    /// compile again, see [`super::library`].
+    ///
+    /// The library is rebuilt, which invalidates the cached results depending
+    /// on it.
+    pub fn set_library_hook(&mut self, hook: Option<LibraryHook>) -> ZResult<()> {
+        self.steal(move |this| {
+            this.compiler.world_mut().set_library_hook(hook);
+            this.recompile_requested = true;
+        })
+    }
+
+    /// Set the preprocessor rewriting the sources and compile again, see
+    /// [`crate::vfs::Vfs::set_preprocessor`].
+    ///
+    /// The jumps between the sources and the document are disabled unless the
+    /// preprocessor preserves the offsets.
+    pub fn set_source_preprocessor(
+        &mut self,
+        preprocessor: Option<Arc<dyn SourcePreprocessor>>,
+    ) -> ZResult<()> {
+        self.steal(move |this| {
+            this.compiler.world_mut().vfs.set_preprocessor(preprocessor);
+            this.recompile_requested = true;
+        })
+    }
+
+    /// Register an untitled buffer of an editor by its URI, see
+    /// [`CompilerWorld::register_untitled`].
+    pub fn register_untitled(&mut self, uri: String, base: ImmutPath) -> ZResult<TypstFileId> {
+        self.steal(move |this| this.compiler.world_mut().register_untitled(&uri, base))
+    }
+
+    /// Save an untitled buffer to the path, see
+    /// [`CompilerWorld::save_untitled`].
+    pub fn save_untitled(&mut self, uri: String, path: PathBuf) -> ZResult<TypstFileId> {
+        self.steal(move |this| {
+            let id = (this.compiler.world_mut().save_untitled(&uri, &path))
+                .map_err(map_string_err("failed to save the untitled buffer"))?;
+            if this.estimated_shadow_files.remove(Path::new(&uri)) {
+                this.estimated_shadow_files.insert(path.as_path().into());
+            }
+            Ok(id)
+        })?
+    }
+
+    /// Register an auxiliary data file of the document, whose cache is
+    /// invalidated by the given strategy instead of its mtime, see
+    /// [`crate::vfs::Vfs::register_aux_path`].
+    pub fn register_aux_path(
+        &mut self,
+        path: ImmutPath,
+        strategy: InvalidationStrategy,
+    ) -> ZResult<()> {
+        self.steal(move |this| {
+            let vfs = &mut this.compiler.world_mut().vfs;
+            vfs.register_aux_path(path, strategy)
+        })
+    }
+
+    /// Unregister an auxiliary data file, restoring the invalidation by its
+    /// mtime. Returns whether the path was registered.
+    pub fn unregister_aux_path(&mut self, path: ImmutPath) -> ZResult<bool> {
+        self.steal(move |this| this.compiler.world_mut().vfs.unregister_aux_path(&path))
+    }
+
+    /// Resolve the value of a counter at a labelled element of the latest
+    /// compiled document, see [`counter_at`].
+    pub fn counter_at(&mut self, counter: String, label: String) -> ZResult<Option<Vec<i64>>> {
+        self.steal(move |this| {
+            let doc = this.document();
+            let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+            counter_at(this.compiler.world(), &doc, &counter, &label)
+        })?
+    }
+
+    /// Get the labels of the latest compiled document, see
+    /// [`document_labels`].
+    pub fn labels(&mut self) -> ZResult<Vec<LabelInfo>> {
+        self.steal(|this| {
+            let doc = this.document();
+            let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+            Ok(document_labels(&doc))
+        })?
+    }
+
+    /// Get the title and the other metadata of the latest compiled document,
+    /// see [`document_info`].
+    pub fn document_info(&mut self) -> ZResult<DocumentInfo> {
+        self.steal(|this| {
+            let doc = this.document();
+            let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+            Ok(document_info(&doc))
+        })?
+    }
+
+    /// Count the pages and the words of the latest compiled document, see
+    /// [`document_stats`].
+    pub fn stats(&mut self) -> ZResult<DocumentStats> {
+        let stats = self.steal(|this| this.document().map(|doc| document_stats(&doc)))?;
+        stats.ok_or_else(|| error_once!("no document compiled"))
+    }
+
+    /// Get the pages on which a source file renders in the latest compiled
+    /// document, see [`pages_for_file`].
+    pub fn pages_for_file(&mut self, file: VirtualPath) -> ZResult<Vec<usize>> {
+        let id = TypstFileId::new(None, file);
+        let pages = self.steal(move |this| this.document().map(|doc| pages_for_file(&doc, id)))?;
+        pages.ok_or_else(|| error_once!("no document compiled"))
     }
 
-    pub fn document(&self) -> Option<Arc<TypstDocument>> {
-        self.latest_doc.clone()
+    /// Get a snapshot of the configuration of the actor, e.g. to display the
+    /// active settings, see [`CompilerConfig`].
+    pub fn config(&mut self) -> ZResult<CompilerConfig> {
+        self.steal(|this| this.config())
     }
-}
-#[derive(Debug, Clone)]
-pub struct CompileClient<Ctx> {
-    steal_send: mpsc::UnboundedSender<BorrowTask<Ctx>>,
-    memory_send: mpsc::UnboundedSender<MemoryEvent>,
 
-    _ctx: std::marker::PhantomData<Ctx>,
-}
+    /// List the shadow files whose contents differ from those on disk, i.e.
+    /// the files with unsaved changes, which take precedence over the disk,
+    /// see [`crate::vfs::Vfs::shadow_divergences`].
+    pub fn shadow_divergences(&mut self) -> ZResult<Vec<PathBuf>> {
+        self.steal(|this| {
+            let paths = this.compiler.world().vfs.shadow_divergences();
+            paths.iter().map(|path| path.to_path_buf()).collect()
+        })
+    }
 
-impl<Ctx> CompileClient<Ctx> {
-    fn steal_inner<Ret: Send + 'static>(
+    /// Get how much of the syntax tree is reused by the latest reparse of an
+    /// edit, see [`CompileClient::apply_edit`] and
+    /// [`crate::vfs::Vfs::last_reparse_stats`].
+    pub fn last_reparse_stats(&mut self) -> ZResult<ReparseStats> {
+        let stats = self.steal(|this| this.compiler.world().vfs.last_reparse_stats())?;
+        stats.ok_or_else(|| error_once!("no edit is reparsed yet"))
+    }
+
+    /// Get the metadata elements of the latest compiled document with their
+    /// pages and sources, see [`document_metadata`] for the key filter.
+    ///
+    /// The positions are in the unit of the
+    /// [`CompileClient::position_encoding`].
+    pub fn metadata_anchors(&mut self, key_filter: Option<String>) -> ZResult<Vec<MetadataAnchor>> {
+        let encoding = self.position_encoding;
+        let anchors = self.steal(move |this| {
+            let doc = this.document()?;
+            let mut anchors = document_metadata(&doc, key_filter.as_deref());
+            resolve_metadata_sources(this.compiler.world(), &mut anchors, encoding);
+            Some(anchors)
+        })?;
+        anchors.ok_or_else(|| error_once!("no document compiled"))
+    }
+
+    /// Write the vector artifact of the latest compiled document to a file
+    /// like [`CompileClient::export_artifact`], which also embeds the
+    /// metadata elements described by [`CompileClient::metadata_anchors`].
+    ///
+    /// The sources of the elements are only embedded with
+    /// `include_source_mapping`, see [`ArtifactOptions::include_source_mapping`].
+    pub fn export_artifact_with_metadata(
         &mut self,
-        f: impl FnOnce(&mut Ctx) -> Ret + Send + 'static,
-    ) -> ZResult<oneshot::Receiver<Ret>> {
-        let (tx, rx) = oneshot::channel();
+        path: &Path,
+        include_source_mapping: bool,
+    ) -> ZResult<()> {
+        let encoding = self.position_encoding;
+        let artifact = self.steal(move |this| {
+            let doc = this.document()?;
+            let world = this.compiler.world();
+            let mut anchors = document_metadata(&doc, None);
+            resolve_metadata_sources(world, &mut anchors, encoding);
+            let options = ArtifactOptions {
+                page_metadata: Some(document_page_metadata(world, &doc, this.page_override)),
+                outline: Some(document_outline(world, &doc)),
+                metadata_anchors: Some(anchors),
+                include_source_mapping,
+                ..ArtifactOptions::default()
+            };
+            Some(vector_artifact_with(&doc, &options))
+        })?;
+        let artifact = artifact.ok_or_else(|| error_once!("no document compiled"))?;
+        typst_ts_core::output::write_atomic(path, artifact)
+            .map_err(map_string_err("failed to write vector artifact"))
+    }
 
-        let task = Box::new(move |this: &mut Ctx| {
-            if tx.send(f(this)).is_err() {
-                // Receiver was dropped. The main thread may have exited, or the request may
-                // have been cancelled.
-                log::warn!("could not send back return value from Typst thread");
-            }
-        });
+    /// Format a source file, which may only exist as a shadow file, see
+    /// [`format_source`].
+    ///
+    /// The positions of the edits are in the unit of the
+    /// [`CompileClient::position_encoding`]. The world is not changed, and the
+    /// editor applies the edits, e.g. by [`CompileClient::apply_edit`].
+    pub fn format(&mut self, filepath: PathBuf, options: FormatOptions) -> ZResult<Vec<TextEdit>> {
+        let encoding = self.position_encoding;
+        self.steal(move |this| {
+            let source = this.source_at(&filepath)?;
+            format_source(this.formatter.as_ref(), &source, &options, encoding)
+        })?
+    }
 
-        self.steal_send
-            .send(task)
-            .map_err(map_string_err("failed to send to steal"))?;
-        Ok(rx)
+    /// Serialize the syntax tree of a source file, which may only exist as a
+    /// shadow file, see [`syntax_tree`].
+    pub fn syntax_tree(&mut self, filepath: PathBuf, format: SyntaxTreeFormat) -> ZResult<String> {
+        self.steal(move |this| {
+            let source = this.source_at(&filepath)?;
+            Ok(syntax_tree(&source, format))
+        })?
     }
 
-    pub fn steal<Ret: Send + 'static>(
+    /// Get the syntax nodes from the root to the leaf at a byte offset of a
+    /// source file, e.g. to tell whether the cursor is in math, see
+    /// [`syntax_path`].
+    pub fn syntax_node_at(
         &mut self,
-        f: impl FnOnce(&mut Ctx) -> Ret + Send + 'static,
-    ) -> ZResult<Ret> {
-        self.steal_inner(f)?
-            .blocking_recv()
-            .map_err(map_string_err("failed to recv from steal"))
+        filepath: PathBuf,
+        offset: usize,
+    ) -> ZResult<Vec<SyntaxAncestor>> {
+        self.steal(move |this| syntax_path(&this.source_at(&filepath)?, offset))?
     }
 
-    /// Steal the compiler thread and run the given function.
-    pub async fn steal_async<Ret: Send + 'static>(
+    /// Compile the bytes in `range` of a source file into a standalone
+    /// preview, see [`compile_fragment`].
+    ///
+    /// The fragment is compiled in isolation with the layout options of the
+    /// actor, leaving the main file and the latest document untouched.
+    pub fn compile_fragment(
         &mut self,
-        f: impl FnOnce(&mut Ctx, tokio::runtime::Handle) -> Ret + Send + 'static,
-    ) -> ZResult<Ret> {
-        // get current async handle
-        let handle = tokio::runtime::Handle::current();
-        self.steal_inner(move |this: &mut Ctx| f(this, handle.clone()))?
-            .await
-            .map_err(map_string_err("failed to call steal_async"))
+        file: VirtualPath,
+        range: Range<usize>,
+    ) -> ZResult<Option<Arc<TypstDocument>>> {
+        self.steal(move |this| {
+            let id = TypstFileId::new(None, file);
+            let doc = compile_fragment(
+                this.compiler.world(),
+                id,
+                range,
+                this.layout_iteration_limit,
+                this.page_override,
+            )?;
+            Ok(doc.map(Arc::new))
+        })?
     }
 
-    pub fn add_memory_changes(&self, event: MemoryEvent) {
-        log_send_error("mem_event", self.memory_send.send(event));
+    /// Find the most specific syntax node at a byte offset of a source file,
+    /// see [`span_at_offset`].
+    pub fn span_at_offset(
+        &mut self,
+        file: VirtualPath,
+        offset: usize,
+    ) -> ZResult<Option<(SyntaxKind, Range<usize>)>> {
+        self.steal(move |this| {
+            let id = TypstFileId::new(None, file);
+            let source = (this.compiler.world().source(id))
+                .map_err(map_string_err("failed to load the source"))?;
+            Ok(span_at_offset(&source, offset))
+        })?
     }
-}
-
-#[derive(Debug, Serialize)]
-pub struct DocToSrcJumpInfo {
-    pub filepath: String,
-    pub start: Option<(usize, usize)>, // row, column
-    pub end: Option<(usize, usize)>,
-}
 
-// todo: remove constraint to CompilerWorld
-impl<F: CompilerFeat, Ctx: Compiler<World = CompilerWorld<F>>> CompileClient<CompileActor<Ctx>>
-where
-    Ctx::World: EntryManager,
-{
-    /// fixme: character is 0-based, UTF-16 code unit.
-    /// We treat it as UTF-8 now.
+    /// Find the position in the latest compiled document for a cursor.
+    ///
+    /// The line and character are 0-based, where the character is in the unit
+    /// of the [`CompileClient::position_encoding`].
     pub async fn resolve_src_to_doc_jump(
         &mut self,
         filepath: PathBuf,
         line: usize,
         character: usize,
     ) -> ZResult<Option<Position>> {
+        let encoding = self.position_encoding;
+        let position = DiagnosticPosition {
+            line,
+            column: character,
+        };
         self.steal_async(move |this, _| {
             let doc = this.document()?;
 
             let world = this.compiler.world();
+            if !world.vfs.preserves_offsets() {
+                return None;
+            }
 
-            let root = this.compiler.world().workspace_root()?;
-            let relative_path = filepath.strip_prefix(&root).ok()?;
-
-            let source_id = TypstFileId::new(None, VirtualPath::new(relative_path));
+            // The source may only exist as a shadow file.
+            let source_id = world.path_mapper().id_for_path(&filepath).ok()?;
             let source = world.source(source_id).ok()?;
-            let cursor = source.line_column_to_byte(line, character)?;
+            let cursor = to_offset(&source, position, encoding);
 
             jump_from_cursor(&doc, &source, cursor)
         })
         .await
     }
 
-    /// fixme: character is 0-based, UTF-16 code unit.
-    /// We treat it as UTF-8 now.
+    /// Find all the positions in the latest compiled document for a cursor,
+    /// e.g. to highlight each occurrence of a text in a function called more
+    /// than once, see [`jump_all_from_cursor`].
+    ///
+    /// The line and character are as in
+    /// [`CompileClient::resolve_src_to_doc_jump`].
+    pub async fn all_positions_for_cursor(
+        &mut self,
+        filepath: PathBuf,
+        line: usize,
+        character: usize,
+    ) -> ZResult<Vec<Position>> {
+        let encoding = self.position_encoding;
+        let position = DiagnosticPosition {
+            line,
+            column: character,
+        };
+        self.steal_async(move |this, _| {
+            let Some(doc) = this.document() else {
+                return vec![];
+            };
+
+            let world = this.compiler.world();
+            if !world.vfs.preserves_offsets() {
+                return vec![];
+            }
+
+            let source = (world.path_mapper().id_for_path(&filepath).ok())
+                .and_then(|id| world.source(id).ok());
+            let Some(source) = source else {
+                return vec![];
+            };
+            let cursor = to_offset(&source, position, encoding);
+
+            jump_all_from_cursor(&doc, &source, cursor)
+        })
+        .await
+    }
+
+    /// Find the caret in the latest compiled document for a cursor, e.g. to
+    /// draw a synchronized caret in a preview, see [`caret_from_cursor`].
+    ///
+    /// The line and character are as in
+    /// [`CompileClient::resolve_src_to_doc_jump`].
+    pub fn caret_position(
+        &mut self,
+        filepath: PathBuf,
+        line: usize,
+        character: usize,
+    ) -> ZResult<Option<CaretPosition>> {
+        let encoding = self.position_encoding;
+        let position = DiagnosticPosition {
+            line,
+            column: character,
+        };
+        self.steal(move |this| {
+            let doc = this.document()?;
+
+            let world = this.compiler.world();
+            if !world.vfs.preserves_offsets() {
+                return None;
+            }
+
+            let source_id = world.path_mapper().id_for_path(&filepath).ok()?;
+            let source = world.source(source_id).ok()?;
+            let cursor = to_offset(&source, position, encoding);
+
+            caret_from_cursor(&doc, &source, cursor)
+        })
+    }
+
+    /// Resolve the span of the text at a location, whose column is in the unit
+    /// of the [`CompileClient::position_encoding`].
     pub async fn resolve_src_location(
         &mut self,
         loc: SourceLocation,
     ) -> ZResult<Option<SourceSpanOffset>> {
+        let encoding = self.position_encoding;
         self.steal_async(move |this, _| {
             let world = this.compiler.world();
 
-            let filepath = Path::new(&loc.filepath);
-
-            let root = this.compiler.world().workspace_root()?;
-            let relative_path = filepath.strip_prefix(&root).ok()?;
-
-            let source_id = TypstFileId::new(None, VirtualPath::new(relative_path));
+            let source_id = (world.path_mapper())
+                .id_for_path(Path::new(&loc.filepath))
+                .ok()?;
             let source = world.source(source_id).ok()?;
-            let cursor = source.line_column_to_byte(loc.pos.line, loc.pos.column)?;
+            let position = DiagnosticPosition {
+                line: loc.pos.line,
+                column: loc.pos.column,
+            };
+            let cursor = to_offset(&source, position, encoding);
 
             let node = LinkedNode::new(source.root()).leaf_at(cursor)?;
             if node.kind() != SyntaxKind::Text {
@@ -552,6 +3769,87 @@ where
         .await
     }
 
+    /// Resolve the source location of the glyph at the given position of the
+    /// latest compiled document.
+    ///
+    /// The page number is 1-based, as in [`Position`].
+    pub async fn source_location_at(
+        &mut self,
+        page: usize,
+        point: Point,
+    ) -> ZResult<Option<DocToSrcJumpInfo>> {
+        let hit = self
+            .steal_async(move |this, _| {
+                let doc = this.document()?;
+                let page = doc.pages.get(page.checked_sub(1)?)?;
+                span_from_point(&page.frame, point)
+            })
+            .await?;
+
+        match hit {
+            Some((span, offset)) => self.resolve_span_and_offset(span, Some(offset)).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Serialize the diagnostics of the latest compilation into a JSON array
+    /// of [`SerializableDiagnostic`], see [`Self::diagnostics`].
+    pub fn diagnostics_json(&mut self) -> ZResult<String> {
+        let diags = self.diagnostics()?;
+        serde_json::to_string(&diags).map_err(map_string_err("failed to serialize diagnostics"))
+    }
+
+    /// Get the links of the latest compiled document with their sources, see
+    /// [`document_links`].
+    pub fn links(&mut self) -> ZResult<Vec<LinkInfo>> {
+        let encoding = self.position_encoding;
+        self.steal(move |this| {
+            let doc = this.document();
+            let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+
+            let world = this.compiler.world();
+            let mut links = document_links(&doc);
+            for link in &mut links {
+                let span = link.span_id.and_then(NonZeroU64::new).map(Span::from_raw);
+                link.source = span.and_then(|span| {
+                    let src_id = span.id()?;
+                    let source = world.source(src_id).ok()?;
+                    let range = source.find(span)?.range();
+                    Some(LinkSource {
+                        filepath: world.path_mapper().display_path(src_id).ok()?,
+                        range: to_lsp_range(&source, range, encoding),
+                    })
+                });
+            }
+            Ok(links)
+        })?
+    }
+
+    /// Lint the sources of the latest compiled document, see
+    /// [`super::lint`].
+    ///
+    /// The positions are in the unit of the
+    /// [`CompileClient::position_encoding`].
+    pub fn lint(&mut self) -> ZResult<Vec<LintFinding>> {
+        let encoding = self.position_encoding;
+        self.steal(move |this| {
+            let doc = this.document();
+            let doc = doc.ok_or_else(|| error_once!("no document compiled"))?;
+
+            let world = this.compiler.world();
+            // The untitled buffers and the files under the extra roots are
+            // linted along with the workspace, unlike the packages.
+            let sources: Vec<_> = (world.source_ids().into_iter())
+                .filter(|id| id.package().is_none_or(is_pseudo_package))
+                .collect();
+            let mut findings = lint_document(world, &sources, &doc);
+            for finding in &mut findings {
+                finding.location = DiagnosticLocation::from_span(world, finding.span, encoding);
+            }
+            Ok(findings)
+        })?
+    }
+
     pub async fn resolve_span(&mut self, span: Span) -> ZResult<Option<DocToSrcJumpInfo>> {
         self.resolve_span_and_offset(span, None).await
     }
@@ -561,11 +3859,12 @@ where
         span: Span,
         offset: Option<usize>,
     ) -> ZResult<Option<DocToSrcJumpInfo>> {
-        let resolve_off =
-            |src: &Source, off: usize| src.byte_to_line(off).zip(src.byte_to_column(off));
-
+        let encoding = self.position_encoding;
         self.steal_async(move |this, _| {
             let world = this.compiler.world();
+            if !world.vfs.preserves_offsets() {
+                return None;
+            }
             let src_id = span.id()?;
             let source = world.source(src_id).ok()?;
             let mut range = source.find(span)?.range();
@@ -574,25 +3873,271 @@ where
                     range.start += off;
                 }
             }
-            let filepath = world.path_for_id(src_id).ok()?;
+            let filepath = world.path_mapper().display_path(src_id).ok()?;
+            let range = to_lsp_range(&source, range, encoding);
             Some(DocToSrcJumpInfo {
-                filepath: filepath.to_string_lossy().to_string(),
-                start: resolve_off(&source, range.start),
-                end: resolve_off(&source, range.end),
+                filepath,
+                start: Some((range.start.line, range.start.column)),
+                end: Some((range.end.line, range.end.column)),
             })
         })
         .await
     }
 }
 
+/// A client of a compiler thread whose methods never block, for the hosts
+/// running it on a tokio runtime, e.g. a GUI application or a web server.
+///
+/// It wraps a [`CompileClient`] without exposing [`CompileClient::steal`], so
+/// that an async host cannot call the blocking methods by mistake. The methods
+/// without an async counterpart are run off the runtime by
+/// [`Self::run_blocking`]. The client is cheap to clone, e.g. once per
+/// request.
+///
+/// ```ignore
+/// use axum::{extract::State, Json};
+/// use typst_ts_compiler::service::{AsyncCompileClient, CompileActor, CompileDriver};
+///
+/// type Client = AsyncCompileClient<CompileActor<CompileDriver>>;
+///
+/// async fn jump(
+///     State(mut client): State<Client>,
+///     Json((path, line, character)): Json<(PathBuf, usize, usize)>,
+/// ) -> Json<Option<(usize, f64, f64)>> {
+///     let pos = client.resolve_src_to_doc_jump(path, line, character).await;
+///     let pos = pos.ok().flatten();
+///     Json(pos.map(|pos| (pos.page.get(), pos.point.x.to_pt(), pos.point.y.to_pt())))
+/// }
+/// ```
+pub struct AsyncCompileClient<Ctx>(CompileClient<Ctx>);
+
+impl<Ctx> Clone for AsyncCompileClient<Ctx> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<Ctx> fmt::Debug for AsyncCompileClient<Ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AsyncCompileClient").field(&self.0).finish()
+    }
+}
+
+impl<Ctx> From<CompileClient<Ctx>> for AsyncCompileClient<Ctx> {
+    fn from(client: CompileClient<Ctx>) -> Self {
+        client.into_async()
+    }
+}
+
+impl<Ctx> AsyncCompileClient<Ctx> {
+    /// See [`CompileClient::with_position_encoding`].
+    pub fn with_position_encoding(self, encoding: PositionEncoding) -> Self {
+        Self(self.0.with_position_encoding(encoding))
+    }
+
+    pub fn position_encoding(&self) -> PositionEncoding {
+        self.0.position_encoding()
+    }
+
+    /// See [`CompileClient::with_task_tag`].
+    pub fn with_task_tag(self, tag: Option<TaskTag>) -> Self {
+        Self(self.0.with_task_tag(tag))
+    }
+
+    /// See [`CompileClient::progress`].
+    pub fn progress(&self) -> watch::Receiver<Option<CompileProgress>> {
+        self.0.progress()
+    }
+
+    /// Steal the compiler thread and run the given function.
+    pub async fn steal_async<Ret: Send + 'static>(
+        &mut self,
+        f: impl FnOnce(&mut Ctx, tokio::runtime::Handle) -> Ret + Send + 'static,
+    ) -> ZResult<Ret> {
+        self.0.steal_async(f).await
+    }
+
+    /// Run the blocking methods of a clone of the wrapped [`CompileClient`] on
+    /// a thread of [`tokio::task::spawn_blocking`].
+    ///
+    /// ```ignore
+    /// let pages = client.run_blocking(|client| client.page_metadata()).await??;
+    /// ```
+    pub async fn run_blocking<Ret: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut CompileClient<Ctx>) -> Ret + Send + 'static,
+    ) -> ZResult<Ret>
+    where
+        Ctx: Send + 'static,
+    {
+        let mut client = self.0.clone();
+        let task = tokio::task::spawn_blocking(move || f(&mut client));
+        Ok(task.await.map_err(|_| CompileServiceError::TaskDropped)?)
+    }
+
+    /// See [`CompileClient::request`].
+    pub fn request<Ret: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Ctx, &CancellationToken) -> ZResult<Ret> + Send + 'static,
+    ) -> RequestHandle<Ret>
+    where
+        Ctx: 'static,
+    {
+        self.0.request(f)
+    }
+
+    /// See [`CompileClient::watch_file`].
+    pub fn watch_file(&self, path: PathBuf) -> broadcast::Receiver<FileChange> {
+        self.0.watch_file(path)
+    }
+
+    /// See [`CompileClient::add_memory_changes`], which doesn't wait for the
+    /// actor.
+    pub fn add_memory_changes(&self, event: MemoryEvent) -> ZResult<()> {
+        self.0.add_memory_changes(event)
+    }
+
+    /// See [`CompileClient::add_memory_changes_batch`].
+    pub fn add_memory_changes_batch(&self, events: Vec<MemoryEvent>) -> ZResult<()> {
+        self.0.add_memory_changes_batch(events)
+    }
+
+    /// See [`CompileClient::apply_edit`].
+    pub fn apply_edit(
+        &self,
+        path: ImmutPath,
+        range: Range<usize>,
+        new_text: String,
+    ) -> ZResult<()> {
+        self.0.apply_edit(path, range, new_text)
+    }
+}
+
+impl<C: Compiler> AsyncCompileClient<CompileActor<C>> {
+    /// See [`CompileClient::health`].
+    pub async fn health(&mut self) -> ZResult<ActorHealth> {
+        let health = self.0.steal_async(|this, _| this.health());
+        match tokio::time::timeout(HEALTH_TIMEOUT, health).await {
+            Ok(Ok(health)) => Ok(health),
+            Ok(Err(..)) | Err(..) => Ok(ActorHealth::default()),
+        }
+    }
+
+    /// See [`CompileClient::compile_async`].
+    pub async fn compile(&self) -> ZResult<Option<Arc<TypstDocument>>> {
+        self.0.compile_async().await
+    }
+
+    /// See [`CompileClient::diagnostics`].
+    pub async fn diagnostics(&mut self) -> ZResult<Vec<SerializableDiagnostic>> {
+        let encoding = self.0.position_encoding;
+        self.0
+            .steal_async(move |this, _| this.diagnostics(encoding))
+            .await
+    }
+
+    /// See [`CompileClient::request_query`].
+    pub fn query(&self, selector: String) -> RequestHandle<Vec<Content>>
+    where
+        C: 'static,
+    {
+        self.0.request_query(selector)
+    }
+
+    /// See [`CompileClient::request_coverage`].
+    pub fn coverage(&self) -> RequestHandle<CoverageReport>
+    where
+        C: 'static,
+    {
+        self.0.request_coverage()
+    }
+
+    /// See [`CompileClient::request_thumbnails`].
+    #[cfg(feature = "render")]
+    pub fn render_thumbnails(
+        &self,
+        options: super::render::ThumbnailOptions,
+    ) -> RequestHandle<Vec<super::render::Thumbnail>>
+    where
+        C: 'static,
+    {
+        self.0.request_thumbnails(options)
+    }
+}
+
+impl<F: CompilerFeat, Ctx: Compiler<World = CompilerWorld<F>>> AsyncCompileClient<CompileActor<Ctx>>
+where
+    Ctx::World: EntryManager,
+{
+    /// See [`CompileClient::resolve_src_to_doc_jump`].
+    pub async fn resolve_src_to_doc_jump(
+        &mut self,
+        filepath: PathBuf,
+        line: usize,
+        character: usize,
+    ) -> ZResult<Option<Position>> {
+        (self.0)
+            .resolve_src_to_doc_jump(filepath, line, character)
+            .await
+    }
+
+    /// See [`CompileClient::all_positions_for_cursor`].
+    pub async fn all_positions_for_cursor(
+        &mut self,
+        filepath: PathBuf,
+        line: usize,
+        character: usize,
+    ) -> ZResult<Vec<Position>> {
+        (self.0)
+            .all_positions_for_cursor(filepath, line, character)
+            .await
+    }
+
+    /// See [`CompileClient::resolve_src_location`].
+    pub async fn resolve_src_location(
+        &mut self,
+        loc: SourceLocation,
+    ) -> ZResult<Option<SourceSpanOffset>> {
+        self.0.resolve_src_location(loc).await
+    }
+
+    /// See [`CompileClient::source_location_at`].
+    pub async fn source_location_at(
+        &mut self,
+        page: usize,
+        point: Point,
+    ) -> ZResult<Option<DocToSrcJumpInfo>> {
+        self.0.source_location_at(page, point).await
+    }
+
+    /// See [`CompileClient::resolve_span`].
+    pub async fn resolve_span(&mut self, span: Span) -> ZResult<Option<DocToSrcJumpInfo>> {
+        self.0.resolve_span(span).await
+    }
+
+    /// See [`CompileClient::resolve_span_and_offset`].
+    pub async fn resolve_span_and_offset(
+        &mut self,
+        span: Span,
+        offset: Option<usize>,
+    ) -> ZResult<Option<DocToSrcJumpInfo>> {
+        self.0.resolve_span_and_offset(span, offset).await
+    }
+}
+
 /// Spawn a thread and run the given future on it.
 ///
 /// Note: the future is run on a single-threaded tokio runtime.
 fn ensure_single_thread<F: std::future::Future<Output = ()> + Send + 'static>(
     name: &str,
+    stack_size: Option<usize>,
     f: F,
 ) -> std::io::Result<std::thread::JoinHandle<()>> {
-    std::thread::Builder::new().name(name.to_owned()).spawn(|| {
+    let mut builder = std::thread::Builder::new().name(name.to_owned());
+    if let Some(stack_size) = stack_size {
+        builder = builder.stack_size(stack_size);
+    }
+    builder.spawn(|| {
         tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()
@@ -601,77 +4146,99 @@ fn ensure_single_thread<F: std::future::Future<Output = ()> + Send + 'static>(
     })
 }
 
-/// Find the output location in the document for a cursor position.
-pub fn jump_from_cursor(
-    document: &TypstDocument,
-    source: &Source,
-    cursor: usize,
-) -> Option<Position> {
-    let node = LinkedNode::new(source.root()).leaf_at(cursor)?;
-    if node.kind() != SyntaxKind::Text {
-        return None;
-    }
-
-    let mut min_dis = u64::MAX;
-    let mut p = Point::default();
-    let mut ppage = 0usize;
-
-    let span = node.span();
-    for (i, page) in document.pages.iter().enumerate() {
-        let t_dis = min_dis;
-        if let Some(pos) = find_in_frame(&page.frame, span, &mut min_dis, &mut p) {
-            return Some(Position {
-                page: NonZeroUsize::new(i + 1)?,
-                point: pos,
-            });
-        }
-        if t_dis != min_dis {
-            ppage = i;
-        }
-    }
-
-    if min_dis == u64::MAX {
-        return None;
+/// Resolve the sources of the metadata elements of a document compiled by the
+/// world, see [`MetadataAnchor::source`].
+fn resolve_metadata_sources<F: CompilerFeat>(
+    world: &CompilerWorld<F>,
+    anchors: &mut [MetadataAnchor],
+    encoding: PositionEncoding,
+) {
+    for anchor in anchors {
+        let span = anchor.span_id.and_then(NonZeroU64::new).map(Span::from_raw);
+        anchor.source = span.and_then(|span| {
+            let src_id = span.id()?;
+            let source = world.source(src_id).ok()?;
+            let range = source.find(span)?.range();
+            Some(MetadataSource {
+                filepath: world.path_mapper().display_path(src_id).ok()?,
+                range: to_lsp_range(&source, range, encoding),
+            })
+        });
     }
+}
 
-    Some(Position {
-        page: NonZeroUsize::new(ppage + 1)?,
-        point: p,
-    })
+#[inline]
+/// Replace the latest progress, which never blocks and succeeds even if
+/// nobody subscribes to it.
+fn report_progress(
+    progress: &watch::Sender<Option<CompileProgress>>,
+    revision: u64,
+    stage: CompileStage,
+) {
+    progress.send_replace(Some(CompileProgress {
+        revision,
+        stage,
+        timestamp: crate::time::now(),
+    }));
 }
 
-/// Find the position of a span in a frame.
-fn find_in_frame(frame: &Frame, span: Span, min_dis: &mut u64, p: &mut Point) -> Option<Point> {
-    for (mut pos, item) in frame.items() {
-        if let FrameItem::Group(group) = item {
-            // TODO: Handle transformation.
-            if let Some(point) = find_in_frame(&group.frame, span, min_dis, p) {
-                return Some(point + pos);
-            }
-        }
+/// Build an interrupt from a recorded one, see [`CompileActor::replay`].
+fn replayed_interrupt<Ctx>(
+    root: &Path,
+    kind: InterruptKind,
+    inserts: &[String],
+    removes: &[String],
+) -> CompilerInterrupt<Ctx> {
+    let resolve = |path: &String| -> ImmutPath { root.join(path).into() };
+    let read = |path: ImmutPath| {
+        let content = std::fs::read(&path)
+            .map(|content| (crate::time::now(), Bytes::from(content)))
+            .map_err(|err| FileError::from_io(err, &path));
+        (path, FileSnapshot::from(content))
+    };
+    let changeset = FileChangeSet {
+        removes: removes.iter().map(resolve).collect(),
+        inserts: inserts.iter().map(resolve).map(read).collect(),
+    };
 
-        if let FrameItem::Text(text) = item {
-            for glyph in &text.glyphs {
-                if glyph.span.0 == span {
-                    return Some(pos);
-                }
-                if glyph.span.0.id() == span.id() {
-                    let dis = glyph.span.0.number().abs_diff(span.number());
-                    if dis < *min_dis {
-                        *min_dis = dis;
-                        *p = pos;
-                    }
-                }
-                pos.x += glyph.x_advance.at(text.size);
-            }
+    match kind {
+        InterruptKind::Task => CompilerInterrupt::Task(Box::new(|_| {})),
+        InterruptKind::Queued => CompilerInterrupt::Queued,
+        InterruptKind::MemorySync => CompilerInterrupt::Memory(vec![MemoryEvent::Sync(changeset)]),
+        // An edit is replayed as an update to the content of the workspace.
+        InterruptKind::MemoryUpdate | InterruptKind::MemoryEdit => {
+            CompilerInterrupt::Memory(vec![MemoryEvent::Update(changeset)])
         }
+        InterruptKind::Fs => CompilerInterrupt::Fs(Some(FilesystemEvent::Update(changeset))),
+        InterruptKind::FsUpstream => CompilerInterrupt::Fs(Some(FilesystemEvent::UpstreamUpdate {
+            changeset,
+            upstream_event: None,
+        })),
+        InterruptKind::ScanComplete => CompilerInterrupt::Fs(None),
+        InterruptKind::ProbeRoot => CompilerInterrupt::ProbeRoot,
     }
+}
 
-    None
+/// Spawn a file watcher forwarding the file system events to the compiler
+/// thread.
+fn spawn_watcher(
+    dep_rx: mpsc::UnboundedReceiver<NotifyMessage>,
+    fs_tx: mpsc::UnboundedSender<Option<FilesystemEvent>>,
+) {
+    let err = CompileServiceError::ActorGone;
+    tokio::spawn(super::watch_deps(dep_rx, move |event| {
+        log_send_error("fs_event", err, fs_tx.send(Some(event)));
+    }));
 }
 
-#[inline]
-fn log_send_error<T>(chan: &'static str, res: Result<(), mpsc::error::SendError<T>>) -> bool {
-    res.map_err(|err| log::warn!("CompileActor: send to {chan} error: {err}"))
+fn log_send_error<T>(
+    chan: &'static str,
+    err: CompileServiceError,
+    res: Result<(), mpsc::error::SendError<T>>,
+) -> bool {
+    res.map_err(|_| log::warn!("CompileActor: send to {chan} error: {err}"))
         .is_ok()
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,12 @@
+use super::*;
+
+/// The compiler thread, the clients and the requests.
+mod actor;
+/// The queries of the compiled documents.
+mod document;
+/// The jumps between the sources and the documents.
+mod jump;
+/// The options and the diagnostics of compilations.
+mod options;
+/// The file events, the dependencies and the roots in watch mode.
+mod watch;
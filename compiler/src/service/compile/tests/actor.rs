@@ -0,0 +1,802 @@
+use typst::foundations::Value;
+
+use super::*;
+
+#[test]
+fn test_compiler_thread_name() {
+    let names = ["typst-compiler-a", "typst-compiler-b"].map(|name| {
+        let (tx, rx) = std::sync::mpsc::channel();
+        ensure_single_thread(name, Some(4 * 1024 * 1024), async move {
+            let name = std::thread::current().name().map(str::to_owned);
+            tx.send(name).unwrap();
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+        rx.recv().unwrap()
+    });
+
+    assert_eq!(names[0].as_deref(), Some("typst-compiler-a"));
+    assert_eq!(names[1].as_deref(), Some("typst-compiler-b"));
+}
+
+#[test]
+fn test_skip_cancelled_steal() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let (steal_send, mut steal_recv) = mpsc::unbounded_channel();
+    let (memory_send, _memory_recv) = mpsc::unbounded_channel();
+    let (queue_send, _queue_recv) = mpsc::unbounded_channel();
+    let client = CompileClient::<AtomicUsize> {
+        steal_send,
+        task_queue: Arc::default(),
+        queue_send,
+        task_tag: None,
+        memory_send,
+        position_encoding: PositionEncoding::default(),
+        progress: Arc::new(tokio::sync::watch::channel(None).0),
+        file_watches: Arc::default(),
+        _ctx: std::marker::PhantomData,
+    };
+    let expensive_work = |this: &mut AtomicUsize| this.fetch_add(1, Ordering::SeqCst);
+
+    let mut counter = AtomicUsize::new(0);
+    let cancelled = client.steal_inner(expensive_work).unwrap();
+    drop(cancelled);
+    let kept = client.steal_inner(expensive_work).unwrap();
+    while let Ok(task) = steal_recv.try_recv() {
+        task(&mut counter);
+    }
+
+    assert_eq!(counter.into_inner(), 1);
+    assert_eq!(kept.blocking_recv().unwrap(), 0);
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_actor_health() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    ws.write("main.typ", "Hello");
+
+    let driver = ws.driver();
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    // The health check may arrive before the initial compilation.
+    let health = tokio::task::spawn_blocking(move || {
+        for _ in 0..500 {
+            let health = client.health().unwrap();
+            if !health.alive || health.last_compile_ms_ago.is_some() {
+                return health;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        client.health().unwrap()
+    })
+    .await
+    .unwrap();
+
+    assert!(health.alive);
+    assert!(health.last_compile_ms_ago.unwrap() < 1000);
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "current_thread")]
+async fn test_async_client() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let driver = ws.shadow_driver(b"Hello");
+    let (actor, client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    // The only thread of the runtime is never blocked while waiting.
+    let mut client = client.into_async();
+    let doc = client.compile().await.unwrap();
+    assert_eq!(doc.expect("compiled document").pages.len(), 1);
+    assert!(client.health().await.unwrap().alive);
+    assert!(client.diagnostics().await.unwrap().is_empty());
+    let pos = client.resolve_src_to_doc_jump(main, 0, 2).await.unwrap();
+    assert_eq!(pos.expect("jump from the main file").page.get(), 1);
+
+    // So does it for the blocking methods.
+    let pages = client.run_blocking(|client| client.page_metadata());
+    assert_eq!(pages.await.unwrap().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_cancel_queued_request() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let (steal_send, _steal_recv) = mpsc::unbounded_channel();
+    let (memory_send, _memory_recv) = mpsc::unbounded_channel();
+    let (queue_send, _queue_recv) = mpsc::unbounded_channel();
+    let task_queue = Arc::<Mutex<TaskQueue<QueuedTask<AtomicUsize>>>>::default();
+    let client = CompileClient::<AtomicUsize> {
+        steal_send,
+        task_queue: task_queue.clone(),
+        queue_send,
+        task_tag: Some(TaskTag::new(TaskCategory::Background)),
+        memory_send,
+        position_encoding: PositionEncoding::default(),
+        progress: Arc::new(tokio::sync::watch::channel(None).0),
+        file_watches: Arc::default(),
+        _ctx: std::marker::PhantomData,
+    };
+    let work = |this: &mut AtomicUsize, token: &CancellationToken| -> ZResult<usize> {
+        token.check()?;
+        Ok(this.fetch_add(1, Ordering::SeqCst))
+    };
+
+    let cancelled = client.request(work);
+    let kept = client.request(work);
+    assert_eq!(task_queue.lock().len(), 2);
+    cancelled.cancel();
+    assert_eq!(task_queue.lock().len(), 1);
+
+    let mut counter = AtomicUsize::new(0);
+    loop {
+        let next = task_queue.lock().pop();
+        let Some((_, (task, _))) = next else {
+            break;
+        };
+        task(&mut counter);
+    }
+    assert_eq!(counter.into_inner(), 1);
+    assert!(!cancelled.is_started());
+    assert!(Cancelled::is(&cancelled.await.unwrap_err()));
+    assert_eq!(kept.await.unwrap(), 0);
+}
+
+#[cfg(all(feature = "system-compile", feature = "render"))]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cancel_running_request() {
+    use crate::{fixture::TestWorkspace, service::render::ThumbnailOptions};
+
+    let ws = TestWorkspace::new();
+    let content = b"#for _ in range(60) [#lorem(100) #pagebreak(weak: true)]";
+    let driver = ws.shadow_driver(content);
+    let (actor, client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+    let client = client.into_async();
+    let doc = client.compile().await.unwrap();
+    assert_eq!(doc.expect("compiled document").pages.len(), 60);
+
+    // The thumbnails are huge, so that rendering all of them takes far
+    // longer than a page.
+    let thumbnails = client.render_thumbnails(ThumbnailOptions {
+        max_dim: 2048,
+        parallel: false,
+        ..ThumbnailOptions::default()
+    });
+    while !thumbnails.is_started() {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    thumbnails.cancel();
+    let res = tokio::time::timeout(Duration::from_secs(10), thumbnails).await;
+    let err = res
+        .expect("the rendering stops once cancelled")
+        .unwrap_err();
+    assert!(Cancelled::is(&err), "{err}");
+
+    // The other requests still run.
+    let coverage = client.coverage().await.unwrap();
+    assert!(!coverage.files.is_empty());
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "current_thread")]
+#[should_panic(expected = "Cannot block the current thread")]
+async fn test_steal_within_runtime() {
+    use typst_ts_core::config::{compiler::EntryOpts, CompileOpts};
+
+    use crate::{fixture::TestWorkspace, service::CompileDriver, TypstSystemWorld};
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let world = TypstSystemWorld::new(CompileOpts {
+        entry: EntryOpts::new_rooted(root.clone(), None),
+        ..ws.opts()
+    })
+    .unwrap();
+    let (actor, mut client) = CompileActor::new(CompileDriver::new(world))
+        .with_watch(true)
+        .split();
+    actor.spawn().await.unwrap();
+
+    let _ = client.steal(|_| ());
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_compile_async() {
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, FileSnapshot},
+    };
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    ws.write("main.typ", "Hello");
+
+    let driver = ws.driver();
+    let (actor, client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    let doc = client.compile_async().await.unwrap();
+    assert_eq!(doc.unwrap().pages.len(), 1);
+
+    // The memory changes sent before are compiled.
+    let content = Bytes::from_static(b"Hello\n#pagebreak()\nWorld");
+    let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+    let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+    client
+        .add_memory_changes(MemoryEvent::Update(changes))
+        .unwrap();
+    let doc = client.compile_async().await.unwrap();
+    assert_eq!(doc.unwrap().pages.len(), 2);
+
+    let content = Bytes::from_static(b"#panic()");
+    let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+    let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+    client
+        .add_memory_changes(MemoryEvent::Update(changes))
+        .unwrap();
+    assert!(client.compile_async().await.unwrap().is_none());
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_compile_latest_request() {
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, FileSnapshot},
+    };
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    ws.write("main.typ", "Initial");
+
+    let driver = ws.driver();
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    let (generation, text, skipped) = tokio::task::spawn_blocking(move || {
+        let update = |content: String| {
+            let content = Bytes::from(content.into_bytes());
+            let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+            let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+            client
+                .add_memory_changes(MemoryEvent::Update(changes))
+                .unwrap();
+        };
+
+        // Flood the requests while the compiler thread is held, so that
+        // they are all pending when it is released.
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let held = client
+            .steal_inner(move |_| {
+                started_tx.send(()).unwrap();
+                let _ = release_rx.recv();
+            })
+            .unwrap();
+        started_rx.recv().unwrap();
+        for i in 0..10 {
+            update(format!("Revision {i}"));
+        }
+        release_tx.send(()).unwrap();
+        blocking_recv(held).unwrap();
+
+        for _ in 0..500 {
+            let (generation, text, skipped) = client
+                .steal(|this| {
+                    let text = this.document().map(|doc| plain_text(&doc, PAGE_DELIMITER));
+                    (this.generation, text, this.compiles_skipped)
+                })
+                .unwrap();
+            if text
+                .as_ref()
+                .is_some_and(|text| text.contains("Revision 9"))
+            {
+                return (generation, text, skipped);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the final state is not compiled");
+    })
+    .await
+    .unwrap();
+
+    // The final state is compiled, while the intermediate states are
+    // skipped.
+    let text = text.unwrap();
+    assert!(text.contains("Revision 9") && !text.contains("Revision 8"));
+    assert!(skipped >= 9, "{skipped}");
+    assert!(generation < 12, "{generation}");
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_eval_only() {
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, FileSnapshot, MemoryEvent},
+    };
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let content = "#let meta = (title: \"Report\", tags: (\"a\", \"b\"))
+#let body = [*Hello* World]
+#let double(x) = 2 * x
+= Heading";
+    let driver = ws.shadow_driver(content.as_bytes());
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let (actor, mut client) = CompileActor::new(driver)
+        .with_watch(true)
+        .with_eval_only(true)
+        .on_evaluated(move |module| {
+            let _ = tx.send(module.scope().iter().count());
+        })
+        .split();
+    actor.spawn().await.unwrap();
+    let bindings = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(bindings, 3);
+
+    // The blocking methods of the client must not run on the workers.
+    let err = tokio::task::spawn_blocking(move || {
+        let json = client.eval_entry().unwrap();
+        assert_eq!(json["meta"]["title"], "Report");
+        assert_eq!(json["meta"]["tags"], serde_json::json!(["a", "b"]));
+        assert_eq!(json["body"], "Hello World");
+        assert_eq!(json["double"]["$type"], "function");
+
+        // Nothing is laid out in the eval-only mode.
+        assert!(client.page_frame(0).is_err());
+
+        // The errors of evaluations are returned.
+        let content = Bytes::from_static(b"#let x = y");
+        let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+        let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+        client
+            .add_memory_changes(MemoryEvent::Update(changes))
+            .unwrap();
+        let mut err = None;
+        for _ in 0..500 {
+            err = client.eval_entry().err();
+            if err.is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        err
+    })
+    .await
+    .unwrap();
+    let err = err.expect("evaluation error").to_string();
+    assert!(err.contains("unknown variable"), "{err}");
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_meta() {
+    use std::sync::Mutex;
+
+    use typst::{diag::FileResult, foundations::Bytes, World};
+
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileExporter, DocumentWithMeta},
+        vfs::notify::{FileChangeSet, FileSnapshot, MemoryEvent},
+    };
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let driver = ws.shadow_driver(b"Hello");
+
+    // Record the build ids passed to the exporter.
+    let build_ids = Arc::new(Mutex::new(vec![]));
+    let recorded = build_ids.clone();
+    let exporter = CompileExporter::new(driver).with_meta_exporter(
+        move |_: &dyn World, output: Arc<DocumentWithMeta>| {
+            let build_id = output.meta.get("build-id").cloned();
+            recorded.lock().unwrap().push(build_id);
+            Ok(())
+        },
+    );
+    let (actor, client) = CompileActor::new(exporter).with_watch(true).split();
+    actor.spawn().await.unwrap();
+    // The blocking methods of the client must not run on the workers.
+    let mut blocking = client.clone();
+    tokio::task::spawn_blocking(move || blocking.set_meta("build-id", "42"))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let content = Bytes::from_static(b"Hello again");
+    let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+    let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+    client
+        .add_memory_changes(MemoryEvent::Update(changes))
+        .unwrap();
+    for _ in 0..500 {
+        if build_ids.lock().unwrap().contains(&Some("42".to_owned())) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // The metadata takes effect from the next compilation.
+    let build_ids = build_ids.lock().unwrap();
+    assert_eq!(build_ids.last(), Some(&Some("42".to_owned())));
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_shared_fonts() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use typst::{
+        foundations::Bytes,
+        text::{Font, FontBook, FontInfo},
+    };
+    use typst_ts_core::font::{BufferFontLoader, FontLoader, FontResolverImpl, FontSlot};
+
+    use crate::{fixture::TestWorkspace, font::system::LazyFontResolver};
+
+    struct CountingLoader(BufferFontLoader, Arc<AtomicUsize>);
+
+    impl FontLoader for CountingLoader {
+        fn load(&mut self) -> Option<Font> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0.load()
+        }
+    }
+
+    let loads = Arc::new(AtomicUsize::new(0));
+    let mut book = FontBook::new();
+    let mut slots = vec![];
+    for data in typst_assets::fonts() {
+        for (index, info) in FontInfo::iter(data).enumerate() {
+            book.push(info);
+            let loader = BufferFontLoader {
+                buffer: Some(Bytes::from_static(data)),
+                index: index as u32,
+            };
+            slots.push(FontSlot::new_boxed(CountingLoader(loader, loads.clone())));
+        }
+    }
+    let fonts = LazyFontResolver::from(FontResolverImpl::new(
+        book,
+        Arc::new(Mutex::new(Default::default())),
+        slots,
+        Default::default(),
+    ));
+
+    let compile = || {
+        let ws = TestWorkspace::new();
+        ws.write("main.typ", "Hello");
+
+        let driver = ws.driver();
+        let actor = CompileActor::new_with_shared_fonts(driver, ws.root().into(), fonts.clone());
+        assert!(actor.run());
+        loads.load(Ordering::SeqCst)
+    };
+
+    let first = compile();
+    assert!(first > 0);
+    assert_eq!(compile(), first);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_task_after_sync() {
+    use std::sync::Mutex;
+
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        service::CompileDriver,
+        vfs::notify::{FileChangeSet, FileSnapshot},
+    };
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let main = ws.path("main.typ");
+    let driver = ws.driver();
+    let mut actor = CompileActor::new(driver);
+
+    let chapter = root.join("chapter.typ");
+    actor
+        .compiler
+        .map_shadow(&chapter, Bytes::from_static(b"Chapter"))
+        .unwrap();
+    let content = Bytes::from_static(b"Before #include \"chapter.typ\"");
+    actor.compiler.map_shadow(&main, content).unwrap();
+    actor.compile(|_| {});
+
+    // The sync replaces all the shadows, and the task right after it reads
+    // the document of exactly the synced state.
+    let snapshot: FileSnapshot =
+        FileResult::Ok((crate::time::now(), Bytes::from_static(b"After"))).into();
+    let sync = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+    let observed = Arc::new(Mutex::new(None::<(Option<String>, usize)>));
+    let task: BorrowTask<CompileActor<CompileDriver>> = Box::new({
+        let observed = observed.clone();
+        move |this| {
+            let text = this.document().map(|doc| plain_text(&doc, PAGE_DELIMITER));
+            let shadows = this.compiler.world().vfs.shadow_usage().0;
+            *observed.lock().unwrap() = Some((text, shadows));
+        }
+    });
+    let mut pending = VecDeque::from([CompilerInterrupt::Task(task)]);
+    actor.handle(
+        CompilerInterrupt::Memory(vec![MemoryEvent::Sync(sync)]),
+        |_| pending.pop_front(),
+        |_| {},
+    );
+
+    let (text, shadows) = observed.lock().unwrap().take().unwrap();
+    let text = text.unwrap();
+    assert!(
+        text.contains("After") && !text.contains("Chapter"),
+        "{text:?}"
+    );
+    assert_eq!(shadows, 1);
+    // Nothing is left to compile after the task.
+    assert!(!actor.pending_compile);
+    assert_eq!(actor.generation, 2);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_task_queue() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, FileSnapshot},
+    };
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let driver = ws.shadow_driver(b"Before");
+    let mut actor = CompileActor::new(driver).with_task_queue_depth(2);
+    actor.compile(|_| {});
+    assert_eq!(actor.generation, 1);
+
+    let (actor, client) = actor.split();
+    let (actor, hover) = actor.split();
+    let (mut actor, tokens) = actor.split();
+    let hover = hover.with_task_tag(Some(
+        TaskTag::new(TaskCategory::Interactive).with_key("hover"),
+    ));
+    let tokens = tokens.with_task_tag(Some(TaskTag::new(TaskCategory::Background)));
+
+    // An edit is pending when the tasks are queued.
+    let snapshot: FileSnapshot =
+        FileResult::Ok((crate::time::now(), Bytes::from_static(b"After"))).into();
+    let update = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+    client
+        .add_memory_changes(MemoryEvent::Update(update))
+        .unwrap();
+    let background = tokens.steal_inner(|this| this.generation).unwrap();
+    let hovered = Arc::new(AtomicUsize::new(0));
+    let hovers: Vec<_> = (0..10)
+        .map(|_| {
+            let hovered = hovered.clone();
+            (hover.steal_inner(move |this| {
+                hovered.fetch_add(1, Ordering::SeqCst);
+                this.generation
+            }))
+            .unwrap()
+        })
+        .collect();
+    // The queue is full, while the hovers are coalesced into one slot.
+    let rejected = tokens.steal_inner(|this| this.generation);
+    assert!(rejected.is_err());
+
+    let first = CompilerInterrupt::Memory(actor.memory_recv.try_recv().unwrap());
+    actor.handle(
+        first,
+        |this| (this.queue_recv.try_recv().ok()).map(|()| CompilerInterrupt::Queued),
+        |_| {},
+    );
+
+    // Only the latest hover runs, before the pending compilation.
+    assert_eq!(hovered.load(Ordering::SeqCst), 1);
+    let mut hovers = hovers.into_iter().rev();
+    assert_eq!(hovers.next().unwrap().blocking_recv().unwrap(), 1);
+    assert!(hovers.all(|hover| hover.blocking_recv().is_err()));
+    // The background task reads the document of the edit.
+    assert_eq!(background.blocking_recv().unwrap(), 2);
+    assert_eq!(actor.generation, 2);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_client_of_dropped_actor() {
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, MemoryEvent},
+    };
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let main = ws.path("main.typ");
+    let driver = ws.driver();
+    let (actor, mut client) = CompileActor::new(driver).split();
+    drop(actor);
+
+    let gone = CompileServiceError::ActorGone.to_string();
+    let assert_gone = |res: ZResult<()>| {
+        let err = res.unwrap_err();
+        assert!(err.to_string().contains(&gone), "{err}");
+    };
+
+    assert!(!client.health().unwrap().alive);
+    assert_gone(client.diagnostics().map(|_| ()));
+    assert_gone(client.document_text().map(|_| ()));
+    assert_gone(client.export_artifact(&root.join("main.sir.in")));
+    assert_gone(client.clickable_regions(1).map(|_| ()));
+    assert_gone(client.word_regions(1).map(|_| ()));
+    assert_gone(client.generation().map(|_| ()));
+    assert_gone(client.changed_pages_since(0).map(|_| ()));
+    assert_gone(client.document_at(1).map(|_| ()));
+    assert_gone(client.document_history().map(|_| ()));
+    assert_gone(client.artifact_delta(1, 2).map(|_| ()));
+    assert_gone(client.memory_report().map(|_| ()));
+    assert_gone(client.trim().map(|_| ()));
+    assert_gone(client.dep_graph(DepGraphFormat::Json).map(|_| ()));
+    assert_gone(client.set_creation_timestamp(None));
+    assert_gone(client.diagnostics_json().map(|_| ()));
+    let changes = MemoryEvent::Update(FileChangeSet::new_inserts(vec![]));
+    assert_gone(client.add_memory_changes(MemoryEvent::Sync(FileChangeSet::default())));
+    assert_gone(client.add_memory_changes_batch(vec![changes]));
+    assert_gone(client.apply_edit(main.as_path().into(), 0..0, "Hello".to_owned()));
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let jump = client.resolve_src_to_doc_jump(main.clone(), 0, 0).await;
+        assert_gone(jump.map(|_| ()));
+        let loc = client.source_location_at(1, Point::zero()).await;
+        assert_gone(loc.map(|_| ()));
+        assert_gone(client.resolve_span(Span::detached()).await.map(|_| ()));
+    });
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_compiler_config() {
+    use typst::foundations::Str;
+
+    use crate::{fixture::TestWorkspace, service::CompileDriver};
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let main = ws.path("main.typ");
+    ws.write("main.typ", "Hello");
+
+    let mut world = ws.world();
+    let mut inputs = Dict::new();
+    inputs.insert(Str::from("draft"), Value::Str("true".into()));
+    world.set_inputs(Arc::new(Prehashed::new(inputs.clone())));
+    let driver = CompileDriver::new(world).with_entry_file(main);
+    let (actor, mut client) = CompileActor::new(driver)
+        .with_page_override(PageOverride::AutoHeight)
+        .with_position_encoding(PositionEncoding::Utf8)
+        .with_max_pages(3)
+        .with_warnings_as_errors(true)
+        .with_thread_name("typst-compiler-config")
+        .with_watch(true)
+        .split();
+    actor.spawn().await.unwrap();
+
+    let config = tokio::task::spawn_blocking(move || client.config().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(config.root.as_deref(), Some(root.as_path()));
+    assert_eq!(config.main.unwrap().vpath(), &VirtualPath::new("main.typ"));
+    assert_eq!(config.inputs, inputs);
+    assert!(config.enable_watch);
+    assert_eq!(config.page_override, Some(PageOverride::AutoHeight));
+    assert_eq!(config.position_encoding, PositionEncoding::Utf8);
+    assert_eq!(config.max_pages, Some(3));
+    assert!(config.warnings_as_errors);
+    assert!(!config.eval_only);
+    assert_eq!(config.layout_iteration_limit, None);
+    assert_eq!(config.thread_name, "typst-compiler-config");
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_actor_observer() {
+    use typst::diag::FileResult;
+
+    use crate::fixture::TestWorkspace;
+
+    #[derive(Default, Clone)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl ActorObserver for Recorder {
+        fn on_event_received(&self, kind: InterruptKind) {
+            self.0.lock().push(format!("event {kind:?}"));
+        }
+
+        fn on_compile_start(&self, request: u64) {
+            self.0.lock().push(format!("start {request}"));
+        }
+
+        fn on_compile_end(&self, end: &CompileEnd) {
+            self.0
+                .lock()
+                .push(format!("end {} {}", end.request, end.ok));
+        }
+    }
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let driver = ws.driver();
+    let recorder = Recorder::default();
+    let mut actor = CompileActor::new(driver).with_observer(recorder.clone());
+
+    let content = Bytes::from_static(b"Hello");
+    let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+    let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+    let first = CompilerInterrupt::Memory(vec![MemoryEvent::Update(changes)]);
+    actor.handle(first, |_| None, |_| {});
+
+    let calls = recorder.0.lock().clone();
+    assert_eq!(calls, ["event MemoryUpdate", "start 1", "end 1 true"]);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_safe_mode() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    ws.write("secret.txt", "secret");
+
+    let driver = ws.driver();
+    let mut actor = CompileActor::new(driver).with_safe_mode(true);
+    assert!(actor.config().safe_mode);
+
+    let mut compile = |content: &str| {
+        let content = Bytes::from(content.as_bytes());
+        actor.compiler.map_shadow(&main, content).unwrap();
+        actor.compile(|_| {});
+        assert!(actor.latest_compile.is_some_and(|(_, ok)| !ok));
+        let messages: Vec<_> = (actor.latest_diagnostics.iter())
+            .map(|diag| diag.message.clone())
+            .collect();
+        messages.join("\n")
+    };
+
+    // Even the files on disk are denied besides the shadow files.
+    for content in [
+        "#read(\"/etc/passwd\")",
+        "#read(\"secret.txt\")",
+        "#import \"@preview/example:0.1.0\": *",
+    ] {
+        let messages = compile(content);
+        assert!(messages.contains("safe mode"), "{content}: {messages}");
+    }
+}
@@ -0,0 +1,388 @@
+use typst::layout::Abs;
+
+use super::*;
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_document_text() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+
+    let content = b"First paragraph.\n\nSecond paragraph.";
+    let driver = ws.shadow_driver(content);
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    // Wait for the initial compilation.
+    let runs = tokio::task::spawn_blocking(move || {
+        for _ in 0..500 {
+            if let Ok(runs) = client.document_text() {
+                return runs;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("no document compiled");
+    })
+    .await
+    .unwrap();
+
+    let text: String = runs.iter().map(|run| run.text.as_str()).collect();
+    let first = text.find("First").unwrap();
+    let second = text.find("Second").unwrap();
+    assert!(first < second);
+    assert!(runs.iter().all(|run| run.page == 1));
+    assert!(runs
+        .iter()
+        .any(|run| !Span::from_raw(run.span_id.try_into().unwrap()).is_detached()));
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_page_frame() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let driver = ws.shadow_driver(b"#block[Hello frame]");
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    // The blocking methods of the client must not run on the workers.
+    let (frame, next) = tokio::task::spawn_blocking(move || {
+        let mut frame = None;
+        for _ in 0..500 {
+            if let Ok(page) = client.page_frame(0) {
+                frame = page;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        (frame, client.page_frame(1).unwrap())
+    })
+    .await
+    .unwrap();
+    let frame = frame.expect("frame of the first page");
+
+    fn has_text(frame: &Frame) -> bool {
+        frame.items().any(|(_, item)| match item {
+            FrameItem::Text(_) => true,
+            FrameItem::Group(group) => has_text(&group.frame),
+            _ => false,
+        })
+    }
+    assert!(has_text(&frame));
+    assert!(next.is_none());
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_doc_history() {
+    use typst::foundations::Bytes;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let driver = ws.driver();
+    let mut actor = CompileActor::new(driver).with_doc_history(2);
+
+    for content in ["First", "Second", "#panic()", "Third"] {
+        let content = Bytes::from_static(content.as_bytes());
+        actor.compiler.map_shadow(&main, content).unwrap();
+        actor.compile(|_| {});
+    }
+
+    // The failed compilation is not retained, and the oldest is evicted.
+    let revisions: Vec<_> = actor.doc_revisions.iter().map(|r| r.revision).collect();
+    assert_eq!(revisions, [2, 3]);
+    assert!(actor.document_at(1).is_none());
+    let text = |doc: Arc<TypstDocument>| -> String {
+        document_text(&doc)
+            .into_iter()
+            .map(|run| run.text)
+            .collect()
+    };
+    assert!(text(actor.document_at(2).unwrap()).contains("Second"));
+
+    let report = actor.memory_report();
+    assert_eq!(report.history_documents, 2);
+    assert_eq!(report.retained_documents, 2);
+
+    let delta = IncrDocServer::pack_delta_between(
+        actor.document_at(2).unwrap(),
+        actor.document_at(3).unwrap(),
+    );
+    assert!(delta.starts_with(b"diff-v1,"));
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_cache_efficiency() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    ws.write("main.typ", "#include \"chapter.typ\"");
+    ws.write("chapter.typ", "Chapter");
+    let driver = ws.driver();
+    let mut actor = CompileActor::new(driver);
+    assert_eq!(actor.latest_cache_efficiency, None);
+
+    actor.compile(|_| {});
+    let first = actor.latest_cache_efficiency.unwrap();
+    assert!(first.reads >= 2, "{first:?}");
+    assert!(first.ratio < 1., "{first:?}");
+
+    // Nothing is read again for an unchanged document.
+    actor.compile(|_| {});
+    let second = actor.latest_cache_efficiency.unwrap();
+    assert_eq!(second.reads, first.reads);
+    assert_eq!(second.cached_reads, second.reads);
+    assert!(second.ratio > first.ratio, "{first:?} {second:?}");
+    // Nothing is persisted by default.
+    assert_eq!(second.font_info, None);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_memory_report() {
+    use typst::foundations::Bytes;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    ws.write("chapter.typ", "Chapter");
+
+    let driver = ws.driver();
+    let mut actor = CompileActor::new(driver);
+    assert_eq!(actor.memory_report().document_bytes, 0);
+
+    let content = Bytes::from_static(b"Intro #include \"chapter.typ\"");
+    actor.compiler.map_shadow(&main, content).unwrap();
+    actor.compile(|_| {});
+    assert!(actor.document().is_some());
+
+    let report = actor.memory_report();
+    assert!(report.vfs_bytes > 0, "{report:?}");
+    assert!(report.cache_bytes > 0, "{report:?}");
+    assert!(report.cached_files > 0, "{report:?}");
+    assert_eq!(report.shadow_files, 1);
+    assert!(report.font_bytes > 0, "{report:?}");
+    assert!(report.document_bytes > 0, "{report:?}");
+    assert_eq!(report.retained_documents, 1);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_document_labels() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content =
+        "#set heading(numbering: \"1.\")\n= Intro <intro>\n#pagebreak()\n= Usage <usage>\nSee @intro.";
+    let mut driver = ws.shadow_driver(content.as_bytes());
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+    let labels = document_labels(&doc);
+    let label = |name: &str, page| LabelInfo {
+        name: name.to_owned(),
+        kind: "heading".to_owned(),
+        page,
+    };
+    assert_eq!(labels, [label("intro", 1), label("usage", 2)]);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_corrupt_font() {
+    use std::borrow::Cow;
+
+    use typst::foundations::Bytes;
+    use typst_ts_core::config::CompileOpts;
+
+    use crate::{fixture::TestWorkspace, service::CompileDriver, TypstSystemWorld};
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let fonts = [Cow::Borrowed(&b"not a font"[..])]
+        .into_iter()
+        .chain(typst_assets::fonts().map(Cow::Borrowed));
+    let world = TypstSystemWorld::new(CompileOpts {
+        with_embedded_fonts: fonts.collect(),
+        ..ws.opts()
+    })
+    .unwrap();
+
+    let errors = world.font_resolver.load_errors();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert_eq!(errors[0].source, "<memory>");
+
+    let mut driver = CompileDriver::new(world).with_entry_file(main.clone());
+    driver
+        .map_shadow(&main, Bytes::from_static(b"Hello"))
+        .unwrap();
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+    assert_eq!(doc.pages.len(), 1);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_document_info() {
+    use typst::foundations::Bytes;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let mut driver = ws.shadow_driver(b"Hello");
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+    assert_eq!(document_info(&doc), DocumentInfo::default());
+
+    let content = r#"#set document(title: "X", author: "Y", keywords: ("a", "b"))
+Hello"#;
+    driver
+        .map_shadow(&main, Bytes::from_static(content.as_bytes()))
+        .unwrap();
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+    let info = document_info(&doc);
+    assert_eq!(info.title.as_deref(), Some("X"));
+    assert_eq!(info.authors, ["Y"]);
+    assert_eq!(info.keywords, ["a", "b"]);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_document_hash() {
+    use typst::foundations::Bytes;
+    use typst_ts_core::content_hash::{content_hash, page_content_hashes, LENGTH_PRECISION};
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let driver = ws.driver();
+    let content = Bytes::from_static(b"= Title\nHello #text(red)[World]\n#pagebreak()\nSecond");
+    driver.map_shadow(&main, content.clone()).unwrap();
+
+    let mut actor = CompileActor::new(driver);
+    actor.compile(|_| {});
+    let doc = actor.document().unwrap();
+    let hash = DocumentHash::new(&doc);
+    assert_eq!(hash.hash, content_hash(&doc));
+    assert_eq!(hash.pages.len(), 2);
+
+    // A recompilation of the unchanged input gets the same hash.
+    actor.compiler.map_shadow(&main, content).unwrap();
+    actor.compile(|_| {});
+    let recompiled = actor.document().unwrap();
+    assert!(!Arc::ptr_eq(&doc, &recompiled));
+    assert_eq!(DocumentHash::new(&recompiled), hash);
+
+    // Moving the glyphs of the second page by more than the precision
+    // only changes the hash of the second page.
+    let mut moved = (*doc).clone();
+    let offset = Abs::pt(LENGTH_PRECISION * 2.);
+    moved.pages[1].frame.translate(Point::with_x(offset));
+    let moved_pages = page_content_hashes(&moved);
+    assert_eq!(moved_pages[0], hash.pages[0]);
+    assert_ne!(moved_pages[1], hash.pages[1]);
+    assert_ne!(content_hash(&moved), hash.hash);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_plain_text() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content = b"First paragraph.\n\nSecond paragraph.\n#pagebreak()\nThird paragraph.";
+    let mut driver = ws.shadow_driver(content);
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+    assert_eq!(doc.pages.len(), 2);
+
+    let text = plain_text(&doc, PAGE_DELIMITER);
+    assert_eq!(
+        text,
+        "First paragraph.\n\nSecond paragraph.\u{c}Third paragraph."
+    );
+    let text = plain_text(&doc, "\n---\n");
+    assert!(
+        text.ends_with("Second paragraph.\n---\nThird paragraph."),
+        "{text:?}"
+    );
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_artifact_flatten_text() {
+    use typst_ts_core::vector::{artifact::load_artifact, ir::VecItem};
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content = b"= Glyphs\n\nSome *text* and #text(stroke: 0.5pt)[strokes].";
+    let mut driver = ws.shadow_driver(content);
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+    let export = |options: ArtifactOptions| {
+        let artifact = vector_artifact_with(&doc, &options);
+        load_artifact(&artifact).unwrap().module
+    };
+    let has_text = |module: &typst_ts_core::vector::ir::Module| {
+        (module.items.values()).any(|item| matches!(item, VecItem::Text(..)))
+    };
+
+    let outline = export(ArtifactOptions::default());
+    assert!(!outline.fonts.is_empty());
+    assert!(outline.glyphs_all().next().is_some());
+    assert!(has_text(&outline));
+
+    let flattened = export(ArtifactOptions {
+        flatten_text: true,
+        ..ArtifactOptions::default()
+    });
+    assert!(flattened.fonts.is_empty());
+    assert!(!has_text(&flattened));
+    assert!((flattened.items.values()).any(|item| matches!(item, VecItem::Path(..))));
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_document_stats() {
+    use typst::{foundations::Bytes, text::Lang};
+    use typst_ts_core::{segment::segment_words, Transformer};
+    use typst_ts_text_exporter::TextExporter;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let mut driver = ws.driver();
+    // The narrow page hyphenates the long words.
+    let content = r#"#set page(width: 60pt, height: auto)
+#set text(lang: "en", hyphenate: true)
+The extraordinarily comprehensive documentation
+
+#text(lang: "zh")[排版引擎]
+
+Mixed 中文 text"#;
+    driver
+        .map_shadow(&main, Bytes::from(content.as_bytes()))
+        .unwrap();
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+    let stats = document_stats(&doc);
+    assert_eq!(stats.pages, 1);
+    assert_eq!(stats.words, 4 + 4 + 4);
+
+    let mut text = vec![];
+    (TextExporter::default())
+        .export(driver.world(), (doc.clone(), &mut text))
+        .unwrap();
+    let text = String::from_utf8(text).unwrap();
+    let words: usize = (text.lines())
+        .map(|line| segment_words(line, Lang::ENGLISH).len())
+        .sum();
+    assert_eq!(stats.words, words, "{text}");
+    assert!(text.contains("extraordinarily"), "{text}");
+}
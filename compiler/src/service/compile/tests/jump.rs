@@ -0,0 +1,459 @@
+use typst::layout::{Abs, Size};
+
+use super::*;
+use crate::service::{jump::jump_to_span, ContextScopeKind, JumpOptions};
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_jump_in_shadow_main() {
+    use crate::fixture::TestWorkspace;
+
+    // The main file only exists in memory.
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+
+    let driver = ws.shadow_driver(b"Hello shadow");
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    // Wait for the initial compilation.
+    let mut pos = None;
+    for _ in 0..500 {
+        pos = client
+            .resolve_src_to_doc_jump(main.clone(), 0, 2)
+            .await
+            .unwrap();
+        if pos.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let pos = pos.expect("jump from a shadow main file");
+    assert_eq!(pos.page.get(), 1);
+
+    // A path relative to the root refers to the same file.
+    let relative = client
+        .resolve_src_to_doc_jump("main.typ".into(), 0, 2)
+        .await
+        .unwrap();
+    assert_eq!(relative, Some(pos));
+}
+
+#[cfg(all(feature = "system-compile", unix))]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_jump_in_symlinked_workspace() {
+    use typst_ts_core::config::{compiler::EntryOpts, CompileOpts};
+
+    use crate::{fixture::TestWorkspace, service::CompileDriver, TypstSystemWorld};
+
+    // The workspace is opened by a symlink, while the editor reports the
+    // real path of the file.
+    let ws = TestWorkspace::new();
+    let (real, link) = (ws.path("real"), ws.path("link"));
+    ws.write("real/main.typ", "Hello symlink");
+    std::os::unix::fs::symlink(&real, &link).unwrap();
+
+    let world = TypstSystemWorld::new(CompileOpts {
+        entry: EntryOpts::new_rooted(link.clone(), Some("main.typ".into())),
+        ..ws.opts()
+    })
+    .unwrap();
+    let driver = CompileDriver::new(world).with_entry_file(link.join("main.typ"));
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    let real_main = std::fs::canonicalize(real.join("main.typ")).unwrap();
+    let mut pos = None;
+    for _ in 0..500 {
+        pos = client
+            .resolve_src_to_doc_jump(real_main.clone(), 0, 2)
+            .await
+            .unwrap();
+        if pos.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(pos.expect("jump from the real path").page.get(), 1);
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_client_position_encoding() {
+    use typst_ts_core::debug_loc::CharPosition;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+
+    let driver = ws.shadow_driver("éabc".as_bytes());
+    let (actor, client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+    assert_eq!(client.position_encoding(), PositionEncoding::Utf16);
+    let mut utf16 = client.clone();
+    let mut utf8 = client.with_position_encoding(PositionEncoding::Utf8);
+
+    // The cursor after `a`, which is at the 3rd byte or the 2nd UTF-16
+    // code unit. Wait for the initial compilation.
+    let mut pos = None;
+    for _ in 0..500 {
+        pos = utf8
+            .resolve_src_to_doc_jump(main.clone(), 0, 3)
+            .await
+            .unwrap();
+        if pos.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let pos = pos.expect("jump from source to document");
+    let utf16_pos = utf16.resolve_src_to_doc_jump(main.clone(), 0, 2).await;
+    assert_eq!(utf16_pos.unwrap(), Some(pos));
+
+    let loc = SourceLocation {
+        filepath: main.to_string_lossy().to_string(),
+        pos: CharPosition { line: 0, column: 3 },
+    };
+    let span = utf8.resolve_src_location(loc).await.unwrap().unwrap();
+    assert_eq!(span.offset, 3);
+
+    let jump = utf8.resolve_span_and_offset(span.span, Some(span.offset));
+    assert_eq!(jump.await.unwrap().unwrap().start, Some((0, 3)));
+    let jump = utf16.resolve_span_and_offset(span.span, Some(span.offset));
+    assert_eq!(jump.await.unwrap().unwrap().start, Some((0, 2)));
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_jump_all_from_cursor() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content = "#let greet() = [Hello]\n#greet()\n\n#greet()\n\nBye";
+    let mut driver = ws.shadow_driver(content.as_bytes());
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+    let source = driver.world().source(driver.main_id()).unwrap();
+
+    // Each call of the function renders the text once.
+    let hello = content.find("Hello").unwrap() + 2;
+    let positions = jump_all_from_cursor(&doc, &source, hello);
+    assert_eq!(positions.len(), 2, "{positions:?}");
+    assert!(positions.iter().all(|pos| pos.page.get() == 1));
+    assert!(positions[0].point.y < positions[1].point.y);
+    assert_eq!(jump_from_cursor(&doc, &source, hello), Some(positions[0]));
+
+    let bye = content.find("Bye").unwrap() + 1;
+    assert_eq!(jump_all_from_cursor(&doc, &source, bye).len(), 1);
+    assert!(jump_all_from_cursor(&doc, &source, 0).is_empty());
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_clickable_regions() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content = "Hello #box(rotate(90deg)[World])";
+    let mut driver = ws.shadow_driver(content.as_bytes());
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+    let regions = clickable_regions(&doc.pages[0].frame);
+    let source = driver.world().source(driver.main_id()).unwrap();
+    let region_of = |text: &str| {
+        let region = regions.iter().find(|region| {
+            let span = Span::from_raw(region.span_id.try_into().unwrap());
+            source.range(span).map(|range| &content[range]) == Some(text)
+        });
+        *region.unwrap_or_else(|| panic!("no region of {text:?} in {regions:?}"))
+    };
+
+    // Each region covers the text of its span.
+    let (hello, world) = (region_of("Hello"), region_of("World"));
+    assert!(hello.rect.2 > 0.0 && hello.rect.3 > 0.0);
+    assert!(hello.rect.0 + hello.rect.2 <= world.rect.0);
+    // The rotated text is taller than wide.
+    assert!(world.rect.3 > world.rect.2);
+
+    let runs = document_text(&doc);
+    for run in runs.iter().filter(|run| run.text.contains("Hello")) {
+        let (x, y, w, h) = hello.rect;
+        let (px, py) = (run.point.x.to_pt(), run.point.y.to_pt());
+        assert!(x <= px && px <= x + w && y <= py && py <= y + h, "{run:?}");
+    }
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_word_regions() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let mut driver = ws.shadow_driver(b"Hello office");
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+    let words = word_regions(&doc.pages[0].frame);
+    let texts: Vec<_> = words.iter().map(|word| word.text.as_str()).collect();
+    // The ligature of "ffi" stays in the word.
+    assert_eq!(texts, ["Hello", "office"]);
+
+    let (hello, office) = (words[0].rect, words[1].rect);
+    assert!(hello.2 > 0.0 && hello.3 > 0.0);
+    assert!(hello.0 + hello.2 < office.0);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_counter_at() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content = "#figure(rect(), caption: [A]) <a>\n#figure(rect(), caption: [B]) <b>";
+    let mut driver = ws.shadow_driver(content.as_bytes());
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+    let at = |counter, label| counter_at(driver.world(), &doc, counter, label).unwrap();
+    assert_eq!(at("figure", "a"), Some(vec![1]));
+    assert_eq!(at("figure", "b"), Some(vec![2]));
+    assert_eq!(at("page", "b"), Some(vec![1]));
+    // Unknown labels and counters.
+    assert_eq!(at("figure", "missing"), None);
+    assert_eq!(at("calc", "a"), None);
+}
+
+#[test]
+fn test_span_at_offset() {
+    let source = Source::detached("= A heading\nSome *strong* text");
+
+    let at = |offset| span_at_offset(&source, offset);
+    // A space between words is a part of the text.
+    assert_eq!(at(5), Some((SyntaxKind::Text, 2..11)));
+    assert_eq!(at(1), Some((SyntaxKind::HeadingMarker, 0..1)));
+    assert_eq!(at(18), Some((SyntaxKind::Star, 17..18)));
+    assert_eq!(at(20), Some((SyntaxKind::Text, 18..24)));
+    assert_eq!(at(100), None);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_context_at() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content = "#let note(body) = emph(body)\n= Intro\nHello\n= Details\n#note[Body text]";
+    let driver = ws.shadow_driver(content.as_bytes());
+
+    let mut actor = CompileActor::new(driver);
+    actor.compile(|_| {});
+    let doc = actor.document().unwrap();
+    let world = actor.compiler.world();
+    let runs = document_text(&doc);
+    let context_of = |needle: &str| {
+        let run = runs
+            .iter()
+            .find(|run| run.text.starts_with(needle))
+            .unwrap();
+        let point = Point::new(run.point.x + Abs::pt(1.), run.point.y - run.size / 2.);
+        context_at(world, &doc, run.page, point).unwrap()
+    };
+
+    let context = context_of("Hello");
+    assert_eq!(context.heading.as_deref(), Some("Intro"));
+    assert!(context.scopes.is_empty(), "{context:?}");
+
+    let context = context_of("Body");
+    assert_eq!(context.heading.as_deref(), Some("Details"));
+    let call = &context.scopes[0];
+    assert_eq!(call.kind, ContextScopeKind::Call);
+    assert_eq!(call.name.as_deref(), Some("note"));
+    assert_eq!(&content[call.range.clone()], "note[Body text]");
+
+    assert_eq!(context_at(world, &doc, 1, Point::zero()), None);
+    assert_eq!(context_at(world, &doc, 2, Point::zero()), None);
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_source_location_at() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let driver = ws.shadow_driver(b"= Intro\nHello world");
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    // Wait for the initial compilation.
+    let mut runs = None;
+    for _ in 0..500 {
+        runs = client
+            .steal_async(|this, _| this.document().map(|doc| document_text(&doc)))
+            .await
+            .unwrap();
+        if runs.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let runs = runs.expect("compile the document");
+    let run = runs.iter().find(|run| run.text.starts_with("Hello"));
+    let run = run.unwrap();
+
+    let point = Point::new(run.point.x + Abs::pt(1.), run.point.y - run.size / 2.);
+    let loc = client.source_location_at(run.page, point).await.unwrap();
+    let loc = loc.expect("a glyph under the point");
+    assert!(loc.filepath.ends_with("main.typ"), "{loc:?}");
+    assert_eq!(loc.start, Some((1, 0)));
+
+    // There is no glyph at the corner of the page, nor a second page.
+    let corner = client.source_location_at(1, Point::zero()).await.unwrap();
+    assert!(corner.is_none(), "{corner:?}");
+    let missing = client.source_location_at(2, point).await.unwrap();
+    assert!(missing.is_none(), "{missing:?}");
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_span_from_point_skips_detached_glyphs() {
+    use typst::text::TextItem;
+
+    use crate::fixture::TestWorkspace;
+
+    fn first_text(frame: &Frame) -> Option<TextItem> {
+        frame.items().find_map(|(_, item)| match item {
+            FrameItem::Group(group) => first_text(&group.frame),
+            FrameItem::Text(text) => Some(text.clone()),
+            _ => None,
+        })
+    }
+
+    let ws = TestWorkspace::new();
+    let driver = ws.shadow_driver(b"Hello");
+    let mut actor = CompileActor::new(driver);
+    actor.compile(|_| {});
+    let doc = actor.document().unwrap();
+    let text = first_text(&doc.pages[0].frame).unwrap();
+
+    // Paint a generated copy of the text over it.
+    let mut generated = text.clone();
+    for glyph in &mut generated.glyphs {
+        glyph.span = (Span::detached(), 0);
+    }
+    let baseline = Point::with_y(Abs::pt(50.));
+    let mut frame = Frame::soft(Size::splat(Abs::pt(100.)));
+    frame.push(baseline, FrameItem::Text(text.clone()));
+    frame.push(baseline, FrameItem::Text(generated));
+
+    let click = Point::new(Abs::pt(1.), baseline.y - text.size / 2.);
+    let (span, offset) = text.glyphs[0].span;
+    assert_eq!(
+        span_from_point(&frame, click),
+        Some((span, offset as usize))
+    );
+    assert!(!span.is_detached());
+}
+
+#[cfg(all(feature = "system-compile", debug_assertions))]
+#[test]
+fn test_dump_spans() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let mut driver = ws.shadow_driver(b"Hello *World*");
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+    let dumps = dump_spans(driver.world(), &doc, 1).unwrap();
+    let world_glyphs: Vec<_> = (dumps.iter())
+        .filter(|dump| dump.text.as_deref() == Some("World"))
+        .collect();
+    assert_eq!(world_glyphs.len(), 5, "{dumps:?}");
+    let main_id = driver.world().main_id();
+    assert!(world_glyphs.iter().all(|dump| dump.file_id == main_id));
+    assert!(world_glyphs[0].point.x > dumps[0].point.x);
+
+    assert!(dump_spans(driver.world(), &doc, 2).is_err());
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_pages_for_file() {
+    use typst::foundations::Bytes;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let content = b"Intro\n#pagebreak()\n#include \"chapter.typ\"\n#pagebreak()\nOutro";
+    let mut driver = ws.shadow_driver(content);
+    let chapter = Bytes::from_static(b"One\n#pagebreak()\nTwo");
+    driver
+        .map_shadow(&root.join("chapter.typ"), chapter)
+        .unwrap();
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+    assert_eq!(doc.pages.len(), 4);
+
+    let id = |path: &str| TypstFileId::new(None, VirtualPath::new(path));
+    assert_eq!(pages_for_file(&doc, id("chapter.typ")), [2, 3]);
+    assert_eq!(pages_for_file(&doc, id("main.typ")), [1, 4]);
+    assert!(pages_for_file(&doc, id("missing.typ")).is_empty());
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_jump_skips_other_files() {
+    use typst::foundations::Bytes;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let content = "#include \"chapters.typ\"\nTarget";
+    let mut driver = ws.shadow_driver(content.as_bytes());
+    let chapters = Bytes::from_static(b"#for i in range(99) [Chapter #pagebreak()]");
+    driver
+        .map_shadow(&root.join("chapters.typ"), chapters)
+        .unwrap();
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+    assert_eq!(doc.pages.len(), 100);
+    let source = driver.world().source(driver.main_id()).unwrap();
+
+    // Only the text of the main file is scanned when skipping, on the last
+    // page.
+    let target = content.find("Target").unwrap() + 1;
+    let span = LinkedNode::new(source.root())
+        .leaf_at(target)
+        .unwrap()
+        .span();
+    let options = JumpOptions {
+        skip_other_files: true,
+    };
+    let (skipped, scanned) = jump_to_span(&doc, span, options);
+    assert_eq!(skipped.unwrap().page.get(), 100);
+    assert_eq!(scanned, 1);
+
+    // All the texts are scanned by default.
+    let (position, scanned) = jump_to_span(&doc, span, JumpOptions::default());
+    assert_eq!(position, skipped);
+    assert!(scanned >= 100, "{scanned}");
+    assert_eq!(jump_from_cursor(&doc, &source, target), skipped);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_caret_from_cursor() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content = "Hello world";
+    let mut driver = ws.shadow_driver(content.as_bytes());
+    let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+    let source = driver.world().source(driver.main_id()).unwrap();
+
+    let start = jump_from_cursor(&doc, &source, 1).unwrap();
+    let middle = caret_from_cursor(&doc, &source, 2).unwrap();
+    let end = caret_from_cursor(&doc, &source, 5).unwrap();
+    assert_eq!(middle.position.page, start.page);
+    assert_eq!(middle.position.point.y, start.point.y);
+    assert!(start.point.x < middle.position.point.x);
+    assert!(middle.position.point.x < end.position.point.x);
+    assert!(middle.height > middle.ascent && middle.ascent > Abs::zero());
+}
@@ -0,0 +1,730 @@
+use typst::{foundations::Value, layout::Abs};
+
+use super::*;
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_compile_with_fixed_now() {
+    use chrono::TimeZone;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    ws.write("main.typ", "#metadata(datetime.today().display()) <today>");
+
+    let mut driver = ws.driver();
+
+    let now = Local.with_ymd_and_hms(2001, 2, 3, 4, 5, 6).unwrap();
+    let mut today = || {
+        let mut env = CompileEnv::default().with_now(Some(now));
+        let doc = driver.compile(&mut env).unwrap();
+        let res = driver.query("<today>".to_owned(), &doc).unwrap();
+        format!("{res:?}")
+    };
+
+    let first = today();
+    assert_eq!(first, today());
+    assert!(first.contains("2001-02-03"));
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_reject_unknown_features() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+
+    let actor = CompileActor::new(ws.shadow_driver(b"Hello"));
+    let err = actor.with_features(vec!["html".to_owned()]).err().unwrap();
+    assert!(err.to_string().contains("unknown typst feature"), "{err}");
+    assert!(err.to_string().contains("html"), "{err}");
+
+    let actor = CompileActor::new(ws.shadow_driver(b"Hello"));
+    let mut actor = actor.with_features(vec![]).unwrap();
+    actor.compile(|_| {});
+    assert!(actor.document().is_some());
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_page_override() {
+    use typst::foundations::Bytes;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let mut driver = ws.shadow_driver(b"#lorem(2000)");
+
+    let normal = driver.compile(&mut CompileEnv::default()).unwrap();
+    assert!(normal.pages.len() > 1);
+
+    let mut actor = CompileActor::new(driver).with_page_override(PageOverride::AutoHeight);
+    actor.compile(|_| {});
+    let doc = actor.document().unwrap();
+    assert_eq!(doc.pages.len(), 1);
+    assert!(doc.pages[0].frame.height() > normal.pages[0].frame.height());
+
+    // The override beats the page setup and the explicit pages of the
+    // document.
+    let driver = &mut actor.compiler;
+    let content = b"#set page(height: 5cm)\n#lorem(2000)\n#page(width: 3cm, height: 4cm)[Explicit]";
+    driver
+        .map_shadow(&main, Bytes::from_static(content))
+        .unwrap();
+    let mut env = CompileEnv::default().with_page_override(Some(PageOverride::AutoHeight));
+    let doc = driver.compile(&mut env).unwrap();
+    assert_eq!(doc.pages.len(), 2);
+    assert!(doc.pages[0].frame.height() > Abs::cm(5.0));
+    assert_ne!(doc.pages[1].frame.height(), Abs::cm(4.0));
+
+    let width = Abs::cm(8.0);
+    let mut env = CompileEnv::default().with_page_override(Some(PageOverride::Size {
+        width: Some(width),
+        height: None,
+    }));
+    let doc = driver.compile(&mut env).unwrap();
+    assert!(doc.pages.iter().all(|page| page.frame.width() == width));
+}
+
+#[cfg(all(feature = "system-compile", feature = "tracing"))]
+#[test]
+fn test_pipeline_spans() {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{span, Subscriber};
+    use tracing_subscriber::{
+        layer::{Context, SubscriberExt},
+        registry::LookupSpan,
+        Layer,
+    };
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        service::CompileExporter,
+        vfs::notify::{FileChangeSet, FileSnapshot},
+    };
+
+    /// Collects the names of the spans and their parents.
+    #[derive(Clone, Default)]
+    struct SpanTree(Arc<Mutex<Vec<(String, Option<String>)>>>);
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanTree {
+        fn on_new_span(&self, _: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+            let span = ctx.span(id).unwrap();
+            let parent = span.parent().map(|parent| parent.name().to_owned());
+            self.0
+                .lock()
+                .unwrap()
+                .push((span.name().to_owned(), parent));
+        }
+    }
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let driver = ws.driver();
+    let (mut actor, client) = CompileActor::new(CompileExporter::new(driver)).split();
+
+    // Simulate saving the file in the editor.
+    let content = Bytes::from_static(b"= Saved");
+    let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+    let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+    client
+        .add_memory_changes(MemoryEvent::Update(changes))
+        .unwrap();
+
+    let tree = SpanTree::default();
+    let subscriber = tracing_subscriber::registry().with(tree.clone());
+    tracing::subscriber::with_default(subscriber, || {
+        let first = actor.memory_recv.try_recv().unwrap();
+        actor.handle(CompilerInterrupt::Memory(first), |_| None, |_| {});
+    });
+    assert_eq!(actor.generation, 1);
+
+    let spans = tree.0.lock().unwrap().clone();
+    let parent = |name: &str| {
+        let span = spans.iter().find(|(span, _)| span == name);
+        span.unwrap_or_else(|| panic!("no span {name} in {spans:?}"))
+            .1
+            .clone()
+    };
+    assert_eq!(parent("request"), None);
+    assert_eq!(parent("memory").as_deref(), Some("request"));
+    assert_eq!(parent("compile").as_deref(), Some("request"));
+    assert_eq!(parent("export").as_deref(), Some("compile"));
+    assert_eq!(parent("sync_deps").as_deref(), Some("compile"));
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_recover_from_limit_violation() {
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        service::{limits::CompileLimits, CompileDriver},
+        vfs::notify::{FileChangeSet, FileSnapshot},
+    };
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let world = ws.world();
+    let limits = CompileLimits::default().with_max_elements(Some(10_000));
+    let driver = CompileDriver::new(world)
+        .with_entry_file(main.clone())
+        .with_limits(limits);
+    let (mut actor, client) = CompileActor::new(driver).split();
+
+    let mut edit = |content: &'static str| {
+        let content = Bytes::from_static(content.as_bytes());
+        let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+        let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+        client
+            .add_memory_changes(MemoryEvent::Update(changes))
+            .unwrap();
+        let event = actor.memory_recv.try_recv().unwrap();
+        actor.handle(CompilerInterrupt::Memory(event), |_| None, |_| {});
+        (actor.document(), actor.latest_diagnostics.clone())
+    };
+
+    // The recursion doubles the content at each level.
+    let (doc, diags) = edit("#let f(n) = if n == 0 [x] else [#f(n - 1) #f(n - 1)]\n#f(16)");
+    assert!(doc.is_none());
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert!(diags[0].message.contains("limit of 10000 elements"));
+
+    // The watch loop recovers on the next edit.
+    let (doc, diags) = edit("#let f(n) = if n == 0 [x] else [#f(n - 1) #f(n - 1)]\n#f(4)");
+    assert!(doc.is_some());
+    assert!(diags.is_empty(), "{diags:?}");
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_max_pages() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content = b"#for i in range(10) [Page #i #pagebreak(weak: true)]";
+    let driver = ws.shadow_driver(content);
+
+    let mut actor = CompileActor::new(driver).with_max_pages(3);
+    let errors = actor.compile_once().unwrap_err();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert_eq!(
+        errors[0].message,
+        "the document exceeded the configured limit of 3 pages with 10 pages"
+    );
+    // The error points to the content of the first page over the limit.
+    assert!(!errors[0].span.is_detached());
+
+    actor.max_pages = Some(10);
+    assert_eq!(actor.compile_once().unwrap().pages.len(), 10);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_warnings_as_errors() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let compile = |warnings_as_errors: bool| {
+        let content = b"Hello **";
+        let driver = ws.shadow_driver(content);
+
+        let mut actor = CompileActor::new(driver).with_warnings_as_errors(warnings_as_errors);
+        let once = actor.compile_once();
+        actor.compile(|_| {});
+        (once, actor.health().last_ok, actor.document())
+    };
+
+    let (once, ok, doc) = compile(false);
+    assert!(once.is_ok());
+    assert!(ok && doc.is_some());
+
+    let (once, ok, doc) = compile(true);
+    let warnings = once.unwrap_err();
+    assert!(
+        warnings[0].message.contains("no text within stars"),
+        "{warnings:?}"
+    );
+    assert!(!ok);
+    // The document is kept to display the warnings.
+    assert!(doc.is_some());
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_prelude() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content = b"#context assert.eq(text.lang, \"de\")\n#greeting\n#unknown";
+    let driver = ws.shadow_driver(content);
+
+    let prelude = "#let greeting = [Hello]\n#set text(lang: \"de\")";
+    let mut actor = CompileActor::new(driver).with_prelude(prelude.to_owned());
+    let errors = actor.compile_once().unwrap_err();
+    // Only the unknown variable fails, at its position in the main source.
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert_eq!(errors[0].message, "unknown variable: unknown");
+    let world = actor.compiler.world();
+    let source = world.source(errors[0].span.id().unwrap()).unwrap();
+    assert_eq!(Some(source.id()), world.main_id());
+    assert_eq!(source.range(errors[0].span), Some(47..54));
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_coverage() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let content = "#let used(name) = [Hello #name]\n#let unused(name) = {\n  let greeting = \"Bye\"\n  [#greeting #name]\n}\n\n// A comment\n#used(\"World\")\nSome text\n";
+    let driver = ws.shadow_driver(content.as_bytes());
+
+    let mut actor = CompileActor::new(driver);
+    actor.compile(|_| {});
+    assert!(actor.latest_coverage.is_none());
+
+    actor.coverage = true;
+    actor.compile(|_| {});
+    let report = actor.latest_coverage.clone().unwrap();
+    let file = &report.files["main.typ"];
+    assert!(file.covered.contains(&1) && file.covered.contains(&9));
+    for line in 2..=5 {
+        assert!(file.uncovered.contains(&line), "{line}: {file:?}");
+    }
+    // Blank lines and comments are not code.
+    assert!(!file.covered.contains(&6) && !file.uncovered.contains(&7));
+
+    let lcov = report.to_lcov();
+    assert!(lcov.starts_with("TN:\nSF:main.typ\n"), "{lcov}");
+    assert!(lcov.contains("DA:1,1\n") && lcov.contains("DA:3,0\n"));
+    assert!(lcov.ends_with("end_of_record\n"));
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_source_preprocessor() {
+    use crate::fixture::TestWorkspace;
+
+    #[derive(Debug)]
+    struct Expand;
+
+    impl SourcePreprocessor for Expand {
+        fn process(&self, _path: &VirtualPath, content: &str) -> String {
+            content.replace("{{name}}", "World")
+        }
+    }
+
+    let ws = TestWorkspace::new();
+    let mut driver = ws.shadow_driver(b"Hello {{name}}");
+    driver.world.vfs.set_preprocessor(Some(Arc::new(Expand)));
+    assert!(!driver.world.vfs.preserves_offsets());
+
+    let mut actor = CompileActor::new(driver);
+    actor.compile(|_| {});
+    let text = plain_text(&actor.document().unwrap(), PAGE_DELIMITER);
+    assert_eq!(text, "Hello World");
+
+    actor.compiler.world_mut().vfs.set_preprocessor(None);
+    actor.compile(|_| {});
+    let text = plain_text(&actor.document().unwrap(), PAGE_DELIMITER);
+    assert_eq!(text, "Hello {{name}}");
+}
+
+#[cfg(all(feature = "system-compile", feature = "pdf"))]
+#[test]
+fn test_compile_with_overrides() {
+    use chrono::TimeZone;
+    use typst::foundations::{Bytes, Smart, Str};
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let main = ws.path("main.typ");
+    let other = root.join("other.typ");
+    let driver = ws.driver();
+    let content =
+        "Draft: #sys.inputs.at(\"draft\", default: \"false\")\n\nDate: #datetime.today().display()";
+    driver
+        .map_shadow(&main, Bytes::from(content.as_bytes()))
+        .unwrap();
+    let content = "First\n#pagebreak()\nSecond";
+    driver
+        .map_shadow(&other, Bytes::from(content.as_bytes()))
+        .unwrap();
+
+    let now = Local.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap();
+    let mut actor = CompileActor::new(driver).with_now(now);
+    let pdf = |actor: &CompileActor<_>| {
+        typst_ts_pdf_exporter::pdf(&actor.document().unwrap(), Smart::Auto, None)
+    };
+    let text = |doc: &TypstDocument| plain_text(doc, PAGE_DELIMITER);
+
+    actor.compile(|_| {});
+    let expected = pdf(&actor);
+    let latest = actor.document().unwrap();
+    let revision = actor.compiler.revision();
+
+    let draft = actor
+        .compile_with_overrides(&CompileOverrides {
+            inputs: Some(Dict::from_iter([(
+                Str::from("draft"),
+                Value::Str("true".into()),
+            )])),
+            creation_timestamp: Some(Utc.with_ymd_and_hms(2000, 1, 2, 12, 0, 0).unwrap()),
+            ..CompileOverrides::default()
+        })
+        .unwrap();
+    let draft = text(&draft);
+    assert!(draft.contains("Draft: true"), "{draft:?}");
+    assert!(draft.contains("2000-01-02"), "{draft:?}");
+
+    let selected = actor
+        .compile_with_overrides(&CompileOverrides {
+            entry: Some(other.clone()),
+            pages: Some(vec![2]),
+            ..CompileOverrides::default()
+        })
+        .unwrap();
+    assert_eq!(text(&selected), "Second");
+    let err = actor.compile_with_overrides(&CompileOverrides {
+        pages: Some(vec![3]),
+        ..CompileOverrides::default()
+    });
+    assert!(err.is_err());
+
+    // The overridden compilations are not recorded.
+    assert!(Arc::ptr_eq(&actor.document().unwrap(), &latest));
+    assert_eq!(actor.compiler.revision(), revision);
+
+    // A compilation afterwards is the same as if they never happened.
+    actor.compile(|_| {});
+    assert_eq!(pdf(&actor), expected);
+    assert!(text(&actor.document().unwrap()).contains("Draft: false"));
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_diagnostic_filter() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let compile = |min_severity: DiagnosticSeverity| {
+        let content = b"Hello ** @missing";
+        let driver = ws.shadow_driver(content);
+
+        let mut actor = CompileActor::new(driver).with_diagnostic_filter(min_severity);
+        actor.compile(|_| {});
+        (actor.latest_diagnostics.iter())
+            .map(|diag| diag.severity)
+            .collect::<Vec<_>>()
+    };
+
+    let severities = compile(DiagnosticSeverity::Warning);
+    assert!(severities.contains(&DiagnosticSeverity::Error));
+    assert!(severities.contains(&DiagnosticSeverity::Warning));
+
+    let severities = compile(DiagnosticSeverity::Error);
+    assert_eq!(severities, vec![DiagnosticSeverity::Error]);
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_diagnostics_json() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{fixture::TestWorkspace, service::CompileDriver};
+
+    #[derive(Default, Clone)]
+    struct Counter(Arc<AtomicUsize>);
+
+    impl ActorObserver for Counter {
+        fn on_compile_start(&self, _request: u64) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    ws.write("main.typ", "Hello @missing");
+
+    let world = ws.world();
+    let driver = CompileDriver::new(world).with_entry_file(root.join("main.typ"));
+    let counter = Counter::default();
+    let (actor, client) = CompileActor::new(driver)
+        .with_watch(true)
+        .with_observer(counter.clone())
+        .split();
+    actor.spawn().await.unwrap();
+
+    let mut blocking = client.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut json = String::new();
+        for _ in 0..500 {
+            json = blocking.diagnostics_json().unwrap();
+            if json != "[]" {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // The diagnostics of the latest compilation are serialized as is.
+        let compiled = counter.0.load(Ordering::SeqCst);
+        let latest = blocking.diagnostics().unwrap();
+        assert_eq!(serde_json::to_string(&latest).unwrap(), json);
+        assert!(latest[0].message.contains("missing"), "{json}");
+        assert_eq!(blocking.diagnostics_json().unwrap(), json);
+        assert_eq!(counter.0.load(Ordering::SeqCst), compiled);
+    })
+    .await
+    .unwrap();
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_compile_progress() {
+    use parking_lot::Mutex;
+
+    use crate::{fixture::TestWorkspace, service::CompileExporter};
+
+    let ws = TestWorkspace::new();
+    ws.write("main.typ", "One #pagebreak() Two #pagebreak() Three");
+
+    let driver = ws.driver();
+    let mut compiler = CompileExporter::new(driver);
+
+    let stages = Arc::new(Mutex::new(vec![]));
+    let recorded = stages.clone();
+    let mut env = CompileEnv::default()
+        .with_progress(Some(Arc::new(move |stage| recorded.lock().push(stage))));
+    compiler.compile(&mut env).unwrap();
+
+    let stages = stages.lock().clone();
+    assert_eq!(
+        stages[..3],
+        [
+            CompileStage::Parse,
+            CompileStage::Eval,
+            CompileStage::Layout { iteration: 1 }
+        ]
+    );
+    assert_eq!(stages.last(), Some(&CompileStage::Export { pages: 3 }));
+    let layouts = stages
+        .iter()
+        .filter(|stage| matches!(stage, CompileStage::Layout { .. }));
+    assert_eq!(layouts.count(), stages.len() - 3);
+
+    // Nobody reads the progress while compiling.
+    let (mut actor, client) = CompileActor::new(compiler).with_watch(true).split();
+    let progress = client.progress();
+    drop(client);
+    for _ in 0..3 {
+        actor.compile(|_| {});
+    }
+    assert_eq!(actor.compiler.revision(), 3);
+
+    assert!(progress.has_changed().unwrap());
+    let latest = progress.borrow().clone().unwrap();
+    assert_eq!(latest.revision, 3);
+    assert_eq!(latest.stage, CompileStage::Done { success: true });
+
+    // The stages aren't reported without subscribers.
+    assert!(actor.make_env(Default::default()).progress.is_some());
+    drop(progress);
+    assert!(actor.make_env(Default::default()).progress.is_none());
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_reproducible_outputs() {
+    use std::cell::RefCell;
+
+    use chrono::TimeZone;
+    use typst_ts_core::Exporter;
+    use typst_ts_pdf_exporter::PdfDocExporter;
+
+    use crate::{
+        fixture::TestWorkspace,
+        service::{export::vector_artifact, CompileDriver},
+        TypstSystemWorld,
+    };
+
+    const OUTPUT_ENV: &str = "TYPST_TS_REPRODUCIBLE_OUTPUT";
+
+    // The hash maps are seeded per process, hence the fixture is compiled
+    // by fresh processes running this test again, which write their outputs
+    // into the workspace of this process.
+    let Ok(output) = std::env::var(OUTPUT_ENV) else {
+        let ws = TestWorkspace::new();
+        ws.write(
+            "main.typ",
+            "#import \"b.typ\": b\n#import \"a.typ\": a\n= #a\n$x^2 + #b$\n\
+             #pagebreak()\n#text(font: \"DejaVu Sans Mono\")[#b]\n#pagebreak()\n#a #b",
+        );
+        ws.write("a.typ", "#let a = [Reproducible]");
+        ws.write("b.typ", "#let b = [Outputs]");
+
+        let (_, test) = module_path!().split_once("::").unwrap();
+        let run = |name: &str| {
+            let output = ws.path(name);
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", &format!("{test}::test_reproducible_outputs")])
+                .env(OUTPUT_ENV, &output)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            ["deps.txt", "main.pdf", "main.artifact.sir.in"]
+                .map(|file| (file, std::fs::read(output.join(file)).unwrap()))
+        };
+
+        let (outputs, rebuilt) = (run("first"), run("second"));
+        for ((file, output), (_, rebuilt)) in outputs.iter().zip(rebuilt.iter()) {
+            assert!(output == rebuilt, "{file} is not reproducible");
+        }
+        return;
+    };
+
+    let output = Path::new(&output);
+    let root = output.parent().unwrap();
+    let timestamp = Utc.with_ymd_and_hms(2001, 2, 3, 4, 5, 6).unwrap();
+    let world = TypstSystemWorld::new(TestWorkspace::opts_at(root.to_owned()))
+        .unwrap()
+        .with_creation_timestamp(timestamp);
+    let driver = CompileDriver::new(world).with_entry_file(root.join("main.typ"));
+    let mut actor = CompileActor::new(driver);
+
+    let deps = RefCell::new(vec![]);
+    actor.compile(|res| {
+        if let CompilerResponse::Notify(NotifyMessage::SyncDependency(paths)) = res {
+            *deps.borrow_mut() = paths;
+        }
+    });
+    let deps = deps.into_inner();
+    assert!(deps.windows(2).all(|pair| pair[0] < pair[1]), "{deps:?}");
+    let doc = actor.latest_doc.clone().unwrap();
+    let pdf = PdfDocExporter::default()
+        .with_timestamp(true)
+        .export(actor.compiler.world(), doc.clone())
+        .unwrap();
+
+    std::fs::create_dir_all(output).unwrap();
+    let deps: Vec<_> = deps.iter().map(|dep| dep.display().to_string()).collect();
+    std::fs::write(output.join("deps.txt"), deps.join("\n")).unwrap();
+    std::fs::write(output.join("main.pdf"), pdf).unwrap();
+    std::fs::write(output.join("main.artifact.sir.in"), vector_artifact(&doc)).unwrap();
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_restore_cache() {
+    use std::borrow::Cow;
+
+    use crate::{fixture::TestWorkspace, service::CompileDriverBuilder, vfs::trace::AccessKind};
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let cache = root.join("cache").join("contents.json");
+    ws.write("main.typ", "#import \"a.typ\": a\n#a");
+    ws.write("a.typ", "#let a = [Cached]");
+
+    let start = || {
+        let builder = CompileDriverBuilder::new()
+            .root(&root)
+            .entry("main.typ")
+            .with_system_fonts(false)
+            .with_embedded_fonts(typst_assets::fonts().map(Cow::Borrowed))
+            .offline()
+            .with_trace_access();
+        let stats = builder.trace_stats();
+        (CompileActor::new(builder.build_driver().unwrap()), stats)
+    };
+
+    let text = |actor: &CompileActor<_>| plain_text(&actor.document().unwrap(), "");
+
+    let (mut actor, stats) = start();
+    actor.compile(|_| {});
+    assert_eq!(text(&actor), "Cached");
+    let cold_reads = stats.calls(AccessKind::Content);
+    assert!(cold_reads >= 2);
+    actor.save_cache(&cache).unwrap();
+
+    // The restored files are not accessed until compiling, where they are
+    // only stat'ed.
+    let (mut actor, stats) = start();
+    let accesses = stats.total_calls();
+    assert_eq!(actor.load_cache(&cache).unwrap(), 2);
+    assert_eq!(stats.total_calls(), accesses);
+    actor.compile(|_| {});
+    assert_eq!(text(&actor), "Cached");
+    assert_eq!(stats.calls(AccessKind::Content), cold_reads - 2);
+
+    // A restored file is not read again for the same mtime, even though
+    // its content has changed on the disk.
+    let a = root.join("a.typ");
+    let mtime = std::fs::metadata(&a).unwrap().modified().unwrap();
+    std::fs::write(&a, "#let a = [Stale!]").unwrap();
+    let file = std::fs::File::options().write(true).open(&a).unwrap();
+    file.set_modified(mtime).unwrap();
+    drop(file);
+    let (mut actor, _) = start();
+    actor.load_cache(&cache).unwrap();
+    actor.compile(|_| {});
+    assert_eq!(text(&actor), "Cached");
+
+    // A changed source is read again.
+    std::thread::sleep(Duration::from_millis(10));
+    std::fs::write(&a, "#let a = [Changed]").unwrap();
+    let (mut actor, stats) = start();
+    assert_eq!(actor.load_cache(&cache).unwrap(), 2);
+    actor.compile(|_| {});
+    assert_eq!(text(&actor), "Changed");
+    assert_eq!(stats.calls(AccessKind::Content), cold_reads - 1);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_compile_all() {
+    use typst::foundations::Bytes;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let docs = root.join("docs");
+    std::fs::create_dir_all(&docs).unwrap();
+    std::fs::write(docs.join("a.typ"), "Page A").unwrap();
+    std::fs::write(docs.join("notes.txt"), "Not an entry").unwrap();
+
+    let driver = ws.shadow_driver(b"Main");
+    // The shadow files are compiled as well.
+    let broken = Bytes::from_static(b"#panic(\"broken\")");
+    driver.map_shadow(&docs.join("b.typ"), broken).unwrap();
+
+    let mut actor = CompileActor::new(driver);
+    actor.compile(|_| {});
+    let latest = actor.document().unwrap();
+
+    let results = actor.compile_all("docs/*.typ").unwrap();
+    let files: Vec<_> = (results.iter())
+        .map(|(file, _)| file.as_rootless_path().to_owned())
+        .collect();
+    assert_eq!(files, [Path::new("docs/a.typ"), Path::new("docs/b.typ")]);
+    let doc = results[0].1.as_ref().unwrap();
+    assert_eq!(plain_text(doc, PAGE_DELIMITER).trim(), "Page A");
+    let diags = results[1].1.as_ref().unwrap_err();
+    assert!(diags[0].message.contains("broken"), "{diags:?}");
+
+    // The main file is restored, and the latest document is kept.
+    assert!(Arc::ptr_eq(&actor.document().unwrap(), &latest));
+    let doc = (actor.compile_with_overrides(&CompileOverrides::default())).unwrap();
+    assert_eq!(plain_text(&doc, PAGE_DELIMITER).trim(), "Main");
+
+    assert!(glob_match("**/*.typ", "main.typ"));
+    assert!(glob_match("docs/**/?.typ", "docs/x/y/a.typ"));
+    assert!(!glob_match("docs/*.typ", "docs/x/a.typ"));
+}
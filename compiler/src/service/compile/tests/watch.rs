@@ -0,0 +1,961 @@
+use super::*;
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_dependencies_changed() {
+    use std::sync::Mutex;
+
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, FileSnapshot, MemoryEvent},
+    };
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let main = ws.path("main.typ");
+    let other = root.join("other.typ");
+    ws.write("main.typ", "Hello");
+
+    let driver = ws.driver();
+
+    let reported = Arc::new(Mutex::new(Vec::<Vec<PathBuf>>::new()));
+    let (actor, client) = CompileActor::new(driver)
+        .with_watch(true)
+        .on_dependencies_changed({
+            let reported = reported.clone();
+            move |deps| reported.lock().unwrap().push(deps.to_vec())
+        })
+        .split();
+    actor.spawn().await.unwrap();
+
+    let wait_for = |count: usize| {
+        let reported = reported.clone();
+        async move {
+            for _ in 0..500 {
+                if let Some(deps) = reported.lock().unwrap().get(count - 1) {
+                    return deps.clone();
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("dependencies are not reported");
+        }
+    };
+    assert!(wait_for(1).await.contains(&main));
+
+    // Import a new file in the middle of the session.
+    std::fs::write(&other, "#let x = 1").unwrap();
+    let content = Bytes::from_static(b"#import \"other.typ\": x\n#x");
+    let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+    let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+    client
+        .add_memory_changes(MemoryEvent::Update(changes))
+        .unwrap();
+
+    let deps = wait_for(2).await;
+    assert!(deps.contains(&main));
+    assert!(deps.contains(&other));
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_root() {
+    use typst_ts_core::config::{compiler::EntryOpts, CompileOpts};
+
+    use crate::{fixture::TestWorkspace, service::CompileDriver, TypstSystemWorld};
+
+    let ws = TestWorkspace::new();
+    let (old, new) = (ws.path("old"), ws.path("new"));
+    ws.write("old/main.typ", "Old");
+    ws.write("new/main.typ", "#pagebreak()\nNew");
+
+    let world = TypstSystemWorld::new(CompileOpts {
+        entry: EntryOpts::new_rooted(old.clone(), Some("main.typ".into())),
+        ..ws.opts()
+    })
+    .unwrap();
+    let driver = CompileDriver::new(world).with_entry_file(old.join("main.typ"));
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    // Jump from the second line, which only exists in the new main file.
+    let mut pages = vec![];
+    for line in [0, 1] {
+        let mut pos = None;
+        for _ in 0..500 {
+            pos = client
+                .resolve_src_to_doc_jump("main.typ".into(), line, 2)
+                .await
+                .unwrap();
+            if pos.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        pages.push(pos.expect("jump from the main file").page.get());
+
+        if line == 0 {
+            // The blocking methods of the client must not run on the workers.
+            let mut blocking = client.clone();
+            let (missing, new) = (ws.path("missing"), new.clone());
+            tokio::task::spawn_blocking(move || {
+                let err = blocking.set_root(missing).unwrap_err();
+                assert!(err.to_string().contains("not a directory"), "{err}");
+                blocking.set_root(new.clone()).unwrap();
+                // The entry file of the driver follows the root.
+                let entry = blocking.steal(|this| this.compiler.compiler.entry_file());
+                assert_eq!(entry.unwrap(), new.join("main.typ"));
+            })
+            .await
+            .unwrap();
+        }
+    }
+    // The relative path is resolved against the new root.
+    assert_eq!(pages, [1, 2]);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_edit_with_fallback() {
+    use typst::foundations::Bytes;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let driver = ws.shadow_driver(b"Hello");
+    let mut actor = CompileActor::new(driver);
+
+    let mut edit = |range: Range<usize>, text: &str, fallback: &'static [u8]| {
+        actor.apply_memory_changes(MemoryEvent::Edit(FileEdit {
+            path: main.as_path().into(),
+            range,
+            text: text.to_owned(),
+            fallback: Some(Bytes::from_static(fallback)),
+        }));
+        let world = actor.compiler.world_mut();
+        world.reset();
+        world.vfs.file(&main).unwrap()
+    };
+
+    // The edit applies cleanly.
+    assert_eq!(&edit(5..5, "!", b"Hello!")[..], b"Hello!");
+    // The range is out of bounds.
+    assert_eq!(&edit(10..12, "?", b"Hello?")[..], b"Hello?");
+    // The edited content has a different length.
+    assert_eq!(&edit(0..1, "J", b"Jello!!")[..], b"Jello!!");
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_evict_removed_dependencies() {
+    use typst::foundations::Bytes;
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    ws.write("chapter.typ", "Chapter");
+
+    let driver = ws.driver();
+    let mut actor = CompileActor::new(driver);
+
+    let mut compile = |content: &'static str| {
+        let content = Bytes::from_static(content.as_bytes());
+        actor.compiler.map_shadow(&main, content).unwrap();
+        actor.compile(|_| {});
+        let text = actor.document().map(|doc| document_text(&doc));
+        let text: Vec<_> = text.unwrap().into_iter().map(|run| run.text).collect();
+        (text.concat(), actor.compiler.world().vfs.cached_files())
+    };
+
+    let (_, baseline) = compile("Intro");
+    let (text, included) = compile("Intro #include \"chapter.typ\"");
+    assert!(text.contains("Chapter"), "{text:?}");
+    assert!(included > baseline);
+    assert_eq!(compile("Intro").1, baseline);
+
+    // The removed file is read again when it is included again.
+    ws.write("chapter.typ", "Edited");
+    let (text, _) = compile("Intro #include \"chapter.typ\"");
+    assert!(text.contains("Edited"), "{text:?}");
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_coalesce_memory_updates() {
+    use std::cell::Cell;
+
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, FileSnapshot},
+    };
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    let driver = ws.driver();
+    let (mut actor, client) = CompileActor::new(driver).split();
+
+    // Rapid typing queues up updates before the actor wakes up.
+    for i in 0..50 {
+        let content = Bytes::from(format!("Update {i}").into_bytes());
+        let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+        let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+        client
+            .add_memory_changes(MemoryEvent::Update(changes))
+            .unwrap();
+    }
+
+    let notified = Cell::new(0);
+    let first = actor.memory_recv.try_recv().unwrap();
+    let need_recompile = actor.process(CompilerInterrupt::Memory(first), |_| {
+        notified.set(notified.get() + 1);
+    });
+    assert!(need_recompile);
+    assert!(actor.memory_recv.try_recv().is_err());
+    assert_eq!(notified.get(), 0);
+
+    actor.compile(|_| {});
+    assert_eq!(actor.generation, 1);
+    let text = document_text(&actor.document().unwrap());
+    let text: String = text.into_iter().map(|run| run.text).collect();
+    assert!(text.contains("49") && !text.contains("48"), "{text:?}");
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_wait_for_entry() {
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, FileSnapshot},
+    };
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let main = ws.path("main.typ");
+    let mut driver = ws.driver();
+
+    let errors = driver.compile(&mut CompileEnv::default()).unwrap_err();
+    let expected = format!(
+        "entry file 'main.typ' not found under root '{}'",
+        root.display()
+    );
+    assert_eq!(errors[0].message.as_str(), expected);
+
+    let mut actor = CompileActor::new(driver);
+    actor.compile(|_| {});
+    assert!(actor.health().waiting_for_entry);
+    assert!(actor.latest_deps.contains(main.as_path()));
+
+    // Creating the entry as a shadow file compiles the document.
+    let snapshot: FileSnapshot =
+        FileResult::Ok((crate::time::now(), Bytes::from_static(b"Created"))).into();
+    let update = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+    actor.handle(
+        CompilerInterrupt::Memory(vec![MemoryEvent::Update(update)]),
+        |_| None,
+        |_| {},
+    );
+    assert!(actor.latest_doc.is_some());
+    assert!(!actor.health().waiting_for_entry);
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_compile_untitled_buffer() {
+    use typst::{diag::FileResult, foundations::Bytes};
+    use typst_ts_core::{
+        config::{compiler::EntryOpts, CompileOpts},
+        debug_loc::CharPosition,
+    };
+
+    use crate::{
+        fixture::TestWorkspace,
+        service::CompileDriver,
+        vfs::notify::{FileChangeSet, FileSnapshot},
+        TypstSystemWorld,
+    };
+
+    let update = |path: &Path, content: &'static [u8]| {
+        let content = Bytes::from_static(content);
+        let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+        let changes = FileChangeSet::new_inserts(vec![(path.into(), snapshot)]);
+        MemoryEvent::Update(changes)
+    };
+
+    const URI: &str = "untitled:Untitled-1";
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    ws.write("chapter.typ", "Chapter");
+    let saved = root.join("saved.typ");
+
+    let mut world = TypstSystemWorld::new(CompileOpts {
+        entry: EntryOpts::new_rooted(root.clone(), None),
+        ..ws.opts()
+    })
+    .unwrap();
+    let id = world.register_untitled(URI, root.as_path().into());
+    let entry = world.entry.select_in_workspace(id);
+    world.mutate_entry(entry).unwrap();
+    let mapper = world.path_mapper();
+    assert_eq!(mapper.id_for_path(Path::new(URI)).unwrap(), id);
+    assert_eq!(mapper.display_path(id).unwrap(), URI);
+
+    let driver = CompileDriver::new(world);
+    let content = b"Hello #include \"chapter.typ\"";
+    driver
+        .map_shadow(Path::new(URI), Bytes::from_static(content))
+        .unwrap();
+    assert_eq!(driver.shadow_paths(), vec![Arc::from(Path::new(URI))]);
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    // Wait for the initial compilation.
+    let mut pos = None;
+    for _ in 0..500 {
+        pos = client
+            .resolve_src_to_doc_jump(URI.into(), 0, 2)
+            .await
+            .unwrap();
+        if pos.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(pos.expect("jump from an untitled buffer").page.get(), 1);
+
+    // The jump back to the source reports the URI of the buffer.
+    let loc = SourceLocation {
+        filepath: URI.to_owned(),
+        pos: CharPosition { line: 0, column: 2 },
+    };
+    let span = client.resolve_src_location(loc).await.unwrap().unwrap();
+    let jump = client.resolve_span(span.span).await.unwrap().unwrap();
+    assert_eq!(jump.filepath, URI);
+
+    // So do the diagnostics in the buffer.
+    let broken = update(Path::new(URI), b"Hello #include \"chapter.typ\" #unknown");
+    client.add_memory_changes(broken).unwrap();
+    // The blocking methods of the client must not run on the workers.
+    let mut blocking = client.clone();
+    let diags = tokio::task::spawn_blocking(move || {
+        let mut diags = vec![];
+        for _ in 0..500 {
+            diags = blocking.diagnostics().unwrap();
+            if !diags.is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        diags
+    })
+    .await
+    .unwrap();
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert_eq!(diags[0].location.path.as_deref(), Some(URI));
+
+    // Saving moves the buffer to the path without resetting the actor.
+    std::fs::write(&saved, content).unwrap();
+    let mut blocking = client.clone();
+    let path = saved.clone();
+    let saved_id =
+        tokio::task::spawn_blocking(move || blocking.save_untitled(URI.to_owned(), path))
+            .await
+            .unwrap()
+            .unwrap();
+    assert_eq!(saved_id.package(), None);
+    let edited = update(&saved, b"Saved #include \"chapter.typ\"");
+    client.add_memory_changes(edited).unwrap();
+
+    let mut jump = None;
+    for _ in 0..500 {
+        let loc = SourceLocation {
+            filepath: saved.to_string_lossy().to_string(),
+            pos: CharPosition { line: 0, column: 2 },
+        };
+        if let Some(span) = client.resolve_src_location(loc).await.unwrap() {
+            jump = client.resolve_span(span.span).await.unwrap();
+            if jump.is_some() {
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let jump = jump.expect("jump to the saved file");
+    assert_eq!(Path::new(&jump.filepath), saved);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_invalidate_dependents_only() {
+    use std::cell::RefCell;
+
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, FileSnapshot, UpstreamUpdateEvent},
+    };
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let (main, a, b) = (
+        root.join("main.typ"),
+        root.join("a.typ"),
+        root.join("b.typ"),
+    );
+    ws.write(
+        "main.typ",
+        "#import \"a.typ\": a\n#import \"b.typ\": b\n#a #b",
+    );
+    std::fs::write(&a, "#let a = [A]").unwrap();
+    std::fs::write(&b, "#let b = [B]").unwrap();
+
+    let driver = ws.driver();
+    let (mut actor, _client) = CompileActor::new(driver).split();
+
+    let update = |path: &Path, content: &str| {
+        let content = Bytes::from(content.as_bytes().to_vec());
+        let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+        MemoryEvent::Update(FileChangeSet::new_inserts(vec![(path.into(), snapshot)]))
+    };
+    let upstream = RefCell::new(vec![]);
+    let send = |resp: CompilerResponse| match resp {
+        CompilerResponse::Notify(NotifyMessage::UpstreamUpdate(event)) => {
+            upstream.borrow_mut().push(event)
+        }
+        CompilerResponse::Notify(_) => {}
+    };
+
+    assert!(actor.process_memory(update(&a, "#let a = [A1]"), send));
+    assert!(actor.process_memory(update(&b, "#let b = [B1]"), send));
+    actor.compile(|_| {});
+    assert!(upstream.borrow().is_empty());
+
+    // Closing the buffer of `a.typ` invalidates it and its importer.
+    let close = MemoryEvent::Update(FileChangeSet::new_removes(vec![a.as_path().into()]));
+    assert!(!actor.process_memory(close, send));
+    let invalidates: Vec<_> = (upstream.borrow().iter())
+        .flat_map(|event: &UpstreamUpdateEvent| event.invalidates.clone())
+        .collect();
+    assert_eq!(invalidates, vec![ImmutPath::from(a.as_path())]);
+    let mut dirty: Vec<_> = actor.dirty_shadows.keys().cloned().collect();
+    dirty.sort();
+    assert_eq!(
+        dirty,
+        vec![ImmutPath::from(a.as_path()), main.as_path().into()]
+    );
+
+    // The independent import is still applied directly, while the
+    // importer waits for the invalidation.
+    assert!(actor.process_memory(update(&b, "#let b = [B2]"), send));
+    assert_eq!(upstream.borrow().len(), 1);
+    assert!(!actor.process_memory(update(&main, "#import \"b.typ\": b\n#b"), send));
+    assert_eq!(upstream.borrow().len(), 2);
+
+    for event in upstream.take() {
+        let event = FilesystemEvent::UpstreamUpdate {
+            changeset: FileChangeSet::default(),
+            upstream_event: Some(event),
+        };
+        assert!(actor.process(CompilerInterrupt::Fs(Some(event)), |_| {}));
+    }
+    assert!(actor.dirty_shadows.is_empty());
+    actor.compile(|_| {});
+    let text = document_text(&actor.document().unwrap());
+    let text: String = text.into_iter().map(|run| run.text).collect();
+    assert_eq!(text, "B2");
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_watch_file() {
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, FileSnapshot},
+    };
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let main = ws.path("main.typ");
+    let other = root.join("other.typ");
+    let driver = ws.driver();
+    let (mut actor, client) = CompileActor::new(driver).split();
+    let mut changes = client.watch_file(main.clone());
+
+    let update = |path: &Path, content: &'static str| {
+        let content = Bytes::from_static(content.as_bytes());
+        let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+        let changes = FileChangeSet::new_inserts(vec![(path.into(), snapshot)]);
+        CompilerInterrupt::Fs(Some(FilesystemEvent::Update(changes)))
+    };
+    actor.handle(update(&other, "Other"), |_| None, |_| {});
+    actor.handle(update(&main, "Main"), |_| None, |_| {});
+    let removal = FileChangeSet::new_removes(vec![other.as_path().into()]);
+    let removal = CompilerInterrupt::Fs(Some(FilesystemEvent::Update(removal)));
+    actor.handle(removal, |_| None, |_| {});
+
+    match changes.try_recv().unwrap() {
+        FileChange::Updated(snapshot) => {
+            assert_eq!(snapshot.content().unwrap().as_slice(), b"Main");
+        }
+        change => panic!("unexpected change {change:?}"),
+    }
+    assert!(changes.try_recv().is_err());
+
+    // The file is forgotten once its receivers are dropped.
+    drop(changes);
+    actor.handle(update(&main, "Again"), |_| None, |_| {});
+    assert!(actor.file_watches.lock().is_empty());
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_buffer_fs_events_until_initial_scan() {
+    use typst::{diag::FileResult, foundations::Bytes};
+
+    use crate::{
+        fixture::TestWorkspace,
+        vfs::notify::{FileChangeSet, FileSnapshot},
+    };
+
+    let ws = TestWorkspace::new();
+    let main = ws.path("main.typ");
+    ws.write("main.typ", "Partial");
+
+    let driver = ws.driver();
+    let (mut actor, _client) = CompileActor::new(driver).with_watch(true).split();
+
+    // A change reported before the scan completes is buffered.
+    let content = Bytes::from("Full".as_bytes().to_vec());
+    let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+    let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+    let event = FilesystemEvent::Update(changes);
+    actor.handle(CompilerInterrupt::Fs(Some(event)), |_| None, |_| {});
+    assert_eq!(actor.compiler.revision(), 0);
+    assert!(actor.document().is_none());
+
+    // The sentinel applies the buffered change and compiles once.
+    actor.handle(CompilerInterrupt::Fs(None), |_| None, |_| {});
+    assert_eq!(actor.compiler.revision(), 1);
+    assert!(actor.pending_fs_events.is_empty());
+    let text = document_text(&actor.document().unwrap());
+    let text: String = text.into_iter().map(|run| run.text).collect();
+    assert_eq!(text, "Full");
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_project_reload() {
+    use std::{borrow::Cow, cell::RefCell};
+
+    use typst::{diag::FileResult, foundations::Bytes};
+    use typst_ts_core::font::FontOrigin;
+
+    use crate::{
+        fixture::TestWorkspace,
+        service::{project::PROJECT_FILE, CompileDriverBuilder},
+        vfs::notify::{FileChangeSet, FileSnapshot},
+        TypstSystemWorld,
+    };
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let fonts = root.join("fonts");
+    let project = root.join(PROJECT_FILE);
+    std::fs::create_dir_all(&fonts).unwrap();
+    ws.write("main.typ", "= Reload");
+    let font = typst_assets::fonts().next().unwrap();
+    std::fs::write(fonts.join("user.otf"), font).unwrap();
+
+    let (driver, state) = CompileDriverBuilder::new()
+        .root(&root)
+        .entry("main.typ")
+        .with_system_fonts(false)
+        .with_embedded_fonts(typst_assets::fonts().map(Cow::Borrowed))
+        .offline()
+        .discover_project()
+        .build_project_driver()
+        .unwrap();
+    let mut actor = CompileActor::new(driver).with_project(state.unwrap());
+    let user_faces = |world: &TypstSystemWorld| {
+        let families = list_fonts(&world.font_resolver);
+        (families.iter().flat_map(|family| &family.faces))
+            .filter(|face| face.origin == Some(FontOrigin::User))
+            .count()
+    };
+
+    // The missing project file is watched, so that creating it takes
+    // effect.
+    let deps = RefCell::new(vec![]);
+    actor.compile(|res| {
+        let CompilerResponse::Notify(NotifyMessage::SyncDependency(paths)) = res else {
+            return;
+        };
+        *deps.borrow_mut() = paths;
+    });
+    assert!(deps.borrow().contains(&project.as_path().into()));
+    assert_eq!(user_faces(actor.compiler.world()), 0);
+
+    let content = Bytes::from_static(b"font-paths = [\"fonts\"]\n");
+    let snapshot: FileSnapshot = FileResult::Ok((crate::time::now(), content)).into();
+    let changes = FileChangeSet::new_inserts(vec![(project.as_path().into(), snapshot)]);
+    let event = CompilerInterrupt::Fs(Some(FilesystemEvent::Update(changes)));
+    assert!(actor.process(event, |_| {}));
+    assert!(user_faces(actor.compiler.world()) > 0);
+    actor.compile(|_| {});
+    assert!(actor.latest_doc.is_some());
+
+    // Removing the project file restores the defaults.
+    let changes = FileChangeSet::new_removes(vec![project.as_path().into()]);
+    let event = CompilerInterrupt::Fs(Some(FilesystemEvent::Update(changes)));
+    assert!(actor.process(event, |_| {}));
+    assert_eq!(user_faces(actor.compiler.world()), 0);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_root_unavailable() {
+    use std::borrow::Cow;
+
+    use crate::{fixture::TestWorkspace, service::CompileDriverBuilder};
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    ws.write("main.typ", "= Mounted <mounted>");
+
+    let driver = CompileDriverBuilder::new()
+        .root(&root)
+        .entry("main.typ")
+        .with_system_fonts(false)
+        .with_embedded_fonts(typst_assets::fonts().map(Cow::Borrowed))
+        .offline()
+        .build_driver()
+        .unwrap();
+    let mut actor = CompileActor::new(driver);
+    actor.compile(|_| {});
+    assert!(actor.latest_compile.is_some_and(|(_, ok)| ok));
+    assert!(!actor.health().root_unavailable);
+
+    // The root is reported once instead of the files under it, and the
+    // latest document is kept.
+    std::fs::remove_dir_all(&root).unwrap();
+    actor.compile(|_| {});
+    assert!(actor.health().root_unavailable);
+    assert_eq!(actor.latest_diagnostics.len(), 1);
+    let message = &actor.latest_diagnostics[0].message;
+    assert!(message.contains(&*root.to_string_lossy()), "{message}");
+    assert!(actor.latest_doc.is_some());
+
+    // Probing an unavailable root skips the compilation.
+    assert!(actor.process(CompilerInterrupt::ProbeRoot, |_| {}));
+    actor.compile(|_| {});
+    assert!(actor.health().root_unavailable);
+    assert_eq!(actor.latest_diagnostics.len(), 1);
+
+    // The files are read again once the root is back.
+    ws.write("main.typ", "= Back <back>");
+    assert!(actor.process(CompilerInterrupt::ProbeRoot, |_| {}));
+    actor.compile(|_| {});
+    assert!(!actor.health().root_unavailable);
+    assert!(actor.latest_compile.is_some_and(|(_, ok)| ok));
+    assert!(actor.latest_diagnostics.is_empty());
+    let labels = document_labels(actor.latest_doc.as_ref().unwrap());
+    assert_eq!(labels[0].name, "back");
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rebuild_world_on_standby() {
+    use std::sync::{mpsc, Mutex};
+
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    ws.write("main.typ", "#answer");
+    let moved = ws.path("moved");
+    ws.write("moved/main.typ", "#answer");
+
+    let driver = ws.driver();
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    let wait_for = |client: &mut CompileClient<_>, done: fn(&ActorHealth) -> bool| {
+        for _ in 0..500 {
+            let health = client.health().unwrap();
+            if done(&health) {
+                return health;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        client.health().unwrap()
+    };
+
+    tokio::task::spawn_blocking(move || {
+        // The answer is unknown to the standard library.
+        let health = wait_for(&mut client, |health| health.last_compile_ms_ago.is_some());
+        assert!(!health.last_ok);
+
+        // The rebuild is held in building the library until the sender is
+        // dropped, after which the rebuilds are no longer held.
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let hook = LibraryHook::new(move |library| {
+            let _ = released.lock().unwrap().recv();
+            library.global.scope_mut().define("answer", 42);
+        });
+        let change = WorldChange::default().with_library_hook(Some(hook));
+        client.rebuild_world(change).unwrap();
+        // A change during the rebuild is amended to it.
+        let change = WorldChange::default().with_root(moved.as_path().into());
+        client.rebuild_world(change).unwrap();
+
+        // The hovers are answered by the old world in the meantime.
+        let old = client.steal(|this| this.compiler.world().library_hook.is_none());
+        assert!(old.unwrap());
+        let old_root = client.steal(|this| this.compiler.world().workspace_root());
+        assert_eq!(old_root.unwrap().as_deref(), Some(ws.root().as_path()));
+        assert!(client.health().unwrap().rebuilding_world);
+        drop(release);
+
+        let health = wait_for(&mut client, |health| {
+            !health.rebuilding_world && health.last_ok
+        });
+        assert!(!health.rebuilding_world);
+        assert!(health.last_ok);
+        let new = client.steal(|this| this.compiler.world().library_hook.is_some());
+        assert!(new.unwrap());
+        let new_root = client.steal(|this| this.compiler.world().workspace_root());
+        assert_eq!(new_root.unwrap().as_deref(), Some(moved.as_path()));
+    })
+    .await
+    .unwrap();
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_replay_session_log() {
+    use typst::diag::FileResult;
+
+    use crate::{fixture::TestWorkspace, service::CompileDriver};
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let main = ws.path("main.typ");
+    let chapter = root.join("chapter.typ");
+    ws.write("main.typ", "= Main\n#include \"chapter.typ\"");
+    std::fs::write(&chapter, "#undefined").unwrap();
+    let logs = tempfile::tempdir().unwrap();
+    let log_path = logs.path().join("session.jsonl");
+
+    let make_actor = || {
+        let world = ws.world();
+        CompileActor::new(CompileDriver::new(world).with_entry_file(main.clone()))
+    };
+    let snapshot = |path: &Path| -> FileSnapshot {
+        let content = Bytes::from(std::fs::read(path).unwrap());
+        FileResult::Ok((crate::time::now(), content)).into()
+    };
+
+    // Record a session, where the included chapter fails to compile until
+    // it is fixed on the disk.
+    let log = SessionLog::to_file(&log_path, 1 << 20, 1).unwrap();
+    let mut actor = make_actor().with_session_log(log.with_root(root.as_path().into()));
+    let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot(&main))]);
+    let first = CompilerInterrupt::Memory(vec![MemoryEvent::Update(changes)]);
+    actor.handle(first, |_| None, |_| {});
+    std::fs::write(&chapter, "Chapter").unwrap();
+    let changes = FileChangeSet::new_inserts(vec![(chapter.as_path().into(), snapshot(&chapter))]);
+    let first = CompilerInterrupt::Fs(Some(FilesystemEvent::Update(changes)));
+    actor.handle(first, |_| None, |_| {});
+    drop(actor);
+
+    // The timings differ between the runs.
+    let normalize = |records: Vec<SessionRecord>| {
+        (records.into_iter())
+            .map(|record| match record.event {
+                SessionEvent::Compile {
+                    revision,
+                    ok,
+                    errors,
+                    warnings,
+                    ..
+                } => SessionEvent::Compile {
+                    revision,
+                    duration_ms: 0,
+                    ok,
+                    errors,
+                    warnings,
+                },
+                event => event,
+            })
+            .collect::<Vec<_>>()
+    };
+    let recorded = normalize(SessionLog::read(&log_path).unwrap());
+    let compiles: Vec<_> = (recorded.iter())
+        .filter_map(|event| match event {
+            SessionEvent::Compile { ok, errors, .. } => Some((*ok, *errors)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(compiles, [(false, 1), (true, 0)]);
+    assert!(recorded.iter().any(|event| matches!(
+        event,
+        SessionEvent::Interrupt { request: 2, kind: InterruptKind::Fs, inserts, .. }
+            if inserts == &["chapter.typ"]
+    )));
+
+    // Replaying the session over the workspace in its initial state
+    // reproduces it, where the chapter is fixed before the file system
+    // event is replayed.
+    std::fs::write(&chapter, "#undefined").unwrap();
+    let replayed = Arc::new(Mutex::new(vec![]));
+    let log = SessionLog::to_callback({
+        let replayed = replayed.clone();
+        move |record| replayed.lock().push(record.clone())
+    });
+    let mut actor = make_actor().with_session_log(log.with_root(root.as_path().into()));
+    let records = SessionLog::read(&log_path).unwrap();
+    let mut fixed = false;
+    for record in &records {
+        if !fixed && matches!(record.event, SessionEvent::Interrupt { request: 2, .. }) {
+            std::fs::write(&chapter, "Chapter").unwrap();
+            fixed = true;
+        }
+        actor.replay(std::slice::from_ref(record), &root);
+    }
+    let replayed = std::mem::take(&mut *replayed.lock());
+    assert_eq!(normalize(replayed), recorded);
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_shadow_divergences() {
+    use crate::fixture::TestWorkspace;
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let main = ws.path("main.typ");
+    let same = root.join("same.typ");
+    ws.write("main.typ", "Hello");
+    std::fs::write(&same, "Same").unwrap();
+
+    let driver = ws.driver();
+    // Only the edited file diverges, while the files not on disk don't.
+    driver
+        .map_shadow(&main, Bytes::from("Hello, world".as_bytes()))
+        .unwrap();
+    driver
+        .map_shadow(&same, Bytes::from("Same".as_bytes()))
+        .unwrap();
+    let new = root.join("new.typ");
+    driver
+        .map_shadow(&new, Bytes::from("New".as_bytes()))
+        .unwrap();
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    let divergences = tokio::task::spawn_blocking(move || client.shadow_divergences().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(divergences, [main]);
+}
+
+#[cfg(feature = "system-compile")]
+#[test]
+fn test_notify_dependency_updates() {
+    use crate::{fixture::TestWorkspace, service::CompileDriver};
+
+    let ws = TestWorkspace::new();
+    let root = ws.root();
+    let world = ws.world();
+    let driver = CompileDriver::new(world).with_entry_file(root.join("main.typ"));
+    let mut actor = CompileActor::new(driver);
+
+    let dep = |idx: usize| -> ImmutPath { root.join(format!("deps/{idx:05}.typ")).into() };
+    let deps: Vec<_> = (0..50_000).map(dep).collect();
+    let msg = actor.notify_dependencies(deps.clone());
+    assert!(matches!(msg, Some(NotifyMessage::SyncDependency(paths)) if paths.len() == 50_000));
+
+    // The changes are sent in the steady state, and nothing without them.
+    let mut changed = deps[1..].to_vec();
+    changed.push(dep(50_000));
+    let msg = actor.notify_dependencies(changed.clone());
+    let Some(NotifyMessage::UpdateDependency { inserts, removes }) = msg else {
+        panic!("expected an update of the dependencies");
+    };
+    assert_eq!(inserts, [dep(50_000)]);
+    assert_eq!(removes, [dep(0)]);
+    assert!(actor.notify_dependencies(changed.clone()).is_none());
+
+    // All of them are synced once in a while.
+    for _ in 3..DEPENDENCY_SYNC_INTERVAL {
+        assert!(actor.notify_dependencies(changed.clone()).is_none());
+    }
+    let msg = actor.notify_dependencies(changed.clone());
+    assert!(matches!(msg, Some(NotifyMessage::SyncDependency(paths)) if paths == changed));
+}
+
+#[cfg(feature = "system-compile")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_restart_watcher_on_set_root() {
+    use typst_ts_core::config::{compiler::EntryOpts, CompileOpts};
+
+    use crate::{fixture::TestWorkspace, service::CompileDriver, TypstSystemWorld};
+
+    let ws = TestWorkspace::new();
+    let (old, new) = (ws.path("old"), ws.path("new"));
+    ws.write("old/main.typ", "Old");
+    ws.write("new/main.typ", "New");
+
+    let world = TypstSystemWorld::new(CompileOpts {
+        entry: EntryOpts::new_rooted(old.clone(), Some("main.typ".into())),
+        ..ws.opts()
+    })
+    .unwrap();
+    let driver = CompileDriver::new(world).with_entry_file(old.join("main.typ"));
+    let (actor, mut client) = CompileActor::new(driver).with_watch(true).split();
+    actor.spawn().await.unwrap();
+
+    tokio::task::spawn_blocking(move || {
+        let wait_for = |client: &mut CompileClient<_>, text: &str| {
+            for _ in 0..500 {
+                if client.export_text().is_ok_and(|it| it.contains(text)) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            panic!("{text:?} is not compiled");
+        };
+        wait_for(&mut client, "Old");
+        client.set_root(new.clone()).unwrap();
+        wait_for(&mut client, "New");
+        // The new watcher may report the dependencies it starts watching, which
+        // recompiles once more. An edit compiled after them settles the
+        // generation.
+        std::fs::write(new.join("main.typ"), "New settled").unwrap();
+        wait_for(&mut client, "New settled");
+
+        // The edits under the old root are no longer watched. The events are
+        // ordered, so the old edit would be compiled before the new one.
+        let generation = client.generation().unwrap();
+        std::fs::write(old.join("main.typ"), "Old edited").unwrap();
+        std::fs::write(new.join("main.typ"), "New edited").unwrap();
+        wait_for(&mut client, "New edited");
+        assert_eq!(client.generation().unwrap(), generation + 1);
+
+        // A restarted watcher keeps watching the dependencies.
+        client.restart_watcher().unwrap();
+        std::fs::write(new.join("main.typ"), "New restarted").unwrap();
+        wait_for(&mut client, "New restarted");
+    })
+    .await
+    .unwrap();
+}
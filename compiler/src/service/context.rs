@@ -0,0 +1,175 @@
+//! Resolve the context of positions in compiled documents, e.g. the
+//! enclosing section and the syntactic scopes of the source.
+
+use std::ops::Range;
+
+use comemo::Track;
+use serde::Serialize;
+use typst::{
+    engine::{Engine, Route},
+    eval::Tracer,
+    foundations::{Element, Label, Selector, Value},
+    introspection::{Counter, CounterKey, Locator},
+    layout::Point,
+    model::HeadingElem,
+    syntax::{
+        ast::{self, AstNode},
+        Source, Span, SyntaxNode,
+    },
+    World,
+};
+use typst_ts_core::{error::prelude::*, TypstDocument};
+
+use super::{jump::span_from_point, outline::heading_text};
+
+/// The context of a position in a document, see [`context_at`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContextInfo {
+    /// The text of the nearest heading at or before the position, i.e. the
+    /// title of the section containing it.
+    pub heading: Option<String>,
+    /// The scopes enclosing the source of the position, from the innermost.
+    pub scopes: Vec<ContextScope>,
+}
+
+/// The kind of a [`ContextScope`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ContextScopeKind {
+    /// The body of a function, i.e. a closure.
+    Function,
+    /// A show rule.
+    ShowRule,
+    /// The arguments of a function call.
+    Call,
+}
+
+/// A syntactic scope enclosing a position, see [`ContextInfo`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContextScope {
+    pub kind: ContextScopeKind,
+    /// The name of the function, the selector of the show rule or the callee
+    /// of the call, which is `None` for an unnamed function or an everything
+    /// show rule.
+    pub name: Option<String>,
+    /// The byte range of the scope in its source.
+    pub range: Range<usize>,
+}
+
+/// Resolve the context of a position on a page of a document, e.g. for
+/// breadcrumbs in a previewer.
+///
+/// The page number is 1-based, as in [`Position`]. The heading is looked up by
+/// the position in the document, while the scopes are the ancestors of the
+/// source of the glyph under the position. `None` is returned if there is no
+/// glyph under the position.
+pub fn context_at(
+    world: &dyn World,
+    document: &TypstDocument,
+    page: usize,
+    point: Point,
+) -> Option<ContextInfo> {
+    let frame = &document.pages.get(page.checked_sub(1)?)?.frame;
+    let (span, _) = span_from_point(frame, point)?;
+
+    let introspector = &document.introspector;
+    let headings = introspector.query(&Selector::Elem(Element::of::<HeadingElem>(), None));
+    let heading = headings
+        .iter()
+        .filter(|elem| {
+            let Some(location) = elem.location() else {
+                return false;
+            };
+            let pos = introspector.position(location);
+            (pos.page.get(), pos.point.y) <= (page, point.y)
+        })
+        .last()
+        .map(heading_text);
+
+    let source = span.id().and_then(|id| world.source(id).ok());
+    let scopes = source
+        .map(|source| context_scopes(&source, span))
+        .unwrap_or_default();
+
+    Some(ContextInfo { heading, scopes })
+}
+
+/// Collect the scopes enclosing a span, from the innermost.
+fn context_scopes(source: &Source, span: Span) -> Vec<ContextScope> {
+    let Some(leaf) = source.find(span) else {
+        return vec![];
+    };
+
+    let text_of = |node: &SyntaxNode| node.clone().into_text().to_string();
+    let mut scopes = vec![];
+    let mut parent = leaf.parent();
+    while let Some(node) = parent {
+        let scope = if let Some(closure) = node.cast::<ast::Closure>() {
+            let name = closure.name().map(|name| name.get().to_string());
+            Some((ContextScopeKind::Function, name))
+        } else if let Some(rule) = node.cast::<ast::ShowRule>() {
+            let name = rule
+                .selector()
+                .map(|selector| text_of(selector.to_untyped()));
+            Some((ContextScopeKind::ShowRule, name))
+        } else if let Some(call) = node.cast::<ast::FuncCall>() {
+            let name = text_of(call.callee().to_untyped());
+            Some((ContextScopeKind::Call, Some(name)))
+        } else {
+            None
+        };
+
+        if let Some((kind, name)) = scope {
+            let range = node.range();
+            scopes.push(ContextScope { kind, name, range });
+        }
+        parent = node.parent();
+    }
+    scopes
+}
+
+/// Resolve the value of a counter at a labelled element in a document, e.g.
+/// the number of a figure.
+///
+/// The counter is either `page` or the name of an element function, e.g.
+/// `heading`, `figure` or `equation`. `None` is returned for an unknown
+/// counter, or a label which is missing or attached to multiple elements.
+pub fn counter_at(
+    world: &dyn World,
+    document: &TypstDocument,
+    counter: &str,
+    label: &str,
+) -> ZResult<Option<Vec<i64>>> {
+    let counter = match counter {
+        "page" => Counter::new(CounterKey::Page),
+        name => match world.library().global.scope().get(name) {
+            Some(Value::Func(func)) => match func.element() {
+                Some(elem) => Counter::of(elem),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        },
+    };
+
+    let introspector = &document.introspector;
+    let elem = introspector.query_label(Label::new(label)).ok();
+    let Some(location) = elem.and_then(|elem| elem.location()) else {
+        return Ok(None);
+    };
+
+    let mut tracer = Tracer::new();
+    let mut locator = Locator::new();
+    let mut engine = Engine {
+        world: world.track(),
+        route: Route::default(),
+        tracer: tracer.track_mut(),
+        locator: &mut locator,
+        introspector: introspector.track(),
+    };
+    let state = counter.at_loc(&mut engine, location).map_err(|diags| {
+        let messages: Vec<_> = diags.iter().map(|diag| diag.message.as_str()).collect();
+        error_once!("failed to resolve counter", label: label, message: messages.join("; "))
+    })?;
+
+    Ok(Some(state.0.iter().map(|&n| n as i64).collect()))
+}
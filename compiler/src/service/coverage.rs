@@ -0,0 +1,194 @@
+//! Report the lines of sources exercised by a compiled document.
+//!
+//! Template collections accumulate functions and show rules which no
+//! document uses anymore. Typst doesn't expose hooks into the evaluation,
+//! hence the coverage is derived from the spans of the laid out document
+//! instead: a line is covered if it produced some content of the document,
+//! e.g. a glyph or an image. Since a function is evaluated as a whole when it
+//! is called, the lines of a closure or a show rule producing some content
+//! are all covered.
+//!
+//! The other lines bearing code, i.e. except blank lines and comments, are
+//! uncovered. Code which only computes values without producing content, e.g.
+//! a binding of a number, is also uncovered, so the report tells the lines
+//! worth checking rather than the dead lines for sure.
+//!
+//! The report is written in the lcov format by [`CoverageReport::to_lcov`],
+//! which is rendered by the existing coverage viewers.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Write,
+    ops::Range,
+};
+
+use serde::Serialize;
+use typst::{
+    layout::{Frame, FrameItem},
+    syntax::{Source, Span, SyntaxKind, SyntaxNode},
+    World,
+};
+use typst_ts_core::{error::prelude::*, TypstDocument, TypstFileId as FileId};
+
+use super::cancel::CancellationToken;
+use crate::world::display_file_name;
+
+/// The covered and uncovered lines of a source file, which are 1-based.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FileCoverage {
+    pub covered: BTreeSet<usize>,
+    pub uncovered: BTreeSet<usize>,
+}
+
+/// The coverage of the source files read by a compilation, see the
+/// [module docs](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CoverageReport {
+    /// The coverage by the paths of the files, which are relative to the
+    /// root, or prefixed by the package specification for package files.
+    pub files: BTreeMap<String, FileCoverage>,
+}
+
+impl CoverageReport {
+    /// Write the report in the lcov format.
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for (path, file) in &self.files {
+            let _ = writeln!(lcov, "TN:\nSF:{path}");
+            let lines = (file.covered.iter().map(|line| (*line, 1)))
+                .chain(file.uncovered.iter().map(|line| (*line, 0)))
+                .collect::<BTreeMap<_, _>>();
+            for (line, hits) in &lines {
+                let _ = writeln!(lcov, "DA:{line},{hits}");
+            }
+            let _ = writeln!(lcov, "LF:{}", lines.len());
+            let _ = writeln!(lcov, "LH:{}", file.covered.len());
+            lcov.push_str("end_of_record\n");
+        }
+        lcov
+    }
+}
+
+/// Compute the coverage of the given source files by a document, see the
+/// [module docs](self).
+///
+/// The files producing content of the document are also included, even if
+/// they are not given.
+pub fn document_coverage(
+    world: &dyn World,
+    sources: impl IntoIterator<Item = FileId>,
+    doc: &TypstDocument,
+) -> CoverageReport {
+    // The token is never cancelled.
+    let report = document_coverage_cancellable(world, sources, doc, &CancellationToken::new());
+    report.unwrap_or_default()
+}
+
+/// Compute the coverage like [`document_coverage`], stopping before the next
+/// page or file once the token is cancelled, see [`super::cancel`].
+pub fn document_coverage_cancellable(
+    world: &dyn World,
+    sources: impl IntoIterator<Item = FileId>,
+    doc: &TypstDocument,
+    token: &CancellationToken,
+) -> ZResult<CoverageReport> {
+    let mut spans = HashSet::new();
+    for page in &doc.pages {
+        token.check()?;
+        collect_spans(&page.frame, &mut spans);
+    }
+
+    let mut spans_by_file: HashMap<FileId, Vec<Span>> = HashMap::new();
+    for span in spans {
+        if let Some(id) = span.id() {
+            spans_by_file.entry(id).or_default().push(span);
+        }
+    }
+    for id in sources {
+        spans_by_file.entry(id).or_default();
+    }
+
+    let mut report = CoverageReport::default();
+    for (id, spans) in spans_by_file {
+        token.check()?;
+        let Ok(source) = world.source(id) else {
+            continue;
+        };
+
+        let mut lines = BTreeSet::new();
+        code_lines(&source, source.root(), 0, &mut lines);
+        let mut covered = BTreeSet::new();
+        for span in spans {
+            let Some(node) = source.find(span) else {
+                continue;
+            };
+            covered.extend(line_range(&source, node.range()));
+
+            let mut parent = node.parent();
+            while let Some(node) = parent {
+                if matches!(node.kind(), SyntaxKind::Closure | SyntaxKind::ShowRule) {
+                    covered.extend(line_range(&source, node.range()));
+                }
+                parent = node.parent();
+            }
+        }
+
+        let uncovered = lines.difference(&covered).copied().collect();
+        let covered = covered.intersection(&lines).copied().collect();
+        report
+            .files
+            .insert(display_file_name(id), FileCoverage { covered, uncovered });
+    }
+    Ok(report)
+}
+
+/// Collect the spans of the content in a frame.
+fn collect_spans(frame: &Frame, spans: &mut HashSet<Span>) {
+    for (_, item) in frame.items() {
+        let span = match item {
+            FrameItem::Group(group) => {
+                collect_spans(&group.frame, spans);
+                continue;
+            }
+            FrameItem::Text(text) => {
+                spans.extend(text.glyphs.iter().map(|glyph| glyph.span.0));
+                continue;
+            }
+            FrameItem::Shape(_, span) | FrameItem::Image(_, _, span) => *span,
+            _ => continue,
+        };
+        spans.insert(span);
+    }
+}
+
+/// Collect the lines bearing code, i.e. the lines of the leaves except
+/// whitespace and comments.
+fn code_lines(source: &Source, node: &SyntaxNode, offset: usize, lines: &mut BTreeSet<usize>) {
+    if node.children().next().is_none() {
+        let trivia = matches!(
+            node.kind(),
+            SyntaxKind::Space
+                | SyntaxKind::Parbreak
+                | SyntaxKind::LineComment
+                | SyntaxKind::BlockComment
+        );
+        if !trivia {
+            lines.extend(line_range(source, offset..offset + node.len()));
+        }
+        return;
+    }
+
+    let mut offset = offset;
+    for child in node.children() {
+        code_lines(source, child, offset, lines);
+        offset += child.len();
+    }
+}
+
+/// Get the 1-based lines of a byte range.
+fn line_range(source: &Source, range: Range<usize>) -> impl Iterator<Item = usize> {
+    let last = range.end.saturating_sub(1).max(range.start);
+    let start = source.byte_to_line(range.start).unwrap_or_default();
+    let end = source.byte_to_line(last).unwrap_or(start);
+    start + 1..=end + 1
+}
@@ -0,0 +1,296 @@
+//! Explain the cyclic imports and includes of a document.
+//!
+//! Typst reports a cyclic import at the innermost import only, which doesn't
+//! name the files involved. When a compilation fails at an import, the
+//! imports with literal paths are followed from the entry to find a cycle, and
+//! the errors at the imports of the cycle are replaced by a single diagnostic
+//! listing the chain, e.g.
+//! `cycle: main.typ → chapters/a.typ → lib/b.typ → chapters/a.typ`.
+//!
+//! The diagnostic carries the import of each hop as a trace point, so that
+//! editors can link to every hop of the chain. Imports of packages are
+//! followed through the entrypoints of the packages.
+
+use std::{collections::HashSet, str::FromStr};
+
+use typst::{
+    diag::{SourceDiagnostic, Tracepoint},
+    syntax::{
+        ast::{self, AstNode},
+        package::PackageManifest,
+        FileId, Span, Spanned, SyntaxKind, SyntaxNode, VirtualPath,
+    },
+    World,
+};
+use typst_ts_core::{package::PackageSpec, typst::prelude::*};
+
+use crate::world::display_file_name;
+
+/// A cycle of imports reachable from an entry, see [`ImportCycle::find`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportCycle {
+    /// The files from the entry to the file closing the cycle, which is also
+    /// the first file of the cycle.
+    pub files: Vec<FileId>,
+    /// The imports of the hops, where `imports[i]` imports `files[i + 1]`
+    /// within `files[i]`.
+    pub imports: Vec<Span>,
+}
+
+impl ImportCycle {
+    /// Find the first cycle of the imports reachable from the entry in the
+    /// order of the sources, if any.
+    pub fn find(world: &dyn World, entry: FileId) -> Option<Self> {
+        let mut searcher = CycleSearcher {
+            world,
+            files: vec![entry],
+            imports: vec![],
+            visited: HashSet::new(),
+        };
+        searcher.visit(entry)
+    }
+
+    /// Explain the cycle by a single error at the import closing the cycle.
+    pub fn diagnostic(&self) -> SourceDiagnostic {
+        let chain: Vec<_> = self.files.iter().map(|&id| display_file_name(id)).collect();
+        let span = self.imports.last().copied().unwrap_or_else(Span::detached);
+        let mut diag = SourceDiagnostic::error(span, eco_format!("cycle: {}", chain.join(" → ")))
+            .with_hint("remove one of the imports or includes to break the cycle");
+        // The trace is ordered from the innermost point.
+        diag.trace = (self.imports.iter().rev())
+            .map(|&span| Spanned::new(Tracepoint::Import, span))
+            .collect();
+        diag
+    }
+}
+
+/// Replace the errors of cyclic imports by a single diagnostic of the cycle,
+/// see the [module docs](self).
+///
+/// The errors are kept if the cycle is not found, e.g. it is formed by
+/// imports of computed paths.
+pub fn explain_cycles(
+    world: &dyn World,
+    entry: FileId,
+    errors: EcoVec<SourceDiagnostic>,
+) -> EcoVec<SourceDiagnostic> {
+    // Only a failure at an import may be caused by a cycle, which saves
+    // walking the imports on other errors.
+    if !errors.iter().any(|diag| is_at_import(world, diag.span)) {
+        return errors;
+    }
+    let Some(cycle) = ImportCycle::find(world, entry) else {
+        return errors;
+    };
+
+    // Typst reports the cycle at the import closing it, which is an import of
+    // the cycle as each file of the cycle may be evaluated first.
+    let is_cyclic = |diag: &SourceDiagnostic| cycle.imports.contains(&diag.span);
+    if !errors.iter().any(is_cyclic) {
+        return errors;
+    }
+    let mut explained = eco_vec![cycle.diagnostic()];
+    explained.extend(errors.into_iter().filter(|diag| !is_cyclic(diag)));
+    explained
+}
+
+/// Whether a span is the path of an import or include.
+fn is_at_import(world: &dyn World, span: Span) -> bool {
+    let Some(source) = span.id().and_then(|id| world.source(id).ok()) else {
+        return false;
+    };
+    let node = source.find(span);
+    let parent = node.as_ref().and_then(|node| node.parent());
+    parent.is_some_and(|parent| {
+        matches!(
+            parent.kind(),
+            SyntaxKind::ModuleImport | SyntaxKind::ModuleInclude
+        )
+    })
+}
+
+struct CycleSearcher<'a> {
+    world: &'a dyn World,
+    /// The files on the path from the entry.
+    files: Vec<FileId>,
+    /// The imports on the path from the entry.
+    imports: Vec<Span>,
+    /// The files whose imports are known to be acyclic.
+    visited: HashSet<FileId>,
+}
+
+impl CycleSearcher<'_> {
+    fn visit(&mut self, id: FileId) -> Option<ImportCycle> {
+        let Ok(source) = self.world.source(id) else {
+            self.visited.insert(id);
+            return None;
+        };
+
+        let mut imports = vec![];
+        collect_imports(source.root(), &mut imports);
+        for (path, span) in imports {
+            let Some(target) = self.resolve(id, &path) else {
+                continue;
+            };
+
+            if self.files.contains(&target) {
+                let mut files = self.files.clone();
+                files.push(target);
+                let mut imports = self.imports.clone();
+                imports.push(span);
+                return Some(ImportCycle { files, imports });
+            }
+            if self.visited.contains(&target) {
+                continue;
+            }
+
+            self.files.push(target);
+            self.imports.push(span);
+            let cycle = self.visit(target);
+            self.files.pop();
+            self.imports.pop();
+            if cycle.is_some() {
+                return cycle;
+            }
+        }
+
+        self.visited.insert(id);
+        None
+    }
+
+    /// Resolve an import path within a file, where a package is resolved to
+    /// its entrypoint.
+    fn resolve(&self, within: FileId, path: &str) -> Option<FileId> {
+        if !path.starts_with('@') {
+            return Some(within.join(path));
+        }
+
+        let spec = PackageSpec::from_str(path).ok()?;
+        let manifest_id = FileId::new(Some(spec), VirtualPath::new("typst.toml"));
+        let manifest = self.world.file(manifest_id).ok()?;
+        let manifest = std::str::from_utf8(manifest.as_slice()).ok()?;
+        let manifest: PackageManifest = toml::from_str(manifest).ok()?;
+        Some(manifest_id.join(&manifest.package.entrypoint))
+    }
+}
+
+/// Collect the literal paths of the imports and includes in a syntax tree
+/// along with the spans of the paths.
+fn collect_imports(node: &SyntaxNode, imports: &mut Vec<(EcoString, Span)>) {
+    let source = node
+        .cast::<ast::ModuleImport>()
+        .map(|import| import.source())
+        .or_else(|| {
+            node.cast::<ast::ModuleInclude>()
+                .map(|include| include.source())
+        });
+    if let Some(ast::Expr::Str(path)) = source {
+        imports.push((path.get(), path.span()));
+    }
+
+    for child in node.children() {
+        collect_imports(child, imports);
+    }
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use std::sync::Arc;
+
+    use typst::foundations::Bytes;
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        package::memory::MemoryPackageResolver,
+        service::{CompileDriver, CompileEnv, Compiler},
+        ShadowApi,
+    };
+
+    #[test]
+    fn test_import_cycle() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let main = ws.path("main.typ");
+        let a = root.join("chapters/a.typ");
+        let b = root.join("lib/b.typ");
+        let mut driver = ws.driver();
+        let shadow = |driver: &mut CompileDriver, path: &std::path::Path, content: &str| {
+            let content = Bytes::from(content.as_bytes());
+            driver.map_shadow(path, content).unwrap();
+        };
+        shadow(&mut driver, &main, "= Main\n#include \"chapters/a.typ\"");
+        shadow(&mut driver, &a, "= A\n#include \"/lib/b.typ\"");
+        shadow(&mut driver, &b, "#import \"../chapters/a.typ\"\nB");
+
+        let errors = driver.compile(&mut CompileEnv::default()).unwrap_err();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(
+            errors[0].message,
+            "cycle: main.typ → chapters/a.typ → lib/b.typ → chapters/a.typ"
+        );
+        assert_eq!(errors[0].trace.len(), 3);
+        let file_of = |span: Span| span.id().map(display_file_name);
+        assert_eq!(file_of(errors[0].span).as_deref(), Some("lib/b.typ"));
+        assert_eq!(
+            file_of(errors[0].trace[2].span).as_deref(),
+            Some("main.typ")
+        );
+
+        // The compilation recovers once the cycle is broken.
+        shadow(&mut driver, &b, "B");
+        assert!(driver.compile(&mut CompileEnv::default()).is_ok());
+
+        // A file including itself is a cycle of a single hop.
+        shadow(&mut driver, &a, "= A\n#include \"a.typ\"");
+        let errors = driver.compile(&mut CompileEnv::default()).unwrap_err();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(
+            errors[0].message,
+            "cycle: main.typ → chapters/a.typ → chapters/a.typ"
+        );
+    }
+
+    #[test]
+    fn test_package_import_cycle() {
+        let spec: PackageSpec = "@preview/example:0.1.0".parse().unwrap();
+        // The entrypoint is only known from the manifest.
+        let manifest = br#"[package]
+name = "example"
+version = "0.1.0"
+entrypoint = "src/lib.typ"
+authors = ["typst.ts"]
+license = "Apache-2.0"
+description = "A package with a cyclic import."
+"#;
+        let resolver = MemoryPackageResolver::default()
+            .with_file(spec.clone(), "typst.toml", Bytes::from_static(manifest))
+            .with_file(
+                spec.clone(),
+                "src/lib.typ",
+                Bytes::from_static(b"#import \"helper.typ\": *\n#let greet() = [Hello]"),
+            )
+            .with_file(
+                spec.clone(),
+                "src/helper.typ",
+                Bytes::from_static(b"#import \"lib.typ\": greet"),
+            );
+
+        let ws = TestWorkspace::new();
+        let main = ws.path("main.typ");
+        let mut world = ws.world();
+        world.set_package_resolver(Some(Arc::new(resolver)));
+        let mut driver = CompileDriver::new(world).with_entry_file(main.clone());
+        let content = Bytes::from_static(b"#import \"@preview/example:0.1.0\": greet\n#greet()");
+        driver.map_shadow(&main, content).unwrap();
+
+        let errors = driver.compile(&mut CompileEnv::default()).unwrap_err();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(
+            errors[0].message,
+            "cycle: main.typ → @preview/example:0.1.0/src/lib.typ → \
+             @preview/example:0.1.0/src/helper.typ → @preview/example:0.1.0/src/lib.typ"
+        );
+        assert_eq!(errors[0].trace.len(), 3);
+    }
+}
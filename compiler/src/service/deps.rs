@@ -0,0 +1,610 @@
+//! Export the dependency graph of a compiled document.
+//!
+//! The nodes are the files read by the latest compilation, grouped by their
+//! packages, and the fonts used by the document. Edges from an importer to
+//! the imported file are recovered from the syntax trees, e.g. `#import
+//! "a.typ"`, `#include "b.typ"` and `#image("c.png")`. The files which are
+//! referred by computed paths are only known to be members of the document,
+//! or of the package including them.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Serialize;
+use typst::{
+    diag::{SourceDiagnostic, SourceResult},
+    layout::{Frame, FrameItem},
+    syntax::{ast, Span, SyntaxNode, VirtualPath},
+    text::Font,
+    World,
+};
+use typst_ts_core::{
+    debug_loc::DataSource, hash::hash128, output::write_atomic, package::PackageSpec,
+    typst::prelude::*, FontResolver, ImmutPath, TypstDocument, TypstFileId as FileId,
+};
+
+use super::{CompileEnv, CompileMeta, CompileMiddleware, Compiler, EntryManager, WorldExporter};
+use crate::world::{is_pseudo_package, CompilerFeat, CompilerWorld};
+
+/// The functions loading a file by their first argument.
+const LOADERS: &[&str] = &[
+    "bibliography",
+    "cbor",
+    "csv",
+    "image",
+    "json",
+    "plugin",
+    "read",
+    "toml",
+    "xml",
+    "yaml",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DepNodeKind {
+    Source,
+    Package,
+    Font,
+    Asset,
+}
+
+/// A node of the [`DepGraph`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepNode {
+    pub kind: DepNodeKind,
+    /// The path relative to the workspace root, the path in the package, the
+    /// package specification, or the font family.
+    pub name: String,
+    /// The path on disk, if the node is a file on disk.
+    pub path: Option<PathBuf>,
+    /// The index of the package node including a package file.
+    pub package: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DepEdgeKind {
+    Import,
+    Include,
+    /// Loaded by a function, e.g. `image` and `read`.
+    Load,
+    /// The reference is not recoverable from the syntax trees.
+    Member,
+}
+
+/// An edge of the [`DepGraph`], from the dependent node to the dependency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepEdge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: DepEdgeKind,
+}
+
+/// The dependency graph of a document, see [`dep_graph`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DepGraph {
+    /// The nodes, where the first node is the entry file if any.
+    pub nodes: Vec<DepNode>,
+    pub edges: Vec<DepEdge>,
+}
+
+/// The format of the exported dependency graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DepGraphFormat {
+    /// The depfile format of Makefiles, listing the files on disk as the
+    /// prerequisites of the targets, e.g. `out.pdf: main.typ`.
+    Make {
+        targets: Vec<PathBuf>,
+    },
+    Json,
+    /// The dot language of Graphviz.
+    Dot,
+}
+
+impl DepGraph {
+    /// Get the paths of the files on disk, including fonts.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.nodes.iter().filter_map(|node| node.path.as_deref())
+    }
+
+    /// Render the graph in the given format.
+    pub fn render(&self, format: &DepGraphFormat) -> String {
+        match format {
+            DepGraphFormat::Make { targets } => self.to_makefile(targets),
+            DepGraphFormat::Json => serde_json::to_string_pretty(self).unwrap(),
+            DepGraphFormat::Dot => self.to_dot(),
+        }
+    }
+
+    fn to_makefile(&self, targets: &[PathBuf]) -> String {
+        let targets: Vec<_> = targets.iter().map(|t| escape_make(t)).collect();
+        let mut out = targets.join(" ");
+        out.push(':');
+        for path in self.files() {
+            out.push_str(" \\\n  ");
+            out.push_str(&escape_make(path));
+        }
+        out.push('\n');
+        out
+    }
+
+    fn to_dot(&self) -> String {
+        let mut out = String::from("digraph deps {\n  node [shape=box];\n");
+        let node = |out: &mut String, indent: &str, idx: usize| {
+            let node = &self.nodes[idx];
+            let shape = match node.kind {
+                DepNodeKind::Source => "",
+                DepNodeKind::Package => ", shape=folder",
+                DepNodeKind::Font => ", shape=ellipse",
+                DepNodeKind::Asset => ", shape=note",
+            };
+            let _ = writeln!(
+                out,
+                "{indent}n{idx} [label={}{shape}];",
+                quote_dot(&node.name)
+            );
+        };
+
+        for (idx, package) in self.nodes.iter().enumerate() {
+            match package.kind {
+                // Group the files in a package.
+                DepNodeKind::Package => {
+                    let _ = writeln!(out, "  subgraph cluster_{idx} {{");
+                    let _ = writeln!(out, "    label={};", quote_dot(&package.name));
+                    node(&mut out, "    ", idx);
+                    let members = self.nodes.iter().enumerate();
+                    for (member, _) in members.filter(|(_, n)| n.package == Some(idx)) {
+                        node(&mut out, "    ", member);
+                    }
+                    out.push_str("  }\n");
+                }
+                _ if package.package.is_none() => node(&mut out, "  ", idx),
+                _ => {}
+            }
+        }
+
+        for edge in &self.edges {
+            let style = match edge.kind {
+                DepEdgeKind::Member => " [style=dashed]",
+                _ => "",
+            };
+            let _ = writeln!(out, "  n{} -> n{}{style};", edge.from, edge.to);
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Escape a path as a target or a prerequisite of a Makefile rule.
+fn escape_make(path: &Path) -> String {
+    let mut out = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' | '#' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '$' => out.push_str("$$"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn quote_dot(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Build the dependency graph of the latest compilation of the world.
+///
+/// The fonts are only known if the compiled document is given.
+pub fn dep_graph<F: CompilerFeat>(
+    world: &CompilerWorld<F>,
+    doc: Option<&TypstDocument>,
+) -> DepGraph {
+    let deps: HashSet<ImmutPath> = world
+        .vfs
+        .iter_dependencies()
+        .map(|(p, _)| p.clone())
+        .collect();
+    let mut builder = DepGraphBuilder {
+        world,
+        deps,
+        graph: DepGraph::default(),
+        files: HashMap::new(),
+        packages: HashMap::new(),
+        queue: VecDeque::new(),
+    };
+    builder.build(doc);
+    builder.graph
+}
+
+struct DepGraphBuilder<'a, F: CompilerFeat> {
+    world: &'a CompilerWorld<F>,
+    /// The files read by the latest compilation.
+    deps: HashSet<ImmutPath>,
+    graph: DepGraph,
+    /// The nodes of files by their paths.
+    files: HashMap<PathBuf, usize>,
+    packages: HashMap<PackageSpec, usize>,
+    /// The sources whose references are not collected yet.
+    queue: VecDeque<(FileId, usize)>,
+}
+
+impl<'a, F: CompilerFeat> DepGraphBuilder<'a, F> {
+    fn build(&mut self, doc: Option<&TypstDocument>) {
+        let Some(main) = self.world.main_id() else {
+            return;
+        };
+        let Some(main_node) = self.file_node(main, None) else {
+            return;
+        };
+
+        while let Some((id, from)) = self.queue.pop_front() {
+            let Ok(source) = self.world.source(id) else {
+                continue;
+            };
+            let mut refs = vec![];
+            collect_references(source.root(), &mut refs);
+
+            for (kind, path) in refs {
+                let to = if path.starts_with('@') {
+                    let spec = path.parse::<PackageSpec>().ok();
+                    spec.map(|spec| self.package_node(spec))
+                } else {
+                    self.file_node(id.join(&path), id.package().cloned())
+                };
+                if let Some(to) = to {
+                    self.edge(from, to, kind);
+                }
+            }
+        }
+
+        // The files referred by computed paths.
+        let mut rest: Vec<_> = (self.deps.iter())
+            .filter(|path| !self.files.contains_key::<Path>(path))
+            .cloned()
+            .collect();
+        rest.sort();
+        for path in rest {
+            let node = self.add_node(DepNode {
+                kind: file_kind(&path),
+                name: self.file_name(&path),
+                path: Some(path.to_path_buf()),
+                package: None,
+            });
+            self.files.insert(path.to_path_buf(), node);
+            self.edge(main_node, node, DepEdgeKind::Member);
+        }
+
+        if let Some(doc) = doc {
+            self.font_nodes(main_node, doc);
+        }
+    }
+
+    /// Get the node of a file read by the compilation, adding it if not
+    /// exists.
+    fn file_node(&mut self, id: FileId, package: Option<PackageSpec>) -> Option<usize> {
+        let path = self.world.path_mapper().vfs_path(id).ok()?;
+        if let Some(node) = self.files.get(&path) {
+            return Some(*node);
+        }
+        if !self.deps.contains(path.as_path()) {
+            return None;
+        }
+
+        let package = package.filter(|spec| !is_pseudo_package(spec));
+        let (name, package) = match package {
+            Some(spec) => {
+                let name = id.vpath().as_rootless_path().to_string_lossy().into();
+                (name, Some(self.package_node(spec)))
+            }
+            None => (self.file_name(&path), None),
+        };
+
+        let kind = file_kind(&path);
+        let node = self.add_node(DepNode {
+            kind,
+            name,
+            path: Some(path.clone()),
+            package,
+        });
+        self.files.insert(path, node);
+        if kind == DepNodeKind::Source {
+            self.queue.push_back((id, node));
+        }
+        Some(node)
+    }
+
+    /// Get the node of a package, adding it with the files read in the
+    /// package if not exists.
+    fn package_node(&mut self, spec: PackageSpec) -> usize {
+        if let Some(node) = self.packages.get(&spec) {
+            return *node;
+        }
+
+        let node = self.add_node(DepNode {
+            kind: DepNodeKind::Package,
+            name: spec.to_string(),
+            path: None,
+            package: None,
+        });
+        self.packages.insert(spec.clone(), node);
+
+        let root_id = FileId::new(Some(spec.clone()), VirtualPath::new(""));
+        let Ok(root) = self.world.path_mapper().vfs_path(root_id) else {
+            return node;
+        };
+        let mut members: Vec<_> = (self.deps.iter())
+            .filter_map(|path| Some((VirtualPath::within_root(path, &root)?, path.clone())))
+            .collect();
+        members.sort_by(|a, b| a.1.cmp(&b.1));
+        for (vpath, _) in members {
+            let id = FileId::new(Some(spec.clone()), vpath);
+            if let Some(member) = self.file_node(id, Some(spec.clone())) {
+                self.edge(node, member, DepEdgeKind::Member);
+            }
+        }
+
+        node
+    }
+
+    fn font_nodes(&mut self, main_node: usize, doc: &TypstDocument) {
+        let mut fonts = vec![];
+        for page in &doc.pages {
+            collect_fonts(&page.frame, &mut fonts);
+        }
+
+        let mut seen = HashSet::new();
+        for font in fonts.into_iter().filter(|font| seen.insert(hash128(font))) {
+            let source = self.world.font_resolver.describe_font(&font);
+            let path = match source.as_deref() {
+                Some(DataSource::Fs(source)) => Some(PathBuf::from(&source.path)),
+                _ => None,
+            };
+            let name = (path.as_ref()).map_or_else(
+                || font.info().family.clone(),
+                |path| path.to_string_lossy().into(),
+            );
+            let node = self.add_node(DepNode {
+                kind: DepNodeKind::Font,
+                name,
+                path,
+                package: None,
+            });
+            self.edge(main_node, node, DepEdgeKind::Member);
+        }
+    }
+
+    /// The path relative to the workspace root if it is in the root.
+    fn file_name(&self, path: &Path) -> String {
+        let root = self.world.entry.root();
+        let rel = root.and_then(|root| path.strip_prefix(root).ok());
+        rel.unwrap_or(path).to_string_lossy().into()
+    }
+
+    fn add_node(&mut self, node: DepNode) -> usize {
+        self.graph.nodes.push(node);
+        self.graph.nodes.len() - 1
+    }
+
+    fn edge(&mut self, from: usize, to: usize, kind: DepEdgeKind) {
+        let edge = DepEdge { from, to, kind };
+        if from != to && !self.graph.edges.contains(&edge) {
+            self.graph.edges.push(edge);
+        }
+    }
+}
+
+fn file_kind(path: &Path) -> DepNodeKind {
+    match path.extension() {
+        Some(ext) if ext == "typ" => DepNodeKind::Source,
+        _ => DepNodeKind::Asset,
+    }
+}
+
+/// Collect the paths referred by string literals in a syntax tree.
+fn collect_references(node: &SyntaxNode, refs: &mut Vec<(DepEdgeKind, EcoString)>) {
+    let reference = if let Some(import) = node.cast::<ast::ModuleImport>() {
+        Some((DepEdgeKind::Import, import.source()))
+    } else if let Some(include) = node.cast::<ast::ModuleInclude>() {
+        Some((DepEdgeKind::Include, include.source()))
+    } else if let Some(call) = node.cast::<ast::FuncCall>() {
+        let is_loader = match call.callee() {
+            ast::Expr::Ident(callee) => LOADERS.contains(&callee.as_str()),
+            _ => false,
+        };
+        let first = call.args().items().next();
+        match first {
+            Some(ast::Arg::Pos(expr)) if is_loader => Some((DepEdgeKind::Load, expr)),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    if let Some((kind, ast::Expr::Str(path))) = reference {
+        refs.push((kind, path.get()));
+    }
+
+    for child in node.children() {
+        collect_references(child, refs);
+    }
+}
+
+/// Collect the importers of the files referred by the sources reachable from
+/// the main file, i.e. the reversed edges of the graph without packages.
+///
+/// The paths of the files are resolved by `path_of`, e.g. by
+/// [`crate::ShadowApi::_shadow_map_id`].
+pub(crate) fn importers(
+    world: &dyn World,
+    main: FileId,
+    path_of: impl Fn(FileId) -> Option<PathBuf>,
+) -> HashMap<PathBuf, Vec<PathBuf>> {
+    let mut importers: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    let mut seen = HashSet::from([main]);
+    let mut queue = VecDeque::from([main]);
+    while let Some(id) = queue.pop_front() {
+        let (Ok(source), Some(from)) = (world.source(id), path_of(id)) else {
+            continue;
+        };
+        let mut refs = vec![];
+        collect_references(source.root(), &mut refs);
+
+        for (_, path) in refs.into_iter().filter(|(_, path)| !path.starts_with('@')) {
+            let dep = id.join(&path);
+            let Some(to) = path_of(dep) else {
+                continue;
+            };
+            if file_kind(&to) == DepNodeKind::Source && seen.insert(dep) {
+                queue.push_back(dep);
+            }
+            importers.entry(to).or_default().push(from.clone());
+        }
+    }
+    importers
+}
+
+pub(crate) fn collect_fonts(frame: &Frame, fonts: &mut Vec<Font>) {
+    for (_, item) in frame.items() {
+        match item {
+            FrameItem::Group(group) => collect_fonts(&group.frame, fonts),
+            FrameItem::Text(text) => fonts.push(text.font.clone()),
+            _ => {}
+        }
+    }
+}
+
+/// Writes the dependency graph to a file after each successful compilation,
+/// see [`dep_graph`].
+pub struct DepGraphExporter<C: Compiler> {
+    pub compiler: C,
+    /// The file to write, or `None` to disable the exporter.
+    output: Option<PathBuf>,
+    format: DepGraphFormat,
+}
+
+impl<C: Compiler> DepGraphExporter<C> {
+    pub fn new(compiler: C, output: Option<PathBuf>, format: DepGraphFormat) -> Self {
+        Self {
+            compiler,
+            output,
+            format,
+        }
+    }
+}
+
+impl<F: CompilerFeat, C: Compiler<World = CompilerWorld<F>>> DepGraphExporter<C> {
+    fn write_deps(&self, doc: &TypstDocument) -> SourceResult<()> {
+        let Some(output) = &self.output else {
+            return Ok(());
+        };
+
+        let graph = dep_graph(self.compiler.world(), Some(doc));
+        write_atomic(output, graph.render(&self.format)).map_err(|err| {
+            eco_vec![SourceDiagnostic::error(
+                Span::detached(),
+                eco_format!("failed to write dependencies: {err}"),
+            )]
+        })
+    }
+}
+
+impl<F: CompilerFeat, C: Compiler<World = CompilerWorld<F>> + WorldExporter> WorldExporter
+    for DepGraphExporter<C>
+{
+    fn export(&mut self, output: Arc<TypstDocument>, meta: &CompileMeta) -> SourceResult<()> {
+        self.compiler.export(output.clone(), meta)?;
+        self.write_deps(&output)
+    }
+}
+
+impl<F: CompilerFeat, C: Compiler<World = CompilerWorld<F>>> CompileMiddleware
+    for DepGraphExporter<C>
+{
+    type Compiler = C;
+
+    fn inner(&self) -> &Self::Compiler {
+        &self.compiler
+    }
+
+    fn inner_mut(&mut self) -> &mut Self::Compiler {
+        &mut self.compiler
+    }
+
+    fn wrap_compile(&mut self, env: &mut CompileEnv) -> SourceResult<Arc<TypstDocument>> {
+        let doc = self.inner_mut().compile(env)?;
+        self.write_deps(&doc)?;
+
+        Ok(doc)
+    }
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use super::*;
+    use crate::fixture::TestWorkspace;
+
+    #[test]
+    fn test_dep_graph() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let main = ws.path("main.typ");
+        std::fs::create_dir_all(root.join("chapters")).unwrap();
+        std::fs::write(
+            &main,
+            "#import \"template.typ\": title\n#title\n#include \"chapters/intro.typ\"\n\
+             #let name = \"computed.typ\"\n#include name",
+        )
+        .unwrap();
+        ws.write("template.typ", "#let title = [Title]");
+        std::fs::write(
+            root.join("chapters/intro.typ"),
+            "#read(\"../data file.txt\")",
+        )
+        .unwrap();
+        ws.write("data file.txt", "Data");
+        ws.write("computed.typ", "Computed");
+
+        let mut driver = ws.driver();
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+        let graph = dep_graph(driver.world(), Some(&doc));
+        let node = |name: &str| {
+            let node = graph.nodes.iter().position(|n| n.name == name);
+            node.unwrap_or_else(|| panic!("{name} not found in {graph:?}"))
+        };
+        let edge = |from: &str, to: &str| {
+            let (from, to) = (node(from), node(to));
+            let edge = graph.edges.iter().find(|e| e.from == from && e.to == to);
+            edge.map(|e| e.kind)
+        };
+
+        assert_eq!(node("main.typ"), 0);
+        assert_eq!(edge("main.typ", "template.typ"), Some(DepEdgeKind::Import));
+        let intro = "chapters/intro.typ";
+        assert_eq!(edge("main.typ", intro), Some(DepEdgeKind::Include));
+        assert_eq!(edge(intro, "data file.txt"), Some(DepEdgeKind::Load));
+        assert_eq!(edge("main.typ", "computed.typ"), Some(DepEdgeKind::Member));
+        assert!(graph.nodes.iter().any(|n| n.kind == DepNodeKind::Font));
+
+        let targets = vec![root.join("out dir/main.pdf")];
+        let depfile = graph.render(&DepGraphFormat::Make { targets });
+        let (targets, prerequisites) = depfile.split_once(':').unwrap();
+        assert!(targets.ends_with("out\\ dir/main.pdf"), "{depfile}");
+        assert!(prerequisites.contains("data\\ file.txt \\\n"), "{depfile}");
+        // Embedded fonts are not files on disk.
+        assert_eq!(prerequisites.matches(" \\\n  ").count(), 5, "{depfile}");
+
+        let dot = graph.render(&DepGraphFormat::Dot);
+        assert!(dot.contains("n0 -> n1;"), "{dot}");
+    }
+}
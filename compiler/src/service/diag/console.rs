@@ -10,20 +10,19 @@ use codespan_reporting::{
     },
 };
 
-use typst::diag::{Severity, SourceResult};
-use typst::syntax::Span;
-use typst::WorldExt;
-use typst::{diag::SourceDiagnostic, World};
+use typst::diag::SourceResult;
+use typst::World;
 
 use typst::diag::eco_format;
-use typst_ts_core::{typst::prelude::*, GenericExporter, PhantomParamData, TakeAs, TypstFileId};
+use typst_ts_core::{GenericExporter, PhantomParamData, TakeAs, TypstFileId};
 
 use crate::service::features::{
-    CompileFeature, FeatureSet, DIAG_FMT_FEATURE, WITH_COMPILING_STATUS_FEATURE,
+    CompileFeature, FeatureSet, DIAG_FMT_FEATURE, DIFF_DIAGNOSTICS_FEATURE,
+    WITH_COMPILING_STATUS_FEATURE,
 };
 use crate::service::CompileReport;
 
-use super::DiagnosticFormat;
+use super::{DiagnosticFormat, DiagnosticLocation, DiagnosticSeverity, PositionEncoding};
 
 /// Get stderr with color support if desirable.
 fn color_stream() -> StandardStream {
@@ -37,7 +36,7 @@ fn color_stream() -> StandardStream {
 /// Print diagnostic messages to the terminal.
 fn print_diagnostics<'files, W: World + Files<'files, FileId = TypstFileId>>(
     world: &'files W,
-    errors: EcoVec<SourceDiagnostic>,
+    diagnostics: &[super::SerializableDiagnostic],
     diagnostic_format: DiagnosticFormat,
 ) -> Result<(), codespan_reporting::files::Error> {
     let mut w = match diagnostic_format {
@@ -53,10 +52,10 @@ fn print_diagnostics<'files, W: World + Files<'files, FileId = TypstFileId>>(
         config.display_style = term::DisplayStyle::Short;
     }
 
-    for diagnostic in errors {
+    for diagnostic in diagnostics {
         let diag = match diagnostic.severity {
-            Severity::Error => Diagnostic::error(),
-            Severity::Warning => Diagnostic::warning(),
+            DiagnosticSeverity::Error => Diagnostic::error(),
+            DiagnosticSeverity::Warning => Diagnostic::warning(),
         }
         .with_message(diagnostic.message.clone())
         .with_notes(
@@ -66,16 +65,15 @@ fn print_diagnostics<'files, W: World + Files<'files, FileId = TypstFileId>>(
                 .map(|e| (eco_format!("hint: {e}")).into())
                 .collect(),
         )
-        .with_labels(label(world, diagnostic.span).into_iter().collect());
+        .with_labels(label(&diagnostic.location).into_iter().collect());
 
         term::emit(&mut w, &config, world, &diag)?;
 
         // Stacktrace-like helper diagnostics.
-        for point in diagnostic.trace {
-            let message = point.v.to_string();
+        for point in &diagnostic.trace {
             let help = Diagnostic::help()
-                .with_message(message)
-                .with_labels(label(world, point.span).into_iter().collect());
+                .with_message(point.message.clone())
+                .with_labels(label(&point.location).into_iter().collect());
 
             term::emit(&mut w, &config, world, &help)?;
         }
@@ -84,12 +82,9 @@ fn print_diagnostics<'files, W: World + Files<'files, FileId = TypstFileId>>(
     Ok(())
 }
 
-/// Create a label for a span.
-fn label<'files, W: World + Files<'files, FileId = TypstFileId>>(
-    world: &'files W,
-    span: Span,
-) -> Option<Label<TypstFileId>> {
-    Some(Label::primary(span.id()?, world.range(span)?))
+/// Create a label for a location.
+fn label(location: &DiagnosticLocation) -> Option<Label<TypstFileId>> {
+    Some(Label::primary(location.id?, location.range.clone()?))
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -128,12 +123,36 @@ where
     ) -> SourceResult<()> {
         let (features, report) = output.take();
 
-        if WITH_COMPILING_STATUS_FEATURE.retrieve(&features) {
+        if let CompileReport::Diagnostics(_, diags) = &report {
+            // Summarize the diagnostics which are printed before.
+            if diags.summary().is_some() {
+                log::info!("{}", report.message());
+            }
+        } else if WITH_COMPILING_STATUS_FEATURE.retrieve(&features) {
             log::info!("{}", report.message());
         }
 
-        if let Some(diag) = report.diagnostics() {
-            let _err = print_diagnostics(world, diag, DIAG_FMT_FEATURE.retrieve(&features));
+        let diag = match report {
+            // Only render the diagnostics which are not printed before.
+            CompileReport::Diagnostics(_, report) => Some(report.added),
+            _ if DIFF_DIAGNOSTICS_FEATURE.retrieve(&features) => None,
+            report => report.diagnostics(),
+        };
+
+        if let Some(diag) = diag {
+            // Revisions are not tracked by reporters.
+            let diag: Vec<_> = diag
+                .iter()
+                .map(|diag| {
+                    super::SerializableDiagnostic::from_source(
+                        world,
+                        diag,
+                        0,
+                        PositionEncoding::Utf8,
+                    )
+                })
+                .collect();
+            let _err = print_diagnostics(world, &diag, DIAG_FMT_FEATURE.retrieve(&features));
             // todo: log in browser compiler
             #[cfg(feature = "system-compile")]
             if _err.is_err() {
@@ -1,3 +1,14 @@
+use std::{collections::HashMap, ops::Range};
+
+use codespan_reporting::files::Files;
+use serde::{Deserialize, Serialize};
+use typst::diag::{Severity, SourceDiagnostic};
+use typst::syntax::Span;
+use typst::{World, WorldExt};
+use typst_ts_core::{typst::prelude::*, TypstFileId};
+
+use super::position::to_lsp_range;
+
 // todo: remove cfg feature here
 #[cfg(feature = "system-compile")]
 mod console;
@@ -16,3 +27,372 @@ impl Default for DiagnosticFormat {
         Self::Human
     }
 }
+
+/// The severity of a [`SerializableDiagnostic`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+impl DiagnosticSeverity {
+    /// Whether the severity is at least as severe as `min`, i.e. errors are
+    /// above warnings.
+    pub fn is_at_least(self, min: Self) -> bool {
+        matches!(
+            (self, min),
+            (Self::Error, _) | (Self::Warning, Self::Warning)
+        )
+    }
+}
+
+impl From<Severity> for DiagnosticSeverity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Error => Self::Error,
+            Severity::Warning => Self::Warning,
+        }
+    }
+}
+
+/// The unit of columns in a [`DiagnosticPosition`].
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PositionEncoding {
+    /// Columns are counted in bytes.
+    Utf8,
+    /// Columns are counted in UTF-16 code units, as in the language server
+    /// protocol and JavaScript strings.
+    #[default]
+    Utf16,
+    /// Columns are counted in unicode scalar values.
+    Utf32,
+}
+
+/// A 0-based position in a file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticPosition {
+    pub line: usize,
+    /// The column in the unit of the [`PositionEncoding`] of the diagnostic.
+    pub column: usize,
+}
+
+/// The location of a [`SerializableDiagnostic`] or of a point in its trace.
+///
+/// The range is `None` if the span is detached or the file cannot be read,
+/// e.g. a package which is not downloaded.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticLocation {
+    /// The file where the diagnostic occurs, which is not serialized.
+    #[serde(skip)]
+    pub id: Option<TypstFileId>,
+    /// The user-facing path of the file.
+    pub path: Option<String>,
+    /// The byte range in the file.
+    pub range: Option<Range<usize>>,
+    pub start: Option<DiagnosticPosition>,
+    pub end: Option<DiagnosticPosition>,
+}
+
+impl DiagnosticLocation {
+    /// Resolve a span with the given world.
+    pub fn from_span<W>(world: &W, span: Span, encoding: PositionEncoding) -> Self
+    where
+        W: World + for<'files> Files<'files, FileId = TypstFileId>,
+    {
+        let Some(id) = span.id() else {
+            return Self::default();
+        };
+
+        let range = world.range(span);
+        let source = World::source(world, id).ok();
+        let positions = source
+            .as_ref()
+            .zip(range.clone())
+            .map(|(source, range)| to_lsp_range(source, range, encoding));
+
+        Self {
+            id: Some(id),
+            path: world.name(id).ok().map(|name| name.to_string()),
+            start: positions.map(|range| range.start),
+            end: positions.map(|range| range.end),
+            range,
+        }
+    }
+
+    /// Convert the positions into another encoding, resolving the byte range
+    /// against the current sources of the given world.
+    pub fn reencode<W: World>(&mut self, world: &W, encoding: PositionEncoding) {
+        let source = self.id.and_then(|id| World::source(world, id).ok());
+        let positions = source
+            .as_ref()
+            .zip(self.range.clone())
+            .map(|(source, range)| to_lsp_range(source, range, encoding));
+
+        self.start = positions.map(|range| range.start);
+        self.end = positions.map(|range| range.end);
+    }
+}
+
+/// A point in the trace of a [`SerializableDiagnostic`], e.g. a function call which
+/// leads to the error.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticTracePoint {
+    pub message: String,
+    pub location: DiagnosticLocation,
+}
+
+/// A diagnostic resolved against the world it was produced in, which can be
+/// passed across process or FFI boundaries.
+///
+/// Unlike [`SourceDiagnostic`], whose spans are only meaningful along with
+/// the sources of a compilation, it carries file paths and positions, so
+/// consumers such as language servers and web frontends are able to use it
+/// without access to the world.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SerializableDiagnostic {
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    pub location: DiagnosticLocation,
+    pub hints: Vec<String>,
+    /// The trace of the diagnostic, from the innermost point.
+    pub trace: Vec<DiagnosticTracePoint>,
+    /// The unit of columns in the positions.
+    pub encoding: PositionEncoding,
+    /// The revision of the compilation producing the diagnostic.
+    pub revision: usize,
+}
+
+impl SerializableDiagnostic {
+    /// Convert a diagnostic, resolving its spans with the given world.
+    pub fn from_source<W>(
+        world: &W,
+        diag: &SourceDiagnostic,
+        revision: usize,
+        encoding: PositionEncoding,
+    ) -> Self
+    where
+        W: World + for<'files> Files<'files, FileId = TypstFileId>,
+    {
+        Self {
+            severity: diag.severity.into(),
+            message: diag.message.to_string(),
+            location: DiagnosticLocation::from_span(world, diag.span, encoding),
+            hints: diag.hints.iter().map(|hint| hint.to_string()).collect(),
+            trace: diag
+                .trace
+                .iter()
+                .map(|point| DiagnosticTracePoint {
+                    message: point.v.to_string(),
+                    location: DiagnosticLocation::from_span(world, point.span, encoding),
+                })
+                .collect(),
+            encoding,
+            revision,
+        }
+    }
+
+    /// Convert the positions into another encoding, see
+    /// [`DiagnosticLocation::reencode`].
+    pub fn reencode<W: World>(&mut self, world: &W, encoding: PositionEncoding) {
+        if self.encoding == encoding {
+            return;
+        }
+
+        self.location.reencode(world, encoding);
+        for point in &mut self.trace {
+            point.location.reencode(world, encoding);
+        }
+        self.encoding = encoding;
+    }
+}
+
+/// Identifies a diagnostic across compilations.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DiagnosticKey {
+    id: Option<TypstFileId>,
+    range: Option<Range<usize>>,
+    message: EcoString,
+}
+
+/// The diagnostics of a compilation, along with their changes since the
+/// previous compilation.
+///
+/// It is reported after every compilation when the
+/// [`DIFF_DIAGNOSTICS_FEATURE`](super::features::DIFF_DIAGNOSTICS_FEATURE)
+/// is enabled, even if there is no diagnostic, so that consumers are able to
+/// clear their diagnostics once they are all fixed.
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticsReport {
+    /// The revision of the compilation.
+    pub revision: usize,
+    /// All diagnostics of the compilation.
+    pub diagnostics: EcoVec<SourceDiagnostic>,
+    /// Diagnostics which did not occur in the previous compilation.
+    pub added: EcoVec<SourceDiagnostic>,
+    /// The number of diagnostics of the previous compilation which still
+    /// occur.
+    pub unchanged: usize,
+    /// The number of diagnostics of the previous compilation which are gone.
+    pub resolved: usize,
+}
+
+impl DiagnosticsReport {
+    /// Summarize the diagnostics which are not added, or get `None` if there
+    /// was no diagnostic in the previous compilation.
+    pub fn summary(&self) -> Option<String> {
+        if self.unchanged == 0 && self.resolved == 0 {
+            return None;
+        }
+
+        Some(format!(
+            "{} previous diagnostic(s) unchanged, {} fixed",
+            self.unchanged, self.resolved
+        ))
+    }
+}
+
+/// Tracks diagnostics across compilations, by their files, ranges and
+/// messages.
+#[derive(Debug, Default)]
+pub struct DiagnosticsTracker {
+    /// Diagnostics of the previous compilation, with their number of
+    /// occurrences.
+    previous: HashMap<DiagnosticKey, usize>,
+}
+
+impl DiagnosticsTracker {
+    /// Compare diagnostics of a compilation with the previous ones, and
+    /// remember them for the next compilation.
+    pub fn update<W: World>(
+        &mut self,
+        world: &W,
+        revision: usize,
+        diagnostics: EcoVec<SourceDiagnostic>,
+    ) -> DiagnosticsReport {
+        let mut previous = std::mem::take(&mut self.previous);
+        let mut added = EcoVec::new();
+        let mut unchanged = 0;
+
+        for diag in diagnostics.iter() {
+            let key = DiagnosticKey {
+                id: diag.span.id(),
+                range: world.range(diag.span),
+                message: diag.message.clone(),
+            };
+
+            match previous.get_mut(&key) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    unchanged += 1;
+                }
+                _ => added.push(diag.clone()),
+            }
+            *self.previous.entry(key).or_default() += 1;
+        }
+
+        DiagnosticsReport {
+            revision,
+            diagnostics,
+            added,
+            unchanged,
+            resolved: previous.values().sum(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use typst::{eval::Tracer, foundations::Bytes};
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileEnv, EnvWorld},
+        ShadowApi,
+    };
+
+    #[test]
+    fn test_serialize_diagnostic() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let mut world = ws.world();
+        world
+            .map_shadow(
+                &root.join("main.typ"),
+                Bytes::from_static(b"Hello\n#unknown"),
+            )
+            .unwrap();
+        world.prepare_env(&mut CompileEnv::default()).unwrap();
+
+        let errors = typst::compile(&world, &mut Tracer::new()).unwrap_err();
+        let diags: Vec<_> = errors
+            .iter()
+            .map(|diag| {
+                SerializableDiagnostic::from_source(&world, diag, 0, PositionEncoding::Utf16)
+            })
+            .collect();
+        let json = serde_json::to_value(&diags).unwrap();
+
+        let diag = &json[0];
+        assert_eq!(diag["severity"], "error");
+        assert_eq!(diag["message"], "unknown variable: unknown");
+        assert_eq!(diag["encoding"], "utf16");
+        let location = &diag["location"];
+        assert!(location["path"].as_str().unwrap().ends_with("main.typ"));
+        assert_eq!(
+            location["range"],
+            serde_json::json!({ "start": 7, "end": 14 })
+        );
+        assert_eq!(
+            location["start"],
+            serde_json::json!({ "line": 1, "column": 1 })
+        );
+        assert!(location.get("id").is_none());
+        assert!(diag["hints"].is_array());
+    }
+
+    #[test]
+    fn test_structured_diagnostic() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let mut world = ws.world();
+        world
+            .map_shadow(
+                &root.join("main.typ"),
+                Bytes::from_static("#let f() = unknown\n😀 #f()".as_bytes()),
+            )
+            .unwrap();
+        world.prepare_env(&mut CompileEnv::default()).unwrap();
+
+        let errors = typst::compile(&world, &mut Tracer::new()).unwrap_err();
+        let columns = [
+            PositionEncoding::Utf8,
+            PositionEncoding::Utf16,
+            PositionEncoding::Utf32,
+        ]
+        .map(|encoding| {
+            let diag = SerializableDiagnostic::from_source(&world, &errors[0], 3, encoding);
+            assert_eq!(diag.revision, 3);
+            assert_eq!(diag.message, "unknown variable: unknown");
+            assert_eq!(diag.location.range, Some(11..18));
+            assert!(diag.location.path.as_ref().unwrap().ends_with("main.typ"));
+
+            // The call of `f` on the second line.
+            let call = diag.trace[0].location.start.unwrap();
+            assert_eq!(call.line, 1);
+            call.column
+        });
+        assert_eq!(columns, [6, 4, 3]);
+
+        // Detached spans have no location.
+        let detached = SourceDiagnostic::error(Span::detached(), "detached");
+        let diag =
+            SerializableDiagnostic::from_source(&world, &detached, 0, PositionEncoding::Utf8);
+        assert_eq!(diag.location, DiagnosticLocation::default());
+    }
+}
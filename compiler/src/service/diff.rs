@@ -0,0 +1,279 @@
+//! Compare two compiled documents page by page.
+//!
+//! Frames are flattened into visual leaves placed in page coordinates,
+//! ignoring spans and other metadata which change without affecting the
+//! appearance of a page, e.g. after an edit on another page.
+
+use std::collections::HashMap;
+
+use typst::{
+    introspection::Meta,
+    layout::{Frame, FrameItem, Point, Size},
+};
+use typst_ts_core::{content_hash::page_content_hashes, hash::hash128, TypstDocument};
+
+/// A region of a page in page coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChangedRegion {
+    /// The top-left corner of the region.
+    pub pos: Point,
+    pub size: Size,
+}
+
+/// The change of a page, see [`diff_documents`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageChange {
+    Unchanged,
+    /// The page changed, where the regions cover the changed items on both
+    /// documents.
+    Changed {
+        regions: Vec<ChangedRegion>,
+    },
+    /// The page only exists in the new document.
+    Added,
+    /// The page only exists in the old document.
+    Removed,
+}
+
+/// The difference between two documents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocDiff {
+    /// Changes of pages, in the order of pages.
+    pub pages: Vec<PageChange>,
+}
+
+impl DocDiff {
+    /// Get indices of the pages which are not unchanged.
+    pub fn changed_pages(&self) -> Vec<usize> {
+        (self.pages.iter().enumerate())
+            .filter(|(_, change)| !matches!(change, PageChange::Unchanged))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+}
+
+/// Compare two documents by the hashes of the visual items of their frames.
+pub fn diff_documents(old: &TypstDocument, new: &TypstDocument) -> DocDiff {
+    let pages = (0..old.pages.len().max(new.pages.len()))
+        .map(|idx| match (old.pages.get(idx), new.pages.get(idx)) {
+            (Some(old), Some(new)) => diff_frame(&old.frame, &new.frame),
+            (Some(..), None) => PageChange::Removed,
+            (None, Some(..)) => PageChange::Added,
+            (None, None) => unreachable!(),
+        })
+        .collect();
+
+    DocDiff { pages }
+}
+
+/// Get a fingerprint for each page of a document, which only changes if the
+/// appearance of the page changes, see [`page_content_hashes`].
+pub(crate) fn page_fingerprints(doc: &TypstDocument) -> Vec<u128> {
+    page_content_hashes(doc)
+}
+
+/// Tracks the pages of the latest exported document, so that an exporter only
+/// exports the pages changed since then, see
+/// [`super::WorldExporter::export_changed`].
+#[derive(Debug, Default)]
+pub struct PageChangeTracker {
+    fingerprints: Option<Vec<u128>>,
+}
+
+impl PageChangeTracker {
+    /// Get the pages of a document changed since the latest committed one,
+    /// along with the fingerprints to commit once they are exported.
+    ///
+    /// All the pages are changed if no document is committed.
+    pub fn changed(&self, doc: &TypstDocument) -> (Vec<usize>, Vec<u128>) {
+        let fingerprints = page_fingerprints(doc);
+        let changed = match &self.fingerprints {
+            Some(old) => changed_pages(old, &fingerprints),
+            None => (0..fingerprints.len()).collect(),
+        };
+        (changed, fingerprints)
+    }
+
+    /// Commit the fingerprints of an exported document.
+    pub fn commit(&mut self, fingerprints: Vec<u128>) {
+        self.fingerprints = Some(fingerprints);
+    }
+
+    /// Forget the exported document, e.g. when the output is moved.
+    pub fn reset(&mut self) {
+        self.fingerprints = None;
+    }
+}
+
+/// Get indices of the pages whose fingerprints differ, including pages which
+/// only exist in one of the documents.
+pub(crate) fn changed_pages(old: &[u128], new: &[u128]) -> Vec<usize> {
+    (0..old.len().max(new.len()))
+        .filter(|idx| old.get(*idx) != new.get(*idx))
+        .collect()
+}
+
+fn diff_frame(old: &Frame, new: &Frame) -> PageChange {
+    let mut removed = leaves_of(old);
+    let mut added = leaves_of(new);
+    if old.size() == new.size() && removed == added {
+        return PageChange::Unchanged;
+    }
+
+    // Cancel out the leaves existing in both frames.
+    let mut counts = HashMap::<(u128, Point), usize>::new();
+    for leaf in &removed {
+        *counts.entry(leaf.key()).or_default() += 1;
+    }
+    added.retain(|leaf| match counts.get_mut(&leaf.key()) {
+        Some(c) if *c > 0 => {
+            *c -= 1;
+            false
+        }
+        _ => true,
+    });
+    removed.retain(|leaf| match counts.get_mut(&leaf.key()) {
+        Some(c) if *c > 0 => {
+            *c -= 1;
+            true
+        }
+        _ => false,
+    });
+
+    // A resized page without changed items changes as a whole.
+    if removed.is_empty() && added.is_empty() {
+        return PageChange::Changed {
+            regions: vec![ChangedRegion {
+                pos: Point::zero(),
+                size: new.size(),
+            }],
+        };
+    }
+
+    let regions = removed
+        .iter()
+        .chain(added.iter())
+        .map(|leaf| ChangedRegion {
+            pos: leaf.origin,
+            size: leaf.size,
+        })
+        .collect();
+    PageChange::Changed { regions }
+}
+
+/// A visual item placed on a page.
+#[derive(Debug, PartialEq)]
+struct Leaf {
+    hash: u128,
+    /// The position of the item.
+    pos: Point,
+    /// The top-left corner of the bounding box.
+    origin: Point,
+    size: Size,
+}
+
+impl Leaf {
+    fn key(&self) -> (u128, Point) {
+        (self.hash, self.pos)
+    }
+}
+
+fn leaves_of(frame: &Frame) -> Vec<Leaf> {
+    let mut leaves = vec![];
+    collect_leaves(frame, Point::zero(), &mut leaves);
+    leaves
+}
+
+fn frame_fingerprint(frame: &Frame) -> u128 {
+    let leaves = leaves_of(frame);
+    let keys: Vec<_> = leaves.iter().map(Leaf::key).collect();
+    hash128(&(frame.size(), keys))
+}
+
+fn collect_leaves(frame: &Frame, offset: Point, leaves: &mut Vec<Leaf>) {
+    for (pos, item) in frame.items() {
+        let pos = offset + *pos;
+        let (hash, origin, size) = match item {
+            FrameItem::Group(group) => {
+                if group.transform.is_identity() && group.clip_path.is_none() {
+                    collect_leaves(&group.frame, pos, leaves);
+                    continue;
+                }
+
+                let hash = hash128(&(
+                    group.transform,
+                    &group.clip_path,
+                    frame_fingerprint(&group.frame),
+                ));
+                (hash, pos, group.frame.size())
+            }
+            FrameItem::Text(text) => {
+                let glyphs: Vec<_> = (text.glyphs.iter())
+                    .map(|glyph| (glyph.id, glyph.x_advance, glyph.x_offset))
+                    .collect();
+                let hash = hash128(&(
+                    &text.font,
+                    text.size,
+                    &text.fill,
+                    &text.stroke,
+                    &text.text,
+                    glyphs,
+                ));
+                let origin = Point::new(pos.x, pos.y - text.size);
+                (hash, origin, Size::new(text.width(), text.size))
+            }
+            FrameItem::Shape(shape, _) => (hash128(shape), pos, shape.geometry.bbox_size()),
+            FrameItem::Image(image, size, _) => (hash128(&(image, size)), pos, *size),
+            FrameItem::Meta(meta @ Meta::Link(..), size) => (hash128(&(meta, size)), pos, *size),
+            // Other metadata is invisible.
+            FrameItem::Meta(..) => continue,
+        };
+
+        leaves.push(Leaf {
+            hash,
+            pos,
+            origin,
+            size,
+        });
+    }
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use typst::foundations::Bytes;
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileEnv, Compiler},
+        ShadowApi,
+    };
+
+    #[test]
+    fn test_diff_documents() {
+        let ws = TestWorkspace::new();
+        let main = ws.path("main.typ");
+        let mut driver = ws.driver();
+
+        let mut compile = |content: &'static str| {
+            let content = Bytes::from_static(content.as_bytes());
+            driver.map_shadow(&main, content).unwrap();
+            driver.compile(&mut CompileEnv::default()).unwrap()
+        };
+        let old = compile("First\n#pagebreak()\nSecond\n#pagebreak()\nThird");
+        let new = compile("First\n#pagebreak()\nEdited\n#pagebreak()\nThird");
+
+        let diff = diff_documents(&old, &new);
+        assert_eq!(diff.changed_pages(), vec![1]);
+        let PageChange::Changed { regions } = &diff.pages[1] else {
+            panic!("page 2 is not changed: {:?}", diff.pages[1]);
+        };
+        assert!(!regions.is_empty());
+
+        assert_eq!(
+            changed_pages(&page_fingerprints(&old), &page_fingerprints(&new)),
+            vec![1]
+        );
+        assert!(diff_documents(&new, &new).changed_pages().is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+//! Query the text and the metadata of compiled documents.
+//!
+//! The text is collected from the text runs of the pages, and reflowed by
+//! lines like the text exporter, see [`typst_ts_core::segment`].
+
+use serde::Serialize;
+use typst_ts_core::{
+    segment::{count_words, join_lines, reflow_lines, text_runs},
+    typst::prelude::EcoString,
+    TypstDocument,
+};
+
+pub use typst_ts_core::segment::TextRun;
+
+/// Collect the text runs of all pages in a document in the order of painting,
+/// e.g. for searching in the document, see [`text_runs`].
+pub fn document_text(document: &TypstDocument) -> Vec<TextRun> {
+    text_runs(document)
+}
+
+/// The default delimiter of pages in [`plain_text`], i.e. a form feed.
+pub const PAGE_DELIMITER: &str = "\u{c}";
+
+/// Extract the text of a document in reading order, e.g. for indexing or
+/// screen readers, where the pages are separated by the delimiter.
+///
+/// The text is reflowed by lines like the text exporter, where the words
+/// broken at the ends of lines are rejoined, see [`join_lines`].
+pub fn plain_text(document: &TypstDocument, page_delimiter: &str) -> String {
+    join_lines(&reflow_lines(&text_runs(document)), page_delimiter)
+}
+
+/// A label attached to an element of a document, see [`document_labels`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LabelInfo {
+    pub name: String,
+    /// The name of the element function, e.g. `heading` or `figure`.
+    pub kind: String,
+    /// The 1-based page number of the element.
+    pub page: usize,
+}
+
+/// Collect the labels of a document in the order of the elements, e.g. for
+/// the completion of references.
+///
+/// Only the labels of locatable elements are known to the introspector, a
+/// label attached to multiple elements is listed for each of them.
+pub fn document_labels(document: &TypstDocument) -> Vec<LabelInfo> {
+    let introspector = &document.introspector;
+    (introspector.all())
+        .filter_map(|elem| {
+            let label = elem.label()?;
+            let location = elem.location()?;
+            Some(LabelInfo {
+                name: label.as_str().to_owned(),
+                kind: elem.func().name().to_owned(),
+                page: introspector.page(location).get(),
+            })
+        })
+        .collect()
+}
+
+/// The metadata of a document, see [`document_info`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DocumentInfo {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub keywords: Vec<String>,
+}
+
+/// Get the metadata set by `set document(..)`, e.g. to show the title of a
+/// document in the tab of a preview.
+pub fn document_info(document: &TypstDocument) -> DocumentInfo {
+    let strings =
+        |list: &[EcoString]| -> Vec<String> { list.iter().map(|s| s.to_string()).collect() };
+    DocumentInfo {
+        title: document.title.as_ref().map(|title| title.to_string()),
+        authors: strings(&document.author),
+        keywords: strings(&document.keywords),
+    }
+}
+
+/// The statistics of a document, see [`document_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DocumentStats {
+    pub pages: usize,
+    /// The number of words, see [`typst_ts_core::segment`].
+    pub words: usize,
+    /// The number of characters except whitespace.
+    pub characters: usize,
+}
+
+/// Count the pages and the words of a document, e.g. for the status bar of an
+/// editor.
+///
+/// The words are counted on the lines exported by the text exporter, so that
+/// the counts agree with the exported text.
+pub fn document_stats(document: &TypstDocument) -> DocumentStats {
+    let lines = reflow_lines(&text_runs(document));
+    let characters = (lines.iter())
+        .flat_map(|line| line.text.chars())
+        .filter(|c| !c.is_whitespace())
+        .count();
+    DocumentStats {
+        pages: document.pages.len(),
+        words: count_words(&lines),
+        characters,
+    }
+}
@@ -6,12 +6,15 @@ use std::{
 use crate::{NotifyApi, ShadowApi};
 use typst::{
     diag::{eco_format, At, FileResult, SourceResult},
+    model::Document,
     syntax::Span,
     World,
 };
 use typst_ts_core::{config::compiler::DETACHED_ENTRY, Bytes, ImmutPath, TypstFileId};
 
-use super::{Compiler, EntryManager, EnvWorld};
+use super::{
+    limits::CompileLimits, CompileEnv, CompileMeta, Compiler, EntryManager, EnvWorld, WorldExporter,
+};
 
 /// CompileDriverImpl is a driver for typst compiler.
 /// It is responsible for operating the compiler without leaking implementation
@@ -21,6 +24,8 @@ pub struct CompileDriverImpl<W: World> {
     pub world: W,
     /// Path to the entry file.
     entry_file: Arc<Path>,
+    /// The limits applied to compilations without any limits by themselves.
+    limits: CompileLimits,
 }
 
 impl<W: World> CompileDriverImpl<W> {
@@ -29,8 +34,21 @@ impl<W: World> CompileDriverImpl<W> {
         Self {
             world,
             entry_file: Path::new("").into(),
+            limits: CompileLimits::default(),
         }
     }
+
+    /// Wrap driver with the given resource limits, which are applied to the
+    /// compilations unless their environment sets the same limits.
+    pub fn with_limits(mut self, limits: CompileLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// set the resource limits.
+    pub fn set_limits(&mut self, limits: CompileLimits) {
+        self.limits = limits;
+    }
 }
 
 impl<W: World + EntryManager> CompileDriverImpl<W> {
@@ -55,8 +73,15 @@ impl<W: World + EntryManager> CompileDriverImpl<W> {
         Ok(())
     }
 
-    pub fn entry_file(&self) -> &Path {
-        &self.entry_file
+    /// Get the path of the entry file, which follows the entry of the world,
+    /// e.g. after the root is changed by [`super::CompileActor::set_root`].
+    pub fn entry_file(&self) -> PathBuf {
+        let state = self.world.entry_state();
+        let main = state.main().filter(|id| id.package().is_none());
+        match (state.root(), main) {
+            (Some(root), Some(main)) => root.join(main.vpath().as_rootless_path()),
+            _ => self.entry_file.to_path_buf(),
+        }
     }
 }
 
@@ -83,6 +108,18 @@ impl<W: World + EnvWorld + EntryManager + NotifyApi> Compiler for CompileDriverI
         Ok(())
     }
 
+    fn compile(&mut self, env: &mut CompileEnv) -> SourceResult<Arc<Document>> {
+        if self.limits.is_unlimited() {
+            return self.pure_compile(env);
+        }
+
+        let env_limits = env.limits.clone();
+        env.limits = env_limits.clone().or(&self.limits);
+        let res = self.pure_compile(env);
+        env.limits = env_limits;
+        res
+    }
+
     /// Check whether a file system event is relevant to the world.
     // todo: remove cfg feature here
     #[cfg(feature = "system-watch")]
@@ -106,6 +143,22 @@ impl<W: World + EnvWorld + EntryManager + NotifyApi> Compiler for CompileDriverI
     fn notify_fs_event(&mut self, event: crate::vfs::notify::FilesystemEvent) {
         self.world.notify_fs_event(event)
     }
+
+    fn evict_files(&mut self, paths: &[ImmutPath]) {
+        self.world.evict_files(paths)
+    }
+
+    fn evict_stale_files(&mut self, age: std::time::Duration) {
+        self.world.evict_stale_files(age)
+    }
+}
+
+/// A bare driver exports nothing, see [`super::CompileExporter`] to export the
+/// compiled documents.
+impl<W: World> WorldExporter for CompileDriverImpl<W> {
+    fn export(&mut self, _output: Arc<Document>, _meta: &CompileMeta) -> SourceResult<()> {
+        Ok(())
+    }
 }
 
 impl<W: World + ShadowApi> ShadowApi for CompileDriverImpl<W> {
@@ -133,6 +186,16 @@ impl<W: World + ShadowApi> ShadowApi for CompileDriverImpl<W> {
     fn unmap_shadow(&self, path: &Path) -> typst::diag::FileResult<()> {
         self.world.unmap_shadow(path)
     }
+
+    #[inline]
+    fn edit_shadow(
+        &self,
+        path: &Path,
+        range: std::ops::Range<usize>,
+        text: &str,
+    ) -> typst::diag::FileResult<usize> {
+        self.world.edit_shadow(path, range, text)
+    }
 }
 
 // todo: Print that a package downloading is happening.
@@ -0,0 +1,88 @@
+//! Convert evaluated modules into JSON.
+//!
+//! Data-extraction pipelines use typst as a templating language, which only
+//! need the values bound by the entry file, e.g. metadata dictionaries, and
+//! skip the layout by [`super::Compiler::evaluate`]. The exported scope of the
+//! module is converted into a JSON object keyed by the names of the bindings.
+//!
+//! The values are mapped as follows:
+//!
+//! - `none` becomes `null`, and booleans, integers, strings, arrays and
+//!   dictionaries become their JSON counterparts.
+//! - Finite floats become numbers.
+//! - Content becomes its plain text, e.g. `[*Hello* World]` becomes
+//!   `"Hello World"`, and symbols become their characters.
+//! - Modules become objects of their exported scopes.
+//! - Any other value, e.g. a length, a function or a non-finite float,
+//!   becomes a marker object `{"$type": <type>, "repr": <repr>}`, where the
+//!   type is the short name of its typst type and the repr is how typst
+//!   displays the value.
+
+use serde_json::{json, Map, Number, Value as Json};
+use typst::foundations::{Module, Repr, Scope, Value};
+
+/// Convert the exported scope of a module into a JSON object, see the
+/// [module docs](self).
+pub fn module_to_json(module: &Module) -> Json {
+    scope_to_json(module.scope())
+}
+
+/// Convert the bindings of a scope into a JSON object.
+pub fn scope_to_json(scope: &Scope) -> Json {
+    let bindings: Map<_, _> = (scope.iter())
+        .map(|(name, value)| (name.to_string(), value_to_json(value)))
+        .collect();
+    Json::Object(bindings)
+}
+
+/// Convert a value into JSON, see the [module docs](self).
+pub fn value_to_json(value: &Value) -> Json {
+    match value {
+        Value::None => Json::Null,
+        Value::Bool(v) => Json::Bool(*v),
+        Value::Int(v) => Json::from(*v),
+        Value::Float(v) => match Number::from_f64(*v) {
+            Some(number) => Json::Number(number),
+            None => marker(value),
+        },
+        Value::Str(v) => Json::String(v.to_string()),
+        Value::Symbol(v) => Json::String(v.get().to_string()),
+        Value::Content(v) => Json::String(v.plain_text().to_string()),
+        Value::Array(v) => Json::Array(v.iter().map(value_to_json).collect()),
+        Value::Dict(v) => Json::Object(
+            v.iter()
+                .map(|(key, value)| (key.to_string(), value_to_json(value)))
+                .collect(),
+        ),
+        Value::Module(v) => module_to_json(v),
+        _ => marker(value),
+    }
+}
+
+fn marker(value: &Value) -> Json {
+    json!({ "$type": value.ty().short_name(), "repr": value.repr().as_str() })
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::foundations::{Array, Dict, Str};
+
+    use super::*;
+
+    #[test]
+    fn test_value_to_json() {
+        let dict = Dict::from_iter([
+            (Str::from("title"), Value::Str("Report".into())),
+            (Str::from("pages"), Value::Int(3)),
+        ]);
+        assert_eq!(
+            value_to_json(&Value::Dict(dict)),
+            json!({ "title": "Report", "pages": 3 })
+        );
+
+        let array = Array::from_iter([Value::None, Value::Bool(true), Value::Float(f64::NAN)]);
+        let array = value_to_json(&Value::Array(array));
+        assert_eq!((&array[0], &array[1]), (&Json::Null, &Json::Bool(true)));
+        assert_eq!(array[2]["$type"], "float");
+    }
+}
@@ -1,13 +1,19 @@
 use std::{path::PathBuf, sync::Arc};
 
-use crate::ShadowApi;
-use typst::{diag::SourceResult, World};
+use crate::{macros::pipeline_span, ShadowApi};
+use typst::{
+    diag::{Severity, SourceDiagnostic, SourceResult},
+    syntax::Span,
+    World,
+};
 use typst_ts_core::{
     exporter_builtins::GroupExporter,
+    output::write_atomic,
     typst::prelude::*,
     vector::{
-        ir::{LayoutRegion, LayoutRegionNode},
+        ir::{LayoutRegion, LayoutRegionNode, ModuleMetadata, VecDocument},
         pass::Typst2VecPass,
+        stats::{stats, ArtifactStats},
     },
     DynExporter, DynGenericExporter, DynPolymorphicExporter, GenericExporter, TakeAs,
     TypstDocument,
@@ -17,17 +23,57 @@ use typst_ts_core::{
 use typst_ts_svg_exporter::MultiVecDocument;
 
 use super::{
-    features::{CompileFeature, FeatureSet, WITH_COMPILING_STATUS_FEATURE},
-    CompileEnv, CompileMiddleware, CompileReport, Compiler,
+    diff::PageChangeTracker,
+    features::{
+        CompileFeature, FeatureSet, DIFF_DIAGNOSTICS_FEATURE, FAIL_ON_WARNINGS_FEATURE,
+        WITH_COMPILING_STATUS_FEATURE,
+    },
+    progress::CompileStage,
+    CompileEnv, CompileMeta, CompileMiddleware, CompileReport, Compiler, DiagnosticsTracker,
+    EnvWorld,
 };
 
 pub trait WorldExporter {
-    fn export(&mut self, output: Arc<typst::model::Document>) -> SourceResult<()>;
+    /// Export a compiled document, along with the metadata of the
+    /// compilation.
+    fn export(
+        &mut self,
+        output: Arc<typst::model::Document>,
+        meta: &CompileMeta,
+    ) -> SourceResult<()>;
+
+    /// Export a compiled document of which only the given pages changed
+    /// since the previous export, e.g. to skip serializing the unchanged
+    /// pages, see [`super::diff::PageChangeTracker`].
+    ///
+    /// The indices also include the pages removed from the end of the
+    /// previous document. Exporters which can't export a part of a document
+    /// export all of it.
+    fn export_changed(
+        &mut self,
+        output: Arc<typst::model::Document>,
+        meta: &CompileMeta,
+        changed: &[usize],
+    ) -> SourceResult<()> {
+        let _ = changed;
+        self.export(output, meta)
+    }
+}
+
+/// A document along with the metadata of its compilation, which is exported
+/// by the exporters set by [`CompileExporter::with_meta_exporter`].
+pub struct DocumentWithMeta {
+    pub doc: Arc<TypstDocument>,
+    pub meta: CompileMeta,
 }
 
 pub struct CompileExporter<C: Compiler> {
     pub compiler: C,
     pub exporter: DynExporter<TypstDocument>,
+    /// The exporter getting the metadata of compilations along with the
+    /// documents, e.g. to stamp the build id set by
+    /// [`super::CompileClient::set_meta`].
+    pub meta_exporter: DynExporter<DocumentWithMeta>,
 }
 
 impl<C: Compiler> CompileExporter<C> {
@@ -35,6 +81,7 @@ impl<C: Compiler> CompileExporter<C> {
         Self {
             compiler,
             exporter: GroupExporter::new(vec![]).into(),
+            meta_exporter: GroupExporter::new(vec![]).into(),
         }
     }
 
@@ -48,12 +95,40 @@ impl<C: Compiler> CompileExporter<C> {
     pub fn set_exporter(&mut self, exporter: impl Into<DynExporter<TypstDocument>>) {
         self.exporter = exporter.into();
     }
+
+    /// Wrap driver with a given exporter of the documents along with the
+    /// metadata of their compilations, which runs after the exporter.
+    pub fn with_meta_exporter(
+        mut self,
+        exporter: impl Into<DynExporter<DocumentWithMeta>>,
+    ) -> Self {
+        self.set_meta_exporter(exporter);
+        self
+    }
+
+    /// set an exporter of the documents along with their metadata.
+    pub fn set_meta_exporter(&mut self, exporter: impl Into<DynExporter<DocumentWithMeta>>) {
+        self.meta_exporter = exporter.into();
+    }
 }
 
 impl<C: Compiler> WorldExporter for CompileExporter<C> {
     /// Export a typst document using `typst_ts_core::DocumentExporter`.
-    fn export(&mut self, output: Arc<typst::model::Document>) -> SourceResult<()> {
-        self.exporter.export(self.compiler.world(), output)
+    ///
+    /// The document exporters only get the document, while the metadata is
+    /// passed to the meta exporter, see [`CompileExporter::with_meta_exporter`].
+    fn export(
+        &mut self,
+        output: Arc<typst::model::Document>,
+        meta: &CompileMeta,
+    ) -> SourceResult<()> {
+        let world = self.compiler.world();
+        self.exporter.export(world, output.clone())?;
+        let with_meta = DocumentWithMeta {
+            doc: output,
+            meta: meta.clone(),
+        };
+        self.meta_exporter.export(world, Arc::new(with_meta))
     }
 }
 
@@ -70,7 +145,405 @@ impl<C: Compiler> CompileMiddleware for CompileExporter<C> {
 
     fn wrap_compile(&mut self, env: &mut CompileEnv) -> SourceResult<Arc<typst::model::Document>> {
         let doc = self.inner_mut().compile(env)?;
-        self.export(doc.clone())?;
+
+        let _span = pipeline_span!("export");
+        env.report_progress(CompileStage::Export {
+            pages: doc.pages.len(),
+        });
+        self.export(doc.clone(), &env.meta)?;
+
+        Ok(doc)
+    }
+}
+
+/// Serialize a document into a vector artifact with a version header, which
+/// is loadable by the typst.ts renderer.
+///
+/// The artifact is reproducible: the same document is serialized to the same
+/// bytes by every run, whatever the order in which the pages are converted.
+/// The fonts are indexed in the order of the document, and the glyphs and
+/// items are sorted by their references.
+pub fn vector_artifact(doc: &TypstDocument) -> Vec<u8> {
+    vector_artifact_with(doc, &ArtifactOptions::default())
+}
+
+/// Get the statistics of the vector artifact of a document, see
+/// [`typst_ts_core::vector::stats::stats`], along with the decisions of the
+/// image limits of the world compiling it, which are not stored in the
+/// artifact.
+pub fn vector_artifact_stats(
+    world: &impl EnvWorld,
+    doc: &TypstDocument,
+    top_n: usize,
+) -> typst_ts_core::error::prelude::ZResult<ArtifactStats> {
+    let artifact = vector_artifact(doc);
+    let mut decisions: Vec<_> = (world.guarded_images().iter())
+        .map(|(id, image)| image.decision(*id))
+        .collect();
+    decisions.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(stats(&artifact, top_n)?.with_image_decisions(decisions))
+}
+
+/// Options of the vector artifact, see [`vector_artifact_with`].
+#[derive(Debug, Clone, Default)]
+pub struct ArtifactOptions {
+    /// Embed the link table of the document, see
+    /// [`super::links::document_links`], so that static viewers can navigate
+    /// without the compiler.
+    pub links: bool,
+    /// Embed the geometry of the pages, see
+    /// [`super::pages::document_page_metadata`], so that static viewers can
+    /// lay out the pages before rendering them.
+    pub page_metadata: Option<Vec<super::pages::PageMeta>>,
+    /// Embed the headings of the document with their numbering and anchors,
+    /// see [`super::outline::document_outline`], so that static viewers can
+    /// link to them.
+    pub outline: Option<Vec<super::outline::HeadingAnchor>>,
+    /// Embed the metadata elements of the document with their pages, see
+    /// [`super::metadata::document_metadata`], so that static viewers can
+    /// navigate by them, e.g. between slides.
+    pub metadata_anchors: Option<Vec<super::metadata::MetadataAnchor>>,
+    /// Embed the resolved sources of the embedded tables, i.e. the files and
+    /// the ranges of the [`Self::metadata_anchors`]. They are left out by
+    /// default, since they disclose the paths of the workspace.
+    pub include_source_mapping: bool,
+    /// Replace the texts by the outlines of their glyphs, so that renderers
+    /// don't need fonts at all, see
+    /// [`typst_ts_core::vector::ir::Module::flatten_text`]. The artifact
+    /// has neither fonts nor glyphs then, otherwise the outlines of the used
+    /// glyphs are embedded along with the fonts.
+    pub flatten_text: bool,
+    /// Write the artifact in the page-wise format, see
+    /// [`typst_ts_core::vector::paged`], so that viewers can render the
+    /// first pages before the whole artifact arrives.
+    pub paged: bool,
+}
+
+/// Serialize a document into a vector artifact like [`vector_artifact`],
+/// attaching the optional data.
+///
+/// A paged artifact is written page by page through
+/// [`typst_ts_core::vector::paged::PagedArtifactWriter`], with the optional
+/// data in its index.
+pub fn vector_artifact_with(doc: &TypstDocument, options: &ArtifactOptions) -> Vec<u8> {
+    let typst2vec = Typst2VecPass::default();
+    let pages = typst2vec.doc(&doc.introspector, doc);
+    let mut module = typst2vec.finalize();
+    if options.flatten_text {
+        module.flatten_text();
+    }
+
+    let mut metadata = vec![];
+    if options.links {
+        let links = super::links::document_links(doc);
+        let table = super::links::link_table(&links);
+        metadata.push(ModuleMetadata::Links(Arc::new(table)));
+    }
+    if let Some(pages) = &options.page_metadata {
+        let table = super::pages::page_meta_table(pages);
+        metadata.push(ModuleMetadata::PageMeta(Arc::new(table)));
+    }
+    if let Some(headings) = &options.outline {
+        let table = super::outline::outline_table(headings);
+        metadata.push(ModuleMetadata::Outline(Arc::new(table)));
+    }
+    if let Some(anchors) = &options.metadata_anchors {
+        let table = super::metadata::metadata_table(anchors, options.include_source_mapping);
+        metadata.push(ModuleMetadata::MetadataAnchors(Arc::new(table)));
+    }
+    let doc = VecDocument { pages, module };
+    if options.paged {
+        // Writing into memory never fails.
+        return doc.to_paged_bytes_with(metadata).unwrap();
+    }
+    doc.to_artifact_bytes_with(metadata)
+}
+
+/// Split a document into single-page documents, keeping the metadata of the
+/// document.
+///
+/// Introspection is rebuilt for each page, hence links to the other pages
+/// point to the start of the page.
+pub fn split_pages(doc: &TypstDocument) -> Vec<TypstDocument> {
+    (0..doc.pages.len())
+        .map(|idx| single_page(doc, idx))
+        .collect()
+}
+
+/// Get a page of a document as a single-page document, see [`split_pages`].
+fn single_page(doc: &TypstDocument, idx: usize) -> TypstDocument {
+    let mut single = TypstDocument {
+        pages: vec![doc.pages[idx].clone()],
+        ..doc.clone()
+    };
+    single.introspector.rebuild(&single.pages);
+    single
+}
+
+/// Export each page of a document into its own PDF file in `dir`, i.e.
+/// `page-001.pdf`, `page-002.pdf`, etc., see [`split_pages`].
+///
+/// It returns the paths of the written files in the order of pages.
+#[cfg(feature = "pdf")]
+pub fn export_pdf_per_page(
+    doc: &TypstDocument,
+    dir: &std::path::Path,
+) -> typst_ts_core::error::prelude::ZResult<Vec<PathBuf>> {
+    use typst::foundations::Smart;
+    use typst_ts_core::error::prelude::*;
+
+    std::fs::create_dir_all(dir).map_err(map_string_err("failed to create pdf directory"))?;
+    (split_pages(doc).iter().enumerate())
+        .map(|(idx, page)| {
+            let path = dir.join(format!("page-{:03}.pdf", idx + 1));
+            let data = typst_ts_pdf_exporter::pdf(page, Smart::Auto, None);
+            write_atomic(&path, data).map_err(map_string_err("failed to write pdf"))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// How the glyphs are embedded into the SVG files of the pages, see
+/// [`export_svg_dir`].
+#[cfg(feature = "svg")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SvgFontEmbedding {
+    /// Every file embeds only the glyphs used by its page.
+    #[default]
+    PerFile,
+    /// Every file embeds the same glyph definitions of the whole document,
+    /// so that the files are interchangeable, e.g. to be merged or cached by
+    /// viewers.
+    Shared,
+}
+
+/// Export each page of a document into its own SVG file in `dir`, i.e.
+/// `page-001.svg`, `page-002.svg`, etc.
+///
+/// It returns the paths of the written files in the order of pages.
+#[cfg(feature = "svg")]
+pub fn export_svg_dir(
+    doc: &TypstDocument,
+    dir: &std::path::Path,
+    fonts: SvgFontEmbedding,
+) -> typst_ts_core::error::prelude::ZResult<Vec<PathBuf>> {
+    use typst_ts_core::error::prelude::*;
+
+    let pages = match fonts {
+        SvgFontEmbedding::PerFile => (split_pages(doc).iter())
+            .map(typst_ts_svg_exporter::render_svg)
+            .collect(),
+        SvgFontEmbedding::Shared => typst_ts_svg_exporter::render_svg_pages(doc),
+    };
+
+    std::fs::create_dir_all(dir).map_err(map_string_err("failed to create svg directory"))?;
+    (pages.into_iter().enumerate())
+        .map(|(idx, svg)| {
+            let path = svg_page_path(dir, idx);
+            write_atomic(&path, svg).map_err(map_string_err("failed to write svg"))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Get the path of the SVG file of a page in [`export_svg_dir`].
+#[cfg(feature = "svg")]
+fn svg_page_path(dir: &std::path::Path, idx: usize) -> PathBuf {
+    dir.join(format!("page-{:03}.svg", idx + 1))
+}
+
+/// Writes the SVG file of each page into a directory after each compilation,
+/// like [`export_svg_dir`] with [`SvgFontEmbedding::PerFile`].
+///
+/// Only the pages changed since the previous export are rendered and
+/// written, and the files of the removed pages are deleted.
+#[cfg(feature = "svg")]
+pub struct SvgPagesExporter<C: Compiler> {
+    pub compiler: C,
+    dir: PathBuf,
+    pages: PageChangeTracker,
+}
+
+#[cfg(feature = "svg")]
+impl<C: Compiler> SvgPagesExporter<C> {
+    pub fn new(compiler: C, dir: PathBuf) -> Self {
+        Self {
+            compiler,
+            dir,
+            pages: PageChangeTracker::default(),
+        }
+    }
+
+    /// Get the directory of the SVG files.
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    pub fn set_dir(&mut self, dir: PathBuf) {
+        self.dir = dir;
+        self.pages.reset();
+    }
+}
+
+#[cfg(feature = "svg")]
+impl<C: Compiler> WorldExporter for SvgPagesExporter<C> {
+    fn export(
+        &mut self,
+        output: Arc<typst::model::Document>,
+        meta: &CompileMeta,
+    ) -> SourceResult<()> {
+        let changed: Vec<_> = (0..output.pages.len()).collect();
+        self.export_changed(output, meta, &changed)
+    }
+
+    fn export_changed(
+        &mut self,
+        output: Arc<typst::model::Document>,
+        _meta: &CompileMeta,
+        changed: &[usize],
+    ) -> SourceResult<()> {
+        let error = |err: std::io::Error| {
+            eco_vec![SourceDiagnostic::error(
+                Span::detached(),
+                eco_format!("failed to write svg pages: {err}"),
+            )]
+        };
+
+        std::fs::create_dir_all(&self.dir).map_err(error)?;
+        for &idx in changed {
+            let path = svg_page_path(&self.dir, idx);
+            if idx >= output.pages.len() {
+                match std::fs::remove_file(&path) {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(error(err))
+                    }
+                    _ => continue,
+                }
+            }
+
+            let svg = typst_ts_svg_exporter::render_svg(&single_page(&output, idx));
+            write_atomic(&path, svg).map_err(error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "svg")]
+impl<C: Compiler> CompileMiddleware for SvgPagesExporter<C> {
+    type Compiler = C;
+
+    fn inner(&self) -> &Self::Compiler {
+        &self.compiler
+    }
+
+    fn inner_mut(&mut self) -> &mut Self::Compiler {
+        &mut self.compiler
+    }
+
+    fn wrap_compile(&mut self, env: &mut CompileEnv) -> SourceResult<Arc<typst::model::Document>> {
+        let doc = self.inner_mut().compile(env)?;
+
+        let _span = pipeline_span!("export");
+        env.report_progress(CompileStage::Export {
+            pages: doc.pages.len(),
+        });
+        let (changed, fingerprints) = self.pages.changed(&doc);
+        self.export_changed(doc.clone(), &env.meta, &changed)?;
+        self.pages.commit(fingerprints);
+
+        Ok(doc)
+    }
+}
+
+/// Writes the vector artifact of the document to a file after each
+/// compilation, see [`vector_artifact`].
+///
+/// The artifact holds all the pages in a file, hence it is rewritten as a
+/// whole, but only if any page changed since the previous export.
+pub struct VectorArtifactExporter<C: Compiler> {
+    pub compiler: C,
+    output: PathBuf,
+    options: ArtifactOptions,
+    pages: PageChangeTracker,
+}
+
+impl<C: Compiler> VectorArtifactExporter<C> {
+    pub fn new(compiler: C, output: PathBuf) -> Self {
+        Self {
+            compiler,
+            output,
+            options: ArtifactOptions::default(),
+            pages: PageChangeTracker::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: ArtifactOptions) -> Self {
+        self.options = options;
+        self.pages.reset();
+        self
+    }
+
+    /// Get the path of the artifact.
+    pub fn output(&self) -> &PathBuf {
+        &self.output
+    }
+
+    pub fn set_output(&mut self, output: PathBuf) {
+        self.output = output;
+        self.pages.reset();
+    }
+}
+
+impl<C: Compiler> WorldExporter for VectorArtifactExporter<C> {
+    fn export(
+        &mut self,
+        output: Arc<typst::model::Document>,
+        _meta: &CompileMeta,
+    ) -> SourceResult<()> {
+        let artifact = vector_artifact_with(&output, &self.options);
+        write_atomic(&self.output, artifact).map_err(|err| {
+            eco_vec![SourceDiagnostic::error(
+                Span::detached(),
+                eco_format!("failed to write vector artifact: {err}"),
+            )]
+        })
+    }
+
+    /// The artifact is kept if no page changed, unless it is missing.
+    fn export_changed(
+        &mut self,
+        output: Arc<typst::model::Document>,
+        meta: &CompileMeta,
+        changed: &[usize],
+    ) -> SourceResult<()> {
+        if changed.is_empty() && self.output.exists() {
+            return Ok(());
+        }
+        self.export(output, meta)
+    }
+}
+
+impl<C: Compiler> CompileMiddleware for VectorArtifactExporter<C> {
+    type Compiler = C;
+
+    fn inner(&self) -> &Self::Compiler {
+        &self.compiler
+    }
+
+    fn inner_mut(&mut self) -> &mut Self::Compiler {
+        &mut self.compiler
+    }
+
+    fn wrap_compile(&mut self, env: &mut CompileEnv) -> SourceResult<Arc<typst::model::Document>> {
+        let doc = self.inner_mut().compile(env)?;
+
+        let _span = pipeline_span!("export");
+        env.report_progress(CompileStage::Export {
+            pages: doc.pages.len(),
+        });
+        let (changed, fingerprints) = self.pages.changed(&doc);
+        self.export_changed(doc.clone(), &env.meta, &changed)?;
+        self.pages.commit(fingerprints);
 
         Ok(doc)
     }
@@ -82,6 +555,12 @@ pub type FeaturedReportExporter = DynExporter<(Arc<FeatureSet>, CompileReport)>;
 pub struct CompileReporter<C: Compiler> {
     pub compiler: C,
     pub reporter: DynGenericExporter<C::World, (Arc<FeatureSet>, CompileReport)>,
+    /// The number of compilations so far.
+    revision: usize,
+    /// Diagnostics of the latest compilation, either errors or warnings.
+    diagnostics: EcoVec<SourceDiagnostic>,
+    /// Tracks diagnostics to report their changes between compilations.
+    tracker: DiagnosticsTracker,
 }
 
 impl<C: Compiler> CompileReporter<C>
@@ -93,6 +572,9 @@ where
         Self {
             compiler,
             reporter: Box::new(DynPolymorphicExporter::<C::World, _, _>::new(x)),
+            revision: 0,
+            diagnostics: EcoVec::new(),
+            tracker: DiagnosticsTracker::default(),
         }
     }
 
@@ -145,27 +627,27 @@ where
     }
 }
 
-impl<C: Compiler + WorldExporter> WorldExporter for CompileReporter<C> {
-    /// Export a typst document using `typst_ts_core::DocumentExporter`.
-    fn export(&mut self, output: Arc<typst::model::Document>) -> SourceResult<()> {
-        self.compiler.export(output)
+impl<C: Compiler> CompileReporter<C> {
+    /// The revision of the latest compilation, which starts from 1.
+    pub fn revision(&self) -> usize {
+        self.revision
     }
-}
 
-impl<C: Compiler> CompileMiddleware for CompileReporter<C> {
-    type Compiler = C;
-
-    fn inner(&self) -> &Self::Compiler {
-        &self.compiler
+    /// Get the diagnostics of the latest compilation.
+    pub fn diagnostics(&self) -> &EcoVec<SourceDiagnostic> {
+        &self.diagnostics
     }
 
-    fn inner_mut(&mut self) -> &mut Self::Compiler {
-        &mut self.compiler
-    }
-
-    fn wrap_compile(&mut self, env: &mut CompileEnv) -> SourceResult<Arc<typst::model::Document>> {
+    /// Run a compilation or an evaluation of the inner compiler, reporting
+    /// its stages and diagnostics.
+    fn run_reported<T>(
+        &mut self,
+        env: &mut CompileEnv,
+        run: impl FnOnce(&mut C, &mut CompileEnv) -> SourceResult<T>,
+    ) -> SourceResult<T> {
         let start = crate::time::now();
         let id = self.main_id();
+        self.revision += 1;
         if WITH_COMPILING_STATUS_FEATURE.retrieve(&env.features) {
             let rep = CompileReport::Stage(id, "compiling", start);
             let rep = Arc::new((env.features.clone(), rep));
@@ -178,24 +660,36 @@ impl<C: Compiler> CompileMiddleware for CompileReporter<C> {
 
         env.tracer = Some(tracer.unwrap_or_default());
 
-        let doc = self.inner_mut().compile(env);
+        let output = run(&mut self.compiler, env);
 
         let elapsed = start.elapsed().unwrap_or_default();
 
         let rep;
 
-        let doc = match doc {
-            Ok(doc) => {
+        let output = match output {
+            Ok(output) => {
                 let warnings = env.tracer.as_ref().unwrap().clone().warnings();
                 if warnings.is_empty() {
                     rep = CompileReport::CompileSuccess(id, warnings, elapsed);
+                    Ok(output)
+                } else if FAIL_ON_WARNINGS_FEATURE.retrieve(&env.features) {
+                    let errors = (warnings.iter().cloned())
+                        .map(|mut diag| {
+                            diag.severity = Severity::Error;
+                            diag
+                        })
+                        .collect();
+                    rep = CompileReport::CompileError(id, warnings, elapsed);
+                    Err(errors)
                 } else {
                     rep = CompileReport::CompileWarning(id, warnings, elapsed);
+                    Ok(output)
                 }
-
-                Ok(doc)
             }
-            Err(err) => {
+            Err(mut err) => {
+                // Keep the warnings along with the errors, e.g. of a layout
+                // failing with delayed errors.
+                err.extend(env.tracer.as_ref().unwrap().clone().warnings());
                 rep = CompileReport::CompileError(id, err, elapsed);
                 Err(eco_vec![])
             }
@@ -205,11 +699,61 @@ impl<C: Compiler> CompileMiddleware for CompileReporter<C> {
             env.tracer = None;
         }
 
+        self.diagnostics = rep.clone().diagnostics().unwrap_or_default();
         let rep = Arc::new((env.features.clone(), rep));
         // we currently ignore export error here
         let _ = self.reporter.export(self.compiler.world(), rep);
 
-        doc
+        if DIFF_DIAGNOSTICS_FEATURE.retrieve(&env.features) {
+            let world = self.compiler.world();
+            let report = (self.tracker).update(world, self.revision, self.diagnostics.clone());
+            let rep = Arc::new((env.features.clone(), CompileReport::Diagnostics(id, report)));
+            let _ = self.reporter.export(world, rep);
+        }
+
+        output
+    }
+}
+
+impl<C: Compiler + WorldExporter> WorldExporter for CompileReporter<C> {
+    /// Export a typst document using `typst_ts_core::DocumentExporter`.
+    fn export(
+        &mut self,
+        output: Arc<typst::model::Document>,
+        meta: &CompileMeta,
+    ) -> SourceResult<()> {
+        self.compiler.export(output, meta)
+    }
+
+    fn export_changed(
+        &mut self,
+        output: Arc<typst::model::Document>,
+        meta: &CompileMeta,
+        changed: &[usize],
+    ) -> SourceResult<()> {
+        self.compiler.export_changed(output, meta, changed)
+    }
+}
+
+impl<C: Compiler> CompileMiddleware for CompileReporter<C> {
+    type Compiler = C;
+
+    fn inner(&self) -> &Self::Compiler {
+        &self.compiler
+    }
+
+    fn inner_mut(&mut self) -> &mut Self::Compiler {
+        &mut self.compiler
+    }
+
+    fn wrap_compile(&mut self, env: &mut CompileEnv) -> SourceResult<Arc<typst::model::Document>> {
+        self.run_reported(env, |compiler, env| compiler.compile(env))
+    }
+
+    /// The diagnostics of evaluations are reported like those of
+    /// compilations.
+    fn wrap_evaluate(&mut self, env: &mut CompileEnv) -> SourceResult<typst::foundations::Module> {
+        self.run_reported(env, |compiler, env| compiler.evaluate(env))
     }
 }
 
@@ -397,10 +941,18 @@ impl<C: Compiler + ShadowApi> DynamicLayoutCompiler<C> {
 
 #[cfg(feature = "dynamic-layout")]
 impl<C: Compiler + ShadowApi> WorldExporter for DynamicLayoutCompiler<C> {
-    fn export(&mut self, _output: Arc<typst::model::Document>) -> SourceResult<()> {
+    fn export(
+        &mut self,
+        _output: Arc<typst::model::Document>,
+        _meta: &CompileMeta,
+    ) -> SourceResult<()> {
         let doc = self.do_export()?;
-        std::fs::write(self.module_dest_path(), doc.to_bytes()).unwrap();
-        Ok(())
+        write_atomic(self.module_dest_path(), doc.to_bytes()).map_err(|err| {
+            eco_vec![SourceDiagnostic::error(
+                Span::detached(),
+                eco_format!("failed to write dynamic layout artifact: {err}"),
+            )]
+        })
     }
 }
 
@@ -422,8 +974,504 @@ impl<C: Compiler + ShadowApi> CompileMiddleware for DynamicLayoutCompiler<C> {
         }
 
         let pure_doc = self.inner_mut().compile(env)?;
-        self.export(pure_doc.clone())?;
+        env.report_progress(CompileStage::Export {
+            pages: pure_doc.pages.len(),
+        });
+        self.export(pure_doc.clone(), &env.meta)?;
 
         Ok(pure_doc)
     }
 }
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use super::*;
+    use crate::{fixture::TestWorkspace, service::CompileDriver};
+
+    #[test]
+    fn test_export_vector_artifact() {
+        use typst_ts_core::vector::{ir::MultiVecDocument, stream::ARTIFACT_MAGIC};
+
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let output = root.join("main.artifact.sir.in");
+        ws.write("main.typ", "#rect(width: 10pt)");
+
+        let driver = ws.driver();
+        let mut driver = VectorArtifactExporter::new(driver, output.clone());
+        driver.compile(&mut CompileEnv::default()).unwrap();
+
+        let mut artifact = std::fs::read(&output).unwrap();
+        assert_eq!(artifact[..8], ARTIFACT_MAGIC);
+        let doc = MultiVecDocument::try_from_slice(&artifact).unwrap();
+        assert_eq!(doc.layouts.len(), 1);
+
+        // An artifact of another version is rejected.
+        artifact[7] += 1;
+        assert!(MultiVecDocument::try_from_slice(&artifact).is_err());
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn test_render_exported_artifact_page() {
+        use std::collections::HashSet;
+
+        use typst_ts_svg_exporter::render_artifact_page;
+
+        let ws = TestWorkspace::new();
+        let output = ws.path("main.artifact.sir.in");
+        ws.write("main.typ", "One #pagebreak() Two #pagebreak() Three");
+
+        let export = |options: ArtifactOptions| {
+            let driver = VectorArtifactExporter::new(ws.driver(), output.clone());
+            let mut driver = driver.with_options(options);
+            driver.compile(&mut CompileEnv::default()).unwrap();
+            std::fs::read(&output).unwrap()
+        };
+        let monolithic = export(ArtifactOptions::default());
+        let paged = export(ArtifactOptions {
+            paged: true,
+            ..ArtifactOptions::default()
+        });
+
+        // A monolithic artifact defines the glyphs of every page, while a
+        // paged one only those of the rendered page.
+        let split_glyphs = |svg: &str| {
+            let (head, rest) = svg.split_once(r#"<defs class="glyph">"#).unwrap();
+            let (glyphs, tail) = rest.split_once("</defs>").unwrap();
+            let glyphs = glyphs.split_inclusive("/>").map(str::to_owned);
+            (format!("{head}{tail}"), glyphs.collect::<HashSet<_>>())
+        };
+
+        // Both formats render the same pages.
+        for page in 0..3 {
+            let svg = render_artifact_page(&monolithic, page).unwrap();
+            assert!(svg.starts_with("<svg"), "{svg}");
+            let (body, glyphs) = split_glyphs(&svg);
+            let (paged_body, paged_glyphs) =
+                split_glyphs(&render_artifact_page(&paged, page).unwrap());
+            assert_eq!(paged_body, body);
+            assert!(!paged_glyphs.is_empty());
+            assert!(paged_glyphs.is_subset(&glyphs));
+        }
+        assert!(render_artifact_page(&monolithic, 3).is_err());
+        assert!(render_artifact_page(&paged, 3).is_err());
+    }
+
+    /// Compile a fixture of the repository by its path from the root, with
+    /// only the embedded fonts.
+    fn compile_fixture(fixture: &str) -> Arc<TypstDocument> {
+        use typst_ts_core::config::{compiler::EntryOpts, CompileOpts};
+
+        use crate::TypstSystemWorld;
+
+        let repo = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        let path = repo.join(fixture).canonicalize().unwrap();
+        let root = path.parent().unwrap().to_owned();
+        let entry = path.file_name().unwrap().to_str().unwrap().to_owned();
+        let opts = CompileOpts {
+            entry: EntryOpts::new_rooted(root.clone(), Some(entry.into())),
+            ..TestWorkspace::opts_at(root)
+        };
+        let world = TypstSystemWorld::new(opts).unwrap();
+        let mut driver = CompileDriver::new(world).with_entry_file(path);
+        driver.compile(&mut CompileEnv::default()).unwrap()
+    }
+
+    /// The fixtures compiled by the tests of artifacts.
+    const FIXTURES: &[&str] = &[
+        "fixtures/book/main.typ",
+        "fixtures/html/show-rule.typ",
+        "fixtures/html/structure.typ",
+        "fixtures/pdf/cross-references.typ",
+        "fixtures/pdf/fidelity.typ",
+        "fuzzers/corpora/math/undergradmath.typ",
+    ];
+
+    #[test]
+    fn test_paged_artifact_of_fixtures() {
+        use typst_ts_core::vector::{
+            artifact::{artifact_pages, load_artifact, load_artifact_with_metadata},
+            ir::{FlatGlyphItem, Module, Page, VecItem},
+            lazy::LazyArtifact,
+        };
+
+        // The items reachable from a page, along with the glyphs of its texts,
+        // where the placeholders of missing glyphs are left out.
+        let page_items = |module: &Module, page: &Page| {
+            let (mut items, mut glyphs) = (vec![], vec![]);
+            module.visit_reachable(&page.content, &mut |fg, item| {
+                if let VecItem::Text(text) = item {
+                    let font = module.get_font(&text.shape.font).unwrap();
+                    glyphs.extend(text.content.glyphs.iter().map(|(_, _, idx)| {
+                        let glyph = (font.glyphs.get(*idx as usize))
+                            .filter(|glyph| !matches!(glyph.as_ref(), FlatGlyphItem::None))
+                            .cloned();
+                        ((font.hash, *idx), glyph)
+                    }));
+                }
+                items.push((*fg, item.clone()));
+            });
+            items.sort_by_key(|(fg, _)| *fg);
+            glyphs.sort_by_key(|(id, _)| *id);
+            (items, glyphs)
+        };
+        let paged = ArtifactOptions {
+            paged: true,
+            ..ArtifactOptions::default()
+        };
+
+        for fixture in FIXTURES {
+            let doc = compile_fixture(fixture);
+            let mut monolithic = load_artifact(&vector_artifact(&doc)).unwrap();
+            monolithic.module.prepare_glyphs();
+            let pages = artifact_pages(&monolithic);
+            assert_eq!(pages.len(), doc.pages.len(), "{fixture}");
+
+            // Each page is decoded along with the resources it depends on.
+            let artifact = vector_artifact_with(&doc, &paged);
+            let lazy = LazyArtifact::open(&artifact).unwrap();
+            assert_eq!(lazy.page_count(), pages.len(), "{fixture}");
+            for (k, expected) in pages.iter().enumerate() {
+                let (module, page) = lazy.page_module(k).unwrap();
+                assert_eq!(&page, expected, "{fixture} page {k}");
+                let (items, glyphs) = page_items(&module, &page);
+                assert_eq!(
+                    (items, glyphs),
+                    page_items(&monolithic.module, expected),
+                    "{fixture} page {k}"
+                );
+            }
+
+            // The whole artifact decodes to the same document.
+            let mut whole = load_artifact(&artifact).unwrap();
+            whole.module.prepare_glyphs();
+            assert_eq!(artifact_pages(&whole), pages, "{fixture}");
+            assert_eq!(whole.module.items, monolithic.module.items, "{fixture}");
+        }
+
+        // The metadata is stored in the index.
+        let doc = compile_fixture("fixtures/pdf/cross-references.typ");
+        let options = ArtifactOptions {
+            links: true,
+            ..paged
+        };
+        let artifact = vector_artifact_with(&doc, &options);
+        let (_, metadata) = load_artifact_with_metadata(&artifact).unwrap();
+        assert!(matches!(metadata[..], [ModuleMetadata::Links(..)]));
+    }
+
+    #[test]
+    fn test_report_warnings() {
+        let ws = TestWorkspace::new();
+        let main = ws.path("main.typ");
+        ws.write("main.typ", "Hello **");
+
+        let world = ws.world();
+        let mut driver = CompileReporter::new(CompileDriver::new(world).with_entry_file(main));
+
+        // Warnings are reported on every compilation.
+        for revision in 1..=2 {
+            assert!(driver.compile(&mut CompileEnv::default()).is_ok());
+            assert_eq!(driver.revision(), revision);
+            let warnings = driver.diagnostics();
+            assert_eq!(warnings.len(), 1);
+            assert_eq!(warnings[0].severity, Severity::Warning);
+        }
+
+        // The warnings are returned as errors.
+        let features = FeatureSet::default().configure(&FAIL_ON_WARNINGS_FEATURE, true);
+        let errors = driver
+            .compile(&mut CompileEnv::default().configure(features))
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].severity, Severity::Error);
+        assert_eq!(driver.diagnostics().len(), 1);
+    }
+
+    #[test]
+    fn test_diff_diagnostics() {
+        use std::sync::Mutex;
+
+        use typst::foundations::Bytes;
+
+        use crate::service::DiagnosticsReport;
+
+        let ws = TestWorkspace::new();
+        let main = ws.path("main.typ");
+        let world = ws.world();
+
+        let reports = Arc::new(Mutex::new(Vec::<DiagnosticsReport>::new()));
+        let reporter: ReportExporter = Box::new({
+            let reports = reports.clone();
+            move |_: &dyn World, rep: Arc<CompileReport>| {
+                if let CompileReport::Diagnostics(_, report) = rep.as_ref() {
+                    reports.lock().unwrap().push(report.clone());
+                }
+                Ok(())
+            }
+        });
+        let mut driver =
+            CompileReporter::new(CompileDriver::new(world).with_entry_file(main.clone()))
+                .with_reporter(reporter);
+
+        let features = Arc::new(FeatureSet::default().configure(&DIFF_DIAGNOSTICS_FEATURE, true));
+        let both = "Hello ** __";
+        let first = "Hello **";
+        for content in [both, first, "ok"] {
+            let content = Bytes::from_static(content.as_bytes());
+            driver.compiler.map_shadow(&main, content).unwrap();
+            let mut env = CompileEnv::default().configure_shared(features.clone());
+            assert!(driver.compile(&mut env).is_ok());
+        }
+
+        let reports = reports.lock().unwrap();
+        let counts: Vec<_> = (reports.iter())
+            .map(|r| {
+                (
+                    r.revision,
+                    r.diagnostics.len(),
+                    r.added.len(),
+                    r.unchanged,
+                    r.resolved,
+                )
+            })
+            .collect();
+        assert_eq!(
+            counts,
+            vec![(1, 2, 2, 0, 0), (2, 1, 0, 1, 1), (3, 0, 0, 0, 1)]
+        );
+        assert_eq!(
+            reports[1].summary().as_deref(),
+            Some("1 previous diagnostic(s) unchanged, 1 fixed")
+        );
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_export_pdf_per_page() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let output = root.join("pages");
+        ws.write("main.typ", "One #pagebreak() Two #pagebreak() Three");
+
+        let mut driver = ws.driver();
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+        assert_eq!(doc.pages.len(), 3);
+
+        let paths = export_pdf_per_page(&doc, &output).unwrap();
+        let names: Vec<_> = paths.iter().map(|p| p.file_name().unwrap()).collect();
+        assert_eq!(names, ["page-001.pdf", "page-002.pdf", "page-003.pdf"]);
+        for path in &paths {
+            let pdf = String::from_utf8_lossy(&std::fs::read(path).unwrap()).into_owned();
+            let pages = pdf.matches("/Type /Page").count() - pdf.matches("/Type /Pages").count();
+            assert_eq!(pages, 1, "{}", path.display());
+        }
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn test_export_svg_dir() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let output = root.join("pages");
+        ws.write("main.typ", "Hello #pagebreak() World");
+
+        let mut driver = ws.driver();
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+        assert_eq!(doc.pages.len(), 2);
+
+        let glyph_defs = |svg: &str| {
+            let start = svg.find(r#"<defs class="glyph">"#).unwrap();
+            let end = start + svg[start..].find("</defs>").unwrap();
+            svg[start..end].to_owned()
+        };
+        for fonts in [SvgFontEmbedding::PerFile, SvgFontEmbedding::Shared] {
+            let paths = export_svg_dir(&doc, &output, fonts).unwrap();
+            let names: Vec<_> = paths.iter().map(|p| p.file_name().unwrap()).collect();
+            assert_eq!(names, ["page-001.svg", "page-002.svg"]);
+
+            let svgs: Vec<_> = (paths.iter())
+                .map(|path| std::fs::read_to_string(path).unwrap())
+                .collect();
+            for svg in &svgs {
+                assert!(
+                    svg.starts_with("<svg") && svg.ends_with("</svg>"),
+                    "{fonts:?}"
+                );
+                assert_eq!(svg.matches(r#"class="typst-page""#).count(), 1);
+            }
+
+            let shared = glyph_defs(&svgs[0]) == glyph_defs(&svgs[1]);
+            assert_eq!(shared, fonts == SvgFontEmbedding::Shared);
+        }
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn test_export_changed_svg_pages() {
+        use typst::foundations::Bytes;
+
+        use crate::ShadowApi;
+
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let main = ws.path("main.typ");
+        let output = root.join("pages");
+
+        let driver = ws.driver();
+        let mut driver = SvgPagesExporter::new(driver, output.clone());
+        let mut compile = |content: &'static str| {
+            let content = Bytes::from_static(content.as_bytes());
+            driver.compiler.map_shadow(&main, content).unwrap();
+            driver.compile(&mut CompileEnv::default()).unwrap();
+        };
+        let page = |idx: usize| output.join(format!("page-{idx:03}.svg"));
+
+        compile("First\n#pagebreak()\nSecond\n#pagebreak()\nThird");
+        for idx in 1..=3 {
+            assert!(page(idx).exists());
+        }
+
+        // Only the edited last page is written again.
+        std::fs::remove_file(page(1)).unwrap();
+        let second = std::fs::read_to_string(page(2)).unwrap();
+        compile("First\n#pagebreak()\nSecond\n#pagebreak()\nThird and more");
+        assert!(!page(1).exists());
+        assert_eq!(std::fs::read_to_string(page(2)).unwrap(), second);
+        assert!(page(3).exists());
+
+        // The file of a removed page is deleted.
+        compile("First\n#pagebreak()\nSecond");
+        assert!(!page(3).exists());
+    }
+
+    #[test]
+    fn test_atomic_fs_exporter() {
+        use std::{fs::File, io::Write};
+        use typst_ts_core::{
+            exporter_builtins::FsPathExporter, exporter_utils::map_err, output::OutputRevisions,
+            AsWritable, Exporter,
+        };
+
+        fn complete(_: &dyn World, (_, mut file): (Arc<TypstDocument>, File)) -> SourceResult<()> {
+            file.write_all(b"complete").map_err(map_err)
+        }
+
+        // An export killed mid-write.
+        fn kill(_: &dyn World, (_, mut file): (Arc<TypstDocument>, File)) -> SourceResult<()> {
+            file.write_all(b"trunc").map_err(map_err)?;
+            Err(map_err("killed"))
+        }
+
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let output = root.join("main.pdf");
+        ws.write("main.typ", "Hello");
+        std::fs::write(&output, "previous").unwrap();
+
+        let mut driver = ws.driver();
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+        let world = driver.world();
+        let dir_names = || {
+            let mut names: Vec<_> = (std::fs::read_dir(&root).unwrap())
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let killed = FsPathExporter::<AsWritable, _>::new(output.clone(), kill);
+        assert!(killed.export(world, doc.clone()).is_err());
+        assert_eq!(std::fs::read(&output).unwrap(), b"previous");
+        assert_eq!(dir_names(), ["main.pdf", "main.typ"]);
+
+        // The latest revision survives as well in the per-revision mode.
+        let exporter = FsPathExporter::<AsWritable, _>::new(output.clone(), complete)
+            .with_revisions(OutputRevisions::new(2));
+        exporter.export(world, doc.clone()).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"complete");
+
+        let killed = FsPathExporter::<AsWritable, _>::new(output.clone(), kill)
+            .with_revisions(OutputRevisions::new(2));
+        assert!(killed.export(world, doc.clone()).is_err());
+        assert_eq!(std::fs::read(&output).unwrap(), b"complete");
+        assert_eq!(dir_names(), ["main.pdf", "main.r1.pdf", "main.typ"]);
+    }
+
+    #[test]
+    fn test_cached_exporter() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use typst::foundations::Bytes;
+        use typst_ts_core::{
+            artifact_cache::{CachedExporter, MemoryArtifactCache, RemoteArtifactCache},
+            exporter_builtins::FsPathExporter,
+        };
+
+        use crate::ShadowApi;
+
+        /// A remote cache counting its hits.
+        #[derive(Default)]
+        struct MockCache {
+            cache: MemoryArtifactCache,
+            hits: AtomicUsize,
+        }
+
+        impl RemoteArtifactCache for MockCache {
+            fn get(&self, hash: u128) -> Option<Bytes> {
+                let bytes = self.cache.get(hash);
+                if bytes.is_some() {
+                    self.hits.fetch_add(1, Ordering::SeqCst);
+                }
+                bytes
+            }
+
+            fn put(&self, hash: u128, bytes: Bytes) {
+                self.cache.put(hash, bytes);
+            }
+        }
+
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let main = ws.path("main.typ");
+        let output = root.join("main.pdf");
+
+        let world = ws.world();
+        let cache = Arc::new(MockCache::default());
+        let exports = Arc::new(AtomicUsize::new(0));
+        let exporter = CachedExporter::new(
+            {
+                let exports = exports.clone();
+                move |_: &dyn World, _: Arc<TypstDocument>| -> SourceResult<Vec<u8>> {
+                    let count = exports.fetch_add(1, Ordering::SeqCst) + 1;
+                    Ok(format!("export {count}").into_bytes())
+                }
+            },
+            cache.clone(),
+        );
+        let mut driver =
+            CompileExporter::new(CompileDriver::new(world).with_entry_file(main.clone()))
+                .with_exporter(FsPathExporter::<Vec<u8>, _>::new(output.clone(), exporter));
+        let mut compile = |content: &'static str| {
+            let content = Bytes::from_static(content.as_bytes());
+            driver.compiler.map_shadow(&main, content).unwrap();
+            driver.compile(&mut CompileEnv::default()).unwrap();
+        };
+
+        compile("#rect(width: 10pt)");
+        assert_eq!(exports.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.hits.load(Ordering::SeqCst), 0);
+
+        // The second compilation of the identical content hits the cache.
+        std::fs::remove_file(&output).unwrap();
+        compile("#rect(width: 10pt) // unchanged");
+        assert_eq!(exports.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.hits.load(Ordering::SeqCst), 1);
+        assert_eq!(std::fs::read(&output).unwrap(), b"export 1");
+
+        compile("#rect(width: 20pt)");
+        assert_eq!(exports.load(Ordering::SeqCst), 2);
+        assert_eq!(std::fs::read(&output).unwrap(), b"export 2");
+    }
+}
@@ -93,6 +93,13 @@ impl<T> BuiltinFeature<T> {
 
 pub static WITH_COMPILING_STATUS_FEATURE: BuiltinFeature<bool> = BuiltinFeature::<bool>::new();
 
+/// Whether to fail a compilation which succeeds with warnings.
+pub static FAIL_ON_WARNINGS_FEATURE: BuiltinFeature<bool> = BuiltinFeature::<bool>::new();
+
+/// Whether to report diagnostics along with their changes since the previous
+/// compilation, see [`CompileReport::Diagnostics`](super::CompileReport).
+pub static DIFF_DIAGNOSTICS_FEATURE: BuiltinFeature<bool> = BuiltinFeature::<bool>::new();
+
 impl CompileFeature<bool> for BuiltinFeature<bool> {
     fn configure(&self, features: FeatureSet, value: bool) -> FeatureSet {
         features.configure_slot(&self.0, if value { "1" } else { "" }.into())
@@ -0,0 +1,158 @@
+//! Inspect the fonts known to a compiler, e.g. to check whether a font family
+//! renders all the characters of a text before using it.
+//!
+//! The coverage of the faces is taken from the metadata of the font book,
+//! hence no font file is loaded by the queries. A character missing in a
+//! family is looked up among the other faces like the text fallback of typst,
+//! i.e. `text(fallback: true)`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+use typst::text::{FontBook, FontInfo, FontVariant};
+use typst_ts_core::{error::prelude::*, font::FontOrigin, FontResolver};
+
+/// A face of a font family, see [`list_fonts`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontFaceInfo {
+    pub variant: FontVariant,
+    /// Where the data of the face comes from, if known.
+    pub origin: Option<FontOrigin>,
+}
+
+/// A font family along with its faces, see [`list_fonts`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontFamilyInfo {
+    pub family: String,
+    pub faces: Vec<FontFaceInfo>,
+}
+
+/// A fallback face covering some characters of a text, see
+/// [`CoverageResult`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FallbackCoverage {
+    pub family: String,
+    pub variant: FontVariant,
+    /// The characters covered by the face, in the order of the text.
+    pub chars: Vec<char>,
+}
+
+/// The coverage of a text by a font family, see [`font_coverage`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageResult {
+    /// The name of the family as in the font book.
+    pub family: String,
+    /// The characters not covered by the family, in the order of the text.
+    pub unsupported: Vec<char>,
+    /// The faces that the unsupported characters fall back to.
+    pub fallbacks: Vec<FallbackCoverage>,
+    /// The unsupported characters which no face covers.
+    pub uncovered: Vec<char>,
+}
+
+/// List the font families known to a font resolver, sorted by their names.
+pub fn list_fonts(fonts: &dyn FontResolver) -> Vec<FontFamilyInfo> {
+    let book = fonts.font_book();
+    let mut families = BTreeMap::<&str, Vec<FontFaceInfo>>::new();
+    for (idx, info) in faces(book) {
+        families
+            .entry(info.family.as_str())
+            .or_default()
+            .push(FontFaceInfo {
+                variant: info.variant,
+                origin: fonts.font_origin(idx),
+            });
+    }
+
+    (families.into_iter())
+        .map(|(family, faces)| FontFamilyInfo {
+            family: family.to_owned(),
+            faces,
+        })
+        .collect()
+}
+
+/// Look up the characters of a text which the regular face of a font family
+/// doesn't cover, see the [module docs](self).
+///
+/// Control characters are skipped, and each character is reported once.
+/// Fails if the family is unknown.
+pub fn font_coverage(book: &FontBook, family: &str, text: &str) -> ZResult<CoverageResult> {
+    let variant = FontVariant::default();
+    let info = (book.select(&family.to_lowercase(), variant))
+        .and_then(|idx| book.info(idx))
+        .ok_or_else(|| error_once!("unknown font family", family: family))?;
+
+    let mut seen = HashSet::new();
+    let unsupported: Vec<_> = (text.chars())
+        .filter(|&c| !c.is_control() && seen.insert(c))
+        .filter(|&c| !info.coverage.contains(c as u32))
+        .collect();
+
+    let mut fallbacks: Vec<(usize, FallbackCoverage)> = vec![];
+    let mut uncovered = vec![];
+    for &c in &unsupported {
+        let fallback = book.select_fallback(Some(info), variant, c.encode_utf8(&mut [0; 4]));
+        let Some((idx, face)) = fallback.and_then(|idx| Some((idx, book.info(idx)?))) else {
+            uncovered.push(c);
+            continue;
+        };
+
+        match fallbacks.iter_mut().find(|(i, _)| *i == idx) {
+            Some((_, coverage)) => coverage.chars.push(c),
+            None => fallbacks.push((
+                idx,
+                FallbackCoverage {
+                    family: face.family.clone(),
+                    variant: face.variant,
+                    chars: vec![c],
+                },
+            )),
+        }
+    }
+
+    Ok(CoverageResult {
+        family: info.family.clone(),
+        unsupported,
+        fallbacks: fallbacks
+            .into_iter()
+            .map(|(_, coverage)| coverage)
+            .collect(),
+        uncovered,
+    })
+}
+
+/// Iterate over the faces of a font book along with their indices.
+fn faces(book: &FontBook) -> impl Iterator<Item = (usize, &FontInfo)> {
+    (0..).map_while(|idx| Some((idx, book.info(idx)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_font_coverage() {
+        let mut book = FontBook::new();
+        for data in typst_assets::fonts() {
+            for info in FontInfo::iter(data) {
+                book.push(info);
+            }
+        }
+
+        let coverage = font_coverage(&book, "Linux Libertine", "Hé 你好 𝒜\n").unwrap();
+        assert_eq!(coverage.family, "Linux Libertine");
+        assert!(!coverage.unsupported.contains(&'é'));
+        assert!(coverage.unsupported.contains(&'你'));
+        // No embedded face covers CJK, while the math font covers the
+        // script letter.
+        assert_eq!(coverage.uncovered, ['你', '好']);
+        assert!(coverage.fallbacks.iter().any(|f| f.chars.contains(&'𝒜')));
+
+        assert!(font_coverage(&book, "No Such Font", "a").is_err());
+    }
+}
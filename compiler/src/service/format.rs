@@ -0,0 +1,224 @@
+//! Format sources by pluggable formatters.
+//!
+//! Editors format a file on save. Formatting through the compiler service
+//! runs on the source of the world, including the unsaved content of shadow
+//! files, instead of a round trip through an external binary. The formatted
+//! text is reduced to the edits changing the source, so that editors apply
+//! them without moving the viewport.
+//!
+//! Formatting never mutates the world: the editor applies the edits through
+//! the memory events, like any other change of the file.
+//!
+//! The service ships the [`WhitespaceFormatter`] by default. Real formatters,
+//! e.g. typstyle or typstfmt, are plugged in by implementing
+//! [`SourceFormatter`].
+
+use std::ops::Range;
+
+use dissimilar::Chunk;
+use serde::{Deserialize, Serialize};
+use typst::syntax::{Source, SyntaxKind, SyntaxNode};
+use typst_ts_core::error::prelude::*;
+
+use super::{
+    position::{to_lsp_range, LspRange},
+    PositionEncoding,
+};
+
+/// The options of a formatting request, as sent by editors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FormatOptions {
+    /// The number of spaces of an indentation level.
+    pub tab_size: usize,
+    /// Whether to indent with spaces instead of tabs.
+    pub insert_spaces: bool,
+    /// The preferred maximum width of lines, for formatters wrapping lines.
+    pub max_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            tab_size: 2,
+            insert_spaces: true,
+            max_width: 120,
+        }
+    }
+}
+
+/// A formatter of typst sources.
+pub trait SourceFormatter: Send {
+    /// Format a source, returning the whole formatted text.
+    ///
+    /// The parse tree of the source is available by [`Source::root`].
+    fn format(&self, source: &Source, options: &FormatOptions) -> ZResult<String>;
+}
+
+/// A formatter normalizing the whitespace of a source.
+///
+/// It removes the trailing whitespace of lines, converts tabs indenting lines
+/// into spaces if [`FormatOptions::insert_spaces`] is set, and ends the
+/// source with exactly one newline. Raw blocks and strings are kept as is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WhitespaceFormatter;
+
+impl SourceFormatter for WhitespaceFormatter {
+    fn format(&self, source: &Source, options: &FormatOptions) -> ZResult<String> {
+        let text = source.text();
+        let mut verbatim = vec![];
+        verbatim_ranges(source.root(), 0, &mut verbatim);
+        let in_verbatim =
+            |offset: usize| (verbatim.iter()).any(|r| r.start < offset && offset < r.end);
+
+        let mut formatted = String::with_capacity(text.len());
+        let mut offset = 0;
+        for line in text.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+
+            let content = line.trim_end_matches(['\n', '\r']);
+            let newline = &line[content.len()..];
+            let content = if in_verbatim(start + content.len()) {
+                content
+            } else {
+                content.trim_end()
+            };
+
+            let tabs = content.len() - content.trim_start_matches('\t').len();
+            if options.insert_spaces && tabs > 0 && !in_verbatim(start) {
+                formatted.push_str(&" ".repeat(tabs * options.tab_size));
+                formatted.push_str(&content[tabs..]);
+            } else {
+                formatted.push_str(content);
+            }
+            formatted.push_str(newline);
+        }
+
+        let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        formatted.truncate(formatted.trim_end_matches(['\n', '\r']).len());
+        if !formatted.is_empty() {
+            formatted.push_str(newline);
+        }
+        Ok(formatted)
+    }
+}
+
+/// Collect the byte ranges of the nodes whose whitespace is significant.
+fn verbatim_ranges(node: &SyntaxNode, offset: usize, ranges: &mut Vec<Range<usize>>) {
+    if matches!(node.kind(), SyntaxKind::Raw | SyntaxKind::Str) {
+        ranges.push(offset..offset + node.len());
+        return;
+    }
+
+    let mut offset = offset;
+    for child in node.children() {
+        verbatim_ranges(child, offset, ranges);
+        offset += child.len();
+    }
+}
+
+/// An edit replacing a range of a source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextEdit {
+    /// The replaced range in positions.
+    pub range: LspRange,
+    /// The replaced range in bytes.
+    pub byte_range: Range<usize>,
+    /// The text replacing the range.
+    pub new_text: String,
+}
+
+/// Format a source, returning the edits turning the source into the
+/// formatted text, see the [module docs](self).
+pub fn format_source(
+    formatter: &dyn SourceFormatter,
+    source: &Source,
+    options: &FormatOptions,
+    encoding: PositionEncoding,
+) -> ZResult<Vec<TextEdit>> {
+    let formatted = formatter.format(source, options)?;
+    Ok(text_edits(source, &formatted, encoding))
+}
+
+/// Compute the minimal edits turning a source into the given text.
+///
+/// The edits are ordered and don't overlap, and their ranges refer to the
+/// original source.
+pub fn text_edits(source: &Source, new_text: &str, encoding: PositionEncoding) -> Vec<TextEdit> {
+    let mut changes: Vec<(Range<usize>, String)> = vec![];
+    let mut offset = 0;
+    let mut in_change = false;
+    for chunk in dissimilar::diff(source.text(), new_text) {
+        let (deleted, inserted) = match chunk {
+            Chunk::Equal(text) => {
+                offset += text.len();
+                in_change = false;
+                continue;
+            }
+            Chunk::Delete(text) => (text.len(), ""),
+            Chunk::Insert(text) => (0, text),
+        };
+
+        if !in_change {
+            changes.push((offset..offset, String::new()));
+            in_change = true;
+        }
+        let (range, text) = changes.last_mut().unwrap();
+        offset += deleted;
+        range.end = offset;
+        text.push_str(inserted);
+    }
+
+    (changes.into_iter())
+        .map(|(byte_range, new_text)| TextEdit {
+            range: to_lsp_range(source, byte_range.clone(), encoding),
+            byte_range,
+            new_text,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whitespace_formatter() {
+        let text = "= Title  \n\tindented\n```\ntrailing  \n```\n#\"spaces  \n\"\n\n\n";
+        let source = Source::detached(text);
+        let options = FormatOptions::default();
+
+        let formatted = WhitespaceFormatter.format(&source, &options).unwrap();
+        assert_eq!(
+            formatted,
+            "= Title\n  indented\n```\ntrailing  \n```\n#\"spaces  \n\"\n"
+        );
+
+        // Only the changed whitespace is edited.
+        let edits =
+            format_source(&WhitespaceFormatter, &source, &options, Default::default()).unwrap();
+        assert_eq!(edits.len(), 3, "{edits:?}");
+        assert!(edits.iter().all(|edit| {
+            let replaced = &text[edit.byte_range.clone()];
+            replaced.trim().is_empty() && edit.new_text.trim().is_empty()
+        }));
+        assert_eq!(edits[0].range.start.line, 0);
+        assert_eq!(edits[2].range.start.line, 7);
+
+        let mut applied = text.to_owned();
+        for edit in edits.iter().rev() {
+            applied.replace_range(edit.byte_range.clone(), &edit.new_text);
+        }
+        assert_eq!(applied, formatted);
+
+        let edits = format_source(
+            &WhitespaceFormatter,
+            &Source::detached(formatted),
+            &options,
+            Default::default(),
+        );
+        assert!(edits.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+//! Compile a selected fragment of a source file into a standalone document.
+//!
+//! Editors preview just the selected lines of a long document. The selection
+//! is compiled as a transient main file next to the source file, so that the
+//! relative imports of the selection are resolved as in the source file. The
+//! setup at the start of the source file, i.e. the set and show rules, the
+//! imports and the bindings before the selection, is prepended to the
+//! selection, so that the fragment looks like it does in the document.
+//!
+//! The transient main only exists in the [`FragmentWorld`], hence compiling a
+//! fragment never disturbs the main file and the compilations of the world.
+
+use std::ops::Range;
+
+use comemo::Prehashed;
+use typst::{
+    diag::{EcoString, FileResult, Severity},
+    eval::Tracer,
+    foundations::Datetime,
+    syntax::{Source, SyntaxKind, VirtualPath},
+    text::{Font, FontBook},
+    Library, World,
+};
+use typst_ts_core::{
+    error::prelude::*, package::PackageSpec, Bytes, TypstDocument, TypstFileId as FileId,
+};
+
+use super::{
+    layout::{compile_with_layout, PageOverride, DEFAULT_ITERATION_LIMIT},
+    limits::CompileLimits,
+};
+
+/// A world whose main file is a fragment of one of its source files, see the
+/// [module docs](self).
+pub struct FragmentWorld<'a> {
+    world: &'a dyn World,
+    main: Source,
+}
+
+impl<'a> FragmentWorld<'a> {
+    /// Create a world compiling the bytes in `range` of the source file
+    /// `file`, along with the setup of the file before the range.
+    pub fn new(world: &'a dyn World, file: FileId, range: Range<usize>) -> ZResult<Self> {
+        let source = (world.source(file)).map_err(map_string_err("failed to load the source"))?;
+        let text = source.text();
+        let valid = range.start <= range.end
+            && range.end <= text.len()
+            && text.is_char_boundary(range.start)
+            && text.is_char_boundary(range.end);
+        if !valid {
+            return Err(error_once!(
+                "invalid range of the fragment",
+                range: format!("{range:?}"),
+                len: text.len()
+            ));
+        }
+
+        let preamble = &text[..preamble_end(&source, range.start)];
+        let fragment = format!("{preamble}\n{}", &text[range]);
+
+        // A sibling of the source file, so that relative paths are resolved
+        // in the same directory.
+        let path = file.vpath().as_rootless_path();
+        let mut name = path.file_name().unwrap_or_default().to_owned();
+        name.push(".fragment.typ");
+        let id = FileId::new(
+            file.package().cloned(),
+            VirtualPath::new(path.with_file_name(name)),
+        );
+
+        Ok(Self {
+            world,
+            main: Source::new(id, fragment),
+        })
+    }
+}
+
+impl World for FragmentWorld<'_> {
+    fn library(&self) -> &Prehashed<Library> {
+        self.world.library()
+    }
+
+    fn book(&self) -> &Prehashed<FontBook> {
+        self.world.book()
+    }
+
+    fn main(&self) -> Source {
+        self.main.clone()
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        if id == self.main.id() {
+            return Ok(self.main.clone());
+        }
+        self.world.source(id)
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        self.world.file(id)
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        self.world.font(index)
+    }
+
+    fn today(&self, offset: Option<i64>) -> Option<Datetime> {
+        self.world.today(offset)
+    }
+
+    fn packages(&self) -> &[(PackageSpec, Option<EcoString>)] {
+        self.world.packages()
+    }
+}
+
+/// Compile the bytes in `range` of the source file `file` into a standalone
+/// document, see the [module docs](self).
+///
+/// An empty selection has nothing to preview, hence gets `None`. A range
+/// splitting a construct, e.g. a function call, usually fails to compile, and
+/// the errors are reported in the returned error.
+pub fn compile_fragment(
+    world: &dyn World,
+    file: FileId,
+    range: Range<usize>,
+    iteration_limit: Option<usize>,
+    page_override: Option<PageOverride>,
+) -> ZResult<Option<TypstDocument>> {
+    let fragment = FragmentWorld::new(world, file, range.clone())?;
+    let text = world
+        .source(file)
+        .map(|source| source.text()[range].to_owned());
+    if text.is_ok_and(|text| text.trim().is_empty()) {
+        return Ok(None);
+    }
+
+    let mut tracer = Tracer::new();
+    let limit = iteration_limit.unwrap_or(DEFAULT_ITERATION_LIMIT);
+    let limits = CompileLimits::default();
+    match compile_with_layout(&fragment, &mut tracer, limit, page_override, &limits) {
+        Ok(doc) => Ok(Some(doc)),
+        Err(diags) => {
+            let errors: Vec<_> = (diags.iter())
+                .filter(|diag| diag.severity == Severity::Error)
+                .map(|diag| diag.message.as_str())
+                .collect();
+            Err(error_once!("failed to compile the fragment", errors: errors.join("; ")))
+        }
+    }
+}
+
+/// Find the end of the setup at the start of a source file, i.e. the leading
+/// set and show rules, imports and bindings which end before `before`.
+fn preamble_end(source: &Source, before: usize) -> usize {
+    let mut offset = 0;
+    let mut end = 0;
+    for child in source.root().children() {
+        let next = offset + child.len();
+        if next > before {
+            break;
+        }
+
+        match child.kind() {
+            SyntaxKind::Hash
+            | SyntaxKind::Space
+            | SyntaxKind::Parbreak
+            | SyntaxKind::LineComment
+            | SyntaxKind::BlockComment => {}
+            SyntaxKind::SetRule
+            | SyntaxKind::ShowRule
+            | SyntaxKind::ModuleImport
+            | SyntaxKind::LetBinding => end = next,
+            _ => break,
+        }
+        offset = next;
+    }
+    end
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use typst::{
+        foundations::{Element, Selector},
+        model::HeadingElem,
+    };
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{outline::heading_text, CompileEnv, Compiler},
+    };
+
+    #[test]
+    fn test_compile_fragment() {
+        let ws = TestWorkspace::new();
+        let content = "#let title = [Heading]\n#set heading(numbering: none)\nIntro\n= #title\nBody #emph[text]";
+
+        let mut driver = ws.shadow_driver(content.as_bytes());
+        // The fragments are compiled with the library of the last compilation.
+        driver.compile(&mut CompileEnv::default()).unwrap();
+        let file = FileId::new(None, VirtualPath::new("main.typ"));
+        let range_of = |needle: &str| {
+            let start = content.find(needle).unwrap();
+            start..start + needle.len()
+        };
+
+        // The heading line uses a binding of the preamble.
+        let doc = compile_fragment(&driver.world, file, range_of("= #title"), None, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(doc.pages.len(), 1);
+        let headings =
+            (doc.introspector).query(&Selector::Elem(Element::of::<HeadingElem>(), None));
+        assert_eq!(headings.len(), 1);
+        assert_eq!(heading_text(&headings[0]), "Heading");
+
+        // A selection splitting a construct reports the errors.
+        let err = compile_fragment(&driver.world, file, range_of("#emph[te"), None, None)
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().contains("failed to compile the fragment"),
+            "{err}"
+        );
+
+        assert!(compile_fragment(&driver.world, file, 0..0, None, None)
+            .unwrap()
+            .is_none());
+        assert!(compile_fragment(&driver.world, file, 0..content.len() + 1, None, None).is_err());
+    }
+}
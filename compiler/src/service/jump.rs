@@ -0,0 +1,348 @@
+//! Jump between the sources and the documents compiled from them.
+//!
+//! The glyphs of a laid out text keep the spans of the source text, hence a
+//! cursor in a source is resolved to the positions of its glyphs in the
+//! document, and a point on a page to the span of the glyph under it.
+
+use std::{num::NonZeroUsize, ops::Range};
+
+#[cfg(debug_assertions)]
+use typst::World;
+use typst::{
+    layout::{Abs, Frame, FrameItem, Point, Position, Size},
+    syntax::{LinkedNode, Source, Span, SyntaxKind},
+};
+#[cfg(debug_assertions)]
+use typst_ts_core::error::prelude::*;
+use typst_ts_core::{flatten, TypstDocument, TypstFileId};
+
+/// Find the leaf syntax node at a byte offset of a source, returning its kind
+/// and byte range.
+///
+/// An offset at the boundary of two nodes belongs to the former one.
+pub fn span_at_offset(source: &Source, offset: usize) -> Option<(SyntaxKind, Range<usize>)> {
+    let node = LinkedNode::new(source.root()).leaf_at(offset)?;
+    Some((node.kind(), node.range()))
+}
+
+/// The options of [`jump_from_cursor_with`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JumpOptions {
+    /// Whether to skip the texts whose first glyphs are from other files than
+    /// the cursor without scanning their glyphs, which is disabled by default.
+    ///
+    /// It saves scanning most of the pages of a large document, e.g. a book
+    /// including its chapters, but misses the glyphs of the file in a text
+    /// starting in another file, e.g. a paragraph starting with the result of
+    /// a function of another file.
+    pub skip_other_files: bool,
+}
+
+/// Find the output location in the document for a cursor position.
+pub fn jump_from_cursor(
+    document: &TypstDocument,
+    source: &Source,
+    cursor: usize,
+) -> Option<Position> {
+    jump_from_cursor_with(document, source, cursor, JumpOptions::default())
+}
+
+/// Find the output location in the document for a cursor position, see
+/// [`JumpOptions`].
+pub fn jump_from_cursor_with(
+    document: &TypstDocument,
+    source: &Source,
+    cursor: usize,
+    options: JumpOptions,
+) -> Option<Position> {
+    let node = LinkedNode::new(source.root()).leaf_at(cursor)?;
+    if node.kind() != SyntaxKind::Text {
+        return None;
+    }
+    jump_to_span(document, node.span(), options).0
+}
+
+/// Find the position of a span in a document, along with the number of the
+/// texts whose glyphs are scanned.
+pub(crate) fn jump_to_span(
+    document: &TypstDocument,
+    span: Span,
+    options: JumpOptions,
+) -> (Option<Position>, usize) {
+    // The exact glyph of the span, or otherwise the nearest glyph of the same
+    // file, at the start of its baseline on the page.
+    let mut nearest = None;
+    let mut scanned = 0;
+    for (placed, text) in flatten::text_items(document) {
+        if options.skip_other_files && placed.span.id() != span.id() {
+            continue;
+        }
+        scanned += 1;
+
+        let mut x = Abs::zero();
+        for glyph in &text.glyphs {
+            let point = placed.to_page(Point::with_x(x));
+            if glyph.span.0 == span {
+                let page = NonZeroUsize::new(placed.page);
+                return (page.map(|page| Position { page, point }), scanned);
+            }
+            if glyph.span.0.id() == span.id() {
+                let dis = glyph.span.0.number().abs_diff(span.number());
+                if nearest.is_none_or(|(min_dis, _, _)| dis < min_dis) {
+                    nearest = Some((dis, placed.page, point));
+                }
+            }
+            x += glyph.x_advance.at(text.size);
+        }
+    }
+
+    let position = nearest.and_then(|(_, page, point)| {
+        Some(Position {
+            page: NonZeroUsize::new(page)?,
+            point,
+        })
+    });
+    (position, scanned)
+}
+
+/// Find the positions of all the occurrences of the text at a cursor in a
+/// document, in the order of the pages.
+///
+/// Unlike [`jump_from_cursor`], only the exact glyphs of the span are taken.
+/// An occurrence is at the start of the baseline of its first glyph, and a
+/// text broken into lines has an occurrence in each of them.
+pub fn jump_all_from_cursor(
+    document: &TypstDocument,
+    source: &Source,
+    cursor: usize,
+) -> Vec<Position> {
+    let Some(node) = LinkedNode::new(source.root()).leaf_at(cursor) else {
+        return vec![];
+    };
+    if node.kind() != SyntaxKind::Text {
+        return vec![];
+    }
+
+    let span = node.span();
+    let mut positions = vec![];
+    for (placed, text) in flatten::text_items(document) {
+        let Some(page) = NonZeroUsize::new(placed.page) else {
+            continue;
+        };
+
+        // The glyphs of an occurrence are consecutive, even if the texts of
+        // several occurrences are shaped together.
+        let mut x = Abs::zero();
+        let mut in_occurrence = false;
+        for glyph in &text.glyphs {
+            let matched = glyph.span.0 == span;
+            if matched && !in_occurrence {
+                let point = placed.to_page(Point::with_x(x));
+                positions.push(Position { page, point });
+            }
+            in_occurrence = matched;
+            x += glyph.x_advance.at(text.size);
+        }
+    }
+    positions
+}
+
+/// The caret of a cursor in a document, see [`caret_from_cursor`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaretPosition {
+    /// The caret on the baseline of the text.
+    pub position: Position,
+    /// The height of the caret above the baseline, i.e. the ascender of the
+    /// font.
+    pub ascent: Abs,
+    /// The height of the caret bar, i.e. the ascender plus the descender of
+    /// the font.
+    pub height: Abs,
+}
+
+/// Find the caret of a cursor in a document, where the cursor is inside or at
+/// the end of a text.
+///
+/// Unlike [`jump_from_cursor`], the caret is placed at the offset of the
+/// cursor in the text, which is interpolated within a glyph shaped from more
+/// than one character, e.g. a ligature. The first occurrence of the text is
+/// taken, as in [`jump_all_from_cursor`].
+pub fn caret_from_cursor(
+    document: &TypstDocument,
+    source: &Source,
+    cursor: usize,
+) -> Option<CaretPosition> {
+    let node = LinkedNode::new(source.root()).leaf_at(cursor)?;
+    if node.kind() != SyntaxKind::Text {
+        return None;
+    }
+    let span = node.span();
+    let offset = cursor.saturating_sub(node.offset()).min(node.len());
+
+    // The glyph of the span starting last before the cursor, along with its
+    // page, its start on the baseline, its advance and its text.
+    let mut caret: Option<(usize, _, _, _, _, _)> = None;
+    for (placed, text) in flatten::text_items(document) {
+        let mut x = Abs::zero();
+        for (i, glyph) in text.glyphs.iter().enumerate() {
+            let advance = glyph.x_advance.at(text.size);
+            let start = usize::from(glyph.span.1);
+            if glyph.span.0 == span
+                && start <= offset
+                && caret.as_ref().is_none_or(|(prev, ..)| start > *prev)
+            {
+                // The glyph spans the text until the next glyph of the span.
+                let end = (text.glyphs[i + 1..].iter())
+                    .find(|next| next.span.0 == span)
+                    .map_or(node.len(), |next| usize::from(next.span.1));
+                caret = Some((
+                    start,
+                    end,
+                    placed.page,
+                    placed.to_page(Point::with_x(x)),
+                    advance,
+                    text,
+                ));
+            }
+            x += advance;
+        }
+    }
+
+    let (start, end, page, point, advance, text) = caret?;
+    let ratio = if end > start {
+        (offset - start).min(end - start) as f64 / (end - start) as f64
+    } else {
+        0.
+    };
+    let metrics = text.font.metrics();
+    let ascent = metrics.ascender.at(text.size);
+    let descent = -metrics.descender.at(text.size);
+    Some(CaretPosition {
+        position: Position {
+            page: NonZeroUsize::new(page)?,
+            point: Point::new(point.x + advance * ratio, point.y),
+        },
+        ascent,
+        height: ascent + descent,
+    })
+}
+
+/// A glyph on a page along with its span, see [`dump_spans`].
+#[cfg(debug_assertions)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanDump {
+    /// The start of the baseline of the glyph on the page.
+    pub point: Point,
+    /// The number of the span, see [`Span::number`].
+    pub number: u64,
+    /// The file of the span, or `None` if the span is detached.
+    pub file_id: Option<TypstFileId>,
+    /// The source text of the span, or `None` if it isn't resolved.
+    pub text: Option<String>,
+}
+
+/// Dump the spans of the glyphs on a page of a document, e.g. to debug why a
+/// jump fails.
+///
+/// The page is 1-based, as in [`Position`]. Unlike [`jump_from_cursor`],
+/// the raw spans are kept, including the detached ones.
+#[cfg(debug_assertions)]
+pub fn dump_spans(
+    world: &dyn World,
+    document: &TypstDocument,
+    page: usize,
+) -> ZResult<Vec<SpanDump>> {
+    if page == 0 || page > document.pages.len() {
+        return Err(error_once!("the page is out of the document", page: page));
+    }
+
+    let text_of = |span: Span| {
+        let source = world.source(span.id()?).ok()?;
+        let range = source.range(span)?;
+        Some(source.text()[range].to_owned())
+    };
+
+    let mut dumps = vec![];
+    for (placed, text) in flatten::text_items(document) {
+        if placed.page != page {
+            continue;
+        }
+
+        let mut x = Abs::zero();
+        for glyph in &text.glyphs {
+            let span = glyph.span.0;
+            dumps.push(SpanDump {
+                point: placed.to_page(Point::with_x(x)),
+                number: span.number(),
+                file_id: span.id(),
+                text: text_of(span),
+            });
+            x += glyph.x_advance.at(text.size);
+        }
+    }
+    Ok(dumps)
+}
+
+/// Find the span and the byte offset into it of the glyph under a point in a
+/// frame.
+pub fn span_from_point(frame: &Frame, click: Point) -> Option<(Span, usize)> {
+    for (mut pos, item) in frame.items().rev() {
+        match item {
+            FrameItem::Group(group) => {
+                let pos = click - pos;
+                let pos = match group.transform.invert() {
+                    Some(inv) => pos.transform(inv),
+                    None => continue,
+                };
+                if let Some(hit) = span_from_point(&group.frame, pos) {
+                    return Some(hit);
+                }
+            }
+            FrameItem::Text(text) => {
+                for glyph in &text.glyphs {
+                    let width = glyph.x_advance.at(text.size);
+                    let origin = Point::new(pos.x, pos.y - text.size);
+                    let size = Size::new(width, text.size);
+                    // A detached glyph may overlap items below it, e.g. a
+                    // generated number over the text.
+                    let (span, offset) = glyph.span;
+                    if is_in_rect(origin, size, click) && !span.is_detached() {
+                        return Some((span, offset as usize));
+                    }
+                    pos.x += width;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Get the 1-based pages on which a source file renders, i.e. which have the
+/// glyphs or the other items spanned in the file, e.g. to navigate to where an
+/// included file renders.
+pub fn pages_for_file(document: &TypstDocument, id: TypstFileId) -> Vec<usize> {
+    let mut pages = vec![];
+    for placed in flatten::flatten_frames(document) {
+        if pages.last() == Some(&placed.page) {
+            continue;
+        }
+        let spanned = match placed.item {
+            FrameItem::Text(text) => {
+                (text.glyphs.iter()).any(|glyph| glyph.span.0.id() == Some(id))
+            }
+            _ => placed.span.id() == Some(id),
+        };
+        if spanned {
+            pages.push(placed.page);
+        }
+    }
+    pages
+}
+
+/// Whether a rectangle with the given size at the given position contains the
+/// click position.
+fn is_in_rect(pos: Point, size: Size, click: Point) -> bool {
+    pos.x <= click.x && pos.x + size.x >= click.x && pos.y <= click.y && pos.y + size.y >= click.y
+}
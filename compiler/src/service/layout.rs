@@ -0,0 +1,325 @@
+//! Compile documents with a limited number of layout iterations.
+//!
+//! Typst relayouts a document until all the introspections, e.g. counters and
+//! states, stabilize, giving up after five attempts. This module mirrors
+//! [`typst::compile`] with a configurable limit, so that a document which
+//! never converges fails fast instead of keeping the compiler busy. It also
+//! lays out documents with another default page size, see [`PageOverride`],
+//! and checks the resource limits, see [`CompileLimits`].
+
+use std::collections::HashSet;
+
+use comemo::{Track, Tracked, Validate};
+use typst::{
+    diag::{SourceDiagnostic, SourceResult},
+    engine::{Engine, Route},
+    eval::Tracer,
+    foundations::{Content, SequenceElem, Smart, StyleChain, StyledElem, Styles},
+    introspection::{Introspector, Locator},
+    layout::{Abs, LayoutRoot, PageElem},
+    model::Document,
+    syntax::Span,
+    World,
+};
+use typst_ts_core::{hash::hash128, typst::prelude::*};
+
+use super::{
+    limits::{self, CompileLimits, LimitedWorld},
+    progress::CompileStage,
+};
+
+/// The number of layout iterations of [`typst::compile`].
+pub const DEFAULT_ITERATION_LIMIT: usize = 5;
+
+/// An override of the page size to lay out a document with, e.g. for
+/// continuous previews.
+///
+/// The override is applied after the page set rules of a document and the
+/// arguments of its explicit pages, so that it forces the size of all the
+/// pages, see [`PageOverride::force`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageOverride {
+    /// Use the width and height of pages if set.
+    Size {
+        width: Option<Abs>,
+        height: Option<Abs>,
+    },
+    /// Lay out the document in a single page growing with its content.
+    /// Explicit page breaks still start new pages.
+    AutoHeight,
+}
+
+impl PageOverride {
+    pub(crate) fn styles(&self) -> Styles {
+        let length = |abs: Abs| Smart::Custom(abs.into());
+
+        let mut styles = Styles::new();
+        match *self {
+            Self::Size { width, height } => {
+                if let Some(width) = width {
+                    styles.set(PageElem::set_width(length(width)));
+                }
+                if let Some(height) = height {
+                    styles.set(PageElem::set_height(length(height)));
+                }
+            }
+            Self::AutoHeight => styles.set(PageElem::set_height(Smart::Auto)),
+        }
+        styles
+    }
+
+    /// Force the override on the evaluated content of a document.
+    ///
+    /// The styles of the override are applied inside each styled part of the
+    /// content, which holds the set rules of the document, and the explicit
+    /// pages get the overridden sizes as their arguments. Pages can't be
+    /// nested in other elements, hence only sequences are walked into.
+    pub(crate) fn force(&self, content: &Content) -> Content {
+        if let Some(sequence) = content.to_packed::<SequenceElem>() {
+            let children = sequence.children.iter().map(|child| self.force(child));
+            return Content::sequence(children);
+        }
+        if let Some(styled) = content.to_packed::<StyledElem>() {
+            return (self.force(&styled.child))
+                .styled_with_map(self.styles())
+                .styled_with_map(styled.styles.clone());
+        }
+
+        let mut content = content.clone();
+        if let Some(page) = content.to_packed_mut::<PageElem>() {
+            let length = |abs: Abs| Smart::Custom(abs.into());
+            match *self {
+                Self::Size { width, height } => {
+                    if let Some(width) = width {
+                        page.push_width(length(width));
+                    }
+                    if let Some(height) = height {
+                        page.push_height(length(height));
+                    }
+                }
+                Self::AutoHeight => page.push_height(Smart::Auto),
+            }
+        }
+        content
+    }
+}
+
+/// Compile the main source of the world, relayouting at most `limit` times.
+///
+/// If the layout doesn't converge within the limit, the last iteration is
+/// used and a warning is reported to the tracer.
+pub fn compile_with_iteration_limit(
+    world: &dyn World,
+    tracer: &mut Tracer,
+    limit: usize,
+) -> SourceResult<Document> {
+    compile_with_layout(world, tracer, limit, None, &CompileLimits::default())
+}
+
+/// Compile the main source of the world like [`compile_with_iteration_limit`],
+/// overriding the page size if set and aborting on a violation of the limits.
+pub fn compile_with_layout(
+    world: &dyn World,
+    tracer: &mut Tracer,
+    limit: usize,
+    page_override: Option<PageOverride>,
+    limits: &CompileLimits,
+) -> SourceResult<Document> {
+    compile_with_progress(world, tracer, limit, page_override, limits, &|_| {})
+}
+
+/// Compile the main source of the world like [`compile_with_layout`],
+/// reporting the evaluation and each layout iteration to `progress`, see
+/// [`super::progress`].
+pub fn compile_with_progress(
+    world: &dyn World,
+    tracer: &mut Tracer,
+    limit: usize,
+    page_override: Option<PageOverride>,
+    limits: &CompileLimits,
+    progress: &dyn Fn(CompileStage),
+) -> SourceResult<Document> {
+    let limited = LimitedWorld::new(world, limits);
+    // Track the world just once to keep comemo's id stable.
+    let world = (&limited as &dyn World).track();
+
+    progress(CompileStage::Eval);
+    let module = typst::eval::eval(
+        world,
+        Route::default().track(),
+        tracer.track_mut(),
+        &world.main(),
+    )
+    .map_err(|errors| deduplicate(limited.explain_errors("evaluation", errors)))?;
+
+    let content = module.content();
+    limited.check_time("evaluation", Span::detached())?;
+    limited.check_elements(&content)?;
+
+    let (content, overrides) = match page_override {
+        Some(page) => (page.force(&content), page.styles()),
+        None => (content, Styles::new()),
+    };
+    let limit = limit.max(1);
+    typeset(
+        world, &limited, tracer, &content, &overrides, limit, progress,
+    )
+    .map_err(deduplicate)
+}
+
+fn typeset(
+    world: Tracked<dyn World + '_>,
+    limited: &LimitedWorld,
+    tracer: &mut Tracer,
+    content: &Content,
+    overrides: &Styles,
+    limit: usize,
+    progress: &dyn Fn(CompileStage),
+) -> SourceResult<Document> {
+    let library = world.library();
+    let base = StyleChain::new(&library.styles);
+    let styles = base.chain(overrides);
+
+    let mut iter = 0;
+    let mut document = Document::default();
+
+    // Relayout until all introspections stabilize.
+    loop {
+        // Clear delayed errors.
+        tracer.delayed();
+        progress(CompileStage::Layout {
+            iteration: iter + 1,
+        });
+
+        let constraint = <Introspector as Validate>::Constraint::new();
+        let mut locator = Locator::new();
+        let mut engine = Engine {
+            world,
+            route: Route::default(),
+            tracer: tracer.track_mut(),
+            locator: &mut locator,
+            introspector: document.introspector.track_with(&constraint),
+        };
+
+        document = (content.layout_root(&mut engine, styles))
+            .map_err(|errors| limited.explain_errors("layout", errors))?;
+        document.introspector.rebuild(&document.pages);
+        limited.check_time("layout", limits::last_span(&document))?;
+        limited.check_pages(&document)?;
+
+        iter += 1;
+        if document.introspector.validate(&constraint) {
+            break;
+        }
+
+        if iter >= limit {
+            tracer.warn(
+                SourceDiagnostic::warning(
+                    Span::detached(),
+                    eco_format!("layout did not converge within {limit} attempts"),
+                )
+                .with_hint("check if any states or queries are updating themselves"),
+            );
+            break;
+        }
+    }
+
+    // Promote delayed errors.
+    let delayed = tracer.delayed();
+    if !delayed.is_empty() {
+        return Err(delayed);
+    }
+
+    Ok(document)
+}
+
+/// Remove the diagnostics reported at the same span with the same message,
+/// e.g. by multiple layout iterations.
+fn deduplicate(mut diags: EcoVec<SourceDiagnostic>) -> EcoVec<SourceDiagnostic> {
+    let mut unique = HashSet::new();
+    diags.retain(|diag| unique.insert(hash128(&(&diag.span, &diag.message))));
+    diags
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use typst::foundations::Bytes;
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileDriver, CompileEnv, Compiler},
+        ShadowApi,
+    };
+
+    #[test]
+    fn test_iteration_limit() {
+        let ws = TestWorkspace::new();
+        let main = ws.path("main.typ");
+        let mut driver = ws.driver();
+
+        let mut compile = |content: &'static str| {
+            let content = Bytes::from_static(content.as_bytes());
+            driver.map_shadow(&main, content).unwrap();
+            let mut env = CompileEnv {
+                tracer: Some(Tracer::new()),
+                ..CompileEnv::default()
+            }
+            .with_layout_iteration_limit(Some(2));
+            driver.compile(&mut env).unwrap();
+            env.tracer.unwrap().warnings()
+        };
+
+        // The state is updated by its own final value, which never converges.
+        let warnings = compile("#let s = state(\"s\", 0)\n#context s.update(s.final() + 1)");
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert!(warnings[0].message.contains("within 2 attempts"));
+
+        let warnings = compile("#let s = state(\"s\", 0)\n#s.update(1)\n#context s.final()");
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+
+    #[test]
+    fn test_time_budget_during_eval() {
+        use std::time::{Duration, Instant};
+
+        use typst::{World, WorldExt};
+
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let main = ws.path("main.typ");
+        let world = ws.world();
+        let limits = CompileLimits::default().with_time_budget(Some(Duration::from_millis(200)));
+        let mut driver = CompileDriver::new(world)
+            .with_entry_file(main.clone())
+            .with_limits(limits);
+
+        // The loop would run for ages, but each iteration imports a module.
+        let content = concat!(
+            "#for i in range(100000) {\n",
+            "  for j in range(100000) {\n",
+            "    import \"lib.typ\": x\n",
+            "  }\n",
+            "}",
+        );
+        driver
+            .map_shadow(&main, Bytes::from_static(content.as_bytes()))
+            .unwrap();
+        (driver.map_shadow(&root.join("lib.typ"), Bytes::from_static(b"#let x = 1"))).unwrap();
+
+        let start = Instant::now();
+        let errors = driver.compile(&mut CompileEnv::default()).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(30));
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(
+            errors[0].message.contains("evaluation exceeded"),
+            "{errors:?}"
+        );
+
+        // The error is at the import in the loop.
+        let world = driver.world();
+        let source = world.source(errors[0].span.id().unwrap()).unwrap();
+        let range = world.range(errors[0].span).unwrap();
+        assert_eq!(&source.text()[range], "\"lib.typ\"");
+    }
+}
@@ -0,0 +1,176 @@
+//! Customize the standard library of a world, e.g. to add a `company-data()`
+//! lookup implemented in Rust, or to shadow the functions which the documents
+//! shouldn't call.
+//!
+//! A [`LibraryHook`] runs once whenever the library is built, i.e. at the
+//! first compilation and whenever the inputs or the hook change, see
+//! [`crate::world::CompilerWorld::set_library_hook`]. It may define bindings
+//! in the global scope, which shadow the standard definitions of the same
+//! names, and set the default styles of the library. The prelude is applied
+//! over the hooked library, see [`super::prelude`].
+//!
+//! A native function made by [`native_func`] reports its errors as
+//! diagnostics at the call by the span of its arguments, while the errors of
+//! casting an argument point to that argument:
+//!
+//! ```ignore
+//! fn company_data(_: &mut Engine, _: Tracked<Context>, args: &mut Args) -> SourceResult<Value> {
+//!     let key: Str = args.expect("key")?;
+//!     args.take().finish()?;
+//!     match key.as_str() {
+//!         "name" => Ok("ACME".into_value()),
+//!         _ => bail!(args.span, "unknown company data `{key}`"),
+//!     }
+//! }
+//!
+//! let company_data = native_func("company-data", company_data);
+//! let hook = LibraryHook::new(move |library| {
+//!     let scope = library.global.scope_mut();
+//!     scope.define("company-data", company_data.clone());
+//! });
+//! ```
+
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use comemo::Tracked;
+use once_cell::sync::Lazy;
+use typst::{
+    diag::SourceResult,
+    engine::Engine,
+    foundations::{Args, CastInfo, Context, Func, NativeFuncData, Scope, Value},
+    Library,
+};
+
+/// A hook customizing the library of a world, see the [module docs](self).
+///
+/// Hooks are compared by their identities: the clones of a hook are the same
+/// hook, while the hooks made separately differ even if they do the same.
+#[derive(Clone)]
+pub struct LibraryHook {
+    id: u64,
+    hook: Arc<dyn Fn(&mut Library) + Send + Sync>,
+}
+
+impl LibraryHook {
+    pub fn new(hook: impl Fn(&mut Library) + Send + Sync + 'static) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            hook: Arc::new(hook),
+        }
+    }
+
+    /// Customize a library built from the standard one.
+    pub fn apply(&self, library: &mut Library) {
+        (self.hook)(library)
+    }
+}
+
+impl fmt::Debug for LibraryHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LibraryHook")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for LibraryHook {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for LibraryHook {}
+
+impl Hash for LibraryHook {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+/// The signature of a native function, see [`native_func`].
+pub type NativeFn = fn(&mut Engine, Tracked<Context>, &mut Args) -> SourceResult<Value>;
+
+/// Wrap a Rust function as a typst function of the name.
+///
+/// The data of the function is leaked, hence a function should be made once
+/// and cloned into the hook rather than made on every build of the library.
+pub fn native_func(name: &'static str, function: NativeFn) -> Func {
+    let data: &'static NativeFuncData = Box::leak(Box::new(NativeFuncData {
+        function,
+        name,
+        title: name,
+        docs: "",
+        keywords: &[],
+        contextual: false,
+        scope: Lazy::new(Scope::new),
+        params: Lazy::new(Vec::new),
+        returns: Lazy::new(|| CastInfo::Any),
+    }));
+    Func::from(data)
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use typst::{
+        diag::At,
+        foundations::{Bytes, IntoValue, Str},
+    };
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileDriver, CompileEnv, Compiler, EntryManager},
+        ShadowApi,
+    };
+
+    fn greet(_: &mut Engine, _: Tracked<Context>, args: &mut Args) -> SourceResult<Value> {
+        let name: Str = args.expect("name")?;
+        args.take().finish()?;
+        if name.is_empty() {
+            return Err("the name is empty").at(args.span);
+        }
+        Ok(format!("Hello, {name}!").into_value())
+    }
+
+    #[test]
+    fn test_library_hook() {
+        let ws = TestWorkspace::new();
+        let main = ws.path("main.typ");
+        let greet = native_func("greet", greet);
+        let world = ws
+            .world()
+            .with_library_hook(LibraryHook::new(move |library| {
+                library.global.scope_mut().define("greet", greet.clone());
+            }));
+        let mut driver = CompileDriver::new(world).with_entry_file(main.clone());
+
+        driver
+            .map_shadow(
+                &main,
+                Bytes::from_static(b"#metadata(greet(\"typst\")) <greeting>"),
+            )
+            .unwrap();
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+        let greeting = driver.query("<greeting>".to_owned(), &doc).unwrap();
+        assert!(
+            format!("{greeting:?}").contains("Hello, typst!"),
+            "{greeting:?}"
+        );
+
+        // The errors of the function are reported at the call.
+        driver
+            .map_shadow(&main, Bytes::from_static(b"#greet(\"\")"))
+            .unwrap();
+        let diags = driver.compile(&mut CompileEnv::default()).unwrap_err();
+        assert_eq!(diags[0].message.as_str(), "the name is empty");
+        assert_eq!(diags[0].span.id(), driver.world().main_id());
+    }
+}
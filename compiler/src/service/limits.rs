@@ -0,0 +1,308 @@
+//! Limit the resources used by compilations.
+//!
+//! A document with a loop generating huge content may keep the compiler busy
+//! or exhaust the memory, e.g. in a multi-tenant rendering service. The limits
+//! are checked when the compiler accesses the world, after the evaluation and
+//! after each layout iteration, aborting the compilation with a diagnostic at
+//! the best-known span.
+//!
+//! The time budget is a deadline, which every access to the world checks
+//! during the compilation, i.e. when the evaluation imports modules, reads
+//! files or gets the date, and when the layout loads fonts. Past the deadline,
+//! the accesses fail, which aborts the evaluation at the accessing expression,
+//! e.g. an import in a loop, and cuts the layout short until the budget is
+//! reported after the layout iteration. Typst offers no other way to interrupt
+//! an evaluation in progress, hence an evaluation which doesn't access the
+//! world, e.g. a loop of arithmetic, is only reported after it finishes.
+//! Likewise, the elements are counted in the evaluated content, and a
+//! document exceeding the page limit is reported after a layout iteration,
+//! but before it is exported.
+//!
+//! All the limits are unset by default, which keeps the behavior of
+//! [`typst::compile`].
+
+use std::{collections::HashMap, time::Duration};
+
+use comemo::Prehashed;
+use parking_lot::Mutex;
+use typst::{
+    diag::{EcoString, FileError, FileResult, SourceDiagnostic, SourceResult},
+    foundations::{Content, Datetime, Value},
+    layout::{Frame, FrameItem},
+    model::Document,
+    syntax::{Source, Span},
+    text::{Font, FontBook},
+    Library, World,
+};
+use typst_ts_core::{package::PackageSpec, typst::prelude::*, Bytes, TypstFileId as FileId};
+
+/// The limits of the resources used by a compilation, see the
+/// [module docs](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileLimits {
+    /// The wall time budget of a compilation.
+    pub time_budget: Option<Duration>,
+    /// The maximum number of elements in the evaluated content.
+    pub max_elements: Option<usize>,
+    /// The maximum total size of the files loaded by a compilation in bytes,
+    /// e.g. of images and data files, as an estimate of the memory usage.
+    pub max_file_bytes: Option<usize>,
+    /// The maximum number of pages in the laid out document.
+    pub max_pages: Option<usize>,
+}
+
+impl CompileLimits {
+    /// Whether none of the limits is set.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn with_time_budget(mut self, budget: Option<Duration>) -> Self {
+        self.time_budget = budget;
+        self
+    }
+
+    pub fn with_max_elements(mut self, max: Option<usize>) -> Self {
+        self.max_elements = max;
+        self
+    }
+
+    pub fn with_max_file_bytes(mut self, max: Option<usize>) -> Self {
+        self.max_file_bytes = max;
+        self
+    }
+
+    pub fn with_max_pages(mut self, max: Option<usize>) -> Self {
+        self.max_pages = max;
+        self
+    }
+
+    /// Fill the unset limits by those of `other`.
+    pub fn or(self, other: &Self) -> Self {
+        Self {
+            time_budget: self.time_budget.or(other.time_budget),
+            max_elements: self.max_elements.or(other.max_elements),
+            max_file_bytes: self.max_file_bytes.or(other.max_file_bytes),
+            max_pages: self.max_pages.or(other.max_pages),
+        }
+    }
+}
+
+/// A world which checks the limits when the compiler loads files.
+pub(crate) struct LimitedWorld<'a> {
+    world: &'a dyn World,
+    limits: &'a CompileLimits,
+    /// When the time budget is exceeded.
+    deadline: Option<instant::Instant>,
+    /// The sizes of the loaded files.
+    loaded: Mutex<HashMap<FileId, usize>>,
+}
+
+impl<'a> LimitedWorld<'a> {
+    pub fn new(world: &'a dyn World, limits: &'a CompileLimits) -> Self {
+        Self {
+            world,
+            limits,
+            deadline: (limits.time_budget).map(|budget| instant::Instant::now() + budget),
+            loaded: Mutex::default(),
+        }
+    }
+
+    /// Check the time budget after a phase of the compilation, attributing a
+    /// violation to the given span.
+    pub fn check_time(&self, phase: &str, span: Span) -> SourceResult<()> {
+        match self.time_exceeded() {
+            Some(message) => Err(eco_vec![SourceDiagnostic::error(
+                span,
+                eco_format!("{phase} {message}"),
+            )
+            .with_hint("the compilation is aborted at the last evaluated location")]),
+            None => Ok(()),
+        }
+    }
+
+    /// Replace the errors of a phase by the violation of the time budget if it
+    /// is exceeded, since the failing accesses to the world abort the phase,
+    /// see the [module docs](self).
+    pub fn explain_errors(
+        &self,
+        phase: &str,
+        errors: EcoVec<SourceDiagnostic>,
+    ) -> EcoVec<SourceDiagnostic> {
+        // The first error is at the access aborting the phase.
+        let span = errors
+            .first()
+            .map_or_else(Span::detached, |error| error.span);
+        match self.check_time(phase, span) {
+            Ok(()) => errors,
+            Err(exceeded) => exceeded,
+        }
+    }
+
+    /// Check the number of the elements in the evaluated content.
+    pub fn check_elements(&self, content: &Content) -> SourceResult<()> {
+        let Some(max) = self.limits.max_elements else {
+            return Ok(());
+        };
+
+        let mut counter = ElementCounter {
+            max,
+            count: 0,
+            last_span: Span::detached(),
+        };
+        match counter.visit(content) {
+            Some(span) => Err(eco_vec![SourceDiagnostic::error(
+                span,
+                eco_format!("the document exceeded the configured limit of {max} elements"),
+            )
+            .with_hint("check if any loops or recursions generate content by themselves")]),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the number of the pages in a laid out document.
+    pub fn check_pages(&self, document: &Document) -> SourceResult<()> {
+        let Some(max) = self.limits.max_pages else {
+            return Ok(());
+        };
+
+        match document.pages.get(max) {
+            // The first page exceeding the limit is where to look at.
+            Some(page) => Err(eco_vec![SourceDiagnostic::error(
+                last_in_frame(&page.frame).unwrap_or_else(Span::detached),
+                eco_format!(
+                    "the document exceeded the configured limit of {max} pages with {} pages",
+                    document.pages.len()
+                ),
+            )
+            .with_hint("check if any loops or recursions generate pages by themselves")]),
+            None => Ok(()),
+        }
+    }
+
+    fn time_exceeded(&self) -> Option<EcoString> {
+        let budget = self.limits.time_budget?;
+        let deadline = self.deadline?;
+        (instant::Instant::now() > deadline)
+            .then(|| eco_format!("exceeded the configured {budget:?} budget"))
+    }
+
+    fn check_file_time(&self) -> FileResult<()> {
+        match self.time_exceeded() {
+            Some(message) => Err(FileError::Other(Some(eco_format!("compilation {message}")))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl World for LimitedWorld<'_> {
+    fn library(&self) -> &Prehashed<Library> {
+        self.world.library()
+    }
+
+    fn book(&self) -> &Prehashed<FontBook> {
+        self.world.book()
+    }
+
+    fn main(&self) -> Source {
+        self.world.main()
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        self.check_file_time()?;
+        self.world.source(id)
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        self.check_file_time()?;
+        let bytes = self.world.file(id)?;
+
+        if let Some(max) = self.limits.max_file_bytes {
+            let mut loaded = self.loaded.lock();
+            loaded.insert(id, bytes.len());
+            if loaded.values().sum::<usize>() > max {
+                return Err(FileError::Other(Some(eco_format!(
+                    "loaded files exceeded the configured limit of {max} bytes"
+                ))));
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        // The layout falls back to other fonts, and the budget is reported
+        // after the layout iteration.
+        if self.time_exceeded().is_some() {
+            return None;
+        }
+        self.world.font(index)
+    }
+
+    fn today(&self, offset: Option<i64>) -> Option<Datetime> {
+        if self.time_exceeded().is_some() {
+            return None;
+        }
+        self.world.today(offset)
+    }
+
+    fn packages(&self) -> &[(PackageSpec, Option<EcoString>)] {
+        self.world.packages()
+    }
+}
+
+struct ElementCounter {
+    max: usize,
+    count: usize,
+    /// The span of the last visited element which is attached to a source.
+    last_span: Span,
+}
+
+impl ElementCounter {
+    /// Count the elements in the content, returning the best-known span of
+    /// the element exceeding the limit.
+    fn visit(&mut self, content: &Content) -> Option<Span> {
+        if !content.span().is_detached() {
+            self.last_span = content.span();
+        }
+        self.count += 1;
+        if self.count > self.max {
+            return Some(self.last_span);
+        }
+
+        for (_, value) in content.fields() {
+            let found = match value {
+                Value::Content(child) => self.visit(&child),
+                Value::Array(children) => children.iter().find_map(|child| match child {
+                    Value::Content(child) => self.visit(child),
+                    _ => None,
+                }),
+                _ => None,
+            };
+            if found.is_some() {
+                return found;
+            }
+        }
+
+        None
+    }
+}
+
+/// The span of the last laid out text of a document, which approximates the
+/// location where the layout stopped.
+pub(crate) fn last_span(document: &Document) -> Span {
+    (document.pages.iter().rev())
+        .find_map(|page| last_in_frame(&page.frame))
+        .unwrap_or_else(Span::detached)
+}
+
+/// The span of the last laid out text of a frame.
+fn last_in_frame(frame: &Frame) -> Option<Span> {
+    frame.items().rev().find_map(|(_, item)| match item {
+        FrameItem::Group(group) => last_in_frame(&group.frame),
+        FrameItem::Text(text) => (text.glyphs.iter().rev())
+            .map(|glyph| glyph.span.0)
+            .find(|span| !span.is_detached()),
+        _ => None,
+    })
+}
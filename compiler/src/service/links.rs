@@ -0,0 +1,284 @@
+//! Enumerate the links in a compiled document.
+//!
+//! A link is laid out as a link marker before each piece of its text, hence
+//! a link broken across lines covers multiple rectangles. Consecutive markers
+//! with the same destination are merged into one link until a glyph outside
+//! of the link is reached.
+
+use typst::{
+    foundations::Selector,
+    introspection::{Introspector, Meta},
+    layout::{Abs, Frame, FrameItem, Point, Size, Transform},
+    model::Destination,
+};
+use typst_ts_core::{
+    vector::{
+        ir::{self, LinkRect, LinkTableItem, LinkTarget},
+        utils::AbsExt,
+    },
+    TypstDocument,
+};
+
+use super::position::LspRange;
+
+/// The target of a link, see [`LinkInfo`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkKind {
+    /// An external URL.
+    Url(String),
+    /// A position in the document, where the page number is 1-based.
+    Position { page: usize, point: Point },
+    /// A labelled element in the document, where the page number is 1-based.
+    Label {
+        label: String,
+        page: usize,
+        point: Point,
+    },
+    /// An element which is not in the document, e.g. a location of another
+    /// compilation.
+    Broken,
+}
+
+/// The source of the text of a link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkSource {
+    pub filepath: String,
+    /// The range in the unit of the position encoding of the client.
+    pub range: LspRange,
+}
+
+/// A link in a document, see [`document_links`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkInfo {
+    pub kind: LinkKind,
+    /// The 1-based page number of the link.
+    pub page: usize,
+    /// The bounding rectangles `(x, y, width, height)` covered by the link on
+    /// the page in points.
+    pub rects: Vec<(f64, f64, f64, f64)>,
+    /// The raw span of the first attached glyph of the link text, which can
+    /// be converted back by [`typst::syntax::Span::from_raw`].
+    pub span_id: Option<u64>,
+    /// The resolved source of the span, which is left empty by
+    /// [`document_links`].
+    pub source: Option<LinkSource>,
+}
+
+/// Collect the links in a document in the order of pages.
+pub fn document_links(document: &TypstDocument) -> Vec<LinkInfo> {
+    let mut collector = LinkCollector {
+        introspector: &document.introspector,
+        current: None,
+        links: vec![],
+    };
+    for (idx, page) in document.pages.iter().enumerate() {
+        collector.current = None;
+        collector.frame(&page.frame, Transform::identity(), idx + 1);
+    }
+    collector.links
+}
+
+/// Convert links into the link table of a vector artifact, see
+/// [`super::ArtifactOptions::links`].
+pub fn link_table(links: &[LinkInfo]) -> Vec<LinkTableItem> {
+    let point = |point: Point| ir::Point::new(point.x.to_f32().into(), point.y.to_f32().into());
+
+    (links.iter())
+        .map(|link| LinkTableItem {
+            target: match &link.kind {
+                LinkKind::Url(url) => LinkTarget::Url(url.as_str().into()),
+                LinkKind::Position { page, point: pos } => LinkTarget::Position {
+                    page: *page as u32,
+                    point: point(*pos),
+                },
+                LinkKind::Label {
+                    label,
+                    page,
+                    point: pos,
+                } => LinkTarget::Label {
+                    label: label.as_str().into(),
+                    page: *page as u32,
+                    point: point(*pos),
+                },
+                LinkKind::Broken => LinkTarget::Broken,
+            },
+            page: link.page as u32,
+            rects: (link.rects.iter())
+                .map(|&(x, y, w, h)| LinkRect {
+                    origin: ir::Point::new((x as f32).into(), (y as f32).into()),
+                    size: ir::Size::new((w as f32).into(), (h as f32).into()),
+                })
+                .collect(),
+            span: link.span_id.unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Classify the destination of a link.
+pub fn link_kind(introspector: &Introspector, dest: &Destination) -> LinkKind {
+    let loc = match dest {
+        Destination::Url(url) => return LinkKind::Url(url.to_string()),
+        Destination::Position(pos) => {
+            return LinkKind::Position {
+                page: pos.page.get(),
+                point: pos.point,
+            }
+        }
+        Destination::Location(loc) => *loc,
+    };
+
+    let elems = introspector.query(&Selector::Location(loc));
+    let Some(elem) = elems.first() else {
+        return LinkKind::Broken;
+    };
+    let pos = introspector.position(loc);
+    match elem.label() {
+        Some(label) => LinkKind::Label {
+            label: label.as_str().to_owned(),
+            page: pos.page.get(),
+            point: pos.point,
+        },
+        None => LinkKind::Position {
+            page: pos.page.get(),
+            point: pos.point,
+        },
+    }
+}
+
+struct LinkCollector<'a> {
+    introspector: &'a Introspector,
+    /// The destination of the last link, if its text is not finished yet.
+    current: Option<Destination>,
+    links: Vec<LinkInfo>,
+}
+
+impl LinkCollector<'_> {
+    fn frame(&mut self, frame: &Frame, ts: Transform, page: usize) {
+        for (pos, item) in frame.items() {
+            let ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+            match item {
+                FrameItem::Group(group) => {
+                    self.frame(&group.frame, ts.pre_concat(group.transform), page)
+                }
+                FrameItem::Meta(Meta::Link(dest), size) => {
+                    let rect = transformed_rect(ts, *size);
+                    match self.links.last_mut() {
+                        Some(link) if self.current.as_ref() == Some(dest) => link.rects.push(rect),
+                        _ => {
+                            self.current = Some(dest.clone());
+                            self.links.push(LinkInfo {
+                                kind: link_kind(self.introspector, dest),
+                                page,
+                                rects: vec![rect],
+                                span_id: None,
+                                source: None,
+                            });
+                        }
+                    }
+                }
+                FrameItem::Text(text) => {
+                    if self.current.is_none() {
+                        continue;
+                    }
+                    let Some(link) = self.links.last_mut() else {
+                        continue;
+                    };
+
+                    // The baseline starts inside of the link text.
+                    let start = Point::zero().transform(ts);
+                    let inside = (link.rects.last()).is_some_and(|&rect| contains(rect, start));
+                    if !inside {
+                        self.current = None;
+                        continue;
+                    }
+                    if link.span_id.is_none() {
+                        link.span_id = (text.glyphs.iter())
+                            .map(|glyph| glyph.span.0)
+                            .find(|span| !span.is_detached())
+                            .map(|span| span.into_raw().get());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Get the bounding box `(x, y, width, height)` of a box at the origin, which
+/// is transformed into the page coordinates.
+fn transformed_rect(ts: Transform, size: Size) -> (f64, f64, f64, f64) {
+    let corners = [
+        Point::zero(),
+        Point::with_x(size.x),
+        Point::with_y(size.y),
+        size.to_point(),
+    ]
+    .map(|corner| corner.transform(ts));
+
+    let min_x = corners.iter().map(|p| p.x).fold(Abs::inf(), Abs::min);
+    let min_y = corners.iter().map(|p| p.y).fold(Abs::inf(), Abs::min);
+    let max_x = corners.iter().map(|p| p.x).fold(-Abs::inf(), Abs::max);
+    let max_y = corners.iter().map(|p| p.y).fold(-Abs::inf(), Abs::max);
+    (
+        min_x.to_pt(),
+        min_y.to_pt(),
+        (max_x - min_x).to_pt(),
+        (max_y - min_y).to_pt(),
+    )
+}
+
+/// Whether a rectangle contains a point, tolerating rounding errors.
+fn contains((x, y, w, h): (f64, f64, f64, f64), point: Point) -> bool {
+    const EPS: f64 = 1e-3;
+    let (px, py) = (point.x.to_pt(), point.y.to_pt());
+    x - EPS <= px && px <= x + w + EPS && y - EPS <= py && py <= y + h + EPS
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use typst::foundations::Label;
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileEnv, Compiler},
+    };
+
+    #[test]
+    fn test_document_links() {
+        let ws = TestWorkspace::new();
+        let content = r#"#set page(width: 120pt)
+= Intro <intro>
+#link("https://example.com")[Example] and #link(<intro>)[the introduction].
+#link("https://typst.app")[a link long enough to break across the lines]"#;
+
+        let mut driver = ws.shadow_driver(content.as_bytes());
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+        let links = document_links(&doc);
+        assert_eq!(links.len(), 3, "{links:?}");
+        assert_eq!(links[0].kind, LinkKind::Url("https://example.com".into()));
+        assert!(links[0].span_id.is_some());
+        assert!(
+            matches!(&links[1].kind, LinkKind::Label { label, page: 1, .. } if label == "intro"),
+            "{:?}",
+            links[1].kind
+        );
+        assert_eq!(links[2].kind, LinkKind::Url("https://typst.app".into()));
+        assert!(links[2].rects.len() > 1, "{:?}", links[2].rects);
+
+        // A missing label fails the compilation, but the location of an
+        // element is broken in another document.
+        let intro = doc.introspector.query_label(Label::new("intro")).unwrap();
+        let dest = Destination::Location(intro.location().unwrap());
+        assert_eq!(link_kind(&doc.introspector, &dest), links[1].kind);
+        assert_eq!(link_kind(&Introspector::default(), &dest), LinkKind::Broken);
+
+        let table = link_table(&links);
+        assert_eq!(
+            table[0].target,
+            LinkTarget::Url("https://example.com".into())
+        );
+        assert_eq!(table[2].rects.len(), links[2].rects.len());
+    }
+}
@@ -0,0 +1,231 @@
+//! Lint the sources of a compiled document beyond the diagnostics of typst.
+//!
+//! A label attached to content is unused if no reference of the document
+//! targets it and no code mentions it, e.g. `link(<intro>)` or
+//! `show <intro>: ..`. The references are taken from the introspector, hence
+//! only the references laid out in the document count, along with the
+//! references written in the sources.
+//!
+//! An imported name is unused if no identifier of the same name occurs in the
+//! importing file. Shadowing is not resolved, hence an import shadowed by a
+//! local binding of the same name is not reported. Wildcard imports are never
+//! reported.
+
+use std::collections::HashSet;
+
+use typst::{
+    foundations::{Element, Selector, Value},
+    model::RefElem,
+    syntax::{
+        ast::{self, AstNode},
+        FileId, Span, SyntaxKind, SyntaxNode,
+    },
+    World,
+};
+use typst_ts_core::{typst::prelude::*, TypstDocument};
+
+use super::DiagnosticLocation;
+
+/// The kind of a [`LintFinding`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// A label which is never referenced.
+    UnusedLabel,
+    /// An imported name which is never used.
+    UnusedImport,
+}
+
+/// A finding of [`lint_document`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    pub kind: LintKind,
+    pub message: String,
+    /// The span of the label or of the imported name.
+    pub span: Span,
+    /// The resolved location of the span, which is left empty by
+    /// [`lint_document`].
+    pub location: DiagnosticLocation,
+}
+
+/// Lint the given sources of a compiled document, see the
+/// [module docs](self).
+///
+/// The findings are ordered by the sources and then by their positions.
+pub fn lint_document(
+    world: &dyn World,
+    sources: &[FileId],
+    document: &TypstDocument,
+) -> Vec<LintFinding> {
+    let sources: Vec<_> = (sources.iter())
+        .filter_map(|&id| world.source(id).ok())
+        .collect();
+
+    let mut usages = Usages::default();
+    let refs = (document.introspector).query(&Selector::Elem(Element::of::<RefElem>(), None));
+    for elem in refs.iter() {
+        if let Ok(Value::Label(label)) = elem.fields().get("target") {
+            usages.labels.insert(label.as_str().into());
+        }
+    }
+    for source in &sources {
+        usages.labels_in(source.root());
+    }
+
+    let mut findings = vec![];
+    for source in &sources {
+        let mut defined = vec![];
+        labels_defined_in(source.root(), &mut defined);
+        for (label, span) in defined {
+            if !usages.labels.contains(&label) {
+                findings.push(LintFinding {
+                    kind: LintKind::UnusedLabel,
+                    message: format!("label `<{label}>` is never referenced"),
+                    span,
+                    location: DiagnosticLocation::default(),
+                });
+            }
+        }
+
+        let mut imports = ImportUsages::default();
+        imports.visit(source.root());
+        for (name, span) in imports.bindings {
+            if !imports.idents.contains(&name) {
+                findings.push(LintFinding {
+                    kind: LintKind::UnusedImport,
+                    message: format!("imported `{name}` is never used"),
+                    span,
+                    location: DiagnosticLocation::default(),
+                });
+            }
+        }
+    }
+
+    let position = |span: Span| {
+        let id = span.id();
+        let source = sources.iter().find(|source| Some(source.id()) == id);
+        source
+            .and_then(|source| source.range(span))
+            .map(|range| range.start)
+    };
+    findings.sort_by_cached_key(|finding| {
+        let file = (sources.iter()).position(|source| Some(source.id()) == finding.span.id());
+        (file, position(finding.span))
+    });
+    findings
+}
+
+/// The labels mentioned by references and code.
+#[derive(Default)]
+struct Usages {
+    labels: HashSet<EcoString>,
+}
+
+impl Usages {
+    fn labels_in(&mut self, node: &SyntaxNode) {
+        if let Some(reference) = node.cast::<ast::Ref>() {
+            self.labels.insert(reference.target().into());
+        }
+        for child in node.children() {
+            // A label in markup is attached to the preceding content.
+            if child.kind() == SyntaxKind::Label && node.kind() != SyntaxKind::Markup {
+                if let Some(label) = child.cast::<ast::Label>() {
+                    self.labels.insert(label.get().into());
+                }
+            }
+            self.labels_in(child);
+        }
+    }
+}
+
+/// Collect the labels attached to content in a syntax tree.
+fn labels_defined_in(node: &SyntaxNode, defined: &mut Vec<(EcoString, Span)>) {
+    for child in node.children() {
+        if child.kind() == SyntaxKind::Label && node.kind() == SyntaxKind::Markup {
+            if let Some(label) = child.cast::<ast::Label>() {
+                defined.push((label.get().into(), child.span()));
+            }
+        }
+        labels_defined_in(child, defined);
+    }
+}
+
+/// The bindings of the imports in a file along with the identifiers used.
+#[derive(Default)]
+struct ImportUsages {
+    bindings: Vec<(EcoString, Span)>,
+    idents: HashSet<EcoString>,
+}
+
+impl ImportUsages {
+    fn visit(&mut self, node: &SyntaxNode) {
+        if let Some(import) = node.cast::<ast::ModuleImport>() {
+            if let Some(name) = import.new_name() {
+                self.bindings.push((name.get().clone(), name.span()));
+            }
+            if let Some(ast::Imports::Items(items)) = import.imports() {
+                for item in items.iter() {
+                    let name = item.bound_name();
+                    self.bindings.push((name.get().clone(), name.span()));
+                }
+            }
+            // The bound names are not usages, but the source may be.
+            self.visit(import.source().to_untyped());
+            return;
+        }
+
+        match node.kind() {
+            SyntaxKind::Ident | SyntaxKind::MathIdent => {
+                self.idents.insert(node.text().clone());
+            }
+            _ => {
+                for child in node.children() {
+                    self.visit(child);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use typst::foundations::Bytes;
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileEnv, Compiler, EnvWorld},
+        ShadowApi,
+    };
+
+    #[test]
+    fn test_lint_document() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let main = ws.path("main.typ");
+        let lib = root.join("lib.typ");
+        let mut driver = ws.driver();
+
+        let lib_content = "#let used = [Used]\n#let unused = [Unused]";
+        driver
+            .map_shadow(&lib, Bytes::from(lib_content.as_bytes()))
+            .unwrap();
+        let content = "#import \"lib.typ\": used, unused\n#set heading(numbering: \"1.\")\n= Intro <intro>\n= Usage <usage>\nSee @intro. #used\n#link(<linked>)[]\n= Linked <linked>";
+        driver
+            .map_shadow(&main, Bytes::from(content.as_bytes()))
+            .unwrap();
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+        let sources = driver.world.source_ids();
+        let findings = lint_document(&driver.world, &sources, &doc);
+        let summary: Vec<_> = (findings.iter())
+            .map(|finding| (finding.kind, finding.message.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (LintKind::UnusedImport, "imported `unused` is never used"),
+                (LintKind::UnusedLabel, "label `<usage>` is never referenced"),
+            ]
+        );
+    }
+}
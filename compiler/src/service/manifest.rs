@@ -0,0 +1,223 @@
+//! Describe everything produced by a compilation in a single JSON manifest,
+//! e.g. for build tools tracking the outputs of a document.
+//!
+//! The manifest lists the files written by the exporters along with their
+//! hashes, the page count, the font families used by the document and the
+//! files read by the compilation. The outputs are hashed after the inner
+//! exporters have written them, see [`ManifestExporter`].
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use typst::{
+    diag::{SourceDiagnostic, SourceResult},
+    syntax::Span,
+};
+use typst_ts_core::{error::prelude::*, output::write_atomic, typst::prelude::*, TypstDocument};
+
+use super::{
+    deps, CompileEnv, CompileMeta, CompileMiddleware, Compiler, EntryManager, WorldExporter,
+};
+use crate::world::{CompilerFeat, CompilerWorld};
+
+/// The version of the schema of [`Manifest`], which is bumped on breaking
+/// changes.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// A file written by an exporter, see [`Manifest::outputs`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestOutput {
+    /// The export format, e.g. `pdf` and `svg`.
+    pub format: String,
+    pub path: PathBuf,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The hash of the file, e.g. `sha256:<hex>`.
+    pub hash: String,
+}
+
+/// The outputs and the inputs of a compilation, see [`document_manifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub version: u32,
+    /// The path of the entry file, if it is a file on disk.
+    pub entry: Option<PathBuf>,
+    pub page_count: usize,
+    pub outputs: Vec<ManifestOutput>,
+    /// The families of the fonts used by the document, sorted.
+    pub fonts: Vec<String>,
+    /// The files read by the compilation, sorted.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// Describe the latest compilation of the world, where `outputs` are the
+/// formats and the paths of the files written by the exporters.
+///
+/// Fails if an output is not readable, e.g. it is not written yet.
+pub fn document_manifest<F: CompilerFeat>(
+    world: &CompilerWorld<F>,
+    doc: &TypstDocument,
+    outputs: &[(String, PathBuf)],
+) -> ZResult<Manifest> {
+    let outputs = (outputs.iter())
+        .map(|(format, path)| {
+            let data = std::fs::read(path).map_err(map_string_err("failed to read output"))?;
+            Ok(ManifestOutput {
+                format: format.clone(),
+                path: path.clone(),
+                size: data.len() as u64,
+                hash: format!("sha256:{}", hex::encode(Sha256::digest(&data))),
+            })
+        })
+        .collect::<ZResult<_>>()?;
+
+    let mut fonts = vec![];
+    for page in &doc.pages {
+        deps::collect_fonts(&page.frame, &mut fonts);
+    }
+    let fonts: BTreeSet<_> = (fonts.iter())
+        .map(|font| font.info().family.clone())
+        .collect();
+
+    let dependencies: BTreeSet<_> = (world.vfs.iter_dependencies())
+        .map(|(path, _)| path.to_path_buf())
+        .collect();
+
+    Ok(Manifest {
+        version: MANIFEST_VERSION,
+        entry: (world.main_id()).and_then(|id| world.path_mapper().path_for_id(id).ok()),
+        page_count: doc.pages.len(),
+        outputs,
+        fonts: fonts.into_iter().collect(),
+        dependencies: dependencies.into_iter().collect(),
+    })
+}
+
+/// Writes the manifest to a file after each successful compilation, see
+/// [`document_manifest`].
+///
+/// It should wrap the exporters writing the outputs, so that the outputs are
+/// written before they are hashed. The manifest is only written by
+/// compilations, while exports are passed to the inner exporters.
+pub struct ManifestExporter<C: Compiler> {
+    pub compiler: C,
+    /// The file to write, or `None` to disable the exporter.
+    output: Option<PathBuf>,
+    /// The formats and the paths of the files written by the exporters.
+    outputs: Vec<(String, PathBuf)>,
+}
+
+impl<C: Compiler> ManifestExporter<C> {
+    pub fn new(compiler: C, output: Option<PathBuf>, outputs: Vec<(String, PathBuf)>) -> Self {
+        Self {
+            compiler,
+            output,
+            outputs,
+        }
+    }
+}
+
+impl<F: CompilerFeat, C: Compiler<World = CompilerWorld<F>>> ManifestExporter<C> {
+    fn write_manifest(&self, doc: &TypstDocument) -> SourceResult<()> {
+        let Some(output) = &self.output else {
+            return Ok(());
+        };
+
+        let write = |output: &Path| -> ZResult<()> {
+            let manifest = document_manifest(self.compiler.world(), doc, &self.outputs)?;
+            let manifest = serde_json::to_string_pretty(&manifest)
+                .map_err(map_string_err("failed to serialize manifest"))?;
+            write_atomic(output, manifest).map_err(map_string_err("failed to write manifest"))
+        };
+        write(output).map_err(|err| {
+            eco_vec![SourceDiagnostic::error(
+                Span::detached(),
+                eco_format!("failed to write manifest: {err}"),
+            )]
+        })
+    }
+}
+
+impl<F: CompilerFeat, C: Compiler<World = CompilerWorld<F>> + WorldExporter> WorldExporter
+    for ManifestExporter<C>
+{
+    fn export(&mut self, output: Arc<TypstDocument>, meta: &CompileMeta) -> SourceResult<()> {
+        self.compiler.export(output, meta)
+    }
+}
+
+impl<F: CompilerFeat, C: Compiler<World = CompilerWorld<F>>> CompileMiddleware
+    for ManifestExporter<C>
+{
+    type Compiler = C;
+
+    fn inner(&self) -> &Self::Compiler {
+        &self.compiler
+    }
+
+    fn inner_mut(&mut self) -> &mut Self::Compiler {
+        &mut self.compiler
+    }
+
+    fn wrap_compile(&mut self, env: &mut CompileEnv) -> SourceResult<Arc<TypstDocument>> {
+        let doc = self.inner_mut().compile(env)?;
+        self.write_manifest(&doc)?;
+
+        Ok(doc)
+    }
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use typst_ts_core::exporter_builtins::FsPathExporter;
+    use typst_ts_pdf_exporter::PdfDocExporter;
+
+    use super::*;
+    use crate::{fixture::TestWorkspace, service::CompileExporter};
+
+    #[test]
+    fn test_manifest() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        let main = ws.path("main.typ");
+        let chapter = root.join("chapter.typ");
+        let out = root.join("out");
+        std::fs::create_dir_all(&out).unwrap();
+        ws.write("main.typ", "= Main\n#pagebreak()\n#include \"chapter.typ\"");
+        std::fs::write(&chapter, "= Chapter").unwrap();
+        let pdf = out.join("main.pdf");
+
+        let driver = ws.driver();
+        let exporter = FsPathExporter::<Vec<u8>, _>::new(pdf.clone(), PdfDocExporter::default());
+        let driver = CompileExporter::new(driver).with_exporter(exporter);
+        let output = out.join("manifest.json");
+        let outputs = vec![("pdf".to_owned(), pdf.clone())];
+        let mut driver = ManifestExporter::new(driver, Some(output.clone()), outputs);
+        driver.compile(&mut CompileEnv::default()).unwrap();
+
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&output).unwrap()).unwrap();
+        assert_eq!(manifest["version"], MANIFEST_VERSION);
+        assert_eq!(manifest["pageCount"], 2);
+        assert_eq!(manifest["entry"], main.to_str().unwrap());
+        assert_eq!(
+            manifest["dependencies"],
+            serde_json::json!([chapter.to_str().unwrap(), main.to_str().unwrap()])
+        );
+        // The PDF is exported before it is hashed.
+        let data = std::fs::read(&pdf).unwrap();
+        assert!(data.starts_with(b"%PDF-"));
+        assert_eq!(manifest["outputs"][0]["format"], "pdf");
+        assert_eq!(manifest["outputs"][0]["size"], data.len());
+        let hash = format!("sha256:{}", hex::encode(Sha256::digest(&data)));
+        assert_eq!(manifest["outputs"][0]["hash"], hash);
+        assert!(!manifest["fonts"].as_array().unwrap().is_empty());
+    }
+}
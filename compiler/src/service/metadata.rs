@@ -0,0 +1,164 @@
+//! Describe the `metadata` elements of a compiled document with their
+//! anchors.
+//!
+//! Tools built on documents mark their structure by metadata, e.g.
+//! `#metadata((kind: "slide", idx: 3))` for each slide of a presentation, and
+//! map it to the pages, e.g. for a presenter view. A metadata element lays out
+//! to nothing but its introspection anchor, which is kept even if the element
+//! is hidden, hence every element reports the page and the position where it
+//! landed.
+
+use serde::Serialize;
+use typst::{
+    foundations::{Content, Repr, Value},
+    introspection::{Meta, MetadataElem},
+    layout::{FrameItem, Point},
+};
+use typst_ts_core::{
+    flatten::flatten_frames,
+    vector::ir::{MetadataAnchorItem, Scalar, SourceRangeItem},
+    TypstDocument,
+};
+
+use super::position::LspRange;
+
+/// The source of a metadata element, see [`MetadataAnchor`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetadataSource {
+    pub filepath: String,
+    /// The range in the unit of the position encoding of the client.
+    pub range: LspRange,
+}
+
+/// A metadata element of a document, see [`document_metadata`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataAnchor {
+    /// The value of the element, where a value not representable in JSON,
+    /// e.g. a function, is replaced by its `repr`.
+    pub value: serde_json::Value,
+    /// The label attached to the element, if any.
+    pub label: Option<String>,
+    /// The 1-based page number of the element.
+    pub page: usize,
+    /// The position of the element on the page in pt.
+    pub y: f64,
+    /// The raw span of the element, which can be converted back by
+    /// [`typst::syntax::Span::from_raw`].
+    pub span_id: Option<u64>,
+    /// The resolved source of the span, which is left empty by
+    /// [`document_metadata`].
+    pub source: Option<MetadataSource>,
+}
+
+/// Collect the metadata elements of a document in the order of the pages.
+///
+/// If a key is given, only the elements labelled by the key or whose values
+/// are dictionaries with the key are kept, e.g. `kind` for
+/// `#metadata((kind: "slide"))`.
+pub fn document_metadata(
+    document: &TypstDocument,
+    key_filter: Option<&str>,
+) -> Vec<MetadataAnchor> {
+    (flatten_frames(document))
+        .filter_map(|placed| {
+            let FrameItem::Meta(Meta::Elem(elem), _) = placed.item else {
+                return None;
+            };
+            if !elem.is::<MetadataElem>() {
+                return None;
+            }
+            let value = value(elem);
+            let label = elem.label().map(|label| label.as_str().to_owned());
+            if let Some(key) = key_filter {
+                let has_key = matches!(&value, Value::Dict(dict) if dict.contains(key));
+                if !has_key && label.as_deref() != Some(key) {
+                    return None;
+                }
+            }
+
+            let span = elem.span();
+            Some(MetadataAnchor {
+                value: serde_json::to_value(&value)
+                    .unwrap_or_else(|_| serde_json::Value::String(value.repr().to_string())),
+                label,
+                page: placed.page,
+                y: placed.to_page(Point::zero()).y.to_pt(),
+                span_id: (!span.is_detached()).then(|| span.into_raw().get()),
+                source: None,
+            })
+        })
+        .collect()
+}
+
+/// Convert metadata elements into the table of a vector artifact, see
+/// [`super::ArtifactOptions::metadata_anchors`].
+///
+/// The sources are only embedded if requested, since they disclose the paths
+/// of the files.
+pub fn metadata_table(
+    anchors: &[MetadataAnchor],
+    include_sources: bool,
+) -> Vec<MetadataAnchorItem> {
+    (anchors.iter())
+        .map(|anchor| MetadataAnchorItem {
+            value: anchor.value.to_string().into(),
+            label: anchor.label.as_deref().map(Into::into),
+            page: anchor.page as u32,
+            y: Scalar(anchor.y as f32),
+            source: (anchor.source.as_ref())
+                .filter(|_| include_sources)
+                .map(|source| SourceRangeItem {
+                    filepath: source.filepath.as_str().into(),
+                    start_line: source.range.start.line as u32,
+                    start_column: source.range.start.column as u32,
+                    end_line: source.range.end.line as u32,
+                    end_column: source.range.end.column as u32,
+                }),
+        })
+        .collect()
+}
+
+/// Get the value of a laid out metadata element.
+fn value(elem: &Content) -> Value {
+    elem.fields().get("value").cloned().unwrap_or(Value::None)
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileEnv, Compiler},
+    };
+
+    #[test]
+    fn test_document_metadata() {
+        let ws = TestWorkspace::new();
+        let content = "#metadata((kind: \"slide\", idx: 1))\nIntro\n#pagebreak()\n#v(1cm)\n#hide[#metadata((kind: \"slide\", idx: 2))]\n#metadata(\"note\") <note>";
+
+        let mut driver = ws.shadow_driver(content.as_bytes());
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+        let anchors = document_metadata(&doc, None);
+        assert_eq!(anchors.len(), 3);
+        assert!(anchors.iter().all(|anchor| anchor.span_id.is_some()));
+
+        // The hidden slide still reports its anchor.
+        let slides = document_metadata(&doc, Some("kind"));
+        let summary: Vec<_> = (slides.iter())
+            .map(|anchor| (anchor.value["idx"].as_i64(), anchor.page))
+            .collect();
+        assert_eq!(summary, [(Some(1), 1), (Some(2), 2)]);
+        assert!(slides[1].y > 0.);
+
+        let notes = document_metadata(&doc, Some("note"));
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].value, serde_json::json!("note"));
+
+        let table = metadata_table(&slides, false);
+        let value: serde_json::Value = serde_json::from_str(&table[0].value).unwrap();
+        assert_eq!(value, serde_json::json!({ "kind": "slide", "idx": 1 }));
+        assert!(table[1].source.is_none());
+    }
+}
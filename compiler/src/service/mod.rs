@@ -1,14 +1,24 @@
 use core::fmt;
 use std::{
+    collections::BTreeMap,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
-use crate::{vfs::notify::FilesystemEvent, ShadowApi};
+use chrono::{DateTime, Local};
+
+use crate::{
+    cache::CacheStats,
+    images::{self, GuardedImage},
+    vfs::{cached::ReadStats, notify::FilesystemEvent},
+    ShadowApi,
+};
+use comemo::Track;
 use typst::{
-    diag::{At, FileResult, Hint, SourceDiagnostic, SourceResult},
+    diag::{At, FileError, FileResult, Hint, SourceDiagnostic, SourceResult},
+    engine::Route,
     eval::Tracer,
-    foundations::Content,
+    foundations::{Content, Module},
     model::Document,
     syntax::Span,
     World,
@@ -29,6 +39,11 @@ pub use watch::*;
 pub(crate) mod driver;
 pub use driver::*;
 
+#[cfg(feature = "system-compile")]
+pub mod builder;
+#[cfg(feature = "system-compile")]
+pub use builder::CompileDriverBuilder;
+
 #[cfg(feature = "system-watch")]
 pub(crate) mod compile;
 #[cfg(feature = "system-watch")]
@@ -36,10 +51,60 @@ pub use compile::*;
 
 pub(crate) mod export;
 pub use export::*;
+
+pub(crate) mod context;
+pub use context::*;
+pub(crate) mod document;
+pub use document::*;
+pub(crate) mod jump;
+pub use jump::*;
+pub(crate) mod regions;
+pub use regions::*;
+
+pub mod cancel;
+pub mod coverage;
+pub mod cycle;
+pub mod deps;
+pub mod diff;
+pub mod eval;
 pub mod features;
+pub mod fonts;
+pub mod format;
+pub mod fragment;
+pub mod layout;
+pub mod library;
+pub mod limits;
+pub mod links;
+pub mod lint;
+pub mod manifest;
+pub mod metadata;
+#[cfg(feature = "system-compile")]
+pub mod observer;
+pub mod outline;
+pub mod pages;
+pub mod position;
+pub mod prelude;
+pub mod progress;
+#[cfg(feature = "system-compile")]
+pub mod project;
 pub mod query;
-
-pub use self::{diag::DiagnosticFormat, features::FeatureSet};
+pub mod queue;
+#[cfg(feature = "render")]
+pub mod render;
+#[cfg(feature = "system-compile")]
+pub mod session_log;
+#[cfg(feature = "system-compile")]
+pub mod standby;
+pub mod syntax;
+
+pub use self::{
+    diag::{
+        DiagnosticFormat, DiagnosticLocation, DiagnosticPosition, DiagnosticSeverity,
+        DiagnosticTracePoint, DiagnosticsReport, DiagnosticsTracker, PositionEncoding,
+        SerializableDiagnostic,
+    },
+    features::FeatureSet,
+};
 
 #[cfg(feature = "system-compile")]
 pub type CompileDriver = CompileDriverImpl<crate::TypstSystemWorld>;
@@ -62,6 +127,22 @@ pub trait EntryManager {
 pub struct CompileEnv {
     pub tracer: Option<Tracer>,
     pub features: Arc<FeatureSet>,
+    /// Overrides the current datetime of the world if set, which makes
+    /// `datetime.today()` and document timestamps reproducible.
+    pub now: Option<DateTime<Local>>,
+    /// Limits the number of layout iterations if set, see
+    /// [`layout::compile_with_iteration_limit`].
+    pub layout_iteration_limit: Option<usize>,
+    /// Overrides the page size of documents if set, see
+    /// [`layout::PageOverride`].
+    pub page_override: Option<layout::PageOverride>,
+    /// Limits the resources used by the compilation, see
+    /// [`limits::CompileLimits`].
+    pub limits: limits::CompileLimits,
+    /// The metadata passed to the exporters, see [`WorldExporter::export`].
+    pub meta: Arc<CompileMeta>,
+    /// Receives the stages of the compilation if set, see [`progress`].
+    pub progress: Option<progress::ProgressCallback>,
 }
 
 impl CompileEnv {
@@ -74,8 +155,50 @@ impl CompileEnv {
         self.features = feature_set;
         self
     }
+
+    pub fn with_now(mut self, now: Option<DateTime<Local>>) -> Self {
+        self.now = now;
+        self
+    }
+
+    pub fn with_layout_iteration_limit(mut self, limit: Option<usize>) -> Self {
+        self.layout_iteration_limit = limit;
+        self
+    }
+
+    pub fn with_page_override(mut self, page_override: Option<layout::PageOverride>) -> Self {
+        self.page_override = page_override;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: limits::CompileLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_meta(mut self, meta: Arc<CompileMeta>) -> Self {
+        self.meta = meta;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: Option<progress::ProgressCallback>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Report a stage of the compilation to the progress callback if set.
+    pub fn report_progress(&self, stage: progress::CompileStage) {
+        if let Some(progress) = &self.progress {
+            progress(stage);
+        }
+    }
 }
 
+/// Metadata of compilations which is not in the documents, e.g. the output
+/// filename, the git commit or the build id, passed to the exporters along
+/// with the documents.
+pub type CompileMeta = BTreeMap<String, String>;
+
 #[derive(Clone, Debug)]
 pub enum CompileReport {
     Stage(TypstFileId, &'static str, crate::Time),
@@ -83,6 +206,10 @@ pub enum CompileReport {
     ExportError(TypstFileId, EcoVec<SourceDiagnostic>, instant::Duration),
     CompileWarning(TypstFileId, EcoVec<SourceDiagnostic>, instant::Duration),
     CompileSuccess(TypstFileId, EcoVec<SourceDiagnostic>, instant::Duration),
+    /// Diagnostics of a compilation along with their changes, which is
+    /// reported after the status of the compilation if the
+    /// [`features::DIFF_DIAGNOSTICS_FEATURE`] is enabled.
+    Diagnostics(TypstFileId, DiagnosticsReport),
 }
 
 impl CompileReport {
@@ -92,13 +219,14 @@ impl CompileReport {
             | Self::CompileError(id, ..)
             | Self::ExportError(id, ..)
             | Self::CompileWarning(id, ..)
-            | Self::CompileSuccess(id, ..) => *id,
+            | Self::CompileSuccess(id, ..)
+            | Self::Diagnostics(id, ..) => *id,
         }
     }
 
     pub fn duration(&self) -> Option<std::time::Duration> {
         match self {
-            Self::Stage(..) => None,
+            Self::Stage(..) | Self::Diagnostics(..) => None,
             Self::CompileError(_, _, dur)
             | Self::ExportError(_, _, dur)
             | Self::CompileWarning(_, _, dur)
@@ -106,9 +234,13 @@ impl CompileReport {
         }
     }
 
+    /// Get the diagnostics of a compilation status.
+    ///
+    /// The diagnostics of [`CompileReport::Diagnostics`] are not included, as
+    /// they are also carried by the status reported before it.
     pub fn diagnostics(self) -> Option<EcoVec<SourceDiagnostic>> {
         match self {
-            Self::Stage(..) => None,
+            Self::Stage(..) | Self::Diagnostics(..) => None,
             Self::CompileError(_, diagnostics, ..)
             | Self::ExportError(_, diagnostics, ..)
             | Self::CompileWarning(_, diagnostics, ..)
@@ -131,12 +263,25 @@ impl<'a> fmt::Display for CompileReportMsg<'a> {
         let input = self.0.compiling_id();
         match self.0 {
             Stage(_, stage, ..) => write!(f, "{:?}: {} ...", input, stage),
-            CompileSuccess(_, _, duration) | CompileWarning(_, _, duration) => {
+            CompileSuccess(_, _, duration) => {
                 write!(f, "{:?}: Compilation succeeded in {:?}", input, duration)
             }
+            CompileWarning(_, warnings, duration) => {
+                write!(
+                    f,
+                    "{:?}: Compilation succeeded with {} warning(s) in {:?}",
+                    input,
+                    warnings.len(),
+                    duration
+                )
+            }
             CompileError(_, _, duration) | ExportError(_, _, duration) => {
                 write!(f, "{:?}: Compilation failed after {:?}", input, duration)
             }
+            Diagnostics(_, report) => match report.summary() {
+                Some(summary) => write!(f, "{:?}: {}", input, summary),
+                None => write!(f, "{:?}: {} new diagnostic(s)", input, report.added.len()),
+            },
         }
     }
 }
@@ -149,6 +294,40 @@ pub trait EnvWorld {
     fn ensure_env(&mut self) -> SourceResult<()> {
         Ok(())
     }
+
+    /// The images guarded by the image limits in the current compilation,
+    /// see [`crate::images`].
+    fn guarded_images(&self) -> Vec<(TypstFileId, GuardedImage)> {
+        vec![]
+    }
+
+    /// The source files read by the current compilation, see
+    /// [`coverage::document_coverage`].
+    fn source_ids(&self) -> Vec<TypstFileId> {
+        vec![]
+    }
+
+    /// The reads of files by the current compilation, if they are tracked,
+    /// see [`crate::vfs::Vfs::read_stats`].
+    fn read_stats(&self) -> Option<ReadStats> {
+        None
+    }
+
+    /// The statistics of the cache persisting derived data, if it is enabled,
+    /// see [`crate::cache::DiskCache`].
+    fn disk_cache_stats(&self) -> Option<Arc<CacheStats>> {
+        None
+    }
+
+    /// Check whether the root of the workspace is unavailable, see
+    /// [`RootUnavailable`].
+    fn check_root(&self) -> Option<RootUnavailable> {
+        None
+    }
+
+    /// Validate the cached files against the file system again, see
+    /// [`crate::vfs::Vfs::revalidate`].
+    fn revalidate_files(&mut self) {}
 }
 
 pub trait Compiler {
@@ -171,20 +350,77 @@ pub trait Compiler {
 
         let main_id = self.main_id();
 
+        env.report_progress(progress::CompileStage::Parse);
+        if let Some(missing) = EntryNotFound::check(self.world(), main_id) {
+            return Err(eco_vec![missing.diagnostic()]);
+        }
         self.world_mut()
             .source(main_id)
             .hint(AtFile(main_id))
             .at(Span::detached())?;
 
-        let res = match env.tracer.as_mut() {
-            Some(tracer) => typst::compile(self.world(), tracer),
-            None => typst::compile(self.world(), &mut Tracer::default()),
+        // The stages are only observable by compiling step by step.
+        let callback = env.progress.clone();
+        let report = |stage: progress::CompileStage| {
+            if let Some(callback) = &callback {
+                callback(stage);
+            }
+        };
+        let mut default_tracer = Tracer::default();
+        let tracer = env.tracer.as_mut().unwrap_or(&mut default_tracer);
+        let res = match (env.layout_iteration_limit, env.page_override) {
+            (None, None) if env.limits.is_unlimited() && callback.is_none() => {
+                typst::compile(self.world(), tracer)
+            }
+            (limit, page_override) => {
+                let limit = limit.unwrap_or(layout::DEFAULT_ITERATION_LIMIT);
+                let world = self.world();
+                let limits = &env.limits;
+                layout::compile_with_progress(world, tracer, limit, page_override, limits, &report)
+            }
         };
 
+        if let Ok(document) = &res {
+            let guarded = self.world().guarded_images();
+            images::warn_guarded_images(document, &guarded, tracer);
+        }
+        let res = res.map_err(|errors| cycle::explain_cycles(self.world(), main_id, errors));
+
         // compile document
         res.map(Arc::new)
     }
 
+    /// Evaluate the main source once from scratch, skipping the layout.
+    fn pure_evaluate(&mut self, env: &mut CompileEnv) -> SourceResult<Module> {
+        self.reset()?;
+
+        self.world_mut().prepare_env(env)?;
+
+        let main_id = self.main_id();
+
+        env.report_progress(progress::CompileStage::Parse);
+        if let Some(missing) = EntryNotFound::check(self.world(), main_id) {
+            return Err(eco_vec![missing.diagnostic()]);
+        }
+        let main = self
+            .world_mut()
+            .source(main_id)
+            .hint(AtFile(main_id))
+            .at(Span::detached())?;
+
+        env.report_progress(progress::CompileStage::Eval);
+        let mut default_tracer = Tracer::default();
+        let tracer = env.tracer.as_mut().unwrap_or(&mut default_tracer);
+        let world: &dyn World = self.world();
+        typst::eval::eval(
+            world.track(),
+            Route::default().track(),
+            tracer.track_mut(),
+            &main,
+        )
+        .map_err(|errors| cycle::explain_cycles(world, main_id, errors))
+    }
+
     /// With **the compilation state**, query the matches for the selector.
     fn pure_query(&mut self, selector: String, document: &Document) -> SourceResult<Vec<Content>> {
         self::query::retrieve(self.world(), &selector, document).at(Span::detached())
@@ -195,6 +431,12 @@ pub trait Compiler {
         self.pure_compile(env)
     }
 
+    /// Evaluate the main source once from scratch, e.g. to extract the values
+    /// of its bindings, see [`eval::module_to_json`].
+    fn evaluate(&mut self, env: &mut CompileEnv) -> SourceResult<Module> {
+        self.pure_evaluate(env)
+    }
+
     /// With **the compilation state**, query the matches for the selector.
     fn query(&mut self, selector: String, document: &Document) -> SourceResult<Vec<Content>> {
         self.pure_query(selector, document)
@@ -210,6 +452,13 @@ pub trait Compiler {
 
     fn notify_fs_event(&mut self, _event: FilesystemEvent) {}
 
+    /// Drop the caches of the files which are no longer depended on by the
+    /// compiler.
+    fn evict_files(&mut self, _paths: &[ImmutPath]) {}
+
+    /// Drop the caches of the files which are not accessed within the age.
+    fn evict_stale_files(&mut self, _age: std::time::Duration) {}
+
     /// Determine whether the event is relevant to the compiler.
     /// The default implementation is conservative, which means that
     /// `MaybeRelevant` implies `MustRelevant`.
@@ -300,6 +549,11 @@ pub trait CompileMiddleware {
         self.inner_mut().compile(env)
     }
 
+    /// Hooked evaluate once from scratch.
+    fn wrap_evaluate(&mut self, env: &mut CompileEnv) -> SourceResult<Module> {
+        self.inner_mut().evaluate(env)
+    }
+
     /// With **the compilation state**, hooked query the matches for the
     /// selector.
     fn wrap_query(&mut self, selector: String, document: &Document) -> SourceResult<Vec<Content>> {
@@ -338,6 +592,11 @@ impl<T: CompileMiddleware> Compiler for T {
         self.inner_mut().pure_compile(env)
     }
 
+    #[inline]
+    fn pure_evaluate(&mut self, env: &mut CompileEnv) -> SourceResult<Module> {
+        self.inner_mut().pure_evaluate(env)
+    }
+
     #[inline]
     fn pure_query(&mut self, selector: String, document: &Document) -> SourceResult<Vec<Content>> {
         self.inner_mut().pure_query(selector, document)
@@ -348,6 +607,11 @@ impl<T: CompileMiddleware> Compiler for T {
         self.wrap_compile(env)
     }
 
+    #[inline]
+    fn evaluate(&mut self, env: &mut CompileEnv) -> SourceResult<Module> {
+        self.wrap_evaluate(env)
+    }
+
     #[inline]
     fn query(&mut self, selector: String, document: &Document) -> SourceResult<Vec<Content>> {
         self.wrap_query(selector, document)
@@ -362,6 +626,16 @@ impl<T: CompileMiddleware> Compiler for T {
     fn notify_fs_event(&mut self, event: crate::vfs::notify::FilesystemEvent) {
         self.inner_mut().notify_fs_event(event)
     }
+
+    #[inline]
+    fn evict_files(&mut self, paths: &[ImmutPath]) {
+        self.inner_mut().evict_files(paths)
+    }
+
+    #[inline]
+    fn evict_stale_files(&mut self, age: std::time::Duration) {
+        self.inner_mut().evict_stale_files(age)
+    }
 }
 
 impl<T: CompileMiddleware> ShadowApi for T
@@ -392,6 +666,89 @@ where
     fn unmap_shadow(&self, path: &Path) -> FileResult<()> {
         self.inner().unmap_shadow(path)
     }
+
+    #[inline]
+    fn edit_shadow(
+        &self,
+        path: &Path,
+        range: std::ops::Range<usize>,
+        text: &str,
+    ) -> FileResult<usize> {
+        self.inner().edit_shadow(path, range, text)
+    }
+}
+
+/// The entry file of a compilation doesn't exist, e.g. a workspace is opened
+/// before its `main.typ` is created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryNotFound {
+    /// The path of the entry file relative to the root.
+    pub entry: PathBuf,
+    pub root: PathBuf,
+}
+
+impl EntryNotFound {
+    /// Check whether the main file of the world is missing.
+    pub fn check(world: &dyn World, main_id: TypstFileId) -> Option<Self> {
+        let Err(FileError::NotFound(path)) = world.source(main_id) else {
+            return None;
+        };
+        let entry = main_id.vpath().as_rootless_path().to_owned();
+        // The path is resolved by joining the root and the entry.
+        let depth = entry.components().count();
+        let root = path.ancestors().nth(depth).unwrap_or(&path).to_owned();
+        Some(Self { entry, root })
+    }
+
+    /// Get the diagnostic reported instead of the error of reading the entry.
+    pub fn diagnostic(&self) -> SourceDiagnostic {
+        SourceDiagnostic::error(Span::detached(), eco_format!("{self}"))
+            .with_hint("the document is compiled once the file is created")
+    }
+}
+
+impl fmt::Display for EntryNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "entry file '{}' not found under root '{}'",
+            self.entry.display(),
+            self.root.display()
+        )
+    }
+}
+
+/// The root of a workspace is gone or unreadable as a whole, e.g. the
+/// removable drive of the workspace is ejected or its remote mount
+/// disconnects.
+///
+/// It is reported once instead of the errors of every file under the root,
+/// e.g. by the `CompileActor` in watch mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootUnavailable {
+    pub root: PathBuf,
+    /// The error of accessing the root itself.
+    pub error: FileError,
+}
+
+impl RootUnavailable {
+    /// Get the diagnostic reported instead of the errors of the files.
+    pub fn diagnostic(&self) -> SourceDiagnostic {
+        SourceDiagnostic::error(Span::detached(), eco_format!("{self}"))
+            .with_hint("check whether the drive or the mount of the workspace is connected")
+            .with_hint("the document is compiled again once the root is available")
+    }
+}
+
+impl fmt::Display for RootUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "workspace root '{}' is unavailable: {}",
+            self.root.display(),
+            self.error
+        )
+    }
 }
 
 struct AtFile(TypstFileId);
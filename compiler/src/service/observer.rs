@@ -0,0 +1,47 @@
+//! Observe the lifecycle of the compile actor, e.g. to integrate the
+//! compilations into metrics or traces, such as OpenTelemetry, without parsing
+//! the logs.
+//!
+//! An [`ActorObserver`] is set by `CompileActor::with_observer`. Its hooks are
+//! called on the compiler thread in watch mode, hence they should return
+//! quickly, e.g. by sending the events to another thread.
+
+use std::time::Duration;
+
+use super::session_log::InterruptKind;
+
+/// The end of a compilation, see [`ActorObserver::on_compile_end`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompileEnd {
+    /// The id of the request compiling the document.
+    pub request: u64,
+    /// The revision of the compiled world.
+    pub revision: usize,
+    /// Whether the compilation succeeded.
+    pub ok: bool,
+    pub duration: Duration,
+}
+
+/// The hooks of the lifecycle of the compile actor, which do nothing by
+/// default.
+pub trait ActorObserver: Send {
+    /// Called when an interrupt is received, where each event of a batch of
+    /// memory events is reported once.
+    fn on_event_received(&self, _kind: InterruptKind) {}
+
+    /// Called when a compilation of a request starts, which is skipped while
+    /// the root is unavailable.
+    fn on_compile_start(&self, _request: u64) {}
+
+    /// Called when a compilation ends.
+    fn on_compile_end(&self, _end: &CompileEnd) {}
+
+    /// Called when the compiler thread exits.
+    fn on_shutdown(&self) {}
+}
+
+/// An observer doing nothing, which is the default of the actor.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopObserver;
+
+impl ActorObserver for NoopObserver {}
@@ -0,0 +1,164 @@
+//! Describe the headings of a compiled document with their anchors.
+//!
+//! Static viewers link to the headings of a document by URL fragments, e.g.
+//! `#sec-installation`, and show their numbering without the compiler. The
+//! slugs are allocated by [`SlugAllocator`] in the order of the document, as
+//! the HTML exporter does for its `id` attributes, so that the links work
+//! across the output formats.
+//!
+//! The numbering is resolved from the heading counter at each heading, e.g.
+//! `2.3.1` for the pattern `1.1.1`. A numbering function is called with the
+//! counter, and the plain text of its result is reported.
+
+use comemo::Track;
+use typst::{
+    engine::{Engine, Route},
+    eval::Tracer,
+    foundations::{Content, Context, Element, FromValue, Selector, Value},
+    introspection::{Counter, Locator},
+    model::{HeadingElem, Numbering},
+    World,
+};
+use typst_ts_core::{
+    slug::SlugAllocator,
+    vector::ir::{OutlineItem, Scalar},
+    TypstDocument,
+};
+
+/// A heading of a document, see [`document_outline`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadingAnchor {
+    /// The 1-based level of the heading.
+    pub level: usize,
+    /// The plain text of the heading.
+    pub text: String,
+    /// The resolved numbering, e.g. `2.3.1`, if the heading is numbered.
+    pub numbering: Option<String>,
+    /// The 1-based page number of the heading.
+    pub page: usize,
+    /// The top of the heading on the page in pt.
+    pub y: f64,
+    /// The anchor of the heading, which is unique in the document.
+    pub slug: String,
+}
+
+/// Describe the headings of a document compiled by the world in the order of
+/// the document, see the [module docs](self).
+pub fn document_outline(world: &dyn World, document: &TypstDocument) -> Vec<HeadingAnchor> {
+    let introspector = &document.introspector;
+    let headings = introspector.query(&Selector::Elem(Element::of::<HeadingElem>(), None));
+
+    let mut tracer = Tracer::new();
+    let mut locator = Locator::new();
+    let mut engine = Engine {
+        world: world.track(),
+        route: Route::default(),
+        tracer: tracer.track_mut(),
+        locator: &mut locator,
+        introspector: introspector.track(),
+    };
+    let counter = Counter::of(Element::of::<HeadingElem>());
+
+    let mut slugs = SlugAllocator::new();
+    (headings.iter())
+        .filter_map(|heading| {
+            let location = heading.location()?;
+            let position = introspector.position(location);
+            let text = heading_text(heading);
+            let numbering = numbering(heading).and_then(|numbering| {
+                let state = counter.at_loc(&mut engine, location).ok()?;
+                let context = Context::new(Some(location), None);
+                let value = (numbering.apply(&mut engine, context.track(), &state.0)).ok()?;
+                Some(value.display().plain_text().to_string())
+            });
+
+            Some(HeadingAnchor {
+                level: level(heading),
+                slug: slugs.heading(&text),
+                text,
+                numbering,
+                page: position.page.get(),
+                y: position.point.y.to_pt(),
+            })
+        })
+        .collect()
+}
+
+/// Convert headings into the outline of a vector artifact, see
+/// [`super::ArtifactOptions::outline`].
+pub fn outline_table(headings: &[HeadingAnchor]) -> Vec<OutlineItem> {
+    (headings.iter())
+        .map(|heading| OutlineItem {
+            level: heading.level as u32,
+            text: heading.text.as_str().into(),
+            numbering: heading.numbering.as_deref().map(Into::into),
+            page: heading.page as u32,
+            y: Scalar(heading.y as f32),
+            slug: heading.slug.as_str().into(),
+        })
+        .collect()
+}
+
+/// Get the level of a laid out heading, which is either set explicitly or is
+/// the depth shifted by the offset.
+fn level(heading: &Content) -> usize {
+    let fields = heading.fields();
+    let int = |name: &str| match fields.get(name) {
+        Ok(Value::Int(n)) => usize::try_from(*n).ok(),
+        _ => None,
+    };
+    int("level").unwrap_or_else(|| int("depth").unwrap_or(1) + int("offset").unwrap_or(0))
+}
+
+/// Get the plain text of the body of a heading, which excludes the synthesized
+/// fields like the supplement.
+pub(crate) fn heading_text(heading: &Content) -> String {
+    match heading.fields().get("body") {
+        Ok(Value::Content(body)) => body.plain_text().to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Get the numbering of a laid out heading, if it is numbered.
+fn numbering(heading: &Content) -> Option<Numbering> {
+    match heading.fields().get("numbering") {
+        Ok(Value::None) | Err(..) => None,
+        Ok(value) => Numbering::from_value(value.clone()).ok(),
+    }
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileEnv, Compiler},
+    };
+
+    #[test]
+    fn test_document_outline() {
+        let ws = TestWorkspace::new();
+        let content = "#set heading(numbering: \"1.1\")\n= Intro\n== Usage\n= Usage\n== Usage\n#pagebreak()\n#heading(numbering: none)[Notes]";
+
+        let mut driver = ws.shadow_driver(content.as_bytes());
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+        let outline = document_outline(&driver.world, &doc);
+        let summary: Vec<_> = (outline.iter())
+            .map(|h| (h.level, h.numbering.as_deref(), h.slug.as_str(), h.page))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, Some("1"), "sec-intro", 1),
+                (2, Some("1.1"), "sec-usage", 1),
+                (1, Some("2"), "sec-usage-1", 1),
+                (2, Some("2.1"), "sec-usage-2", 1),
+                (1, None, "sec-notes", 2),
+            ]
+        );
+        assert!(outline.windows(2).take(3).all(|w| w[0].y < w[1].y));
+        assert_eq!(outline[4].text, "Notes");
+        assert_eq!(outline_table(&outline)[1].slug.as_ref(), "sec-usage");
+    }
+}
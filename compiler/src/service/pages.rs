@@ -0,0 +1,390 @@
+//! Describe the geometry of the pages of a compiled document.
+//!
+//! Viewers lay out scroll containers, rulers and "fit width" before rendering
+//! any page. The size of a page is read from its frame, so that documents with
+//! mixed page sizes are described page by page.
+//!
+//! The margins are not kept in the laid out frames, hence they are resolved
+//! from the page setup: the styles of the library, the page override of the
+//! compiler, and the page set rules and explicit pages of the main source,
+//! e.g. `#set page(margin: 2cm)` or `#page(margin: 0pt)[..]`. Each part of the
+//! content with its own page setup is matched to the pages showing its first
+//! texts, see [`document_page_metadata`]. Inside and outside margins of
+//! two-sided layouts are reported as the left and right margins.
+
+use std::collections::HashSet;
+
+use comemo::Track;
+use typst::{
+    engine::Route,
+    eval::Tracer,
+    foundations::{
+        Content, NativeElement, Packed, Resolve, Selector, SequenceElem, Smart, StyleChain,
+        StyledElem, Styles,
+    },
+    layout::{Abs, Frame, FrameItem, Length, PageElem, Rel, Size},
+    model::ParbreakElem,
+    syntax::Span,
+    text::{SpaceElem, TextElem},
+    util::Numeric,
+    World,
+};
+use typst_ts_core::{
+    vector::ir::{self, PageMetaItem, Scalar},
+    TypstDocument,
+};
+
+use super::layout::PageOverride;
+
+/// The margins of a page in pt.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PageMargins {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+/// The geometry of a page, see [`page_metadata`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageMeta {
+    /// The 1-based page number.
+    pub page: usize,
+    /// The width of the page in pt.
+    pub width: f64,
+    /// The height of the page in pt.
+    pub height: f64,
+    /// The effective margins resolved from the page setup.
+    pub margins: PageMargins,
+    /// Whether the size differs from the size shared by most pages of the
+    /// document.
+    pub differs_from_default: bool,
+}
+
+impl PageMeta {
+    /// Whether the page is wider than it is high.
+    pub fn is_landscape(&self) -> bool {
+        self.width > self.height
+    }
+}
+
+/// Describe the pages of a document compiled by the world, see the
+/// [module docs](self).
+///
+/// The main source is evaluated again to find its page setups, which is cheap
+/// compared to the layout. A page gets the setup of the last part of the
+/// content starting at or before it, where a part starts at the first page
+/// showing one of its texts.
+pub fn document_page_metadata(
+    world: &dyn World,
+    document: &TypstDocument,
+    page_override: Option<PageOverride>,
+) -> Vec<PageMeta> {
+    let overrides = page_override.map(|page| page.styles()).unwrap_or_default();
+    let runs = page_runs(world);
+    let located = locate_runs(document, &runs);
+
+    let library = world.library();
+    let base = StyleChain::new(&library.styles);
+    describe_pages(document, |idx, size| {
+        let run = &runs[located[idx]];
+        // The override is forced after the page setup of the document, see
+        // [`PageOverride::force`].
+        let styles = base.chain(&run.styles);
+        let styles = styles.chain(&overrides);
+        resolve_margins(styles, run.page.as_deref(), size)
+    })
+}
+
+/// Describe the pages of a document, resolving the margins in the given
+/// styles.
+pub fn page_metadata(document: &TypstDocument, styles: StyleChain) -> Vec<PageMeta> {
+    describe_pages(document, |_, size| resolve_margins(styles, None, size))
+}
+
+/// Describe the pages of a document with the margins resolved by the index
+/// and the size of each page.
+fn describe_pages(
+    document: &TypstDocument,
+    margins: impl Fn(usize, Size) -> PageMargins,
+) -> Vec<PageMeta> {
+    // The most common size, where the first size wins a tie.
+    let mut sizes: Vec<(Size, usize)> = vec![];
+    for page in &document.pages {
+        let size = page.frame.size();
+        match sizes.iter_mut().find(|(s, _)| *s == size) {
+            Some((_, count)) => *count += 1,
+            None => sizes.push((size, 1)),
+        }
+    }
+    let mut default = None;
+    for (size, count) in &sizes {
+        if default.is_none_or(|(_, max)| *count > max) {
+            default = Some((*size, *count));
+        }
+    }
+    let default = default.map(|(size, _)| size);
+
+    (document.pages.iter().enumerate())
+        .map(|(idx, page)| {
+            let size = page.frame.size();
+            PageMeta {
+                page: idx + 1,
+                width: size.x.to_pt(),
+                height: size.y.to_pt(),
+                margins: margins(idx, size),
+                differs_from_default: default != Some(size),
+            }
+        })
+        .collect()
+}
+
+/// Convert page metadata into the table of a vector artifact, see
+/// [`super::ArtifactOptions::page_metadata`].
+pub fn page_meta_table(pages: &[PageMeta]) -> Vec<PageMetaItem> {
+    let scalar = |pt: f64| Scalar(pt as f32);
+    (pages.iter())
+        .map(|page| PageMetaItem {
+            size: ir::Size::new(scalar(page.width), scalar(page.height)),
+            margins: ir::PageMargins {
+                left: scalar(page.margins.left),
+                top: scalar(page.margins.top),
+                right: scalar(page.margins.right),
+                bottom: scalar(page.margins.bottom),
+            },
+            differs_from_default: page.differs_from_default,
+        })
+        .collect()
+}
+
+/// Resolve the margins of a page like the layout of typst, where an `auto`
+/// margin is 2.5/21 of the smaller side of the configured page size. The
+/// arguments of an explicit page take precedence over the styles.
+fn resolve_margins(styles: StyleChain, page: Option<&PageElem>, size: Size) -> PageMargins {
+    let (width, height, margin) = match page {
+        Some(page) => (page.width(styles), page.height(styles), page.margin(styles)),
+        None => (
+            PageElem::width_in(styles),
+            PageElem::height_in(styles),
+            PageElem::margin_in(styles),
+        ),
+    };
+    let configured = |length: Smart<Abs>| length.unwrap_or(Abs::inf());
+    let min = configured(width).min(configured(height));
+    let min = if min.is_finite() {
+        min
+    } else {
+        size.x.min(size.y)
+    };
+    let default = Rel::<Length>::from((2.5 / 21.0) * min);
+
+    let sides = margin.sides;
+    let resolve = |side: Option<Smart<Rel<Length>>>, whole: Abs| {
+        let side = side.and_then(Smart::custom).unwrap_or(default);
+        side.resolve(styles).relative_to(whole).to_pt()
+    };
+    PageMargins {
+        left: resolve(sides.left, size.x),
+        top: resolve(sides.top, size.y),
+        right: resolve(sides.right, size.x),
+        bottom: resolve(sides.bottom, size.y),
+    }
+}
+
+/// The number of spans of texts locating a part of the content.
+const RUN_SPANS: usize = 8;
+
+/// A part of the content laid out with the same page setup.
+#[derive(Default)]
+struct PageRun {
+    /// The styles of the set rules wrapping the part.
+    styles: Styles,
+    /// The explicit page wrapping the part.
+    page: Option<Packed<PageElem>>,
+    /// The spans of the first texts of the part.
+    spans: Vec<Span>,
+}
+
+/// Collect the parts of the main source with their page setups, where the
+/// first part is set up by the library.
+fn page_runs(world: &dyn World) -> Vec<PageRun> {
+    let world = world.track();
+    let mut tracer = Tracer::new();
+    let module = typst::eval::eval(
+        world,
+        Route::default().track(),
+        tracer.track_mut(),
+        &world.main(),
+    );
+
+    let mut runs = vec![PageRun::default()];
+    if let Ok(module) = module {
+        collect_runs(&module.content(), &Styles::new(), None, &mut runs);
+    }
+    runs
+}
+
+/// Walk the content like the layout of a document, starting a part whenever
+/// the page setup changes, i.e. by a page set rule or an explicit page, and
+/// after it ends.
+fn collect_runs(
+    content: &Content,
+    styles: &Styles,
+    page: Option<&Packed<PageElem>>,
+    runs: &mut Vec<PageRun>,
+) {
+    if let Some(sequence) = content.to_packed::<SequenceElem>() {
+        for child in &sequence.children {
+            collect_runs(child, styles, page, runs);
+        }
+    } else if let Some(styled) = content.to_packed::<StyledElem>() {
+        let mut inner = styled.styles.clone();
+        inner.apply(styles.clone());
+        let setup = styled.styles.interruption::<PageElem>().is_some();
+        if setup {
+            start_run(runs, &inner, page);
+        }
+        collect_runs(&styled.child, &inner, page, runs);
+        if setup {
+            start_run(runs, styles, page);
+        }
+    } else if let Some(elem) = content.to_packed::<PageElem>() {
+        start_run(runs, styles, Some(elem));
+        collect_runs(&elem.body, styles, Some(elem), runs);
+        start_run(runs, styles, page);
+    } else if let Some(run) = runs.last_mut() {
+        let texts = content.query(Selector::Elem(TextElem::elem(), None));
+        let spans = std::iter::once(content.span()).chain(texts.iter().map(Content::span));
+        let spans = spans.filter(|span| !span.is_detached());
+        let rest = RUN_SPANS.saturating_sub(run.spans.len());
+        run.spans.extend(spans.take(rest));
+    }
+}
+
+/// Start a part of the content with its page setup.
+fn start_run(runs: &mut Vec<PageRun>, styles: &Styles, page: Option<&Packed<PageElem>>) {
+    runs.push(PageRun {
+        styles: styles.clone(),
+        page: page.cloned(),
+        spans: vec![],
+    });
+}
+
+/// Get the index of the part of the content laid out in each page, see
+/// [`document_page_metadata`].
+fn locate_runs(document: &TypstDocument, runs: &[PageRun]) -> Vec<usize> {
+    let wanted: HashSet<Span> = runs
+        .iter()
+        .flat_map(|run| run.spans.iter().copied())
+        .collect();
+    let shown: Vec<_> = (document.pages.iter())
+        .map(|page| {
+            let mut shown = HashSet::new();
+            frame_spans(&page.frame, &wanted, &mut shown);
+            shown
+        })
+        .collect();
+
+    let mut located = vec![0; shown.len()];
+    let mut cursor = 0;
+    for (idx, run) in runs.iter().enumerate().skip(1) {
+        let start = (cursor..shown.len())
+            .find(|&page| run.spans.iter().any(|span| shown[page].contains(span)));
+        if let Some(start) = start {
+            located[start..].fill(idx);
+            cursor = start;
+        }
+    }
+    located
+}
+
+/// Collect the wanted spans shown in a frame.
+fn frame_spans(frame: &Frame, wanted: &HashSet<Span>, shown: &mut HashSet<Span>) {
+    for (_, item) in frame.items() {
+        match item {
+            FrameItem::Group(group) => frame_spans(&group.frame, wanted, shown),
+            FrameItem::Text(text) => {
+                let spans = text.glyphs.iter().map(|glyph| glyph.span.0);
+                shown.extend(spans.filter(|span| wanted.contains(span)));
+            }
+            FrameItem::Shape(_, span) | FrameItem::Image(_, _, span) if wanted.contains(span) => {
+                shown.insert(*span);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Collect the styles of the set and show rules wrapping the content, which
+/// apply to all of it.
+pub(crate) fn leading_styles(content: &Content, setup: &mut Styles) {
+    if let Some(styled) = content.to_packed::<StyledElem>() {
+        let mut styles = styled.styles.clone();
+        styles.apply(std::mem::take(setup));
+        *setup = styles;
+        leading_styles(&styled.child, setup);
+    } else if let Some(sequence) = content.to_packed::<SequenceElem>() {
+        // A set rule wraps the rest of the document, hence it is the only
+        // child besides the spaces before it.
+        let mut children = (sequence.children.iter())
+            .filter(|child| !(child.is::<SpaceElem>() || child.is::<ParbreakElem>()));
+        if let (Some(child), None) = (children.next(), children.next()) {
+            leading_styles(child, setup);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileEnv, Compiler},
+    };
+
+    #[test]
+    fn test_page_metadata() {
+        let ws = TestWorkspace::new();
+        let content = "#set page(width: 300pt, height: 200pt, margin: (x: 10pt, top: 20%))
+= Wide
+#page(width: 100pt, height: 400pt)[Tall]
+Wide again
+#set page(margin: 5pt)
+Narrow margins";
+
+        let mut driver = ws.shadow_driver(content.as_bytes());
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+        let pages = document_page_metadata(&driver.world, &doc, None);
+        let sizes: Vec<_> = pages.iter().map(|p| (p.width, p.height)).collect();
+        assert_eq!(
+            sizes,
+            [(300., 200.), (100., 400.), (300., 200.), (300., 200.)]
+        );
+        let differs: Vec<_> = pages.iter().map(|p| p.differs_from_default).collect();
+        assert_eq!(differs, [false, true, false, false]);
+        assert!(pages[0].is_landscape() && !pages[1].is_landscape());
+
+        let margins = pages[0].margins;
+        assert_eq!((margins.left, margins.right), (10., 10.));
+        assert!((margins.top - 40.).abs() < 1e-6, "{margins:?}");
+        // An auto margin is 2.5/21 of the smaller side of the page.
+        assert!(
+            (margins.bottom - 200. * 2.5 / 21.).abs() < 1e-6,
+            "{margins:?}"
+        );
+
+        assert_eq!(pages[2].margins, margins);
+
+        // The explicit page and the later set rule set up their own pages.
+        let tall = pages[1].margins;
+        assert!((tall.top - 80.).abs() < 1e-6, "{tall:?}");
+        assert!((tall.bottom - 100. * 2.5 / 21.).abs() < 1e-6, "{tall:?}");
+        let narrow = pages[3].margins;
+        assert_eq!((narrow.left, narrow.top, narrow.bottom), (5., 5., 5.));
+
+        let table = page_meta_table(&pages);
+        assert_eq!(table.len(), 4);
+        assert!(table[1].differs_from_default);
+    }
+}
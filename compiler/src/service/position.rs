@@ -0,0 +1,195 @@
+//! Conversion between byte offsets and (line, column) positions.
+//!
+//! Typst addresses sources by byte offsets, while editors address them by
+//! lines and columns in some unit, see [`PositionEncoding`]. All services
+//! returning positions convert them here, so that they agree with each other.
+//!
+//! The conversion never panics: offsets out of bounds or inside a character
+//! are clamped, and so are positions beyond the end of a line or a file, as
+//! in the language server protocol.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use typst::syntax::Source;
+
+use super::{DiagnosticPosition, PositionEncoding};
+
+/// A range between two positions in a file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LspRange {
+    pub start: DiagnosticPosition,
+    /// The exclusive end position.
+    pub end: DiagnosticPosition,
+}
+
+/// Convert a byte offset into a position.
+pub fn to_lsp_position(
+    source: &Source,
+    offset: usize,
+    encoding: PositionEncoding,
+) -> DiagnosticPosition {
+    let text = source.text();
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let line = source.byte_to_line(offset).unwrap_or_default();
+    let line_start = source.line_to_byte(line).unwrap_or_default().min(offset);
+    let column = text[line_start..offset]
+        .chars()
+        .map(|c| char_len(c, encoding))
+        .sum();
+
+    DiagnosticPosition { line, column }
+}
+
+/// Convert a byte range into a range of positions.
+///
+/// A reversed range is treated as an empty range at its start.
+pub fn to_lsp_range(source: &Source, range: Range<usize>, encoding: PositionEncoding) -> LspRange {
+    let start = to_lsp_position(source, range.start, encoding);
+    let end = if range.end > range.start {
+        to_lsp_position(source, range.end, encoding)
+    } else {
+        start
+    };
+
+    LspRange { start, end }
+}
+
+/// Convert a position into a byte offset.
+///
+/// A column beyond the end of its line is clamped to the end of the line,
+/// excluding the line terminator, and a line beyond the end of the file is
+/// clamped to the end of the file. A column in the middle of a character,
+/// e.g. between the surrogates of a UTF-16 pair, is rounded down to the
+/// start of the character.
+pub fn to_offset(
+    source: &Source,
+    position: DiagnosticPosition,
+    encoding: PositionEncoding,
+) -> usize {
+    let text = source.text();
+    let Some(line_start) = source.line_to_byte(position.line) else {
+        return text.len();
+    };
+    let line_end = source.line_to_byte(position.line + 1).unwrap_or(text.len());
+    let line = text[line_start..line_end].trim_end_matches(is_newline);
+
+    let mut column = 0;
+    for (idx, c) in line.char_indices() {
+        column += char_len(c, encoding);
+        if column > position.column {
+            return line_start + idx;
+        }
+    }
+
+    line_start + line.len()
+}
+
+/// Convert a range of positions into a byte range.
+pub fn to_offset_range(
+    source: &Source,
+    range: LspRange,
+    encoding: PositionEncoding,
+) -> Range<usize> {
+    let start = to_offset(source, range.start, encoding);
+    let end = to_offset(source, range.end, encoding).max(start);
+    start..end
+}
+
+/// The length of a character in the unit of the encoding.
+fn char_len(c: char, encoding: PositionEncoding) -> usize {
+    match encoding {
+        PositionEncoding::Utf8 => c.len_utf8(),
+        PositionEncoding::Utf16 => c.len_utf16(),
+        PositionEncoding::Utf32 => 1,
+    }
+}
+
+/// Whether a character terminates a line, as in Typst.
+fn is_newline(c: char) -> bool {
+    matches!(
+        c,
+        '\n' | '\x0B' | '\x0C' | '\r' | '\u{0085}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODINGS: [PositionEncoding; 3] = [
+        PositionEncoding::Utf8,
+        PositionEncoding::Utf16,
+        PositionEncoding::Utf32,
+    ];
+
+    /// Generate a document of multibyte characters and line terminators.
+    fn random_text(seed: &mut u64, len: usize) -> String {
+        const CHARS: &[&str] = &["a", "é", "中", "😀", " ", "\n", "\r\n", "\u{2028}", "#"];
+
+        (0..len)
+            .map(|_| {
+                // xorshift
+                *seed ^= *seed << 13;
+                *seed ^= *seed >> 7;
+                *seed ^= *seed << 17;
+                CHARS[(*seed % CHARS.len() as u64) as usize]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_position_round_trip() {
+        let mut seed = 0x2545_F491_4F6C_DD1D;
+        for len in 0..64 {
+            let source = Source::detached(random_text(&mut seed, len));
+            let text = source.text();
+
+            for offset in (0..=text.len()).filter(|off| text.is_char_boundary(*off)) {
+                // Positions inside a line terminator are not representable.
+                if text[..offset].ends_with('\r') && text[offset..].starts_with('\n') {
+                    continue;
+                }
+
+                for encoding in ENCODINGS {
+                    let position = to_lsp_position(&source, offset, encoding);
+                    assert_eq!(to_offset(&source, position, encoding), offset, "{text:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_clamp_position() {
+        let source = Source::detached("a😀\nb");
+
+        for encoding in ENCODINGS {
+            let end = to_lsp_position(&source, 100, encoding);
+            assert_eq!(end, DiagnosticPosition { line: 1, column: 1 });
+
+            let past_line = DiagnosticPosition {
+                line: 0,
+                column: 100,
+            };
+            assert_eq!(to_offset(&source, past_line, encoding), 5);
+            let past_file = DiagnosticPosition { line: 9, column: 0 };
+            assert_eq!(to_offset(&source, past_file, encoding), 7);
+
+            let empty = to_lsp_range(&source, 7..7, encoding);
+            assert_eq!(empty.start, empty.end);
+            #[allow(clippy::reversed_empty_ranges)]
+            let reversed = to_lsp_range(&source, 6..2, encoding);
+            assert_eq!(reversed.start, reversed.end);
+        }
+
+        // Inside a character.
+        let half = to_lsp_position(&source, 2, PositionEncoding::Utf16);
+        assert_eq!(half, DiagnosticPosition { line: 0, column: 1 });
+        let surrogate = DiagnosticPosition { line: 0, column: 2 };
+        assert_eq!(to_offset(&source, surrogate, PositionEncoding::Utf16), 1);
+    }
+}
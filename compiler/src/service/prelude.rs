@@ -0,0 +1,58 @@
+//! Compile documents with a custom prelude, e.g. to share helpers or to set
+//! document-wide defaults without editing the sources.
+//!
+//! The prelude is a source of its own, identified by [`PRELUDE_ID`], which is
+//! evaluated before each compilation. Its bindings are added to the global
+//! scope of the library, overriding the standard definitions of the same
+//! names, and its set and show rules become default styles of the library,
+//! e.g. `#set text(lang: "de")`. Any other content of the prelude is ignored.
+//!
+//! Since the main source is not modified, the diagnostics and the jumps of the
+//! main source keep their positions. Diagnostics of the prelude point into
+//! the prelude.
+
+use comemo::Track;
+use once_cell::sync::Lazy;
+use typst::{
+    diag::SourceResult,
+    engine::Route,
+    eval::Tracer,
+    foundations::Styles,
+    syntax::{FileId, Source, VirtualPath},
+    Library, World,
+};
+
+use super::pages;
+
+/// The id of the prelude source, see [`crate::world::CompilerWorld::set_prelude`].
+pub static PRELUDE_ID: Lazy<FileId> =
+    Lazy::new(|| FileId::new(None, VirtualPath::new("/__prelude.typ")));
+
+/// Evaluate the prelude in the world, returning the library of the world
+/// extended by the prelude, see the [module docs](self).
+pub fn apply_prelude(
+    world: &dyn World,
+    tracer: &mut Tracer,
+    prelude: &Source,
+) -> SourceResult<Library> {
+    let module = typst::eval::eval(
+        world.track(),
+        Route::default().track(),
+        tracer.track_mut(),
+        prelude,
+    )?;
+
+    let mut library = Library::clone(world.library());
+    let scope = library.global.scope_mut();
+    for (name, value) in module.scope().iter() {
+        scope.define(name.clone(), value.clone());
+    }
+
+    // The rules of the prelude take precedence over the defaults.
+    let mut styles = Styles::new();
+    pages::leading_styles(&module.content(), &mut styles);
+    styles.apply(std::mem::take(&mut library.styles));
+    library.styles = styles;
+
+    Ok(library)
+}
@@ -0,0 +1,58 @@
+//! Report the progress of compilations.
+//!
+//! Large documents take seconds to compile, during which editors show the
+//! stage of the compilation instead of a frozen spinner. The stages are
+//! reported by the callback of [`super::CompileEnv::progress`]:
+//!
+//! - [`CompileStage::Parse`] when the main source is resolved.
+//! - [`CompileStage::Eval`] when the main source starts to be evaluated.
+//! - [`CompileStage::Layout`] when a layout iteration starts, since typst
+//!   relayouts the document until the introspections stabilize. Typst doesn't
+//!   expose hooks of completed pages, hence the layout is reported by
+//!   iterations rather than by pages.
+//! - [`CompileStage::Export`] when the laid out document starts to be
+//!   exported by an exporting middleware, e.g. [`super::CompileExporter`].
+//! - [`CompileStage::Done`] when the compilation finishes, which is reported
+//!   by the [`super::CompileActor`].
+//!
+//! The actor delivers the stages through a `watch` channel, see
+//! [`super::CompileClient::progress`], which only keeps the latest progress,
+//! so that slow consumers never block the compiler thread.
+
+use std::sync::Arc;
+
+use serde::Serialize;
+
+/// A stage of a compilation, see the [module docs](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum CompileStage {
+    /// The main source is being resolved and parsed.
+    Parse,
+    /// The main source is being evaluated.
+    Eval,
+    /// The document is being laid out, in the 1-based iteration.
+    Layout { iteration: usize },
+    /// The document of the given number of pages is being exported.
+    Export { pages: usize },
+    /// The compilation finished.
+    Done { success: bool },
+}
+
+/// The progress of a compilation, as delivered by
+/// [`super::CompileClient::progress`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileProgress {
+    /// The revision of the compilation, which starts from 1.
+    ///
+    /// A progress of an older revision than the latest known one belongs to
+    /// an abandoned compilation and can be discarded.
+    pub revision: u64,
+    /// The stage the compilation has entered.
+    pub stage: CompileStage,
+    /// The time when the stage is entered.
+    pub timestamp: crate::Time,
+}
+
+/// The callback receiving the stages of compilations.
+pub type ProgressCallback = Arc<dyn Fn(CompileStage) + Send + Sync>;
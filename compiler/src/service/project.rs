@@ -0,0 +1,299 @@
+//! Read the options of a project from the `typst-ts.toml` at the root of its
+//! workspace, so that they don't have to be repeated by every command.
+//!
+//! ```toml
+//! entry = "main.typ"
+//! font-paths = ["fonts"]
+//!
+//! [packages]
+//! "@preview/example:0.1.0" = "vendor/example"
+//!
+//! [export]
+//! output = "out"
+//!
+//! [watch]
+//! ignore = ["out", "data/generated.csv"]
+//! ```
+//!
+//! The relative paths are relative to the directory of the file. The
+//! `packages` table resolves packages to local directories instead of the
+//! registry, and the files or directories listed by `watch.ignore` are not
+//! watched for changes.
+//!
+//! The options are layered by precedence: the command line, then the options
+//! of [`super::CompileDriverBuilder`], then the project file, then the
+//! defaults. A list set by a layer replaces the list of the layers below,
+//! while the `packages` table is merged by package.
+//!
+//! An unknown key is warned about and ignored, so that a file written for a
+//! newer version is still usable. In watch mode, the file is reloaded when it
+//! changes, see [`super::CompileActor::with_project`].
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use typst_ts_core::{error::prelude::*, path::PathClean, ImmutPath};
+
+use crate::package::PackageSpec;
+
+/// The name of the project file at the root of a workspace.
+pub const PROJECT_FILE: &str = "typst-ts.toml";
+
+/// The options of a project, see the [module docs](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ProjectConfig {
+    /// The default entry file.
+    pub entry: Option<PathBuf>,
+    /// The files or directories of fonts to search.
+    pub font_paths: Vec<PathBuf>,
+    /// The local directories of packages by their specs, e.g.
+    /// `@preview/example:0.1.0`.
+    pub packages: BTreeMap<String, PathBuf>,
+    pub export: ExportConfig,
+    pub watch: WatchConfig,
+}
+
+/// The defaults of exports, see [`ProjectConfig`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ExportConfig {
+    /// The directory of the exported files.
+    pub output: Option<PathBuf>,
+}
+
+/// The options of watch mode, see [`ProjectConfig`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct WatchConfig {
+    /// The files or directories whose changes are not watched.
+    pub ignore: Vec<PathBuf>,
+}
+
+impl ProjectConfig {
+    /// Parse the content of a project file, whose relative paths are resolved
+    /// against `base`, returning the warnings about the ignored keys as well.
+    pub fn parse(content: &str, base: &Path) -> ZResult<(Self, Vec<String>)> {
+        let table: toml::Table =
+            (content.parse()).map_err(map_string_err("failed to parse the project file"))?;
+
+        let mut warnings = vec![];
+        unknown_keys(&table, &mut warnings);
+        let mut config: Self = (toml::Value::Table(table).try_into())
+            .map_err(map_string_err("invalid project file"))?;
+        config
+            .packages
+            .retain(|spec, _| match spec.parse::<PackageSpec>() {
+                Ok(_) => true,
+                Err(err) => {
+                    warnings.push(format!("ignored package `{spec}`: {err}"));
+                    false
+                }
+            });
+
+        config.resolve(base);
+        Ok((config, warnings))
+    }
+
+    /// Load a project file, logging the warnings about the ignored keys.
+    pub fn load(path: &Path) -> ZResult<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(map_string_err("failed to read the project file"))?;
+        Self::load_content(path, &content)
+    }
+
+    /// Load the project file at the root of a workspace if it exists.
+    pub fn discover(root: &Path) -> ZResult<Option<Self>> {
+        let path = root.join(PROJECT_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Self::load(&path).map(Some)
+    }
+
+    fn load_content(path: &Path, content: &str) -> ZResult<Self> {
+        let base = path.parent().unwrap_or(Path::new(""));
+        let (config, warnings) = Self::parse(content, base)?;
+        for warning in warnings {
+            log::warn!("{}: {warning}", path.display());
+        }
+        Ok(config)
+    }
+
+    /// Layer the options over those of a lower precedence, see the
+    /// [module docs](self).
+    pub fn merge_over(self, lower: Self) -> Self {
+        let list = |list: Vec<PathBuf>, lower: Vec<PathBuf>| match list.is_empty() {
+            true => lower,
+            false => list,
+        };
+
+        let mut packages = lower.packages;
+        packages.extend(self.packages);
+        Self {
+            entry: self.entry.or(lower.entry),
+            font_paths: list(self.font_paths, lower.font_paths),
+            packages,
+            export: ExportConfig {
+                output: self.export.output.or(lower.export.output),
+            },
+            watch: WatchConfig {
+                ignore: list(self.watch.ignore, lower.watch.ignore),
+            },
+        }
+    }
+
+    /// Get the local directories of packages, see [`ProjectConfig::packages`].
+    pub fn package_overrides(&self) -> Vec<(PackageSpec, PathBuf)> {
+        (self.packages.iter())
+            .filter_map(|(spec, dir)| Some((spec.parse().ok()?, dir.clone())))
+            .collect()
+    }
+
+    /// Whether changes of the file are not watched, see
+    /// [`WatchConfig::ignore`].
+    pub fn is_watch_ignored(&self, path: &Path) -> bool {
+        self.watch
+            .ignore
+            .iter()
+            .any(|ignored| path.starts_with(ignored))
+    }
+
+    fn resolve(&mut self, base: &Path) {
+        let resolve = |path: &mut PathBuf| *path = base.join(&*path).clean();
+
+        self.entry.iter_mut().for_each(resolve);
+        self.font_paths.iter_mut().for_each(resolve);
+        self.packages.values_mut().for_each(resolve);
+        self.export.output.iter_mut().for_each(resolve);
+        self.watch.ignore.iter_mut().for_each(resolve);
+    }
+}
+
+/// Collect the keys of a project file which are not options.
+fn unknown_keys(table: &toml::Table, warnings: &mut Vec<String>) {
+    for (key, value) in table {
+        let known: &[&str] = match key.as_str() {
+            "entry" | "font-paths" | "packages" => continue,
+            "export" => &["output"],
+            "watch" => &["ignore"],
+            _ => {
+                warnings.push(format!("ignored unknown key `{key}`"));
+                continue;
+            }
+        };
+
+        // A value of a wrong type fails the parsing instead.
+        let Some(section) = value.as_table() else {
+            continue;
+        };
+        for key2 in section.keys() {
+            if !known.contains(&key2.as_str()) {
+                warnings.push(format!("ignored unknown key `{key}.{key2}`"));
+            }
+        }
+    }
+}
+
+/// A project file along with the options of higher precedence, which is
+/// reloaded when the file changes, see
+/// [`super::CompileActor::with_project`].
+#[derive(Debug, Clone)]
+pub struct ProjectState {
+    /// The path of the project file, which may not exist yet.
+    pub path: ImmutPath,
+    /// The options layered over the project file, e.g. those of the builder.
+    pub overrides: ProjectConfig,
+    /// The merged options.
+    pub config: ProjectConfig,
+}
+
+impl ProjectState {
+    pub fn new(path: ImmutPath, overrides: ProjectConfig, file: Option<ProjectConfig>) -> Self {
+        let config = overrides.clone().merge_over(file.unwrap_or_default());
+        Self {
+            path,
+            overrides,
+            config,
+        }
+    }
+
+    /// Reload the options from the new content of the project file, or from
+    /// the defaults if the file is removed, returning the previous options.
+    ///
+    /// The options are kept if the content is invalid.
+    pub fn reload(&mut self, content: Option<&[u8]>) -> ZResult<ProjectConfig> {
+        let file = match content {
+            Some(content) => {
+                let content = std::str::from_utf8(content)
+                    .map_err(map_string_err("failed to read the project file"))?;
+                ProjectConfig::load_content(&self.path, content)?
+            }
+            None => ProjectConfig::default(),
+        };
+
+        let config = self.overrides.clone().merge_over(file);
+        Ok(std::mem::replace(&mut self.config, config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_config() {
+        let base = Path::new("/project");
+        let content = r#"
+entry = "main.typ"
+font-paths = ["fonts", "/usr/share/fonts"]
+colour = "red"
+
+[packages]
+"@preview/example:0.1.0" = "vendor/example"
+"not-a-spec" = "vendor/other"
+
+[export]
+output = "out"
+format = "pdf"
+
+[watch]
+ignore = ["out"]
+"#;
+        let (config, warnings) = ProjectConfig::parse(content, base).unwrap();
+        assert_eq!(
+            config.entry.as_deref(),
+            Some(Path::new("/project/main.typ"))
+        );
+        assert_eq!(
+            config.font_paths,
+            [
+                PathBuf::from("/project/fonts"),
+                PathBuf::from("/usr/share/fonts")
+            ]
+        );
+        assert_eq!(config.package_overrides().len(), 1);
+        assert!(config.is_watch_ignored(Path::new("/project/out/main.pdf")));
+        assert!(!config.is_watch_ignored(Path::new("/project/main.typ")));
+        assert_eq!(warnings.len(), 3, "{warnings:?}");
+        assert!(warnings[0].contains("`colour`"), "{warnings:?}");
+        assert!(warnings[1].contains("`export.format`"), "{warnings:?}");
+        assert!(warnings[2].contains("`not-a-spec`"), "{warnings:?}");
+
+        // A wrong type fails unlike an unknown key.
+        assert!(ProjectConfig::parse("font-paths = \"fonts\"", base).is_err());
+
+        // The higher layer wins, and its lists replace the lower ones.
+        let builder = ProjectConfig {
+            font_paths: vec!["/builder/fonts".into()],
+            ..ProjectConfig::default()
+        };
+        let merged = builder.merge_over(config.clone());
+        assert_eq!(merged.entry, config.entry);
+        assert_eq!(merged.font_paths, [PathBuf::from("/builder/fonts")]);
+        assert_eq!(merged.export.output, config.export.output);
+    }
+}
@@ -7,11 +7,43 @@ use typst::{
     syntax::Span,
     World,
 };
+use typst_ts_core::error::prelude::*;
+
+use super::cancel::CancellationToken;
 
 // todo: query exporter
 /// Retrieve the matches for the selector.
 pub fn retrieve(world: &dyn World, selector: &str, document: &Document) -> StrResult<Vec<Content>> {
-    let selector = eval_string(
+    let selector = eval_selector(world, selector)?;
+
+    Ok(document
+        .introspector
+        .query(&selector.0)
+        .into_iter()
+        .collect::<Vec<_>>())
+}
+
+/// Retrieve the matches like [`retrieve`], checking the token between the
+/// evaluation of the selector and the query, see [`super::cancel`].
+///
+/// The query itself cannot be interrupted, hence cancelling the request while
+/// querying only fails it once the query returns.
+pub fn retrieve_cancellable(
+    world: &dyn World,
+    selector: &str,
+    document: &Document,
+    token: &CancellationToken,
+) -> ZResult<Vec<Content>> {
+    token.check()?;
+    let selector = eval_selector(world, selector).map_err(map_string_err("invalid selector"))?;
+    token.check()?;
+    let matches = document.introspector.query(&selector.0);
+    token.check()?;
+    Ok(matches.into_iter().collect())
+}
+
+fn eval_selector(world: &dyn World, selector: &str) -> StrResult<LocatableSelector> {
+    eval_string(
         world.track(),
         selector,
         Span::detached(),
@@ -26,11 +58,5 @@ pub fn retrieve(world: &dyn World, selector: &str, document: &Document) -> StrRe
         }
         message
     })?
-    .cast::<LocatableSelector>()?;
-
-    Ok(document
-        .introspector
-        .query(&selector.0)
-        .into_iter()
-        .collect::<Vec<_>>())
+    .cast::<LocatableSelector>()
 }
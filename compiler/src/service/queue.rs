@@ -0,0 +1,177 @@
+//! Queue the tasks of clients by priority, e.g. to keep an editor responsive
+//! when it sends requests faster than the compiler thread runs them.
+//!
+//! Each task is tagged by a [`TaskCategory`] and an optional coalescing key.
+//! A task replaces the queued task of the same category and key, which is
+//! dropped without running, so that only the latest of repeated requests
+//! runs, e.g. a hover at the latest position of the cursor. The replacing
+//! task takes the place of the replaced one, so that repeated requests are
+//! not delayed forever by the others.
+//!
+//! The tasks are taken by their categories and then in the order of pushing.
+//! A push is rejected once the queue holds as many tasks as its depth. The
+//! tasks of cancelled requests are dropped from the queue, see
+//! [`super::cancel`].
+
+use std::fmt;
+
+use typst_ts_core::typst::prelude::*;
+
+/// The default number of tasks a [`TaskQueue`] holds.
+pub const DEFAULT_QUEUE_DEPTH: usize = 64;
+
+/// The category of a task, ordered from the lowest priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TaskCategory {
+    /// A task whose result is not awaited by a user, e.g. semantic tokens.
+    Background,
+    /// A task exporting the document.
+    Export,
+    /// A task answering a user interaction, e.g. a hover or a completion,
+    /// which runs before a pending compilation.
+    Interactive,
+}
+
+/// The tag of a task in a [`TaskQueue`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TaskTag {
+    pub category: TaskCategory,
+    /// The key coalescing the tasks of the same category, see the
+    /// [module docs](self).
+    pub key: Option<EcoString>,
+}
+
+impl TaskTag {
+    pub fn new(category: TaskCategory) -> Self {
+        Self {
+            category,
+            key: None,
+        }
+    }
+
+    pub fn with_key(mut self, key: impl Into<EcoString>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+}
+
+struct QueuedTask<T> {
+    tag: TaskTag,
+    /// The order of pushing.
+    seq: u64,
+    task: T,
+}
+
+/// A bounded queue of tagged tasks, see the [module docs](self).
+pub struct TaskQueue<T> {
+    depth: usize,
+    seq: u64,
+    tasks: Vec<QueuedTask<T>>,
+}
+
+impl<T> fmt::Debug for TaskQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskQueue")
+            .field("depth", &self.depth)
+            .field("len", &self.tasks.len())
+            .finish()
+    }
+}
+
+impl<T> Default for TaskQueue<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUEUE_DEPTH)
+    }
+}
+
+impl<T> TaskQueue<T> {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            seq: 0,
+            tasks: vec![],
+        }
+    }
+
+    /// Set the number of tasks the queue holds, which doesn't drop the tasks
+    /// already queued.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Push a task, returning the task replaced by it if any.
+    ///
+    /// The task is given back if the queue is full.
+    pub fn push(&mut self, tag: TaskTag, task: T) -> Result<Option<T>, T> {
+        if tag.key.is_some() {
+            if let Some(queued) = self.tasks.iter_mut().find(|queued| queued.tag == tag) {
+                return Ok(Some(std::mem::replace(&mut queued.task, task)));
+            }
+        }
+        if self.tasks.len() >= self.depth {
+            return Err(task);
+        }
+
+        self.seq += 1;
+        self.tasks.push(QueuedTask {
+            tag,
+            seq: self.seq,
+            task,
+        });
+        Ok(None)
+    }
+
+    /// Drop the tasks for which the predicate is false, e.g. the cancelled
+    /// ones.
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        self.tasks.retain(|queued| f(&queued.task));
+    }
+
+    /// Take the task of the highest category which is pushed first.
+    pub fn pop(&mut self) -> Option<(TaskTag, T)> {
+        let index = (self.tasks.iter().enumerate())
+            .max_by_key(|(_, queued)| (queued.tag.category, std::cmp::Reverse(queued.seq)))
+            .map(|(index, _)| index)?;
+        let queued = self.tasks.remove(index);
+        Some((queued.tag, queued.task))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_queue() {
+        let mut queue = TaskQueue::new(3);
+        let hover = TaskTag::new(TaskCategory::Interactive).with_key("hover");
+        let tokens = TaskTag::new(TaskCategory::Background);
+        assert!(matches!(queue.push(tokens.clone(), "tokens"), Ok(None)));
+        assert!(matches!(queue.push(hover.clone(), "hover 1"), Ok(None)));
+        assert!(matches!(
+            queue.push(hover.clone(), "hover 2"),
+            Ok(Some("hover 1"))
+        ));
+        assert!(matches!(queue.push(tokens.clone(), "tokens 2"), Ok(None)));
+        assert!(matches!(
+            queue.push(tokens.clone(), "tokens 3"),
+            Err("tokens 3")
+        ));
+        // A coalesced task is accepted by a full queue.
+        assert!(matches!(
+            queue.push(hover.clone(), "hover 3"),
+            Ok(Some("hover 2"))
+        ));
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop().map(|(_, task)| task)).collect();
+        assert_eq!(order, ["hover 3", "tokens", "tokens 2"]);
+    }
+}
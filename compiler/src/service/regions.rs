@@ -0,0 +1,201 @@
+//! Collect the regions of the text on the pages of compiled documents, e.g.
+//! for overlaying a rendered page with clickable and selectable rectangles.
+//!
+//! The rectangles are the boxes of the glyphs along their baselines, which are
+//! transformed into the page coordinates in points.
+
+use std::ops::Range;
+
+use typst::{
+    layout::{Abs, Frame, FrameItem, Point, Transform},
+    syntax::Span,
+    text::Glyph,
+};
+
+/// A clickable region of text on a page, see [`clickable_regions`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClickRegion {
+    /// The bounding rectangle `(x, y, width, height)` on the page in points.
+    pub rect: (f64, f64, f64, f64),
+    /// The raw span of the glyphs in the region, which can be converted back
+    /// by [`Span::from_raw`].
+    pub span_id: u64,
+}
+
+/// Collect the regions of consecutive glyphs sharing a span in a frame, e.g.
+/// for overlaying the page with clickable rectangles.
+///
+/// Glyphs without spans are not clickable, and are skipped.
+pub fn clickable_regions(frame: &Frame) -> Vec<ClickRegion> {
+    let mut regions = vec![];
+    collect_click_regions(frame, Transform::identity(), &mut regions);
+    regions
+}
+
+fn collect_click_regions(frame: &Frame, ts: Transform, regions: &mut Vec<ClickRegion>) {
+    for (pos, item) in frame.items() {
+        let ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+        match item {
+            FrameItem::Group(group) => {
+                collect_click_regions(&group.frame, ts.pre_concat(group.transform), regions);
+            }
+            FrameItem::Text(text) => {
+                let mut x = Abs::zero();
+                let mut run: Option<(Span, Abs, Abs)> = None;
+                for glyph in &text.glyphs {
+                    let width = glyph.x_advance.at(text.size);
+                    let span = glyph.span.0;
+                    let start = x;
+                    x += width;
+
+                    // Extend the run if the glyph shares the span.
+                    if let Some((run_span, _, end)) = &mut run {
+                        if *run_span == span {
+                            *end = x;
+                            continue;
+                        }
+                    }
+                    if let Some(run) = run.take() {
+                        push_click_region(regions, ts, text.size, run);
+                    }
+                    if !span.is_detached() {
+                        run = Some((span, start, x));
+                    }
+                }
+                if let Some(run) = run {
+                    push_click_region(regions, ts, text.size, run);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Push the bounding box of a run of glyphs from `start` to `end` along the
+/// baseline, which is transformed into the page coordinates.
+fn push_click_region(
+    regions: &mut Vec<ClickRegion>,
+    ts: Transform,
+    size: Abs,
+    (span, start, end): (Span, Abs, Abs),
+) {
+    regions.push(ClickRegion {
+        rect: baseline_rect(ts, size, start, end),
+        span_id: span.into_raw().get(),
+    });
+}
+
+/// Get the bounding box `(x, y, width, height)` of text from `start` to
+/// `end` along the baseline, which is transformed into the page coordinates.
+fn baseline_rect(ts: Transform, size: Abs, start: Abs, end: Abs) -> (f64, f64, f64, f64) {
+    let corners = [
+        Point::new(start, -size),
+        Point::new(end, -size),
+        Point::new(start, Abs::zero()),
+        Point::new(end, Abs::zero()),
+    ]
+    .map(|corner| corner.transform(ts));
+
+    let min_x = corners.iter().map(|p| p.x).fold(Abs::inf(), Abs::min);
+    let min_y = corners.iter().map(|p| p.y).fold(Abs::inf(), Abs::min);
+    let max_x = corners.iter().map(|p| p.x).fold(-Abs::inf(), Abs::max);
+    let max_y = corners.iter().map(|p| p.y).fold(-Abs::inf(), Abs::max);
+    (
+        min_x.to_pt(),
+        min_y.to_pt(),
+        (max_x - min_x).to_pt(),
+        (max_y - min_y).to_pt(),
+    )
+}
+
+/// A word on a page, see [`word_regions`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordRegion {
+    pub text: String,
+    /// The bounding rectangle `(x, y, width, height)` on the page in points.
+    pub rect: (f64, f64, f64, f64),
+    /// The raw span of the first attached glyph in the word, which can be
+    /// converted back by [`Span::from_raw`].
+    pub span_id: u64,
+    /// The byte offset of the word into the text of its span.
+    pub offset: usize,
+}
+
+/// Collect the words of the text in a frame, e.g. for selecting a word by
+/// double-clicking on the page.
+///
+/// Text is split at whitespace between glyphs. A glyph is never split, so
+/// that ligatures and characters with combining marks stay in one word.
+pub fn word_regions(frame: &Frame) -> Vec<WordRegion> {
+    let mut words = vec![];
+    collect_word_regions(frame, Transform::identity(), &mut words);
+    words
+}
+
+fn collect_word_regions(frame: &Frame, ts: Transform, words: &mut Vec<WordRegion>) {
+    for (pos, item) in frame.items() {
+        let ts = ts.pre_concat(Transform::translate(pos.x, pos.y));
+        match item {
+            FrameItem::Group(group) => {
+                collect_word_regions(&group.frame, ts.pre_concat(group.transform), words);
+            }
+            FrameItem::Text(text) => {
+                let mut x = Abs::zero();
+                // The glyphs of the current word and their extent.
+                let mut word: Option<(Range<usize>, Abs, Abs)> = None;
+                let mut glyphs: Vec<&Glyph> = vec![];
+                for glyph in &text.glyphs {
+                    let width = glyph.x_advance.at(text.size);
+                    let start = x;
+                    x += width;
+
+                    let range = glyph.range();
+                    let is_space = text.text[range.clone()].chars().all(char::is_whitespace);
+                    if is_space {
+                        if let Some(word) = word.take() {
+                            push_word_region(words, ts, &text.text, text.size, word, &glyphs);
+                        }
+                        glyphs.clear();
+                        continue;
+                    }
+
+                    // Glyphs are in visual order, which reverses the text in
+                    // right-to-left scripts.
+                    match &mut word {
+                        Some((text_range, _, end)) => {
+                            text_range.start = text_range.start.min(range.start);
+                            text_range.end = text_range.end.max(range.end);
+                            *end = x;
+                        }
+                        None => word = Some((range, start, x)),
+                    }
+                    glyphs.push(glyph);
+                }
+                if let Some(word) = word {
+                    push_word_region(words, ts, &text.text, text.size, word, &glyphs);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn push_word_region(
+    words: &mut Vec<WordRegion>,
+    ts: Transform,
+    text: &str,
+    size: Abs,
+    (range, start, end): (Range<usize>, Abs, Abs),
+    glyphs: &[&Glyph],
+) {
+    let (span, offset) = (glyphs.iter())
+        .map(|glyph| glyph.span)
+        .find(|(span, _)| !span.is_detached())
+        .unwrap_or((Span::detached(), 0));
+    words.push(WordRegion {
+        text: text[range].to_owned(),
+        rect: baseline_rect(ts, size, start, end),
+        span_id: span.into_raw().get(),
+        offset: offset as usize,
+    });
+}
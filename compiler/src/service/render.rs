@@ -0,0 +1,639 @@
+//! Rasterize pages of compiled documents off the compiler thread.
+//!
+//! A compiled document is an immutable snapshot, which is shared with the
+//! workers of a [`RenderService`] by an [`Arc`]. The compiler thread only
+//! enqueues jobs, e.g. from the export hook by [`PreviewExporter`], so that
+//! rasterization doesn't delay the next compilation.
+//!
+//! The queue is bounded for previews, where the oldest preview is dropped for
+//! a newer one. Exports must complete instead, hence enqueuing an export waits
+//! until the queue has space for it.
+//!
+//! Thumbnails of many pages are rendered in a batch by [`render_thumbnails`]
+//! instead, which shares the document and its fonts across the pages.
+//!
+//! Zoomable previews render a page as tiles by [`render_tile`], each of which
+//! only covers a region of the page, hence a page at a high zoom is never
+//! rasterized as a whole.
+
+use std::{collections::VecDeque, future::Future, sync::Arc, thread::JoinHandle};
+
+use parking_lot::{Condvar, Mutex};
+use tiny_skia::Pixmap;
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
+use typst::{
+    diag::SourceResult,
+    layout::{Abs, Frame, Point, Size},
+    visualize::Color,
+    World,
+};
+use typst_ts_core::{error::prelude::*, Exporter, TypstDocument};
+
+use super::cancel::CancellationToken;
+
+/// How a render job is queued when the queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderPolicy {
+    /// Drop the oldest queued preview, e.g. for thumbnails which are outdated
+    /// by the next compilation anyway.
+    #[default]
+    Preview,
+    /// Wait until the queue has space, so that the job always completes.
+    Export,
+}
+
+/// Options of a render job, see [`RenderService::render`].
+#[derive(Debug, Clone, Copy)]
+pub struct RenderOptions {
+    /// The number of pixels per point.
+    pub pixel_per_pt: f32,
+    /// The background color of the page.
+    pub fill: Color,
+    pub policy: RenderPolicy,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            pixel_per_pt: 1.,
+            fill: Color::WHITE,
+            policy: RenderPolicy::default(),
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Options of a preview job with the given resolution.
+    pub fn preview(pixel_per_pt: f32) -> Self {
+        Self {
+            pixel_per_pt,
+            ..Self::default()
+        }
+    }
+
+    /// Options of an export job with the given resolution.
+    pub fn export(pixel_per_pt: f32) -> Self {
+        Self {
+            pixel_per_pt,
+            policy: RenderPolicy::Export,
+            ..Self::default()
+        }
+    }
+}
+
+type RenderCallback = Box<dyn FnOnce(ZResult<Pixmap>) + Send>;
+
+struct RenderJob {
+    snapshot: Arc<TypstDocument>,
+    /// The 1-based page number.
+    page: usize,
+    options: RenderOptions,
+    /// The slot of an export job in the queue, released on completion.
+    slot: Option<OwnedSemaphorePermit>,
+    done: RenderCallback,
+}
+
+#[derive(Default)]
+struct RenderQueue {
+    exports: VecDeque<RenderJob>,
+    previews: VecDeque<RenderJob>,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<RenderQueue>,
+    ready: Condvar,
+}
+
+struct Workers {
+    shared: Arc<Shared>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        self.shared.queue.lock().closed = true;
+        self.shared.ready.notify_all();
+        let current = std::thread::current().id();
+        for handle in self.handles.drain(..) {
+            // The last service may be dropped by a callback on a worker.
+            if handle.thread().id() == current {
+                continue;
+            }
+            if handle.join().is_err() {
+                log::error!("RenderService: worker thread panicked");
+            }
+        }
+    }
+}
+
+/// A pool of workers rasterizing pages of compiled documents.
+///
+/// The service is cheap to clone, and the workers exit when the last clone is
+/// dropped. The pending jobs are cancelled then.
+#[derive(Clone)]
+pub struct RenderService {
+    workers: Arc<Workers>,
+    capacity: usize,
+    export_slots: Arc<Semaphore>,
+}
+
+impl RenderService {
+    /// Spawn `workers` threads, queueing at most `capacity` previews and
+    /// `capacity` exports.
+    pub fn new(workers: usize, capacity: usize) -> ZResult<Self> {
+        let shared = Arc::new(Shared::default());
+        let capacity = capacity.max(1);
+
+        let mut handles = vec![];
+        for idx in 0..workers.max(1) {
+            let shared = shared.clone();
+            let handle = std::thread::Builder::new()
+                .name(format!("typst-render-{idx}"))
+                .spawn(move || work(&shared))
+                .map_err(map_string_err("failed to spawn render worker"))?;
+            handles.push(handle);
+        }
+
+        Ok(Self {
+            workers: Arc::new(Workers { shared, handles }),
+            capacity,
+            export_slots: Arc::new(Semaphore::new(capacity)),
+        })
+    }
+
+    /// Rasterize a page of a compiled document, where the page number is
+    /// 1-based.
+    ///
+    /// A preview is resolved with an error if it is dropped for a newer one.
+    pub fn render(
+        &self,
+        snapshot: Arc<TypstDocument>,
+        page: usize,
+        options: RenderOptions,
+    ) -> impl Future<Output = ZResult<Pixmap>> + Send + 'static {
+        let this = self.clone();
+        async move {
+            let slot = match options.policy {
+                RenderPolicy::Preview => None,
+                RenderPolicy::Export => Some(
+                    (this.export_slots.clone().acquire_owned().await)
+                        .map_err(map_string_err("render service is closed"))?,
+                ),
+            };
+
+            let (tx, rx) = oneshot::channel();
+            this.push(RenderJob {
+                snapshot,
+                page,
+                options,
+                slot,
+                done: Box::new(move |res| {
+                    let _ = tx.send(res);
+                }),
+            });
+            rx.await
+                .map_err(|_| error_once!("render job is cancelled"))?
+        }
+    }
+
+    /// Enqueue a preview without waiting for it, calling `done` with the
+    /// result on the worker thread.
+    ///
+    /// It never blocks, hence it is suitable for the compiler thread.
+    pub fn enqueue_preview(
+        &self,
+        snapshot: Arc<TypstDocument>,
+        page: usize,
+        options: RenderOptions,
+        done: impl FnOnce(ZResult<Pixmap>) + Send + 'static,
+    ) {
+        self.push(RenderJob {
+            snapshot,
+            page,
+            options: RenderOptions {
+                policy: RenderPolicy::Preview,
+                ..options
+            },
+            slot: None,
+            done: Box::new(done),
+        });
+    }
+
+    fn push(&self, job: RenderJob) {
+        let shared = &self.workers.shared;
+        let dropped = {
+            let mut queue = shared.queue.lock();
+            if job.slot.is_some() {
+                queue.exports.push_back(job);
+                None
+            } else {
+                queue.previews.push_back(job);
+                (queue.previews.len() > self.capacity)
+                    .then(|| queue.previews.pop_front())
+                    .flatten()
+            }
+        };
+        shared.ready.notify_one();
+
+        if let Some(job) = dropped {
+            (job.done)(Err(error_once!("preview is dropped for a newer one")));
+        }
+    }
+}
+
+fn work(shared: &Shared) {
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock();
+            loop {
+                if let Some(job) =
+                    (queue.exports.pop_front()).or_else(|| queue.previews.pop_front())
+                {
+                    break job;
+                }
+                if queue.closed {
+                    return;
+                }
+                shared.ready.wait(&mut queue);
+            }
+        };
+
+        let res = rasterize(&job.snapshot, job.page, &job.options);
+        drop(job.slot);
+        (job.done)(res);
+    }
+}
+
+fn rasterize(doc: &TypstDocument, page: usize, options: &RenderOptions) -> ZResult<Pixmap> {
+    let frame = page_frame(doc, page)?;
+    Ok(typst_render::render(
+        frame,
+        options.pixel_per_pt,
+        options.fill,
+    ))
+}
+
+/// Get the frame of a page, where the page number is 1-based.
+fn page_frame(doc: &TypstDocument, page: usize) -> ZResult<&Frame> {
+    page.checked_sub(1)
+        .and_then(|idx| doc.pages.get(idx))
+        .map(|page| &page.frame)
+        .ok_or_else(|| error_once!("page not found", page: page))
+}
+
+/// Rasterize a region of a page, where the page number is 1-based and the
+/// region is `(x, y, width, height)` in pt, clamped to the page.
+///
+/// The tile is `width * pixel_per_pt` by `height * pixel_per_pt` pixels, where
+/// the region is filled by the color outside of the items.
+pub fn render_tile(
+    doc: &TypstDocument,
+    page: usize,
+    tile_rect: (f64, f64, f64, f64),
+    pixel_per_pt: f32,
+    fill: Color,
+) -> ZResult<Pixmap> {
+    let frame = page_frame(doc, page)?;
+    let (x, y, width, height) = tile_rect;
+    let clamp = |p: f64, max: Abs| Abs::pt(p).max(Abs::zero()).min(max);
+    let min = Point::new(clamp(x, frame.width()), clamp(y, frame.height()));
+    let max = Point::new(
+        clamp(x + width, frame.width()),
+        clamp(y + height, frame.height()),
+    );
+    let size = Size::new(max.x - min.x, max.y - min.y);
+    if size.x <= Abs::zero() || size.y <= Abs::zero() {
+        return Err(error_once!("tile is outside of the page", page: page));
+    }
+
+    // Shift the page so that the region is at the origin of the tile, where
+    // the rest of the page falls outside of the pixels.
+    let mut tile = Frame::hard(size);
+    tile.push_frame(Point::new(-min.x, -min.y), frame.clone());
+    Ok(typst_render::render(&tile, pixel_per_pt, fill))
+}
+
+/// The pixel format of a [`Thumbnail`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThumbnailFormat {
+    /// An encoded PNG image.
+    #[default]
+    Png,
+    /// The raw pixels in premultiplied RGBA, row by row.
+    Rgba,
+}
+
+/// Options of [`render_thumbnails`].
+#[derive(Debug, Clone)]
+pub struct ThumbnailOptions {
+    /// The maximum of the width and the height of a thumbnail in pixels.
+    pub max_dim: u32,
+    /// The 1-based page numbers to render, or all the pages if `None`.
+    pub pages: Option<Vec<usize>>,
+    pub format: ThumbnailFormat,
+    /// The background color of the pages.
+    pub fill: Color,
+    /// Whether to render the pages on multiple threads.
+    pub parallel: bool,
+}
+
+impl Default for ThumbnailOptions {
+    fn default() -> Self {
+        Self {
+            max_dim: 128,
+            pages: None,
+            format: ThumbnailFormat::default(),
+            fill: Color::WHITE,
+            parallel: true,
+        }
+    }
+}
+
+/// A thumbnail of a page, see [`render_thumbnails`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    /// The 1-based page number.
+    pub page: usize,
+    pub width: u32,
+    pub height: u32,
+    /// The image in the [`ThumbnailOptions::format`].
+    pub data: Vec<u8>,
+}
+
+/// Render thumbnails of the pages of a document in the order of the page
+/// numbers requested.
+///
+/// Each page is rasterized right at the resolution fitting its longer side
+/// into `max_dim`, rather than downsampling a full rendering, hence the aspect
+/// ratio of each page is preserved for documents of mixed page sizes.
+pub fn render_thumbnails(
+    doc: &TypstDocument,
+    options: &ThumbnailOptions,
+) -> ZResult<Vec<Thumbnail>> {
+    render_thumbnails_cancellable(doc, options, &CancellationToken::new())
+}
+
+/// Render thumbnails like [`render_thumbnails`], stopping before the next
+/// page once the token is cancelled, see [`super::cancel`].
+pub fn render_thumbnails_cancellable(
+    doc: &TypstDocument,
+    options: &ThumbnailOptions,
+    token: &CancellationToken,
+) -> ZResult<Vec<Thumbnail>> {
+    let render = |page: usize| {
+        token.check()?;
+        render_thumbnail(doc, page, options)
+    };
+    let pages = match &options.pages {
+        Some(pages) => pages.clone(),
+        None => (1..=doc.pages.len()).collect(),
+    };
+    let threads = match options.parallel {
+        true => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        false => 1,
+    };
+    if threads <= 1 || pages.len() <= 1 {
+        return (pages.iter()).map(|&page| render(page)).collect();
+    }
+
+    let chunk = pages.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (pages.chunks(chunk))
+            .map(|pages| {
+                scope.spawn(move || {
+                    (pages.iter())
+                        .map(|&page| render(page))
+                        .collect::<ZResult<Vec<_>>>()
+                })
+            })
+            .collect();
+
+        let mut thumbnails = Vec::with_capacity(pages.len());
+        for worker in workers {
+            let rendered =
+                (worker.join()).map_err(|_| error_once!("thumbnail worker panicked"))??;
+            thumbnails.extend(rendered);
+        }
+        Ok(thumbnails)
+    })
+}
+
+fn render_thumbnail(
+    doc: &TypstDocument,
+    page: usize,
+    options: &ThumbnailOptions,
+) -> ZResult<Thumbnail> {
+    let frame = page_frame(doc, page)?;
+    let longer = frame.width().to_pt().max(frame.height().to_pt());
+    let pixel_per_pt = match longer {
+        longer if longer > 0. => options.max_dim.max(1) as f64 / longer,
+        _ => 1.,
+    };
+
+    let pixmap = typst_render::render(frame, pixel_per_pt as f32, options.fill);
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let data = match options.format {
+        ThumbnailFormat::Png => pixmap
+            .encode_png()
+            .map_err(map_string_err("failed to encode thumbnail"))?,
+        ThumbnailFormat::Rgba => pixmap.take(),
+    };
+    Ok(Thumbnail {
+        page,
+        width,
+        height,
+        data,
+    })
+}
+
+/// Enqueue a preview of a page for each compiled document, see
+/// [`RenderService::enqueue_preview`].
+pub struct PreviewExporter {
+    service: RenderService,
+    page: usize,
+    options: RenderOptions,
+    on_render: Arc<dyn Fn(ZResult<Pixmap>) + Send + Sync>,
+}
+
+impl PreviewExporter {
+    pub fn new(
+        service: RenderService,
+        page: usize,
+        options: RenderOptions,
+        on_render: impl Fn(ZResult<Pixmap>) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            service,
+            page,
+            options,
+            on_render: Arc::new(on_render),
+        }
+    }
+}
+
+impl Exporter<TypstDocument> for PreviewExporter {
+    fn export(&self, _world: &dyn World, output: Arc<TypstDocument>) -> SourceResult<()> {
+        let on_render = self.on_render.clone();
+        (self.service).enqueue_preview(output, self.page, self.options, move |res| on_render(res));
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use typst::foundations::Bytes;
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileEnv, Compiler},
+    };
+
+    #[cfg(feature = "system-watch")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_render_under_compile_load() {
+        use typst::diag::FileResult;
+
+        use crate::{
+            service::CompileActor,
+            vfs::notify::{FileChangeSet, FileSnapshot, MemoryEvent},
+        };
+
+        let ws = TestWorkspace::new();
+        let main = ws.path("main.typ");
+        let driver = ws.driver();
+        let (actor, client) = CompileActor::new(driver).with_watch(true).split();
+        actor.spawn().await.unwrap();
+        let client = client.into_async();
+
+        // Edit the main file like an editor does, and wait for the actor to
+        // compile it.
+        let compile = |revision: usize| {
+            let content =
+                format!("#set page(width: 200pt, height: 200pt)\n= Rev {revision}\n#lorem(40)");
+            let snapshot: FileSnapshot =
+                FileResult::Ok((crate::time::now(), Bytes::from(content.into_bytes()))).into();
+            let changes = FileChangeSet::new_inserts(vec![(main.as_path().into(), snapshot)]);
+            let start = Instant::now();
+            client
+                .add_memory_changes(MemoryEvent::Update(changes))
+                .unwrap();
+            let compiled = client.compile();
+            async move {
+                let doc = compiled.await.unwrap().expect("compiled document");
+                (doc, start.elapsed())
+            }
+        };
+
+        let service = RenderService::new(2, 4).unwrap();
+        let (doc, _) = compile(0).await;
+        let pixmap = service
+            .render(doc.clone(), 1, RenderOptions::export(2.))
+            .await;
+        assert_eq!(pixmap.unwrap().width(), 400);
+        let missing = service
+            .render(doc.clone(), 2, RenderOptions::export(1.))
+            .await;
+        assert!(missing.is_err());
+
+        // Hammer the service with previews while compiling in a loop.
+        const PREVIEWS: usize = 200;
+        let rendered = Arc::new(AtomicUsize::new(0));
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let hammer = {
+            let (service, doc) = (service.clone(), doc.clone());
+            let (rendered, dropped) = (rendered.clone(), dropped.clone());
+            std::thread::spawn(move || {
+                for _ in 0..PREVIEWS {
+                    let (rendered, dropped) = (rendered.clone(), dropped.clone());
+                    let options = RenderOptions::preview(3.);
+                    service.enqueue_preview(doc.clone(), 1, options, move |res| {
+                        let counter = if res.is_ok() { &rendered } else { &dropped };
+                        counter.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            })
+        };
+
+        for revision in 1..=10 {
+            let (doc, elapsed) = compile(revision).await;
+            assert!(elapsed < Duration::from_secs(2), "compile took {elapsed:?}");
+            // The exports complete even if the queue is full of previews.
+            let pixmap = service.render(doc, 1, RenderOptions::export(1.)).await;
+            assert_eq!(pixmap.unwrap().height(), 200);
+        }
+        hammer.join().unwrap();
+
+        // Wait for the remaining previews.
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while rendered.load(Ordering::SeqCst) + dropped.load(Ordering::SeqCst) < PREVIEWS {
+            assert!(Instant::now() < deadline, "previews are not finished");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(dropped.load(Ordering::SeqCst) > 0);
+        assert!(rendered.load(Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn test_thumbnails_of_mixed_pages() {
+        let ws = TestWorkspace::new();
+        let content = "#page(width: 300pt, height: 200pt)[Landscape]\n#page(width: 200pt, height: 300pt)[Portrait]";
+        let mut driver = ws.shadow_driver(content.as_bytes());
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+        let options = ThumbnailOptions {
+            max_dim: 60,
+            ..ThumbnailOptions::default()
+        };
+        let thumbnails = render_thumbnails(&doc, &options).unwrap();
+        let dims: Vec<_> = (thumbnails.iter())
+            .map(|t| (t.page, t.width, t.height))
+            .collect();
+        assert_eq!(dims, [(1, 60, 40), (2, 40, 60)]);
+        assert!(thumbnails[0].data.starts_with(b"\x89PNG"));
+
+        let options = ThumbnailOptions {
+            max_dim: 60,
+            pages: Some(vec![2]),
+            format: ThumbnailFormat::Rgba,
+            parallel: false,
+            ..ThumbnailOptions::default()
+        };
+        let thumbnails = render_thumbnails(&doc, &options).unwrap();
+        assert_eq!(thumbnails.len(), 1);
+        assert_eq!((thumbnails[0].width, thumbnails[0].height), (40, 60));
+        assert_eq!(thumbnails[0].data.len(), 40 * 60 * 4);
+
+        let missing = ThumbnailOptions {
+            pages: Some(vec![3]),
+            ..ThumbnailOptions::default()
+        };
+        assert!(render_thumbnails(&doc, &missing).is_err());
+    }
+
+    #[test]
+    fn test_render_tile() {
+        let ws = TestWorkspace::new();
+        let content = "#set page(width: 200pt, height: 100pt, fill: black)\nTile";
+        let mut driver = ws.shadow_driver(content.as_bytes());
+        let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+
+        let tile = render_tile(&doc, 1, (50., 20., 40., 30.), 4., Color::WHITE).unwrap();
+        assert_eq!((tile.width(), tile.height()), (160, 120));
+        // The tile is covered by the fill of the page.
+        assert!(tile.pixels().iter().all(|pixel| pixel.red() == 0));
+
+        // The region is clamped to the page.
+        let tile = render_tile(&doc, 1, (150., -10., 100., 30.), 2., Color::WHITE).unwrap();
+        assert_eq!((tile.width(), tile.height()), (100, 40));
+        assert!(render_tile(&doc, 1, (250., 0., 10., 10.), 1., Color::WHITE).is_err());
+        assert!(render_tile(&doc, 2, (0., 0., 10., 10.), 1., Color::WHITE).is_err());
+    }
+}
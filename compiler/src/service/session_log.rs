@@ -0,0 +1,405 @@
+//! Record what a compile actor sees during a watch session, e.g. to debug a
+//! report that the actor stopped compiling after an hour.
+//!
+//! A [`SessionLog`] set by `CompileActor::with_session_log` records
+//! - every interrupt of the actor, i.e. the tasks, the memory events, the file
+//!   system events and the probes of the root, along with the paths of the
+//!   changed files, the request and the logical tick,
+//! - every compilation, along with the revision, the duration and the numbers
+//!   of the errors and the warnings,
+//! - every message sent to the file watcher, and
+//! - every failure to reach the file watcher.
+//!
+//! The records are appended to a file as JSON lines, which is rotated once it
+//! exceeds a size, or handed to a callback. The contents of the files are
+//! never recorded. The paths in the root of the workspace are recorded
+//! relative to it, and may be anonymized by their stable hashes.
+//!
+//! A recorded session is replayed by `CompileActor::replay` over a workspace,
+//! which reproduces the transitions of the actor deterministically.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use typst_ts_core::ImmutPath;
+
+use crate::vfs::notify::{FileChangeSet, NotifyMessage};
+
+/// The kind of an interrupt of the actor, see [`SessionEvent::Interrupt`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InterruptKind {
+    /// A task stealing the compiler thread.
+    Task,
+    /// A wakeup for the tagged tasks.
+    Queued,
+    /// A memory event resetting the shadow files.
+    MemorySync,
+    /// A memory event updating the shadow files.
+    MemoryUpdate,
+    /// A memory event editing a shadow file in place.
+    MemoryEdit,
+    /// A file system event of the watcher.
+    Fs,
+    /// A file system event of an upstream invalidation.
+    FsUpstream,
+    /// The end of the initial scan of the watcher.
+    ScanComplete,
+    /// A probe of the unavailable root.
+    ProbeRoot,
+}
+
+/// The kind of a message to the file watcher, see [`SessionEvent::Notify`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NotifyKind {
+    Settle,
+    SyncDependency,
+    UpdateDependency,
+    UpstreamUpdate,
+}
+
+/// An event of a session, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum SessionEvent {
+    Interrupt {
+        /// The id of the request handling the interrupt, whose interrupts are
+        /// handled together before compiling.
+        request: u64,
+        /// The logical tick of the actor.
+        tick: usize,
+        kind: InterruptKind,
+        /// The inserted or updated files.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        inserts: Vec<String>,
+        /// The removed files.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        removes: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Compile {
+        revision: usize,
+        duration_ms: u64,
+        ok: bool,
+        errors: usize,
+        warnings: usize,
+    },
+    Notify {
+        kind: NotifyKind,
+        /// The paths of the message, i.e. the added ones of an update of the
+        /// dependencies.
+        paths: Vec<String>,
+        /// The paths removed by an update of the dependencies.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        removes: Vec<String>,
+    },
+    WatcherError {
+        message: String,
+    },
+}
+
+/// An event along with the time since the start of the session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRecord {
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub event: SessionEvent,
+}
+
+/// The callback receiving the records, see [`SessionLog::to_callback`].
+type SessionCallback = Box<dyn FnMut(&SessionRecord) + Send>;
+
+enum SessionSink {
+    File(RotatingFile),
+    Callback(SessionCallback),
+}
+
+/// The log of a session, see the [module docs](self).
+pub struct SessionLog {
+    sink: Mutex<SessionSink>,
+    root: Option<ImmutPath>,
+    anonymize: bool,
+    start: Instant,
+}
+
+impl fmt::Debug for SessionLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionLog")
+            .field("root", &self.root)
+            .field("anonymize", &self.anonymize)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SessionLog {
+    /// Append the records to a file, which is rotated once it exceeds
+    /// `max_bytes`, keeping the latest `max_files` rotated files, e.g.
+    /// `session.jsonl.1` as the latest one.
+    pub fn to_file(path: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let file = RotatingFile::open(path.into(), max_bytes, max_files)?;
+        Ok(Self::new(SessionSink::File(file)))
+    }
+
+    /// Hand the records to a callback.
+    pub fn to_callback(callback: impl FnMut(&SessionRecord) + Send + 'static) -> Self {
+        Self::new(SessionSink::Callback(Box::new(callback)))
+    }
+
+    fn new(sink: SessionSink) -> Self {
+        Self {
+            sink: Mutex::new(sink),
+            root: None,
+            anonymize: false,
+            start: Instant::now(),
+        }
+    }
+
+    /// Record the paths in the root relative to it.
+    pub fn with_root(mut self, root: ImmutPath) -> Self {
+        self.root = Some(root);
+        self
+    }
+
+    /// Record the paths by their stable hashes, keeping their extensions,
+    /// e.g. `3f0c...9a1b.typ`.
+    pub fn with_anonymized_paths(mut self, enabled: bool) -> Self {
+        self.anonymize = enabled;
+        self
+    }
+
+    /// Read the records of a log file.
+    pub fn read(path: &Path) -> io::Result<Vec<SessionRecord>> {
+        let mut records = vec![];
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            records.push(serde_json::from_str(&line)?);
+        }
+        Ok(records)
+    }
+
+    pub fn record(&self, event: SessionEvent) {
+        let record = SessionRecord {
+            at_ms: self.start.elapsed().as_millis() as u64,
+            event,
+        };
+        match &mut *self.sink.lock() {
+            SessionSink::File(file) => {
+                if let Err(err) = file.append(&record) {
+                    log::warn!("SessionLog: failed to append a record: {err}");
+                }
+            }
+            SessionSink::Callback(callback) => callback(&record),
+        }
+    }
+
+    /// Record a path as configured, see [`Self::with_root`] and
+    /// [`Self::with_anonymized_paths`].
+    pub fn path(&self, path: &Path) -> String {
+        let relative = (self.root.as_deref()).and_then(|root| path.strip_prefix(root).ok());
+        let path = relative.unwrap_or(path);
+        let text = path.to_string_lossy().replace('\\', "/");
+        if !self.anonymize {
+            return text;
+        }
+
+        let hash = typst::util::hash128(&text);
+        match path.extension() {
+            Some(ext) => format!("{hash:032x}.{}", ext.to_string_lossy()),
+            None => format!("{hash:032x}"),
+        }
+    }
+
+    /// Record the paths of the inserted and the removed files.
+    pub(crate) fn changes(&self, changeset: &FileChangeSet) -> (Vec<String>, Vec<String>) {
+        let inserts = (changeset.inserts.iter()).map(|(path, _)| self.path(path));
+        let removes = changeset.removes.iter().map(|path| self.path(path));
+        (inserts.collect(), removes.collect())
+    }
+
+    /// Record a message to the file watcher.
+    pub(crate) fn notify(&self, msg: &NotifyMessage) {
+        let (kind, paths, removes) = match msg {
+            NotifyMessage::Settle => (NotifyKind::Settle, &[][..], &[][..]),
+            NotifyMessage::SyncDependency(deps) => (NotifyKind::SyncDependency, &deps[..], &[][..]),
+            NotifyMessage::UpdateDependency { inserts, removes } => {
+                (NotifyKind::UpdateDependency, &inserts[..], &removes[..])
+            }
+            NotifyMessage::UpstreamUpdate(event) => {
+                (NotifyKind::UpstreamUpdate, &event.invalidates[..], &[][..])
+            }
+        };
+        let paths = paths.iter().map(|path| self.path(path)).collect();
+        let removes = removes.iter().map(|path| self.path(path)).collect();
+        self.record(SessionEvent::Notify {
+            kind,
+            paths,
+            removes,
+        });
+    }
+}
+
+/// A log file rotated by its size.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            len,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    fn append(&mut self, record: &SessionRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if self.len > 0 && self.len + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(&line)?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    /// Rotate the file, where `{path}.{n}` is the n-th latest rotated file.
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files > 0 {
+            let oldest = rotated_path(&self.path, self.max_files);
+            remove_if_exists(&oldest)?;
+            for n in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, n);
+                if from.exists() {
+                    std::fs::rename(&from, rotated_path(&self.path, n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        } else {
+            remove_if_exists(&self.path)?;
+        }
+
+        self.file = File::create(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{n}"));
+    path.with_file_name(name)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(revision: usize) -> SessionEvent {
+        SessionEvent::Compile {
+            revision,
+            duration_ms: 1,
+            ok: true,
+            errors: 0,
+            warnings: 0,
+        }
+    }
+
+    #[test]
+    fn test_rotate_session_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let path = dir.join("session.jsonl");
+
+        // Each record exceeds the size, hence it is rotated on every record.
+        let log = SessionLog::to_file(&path, 10, 2).unwrap();
+        for revision in 1..=4 {
+            log.record(compile(revision));
+        }
+        let revisions = |path: &Path| {
+            let records = SessionLog::read(path).unwrap();
+            (records.into_iter())
+                .map(|record| match record.event {
+                    SessionEvent::Compile { revision, .. } => revision,
+                    event => panic!("unexpected event {event:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(revisions(&path), [4]);
+        assert_eq!(revisions(&dir.join("session.jsonl.1")), [3]);
+        assert_eq!(revisions(&dir.join("session.jsonl.2")), [2]);
+        assert!(!dir.join("session.jsonl.3").exists());
+    }
+
+    #[test]
+    fn test_session_log_paths() {
+        let root: ImmutPath = Path::new("/workspace").into();
+        let log = SessionLog::to_callback(|_| {}).with_root(root.clone());
+        assert_eq!(
+            log.path(Path::new("/workspace/chapters/a.typ")),
+            "chapters/a.typ"
+        );
+        assert_eq!(log.path(Path::new("/fonts/a.ttf")), "/fonts/a.ttf");
+
+        // The anonymized paths are stable.
+        let log = SessionLog::to_callback(|_| {})
+            .with_root(root)
+            .with_anonymized_paths(true);
+        let path = log.path(Path::new("/workspace/chapters/a.typ"));
+        assert_eq!(path, log.path(Path::new("/workspace/chapters/a.typ")));
+        assert!(
+            path.ends_with(".typ") && !path.contains("chapters"),
+            "{path}"
+        );
+    }
+
+    #[test]
+    fn test_session_record_json() {
+        let record = SessionRecord {
+            at_ms: 5,
+            event: SessionEvent::Interrupt {
+                request: 1,
+                tick: 2,
+                kind: InterruptKind::MemoryUpdate,
+                inserts: vec!["main.typ".into()],
+                removes: vec![],
+            },
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            json,
+            r#"{"atMs":5,"event":"interrupt","request":1,"tick":2,"kind":"memoryUpdate","inserts":["main.typ"]}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<SessionRecord>(&json).unwrap(),
+            record
+        );
+    }
+}
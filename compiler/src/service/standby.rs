@@ -0,0 +1,251 @@
+//! Rebuild the world on a standby thread, so that a change of the font paths,
+//! the workspace root or the library hook doesn't stall the compiler thread
+//! while the fonts are searched and the library is built.
+//!
+//! A [`WorldChange`] requested by `CompileClient::rebuild_world` is built off
+//! the compiler thread, see [`build`], while the old world keeps serving the
+//! requests, e.g. hovers. Once the build completes, the built parts are
+//! swapped into the world at once by a task on the compiler thread, which
+//! compiles again.
+//!
+//! A change of the root builds the state depending on it ahead, see
+//! [`BuiltRoot`], i.e. the entry, the sources under the root, which are
+//! restored to the cache of the vfs instead of being read by the next
+//! compilation, and the project file at the root, whose packages are resolved
+//! by the registry.
+//!
+//! The changes requested while a build is in flight are amended to it: the
+//! build is restarted with the amended change once it completes, instead of
+//! swapping the world once per change.
+
+use std::{path::PathBuf, sync::Arc};
+
+use comemo::Prehashed;
+use typst::foundations::Dict;
+use typst_ts_core::{
+    config::compiler::EntryState, error::prelude::*, font::FontResolver, path::PathClean, Bytes,
+    ImmutPath, TypstFileId,
+};
+
+use super::{library::LibraryHook, project::ProjectConfig};
+use crate::{
+    font::system::LazyFontResolver,
+    vfs::{system::SystemAccessModel, AccessModel},
+    world::create_library,
+    Time,
+};
+
+/// The number of sources under a new root read ahead at most, see
+/// [`BuiltRoot::contents`].
+const READ_AHEAD_LIMIT: usize = 1024;
+
+/// A change rebuilding the world, see the [module docs](self).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorldChange {
+    /// The font paths to search the fonts in besides the embedded and system
+    /// fonts.
+    pub font_paths: Option<Vec<PathBuf>>,
+    /// The root to move the workspace to, see `CompileActor::set_root`.
+    pub root: Option<ImmutPath>,
+    /// The hook customizing the library, or `Some(None)` to remove it.
+    pub library_hook: Option<Option<LibraryHook>>,
+}
+
+impl WorldChange {
+    pub fn with_font_paths(mut self, font_paths: Vec<PathBuf>) -> Self {
+        self.font_paths = Some(font_paths);
+        self
+    }
+
+    pub fn with_root(mut self, root: ImmutPath) -> Self {
+        self.root = Some(root);
+        self
+    }
+
+    pub fn with_library_hook(mut self, hook: Option<LibraryHook>) -> Self {
+        self.library_hook = Some(hook);
+        self
+    }
+
+    /// Amend the change by a later one, whose parts take precedence.
+    pub fn amend(&mut self, later: WorldChange) {
+        if later.font_paths.is_some() {
+            self.font_paths = later.font_paths;
+        }
+        if later.root.is_some() {
+            self.root = later.root;
+        }
+        if later.library_hook.is_some() {
+            self.library_hook = later.library_hook;
+        }
+    }
+}
+
+/// The parts of a world built for a change, which are swapped into the world
+/// at once.
+pub(crate) struct BuiltWorld {
+    pub change: WorldChange,
+    /// The fonts searched from the changed font paths.
+    pub fonts: Option<LazyFontResolver>,
+    /// The state built for the changed root.
+    pub root: Option<BuiltRoot>,
+}
+
+/// The state of a world depending on its root, which is built ahead for a
+/// change of the root.
+pub(crate) struct BuiltRoot {
+    /// The entry moved to the root, keeping the main file at the same path
+    /// relative to the root.
+    pub entry: EntryState,
+    /// The sources under the root with their mtimes, which are restored to
+    /// the cache of the vfs, see `Vfs::restore_contents`.
+    pub contents: Vec<(PathBuf, Time, Bytes)>,
+    /// The project file at the root, which replaces the one of the old root
+    /// if the actor reads it, see `CompileActor::with_project`.
+    pub project: Option<ProjectConfig>,
+}
+
+/// The state of the standby builds of an actor.
+#[derive(Debug, Default)]
+pub(crate) struct Standby {
+    /// Whether a build is in flight.
+    pub building: bool,
+    /// The changes requested while the build is in flight.
+    pub pending: Option<WorldChange>,
+}
+
+/// Build the parts of a world for a change off the compiler thread.
+///
+/// The fonts are searched from the changed font paths, the state depending on
+/// the changed root is built, see [`build_root`], and the library is built with
+/// the changed hook, which is cached for the next compilation.
+pub(crate) fn build(
+    fonts: &LazyFontResolver,
+    inputs: Arc<Prehashed<Dict>>,
+    main: Option<TypstFileId>,
+    change: WorldChange,
+) -> ZResult<BuiltWorld> {
+    let root = match &change.root {
+        Some(root) => Some(build_root(root.clone(), main)?),
+        None => None,
+    };
+
+    let fonts = match &change.font_paths {
+        Some(font_paths) => {
+            let fonts = (fonts.respawn_with_font_paths(font_paths.clone()))
+                .ok_or_else(|| error_once!("the fonts cannot be searched again"))?;
+            // Wait for the search, which the first compilation would wait for
+            // otherwise.
+            fonts.font_book();
+            Some(fonts)
+        }
+        None => None,
+    };
+
+    if let Some(hook) = &change.library_hook {
+        create_library(inputs, hook.clone());
+    }
+
+    Ok(BuiltWorld {
+        change,
+        fonts,
+        root,
+    })
+}
+
+/// Build the state of a world depending on a new root.
+///
+/// The `.typ` files under the root are read ahead, up to
+/// [`READ_AHEAD_LIMIT`] files in the order of their paths, while the other
+/// files, e.g. images and data, are read by the compilation on demand.
+pub(crate) fn build_root(root: ImmutPath, main: Option<TypstFileId>) -> ZResult<BuiltRoot> {
+    if !root.is_dir() {
+        return Err(error_once!("the root is not a directory", root: root.display()));
+    }
+    let access_model = SystemAccessModel;
+    (access_model.check_root(&root)).map_err(
+        |err| error_once!("the root is unavailable", root: root.display(), err: format!("{err:?}")),
+    )?;
+
+    let sources = (access_model
+        .list_files(&root)
+        .unwrap_or_default()
+        .into_iter())
+    .filter(|path| path.extension().is_some_and(|ext| ext == "typ"))
+    .take(READ_AHEAD_LIMIT);
+    let contents = sources
+        .filter_map(|path| {
+            // A file removed in the meantime is left to the compilation.
+            let mtime = access_model.mtime(&path).ok()?;
+            let content = access_model.content(&path).ok()?;
+            Some((path.clean(), mtime, content))
+        })
+        .collect();
+
+    let project = ProjectConfig::discover(&root).unwrap_or_else(|err| {
+        log::error!("CompileActor: failed to load the project file of the new root: {err}");
+        None
+    });
+
+    Ok(BuiltRoot {
+        entry: EntryState::new_rooted(root, main),
+        contents,
+        project,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::fixture::TestWorkspace;
+
+    #[test]
+    fn test_amend_world_change() {
+        let hook = LibraryHook::new(|_| {});
+        let mut change = WorldChange::default()
+            .with_font_paths(vec!["fonts".into()])
+            .with_library_hook(Some(hook));
+        change.amend(WorldChange::default().with_root(Arc::from(PathBuf::from("root"))));
+        change.amend(WorldChange::default().with_library_hook(None));
+
+        let expected = WorldChange {
+            font_paths: Some(vec!["fonts".into()]),
+            root: Some(Arc::from(PathBuf::from("root"))),
+            library_hook: Some(None),
+        };
+        assert_eq!(change, expected);
+    }
+
+    #[test]
+    fn test_build_root() {
+        let ws = TestWorkspace::new();
+        let root = ws.root();
+        std::fs::create_dir_all(root.join("chapters")).unwrap();
+        ws.write("main.typ", "#include \"chapters/a.typ\"");
+        ws.write("chapters/a.typ", "A");
+        ws.write("data.csv", "a,b");
+        let project = "[packages]\n\"@preview/example:0.1.0\" = \"vendor/example\"\n";
+        ws.write("typst-ts.toml", project);
+
+        let built = build_root(root.as_path().into(), None).unwrap();
+        assert_eq!(built.entry.root().as_deref(), Some(root.as_path()));
+        let contents: Vec<_> = (built.contents.iter())
+            .map(|(path, _, content)| (path.strip_prefix(&root).unwrap(), &content[..]))
+            .collect();
+        let expected: Vec<(&Path, &[u8])> = vec![
+            (Path::new("chapters/a.typ"), b"A"),
+            (Path::new("main.typ"), b"#include \"chapters/a.typ\""),
+        ];
+        assert_eq!(contents, expected);
+        let packages = built.project.unwrap().packages;
+        assert_eq!(
+            packages["@preview/example:0.1.0"],
+            root.join("vendor/example")
+        );
+
+        let file = root.join("main.typ");
+        assert!(build_root(file.as_path().into(), None).is_err());
+    }
+}
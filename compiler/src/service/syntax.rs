@@ -0,0 +1,215 @@
+//! Serialize the syntax trees of sources for the tools outside of the
+//! compiler, e.g. linters or structure-aware diffs, so that they don't have to
+//! parse the files again.
+//!
+//! A tree is printed either as an S-expression or as JSON. Each node has its
+//! kind and its byte range in the source, a leaf has its text as well, and an
+//! error node has its message. The kinds are the names of
+//! [`SyntaxKind`] in kebab case, e.g. `func-call` or `math-attach`.
+//!
+//! ```text
+//! ; typst-ts syntax tree, version 1
+//! (markup 0..5
+//!   (strong 0..5
+//!     (star 0..1 "*")
+//!     (markup 1..4
+//!       (text 1..4 "bold"))
+//!     (star 4..5 "*")))
+//! ```
+//!
+//! The texts of the S-expressions are escaped like the strings of Rust. The
+//! format is versioned by [`SYNTAX_FORMAT_VERSION`], which is bumped once the
+//! format changes.
+
+use std::{fmt::Write, ops::Range};
+
+use serde::Serialize;
+use typst::syntax::{LinkedNode, Source, SyntaxKind};
+use typst_ts_core::{error::prelude::*, typst::prelude::*};
+
+/// The version of the serialized syntax trees, see the [module docs](self).
+pub const SYNTAX_FORMAT_VERSION: u32 = 1;
+
+/// The format of a serialized syntax tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxTreeFormat {
+    SExpr,
+    /// An object with the `version` and the `root` node.
+    Json,
+}
+
+/// A node of a syntax tree, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntaxTreeNode {
+    pub kind: String,
+    pub range: Range<usize>,
+    /// The text of a leaf.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<EcoString>,
+    /// The message of an error node.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<EcoString>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<SyntaxTreeNode>,
+}
+
+/// A node on the path from the root to a leaf, see [`syntax_path`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntaxAncestor {
+    pub kind: String,
+    pub range: Range<usize>,
+}
+
+#[derive(Serialize)]
+struct SyntaxTree {
+    version: u32,
+    root: SyntaxTreeNode,
+}
+
+/// Serialize the syntax tree of a source, which includes the error nodes of
+/// a source failing to parse.
+pub fn syntax_tree(source: &Source, format: SyntaxTreeFormat) -> String {
+    let root = tree_node(&LinkedNode::new(source.root()));
+    match format {
+        SyntaxTreeFormat::SExpr => {
+            let mut out = format!("; typst-ts syntax tree, version {SYNTAX_FORMAT_VERSION}\n");
+            write_sexpr(&mut out, &root, 0);
+            out.push('\n');
+            out
+        }
+        SyntaxTreeFormat::Json => serde_json::to_string_pretty(&SyntaxTree {
+            version: SYNTAX_FORMAT_VERSION,
+            root,
+        })
+        .unwrap(),
+    }
+}
+
+/// Get the nodes from the root to the leaf at a byte offset of a source.
+///
+/// An offset between two leaves is in the leaf before it, like a cursor
+/// after the last character that was typed.
+pub fn syntax_path(source: &Source, offset: usize) -> ZResult<Vec<SyntaxAncestor>> {
+    let root = LinkedNode::new(source.root());
+    let leaf = (root.leaf_at(offset))
+        .ok_or_else(|| error_once!("the offset is out of the source", offset: offset))?;
+
+    let mut path = vec![];
+    let mut node = Some(leaf);
+    while let Some(current) = node {
+        path.push(SyntaxAncestor {
+            kind: kind_name(current.kind()),
+            range: current.range(),
+        });
+        node = current.parent().cloned();
+    }
+    path.reverse();
+    Ok(path)
+}
+
+fn tree_node(node: &LinkedNode) -> SyntaxTreeNode {
+    let leaf = node.get().children().len() == 0;
+    let message = match node.kind() {
+        SyntaxKind::Error => node.errors().into_iter().next().map(|err| err.message),
+        _ => None,
+    };
+
+    SyntaxTreeNode {
+        kind: kind_name(node.kind()),
+        range: node.range(),
+        text: leaf.then(|| node.text().clone()),
+        message,
+        children: node.children().map(|child| tree_node(&child)).collect(),
+    }
+}
+
+fn write_sexpr(out: &mut String, node: &SyntaxTreeNode, depth: usize) {
+    let range = &node.range;
+    write!(out, "({} {}..{}", node.kind, range.start, range.end).unwrap();
+    if let Some(text) = &node.text {
+        write!(out, " {:?}", text.as_str()).unwrap();
+    }
+    if let Some(message) = &node.message {
+        write!(out, " :message {:?}", message.as_str()).unwrap();
+    }
+    for child in &node.children {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth + 1));
+        write_sexpr(out, child, depth + 1);
+    }
+    out.push(')');
+}
+
+/// Get the name of a kind in kebab case, e.g. `func-call` for
+/// [`SyntaxKind::FuncCall`].
+fn kind_name(kind: SyntaxKind) -> String {
+    let mut name = String::new();
+    for c in format!("{kind:?}").chars() {
+        if c.is_ascii_uppercase() && !name.is_empty() {
+            name.push('-');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syntax_tree() {
+        let source = Source::detached("= A\n#f(1)\n$x^2$");
+        let golden = r##"; typst-ts syntax tree, version 1
+(markup 0..15
+  (heading 0..3
+    (heading-marker 0..1 "=")
+    (space 1..2 " ")
+    (markup 2..3
+      (text 2..3 "A")))
+  (space 3..4 "\n")
+  (hash 4..5 "#")
+  (func-call 5..9
+    (ident 5..6 "f")
+    (args 6..9
+      (left-paren 6..7 "(")
+      (int 7..8 "1")
+      (right-paren 8..9 ")")))
+  (space 9..10 "\n")
+  (equation 10..15
+    (dollar 10..11 "$")
+    (math 11..14
+      (math-attach 11..14
+        (text 11..12 "x")
+        (hat 12..13 "^")
+        (text 13..14 "2")))
+    (dollar 14..15 "$")))
+"##;
+        assert_eq!(syntax_tree(&source, SyntaxTreeFormat::SExpr), golden);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&syntax_tree(&source, SyntaxTreeFormat::Json)).unwrap();
+        assert_eq!(json["version"], SYNTAX_FORMAT_VERSION);
+        assert_eq!(json["root"]["children"][3]["kind"], "func-call");
+        assert_eq!(
+            json["root"]["children"][3]["range"],
+            serde_json::json!({ "start": 5, "end": 9 })
+        );
+        assert_eq!(json["root"]["children"][0]["children"][0]["text"], "=");
+
+        // After the `x` of the equation.
+        let path = syntax_path(&source, 12).unwrap();
+        let kinds: Vec<_> = path.iter().map(|node| node.kind.as_str()).collect();
+        assert_eq!(kinds, ["markup", "equation", "math", "math-attach", "text"]);
+        assert_eq!(path[4].range, 11..12);
+        assert!(syntax_path(&source, 16).is_err());
+
+        // A source failing to parse is serialized along with its errors.
+        let source = Source::detached("#f(");
+        let tree = syntax_tree(&source, SyntaxTreeFormat::SExpr);
+        assert!(tree.contains("(error "), "{tree}");
+        assert!(tree.contains(":message "), "{tree}");
+    }
+}
@@ -9,7 +9,7 @@
 //! Hopefully, one day a reliable file watching/walking crate appears on
 //! crates.io, and we can reduce this to trivial glue code.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
 use tokio::sync::mpsc;
@@ -28,6 +28,10 @@ type NotifyEvent = notify::Result<notify::Event>;
 type FileEntry = (/* key */ ImmutPath, /* value */ FileSnapshot);
 type NotifyFilePair = FileResult<(/* mtime */ crate::Time, /* content */ Bytes)>;
 
+/// The number of dependency updates after which the entry of a file no longer
+/// depended on is dropped, see [`NotifyActor::collect_garbage`].
+const ENTRY_LIFETIME: usize = 30;
+
 /// The state of a watched file.
 ///
 /// It is used to determine some dirty editors' implementation.
@@ -54,15 +58,17 @@ impl Default for WatchState {
 /// The data entry of a watched file.
 #[derive(Debug)]
 struct WatchedEntry {
-    /// The lifetime of the entry.
+    /// The lifetime when the entry is last depended on.
     ///
-    /// The entry will be removed if the entry is too old.
-    // todo: generalize lifetime
+    /// The entry will be removed if it is no longer depended on for
+    /// [`ENTRY_LIFETIME`] lifetimes.
     lifetime: usize,
     /// A flag for whether it is really watching.
     watching: bool,
-    /// A flag for watch update.
+    /// A flag for whether the file is depended on.
     seen: bool,
+    /// The directory watched for the creation of the file if it is missing.
+    missing_parent: Option<ImmutPath>,
     /// The state of the entry.
     state: WatchState,
     /// Previous content of the file.
@@ -91,7 +97,8 @@ pub struct NotifyActor {
     /// We concrete the access model to `SystemAccessModel` for now.
     inner: SystemAccessModel,
 
-    /// The lifetime of the watched files.
+    /// The lifetime of the watched files, which is increased per update of
+    /// the dependencies.
     lifetime: usize,
     /// The logical tick of the actor.
     logical_tick: usize,
@@ -106,6 +113,9 @@ pub struct NotifyActor {
 
     /// The hold entries for watching, one entry for per file.
     watched_entries: HashMap<ImmutPath, WatchedEntry>,
+    /// The directories watched for the creation of the missing files, e.g.
+    /// an entry file not created yet.
+    missing_parents: HashSet<ImmutPath>,
 
     /// The builtin watcher object.
     watcher: Option<WatcherPair>,
@@ -141,6 +151,7 @@ impl NotifyActor {
             undetermined_recv,
 
             watched_entries: HashMap::new(),
+            missing_parents: HashSet::new(),
             watcher: watcher.map(|it| (it, watcher_receiver)),
         }
     }
@@ -203,6 +214,11 @@ impl NotifyActor {
                         self.send(FilesystemEvent::Update(changeset));
                     }
                 }
+                ActorEvent::Message(NotifyMessage::UpdateDependency { inserts, removes }) => {
+                    if let Some(changeset) = self.update_watch_delta(&inserts, &removes) {
+                        self.send(FilesystemEvent::Update(changeset));
+                    }
+                }
                 ActorEvent::NotifyEvent(event) => {
                     // log::info!("notify event {event:?}");
                     if let Some(event) = log_notify_error(event, "failed to notify") {
@@ -243,63 +259,12 @@ impl NotifyActor {
         }
 
         // Update watched entries.
-        //
-        // Also check whether the file is updated since there is a window
-        // between unwatch the file and watch the file again.
         for path in paths.iter() {
-            let mut contained = false;
-            // Update or insert the entry with the new lifetime.
-            let entry = self
-                .watched_entries
-                .entry(path.clone())
-                .and_modify(|watch_entry| {
-                    contained = true;
-                    watch_entry.lifetime = self.lifetime;
-                    watch_entry.seen = true;
-                })
-                .or_insert_with(|| WatchedEntry {
-                    lifetime: self.lifetime,
-                    watching: false,
-                    seen: true,
-                    state: WatchState::Stable,
-                    prev: None,
-                    prev_meta: Err(FileError::Other(Some(EcoString::from("_not-init_")))),
-                });
-
-            // Update in-memory metadata for now.
-            let meta = path.metadata().map_err(|e| FileError::from_io(e, path));
-
-            if let Some((watcher, _)) = &mut self.watcher {
-                // Case1. meta = Err(..) We cannot get the metadata successfully, so we
-                // are okay to ignore this file for watching.
-                //
-                // Case2. meta = Ok(..) Watch the file if it's not watched.
-                if meta
-                    .as_ref()
-                    .is_ok_and(|meta| !meta.is_dir() && (!contained || !entry.watching))
-                {
-                    log::debug!("watching {path:?}");
-                    entry.watching = log_notify_error(
-                        watcher.watch(path.as_ref(), RecursiveMode::NonRecursive),
-                        "failed to watch",
-                    )
-                    .is_some();
-                }
-
-                changeset.may_insert(self.notify_entry_update(path.clone(), Some(meta)));
-            } else {
-                let watched = meta.and_then(|meta| {
-                    let content = self.inner.content(path)?;
-                    Ok((meta.modified().unwrap(), content))
-                });
-                changeset.inserts.push((path.clone(), watched.into()));
-            }
+            self.watch_path(path, &mut changeset);
         }
 
-        // Remove old entries.
-        // Note: since we have increased the lifetime, it is safe to remove the
-        // old entries after updating the watched entries.
-        self.watched_entries.retain(|path, entry| {
+        // Unwatch the unseen entries.
+        for (path, entry) in self.watched_entries.iter_mut() {
             if !entry.seen && entry.watching {
                 log::debug!("unwatch {path:?}");
                 if let Some(watcher) = &mut self.watcher {
@@ -307,15 +272,150 @@ impl NotifyActor {
                     entry.watching = false;
                 }
             }
+        }
+
+        self.collect_garbage(&mut changeset);
+        (!changeset.is_empty()).then_some(changeset)
+    }
+
+    /// Update the watches of the changed dependencies, leaving the other
+    /// watched files as is, see [`NotifyMessage::UpdateDependency`].
+    ///
+    /// The entries of the removed files are unwatched, and dropped like those
+    /// of a full sync, see [`Self::collect_garbage`].
+    fn update_watch_delta(
+        &mut self,
+        inserts: &[ImmutPath],
+        removes: &[ImmutPath],
+    ) -> Option<FileChangeSet> {
+        // Increase the lifetime per external message.
+        self.lifetime += 1;
+
+        let mut changeset = FileChangeSet::default();
+        for path in inserts {
+            self.watch_path(path, &mut changeset);
+        }
+
+        for path in removes {
+            let Some(entry) = self.watched_entries.get_mut(path) else {
+                continue;
+            };
+            // The entry is last depended on by the previous update.
+            entry.seen = false;
+            entry.lifetime = self.lifetime - 1;
+            if let Some((watcher, _)) = &mut self.watcher {
+                if entry.watching {
+                    log::debug!("unwatch {path:?}");
+                    log_notify_error(watcher.unwatch(path), "failed to unwatch");
+                    entry.watching = false;
+                }
+            }
+        }
 
-            let fresh = self.lifetime - entry.lifetime < 30;
+        self.collect_garbage(&mut changeset);
+        (!changeset.is_empty()).then_some(changeset)
+    }
+
+    /// Drop the entries which are not depended on for [`ENTRY_LIFETIME`]
+    /// lifetimes, and update the watched directories of the missing files
+    /// which are depended on.
+    ///
+    /// It is called by both the full syncs and the deltas of the
+    /// dependencies, since the full syncs are rare, see
+    /// [`NotifyMessage::UpdateDependency`].
+    fn collect_garbage(&mut self, changeset: &mut FileChangeSet) {
+        let lifetime = self.lifetime;
+        self.watched_entries.retain(|path, entry| {
+            let fresh = entry.seen || lifetime - entry.lifetime < ENTRY_LIFETIME;
             if !fresh {
                 changeset.removes.push(path.clone());
             }
             fresh
         });
 
-        (!changeset.is_empty()).then_some(changeset)
+        let missing_parents: HashSet<ImmutPath> = (self.watched_entries.values())
+            .filter(|entry| entry.seen)
+            .filter_map(|entry| entry.missing_parent.clone())
+            .collect();
+        if let Some((watcher, _)) = &mut self.watcher {
+            for dir in missing_parents.difference(&self.missing_parents) {
+                log::debug!("watching directory {dir:?}");
+                log_notify_error(
+                    watcher.watch(dir.as_ref(), RecursiveMode::NonRecursive),
+                    "failed to watch",
+                );
+            }
+            for dir in self.missing_parents.difference(&missing_parents) {
+                log::debug!("unwatch directory {dir:?}");
+                log_notify_error(watcher.unwatch(dir), "failed to unwatch");
+            }
+        }
+        self.missing_parents = missing_parents;
+    }
+
+    /// Watch a dependency with the current lifetime, collecting its changes
+    /// and the directory to watch if it is missing.
+    ///
+    /// Also check whether the file is updated since there is a window between
+    /// unwatch the file and watch the file again.
+    fn watch_path(&mut self, path: &ImmutPath, changeset: &mut FileChangeSet) {
+        let mut contained = false;
+        // Update or insert the entry with the new lifetime.
+        let entry = self
+            .watched_entries
+            .entry(path.clone())
+            .and_modify(|watch_entry| {
+                contained = true;
+                watch_entry.lifetime = self.lifetime;
+                watch_entry.seen = true;
+            })
+            .or_insert_with(|| WatchedEntry {
+                lifetime: self.lifetime,
+                watching: false,
+                seen: true,
+                missing_parent: None,
+                state: WatchState::Stable,
+                prev: None,
+                prev_meta: Err(FileError::Other(Some(EcoString::from("_not-init_")))),
+            });
+
+        // Update in-memory metadata for now.
+        let meta = path.metadata().map_err(|e| FileError::from_io(e, path));
+
+        // A missing file can't be watched, so watch its directory to notice the
+        // creation of the file.
+        entry.missing_parent = match &meta {
+            Err(FileError::NotFound(..)) => (path.parent())
+                .filter(|parent| parent.is_dir())
+                .map(ImmutPath::from),
+            _ => None,
+        };
+
+        if let Some((watcher, _)) = &mut self.watcher {
+            // Case1. meta = Err(..) We cannot get the metadata successfully, so we
+            // are okay to ignore this file for watching.
+            //
+            // Case2. meta = Ok(..) Watch the file if it's not watched.
+            if meta
+                .as_ref()
+                .is_ok_and(|meta| !meta.is_dir() && (!contained || !entry.watching))
+            {
+                log::debug!("watching {path:?}");
+                entry.watching = log_notify_error(
+                    watcher.watch(path.as_ref(), RecursiveMode::NonRecursive),
+                    "failed to watch",
+                )
+                .is_some();
+            }
+
+            changeset.may_insert(self.notify_entry_update(path.clone(), Some(meta)));
+        } else {
+            let watched = meta.and_then(|meta| {
+                let content = self.inner.content(path)?;
+                Ok((meta.modified().unwrap(), content))
+            });
+            changeset.inserts.push((path.clone(), watched.into()));
+        }
     }
 
     /// Notify the event from the builtin watcher.
@@ -593,3 +693,44 @@ pub async fn watch_deps(
     }
     log::debug!("stop watching files...");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_garbage_on_deltas() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("main.typ"), "main").unwrap();
+        std::fs::write(root.join("removed.typ"), "removed").unwrap();
+        let path = |name: &str| ImmutPath::from(root.join(name));
+        let (main, removed, missing) = (path("main.typ"), path("removed.typ"), path("missing.typ"));
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut actor = NotifyActor::new(tx);
+        actor.update_watches(&[main.clone(), removed.clone()]);
+        actor.update_watch_delta(std::slice::from_ref(&missing), &[]);
+        assert!(actor.missing_parents.contains(root));
+
+        // The directory of the missing file is unwatched by the delta, while
+        // the entries are dropped once they are not depended on for a while.
+        actor.update_watch_delta(&[], &[removed.clone(), missing.clone()]);
+        assert!(actor.missing_parents.is_empty());
+        let mut dropped = vec![];
+        for lifetime in 1..=ENTRY_LIFETIME {
+            let changeset = actor.update_watch_delta(&[], &[]).unwrap_or_default();
+            for path in changeset.removes {
+                dropped.push((lifetime, path));
+            }
+        }
+        dropped.sort();
+        let expected = vec![
+            (ENTRY_LIFETIME - 1, missing.clone()),
+            (ENTRY_LIFETIME - 1, removed.clone()),
+        ];
+        assert_eq!(dropped, expected);
+        assert!(actor.watched_entries.contains_key(&main));
+        assert!(!actor.watched_entries.contains_key(&removed));
+    }
+}
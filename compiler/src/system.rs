@@ -1,12 +1,13 @@
 use std::sync::Arc;
 
 use comemo::Prehashed;
-use typst_ts_core::{config::CompileOpts, error::prelude::*, font::FontResolverImpl};
+use typst_ts_core::{config::CompileOpts, error::prelude::*};
 
 use crate::{
-    font::system::SystemFontSearcher,
+    cache::DiskCache,
+    font::system::LazyFontResolver,
     package::http::HttpRegistry,
-    vfs::{system::SystemAccessModel, Vfs},
+    vfs::{system::SystemAccessModel, trace::TraceAccessModel, Vfs},
 };
 
 /// type trait of [`TypstSystemWorld`].
@@ -14,8 +15,8 @@ use crate::{
 pub struct SystemCompilerFeat;
 
 impl crate::world::CompilerFeat for SystemCompilerFeat {
-    /// Uses [`FontResolverImpl`] directly.
-    type FontResolver = FontResolverImpl;
+    /// Searches fonts in the background, see [`LazyFontResolver`].
+    type FontResolver = LazyFontResolver;
     /// It accesses a physical file system.
     type AccessModel = SystemAccessModel;
     /// It performs native HTTP requests for fetching package data.
@@ -25,26 +26,48 @@ impl crate::world::CompilerFeat for SystemCompilerFeat {
 /// The compiler world in system environment.
 pub type TypstSystemWorld = crate::world::CompilerWorld<SystemCompilerFeat>;
 
+/// type trait of [`TracedSystemWorld`].
+#[derive(Debug, Clone, Copy)]
+pub struct TracedSystemCompilerFeat;
+
+impl crate::world::CompilerFeat for TracedSystemCompilerFeat {
+    /// Searches fonts in the background, see [`LazyFontResolver`].
+    type FontResolver = LazyFontResolver;
+    /// It counts the accesses to a physical file system.
+    type AccessModel = TraceAccessModel<SystemAccessModel>;
+    /// It performs native HTTP requests for fetching package data.
+    type Registry = HttpRegistry;
+}
+
+/// The compiler world in system environment, which counts the accesses to
+/// the file system, see [`crate::service::CompileDriverBuilder::with_trace_access`].
+pub type TracedSystemWorld = crate::world::CompilerWorld<TracedSystemCompilerFeat>;
+
 impl TypstSystemWorld {
     /// Create [`TypstSystemWorld`] with the given options.
     /// See SystemCompilerFeat for instantiation details.
     /// See [`CompileOpts`] for available options.
     pub fn new(mut opts: CompileOpts) -> ZResult<Self> {
         let inputs = std::mem::take(&mut opts.inputs);
+        let extra_roots = std::mem::take(&mut opts.extra_roots);
+        let extra_roots = extra_roots.into_iter().map(From::from).collect();
+        let disk_cache = opts.cache_dir.as_ref().map(DiskCache::open);
         let mut w = Self::new_raw(
             opts.entry.clone().try_into()?,
             Vfs::new(SystemAccessModel {}),
             HttpRegistry::default(),
-            Self::resolve_fonts(opts)?,
+            Self::resolve_fonts(opts),
         );
         w.set_inputs(Arc::new(Prehashed::new(inputs)));
+        w.set_extra_roots(extra_roots);
+        w.set_disk_cache(disk_cache);
         Ok(w)
     }
 
     /// Resolve fonts from given options.
-    fn resolve_fonts(opts: CompileOpts) -> ZResult<FontResolverImpl> {
-        let mut searcher = SystemFontSearcher::new();
-        searcher.resolve_opts(opts.into())?;
-        Ok(searcher.into())
+    ///
+    /// The fonts are searched in the background until they are first used.
+    fn resolve_fonts(opts: CompileOpts) -> LazyFontResolver {
+        LazyFontResolver::spawn(opts.into())
     }
 }
@@ -0,0 +1,240 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use typst::diag::{FileError, FileResult};
+
+use typst_ts_core::{error::prelude::*, path::PathClean, Bytes, ImmutPath};
+
+use crate::Time;
+
+use super::AccessModel;
+
+/// Provides access model which serves files from an in-memory tar archive.
+///
+/// Paths are resolved inside the archive namespace, i.e. a path `root/a.typ`
+/// corresponds to the entry `a.typ` in the archive. Gzip-compressed archives
+/// are decompressed on loading.
+#[derive(Debug, Clone)]
+pub struct ArchiveAccessModel {
+    /// The path where the archive is mounted.
+    root: ImmutPath,
+    files: HashMap<PathBuf, (Time, Bytes)>,
+    dirs: HashSet<PathBuf>,
+}
+
+impl ArchiveAccessModel {
+    /// Load all entries of a tar archive, mounting it at `root`.
+    pub fn from_tar(root: ImmutPath, data: &[u8]) -> ZResult<Self> {
+        let mut model = Self {
+            root,
+            files: HashMap::new(),
+            dirs: HashSet::new(),
+        };
+
+        // gzip magic number
+        if data.starts_with(&[0x1f, 0x8b]) {
+            model.load_tar(flate2::read::GzDecoder::new(data))?;
+        } else {
+            model.load_tar(data)?;
+        }
+
+        Ok(model)
+    }
+
+    /// Serve the given files, mounting them at `root`.
+    ///
+    /// The paths of the files are relative to the root.
+    pub fn from_files(root: ImmutPath, files: impl IntoIterator<Item = (PathBuf, Bytes)>) -> Self {
+        let mut model = Self {
+            root,
+            files: HashMap::new(),
+            dirs: HashSet::new(),
+        };
+
+        for (path, content) in files {
+            let path = path.clean();
+            model.add_parents(&path);
+            model.files.insert(path, (Time::UNIX_EPOCH, content));
+        }
+
+        model
+    }
+
+    fn load_tar(&mut self, reader: impl Read) -> ZResult<()> {
+        let mut archive = tar::Archive::new(reader);
+        let entries = archive
+            .entries()
+            .map_err(map_string_err("failed to read tar archive"))?;
+
+        for entry in entries {
+            let mut entry = entry.map_err(map_string_err("failed to read tar entry"))?;
+            let path = entry
+                .path()
+                .map_err(map_string_err("failed to read tar entry path"))?
+                .clean();
+            let mtime = entry.header().mtime().unwrap_or_default();
+            let mtime = Time::UNIX_EPOCH + std::time::Duration::from_secs(mtime);
+
+            match entry.header().entry_type() {
+                tar::EntryType::Regular | tar::EntryType::Continuous => {
+                    let mut buf = Vec::new();
+                    entry
+                        .read_to_end(&mut buf)
+                        .map_err(map_string_err("failed to read tar entry"))?;
+                    self.add_parents(&path);
+                    self.files.insert(path, (mtime, buf.into()));
+                }
+                tar::EntryType::Directory => {
+                    self.add_parents(&path);
+                    self.dirs.insert(path);
+                }
+                // Links and special files are not supported.
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_parents(&mut self, path: &Path) {
+        for dir in path.ancestors().skip(1) {
+            self.dirs.insert(dir.to_owned());
+        }
+    }
+
+    /// Get the path of an entry relative to the archive root.
+    fn entry_path(&self, src: &Path) -> FileResult<PathBuf> {
+        let path = src.clean();
+        match path.strip_prefix(self.root.clean()) {
+            Ok(path) => Ok(path.to_owned()),
+            Err(_) => Err(FileError::NotFound(src.to_owned())),
+        }
+    }
+
+    fn file(&self, src: &Path) -> FileResult<&(Time, Bytes)> {
+        let path = self.entry_path(src)?;
+        if self.dirs.contains(&path) {
+            return Err(FileError::IsDirectory);
+        }
+        self.files
+            .get(&path)
+            .ok_or_else(|| FileError::NotFound(src.to_owned()))
+    }
+}
+
+impl AccessModel for ArchiveAccessModel {
+    type RealPath = PathBuf;
+
+    fn mtime(&self, src: &Path) -> FileResult<Time> {
+        let path = self.entry_path(src)?;
+        if self.dirs.contains(&path) {
+            return Ok(Time::UNIX_EPOCH);
+        }
+        Ok(self.file(src)?.0)
+    }
+
+    fn is_file(&self, src: &Path) -> FileResult<bool> {
+        let path = self.entry_path(src)?;
+        if self.dirs.contains(&path) {
+            return Ok(false);
+        }
+        self.file(src).map(|_| true)
+    }
+
+    fn real_path(&self, src: &Path) -> FileResult<Self::RealPath> {
+        Ok(src.to_owned())
+    }
+
+    fn content(&self, src: &Path) -> FileResult<Bytes> {
+        Ok(self.file(src)?.1.clone())
+    }
+
+    fn list_files(&self, dir: &Path) -> FileResult<Vec<PathBuf>> {
+        let dir = self.entry_path(dir)?;
+        let mut files: Vec<_> = (self.files.keys())
+            .filter(|path| path.starts_with(&dir))
+            .map(|path| self.root.join(path))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::{eval::Tracer, syntax::VirtualPath};
+    use typst_ts_core::{
+        config::compiler::EntryState, font::FontResolverImpl, package::dummy::DummyRegistry,
+        TypstFileId,
+    };
+
+    use super::*;
+    use crate::{
+        font::pure::MemoryFontBuilder,
+        service::{CompileEnv, EnvWorld},
+        vfs::Vfs,
+        world::{CompilerFeat, CompilerWorld},
+    };
+
+    #[derive(Debug, Clone, Copy)]
+    struct ArchiveCompilerFeat;
+
+    impl CompilerFeat for ArchiveCompilerFeat {
+        type FontResolver = FontResolverImpl;
+        type AccessModel = ArchiveAccessModel;
+        type Registry = DummyRegistry;
+    }
+
+    fn build_tar(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(42);
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_compile_from_tar() {
+        let data = build_tar(&[
+            (
+                "main.typ",
+                "#import \"lib/util.typ\": greet\n#greet(\"archive\")",
+            ),
+            ("lib/util.typ", "#let greet(name) = [Hello, #name!]"),
+        ]);
+        let root: ImmutPath = Path::new("/archive").into();
+        let model = ArchiveAccessModel::from_tar(root.clone(), &data).unwrap();
+
+        let main = root.join("main.typ");
+        assert_eq!(model.is_file(&main), Ok(true));
+        assert_eq!(model.is_file(&root.join("lib")), Ok(false));
+        assert!(model.is_file(&root.join("missing.typ")).is_err());
+        let mtime = Time::UNIX_EPOCH + std::time::Duration::from_secs(42);
+        assert_eq!(model.mtime(&main), Ok(mtime));
+
+        let mut fonts = MemoryFontBuilder::new();
+        for font in typst_assets::fonts() {
+            fonts.add_memory_font(Bytes::from_static(font));
+        }
+        let main_id = TypstFileId::new(None, VirtualPath::new("main.typ"));
+        let mut world = CompilerWorld::<ArchiveCompilerFeat>::new_raw(
+            EntryState::new_rooted(root, Some(main_id)),
+            Vfs::new(model),
+            DummyRegistry,
+            fonts.into(),
+        );
+
+        world.prepare_env(&mut CompileEnv::default()).unwrap();
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        assert_eq!(doc.pages.len(), 1);
+    }
+}
@@ -1,22 +1,40 @@
-use std::{collections::HashMap, ffi::OsStr, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use parking_lot::{RwLock, RwLockUpgradableReadGuard};
+use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
 use typst::diag::{FileError, FileResult};
 
-use typst_ts_core::{Bytes, QueryRef};
+use typst_ts_core::{hash::hash128, Bytes, QueryRef};
 
-use crate::{vfs::from_utf8_or_bom, Time};
+use crate::{
+    images::{GuardedImage, ImageLimits},
+    vfs::from_utf8_or_bom,
+    Time,
+};
 
 use super::AccessModel;
 
 /// incrementally query a value from a self holding state
 type IncrQueryRef<S, E> = QueryRef<S, E, Option<S>>;
 
+/// guarded images by the hashes of their content and the limits
+type GuardedImages = HashMap<(u128, u128), (usize, Option<GuardedImage>)>;
+
 /// Holds the cached data of a single file
 #[derive(Debug)]
 pub struct CacheEntry<S> {
     /// The last lifetime count when the cache is updated
     last_access_lifetime: usize,
+    /// The time when the cache is accessed last
+    last_access: Mutex<Time>,
     /// The cached mtime of the file
     mtime: Time,
     /// Whether the file is a file, lazily triggered when mtime is changed
@@ -28,6 +46,16 @@ pub struct CacheEntry<S> {
     source_state: IncrQueryRef<S, FileError>,
 }
 
+/// The reads of file contents and sources through a [`CachedAccessModel`]
+/// since it is cleared, see [`CachedAccessModel::read_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadStats {
+    pub reads: u64,
+    /// The reads served by the cache without reading the underlying access
+    /// model, i.e. of the files unchanged since they were read.
+    pub cached: u64,
+}
+
 /// Provides general cache to file access.
 #[derive(Debug)]
 pub struct CachedAccessModel<Inner: AccessModel, C> {
@@ -39,6 +67,15 @@ pub struct CachedAccessModel<Inner: AccessModel, C> {
     lifetime_cnt: usize,
     /// The cache entries for each paths
     cache_entries: RwLock<HashMap<Arc<OsStr>, CacheEntry<C>>>,
+    /// The clock to timestamp the accesses, see [`Self::evict_older_than`]
+    clock: fn() -> Time,
+    /// The guarded images by the hashes of their content and the limits, with
+    /// the last lifetime count when they are accessed
+    guarded_images: Mutex<GuardedImages>,
+    /// The number of reads since the last clear
+    reads: AtomicU64,
+    /// The number of reads served by the cache since the last clear
+    cached_reads: AtomicU64,
 }
 
 impl<Inner: AccessModel, C> CachedAccessModel<Inner, C> {
@@ -48,9 +85,19 @@ impl<Inner: AccessModel, C> CachedAccessModel<Inner, C> {
             inner,
             lifetime_cnt: 1,
             cache_entries: RwLock::new(HashMap::new()),
+            clock: crate::time::now,
+            guarded_images: Mutex::new(HashMap::new()),
+            reads: AtomicU64::new(0),
+            cached_reads: AtomicU64::new(0),
         }
     }
 
+    /// Use another clock to timestamp the accesses, e.g. a mock clock
+    pub fn with_clock(mut self, clock: fn() -> Time) -> Self {
+        self.clock = clock;
+        self
+    }
+
     /// Get the inner access model
     pub fn inner(&self) -> &Inner {
         &self.inner
@@ -60,6 +107,90 @@ impl<Inner: AccessModel, C> CachedAccessModel<Inner, C> {
     pub fn inner_mut(&mut self) -> &mut Inner {
         &mut self.inner
     }
+
+    /// Get the number of cached files
+    pub fn entry_count(&self) -> usize {
+        self.cache_entries.read().len()
+    }
+
+    /// Get the reads since the last clear, e.g. of the current compilation
+    pub fn read_stats(&self) -> ReadStats {
+        ReadStats {
+            reads: self.reads.load(Ordering::Relaxed),
+            cached: self.cached_reads.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_read(&self, cached: bool) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        if cached {
+            self.cached_reads.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Get the cached contents of the files along with their mtimes, e.g. to
+    /// seed the cache of another process, see [`Self::seed`]
+    pub fn cached_contents(&self) -> Vec<(PathBuf, Time, Bytes)> {
+        (self.cache_entries.read().iter())
+            .filter_map(|(path, entry)| {
+                let content = entry.read_all.get_uninitialized()?.as_ref().ok()?;
+                Some((PathBuf::from(&**path), entry.mtime, content.clone()))
+            })
+            .collect()
+    }
+
+    /// Get the total size of the cached file contents in bytes
+    pub fn memory_usage(&self) -> usize {
+        (self.cache_entries.read().values())
+            .filter_map(|entry| entry.read_all.get_uninitialized())
+            .filter_map(|content| content.as_ref().ok())
+            .map(|content| content.len())
+            .sum()
+    }
+
+    /// Get an image guarded by the limits, reusing the result of the same
+    /// content and limits, see [`crate::images`]
+    pub fn guard_image(
+        &self,
+        content: &Bytes,
+        limits: &ImageLimits,
+        guard: impl FnOnce() -> Option<GuardedImage>,
+    ) -> Option<GuardedImage> {
+        let key = (hash128(content), hash128(limits));
+        if let Some((lifetime, guarded)) = self.guarded_images.lock().get_mut(&key) {
+            *lifetime = self.lifetime_cnt;
+            return guarded.clone();
+        }
+
+        // Guard the image without holding the lock, since decoding takes a
+        // while.
+        let guarded = guard();
+        (self.guarded_images.lock()).insert(key, (self.lifetime_cnt, guarded.clone()));
+        guarded
+    }
+
+    /// Drop the cache entry of a path, so that the next access reads the file
+    /// again regardless of its mtime
+    pub fn invalidate(&mut self, src: &Path) {
+        self.cache_entries.get_mut().remove(src.as_os_str());
+    }
+
+    /// Retain only the cache entries of the paths specified by the predicate
+    pub fn retain(&mut self, mut f: impl FnMut(&Path) -> bool) {
+        (self.cache_entries.get_mut()).retain(|path, _| f(Path::new(&**path)));
+    }
+
+    /// Drop the cache entries which are not accessed within the given age,
+    /// e.g. of files which are no longer referenced by a long-running server
+    pub fn evict_older_than(&mut self, age: Duration) {
+        let now = (self.clock)();
+        (self.cache_entries.get_mut()).retain(|_, entry| {
+            // Keep the entries accessed in the future of a skewed clock.
+            let last_access = *entry.last_access.get_mut();
+            now.duration_since(last_access)
+                .map_or(true, |elapsed| elapsed <= age)
+        });
+    }
 }
 
 impl<Inner: AccessModel, C: Clone> CachedAccessModel<Inner, C> {
@@ -76,6 +207,7 @@ impl<Inner: AccessModel, C: Clone> CachedAccessModel<Inner, C> {
         let path_results = self.cache_entries.upgradable_read();
         let entry = path_results.get(path_key);
         let (new_mtime, prev_to_diff) = if let Some(entry) = entry {
+            *entry.last_access.lock() = (self.clock)();
             if entry.last_access_lifetime == self.lifetime_cnt {
                 return cb(entry);
             }
@@ -102,6 +234,7 @@ impl<Inner: AccessModel, C: Clone> CachedAccessModel<Inner, C> {
             path_key.into(),
             CacheEntry {
                 last_access_lifetime: self.lifetime_cnt,
+                last_access: Mutex::new((self.clock)()),
                 mtime: new_mtime,
                 is_file: QueryRef::default(),
                 read_all: QueryRef::default(),
@@ -116,21 +249,118 @@ impl<Inner: AccessModel, C: Clone> CachedAccessModel<Inner, C> {
 }
 
 impl<Inner: AccessModel, C: Clone> CachedAccessModel<Inner, C> {
+    /// Seed the cache of a file with content which is known to be up to date,
+    /// e.g. carried by a filesystem event.
+    ///
+    /// Later accesses are cache hits without reading the underlying access
+    /// model, as long as it reports the same mtime.
+    pub fn seed(&mut self, src: &Path, mtime: Time, content: Bytes) {
+        let path_key = src.as_os_str();
+        let entries = self.cache_entries.get_mut();
+        let prev = entries.get(path_key);
+        let unchanged = prev.is_some_and(|entry| {
+            entry.mtime == mtime
+                && matches!(entry.read_all.get_uninitialized(), Some(Ok(prev)) if *prev == content)
+        });
+        if unchanged {
+            return;
+        }
+
+        let prev_to_diff = prev
+            .and_then(|entry| entry.source_state.get_uninitialized())
+            .and_then(|e| e.clone().ok());
+        entries.insert(
+            path_key.into(),
+            CacheEntry {
+                last_access_lifetime: self.lifetime_cnt,
+                last_access: Mutex::new((self.clock)()),
+                mtime,
+                is_file: QueryRef::with_value(true),
+                read_all: QueryRef::with_value(content),
+                source_state: QueryRef::with_context(prev_to_diff),
+            },
+        );
+    }
+
+    /// Restore the cache of a file with content read by another process, e.g.
+    /// persisted across restarts.
+    ///
+    /// Unlike [`Self::seed`], the entry is validated on the next access, where
+    /// the file is stat'ed but not read again unless its mtime has changed.
+    /// An existing entry is kept, returning false.
+    pub fn restore(&mut self, src: &Path, mtime: Time, content: Bytes) -> bool {
+        let entries = self.cache_entries.get_mut();
+        if entries.contains_key(src.as_os_str()) {
+            return false;
+        }
+
+        entries.insert(
+            src.as_os_str().into(),
+            CacheEntry {
+                // The previous lifetime, hence the mtime is checked, while
+                // the entry is retained by the next clears like a used one.
+                last_access_lifetime: self.lifetime_cnt.saturating_sub(1),
+                last_access: Mutex::new((self.clock)()),
+                mtime,
+                is_file: QueryRef::with_value(true),
+                read_all: QueryRef::with_value(content),
+                source_state: QueryRef::with_context(None),
+            },
+        );
+        true
+    }
+
+    /// Read the file again and drop the cache entry if the content differs
+    /// from the cached one, even though the mtime is unchanged.
+    ///
+    /// Entries whose content is not read yet are kept, since they are read
+    /// from the underlying access model on the next access anyway.
+    pub fn revalidate(&mut self, src: &Path) {
+        let path_key = src.as_os_str();
+        let Some(entry) = self.cache_entries.get_mut().get(path_key) else {
+            return;
+        };
+        let Some(cached) = entry.read_all.get_uninitialized() else {
+            return;
+        };
+
+        let cached = cached.as_ref().ok().map(hash128);
+        let current = self
+            .inner
+            .content(src)
+            .ok()
+            .map(|content| hash128(&content));
+        if cached != current {
+            self.cache_entries.get_mut().remove(path_key);
+        }
+    }
+
     /// This is not a common interface for access model, but it is used for vfs
     /// incremental parsing.
+    ///
+    /// Only text files are handled by this method. If the content is not valid
+    /// UTF-8, it returns [`FileError::InvalidUtf8`] without calling `compute`.
+    /// The raw content of binary files (images, fonts, etc.) is still
+    /// available from [`AccessModel::content`], which shares the same cached
+    /// buffer.
     pub fn read_all_diff(
         &self,
         src: &Path,
         compute: impl FnOnce(Option<C>, String) -> FileResult<C>,
     ) -> FileResult<C> {
         self.cache_entry(src, |entry| {
+            let mut cached = true;
             let data = entry.source_state.compute_with_context(|prev_to_diff| {
+                cached = false;
                 let data = entry.read_all.compute(|| self.inner.content(src))?;
-                let text = from_utf8_or_bom(data)?.to_owned();
-                compute(prev_to_diff, text)
-            })?;
+                let Ok(text) = from_utf8_or_bom(data) else {
+                    return Err(FileError::InvalidUtf8);
+                };
+                compute(prev_to_diff, text.to_owned())
+            });
+            self.record_read(cached);
 
-            let t = data.clone();
+            let t = data?.clone();
             Ok(t)
         })
     }
@@ -142,9 +372,13 @@ impl<Inner: AccessModel, C: Clone> AccessModel for CachedAccessModel<Inner, C> {
     fn clear(&mut self) {
         self.lifetime_cnt += 1;
 
+        *self.reads.get_mut() = 0;
+        *self.cached_reads.get_mut() = 0;
+
         let mut path_results = self.cache_entries.write();
         let new_lifetime = self.lifetime_cnt;
         path_results.retain(|_, v| new_lifetime - v.last_access_lifetime <= 30);
+        (self.guarded_images.get_mut()).retain(|_, (lifetime, _)| new_lifetime - *lifetime <= 30);
     }
 
     fn mtime(&self, src: &Path) -> FileResult<Time> {
@@ -164,8 +398,159 @@ impl<Inner: AccessModel, C: Clone> AccessModel for CachedAccessModel<Inner, C> {
 
     fn content(&self, src: &Path) -> FileResult<Bytes> {
         self.cache_entry(src, |entry| {
-            let data = entry.read_all.compute(|| self.inner.content(src));
+            let mut cached = true;
+            let data = entry.read_all.compute(|| {
+                cached = false;
+                self.inner.content(src)
+            });
+            self.record_read(cached);
             Ok(data?.clone())
         })
     }
+
+    fn check_root(&self, root: &Path) -> FileResult<()> {
+        // The result is never cached, since the root may come back any time.
+        self.inner.check_root(root)
+    }
+
+    fn list_files(&self, dir: &Path) -> FileResult<Vec<PathBuf>> {
+        self.inner.list_files(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// Serves the same content for every path.
+    struct BufferAccessModel(Bytes);
+
+    impl AccessModel for BufferAccessModel {
+        type RealPath = std::path::PathBuf;
+
+        fn mtime(&self, _src: &Path) -> FileResult<Time> {
+            Ok(Time::UNIX_EPOCH)
+        }
+
+        fn is_file(&self, _src: &Path) -> FileResult<bool> {
+            Ok(true)
+        }
+
+        fn real_path(&self, src: &Path) -> FileResult<Self::RealPath> {
+            Ok(src.to_owned())
+        }
+
+        fn content(&self, _src: &Path) -> FileResult<Bytes> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_read_binary_through_cache() {
+        let png_header = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\xff\xfe".to_vec();
+        let model =
+            CachedAccessModel::<_, String>::new(BufferAccessModel(Bytes::from(png_header.clone())));
+        let path = Path::new("/logo.png");
+
+        let res = model.read_all_diff(path, |_, text| Ok(text));
+        assert!(matches!(res, Err(FileError::InvalidUtf8)));
+
+        let content = model.content(path).unwrap();
+        assert_eq!(&content[..], png_header.as_slice());
+    }
+
+    /// Serves the same stale content for every path, counting the reads.
+    #[derive(Default)]
+    struct CountingAccessModel {
+        reads: AtomicUsize,
+    }
+
+    impl AccessModel for CountingAccessModel {
+        type RealPath = std::path::PathBuf;
+
+        fn mtime(&self, _src: &Path) -> FileResult<Time> {
+            Ok(Time::UNIX_EPOCH + Duration::from_secs(1))
+        }
+
+        fn is_file(&self, _src: &Path) -> FileResult<bool> {
+            Ok(true)
+        }
+
+        fn real_path(&self, src: &Path) -> FileResult<Self::RealPath> {
+            Ok(src.to_owned())
+        }
+
+        fn content(&self, _src: &Path) -> FileResult<Bytes> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            Ok(Bytes::from_static(b"stale"))
+        }
+    }
+
+    #[test]
+    fn test_seed_from_event() {
+        let mut model = CachedAccessModel::<_, String>::new(CountingAccessModel::default());
+        let path = Path::new("/main.typ");
+        let mtime = Time::UNIX_EPOCH + Duration::from_secs(1);
+        model.seed(path, mtime, Bytes::from_static(b"saved"));
+
+        // The next compilation hits the seeded cache.
+        model.clear();
+        let text = model.read_all_diff(path, |_, text| Ok(text)).unwrap();
+        assert_eq!(text, "saved");
+        assert_eq!(&model.content(path).unwrap()[..], b"saved");
+        assert_eq!(model.inner().reads.load(Ordering::SeqCst), 0);
+
+        let other = Path::new("/other.typ");
+        assert_eq!(&model.content(other).unwrap()[..], b"stale");
+        assert_eq!(model.inner().reads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_restore_after_many_lifetimes() {
+        let mut model = CachedAccessModel::<_, String>::new(CountingAccessModel::default());
+        for _ in 0..40 {
+            model.clear();
+        }
+        let path = Path::new("/main.typ");
+        let mtime = Time::UNIX_EPOCH + Duration::from_secs(1);
+        assert!(model.restore(path, mtime, Bytes::from_static(b"restored")));
+
+        // The restored entry survives the clear of the next compilation.
+        model.clear();
+        assert_eq!(&model.content(path).unwrap()[..], b"restored");
+        assert_eq!(model.inner().reads.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_evict_older_than() {
+        use std::sync::atomic::AtomicU64;
+
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        fn mock_now() -> Time {
+            Time::UNIX_EPOCH + Duration::from_secs(NOW.load(Ordering::SeqCst))
+        }
+
+        let mut model = CachedAccessModel::<_, String>::new(CountingAccessModel::default())
+            .with_clock(mock_now);
+        let (old, recent) = (Path::new("/old.typ"), Path::new("/recent.typ"));
+        model.content(old).unwrap();
+        model.content(recent).unwrap();
+
+        NOW.store(50, Ordering::SeqCst);
+        model.content(recent).unwrap();
+        NOW.store(100, Ordering::SeqCst);
+        model.evict_older_than(Duration::from_secs(60));
+        assert_eq!(model.entry_count(), 1);
+
+        // The evicted file is read again, while the recent one is cached.
+        model.content(recent).unwrap();
+        assert_eq!(model.inner().reads.load(Ordering::SeqCst), 2);
+        model.content(old).unwrap();
+        assert_eq!(model.inner().reads.load(Ordering::SeqCst), 3);
+    }
 }
@@ -11,6 +11,9 @@ pub mod browser;
 #[cfg(feature = "system-compile")]
 pub mod system;
 
+/// Provides archive access model which serves files from an in-memory tar
+/// archive, e.g. a self-contained document bundle.
+pub mod archive;
 /// Provides general cache to file access.
 pub mod cached;
 /// Provides dummy access model.
@@ -25,6 +28,9 @@ pub mod notify;
 /// Provides overlay access model which allows to shadow the underlying access
 /// model with memory contents.
 pub mod overlay;
+/// Provides retry access model which retries the underlying access model on
+/// transient failures.
+pub mod retry;
 /// Provides trace access model which traces the underlying access model.
 pub mod trace;
 
@@ -33,21 +39,32 @@ mod path_interner;
 pub(crate) use path_interner::PathInterner;
 
 use core::fmt;
-use std::{collections::HashMap, ffi::OsStr, hash::Hash, path::Path, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    hash::Hash,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use append_only_vec::AppendOnlyVec;
 use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
 use typst::{
-    diag::{FileError, FileResult},
-    syntax::Source,
+    diag::{eco_format, FileError, FileResult},
+    syntax::{Source, VirtualPath},
 };
 
-use typst_ts_core::{path::PathClean, Bytes, ImmutPath, QueryRef, TypstFileId};
+use typst_ts_core::{hash::hash128, path::PathClean, Bytes, ImmutPath, QueryRef, TypstFileId};
 
-use crate::{parser::reparse, Time};
+use crate::{
+    images::{GuardedImage, ImageLimits},
+    parser::{reparse_with_edits, ReparseStats},
+    Time,
+};
 
 use self::{
-    cached::CachedAccessModel,
+    cached::{CachedAccessModel, ReadStats},
     notify::{FilesystemEvent, NotifyAccessModel},
     overlay::OverlayAccessModel,
 };
@@ -93,10 +110,78 @@ pub trait AccessModel {
 
     /// Return the content of a file entry.
     fn content(&self, src: &Path) -> FileResult<Bytes>;
+
+    /// Check whether the root of a workspace is available as a whole, e.g.
+    /// the removable drive or the remote mount of the workspace is not gone.
+    ///
+    /// Unlike the errors of the files under the root, the error is about the
+    /// root itself. The access models without a root on disk always succeed.
+    fn check_root(&self, _root: &Path) -> FileResult<()> {
+        Ok(())
+    }
+
+    /// List the files under a directory recursively in the order of their
+    /// paths, e.g. to find the entry files of a workspace.
+    ///
+    /// The access models which can't list directories list nothing.
+    fn list_files(&self, _dir: &Path) -> FileResult<Vec<PathBuf>> {
+        Ok(vec![])
+    }
 }
 
 type FileQuery<T> = QueryRef<T, FileError>;
 
+/// The byte-range edits of shadow files, i.e. the replaced ranges and texts.
+type ShadowEdits = HashMap<ImmutPath, Vec<(Range<usize>, String)>>;
+
+/// How the cache of an auxiliary file is invalidated, see
+/// [`Vfs::register_aux_path`].
+///
+/// By default, the vfs trusts the mtime of a file and reuses its cached
+/// content as long as the mtime is unchanged. Auxiliary data files, e.g.
+/// bibliographies or CSV files regenerated by external tools, may be rewritten
+/// within the resolution of the mtime, which then goes unnoticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InvalidationStrategy {
+    /// Hash the content of the file on every filesystem event, and drop the
+    /// cache if it changes.
+    ContentHash,
+    /// Read the file again before each compilation.
+    Reread,
+}
+
+/// Rewrites the text of sources before they are parsed, e.g. to expand macros
+/// or templates, see [`Vfs::set_preprocessor`].
+///
+/// The spans of the parsed sources point into the rewritten text, hence
+/// diagnostics and jumps between the sources and the document would land on
+/// shifted offsets. The jumps are disabled unless the preprocessor declares
+/// that it keeps the offsets by [`Self::preserves_offsets`].
+pub trait SourcePreprocessor: fmt::Debug + Send + Sync {
+    /// Rewrite the content of a source at the path in the workspace.
+    fn process(&self, path: &VirtualPath, content: &str) -> String;
+
+    /// Whether the rewritten text keeps the byte offsets of the original
+    /// text, e.g. by replacing tokens with ones of the same lengths.
+    fn preserves_offsets(&self) -> bool {
+        false
+    }
+}
+
+/// The preprocessor keeping the sources unchanged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityPreprocessor;
+
+impl SourcePreprocessor for IdentityPreprocessor {
+    fn process(&self, _path: &VirtualPath, content: &str) -> String {
+        content.to_owned()
+    }
+
+    fn preserves_offsets(&self) -> bool {
+        true
+    }
+}
+
 /// Holds canonical data for all paths pointing to the same entity.
 #[derive(Debug)]
 pub struct PathSlot {
@@ -151,6 +236,15 @@ pub struct Vfs<M: AccessModel + Sized> {
     /// Whether to reparse the file when it is changed.
     /// Default to `true`.
     pub do_reparse: bool,
+    /// Edits applied to shadow files since they were last parsed.
+    shadow_edits: Mutex<ShadowEdits>,
+    /// The extent of the latest reparse of the edits, see
+    /// [`Self::last_reparse_stats`].
+    last_reparse: Mutex<Option<ReparseStats>>,
+    /// The auxiliary files bypassing the mtime check of the cache.
+    aux_paths: HashMap<ImmutPath, InvalidationStrategy>,
+    /// The preprocessor of the sources, see [`Self::set_preprocessor`].
+    preprocessor: Option<Arc<dyn SourcePreprocessor>>,
 }
 
 impl<M: AccessModel + Sized> fmt::Debug for Vfs<M> {
@@ -161,6 +255,8 @@ impl<M: AccessModel + Sized> fmt::Debug for Vfs<M> {
             .field("src2file_id", &self.src2file_id)
             .field("slots", &self.slots)
             .field("do_reparse", &self.do_reparse)
+            .field("aux_paths", &self.aux_paths)
+            .field("preprocessor", &self.preprocessor)
             .finish()
     }
 }
@@ -196,6 +292,10 @@ impl<M: AccessModel + Sized> Vfs<M> {
             src2file_id: RwLock::new(HashMap::new()),
             path2slot: RwLock::new(HashMap::new()),
             do_reparse: true,
+            shadow_edits: Mutex::new(HashMap::new()),
+            last_reparse: Mutex::new(None),
+            aux_paths: HashMap::new(),
+            preprocessor: None,
         }
     }
 
@@ -219,6 +319,34 @@ impl<M: AccessModel + Sized> Vfs<M> {
             .retain(|_, lifetime| new_lifetime_cnt - *lifetime <= 30);
 
         self.access_model.clear();
+        for (path, strategy) in &self.aux_paths {
+            if *strategy == InvalidationStrategy::Reread {
+                // The snapshot notified by the watcher may be stale as well.
+                self.access_model.invalidate(path);
+                self.access_model.inner_mut().inner_mut().forget(path);
+            }
+        }
+    }
+
+    /// Register an auxiliary file whose cache is invalidated by the given
+    /// strategy instead of its mtime, see [`InvalidationStrategy`].
+    ///
+    /// Registering a path again replaces its strategy.
+    pub fn register_aux_path(&mut self, path: ImmutPath, strategy: InvalidationStrategy) {
+        self.aux_paths.insert(path, strategy);
+    }
+
+    /// Unregister an auxiliary file, so that its cache is invalidated by its
+    /// mtime again. Returns whether the path was registered.
+    pub fn unregister_aux_path(&mut self, path: &Path) -> bool {
+        self.aux_paths.remove(path).is_some()
+    }
+
+    /// Get the registered auxiliary files and their strategies.
+    pub fn aux_paths(&self) -> impl Iterator<Item = (&ImmutPath, InvalidationStrategy)> {
+        self.aux_paths
+            .iter()
+            .map(|(path, strategy)| (path, *strategy))
     }
 
     /// Reset the shadowing files in [`OverlayAccessModel`].
@@ -226,6 +354,7 @@ impl<M: AccessModel + Sized> Vfs<M> {
     /// Note: This function is independent from [`Vfs::reset`].
     pub fn reset_shadow(&mut self) {
         self.access_model.inner().clear_shadow();
+        self.shadow_edits.get_mut().clear();
     }
 
     /// Get paths to all the shadowing files in [`OverlayAccessModel`].
@@ -233,23 +362,155 @@ impl<M: AccessModel + Sized> Vfs<M> {
         self.access_model.inner().file_paths()
     }
 
+    /// Get the content of a shadowing file in the [`OverlayAccessModel`].
+    pub fn shadow_content(&self, path: &Path) -> Option<Bytes> {
+        self.access_model.inner().file(path)
+    }
+
+    /// Get the paths of the shadowing files whose contents differ from those
+    /// of the underlying access model by their hashes, e.g. unsaved edits.
+    ///
+    /// The shadowing files missing in the underlying access model are not
+    /// divergent, e.g. untitled buffers.
+    pub fn shadow_divergences(&self) -> Vec<Arc<Path>> {
+        let overlay = self.access_model.inner();
+        let mut paths: Vec<_> = (overlay.file_paths().into_iter())
+            .filter(|path| {
+                let Some(shadow) = overlay.file(path) else {
+                    return false;
+                };
+                let content = overlay.inner().content(path);
+                content.is_ok_and(|content| hash128(&content) != hash128(&shadow))
+            })
+            .collect();
+        paths.sort();
+        paths
+    }
+
     /// Add a shadowing file to the [`OverlayAccessModel`].
     pub fn map_shadow(&self, path: &Path, content: Bytes) -> FileResult<()> {
         self.access_model.inner().add_file(path.into(), content);
+        self.shadow_edits.lock().remove(path);
 
         Ok(())
     }
 
+    /// Replace a byte range of a shadowing file with the given text, returning
+    /// the length of the edited content in bytes.
+    ///
+    /// The edit is replayed on the parsed source when the file is resolved
+    /// next time, so that the source is reparsed incrementally.
+    pub fn edit_shadow(&self, path: &Path, range: Range<usize>, text: &str) -> FileResult<usize> {
+        let overlay = self.access_model.inner();
+        let content = overlay
+            .file(path)
+            .ok_or_else(|| FileError::NotFound(path.into()))?;
+        let mut content = from_utf8_or_bom(&content)?.to_owned();
+        if content.get(range.clone()).is_none() {
+            return Err(FileError::Other(Some(eco_format!(
+                "invalid edit range {range:?} of {}",
+                path.display()
+            ))));
+        }
+        content.replace_range(range.clone(), text);
+        let len = content.len();
+
+        overlay.add_file(path.into(), Bytes::from(content.into_bytes()));
+        self.shadow_edits
+            .lock()
+            .entry(path.into())
+            .or_default()
+            .push((range, text.to_owned()));
+
+        Ok(len)
+    }
+
+    /// Get how much of the syntax tree is reused by the latest reparse of an
+    /// edited shadow file, see [`Self::edit_shadow`].
+    ///
+    /// The edits are reparsed when the file is resolved next time, e.g. by the
+    /// next compilation.
+    pub fn last_reparse_stats(&self) -> Option<ReparseStats> {
+        *self.last_reparse.lock()
+    }
+
+    /// Deny the access to the files other than the shadow files, e.g. for
+    /// untrusted documents, see [`OverlayAccessModel::set_deny_inner`].
+    ///
+    /// The cached contents of the other files are dropped on enabling, so that
+    /// the files read before aren't served either.
+    pub fn set_safe_mode(&mut self, enabled: bool) {
+        let overlay = self.access_model.inner_mut();
+        overlay.set_deny_inner(enabled);
+        if enabled {
+            let shadows: HashSet<_> = overlay.file_paths().into_iter().collect();
+            self.access_model.retain(|path| shadows.contains(path));
+        }
+    }
+
+    /// Whether the access to the files other than the shadow files is
+    /// denied, see [`Self::set_safe_mode`].
+    pub fn is_safe_mode(&self) -> bool {
+        self.access_model.inner().denies_inner()
+    }
+
     /// Remove a shadowing file from the [`OverlayAccessModel`].
     pub fn remove_shadow(&self, path: &Path) {
         self.access_model.inner().remove_file(path);
+        self.shadow_edits.lock().remove(path);
     }
 
     /// Let the vfs notify the access model with a filesystem event.
     ///
+    /// The contents carried by the event are also seeded into the cache, so
+    /// that the next compilation doesn't read the files again. The auxiliary
+    /// files registered with [`InvalidationStrategy::ContentHash`] are hashed
+    /// again.
+    ///
     /// See [`NotifyAccessModel`] for more information.
     pub fn notify_fs_event(&mut self, event: FilesystemEvent) {
+        let (FilesystemEvent::Update(changeset)
+        | FilesystemEvent::UpstreamUpdate { changeset, .. }) = &event;
+        for (path, snapshot) in &changeset.inserts {
+            // Shadow files take precedence over the file system.
+            if self.access_model.inner().file(path).is_some() {
+                continue;
+            }
+            if let (Ok(mtime), Ok(content)) = (snapshot.mtime(), snapshot.content()) {
+                self.access_model.seed(path, *mtime, content.clone());
+            }
+        }
+
         self.access_model.inner_mut().inner_mut().notify(event);
+
+        for (path, strategy) in &self.aux_paths {
+            if *strategy == InvalidationStrategy::ContentHash {
+                self.access_model.revalidate(path);
+            }
+        }
+    }
+
+    /// Check whether the root of a workspace is available, see
+    /// [`AccessModel::check_root`].
+    pub fn check_root(&self, root: &Path) -> FileResult<()> {
+        self.access_model.check_root(root)
+    }
+
+    /// List the files under a directory, including the shadow files, see
+    /// [`AccessModel::list_files`].
+    pub fn list_files(&self, dir: &Path) -> FileResult<Vec<PathBuf>> {
+        self.access_model.list_files(dir)
+    }
+
+    /// Validate all the files against the file system again, e.g. after the
+    /// root of the workspace is available again.
+    ///
+    /// The snapshots of the notified files are dropped, since the events of
+    /// an unavailable root are unreliable, and the cached files are reused
+    /// only if their mtimes are unchanged.
+    pub fn revalidate(&mut self) {
+        self.access_model.inner_mut().inner_mut().clear_snapshots();
+        self.reset();
     }
 
     /// Set the `do_reparse` flag that indicates whether to reparsing the file
@@ -262,6 +523,21 @@ impl<M: AccessModel + Sized> Vfs<M> {
         self.do_reparse = do_reparse;
     }
 
+    /// Set the preprocessor rewriting the sources before they are parsed, or
+    /// parse the sources as they are by `None`.
+    ///
+    /// The sources are rewritten from their full contents instead of being
+    /// reparsed incrementally. The change applies after the next reset.
+    pub fn set_preprocessor(&mut self, preprocessor: Option<Arc<dyn SourcePreprocessor>>) {
+        self.preprocessor = preprocessor;
+    }
+
+    /// Whether the offsets of the parsed sources are those of the files, i.e.
+    /// whether there is no preprocessor shifting them.
+    pub fn preserves_offsets(&self) -> bool {
+        (self.preprocessor.as_ref()).is_none_or(|preprocessor| preprocessor.preserves_offsets())
+    }
+
     /// Returns the overall memory usage for the stored files.
     pub fn memory_usage(&self) -> usize {
         let mut w = self.slots.len() * core::mem::size_of::<PathSlot>();
@@ -286,6 +562,95 @@ impl<M: AccessModel + Sized> Vfs<M> {
         w
     }
 
+    /// Returns the total size of the file contents cached by the access model.
+    pub fn cached_bytes(&self) -> usize {
+        self.access_model.memory_usage()
+    }
+
+    /// Returns the number of shadowing files and the total size of their
+    /// contents.
+    pub fn shadow_usage(&self) -> (usize, usize) {
+        self.access_model.inner().memory_usage()
+    }
+
+    /// Drop the caches of the files which are not accessed in the current
+    /// lifecycle, i.e. not the dependencies of the latest compilation.
+    pub fn trim(&mut self) {
+        let path2slot = self.path2slot.get_mut();
+        self.access_model
+            .retain(|path| path2slot.contains_key(path.as_os_str()));
+    }
+
+    /// Returns the reads of files in the current lifecycle, and how many of
+    /// them are served by the cache of the access model.
+    pub fn read_stats(&self) -> ReadStats {
+        self.access_model.read_stats()
+    }
+
+    /// Returns the cached contents of the files besides the shadow files,
+    /// along with their mtimes, e.g. to persist them across processes, see
+    /// [`Self::restore_contents`].
+    pub fn cached_contents(&self) -> Vec<(PathBuf, Time, Bytes)> {
+        let overlay = self.access_model.inner();
+        let mut contents = self.access_model.cached_contents();
+        contents.retain(|(path, ..)| overlay.file(path).is_none());
+        contents.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+        contents
+    }
+
+    /// Restore the cache with the contents of [`Self::cached_contents`], e.g.
+    /// persisted by a previous process, returning the number of restored
+    /// files, see [`CachedAccessModel::restore`].
+    ///
+    /// The shadowed files and the cached ones are skipped. Nothing is read
+    /// here, and a file whose mtime has changed since is read again on its
+    /// first access. The restored files are still parsed again, since the
+    /// syntax trees of typst are not serializable, hence only the reads are
+    /// spared.
+    pub fn restore_contents(
+        &mut self,
+        contents: impl IntoIterator<Item = (PathBuf, Time, Bytes)>,
+    ) -> usize {
+        let mut restored = 0;
+        for (path, mtime, content) in contents {
+            if self.access_model.inner().file(&path).is_some() {
+                continue;
+            }
+            if self.access_model.restore(&path, mtime, content) {
+                restored += 1;
+            }
+        }
+        restored
+    }
+
+    /// Returns the number of files cached by the access model.
+    pub fn cached_files(&self) -> usize {
+        self.access_model.entry_count()
+    }
+
+    /// Drop the caches of the given files, except the shadowing ones.
+    ///
+    /// It releases the files which are no longer depended on by the
+    /// compilation, which are read again if they are accessed later.
+    pub fn evict_files(&mut self, paths: &[ImmutPath]) {
+        let overlay = self.access_model.inner();
+        let evicted: HashSet<&Path> = (paths.iter())
+            .map(|path| path.as_ref())
+            .filter(|path| overlay.file(path).is_none())
+            .collect();
+        if evicted.is_empty() {
+            return;
+        }
+
+        self.access_model.retain(|path| !evicted.contains(path));
+    }
+
+    /// Drop the caches of the files which are not accessed within the given
+    /// age, see [`CachedAccessModel::evict_older_than`].
+    pub fn evict_older_than(&mut self, age: std::time::Duration) {
+        self.access_model.evict_older_than(age);
+    }
+
     /// Id of the given path if it exists in the `Vfs` and is not deleted.
     pub fn file_id(&self, path: &Path) -> Option<FileId> {
         let path = path.clean();
@@ -319,6 +684,12 @@ impl<M: AccessModel + Sized> Vfs<M> {
         })
     }
 
+    /// Get the typst file ids of the sources resolved in the current
+    /// lifecycle.
+    pub fn source_ids(&self) -> Vec<TypstFileId> {
+        self.src2file_id.read().keys().copied().collect()
+    }
+
     /// Get all the files that are currently in the VFS. This function is
     /// similar to [`Vfs::iter_dependencies`], but it is for trait objects.
     pub fn iter_dependencies_dyn<'a>(
@@ -354,12 +725,31 @@ impl<M: AccessModel + Sized> Vfs<M> {
         Ok(buffer.clone())
     }
 
+    /// Get an image guarded by the limits, which is cached across
+    /// compilations, see [`cached::CachedAccessModel::guard_image`].
+    pub fn guard_image(
+        &self,
+        content: &Bytes,
+        limits: &ImageLimits,
+        guard: impl FnOnce() -> Option<GuardedImage>,
+    ) -> Option<GuardedImage> {
+        self.access_model.guard_image(content, limits, guard)
+    }
+
     /// Get source content by path and assign the source with a given typst
     /// global file id.
     ///
     /// See `Vfs::resolve_with_f` for more information.
     pub fn resolve(&self, path: &Path, source_id: TypstFileId) -> FileResult<Source> {
         self.resolve_with_f(path, source_id, || {
+            // Rewrite the full content, since the edits are of the file.
+            if let Some(preprocessor) = &self.preprocessor {
+                self.shadow_edits.lock().remove(path);
+                let content = self.read(path)?;
+                let content = preprocessor.process(source_id.vpath(), from_utf8_or_bom(&content)?);
+                return Ok(Source::new(source_id, content));
+            }
+
             // Return a new source if we don't have a reparse feature
             if !self.do_reparse {
                 let content = self.read(path)?;
@@ -371,9 +761,15 @@ impl<M: AccessModel + Sized> Vfs<M> {
 
             // otherwise reparse the source
             if self.access_model.is_file(path)? {
-                Ok(self
-                    .access_model
-                    .read_all_diff(path, |x, y| reparse(source_id, x, y))?)
+                Ok(self.access_model.read_all_diff(path, |x, y| {
+                    let edits = self.shadow_edits.lock().remove(path);
+                    let (source, stats) =
+                        reparse_with_edits(source_id, x, y, edits.as_deref().unwrap_or_default())?;
+                    if edits.is_some() {
+                        *self.last_reparse.lock() = Some(stats);
+                    }
+                    Ok(source)
+                })?)
             } else {
                 Err(FileError::IsDirectory)
             }
@@ -485,4 +881,214 @@ mod tests {
         is_send::<super::Vfs<super::dummy::DummyAccessModel>>();
         is_sync::<super::Vfs<super::dummy::DummyAccessModel>>();
     }
+
+    #[test]
+    fn test_vfs_memory_usage() {
+        use std::path::Path;
+
+        use typst_ts_core::Bytes;
+
+        let mut vfs = super::Vfs::new(super::dummy::DummyAccessModel);
+        let path = Path::new("/large.typ");
+        let content = Bytes::from(vec![b'a'; 1 << 20]);
+        assert_eq!(vfs.shadow_usage(), (0, 0));
+
+        vfs.map_shadow(path, content).unwrap();
+        assert_eq!(vfs.shadow_usage(), (1, 1 << 20));
+        assert_eq!(vfs.file(path).unwrap().len(), 1 << 20);
+        assert_eq!(vfs.cached_bytes(), 1 << 20);
+
+        // The file is still a dependency of the current lifecycle.
+        vfs.trim();
+        assert_eq!(vfs.cached_bytes(), 1 << 20);
+
+        vfs.remove_shadow(path);
+        assert_eq!(vfs.shadow_usage(), (0, 0));
+        vfs.reset();
+        vfs.trim();
+        assert_eq!(vfs.cached_bytes(), 0);
+    }
+
+    #[test]
+    fn test_aux_path_invalidation() {
+        use std::{
+            path::{Path, PathBuf},
+            sync::Arc,
+        };
+
+        use parking_lot::Mutex;
+        use typst::diag::FileResult;
+        use typst_ts_core::Bytes;
+
+        use super::{
+            notify::{FileChangeSet, FileSnapshot, FilesystemEvent},
+            AccessModel, InvalidationStrategy, Vfs,
+        };
+        use crate::Time;
+
+        /// Serves a rewritable content, which never changes its mtime.
+        struct SameMtimeAccessModel(Arc<Mutex<Bytes>>);
+
+        impl AccessModel for SameMtimeAccessModel {
+            type RealPath = PathBuf;
+
+            fn mtime(&self, _src: &Path) -> FileResult<Time> {
+                Ok(Time::UNIX_EPOCH)
+            }
+
+            fn is_file(&self, _src: &Path) -> FileResult<bool> {
+                Ok(true)
+            }
+
+            fn real_path(&self, src: &Path) -> FileResult<Self::RealPath> {
+                Ok(src.to_owned())
+            }
+
+            fn content(&self, _src: &Path) -> FileResult<Bytes> {
+                Ok(self.0.lock().clone())
+            }
+        }
+
+        let content = Arc::new(Mutex::new(Bytes::from_static(b"a,b\n1,2")));
+        let mut vfs = Vfs::new(SameMtimeAccessModel(content.clone()));
+        let path: &Path = Path::new("/data.csv");
+        let rewrite = |vfs: &mut Vfs<SameMtimeAccessModel>, data: &'static [u8]| {
+            *content.lock() = Bytes::from_static(data);
+            vfs.notify_fs_event(FilesystemEvent::Update(FileChangeSet::new_inserts(vec![])));
+            vfs.reset();
+            vfs.file(path).unwrap()
+        };
+        assert_eq!(&vfs.file(path).unwrap()[..], b"a,b\n1,2");
+
+        // The mtime shortcut misses the change.
+        assert_eq!(&rewrite(&mut vfs, b"a,b\n3,4")[..], b"a,b\n1,2");
+
+        vfs.register_aux_path(path.into(), InvalidationStrategy::ContentHash);
+        assert_eq!(&rewrite(&mut vfs, b"a,b\n5,6")[..], b"a,b\n5,6");
+
+        vfs.register_aux_path(path.into(), InvalidationStrategy::Reread);
+        *content.lock() = Bytes::from_static(b"a,b\n7,8");
+        vfs.reset();
+        assert_eq!(&vfs.file(path).unwrap()[..], b"a,b\n7,8");
+
+        // The snapshot notified by the watcher is read again as well.
+        let snapshot = FileSnapshot::from(Ok((Time::UNIX_EPOCH, content.lock().clone())));
+        let inserts = FileChangeSet::new_inserts(vec![(path.into(), snapshot)]);
+        vfs.notify_fs_event(FilesystemEvent::Update(inserts));
+        *content.lock() = Bytes::from_static(b"a,b\n8,9");
+        vfs.reset();
+        assert_eq!(&vfs.file(path).unwrap()[..], b"a,b\n8,9");
+
+        assert!(vfs.unregister_aux_path(path));
+        assert_eq!(&rewrite(&mut vfs, b"a,b\n9,0")[..], b"a,b\n8,9");
+    }
+
+    #[cfg(feature = "system-compile")]
+    #[test]
+    fn test_edit_shadow() {
+        use typst::{eval::Tracer, foundations::Bytes};
+
+        use crate::{
+            fixture::TestWorkspace,
+            service::{CompileEnv, EnvWorld},
+            ShadowApi,
+        };
+
+        let ws = TestWorkspace::new();
+        let main = ws.path("main.typ");
+        let mut world = ws.world();
+        world
+            .map_shadow(&main, Bytes::from_static(b"#let a = 1\n#a"))
+            .unwrap();
+        world.prepare_env(&mut CompileEnv::default()).unwrap();
+        assert!(typst::compile(&world, &mut Tracer::new()).is_ok());
+
+        // Replace the reference to `a` with an unknown variable.
+        world.edit_shadow(&main, 12..13, "b").unwrap();
+        assert!(world.edit_shadow(&main, 12..20, "b").is_err());
+        world.reset();
+
+        assert_eq!(&world.vfs.file(&main).unwrap()[..], b"#let a = 1\n#b");
+        let source = typst::World::main(&world);
+        assert_eq!(source.text(), "#let a = 1\n#b");
+        let errors = typst::compile(&world, &mut Tracer::new()).unwrap_err();
+        assert_eq!(errors[0].message, "unknown variable: b");
+    }
+
+    #[cfg(feature = "system-compile")]
+    #[test]
+    fn test_edit_shadow_reparse() {
+        use typst::{foundations::Bytes, syntax::Source};
+
+        use crate::{
+            fixture::TestWorkspace,
+            service::{CompileEnv, EnvWorld},
+            ShadowApi,
+        };
+
+        let ws = TestWorkspace::new();
+        let main = ws.path("main.typ");
+        let mut world = ws.world();
+
+        let text: String = (0..2000)
+            .map(|i| format!("= Section {i}\n#let v{i} = {i} * 2\nSome *strong* $x^{i}$.\n\n"))
+            .collect();
+        world
+            .map_shadow(&main, Bytes::from(text.clone().into_bytes()))
+            .unwrap();
+        world.prepare_env(&mut CompileEnv::default()).unwrap();
+        let main_id = typst::World::main(&world).id();
+
+        // Type a character in the middle of the file.
+        let offset = text.len() / 2;
+        let offset = (offset..).find(|off| text.is_char_boundary(*off)).unwrap();
+        let len = world.edit_shadow(&main, offset..offset, "x").unwrap();
+        assert_eq!(len, text.len() + 1);
+        world.reset();
+
+        // The incrementally reparsed tree agrees with a full parse, except for
+        // the numbers of the spans.
+        let incremental = typst::World::main(&world);
+        let full = Source::new(main_id, incremental.text().to_owned());
+        assert_eq!(incremental.text(), full.text());
+        assert!(incremental.root().spanless_eq(full.root()));
+    }
+
+    #[cfg(feature = "system-compile")]
+    #[test]
+    fn test_last_reparse_stats() {
+        use typst::foundations::Bytes;
+
+        use crate::{
+            fixture::TestWorkspace,
+            service::{CompileEnv, EnvWorld},
+            ShadowApi,
+        };
+
+        let ws = TestWorkspace::new();
+        let main = ws.path("main.typ");
+        let mut world = ws.world();
+
+        let text: String = (0..2000)
+            .map(|i| format!("= Section {i}\nSome *strong* text.\n\n"))
+            .collect();
+        world
+            .map_shadow(&main, Bytes::from(text.clone().into_bytes()))
+            .unwrap();
+        world.prepare_env(&mut CompileEnv::default()).unwrap();
+        typst::World::main(&world);
+        assert_eq!(world.vfs.last_reparse_stats(), None);
+
+        // Type a character in a word in the middle of the file.
+        let offset = text.len() / 2;
+        let offset = offset + text[offset..].find("strong").unwrap() + 1;
+        world.edit_shadow(&main, offset..offset, "x").unwrap();
+        world.reset();
+        typst::World::main(&world);
+
+        let stats = world.vfs.last_reparse_stats().unwrap();
+        assert_eq!(stats.reused_bytes + stats.reparsed_bytes, text.len() + 1);
+        assert!(stats.reparsed_bytes > 0);
+        assert!(stats.reparsed_bytes * 100 < text.len(), "{stats:?}");
+    }
 }
@@ -1,5 +1,9 @@
 use core::fmt;
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
 use typst::diag::{FileError, FileResult};
 use typst_ts_core::{Bytes, ImmutPath};
@@ -120,6 +124,38 @@ impl FileChangeSet {
             self.inserts.extend(v);
         }
     }
+
+    /// Merge a later changeset into this one, where the later change of a
+    /// file wins
+    pub fn merge(&mut self, later: FileChangeSet) {
+        for path in later.removes {
+            self.inserts.retain(|(p, _)| *p != path);
+            if !self.removes.contains(&path) {
+                self.removes.push(path);
+            }
+        }
+        for (path, snapshot) in later.inserts {
+            self.inserts.retain(|(p, _)| *p != path);
+            self.removes.retain(|p| *p != path);
+            self.inserts.push((path, snapshot));
+        }
+    }
+}
+
+/// A byte-range edit of a memory file
+#[derive(Debug, Clone)]
+pub struct FileEdit {
+    /// The memory file to edit
+    pub path: ImmutPath,
+    /// The byte range to replace
+    pub range: Range<usize>,
+    /// The text replacing the range
+    pub text: String,
+    /// The full content after the edit, if known
+    ///
+    /// It is mapped instead if the edit doesn't apply cleanly, i.e. the range
+    /// is out of bounds or the edited content has a different length.
+    pub fallback: Option<Bytes>,
 }
 
 /// A memory event that is notified by some external source
@@ -138,6 +174,32 @@ pub enum MemoryEvent {
     Sync(FileChangeSet),
     /// Update according to the given changeset
     Update(FileChangeSet),
+    /// Patch the content of an existing memory file in place
+    ///
+    /// Unlike updating the entire file, the edit is replayed on the parsed
+    /// source, see [`crate::ShadowApi::edit_shadow`].
+    Edit(FileEdit),
+}
+
+impl MemoryEvent {
+    /// Coalesce consecutive events into fewer ones with the same effect.
+    ///
+    /// An update is merged into the preceding update or sync, where the last
+    /// content of a file wins. Edits are kept in place, since they apply to
+    /// the content preceding them.
+    pub fn coalesce(events: Vec<MemoryEvent>) -> Vec<MemoryEvent> {
+        let mut coalesced: Vec<MemoryEvent> = Vec::with_capacity(events.len());
+        for event in events {
+            match (coalesced.last_mut(), event) {
+                (
+                    Some(MemoryEvent::Update(prev) | MemoryEvent::Sync(prev)),
+                    MemoryEvent::Update(event),
+                ) => prev.merge(event),
+                (_, event) => coalesced.push(event),
+            }
+        }
+        coalesced
+    }
 }
 
 /// A upstream update event that is notified by some external source.
@@ -167,13 +229,31 @@ pub enum FilesystemEvent {
     },
 }
 
+impl FilesystemEvent {
+    /// The changes of the files in the event.
+    pub fn changeset(&self) -> &FileChangeSet {
+        match self {
+            FilesystemEvent::Update(changeset)
+            | FilesystemEvent::UpstreamUpdate { changeset, .. } => changeset,
+        }
+    }
+}
+
 /// A message that is sent to some file watcher
 #[derive(Debug)]
 pub enum NotifyMessage {
     /// Oettle the watching
     Settle,
-    /// Overrides all dependencies
+    /// Overrides all dependencies, which are sorted by their paths
     SyncDependency(Vec<ImmutPath>),
+    /// Changes the dependencies since the last [`Self::SyncDependency`] or
+    /// [`Self::UpdateDependency`], which are sorted by their paths
+    UpdateDependency {
+        /// The paths newly depended on
+        inserts: Vec<ImmutPath>,
+        /// The paths no longer depended on
+        removes: Vec<ImmutPath>,
+    },
     /// upstream invalidation This is very important to make some atomic changes
     ///
     /// Example:
@@ -236,6 +316,18 @@ impl<M: AccessModel> NotifyAccessModel<M> {
             }
         }
     }
+
+    /// Drop the snapshots of the notified files, which are read from the
+    /// fallback access model until they are notified again.
+    pub fn clear_snapshots(&mut self) {
+        self.files.clear();
+    }
+
+    /// Drop the snapshot of a notified file, which is read from the fallback
+    /// access model until it is notified again.
+    pub fn forget(&mut self, src: &Path) {
+        self.files.remove(src);
+    }
 }
 
 impl<M: AccessModel> AccessModel for NotifyAccessModel<M> {
@@ -272,6 +364,14 @@ impl<M: AccessModel> AccessModel for NotifyAccessModel<M> {
 
         self.inner.content(src)
     }
+
+    fn check_root(&self, root: &Path) -> FileResult<()> {
+        self.inner.check_root(root)
+    }
+
+    fn list_files(&self, dir: &Path) -> FileResult<Vec<PathBuf>> {
+        self.inner.list_files(dir)
+    }
 }
 
 #[derive(Debug)]
@@ -1,8 +1,11 @@
 use std::sync::Arc;
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use parking_lot::RwLock;
-use typst::diag::FileResult;
+use typst::diag::{eco_format, FileError, FileResult};
 
 use typst_ts_core::Bytes;
 
@@ -21,6 +24,9 @@ struct OverlayFileMeta {
 #[derive(Default, Debug)]
 pub struct OverlayAccessModel<M: AccessModel> {
     files: RwLock<HashMap<Arc<Path>, OverlayFileMeta>>,
+    /// Whether to deny the access to the underlying access model, see
+    /// [`Self::set_deny_inner`].
+    deny_inner: bool,
     /// The underlying access model
     pub inner: M,
 }
@@ -30,10 +36,33 @@ impl<M: AccessModel> OverlayAccessModel<M> {
     pub fn new(inner: M) -> Self {
         Self {
             files: RwLock::new(HashMap::new()),
+            deny_inner: false,
             inner,
         }
     }
 
+    /// Deny the access to the underlying access model, so that only the
+    /// shadowed files are accessible, e.g. for untrusted documents.
+    pub fn set_deny_inner(&mut self, deny: bool) {
+        self.deny_inner = deny;
+    }
+
+    /// Whether the access to the underlying access model is denied
+    pub fn denies_inner(&self) -> bool {
+        self.deny_inner
+    }
+
+    /// Get the underlying access model unless the access is denied
+    fn inner_for(&self, src: &Path) -> FileResult<&M> {
+        if self.deny_inner {
+            return Err(FileError::Other(Some(eco_format!(
+                "cannot access {} in safe mode, which only allows shadow files",
+                src.display()
+            ))));
+        }
+        Ok(&self.inner)
+    }
+
     /// Get the inner access model
     pub fn inner(&self) -> &M {
         &self.inner
@@ -54,6 +83,18 @@ impl<M: AccessModel> OverlayAccessModel<M> {
         self.files.read().keys().cloned().collect()
     }
 
+    /// Get the number of shadow files and the total size of their contents
+    pub fn memory_usage(&self) -> (usize, usize) {
+        let files = self.files.read();
+        let bytes = files.values().map(|meta| meta.content.len()).sum();
+        (files.len(), bytes)
+    }
+
+    /// Get the content of a shadow file
+    pub fn file(&self, path: &Path) -> Option<Bytes> {
+        self.files.read().get(path).map(|meta| meta.content.clone())
+    }
+
     /// Add a shadow file to the [`OverlayAccessModel`]
     pub fn add_file(&self, path: Arc<Path>, content: Bytes) {
         // we change mt every time, since content almost changes every time
@@ -98,7 +139,7 @@ impl<M: AccessModel> AccessModel for OverlayAccessModel<M> {
             return Ok(meta.mt);
         }
 
-        self.inner.mtime(src)
+        self.inner_for(src)?.mtime(src)
     }
 
     fn is_file(&self, src: &Path) -> FileResult<bool> {
@@ -106,7 +147,7 @@ impl<M: AccessModel> AccessModel for OverlayAccessModel<M> {
             return Ok(true);
         }
 
-        self.inner.is_file(src)
+        self.inner_for(src)?.is_file(src)
     }
 
     fn real_path(&self, src: &Path) -> FileResult<Self::RealPath> {
@@ -114,7 +155,7 @@ impl<M: AccessModel> AccessModel for OverlayAccessModel<M> {
             return Ok(src.into());
         }
 
-        self.inner.real_path(src)
+        self.inner_for(src)?.real_path(src)
     }
 
     fn content(&self, src: &Path) -> FileResult<Bytes> {
@@ -122,6 +163,30 @@ impl<M: AccessModel> AccessModel for OverlayAccessModel<M> {
             return Ok(meta.content.clone());
         }
 
-        self.inner.content(src)
+        self.inner_for(src)?.content(src)
+    }
+
+    fn check_root(&self, root: &Path) -> FileResult<()> {
+        // The root on disk is irrelevant to the shadow files.
+        if self.deny_inner {
+            return Ok(());
+        }
+        self.inner.check_root(root)
+    }
+
+    fn list_files(&self, dir: &Path) -> FileResult<Vec<PathBuf>> {
+        let mut files = match self.deny_inner {
+            true => vec![],
+            false => self.inner.list_files(dir)?,
+        };
+        let shadows = self.files.read();
+        files.extend(
+            (shadows.keys())
+                .filter(|path| path.starts_with(dir))
+                .map(|path| path.to_path_buf()),
+        );
+        files.sort();
+        files.dedup();
+        Ok(files)
     }
 }
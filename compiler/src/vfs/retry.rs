@@ -0,0 +1,209 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use typst::diag::{FileError, FileResult, PackageError};
+
+use typst_ts_core::Bytes;
+
+use crate::Time;
+
+use super::AccessModel;
+
+/// Provides retry access model which retries the reads of the underlying
+/// access model on transient failures.
+///
+/// Network-backed access models may fail occasionally, e.g. on a timeout. The
+/// [`AccessModel::mtime`] and [`AccessModel::content`] reads are retried with
+/// an exponential backoff, while the errors which won't go away by retrying,
+/// e.g. [`FileError::NotFound`], are returned immediately.
+#[derive(Debug)]
+pub struct RetryingAccessModel<M: AccessModel + Sized> {
+    inner: M,
+    attempts: usize,
+    backoff: Duration,
+    is_transient: fn(&FileError) -> bool,
+}
+
+impl<M: AccessModel + Sized> RetryingAccessModel<M> {
+    /// The default number of attempts of a read, including the first one.
+    pub const DEFAULT_ATTEMPTS: usize = 3;
+    /// The default delay before the first retry, which is doubled for each
+    /// further retry.
+    pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(50);
+
+    /// Create a new [`RetryingAccessModel`] with the given inner access model
+    pub fn new(inner: M) -> Self {
+        Self {
+            inner,
+            attempts: Self::DEFAULT_ATTEMPTS,
+            backoff: Self::DEFAULT_BACKOFF,
+            is_transient,
+        }
+    }
+
+    /// Set the number of attempts of a read, including the first one.
+    pub fn with_attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set the classification of the errors to retry, which defaults to
+    /// [`is_transient`].
+    pub fn with_transient(mut self, is_transient: fn(&FileError) -> bool) -> Self {
+        self.is_transient = is_transient;
+        self
+    }
+
+    /// Get the inner access model
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    /// Get the mutable reference to the inner access model
+    pub fn inner_mut(&mut self) -> &mut M {
+        &mut self.inner
+    }
+
+    fn retry<T>(&self, mut read: impl FnMut() -> FileResult<T>) -> FileResult<T> {
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match read() {
+                Err(err) if attempt < self.attempts && (self.is_transient)(&err) => {
+                    sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Whether an error may go away by retrying, i.e. an unclassified IO error
+/// or a network failure of a package download.
+pub fn is_transient(err: &FileError) -> bool {
+    matches!(
+        err,
+        FileError::Other(..) | FileError::Package(PackageError::NetworkFailed(..))
+    )
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn sleep(duration: Duration) {
+    if !duration.is_zero() {
+        std::thread::sleep(duration);
+    }
+}
+
+/// The browser can't block the thread, hence retries immediately.
+#[cfg(target_arch = "wasm32")]
+fn sleep(_duration: Duration) {}
+
+impl<M: AccessModel + Sized> AccessModel for RetryingAccessModel<M> {
+    type RealPath = M::RealPath;
+
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    fn mtime(&self, src: &Path) -> FileResult<Time> {
+        self.retry(|| self.inner.mtime(src))
+    }
+
+    fn is_file(&self, src: &Path) -> FileResult<bool> {
+        self.inner.is_file(src)
+    }
+
+    fn real_path(&self, src: &Path) -> FileResult<Self::RealPath> {
+        self.inner.real_path(src)
+    }
+
+    fn content(&self, src: &Path) -> FileResult<Bytes> {
+        self.retry(|| self.inner.content(src))
+    }
+
+    fn check_root(&self, root: &Path) -> FileResult<()> {
+        self.retry(|| self.inner.check_root(root))
+    }
+
+    fn list_files(&self, dir: &Path) -> FileResult<Vec<PathBuf>> {
+        self.retry(|| self.inner.list_files(dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    /// Fails the first `failures` reads with the given error.
+    struct FlakyAccessModel {
+        failures: usize,
+        error: FileError,
+        reads: AtomicUsize,
+    }
+
+    impl AccessModel for FlakyAccessModel {
+        type RealPath = PathBuf;
+
+        fn mtime(&self, _src: &Path) -> FileResult<Time> {
+            Ok(Time::UNIX_EPOCH)
+        }
+
+        fn is_file(&self, _src: &Path) -> FileResult<bool> {
+            Ok(true)
+        }
+
+        fn real_path(&self, src: &Path) -> FileResult<Self::RealPath> {
+            Ok(src.to_owned())
+        }
+
+        fn content(&self, _src: &Path) -> FileResult<Bytes> {
+            if self.reads.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(self.error.clone());
+            }
+            Ok(Bytes::from_static(b"= Hello"))
+        }
+    }
+
+    fn flaky(failures: usize, error: FileError) -> RetryingAccessModel<FlakyAccessModel> {
+        let inner = FlakyAccessModel {
+            failures,
+            error,
+            reads: AtomicUsize::new(0),
+        };
+        RetryingAccessModel::new(inner).with_backoff(Duration::ZERO)
+    }
+
+    #[test]
+    fn test_retry_transient() {
+        let src = Path::new("/remote/main.typ");
+        let network = FileError::Package(PackageError::NetworkFailed(None));
+
+        let model = flaky(2, network.clone());
+        assert_eq!(model.content(src), Ok(Bytes::from_static(b"= Hello")));
+        assert_eq!(model.inner().reads.load(Ordering::SeqCst), 3);
+
+        // The budget is exhausted before the read succeeds.
+        let model = flaky(2, network.clone()).with_attempts(2);
+        assert_eq!(model.content(src), Err(network));
+        assert_eq!(model.inner().reads.load(Ordering::SeqCst), 2);
+
+        let model = flaky(2, FileError::NotFound(src.to_owned()));
+        assert_eq!(model.content(src), Err(FileError::NotFound(src.to_owned())));
+        assert_eq!(model.inner().reads.load(Ordering::SeqCst), 1);
+    }
+}
@@ -52,6 +52,31 @@ impl AccessModel for SystemAccessModel {
             .map_err(f)?;
         Ok(buf.into())
     }
+
+    fn check_root(&self, root: &Path) -> FileResult<()> {
+        // Listing the root also fails if it exists but is unreadable, e.g. a
+        // disconnected remote mount.
+        std::fs::read_dir(root)
+            .map(|_| ())
+            .map_err(|e| FileError::from_io(e, root))
+    }
+
+    fn list_files(&self, dir: &Path) -> FileResult<Vec<PathBuf>> {
+        // A directory which doesn't exist yet has no files, e.g. a workspace
+        // of shadow files only.
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut files = vec![];
+        for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+            let entry = entry.map_err(|e| FileError::from_io(e.into(), dir))?;
+            if entry.file_type().is_file() {
+                files.push(entry.into_path());
+            }
+        }
+        Ok(files)
+    }
 }
 
 /// Lazily opened file entry corresponding to a file in the local file system.
@@ -1,4 +1,10 @@
-use std::{path::Path, sync::atomic::AtomicU64};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use typst::diag::FileResult;
 
@@ -6,6 +12,55 @@ use typst_ts_core::Bytes;
 
 use super::{cached::CachedAccessModel, AccessModel};
 
+/// The kinds of accesses traced by [`TraceAccessModel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Mtime,
+    IsFile,
+    RealPath,
+    Content,
+    ReadAllDiff,
+}
+
+/// The number and the duration of accesses traced by [`TraceAccessModel`].
+///
+/// It is shared with the access model, so that it can be read after the
+/// access model is moved into a [`super::Vfs`].
+#[derive(Debug, Default)]
+pub struct TraceStats {
+    calls: [AtomicU64; 5],
+    nanos: [AtomicU64; 5],
+}
+
+impl TraceStats {
+    fn record(&self, kind: AccessKind, elapsed: instant::Duration) {
+        self.calls[kind as usize].fetch_add(1, Ordering::Relaxed);
+        (self.nanos[kind as usize]).fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Get the number of accesses of a kind.
+    pub fn calls(&self, kind: AccessKind) -> u64 {
+        self.calls[kind as usize].load(Ordering::Relaxed)
+    }
+
+    /// Get the total number of accesses.
+    pub fn total_calls(&self) -> u64 {
+        self.calls.iter().map(|c| c.load(Ordering::Relaxed)).sum()
+    }
+
+    /// Get the time spent in accesses of a kind.
+    pub fn elapsed(&self, kind: AccessKind) -> instant::Duration {
+        instant::Duration::from_nanos(self.nanos[kind as usize].load(Ordering::Relaxed))
+    }
+
+    /// Reset all the counters to zero.
+    pub fn reset(&self) {
+        for counter in self.calls.iter().chain(self.nanos.iter()) {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
 /// Provides trace access model which traces the underlying access model.
 ///
 /// It simply wraps the underlying access model and prints all the access to the
@@ -13,7 +68,26 @@ use super::{cached::CachedAccessModel, AccessModel};
 #[derive(Debug)]
 pub struct TraceAccessModel<M: AccessModel + Sized> {
     inner: M,
-    trace: [AtomicU64; 6],
+    stats: Arc<TraceStats>,
+    /// Whether to print the accesses.
+    verbose: bool,
+}
+
+impl<M: AccessModel + Sized> TraceAccessModel<M> {
+    /// Create a [`TraceAccessModel`] which only counts the accesses without
+    /// printing them, see [`Self::stats`].
+    pub fn counting(inner: M) -> Self {
+        Self {
+            inner,
+            stats: Default::default(),
+            verbose: false,
+        }
+    }
+
+    /// Get the statistics of the accesses.
+    pub fn stats(&self) -> Arc<TraceStats> {
+        self.stats.clone()
+    }
 }
 
 impl<M: AccessModel + Sized, C: Clone> TraceAccessModel<CachedAccessModel<M, C>> {
@@ -21,7 +95,8 @@ impl<M: AccessModel + Sized, C: Clone> TraceAccessModel<CachedAccessModel<M, C>>
     pub fn new(inner: CachedAccessModel<M, C>) -> Self {
         Self {
             inner,
-            trace: Default::default(),
+            stats: Default::default(),
+            verbose: true,
         }
     }
 
@@ -45,11 +120,10 @@ impl<M: AccessModel + Sized, C: Clone> TraceAccessModel<CachedAccessModel<M, C>>
         let instant = instant::Instant::now();
         let res = self.inner.read_all_diff(src, compute);
         let elapsed = instant.elapsed();
-        self.trace[4].fetch_add(
-            elapsed.as_nanos() as u64,
-            std::sync::atomic::Ordering::Relaxed,
-        );
-        crate::utils::console_log!("read_all_diff: {:?} {:?}", src, elapsed);
+        self.stats.record(AccessKind::ReadAllDiff, elapsed);
+        if self.verbose {
+            crate::utils::console_log!("read_all_diff: {:?} {:?}", src, elapsed);
+        }
         res
     }
 }
@@ -63,12 +137,10 @@ impl<M: AccessModel + Sized> AccessModel for TraceAccessModel<M> {
         let instant = instant::Instant::now();
         let res = self.inner.mtime(src);
         let elapsed = instant.elapsed();
-        // self.trace[0] += elapsed.as_nanos() as u64;
-        self.trace[0].fetch_add(
-            elapsed.as_nanos() as u64,
-            std::sync::atomic::Ordering::Relaxed,
-        );
-        crate::utils::console_log!("mtime: {:?} {:?} => {:?}", src, elapsed, res);
+        self.stats.record(AccessKind::Mtime, elapsed);
+        if self.verbose {
+            crate::utils::console_log!("mtime: {:?} {:?} => {:?}", src, elapsed, res);
+        }
         res
     }
 
@@ -76,11 +148,10 @@ impl<M: AccessModel + Sized> AccessModel for TraceAccessModel<M> {
         let instant = instant::Instant::now();
         let res = self.inner.is_file(src);
         let elapsed = instant.elapsed();
-        self.trace[1].fetch_add(
-            elapsed.as_nanos() as u64,
-            std::sync::atomic::Ordering::Relaxed,
-        );
-        crate::utils::console_log!("is_file: {:?} {:?}", src, elapsed);
+        self.stats.record(AccessKind::IsFile, elapsed);
+        if self.verbose {
+            crate::utils::console_log!("is_file: {:?} {:?}", src, elapsed);
+        }
         res
     }
 
@@ -88,11 +159,10 @@ impl<M: AccessModel + Sized> AccessModel for TraceAccessModel<M> {
         let instant = instant::Instant::now();
         let res = self.inner.real_path(src);
         let elapsed = instant.elapsed();
-        self.trace[2].fetch_add(
-            elapsed.as_nanos() as u64,
-            std::sync::atomic::Ordering::Relaxed,
-        );
-        crate::utils::console_log!("real_path: {:?} {:?}", src, elapsed);
+        self.stats.record(AccessKind::RealPath, elapsed);
+        if self.verbose {
+            crate::utils::console_log!("real_path: {:?} {:?}", src, elapsed);
+        }
         res
     }
 
@@ -100,13 +170,20 @@ impl<M: AccessModel + Sized> AccessModel for TraceAccessModel<M> {
         let instant = instant::Instant::now();
         let res = self.inner.content(src);
         let elapsed = instant.elapsed();
-        self.trace[3].fetch_add(
-            elapsed.as_nanos() as u64,
-            std::sync::atomic::Ordering::Relaxed,
-        );
-        crate::utils::console_log!("read_all: {:?} {:?}", src, elapsed);
+        self.stats.record(AccessKind::Content, elapsed);
+        if self.verbose {
+            crate::utils::console_log!("read_all: {:?} {:?}", src, elapsed);
+        }
         res
     }
 
+    fn check_root(&self, root: &Path) -> FileResult<()> {
+        self.inner.check_root(root)
+    }
+
+    fn list_files(&self, dir: &Path) -> FileResult<Vec<PathBuf>> {
+        self.inner.list_files(dir)
+    }
+
     type RealPath = M::RealPath;
 }
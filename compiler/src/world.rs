@@ -1,17 +1,21 @@
 use std::{
-    ops::Deref,
+    borrow::Cow,
+    collections::HashMap,
+    ops::{Deref, Range},
     path::{Path, PathBuf},
     sync::Arc,
 };
 
-use chrono::{DateTime, Datelike, Local};
+use chrono::{DateTime, Datelike, FixedOffset, Local, Utc};
 use comemo::Prehashed;
 use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use typst::{
     diag::{eco_format, At, EcoString, FileError, FileResult, Hint, SourceResult},
+    eval::Tracer,
     foundations::{Datetime, Dict},
-    syntax::{Source, Span, VirtualPath},
+    syntax::{package::PackageVersion, Source, Span, VirtualPath},
     text::{Font, FontBook},
     Library, World,
 };
@@ -19,22 +23,40 @@ use typst::{
 use typst_ts_core::{
     config::compiler::{EntryState, DETACHED_ENTRY},
     font::FontProfile,
+    hash::hash128,
     package::PackageSpec,
+    path::PathClean,
+    vector::stats::ImageDecision,
     Bytes, FontResolver, ImmutPath, TypstFileId as FileId,
 };
 
 use crate::{
+    cache::{CacheStats, DiskCache},
     dependency::{DependencyTree, DependentFileInfo},
-    package::Registry as PackageRegistry,
+    images::{GuardedImage, ImageLimits},
+    package::{PackageResolver, Registry as PackageRegistry},
     parser::{
         get_semantic_tokens_full, get_semantic_tokens_legend, OffsetEncoding, SemanticToken,
         SemanticTokensLegend,
     },
-    service::{CompileEnv, EntryManager, EnvWorld},
-    vfs::{notify::FilesystemEvent, AccessModel as VfsAccessModel, Vfs},
+    service::{library::LibraryHook, prelude, CompileEnv, EntryManager, EnvWorld, RootUnavailable},
+    vfs::{cached::ReadStats, notify::FilesystemEvent, AccessModel as VfsAccessModel, Vfs},
     NotifyApi, ShadowApi, Time,
 };
 
+/// The namespace of the pseudo packages identifying files under the extra
+/// roots, see [`crate::path_mapper::PathMapper::id_for_path`].
+pub const EXTRA_ROOT_NAMESPACE: &str = "__root__";
+/// The namespace of the pseudo packages identifying files under no root by
+/// absolute paths, see [`crate::path_mapper::PathMapper::id_for_path`].
+pub const ABSOLUTE_PATH_NAMESPACE: &str = "__abs__";
+/// The namespace of the pseudo packages identifying untitled buffers of
+/// editors by their URIs, and the files included by them, see
+/// [`CompilerWorld::register_untitled`].
+pub const UNTITLED_NAMESPACE: &str = "__untitled__";
+/// The virtual path of an untitled buffer in its pseudo package.
+const UNTITLED_VPATH: &str = "/__untitled__.typ";
+
 type CodespanResult<T> = Result<T, CodespanError>;
 type CodespanError = codespan_reporting::files::Error;
 
@@ -59,16 +81,46 @@ pub struct CompilerWorld<F: CompilerFeat> {
 
     /// Provides library for typst compiler.
     pub library: Option<Arc<Prehashed<Library>>>,
+    /// The hook customizing the library, see [`crate::service::library`].
+    pub library_hook: Option<LibraryHook>,
+    /// The prelude extending the library, see [`crate::service::prelude`].
+    pub prelude: Option<Source>,
     /// Provides font management for typst compiler.
     pub font_resolver: F::FontResolver,
     /// Provides package management for typst compiler.
     pub registry: F::Registry,
+    /// Overrides the package resolution of the registry if set.
+    pub package_resolver: Option<Arc<dyn PackageResolver>>,
+    /// Roots of the workspace besides the primary root of the entry, e.g.
+    /// directories of shared templates in a monorepo.
+    pub extra_roots: Vec<ImmutPath>,
+    /// The base directories of the untitled buffers by their URIs, see
+    /// [`Self::register_untitled`].
+    untitled: HashMap<EcoString, ImmutPath>,
     /// Provides path-based data access for typst compiler.
     pub vfs: Vfs<F::AccessModel>,
+    /// The limits of the loaded images, see [`crate::images`].
+    pub image_limits: ImageLimits,
+    /// The cache persisting derived data, which is shared with the fonts,
+    /// see [`crate::cache`].
+    pub disk_cache: Option<Arc<DiskCache>>,
+    /// The images guarded by the limits in the current compilation. Reset
+    /// between compilations.
+    guarded_images: Mutex<HashMap<FileId, GuardedImage>>,
+
+    /// The fixed datetime to compile with instead of the system clock, e.g.
+    /// from `SOURCE_DATE_EPOCH`. Kept between compilations.
+    pub creation_timestamp: Option<DateTime<Utc>>,
+    /// The fixed timezone of the current date. The local timezone is used if
+    /// not set.
+    pub timezone: Option<FixedOffset>,
 
     /// The current datetime if requested. This is stored here to ensure it is
     /// always the same within one compilation. Reset between compilations.
-    now: OnceCell<DateTime<Local>>,
+    now: OnceCell<DateTime<Utc>>,
+    /// Whether to deny the access to the disk and the package registry, see
+    /// [`Self::set_safe_mode`].
+    safe_mode: bool,
 }
 
 impl<F: CompilerFeat> CompilerWorld<F> {
@@ -89,24 +141,293 @@ impl<F: CompilerFeat> CompilerWorld<F> {
             inputs: Arc::new(Prehashed::new(Dict::new())),
 
             library: None,
+            library_hook: None,
+            prelude: None,
             font_resolver,
             registry,
+            package_resolver: None,
+            extra_roots: Vec::new(),
+            untitled: HashMap::new(),
             vfs,
+            image_limits: ImageLimits::default(),
+            disk_cache: None,
+            guarded_images: Mutex::new(HashMap::new()),
 
+            creation_timestamp: None,
+            timezone: None,
             now: OnceCell::new(),
+            safe_mode: false,
         }
     }
 
+    /// Compile with a fixed datetime instead of the system clock, so that
+    /// `datetime.today()` and document timestamps are reproducible.
+    pub fn with_creation_timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.creation_timestamp = Some(timestamp);
+        self
+    }
+
+    /// Compute the current date in a fixed timezone instead of the local one.
+    pub fn with_timezone(mut self, timezone: FixedOffset) -> Self {
+        self.timezone = Some(timezone);
+        self
+    }
+
+    /// Set the fixed datetime to compile with, or reset to the system clock by
+    /// `None`.
+    pub fn set_creation_timestamp(&mut self, timestamp: Option<DateTime<Utc>>) {
+        self.creation_timestamp = timestamp;
+        self.now.take();
+    }
+
     pub fn set_inputs(&mut self, inputs: Arc<Prehashed<Dict>>) {
         self.inputs = inputs;
     }
+
+    /// Customize the library by a hook, see [`crate::service::library`].
+    pub fn with_library_hook(mut self, hook: LibraryHook) -> Self {
+        self.library_hook = Some(hook);
+        self
+    }
+
+    /// Set the hook customizing the library, or remove it by `None`. The
+    /// library is rebuilt by the next compilation.
+    pub fn set_library_hook(&mut self, hook: Option<LibraryHook>) {
+        self.library_hook = hook;
+    }
+
+    /// Set the prelude evaluated before the main source, or remove it by
+    /// `None`, see [`crate::service::prelude`].
+    pub fn set_prelude(&mut self, prelude: Option<String>) {
+        self.prelude = prelude.map(|text| Source::new(*prelude::PRELUDE_ID, text));
+    }
+
+    /// Set the roots of the workspace besides the primary root.
+    pub fn set_extra_roots(&mut self, roots: Vec<ImmutPath>) {
+        self.extra_roots = roots;
+    }
+
+    /// Guard the loaded images by the limits, see [`crate::images`].
+    pub fn with_image_limits(mut self, limits: ImageLimits) -> Self {
+        self.image_limits = limits;
+        self
+    }
+
+    pub fn set_image_limits(&mut self, limits: ImageLimits) {
+        self.image_limits = limits;
+    }
+
+    /// Set the cache persisting derived data, whose statistics are reported
+    /// by the compilations, see [`crate::cache`].
+    pub fn set_disk_cache(&mut self, cache: Option<Arc<DiskCache>>) {
+        self.disk_cache = cache;
+    }
+
+    /// Get the decisions of the image limits on the images loaded by the
+    /// latest compilation, ordered by their paths.
+    pub fn image_decisions(&self) -> Vec<ImageDecision> {
+        let mut decisions: Vec<_> = (self.guarded_images.lock().iter())
+            .map(|(id, image)| image.decision(*id))
+            .collect();
+        decisions.sort_by(|a, b| a.path.cmp(&b.path));
+        decisions
+    }
+
+    /// Serve the images as is, since the image limits need the `image-limits`
+    /// feature.
+    #[cfg(not(feature = "image-limits"))]
+    fn guard_image(&self, _id: FileId, content: Bytes) -> FileResult<Bytes> {
+        Ok(content)
+    }
+
+    /// Apply the image limits to the content of an image file.
+    #[cfg(feature = "image-limits")]
+    fn guard_image(&self, id: FileId, content: Bytes) -> FileResult<Bytes> {
+        use typst_ts_core::vector::stats::ImageAction;
+
+        use crate::images;
+
+        if self.image_limits.is_unlimited() {
+            return Ok(content);
+        }
+        let Some(format) = images::image_format(id.vpath().as_rootless_path()) else {
+            return Ok(content);
+        };
+
+        let limits = &self.image_limits;
+        let guarded = (self.vfs).guard_image(&content, limits, || {
+            images::guard_image(&content, format, limits)
+        });
+        let Some(guarded) = guarded else {
+            return Ok(content);
+        };
+
+        let res = match &guarded.action {
+            ImageAction::Rejected { reason } => Err(FileError::Other(Some(reason.into()))),
+            _ => Ok(guarded.data.clone()),
+        };
+        self.guarded_images.lock().insert(id, guarded);
+        res
+    }
+
+    /// Compile untrusted documents without access to the disk and the
+    /// network, see [`Vfs::set_safe_mode`].
+    ///
+    /// Only the shadow files are readable, and the packages are only resolved
+    /// by the custom package resolver if set, since the registry may download
+    /// them. The fonts are still loaded by the font resolver, which is not
+    /// controlled by the documents.
+    pub fn set_safe_mode(&mut self, enabled: bool) {
+        self.safe_mode = enabled;
+        self.vfs.set_safe_mode(enabled);
+    }
+
+    /// Whether the world is in safe mode, see [`Self::set_safe_mode`].
+    pub fn safe_mode(&self) -> bool {
+        self.safe_mode
+    }
+
+    /// Set a custom package resolver, or reset to the registry by `None`.
+    pub fn set_package_resolver(&mut self, resolver: Option<Arc<dyn PackageResolver>>) {
+        self.package_resolver = resolver;
+    }
+
+    /// Register an untitled buffer of an editor, e.g. `untitled:Untitled-1`,
+    /// returning its file id.
+    ///
+    /// The buffer has no path on disk, hence it is identified by its URI in
+    /// the [`UNTITLED_NAMESPACE`], and the relative paths in it, e.g. of
+    /// includes, are resolved against the given base directory. The URI is
+    /// accepted in place of a path by the [`Self::path_mapper`] and the
+    /// [`ShadowApi`], and it is reported back in place of a path by the
+    /// mapper and diagnostics.
+    pub fn register_untitled(&mut self, uri: &str, base: ImmutPath) -> FileId {
+        self.untitled.insert(uri.into(), base);
+        untitled_id(uri)
+    }
+
+    /// Get the id of a registered untitled buffer by its URI.
+    pub fn untitled_id(&self, uri: &str) -> Option<FileId> {
+        self.untitled.contains_key(uri).then(|| untitled_id(uri))
+    }
+
+    /// Get the URI of an untitled buffer by its id.
+    pub fn untitled_uri(&self, id: FileId) -> Option<&'static str> {
+        untitled_uri(id)
+    }
+
+    /// Save an untitled buffer to the path, returning the id of the saved
+    /// file.
+    ///
+    /// The shadow content of the buffer is moved to the path, and the entry
+    /// is moved if the buffer is the main file, so that the next compilation
+    /// continues without resetting the world.
+    pub fn save_untitled(&mut self, uri: &str, path: &Path) -> FileResult<FileId> {
+        let untitled = self
+            .untitled_id(uri)
+            .ok_or_else(|| FileError::NotFound(uri.into()))?;
+        let untitled_path = self.path_mapper().vfs_path(untitled)?;
+        self.untitled.remove(uri);
+        let id =
+            (self.path_mapper().lookup_id(path)).ok_or_else(|| FileError::NotFound(path.into()))?;
+
+        if let Some(content) = self.vfs.shadow_content(&untitled_path) {
+            self.vfs.remove_shadow(&untitled_path);
+            self.vfs.map_shadow(path, content)?;
+        }
+        if self.entry.main() == Some(untitled) {
+            self.entry = self.entry.select_in_workspace(id);
+        }
+
+        Ok(id)
+    }
+
+    /// Get the path of a shadow file, where an untitled buffer is keyed by
+    /// its path in [`crate::path_mapper::PathMapper::vfs_path`].
+    fn shadow_path<'p>(&self, path: &'p Path) -> FileResult<Cow<'p, Path>> {
+        match path.to_str().and_then(|uri| self.untitled_id(uri)) {
+            Some(id) => Ok(Cow::Owned(self.path_mapper().vfs_path(id)?)),
+            None => Ok(Cow::Borrowed(path)),
+        }
+    }
+
+    /// Read a package file served by the custom package resolver.
+    fn package_file(&self, id: FileId) -> Option<FileResult<Bytes>> {
+        let spec = id.package()?;
+        if is_pseudo_package(spec) {
+            return None;
+        }
+        self.package_resolver.as_ref()?.file(spec, id.vpath())
+    }
+}
+
+/// Create a pseudo package identifying files under a root.
+fn pseudo_package(namespace: &str, name: String) -> PackageSpec {
+    PackageSpec {
+        namespace: namespace.into(),
+        name: name.into(),
+        version: PackageVersion {
+            major: 0,
+            minor: 0,
+            patch: 0,
+        },
+    }
+}
+
+/// Create the id of an untitled buffer.
+fn untitled_id(uri: &str) -> FileId {
+    let package = pseudo_package(UNTITLED_NAMESPACE, uri.to_owned());
+    FileId::new(Some(package), VirtualPath::new(UNTITLED_VPATH))
+}
+
+/// Get the URI of an untitled buffer by its id.
+fn untitled_uri(id: FileId) -> Option<&'static str> {
+    let spec = id.package()?;
+    let is_buffer = id.vpath().as_rooted_path() == Path::new(UNTITLED_VPATH);
+    (spec.namespace == UNTITLED_NAMESPACE && is_buffer).then_some(spec.name.as_str())
+}
+
+/// Get the name of a file to display where no world is at hand, e.g. in the
+/// chain of a cycle, like [`crate::path_mapper::PathMapper::display_path`].
+///
+/// The pseudo packages never show up: an untitled buffer is named by its
+/// URI, a file under no root by its absolute path, and the other files of
+/// pseudo packages by their paths relative to their roots.
+pub(crate) fn display_file_name(id: FileId) -> String {
+    if let Some(uri) = untitled_uri(id) {
+        return uri.to_owned();
+    }
+    let path = id.vpath().as_rootless_path();
+    match id.package() {
+        Some(spec) if spec.namespace == ABSOLUTE_PATH_NAMESPACE => Path::new(spec.name.as_str())
+            .join(path)
+            .display()
+            .to_string(),
+        Some(spec) if is_pseudo_package(spec) => path.display().to_string(),
+        Some(spec) => format!("{spec}/{}", path.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Whether the package is a pseudo package, see
+/// [`crate::path_mapper::PathMapper::id_for_path`].
+pub(crate) fn is_pseudo_package(spec: &PackageSpec) -> bool {
+    spec.namespace == EXTRA_ROOT_NAMESPACE
+        || spec.namespace == ABSOLUTE_PATH_NAMESPACE
+        || spec.namespace == UNTITLED_NAMESPACE
 }
 
 #[comemo::memoize]
-fn create_library(inputs: Arc<Prehashed<Dict>>) -> Arc<Prehashed<Library>> {
-    let lib = typst::Library::builder()
+pub(crate) fn create_library(
+    inputs: Arc<Prehashed<Dict>>,
+    hook: Option<LibraryHook>,
+) -> Arc<Prehashed<Library>> {
+    let mut lib = typst::Library::builder()
         .with_inputs(inputs.deref().deref().clone())
         .build();
+    if let Some(hook) = &hook {
+        hook.apply(&mut lib);
+    }
 
     Arc::new(Prehashed::new(lib))
 }
@@ -122,13 +443,58 @@ impl<F: CompilerFeat> EnvWorld for CompilerWorld<F> {
         Ok(())
     }
 
-    fn prepare_env(&mut self, _env: &mut CompileEnv) -> SourceResult<()> {
+    fn prepare_env(&mut self, env: &mut CompileEnv) -> SourceResult<()> {
         // Hook up the lang items.
         // todo: bad upstream changes
-        self.library = Some(create_library(self.inputs.clone()));
+        self.library = Some(create_library(
+            self.inputs.clone(),
+            self.library_hook.clone(),
+        ));
+        if let Some(prelude) = &self.prelude {
+            let mut default_tracer = Tracer::default();
+            let tracer = env.tracer.as_mut().unwrap_or(&mut default_tracer);
+            let library = prelude::apply_prelude(self, tracer, prelude)?;
+            self.library = Some(Arc::new(Prehashed::new(library)));
+        }
+
+        if let Some(now) = env.now {
+            self.now.take();
+            let _ = self.now.set(now.with_timezone(&Utc));
+        }
 
         Ok(())
     }
+
+    fn guarded_images(&self) -> Vec<(FileId, GuardedImage)> {
+        (self.guarded_images.lock().iter())
+            .map(|(id, image)| (*id, image.clone()))
+            .collect()
+    }
+
+    fn source_ids(&self) -> Vec<FileId> {
+        self.vfs.source_ids()
+    }
+
+    fn read_stats(&self) -> Option<ReadStats> {
+        Some(self.vfs.read_stats())
+    }
+
+    fn disk_cache_stats(&self) -> Option<Arc<CacheStats>> {
+        self.disk_cache.as_ref().map(|cache| cache.stats())
+    }
+
+    fn check_root(&self) -> Option<RootUnavailable> {
+        let root = self.entry.root()?;
+        let error = self.vfs.check_root(&root).err()?;
+        Some(RootUnavailable {
+            root: root.to_path_buf(),
+            error,
+        })
+    }
+
+    fn revalidate_files(&mut self) {
+        self.vfs.revalidate();
+    }
 }
 
 impl<F: CompilerFeat> World for CompilerWorld<F> {
@@ -166,13 +532,27 @@ impl<F: CompilerFeat> World for CompilerWorld<F> {
         if id == *DETACHED_ENTRY {
             return Ok(DETACH_SOURCE.clone());
         }
+        if let Some(prelude) = self.prelude.as_ref().filter(|_| id == *prelude::PRELUDE_ID) {
+            return Ok(prelude.clone());
+        }
 
-        self.vfs.resolve(&self.path_for_id(id)?, id)
+        if let Some(content) = self.package_file(id) {
+            let content = content?;
+            let text = std::str::from_utf8(&content).map_err(|_| FileError::InvalidUtf8)?;
+            return Ok(Source::new(id, text.to_owned()));
+        }
+
+        self.vfs.resolve(&self.path_mapper().vfs_path(id)?, id)
     }
 
     /// Try to access the specified file.
     fn file(&self, id: FileId) -> FileResult<Bytes> {
-        self.vfs.file(&self.path_for_id(id)?)
+        let content = match self.package_file(id) {
+            Some(content) => content?,
+            None => self.vfs.file(&self.path_mapper().vfs_path(id)?)?,
+        };
+
+        self.guard_image(id, content)
     }
 
     /// Get the current date.
@@ -183,11 +563,12 @@ impl<F: CompilerFeat> World for CompilerWorld<F> {
     /// If this function returns `None`, Typst's `datetime` function will
     /// return an error.
     fn today(&self, offset: Option<i64>) -> Option<Datetime> {
-        let now = self.now.get_or_init(chrono::Local::now);
+        let now = (self.now).get_or_init(|| self.creation_timestamp.unwrap_or_else(Utc::now));
 
-        let naive = match offset {
-            None => now.naive_local(),
-            Some(o) => now.naive_utc() + chrono::Duration::try_hours(o)?,
+        let naive = match (offset, self.timezone) {
+            (None, Some(timezone)) => now.with_timezone(&timezone).naive_local(),
+            (None, None) => now.with_timezone(&Local).naive_local(),
+            (Some(o), _) => now.naive_utc() + chrono::Duration::try_hours(o)?,
         };
 
         Datetime::from_ymd(
@@ -214,6 +595,7 @@ impl<F: CompilerFeat> CompilerWorld<F> {
         self.vfs.reset();
 
         self.now.take();
+        self.guarded_images.get_mut().clear();
     }
 
     /// Set the `do_reparse` flag.
@@ -227,7 +609,34 @@ impl<F: CompilerFeat> CompilerWorld<F> {
     }
 
     /// Resolve the real path for a file id.
+    #[deprecated(note = "use `PathMapper::path_for_id` of `Self::path_mapper` instead")]
     pub fn path_for_id(&self, id: FileId) -> Result<PathBuf, FileError> {
+        self.path_mapper().vfs_path(id)
+    }
+
+    /// Get the user-facing path of a file, which is the URI of an untitled
+    /// buffer, or the real path otherwise.
+    #[deprecated(note = "use `PathMapper::display_path` of `Self::path_mapper` instead")]
+    pub fn uri_for_id(&self, id: FileId) -> FileResult<String> {
+        match self.untitled_uri(id) {
+            Some(uri) => Ok(uri.to_owned()),
+            None => Ok(self
+                .path_mapper()
+                .vfs_path(id)?
+                .to_string_lossy()
+                .to_string()),
+        }
+    }
+
+    /// Get the id of a file in the workspace by its path.
+    #[deprecated(note = "use `PathMapper::id_for_path` of `Self::path_mapper` instead")]
+    pub fn id_for_path(&self, path: &Path) -> Option<FileId> {
+        self.path_mapper().lookup_id(path)
+    }
+
+    /// Resolve the path of a file, see
+    /// [`crate::path_mapper::PathMapper::vfs_path`].
+    pub(crate) fn resolve_path(&self, id: FileId) -> FileResult<PathBuf> {
         if id == *DETACHED_ENTRY {
             return Ok(DETACHED_ENTRY.vpath().as_rooted_path().to_owned());
         }
@@ -235,7 +644,37 @@ impl<F: CompilerFeat> CompilerWorld<F> {
         // Determine the root path relative to which the file path
         // will be resolved.
         let root = match id.package() {
-            Some(spec) => self.registry.resolve(spec)?,
+            Some(spec) if spec.namespace == EXTRA_ROOT_NAMESPACE => {
+                let root = spec
+                    .name
+                    .parse()
+                    .ok()
+                    .and_then(|i: usize| self.extra_roots.get(i));
+                root.cloned()
+                    .ok_or_else(|| FileError::NotFound(id.vpath().as_rootless_path().into()))?
+            }
+            Some(spec) if spec.namespace == ABSOLUTE_PATH_NAMESPACE => {
+                Path::new(spec.name.as_str()).into()
+            }
+            Some(spec) if spec.namespace == UNTITLED_NAMESPACE => {
+                let base = (self.untitled.get(spec.name.as_str()))
+                    .ok_or_else(|| FileError::NotFound(spec.name.as_str().into()))?;
+                if self.untitled_uri(id).is_some() {
+                    // The buffer is keyed by a path which never exists on disk.
+                    let name = format!("__untitled_{:032x}.typ", hash128(&spec.name));
+                    return Ok(base.join(name));
+                }
+                base.clone()
+            }
+            Some(spec) => match &self.package_resolver {
+                Some(resolver) => resolver.resolve(spec)?,
+                None if self.safe_mode => {
+                    return Err(FileError::Other(Some(eco_format!(
+                        "cannot resolve the package {spec} in safe mode"
+                    ))));
+                }
+                None => self.registry.resolve(spec)?,
+            },
             None => self.entry.root().ok_or(FileError::Other(Some(eco_format!(
                 "cannot access directory without root: state: {:?}",
                 self.entry
@@ -247,6 +686,85 @@ impl<F: CompilerFeat> CompilerWorld<F> {
         id.vpath().resolve(&root).ok_or(FileError::AccessDenied)
     }
 
+    /// Get the id of a file in the workspace by its path, which is the
+    /// inverse of [`Self::resolve_path`], see
+    /// [`crate::path_mapper::PathMapper::id_for_path`].
+    ///
+    /// The file is not required to exist on disk, e.g. a shadow file. A
+    /// relative path is resolved against the primary root, so that files
+    /// living only in memory are also able to be referred by their virtual
+    /// paths.
+    ///
+    /// An absolute path is resolved against the root containing it, where the
+    /// longest root wins and the primary root wins a tie. Files under the
+    /// [`Self::extra_roots`] are identified by pseudo packages in the
+    /// [`EXTRA_ROOT_NAMESPACE`], and files under no root are identified by
+    /// their absolute paths in the [`ABSOLUTE_PATH_NAMESPACE`]. The URI of a
+    /// registered untitled buffer is also accepted, see
+    /// [`Self::register_untitled`].
+    pub(crate) fn resolve_id(&self, path: &Path) -> Option<FileId> {
+        if let Some(id) = path.to_str().and_then(|uri| self.untitled_id(uri)) {
+            return Some(id);
+        }
+
+        let path = path.clean();
+        if path.is_relative() {
+            return Some(FileId::new(None, VirtualPath::new(&path)));
+        }
+
+        if let Some(id) = self.id_in_roots(&path, |root| root.clean()) {
+            return Some(id);
+        }
+
+        // The path may be in a root by a different normalization, e.g. through
+        // a symlink, or in a different case on case-insensitive file systems.
+        if let Ok(real_path) = std::fs::canonicalize(&path) {
+            let canonicalize =
+                |root: &Path| std::fs::canonicalize(root).unwrap_or_else(|_| root.clean());
+            if let Some(id) = self.id_in_roots(&real_path, canonicalize) {
+                return Some(id);
+            }
+
+            // Otherwise, look for the known source having the same real path.
+            for (dep, _) in self.vfs.iter_dependencies() {
+                if std::fs::canonicalize(dep).is_ok_and(|dep| dep == real_path) {
+                    if let Some(id) = self.id_in_roots(dep, |root| root.clean()) {
+                        return Some(id);
+                    }
+                }
+            }
+        }
+
+        // The root directory of the path, e.g. `/` or `C:\`.
+        let root = path.ancestors().last()?;
+        let name = root.to_str()?.to_owned();
+        let package = pseudo_package(ABSOLUTE_PATH_NAMESPACE, name);
+        let relative_path = path.strip_prefix(root).ok()?;
+        Some(FileId::new(Some(package), VirtualPath::new(relative_path)))
+    }
+
+    /// Get the id of a file by the longest root containing it, where the roots
+    /// are normalized by the given function before matching.
+    fn id_in_roots(&self, path: &Path, normalize: impl Fn(&Path) -> PathBuf) -> Option<FileId> {
+        let primary = self.entry.root().map(|root| (None, root));
+        let extra = (self.extra_roots.iter().cloned().enumerate()).map(|(i, root)| (Some(i), root));
+        let mut matched: Option<(Option<usize>, PathBuf)> = None;
+        for (idx, root) in primary.into_iter().chain(extra) {
+            let root = normalize(&root);
+            let longer = matched
+                .as_ref()
+                .is_none_or(|(_, prev)| root.components().count() > prev.components().count());
+            if longer && path.starts_with(&root) {
+                matched = Some((idx, root));
+            }
+        }
+
+        let (idx, root) = matched?;
+        let package = idx.map(|idx| pseudo_package(EXTRA_ROOT_NAMESPACE, idx.to_string()));
+        let relative_path = path.strip_prefix(&root).ok()?;
+        Some(FileId::new(package, VirtualPath::new(relative_path)))
+    }
+
     /// Get found dependencies in current state of vfs.
     pub fn get_dependencies(&self) -> Option<DependencyTree> {
         let root = self.entry.root()?;
@@ -273,10 +791,8 @@ impl<F: CompilerFeat> CompilerWorld<F> {
     ) -> Arc<Vec<SemanticToken>> {
         let src = &file_path
             .and_then(|e| {
-                let relative_path = Path::new(&e).strip_prefix(&self.workspace_root()?).ok()?;
-
-                let source_id = FileId::new(None, VirtualPath::new(relative_path));
-                self.source(source_id).ok()
+                let id = self.path_mapper().lookup_id(Path::new(&e))?;
+                self.source(id).ok()
             })
             .unwrap_or_else(|| self.main());
 
@@ -306,12 +822,21 @@ impl<F: CompilerFeat> CompilerWorld<F> {
 impl<F: CompilerFeat> ShadowApi for CompilerWorld<F> {
     #[inline]
     fn _shadow_map_id(&self, file_id: FileId) -> FileResult<PathBuf> {
-        self.path_for_id(file_id)
+        self.path_mapper().vfs_path(file_id)
     }
 
-    #[inline]
+    /// Get the shadow files, where untitled buffers are reported by their
+    /// URIs.
     fn shadow_paths(&self) -> Vec<Arc<Path>> {
-        self.vfs.shadow_paths()
+        let untitled: HashMap<_, _> = (self.untitled.keys())
+            .filter_map(|uri| Some((self.path_mapper().vfs_path(untitled_id(uri)).ok()?, uri)))
+            .collect();
+        (self.vfs.shadow_paths().into_iter())
+            .map(|path| match untitled.get(path.as_ref()) {
+                Some(uri) => Path::new(uri.as_str()).into(),
+                None => path,
+            })
+            .collect()
     }
 
     #[inline]
@@ -321,15 +846,20 @@ impl<F: CompilerFeat> ShadowApi for CompilerWorld<F> {
 
     #[inline]
     fn map_shadow(&self, path: &Path, content: Bytes) -> FileResult<()> {
-        self.vfs.map_shadow(path, content)
+        self.vfs.map_shadow(&self.shadow_path(path)?, content)
     }
 
     #[inline]
     fn unmap_shadow(&self, path: &Path) -> FileResult<()> {
-        self.vfs.remove_shadow(path);
+        self.vfs.remove_shadow(&self.shadow_path(path)?);
 
         Ok(())
     }
+
+    #[inline]
+    fn edit_shadow(&self, path: &Path, range: Range<usize>, text: &str) -> FileResult<usize> {
+        self.vfs.edit_shadow(&self.shadow_path(path)?, range, text)
+    }
 }
 
 impl<F: CompilerFeat> NotifyApi for CompilerWorld<F> {
@@ -342,6 +872,16 @@ impl<F: CompilerFeat> NotifyApi for CompilerWorld<F> {
     fn notify_fs_event(&mut self, event: FilesystemEvent) {
         self.vfs.notify_fs_event(event)
     }
+
+    #[inline]
+    fn evict_files(&mut self, paths: &[ImmutPath]) {
+        self.vfs.evict_files(paths)
+    }
+
+    #[inline]
+    fn evict_stale_files(&mut self, age: std::time::Duration) {
+        self.vfs.evict_older_than(age)
+    }
 }
 
 impl<F: CompilerFeat> EntryManager for CompilerWorld<F> {
@@ -382,6 +922,17 @@ impl<'a, F: CompilerFeat> codespan_reporting::files::Files<'a> for CompilerWorld
 
     /// The user-facing name of a file.
     fn name(&'a self, id: FileId) -> CodespanResult<Self::Name> {
+        if let Some(uri) = self.untitled_uri(id) {
+            return Ok(uri.to_owned());
+        }
+        // The files included by untitled buffers are named by their paths.
+        let package = id.package();
+        if package.is_some_and(|spec| spec.namespace == UNTITLED_NAMESPACE) {
+            if let Ok(path) = self.path_mapper().vfs_path(id) {
+                return Ok(path.to_string_lossy().into());
+            }
+        }
+
         let vpath = id.vpath();
         Ok(if let Some(package) = id.package() {
             format!("{package}{}", vpath.as_rooted_path().display())
@@ -453,3 +1004,116 @@ pub struct WorldSnapshot {
     /// document specific data
     pub artifact_data: String,
 }
+
+#[cfg(all(test, feature = "system-compile"))]
+mod tests {
+    use chrono::TimeZone;
+    use typst_ts_core::{
+        config::{compiler::EntryOpts, CompileOpts},
+        vector::incr::IncrDocServer,
+        Exporter,
+    };
+    use typst_ts_pdf_exporter::PdfDocExporter;
+
+    use super::*;
+    use crate::{
+        fixture::TestWorkspace,
+        service::{CompileDriver, CompileEnv, Compiler},
+        TypstSystemWorld,
+    };
+
+    #[test]
+    fn test_id_for_path_in_extra_roots() {
+        let ws = TestWorkspace::new();
+        let root = ws.root().clean();
+        let docs = root.join("docs");
+        let packages = root.join("packages");
+        let common = packages.join("typst-common");
+        std::fs::create_dir_all(&common).unwrap();
+        std::fs::write(common.join("template.typ"), "#let title = [Shared]").unwrap();
+
+        let world = TypstSystemWorld::new(CompileOpts {
+            entry: EntryOpts::new_rooted(docs.clone(), Some("main.typ".into())),
+            // The nested root is longer, and the duplicated primary root ties.
+            extra_roots: vec![packages.clone(), common.clone(), docs.clone()],
+            ..ws.opts()
+        })
+        .unwrap();
+
+        let mapper = world.path_mapper();
+        let namespace_of = |path: &Path| {
+            let id = mapper.id_for_path(path).unwrap();
+            assert_eq!(mapper.path_for_id(id).unwrap(), path, "{id:?}");
+            id.package()
+                .map(|spec| (spec.namespace.clone(), spec.name.clone()))
+        };
+
+        assert_eq!(namespace_of(&docs.join("main.typ")), None);
+        let template = common.join("template.typ");
+        assert_eq!(
+            namespace_of(&template),
+            Some((EXTRA_ROOT_NAMESPACE.into(), "1".into()))
+        );
+        assert_eq!(
+            namespace_of(&packages.join("other.typ")),
+            Some((EXTRA_ROOT_NAMESPACE.into(), "0".into()))
+        );
+        let (namespace, _) = namespace_of(&root.join("elsewhere/main.typ")).unwrap();
+        assert_eq!(namespace, ABSOLUTE_PATH_NAMESPACE);
+
+        // The pseudo packages don't show up in the names of the files.
+        let name_of = |path: &Path| display_file_name(mapper.id_for_path(path).unwrap());
+        assert_eq!(name_of(&template), "template.typ");
+        let elsewhere = root.join("elsewhere/main.typ");
+        assert_eq!(name_of(&elsewhere), elsewhere.display().to_string());
+        assert_eq!(
+            display_file_name(untitled_id("untitled:Untitled-1")),
+            "untitled:Untitled-1"
+        );
+
+        let source = world
+            .source(mapper.id_for_path(&template).unwrap())
+            .unwrap();
+        assert_eq!(source.text(), "#let title = [Shared]");
+    }
+
+    #[test]
+    fn test_reproducible_build() {
+        let ws = TestWorkspace::new();
+        ws.write(
+            "main.typ",
+            "#let today = datetime.today()\n#metadata(today.display()) <today>\n#today.display()",
+        );
+
+        // Late in the evening at UTC, which is already the next day at UTC+2.
+        let timestamp = Utc.with_ymd_and_hms(2001, 2, 3, 23, 30, 0).unwrap();
+        let timezone = FixedOffset::east_opt(2 * 3600).unwrap();
+
+        let build = || {
+            let world = ws
+                .world()
+                .with_creation_timestamp(timestamp)
+                .with_timezone(timezone);
+            let mut driver = CompileDriver::new(world).with_entry_file(ws.path("main.typ"));
+
+            let doc = driver.compile(&mut CompileEnv::default()).unwrap();
+            let today = driver.query("<today>".to_owned(), &doc).unwrap();
+            let pdf = PdfDocExporter::default()
+                .with_timestamp(true)
+                .export(driver.world(), doc.clone())
+                .unwrap();
+            let artifact = IncrDocServer::default().pack_delta(doc);
+            (format!("{today:?}"), pdf, artifact)
+        };
+
+        let (today, pdf, artifact) = build();
+        assert!(today.contains("2001-02-04"), "{today}");
+
+        let (_, rebuilt_pdf, rebuilt_artifact) = build();
+        assert!(pdf == rebuilt_pdf, "the pdf is not reproducible");
+        assert!(
+            artifact == rebuilt_artifact,
+            "the artifact is not reproducible"
+        );
+    }
+}
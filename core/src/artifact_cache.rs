@@ -0,0 +1,143 @@
+//! Cache the artifacts exported from documents by their content, e.g. in a
+//! store shared by the machines of a build farm, so that a document compiled
+//! again to the same content isn't exported again.
+//!
+//! A [`CachedExporter`] wraps an exporter producing bytes. Before exporting, it
+//! looks up the key of the document in a [`RemoteArtifactCache`], which is the
+//! hash of the content of the document, see [`crate::content_hash`], along
+//! with the type of the wrapped exporter and its options. On a hit, the cached
+//! artifact is returned without calling the wrapped exporter. On a miss, the
+//! artifact is exported and put into the cache.
+//!
+//! The options must cover whatever changes the artifact besides the content,
+//! e.g. the creation timestamp of a PDF, or else a stale artifact is reused.
+
+use std::{any::type_name, collections::HashMap, hash::Hash, sync::Arc};
+
+use parking_lot::Mutex;
+use typst::{diag::SourceResult, World};
+
+use crate::{content_hash::content_hash, Bytes, Exporter, TypstDocument};
+
+/// A store of artifacts keyed by the hashes of their content, see the
+/// [module docs](self).
+///
+/// A remote store should treat its failures as misses, since the artifact is
+/// exported anyway.
+pub trait RemoteArtifactCache: Send + Sync {
+    /// Get the artifact of a hash, if any.
+    fn get(&self, hash: u128) -> Option<Bytes>;
+
+    /// Put the artifact of a hash.
+    fn put(&self, hash: u128, bytes: Bytes);
+}
+
+/// An in-memory cache, which is shared by the exporters of a process, e.g.
+/// across the compilations in watch mode.
+#[derive(Debug, Default)]
+pub struct MemoryArtifactCache {
+    artifacts: Mutex<HashMap<u128, Bytes>>,
+}
+
+impl MemoryArtifactCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.artifacts.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.artifacts.lock().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.artifacts.lock().clear();
+    }
+}
+
+impl RemoteArtifactCache for MemoryArtifactCache {
+    fn get(&self, hash: u128) -> Option<Bytes> {
+        self.artifacts.lock().get(&hash).cloned()
+    }
+
+    fn put(&self, hash: u128, bytes: Bytes) {
+        self.artifacts.lock().insert(hash, bytes);
+    }
+}
+
+/// An exporter reading through a cache of artifacts, see the
+/// [module docs](self).
+pub struct CachedExporter<E> {
+    exporter: E,
+    cache: Arc<dyn RemoteArtifactCache>,
+    options: u128,
+}
+
+impl<E> CachedExporter<E> {
+    pub fn new(exporter: E, cache: Arc<dyn RemoteArtifactCache>) -> Self {
+        Self {
+            exporter,
+            cache,
+            options: 0,
+        }
+    }
+
+    /// Set the options of the wrapped exporter, which are mixed into the keys
+    /// of the artifacts.
+    pub fn with_options(mut self, options: &impl Hash) -> Self {
+        self.options = typst::util::hash128(options);
+        self
+    }
+
+    /// Get the key of the artifact of a document.
+    pub fn key(&self, doc: &TypstDocument) -> u128 {
+        typst::util::hash128(&(content_hash(doc), type_name::<E>(), self.options))
+    }
+}
+
+impl<E> Exporter<TypstDocument, Vec<u8>> for CachedExporter<E>
+where
+    E: Exporter<TypstDocument, Vec<u8>>,
+{
+    fn export(&self, world: &dyn World, output: Arc<TypstDocument>) -> SourceResult<Vec<u8>> {
+        let key = self.key(&output);
+        if let Some(bytes) = self.cache.get(key) {
+            return Ok(bytes.to_vec());
+        }
+
+        let bytes = self.exporter.export(world, output)?;
+        self.cache.put(key, Bytes::from(bytes.as_slice()));
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_artifact_cache() {
+        let cache = MemoryArtifactCache::new();
+        assert!(cache.get(1).is_none());
+        cache.put(1, Bytes::from_static(b"artifact"));
+        assert_eq!(cache.get(1).as_deref(), Some(&b"artifact"[..]));
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_artifact_keys() {
+        let cache: Arc<dyn RemoteArtifactCache> = Arc::new(MemoryArtifactCache::new());
+        let doc = TypstDocument::default();
+        let exporter =
+            |_: &dyn World, _: Arc<TypstDocument>| -> SourceResult<Vec<u8>> { Ok(vec![]) };
+
+        let plain = CachedExporter::new(exporter, cache.clone());
+        let with_options = CachedExporter::new(exporter, cache).with_options(&"timestamp");
+        assert_eq!(plain.key(&doc), plain.key(&TypstDocument::default()));
+        assert_ne!(plain.key(&doc), with_options.key(&doc));
+    }
+}
@@ -223,6 +223,10 @@ pub struct CompileOpts {
     /// Path to entry
     pub entry: EntryOpts,
 
+    /// Roots of the workspace besides the root of the entry
+    #[serde(rename = "extraRoots", default)]
+    pub extra_roots: Vec<PathBuf>,
+
     /// Additional input arguments to compile the entry file.
     pub inputs: Dict,
 
@@ -242,6 +246,11 @@ pub struct CompileOpts {
     #[serde(rename = "withEmbeddedFonts")]
     #[serde_as(as = "Vec<AsCowBytes>")]
     pub with_embedded_fonts: Vec<Cow<'static, [u8]>>,
+
+    /// Directory to persist derived data across processes, e.g. the metadata
+    /// of fonts. Nothing is persisted if it is not set.
+    #[serde(rename = "cacheDir", default)]
+    pub cache_dir: Option<PathBuf>,
 }
 
 #[serde_as]
@@ -263,6 +272,11 @@ pub struct CompileFontOpts {
     #[serde(rename = "withEmbeddedFonts")]
     #[serde_as(as = "Vec<AsCowBytes>")]
     pub with_embedded_fonts: Vec<Cow<'static, [u8]>>,
+
+    /// Directory to persist derived data across processes, e.g. the metadata
+    /// of fonts. Nothing is persisted if it is not set.
+    #[serde(rename = "cacheDir", default)]
+    pub cache_dir: Option<PathBuf>,
 }
 
 impl From<CompileOpts> for CompileFontOpts {
@@ -272,6 +286,7 @@ impl From<CompileOpts> for CompileFontOpts {
             font_paths: opts.font_paths,
             no_system_fonts: opts.no_system_fonts,
             with_embedded_fonts: opts.with_embedded_fonts,
+            cache_dir: opts.cache_dir,
         }
     }
 }
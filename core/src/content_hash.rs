@@ -0,0 +1,355 @@
+//! Hash the semantic content of documents, e.g. to skip re-exporting an
+//! unchanged document in build systems.
+//!
+//! Hashing a serialized artifact is unreliable, since the encoding, e.g. the
+//! order of items or the formatting of floats, may change across versions of
+//! the crates. Instead, the content hash is computed from an explicit encoding
+//! of what a document looks like:
+//!
+//! - The geometry of items, i.e. positions, sizes, transforms and paths, in
+//!   points quantized to [`LENGTH_PRECISION`]. Ratios, e.g. the scales of
+//!   transforms and the advances of glyphs, are quantized to
+//!   [`RATIO_PRECISION`].
+//! - The text and the glyphs of text items, which refer to their fonts by the
+//!   family, the variant, the index in the collection and the size of the
+//!   font data.
+//! - The resolved styles, i.e. fills and strokes. Solid colors are compared in
+//!   8-bit RGBA, while gradients are compared by their representations.
+//! - The content of images, along with their sizes.
+//! - The destinations of links.
+//!
+//! Spans and other invisible metadata are ignored, so that a recompilation of
+//! unchanged input gets the same hash. The hash is stable within a major
+//! version of the crate, and the encoding is versioned by [`HASH_VERSION`],
+//! which is also mixed into the hashes.
+
+use std::hash::Hasher;
+
+use serde::{Deserialize, Serialize};
+use siphasher::sip128::{Hasher128, SipHasher13};
+use typst::{
+    foundations::Repr,
+    introspection::{Introspector, Meta},
+    layout::{Abs, Frame, FrameItem, Point, Position, Ratio, Size, Transform},
+    model::Destination,
+    text::{Font, FontStyle},
+    visualize::{FixedStroke, Geometry, LineCap, LineJoin, Paint, Path, PathItem, Shape},
+};
+
+use crate::TypstDocument;
+
+/// The version of the encoding of content hashes, which is bumped whenever
+/// the hashes of the same content change.
+pub const HASH_VERSION: u32 = 1;
+
+/// The precision of lengths in points, below which moving an item doesn't
+/// change the hash.
+pub const LENGTH_PRECISION: f64 = 0.01;
+
+/// The precision of ratios, below which scaling an item doesn't change the
+/// hash.
+pub const RATIO_PRECISION: f64 = 0.0001;
+
+/// The content hashes of a document along with the version of the encoding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentHash {
+    /// The [`HASH_VERSION`] of the hashes, which are only comparable to the
+    /// hashes of the same version.
+    pub hash_version: u32,
+    /// The hash of the whole document, see [`content_hash`].
+    pub hash: u128,
+    /// The hashes of the pages, see [`page_content_hashes`].
+    pub pages: Vec<u128>,
+}
+
+impl DocumentHash {
+    /// Hash the content of a document and its pages.
+    pub fn new(doc: &TypstDocument) -> Self {
+        let pages = page_content_hashes(doc);
+        Self {
+            hash_version: HASH_VERSION,
+            hash: combine(&pages),
+            pages,
+        }
+    }
+}
+
+/// Hash the content of a document, see the [module docs](self).
+pub fn content_hash(doc: &TypstDocument) -> u128 {
+    combine(&page_content_hashes(doc))
+}
+
+/// Hash the content of each page of a document, see the [module docs](self).
+pub fn page_content_hashes(doc: &TypstDocument) -> Vec<u128> {
+    (doc.pages.iter())
+        .map(|page| {
+            let mut hasher = ContentHasher::new();
+            hasher.frame(&doc.introspector, &page.frame);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Hash a document by the hashes of its pages.
+fn combine(pages: &[u128]) -> u128 {
+    let mut hasher = ContentHasher::new();
+    hasher.usize(pages.len());
+    for page in pages {
+        hasher.u128(*page);
+    }
+    hasher.finish()
+}
+
+/// A hasher writing explicit little-endian encodings, so that the hashes
+/// don't depend on the [`std::hash::Hash`] implementations of typst.
+struct ContentHasher(SipHasher13);
+
+impl ContentHasher {
+    fn new() -> Self {
+        let mut hasher = Self(SipHasher13::new());
+        hasher.u32(HASH_VERSION);
+        hasher
+    }
+
+    fn finish(self) -> u128 {
+        self.0.finish128().as_u128()
+    }
+
+    fn tag(&mut self, tag: u8) {
+        self.0.write(&[tag]);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.write(&v.to_le_bytes());
+    }
+
+    fn u128(&mut self, v: u128) {
+        self.0.write(&v.to_le_bytes());
+    }
+
+    fn usize(&mut self, v: usize) {
+        self.0.write(&(v as u64).to_le_bytes());
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        self.usize(v.len());
+        self.0.write(v);
+    }
+
+    fn str(&mut self, v: &str) {
+        self.bytes(v.as_bytes());
+    }
+
+    fn quantized(&mut self, v: f64, precision: f64) {
+        let v = (v / precision).round() as i64;
+        self.0.write(&v.to_le_bytes());
+    }
+
+    fn abs(&mut self, v: Abs) {
+        self.quantized(v.to_pt(), LENGTH_PRECISION);
+    }
+
+    fn ratio(&mut self, v: Ratio) {
+        self.quantized(v.get(), RATIO_PRECISION);
+    }
+
+    fn point(&mut self, v: Point) {
+        self.abs(v.x);
+        self.abs(v.y);
+    }
+
+    fn size(&mut self, v: Size) {
+        self.abs(v.x);
+        self.abs(v.y);
+    }
+
+    fn position(&mut self, v: Position) {
+        self.tag(1);
+        self.usize(v.page.get());
+        self.point(v.point);
+    }
+
+    fn transform(&mut self, v: Transform) {
+        for ratio in [v.sx, v.ky, v.kx, v.sy] {
+            self.ratio(ratio);
+        }
+        self.abs(v.tx);
+        self.abs(v.ty);
+    }
+
+    fn frame(&mut self, introspector: &Introspector, frame: &Frame) {
+        self.size(frame.size());
+        for (pos, item) in frame.items() {
+            match item {
+                FrameItem::Group(group) => {
+                    self.tag(0);
+                    self.point(*pos);
+                    self.transform(group.transform);
+                    match &group.clip_path {
+                        Some(path) => {
+                            self.tag(1);
+                            self.path(path);
+                        }
+                        None => self.tag(0),
+                    }
+                    self.frame(introspector, &group.frame);
+                }
+                FrameItem::Text(text) => {
+                    self.tag(1);
+                    self.point(*pos);
+                    self.font(&text.font);
+                    self.abs(text.size);
+                    self.paint(introspector, &text.fill);
+                    self.stroke(introspector, text.stroke.as_ref());
+                    self.str(&text.text);
+                    self.usize(text.glyphs.len());
+                    for glyph in &text.glyphs {
+                        self.0.write(&glyph.id.to_le_bytes());
+                        self.quantized(glyph.x_advance.get(), RATIO_PRECISION);
+                        self.quantized(glyph.x_offset.get(), RATIO_PRECISION);
+                    }
+                }
+                FrameItem::Shape(shape, _) => {
+                    self.tag(2);
+                    self.point(*pos);
+                    self.shape(introspector, shape);
+                }
+                FrameItem::Image(image, size, _) => {
+                    self.tag(3);
+                    self.point(*pos);
+                    self.size(*size);
+                    self.bytes(image.data());
+                }
+                FrameItem::Meta(Meta::Link(dest), size) => {
+                    self.tag(4);
+                    self.point(*pos);
+                    self.size(*size);
+                    match dest {
+                        Destination::Url(url) => {
+                            self.tag(0);
+                            self.str(url);
+                        }
+                        Destination::Position(pos) => self.position(*pos),
+                        Destination::Location(loc) => self.position(introspector.position(*loc)),
+                    }
+                }
+                // Other metadata is invisible.
+                FrameItem::Meta(..) => {}
+            }
+        }
+    }
+
+    fn font(&mut self, font: &Font) {
+        let info = font.info();
+        self.str(&info.family);
+        self.tag(match info.variant.style {
+            FontStyle::Normal => 0,
+            FontStyle::Italic => 1,
+            FontStyle::Oblique => 2,
+        });
+        self.0.write(&info.variant.weight.to_number().to_le_bytes());
+        self.ratio(info.variant.stretch.to_ratio());
+        self.u32(font.index());
+        self.usize(font.data().len());
+    }
+
+    fn shape(&mut self, introspector: &Introspector, shape: &Shape) {
+        match &shape.geometry {
+            Geometry::Line(to) => {
+                self.tag(0);
+                self.point(*to);
+            }
+            Geometry::Rect(size) => {
+                self.tag(1);
+                self.size(*size);
+            }
+            Geometry::Path(path) => {
+                self.tag(2);
+                self.path(path);
+            }
+        }
+        match &shape.fill {
+            Some(paint) => {
+                self.tag(1);
+                self.paint(introspector, paint);
+            }
+            None => self.tag(0),
+        }
+        self.stroke(introspector, shape.stroke.as_ref());
+    }
+
+    fn path(&mut self, path: &Path) {
+        self.usize(path.0.len());
+        for item in &path.0 {
+            match item {
+                PathItem::MoveTo(p) => {
+                    self.tag(0);
+                    self.point(*p);
+                }
+                PathItem::LineTo(p) => {
+                    self.tag(1);
+                    self.point(*p);
+                }
+                PathItem::CubicTo(p1, p2, p3) => {
+                    self.tag(2);
+                    self.point(*p1);
+                    self.point(*p2);
+                    self.point(*p3);
+                }
+                PathItem::ClosePath => self.tag(3),
+            }
+        }
+    }
+
+    fn paint(&mut self, introspector: &Introspector, paint: &Paint) {
+        match paint {
+            Paint::Solid(color) => {
+                self.tag(0);
+                self.0.write(&color.to_rgb().to_vec4_u8());
+            }
+            Paint::Gradient(gradient) => {
+                self.tag(1);
+                self.str(&gradient.repr());
+            }
+            Paint::Pattern(pattern) => {
+                self.tag(2);
+                self.size(pattern.size());
+                self.size(pattern.spacing());
+                self.frame(introspector, pattern.frame());
+            }
+        }
+    }
+
+    fn stroke(&mut self, introspector: &Introspector, stroke: Option<&FixedStroke>) {
+        let Some(stroke) = stroke else {
+            self.tag(0);
+            return;
+        };
+
+        self.tag(1);
+        self.paint(introspector, &stroke.paint);
+        self.abs(stroke.thickness);
+        self.tag(match stroke.cap {
+            LineCap::Butt => 0,
+            LineCap::Round => 1,
+            LineCap::Square => 2,
+        });
+        self.tag(match stroke.join {
+            LineJoin::Miter => 0,
+            LineJoin::Round => 1,
+            LineJoin::Bevel => 2,
+        });
+        match &stroke.dash {
+            Some(dash) => {
+                self.tag(1);
+                self.usize(dash.array.len());
+                for length in &dash.array {
+                    self.abs(*length);
+                }
+                self.abs(dash.phase);
+            }
+            None => self.tag(0),
+        }
+        self.quantized(stroke.miter_limit.get(), RATIO_PRECISION);
+    }
+}
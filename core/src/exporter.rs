@@ -95,9 +95,13 @@ where
 }
 
 pub mod builtins {
-    use std::{fs::File, sync::Arc};
+    use std::{fs::File, io::Write, sync::Arc};
 
-    use crate::{exporter_utils::map_err, AsOwnedBytes, AsOwnedString, AsWritable, Transformer};
+    use crate::{
+        exporter_utils::map_err,
+        output::{AtomicFile, OutputRevisions},
+        AsOwnedBytes, AsOwnedString, AsWritable, Transformer,
+    };
 
     use super::{utils, DynExporter, Exporter};
     use ecow::EcoVec;
@@ -178,9 +182,12 @@ pub mod builtins {
         }
     }
 
+    /// Write the output of an exporter to a file atomically, see
+    /// [`crate::output`].
     pub struct FsPathExporter<Writable, E> {
         path: std::path::PathBuf,
         exporter: E,
+        revisions: Option<OutputRevisions>,
 
         as_bytes: std::marker::PhantomData<Writable>,
     }
@@ -190,9 +197,33 @@ pub mod builtins {
             Self {
                 path,
                 exporter,
+                revisions: None,
                 as_bytes: std::marker::PhantomData,
             }
         }
+
+        /// Write each export to a new revision of the file, to which the path
+        /// links.
+        pub fn with_revisions(mut self, revisions: OutputRevisions) -> Self {
+            self.revisions = Some(revisions);
+            self
+        }
+
+        fn create(&self) -> SourceResult<AtomicFile> {
+            match &self.revisions {
+                Some(revisions) => revisions.create(&self.path),
+                None => AtomicFile::create(&self.path),
+            }
+            .map_err(map_err)
+        }
+
+        fn commit(&self, file: AtomicFile) -> SourceResult<()> {
+            match &self.revisions {
+                Some(revisions) => revisions.commit(&self.path, file),
+                None => file.commit(),
+            }
+            .map_err(map_err)
+        }
     }
 
     impl<I, Bytes, E> Exporter<I> for FsPathExporter<Bytes, E>
@@ -202,8 +233,9 @@ pub mod builtins {
     {
         fn export(&self, world: &dyn World, output: Arc<I>) -> SourceResult<()> {
             let vec = self.exporter.export(world, output)?;
-            std::fs::write(&self.path, vec.as_ref()).map_err(map_err)?;
-            Ok(())
+            let mut file = self.create()?;
+            file.write_all(vec.as_ref()).map_err(map_err)?;
+            self.commit(file)
         }
     }
 
@@ -212,10 +244,12 @@ pub mod builtins {
         E: Transformer<(Arc<I>, File)>,
     {
         fn export(&self, world: &dyn World, output: Arc<I>) -> SourceResult<()> {
-            let file = std::fs::File::create(&self.path).map_err(map_err)?;
+            let file = self.create()?;
+            let writable = file.file().try_clone().map_err(map_err)?;
 
-            self.exporter.export(world, (output, file))?;
-            Ok(())
+            // The file is removed rather than committed if the export fails.
+            self.exporter.export(world, (output, writable))?;
+            self.commit(file)
         }
     }
 
@@ -0,0 +1,512 @@
+//! Iterate over the items of documents in the absolute coordinates of pages.
+//!
+//! Frames nest groups, each of which may transform and clip its content.
+//! Tools locating items on pages, e.g. for jumps, text extraction or search
+//! highlighting, need the transforms and clips of all the enclosing groups.
+//! [`flatten_frames`] walks the frames once and composes them, yielding the
+//! leaves of the frame trees as [`PlacedItem`]s, in the order of painting.
+//!
+//! The clips are approximated by the bounding boxes of the clip paths, which
+//! are intersected through the nested groups.
+
+use typst::{
+    introspection::Meta,
+    layout::{Frame, FrameItem, Point, Size, Transform},
+    model::Destination,
+    syntax::Span,
+    text::TextItem,
+    visualize::{Geometry, Image, Path, PathItem, Shape},
+};
+
+use crate::TypstDocument;
+
+/// An axis-aligned bounding box in the coordinates of a page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BBox {
+    /// The top-left corner.
+    pub min: Point,
+    /// The bottom-right corner.
+    pub max: Point,
+}
+
+impl BBox {
+    /// Create the bounding box of a rectangle.
+    pub fn from_rect(origin: Point, size: Size) -> Self {
+        Self {
+            min: origin,
+            max: origin + size.to_point(),
+        }
+    }
+
+    /// Create the bounding box of some points, which is `None` if there are
+    /// no points.
+    pub fn from_points(points: impl IntoIterator<Item = Point>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |bbox, p| Self {
+                min: Point::new(bbox.min.x.min(p.x), bbox.min.y.min(p.y)),
+                max: Point::new(bbox.max.x.max(p.x), bbox.max.y.max(p.y)),
+            },
+        ))
+    }
+
+    /// Get the bounding box of the transformed corners of this box.
+    pub fn transform(self, ts: Transform) -> Self {
+        let corners = [
+            self.min,
+            Point::new(self.max.x, self.min.y),
+            Point::new(self.min.x, self.max.y),
+            self.max,
+        ];
+        Self::from_points(corners.map(|p| p.transform(ts))).unwrap_or(self)
+    }
+
+    /// Intersect two boxes, which is an empty box at the corner of the
+    /// overlap if they don't overlap.
+    pub fn intersect(self, other: Self) -> Self {
+        let min = Point::new(self.min.x.max(other.min.x), self.min.y.max(other.min.y));
+        let max = Point::new(self.max.x.min(other.max.x), self.max.y.min(other.max.y));
+        Self {
+            min,
+            max: Point::new(max.x.max(min.x), max.y.max(min.y)),
+        }
+    }
+
+    /// Whether the box covers no area.
+    pub fn is_empty(&self) -> bool {
+        self.min.x >= self.max.x || self.min.y >= self.max.y
+    }
+
+    /// Whether the box contains a point, including its edges.
+    pub fn contains(&self, p: Point) -> bool {
+        self.min.x <= p.x && p.x <= self.max.x && self.min.y <= p.y && p.y <= self.max.y
+    }
+
+    pub fn size(&self) -> Size {
+        Size::new(self.max.x - self.min.x, self.max.y - self.min.y)
+    }
+}
+
+/// A leaf item of a frame tree placed on a page, see [`flatten_frames`].
+#[derive(Debug, Clone, Copy)]
+pub struct PlacedItem<'a> {
+    /// The 1-based page number.
+    pub page: usize,
+    /// The transform from the coordinates of the item to those of the page,
+    /// including the position of the item.
+    pub transform: Transform,
+    /// The bounding box of the item on the page, ignoring the clips. The box
+    /// of text spans from the baseline up by the font size.
+    pub bbox: BBox,
+    /// The composed clips of the enclosing groups on the page, which is
+    /// `None` if the item is not clipped.
+    pub clip: Option<BBox>,
+    pub item: &'a FrameItem,
+    /// The span of the item, i.e. that of the first attached glyph of text,
+    /// which is detached for metadata.
+    pub span: Span,
+}
+
+impl PlacedItem<'_> {
+    /// Get the visible part of the bounding box, i.e. clipped by the clips.
+    pub fn visible_bbox(&self) -> BBox {
+        match self.clip {
+            Some(clip) => self.bbox.intersect(clip),
+            None => self.bbox,
+        }
+    }
+
+    /// Transform a point in the coordinates of the item to the page.
+    pub fn to_page(&self, p: Point) -> Point {
+        p.transform(self.transform)
+    }
+}
+
+/// Iterate over the leaf items of all pages in a document, see the
+/// [module docs](self).
+pub fn flatten_frames(doc: &TypstDocument) -> FlattenFrames<'_> {
+    let frames = doc.pages.iter().enumerate();
+    FlattenFrames::new(frames.map(|(idx, page)| (idx + 1, &page.frame)).collect())
+}
+
+/// Iterate over the leaf items of a frame, which is placed on the given
+/// 1-based page.
+pub fn flatten_frame(frame: &Frame, page: usize) -> FlattenFrames<'_> {
+    FlattenFrames::new(vec![(page, frame)])
+}
+
+/// Iterate over the text items of a document, see [`flatten_frames`].
+pub fn text_items(doc: &TypstDocument) -> impl Iterator<Item = (PlacedItem<'_>, &TextItem)> {
+    flatten_frames(doc).filter_map(|placed| match placed.item {
+        FrameItem::Text(text) => Some((placed, text)),
+        _ => None,
+    })
+}
+
+/// Iterate over the shapes of a document, see [`flatten_frames`].
+pub fn shape_items(doc: &TypstDocument) -> impl Iterator<Item = (PlacedItem<'_>, &Shape)> {
+    flatten_frames(doc).filter_map(|placed| match placed.item {
+        FrameItem::Shape(shape, _) => Some((placed, shape)),
+        _ => None,
+    })
+}
+
+/// Iterate over the images of a document, see [`flatten_frames`].
+pub fn image_items(doc: &TypstDocument) -> impl Iterator<Item = (PlacedItem<'_>, &Image)> {
+    flatten_frames(doc).filter_map(|placed| match placed.item {
+        FrameItem::Image(image, _, _) => Some((placed, image)),
+        _ => None,
+    })
+}
+
+/// Iterate over the links of a document, see [`flatten_frames`].
+pub fn link_items(doc: &TypstDocument) -> impl Iterator<Item = (PlacedItem<'_>, &Destination)> {
+    flatten_frames(doc).filter_map(|placed| match placed.item {
+        FrameItem::Meta(Meta::Link(dest), _) => Some((placed, dest)),
+        _ => None,
+    })
+}
+
+/// The iterator of [`flatten_frames`].
+pub struct FlattenFrames<'a> {
+    /// The frames of the pages to walk, the last first.
+    frames: Vec<(usize, &'a Frame)>,
+    /// The page of the frame being walked.
+    page: usize,
+    /// The groups being walked, the innermost last.
+    stack: Vec<Level<'a>>,
+}
+
+/// A frame being walked with its composed transform and clip.
+struct Level<'a> {
+    items: std::slice::Iter<'a, (Point, FrameItem)>,
+    transform: Transform,
+    clip: Option<BBox>,
+}
+
+impl<'a> FlattenFrames<'a> {
+    fn new(mut frames: Vec<(usize, &'a Frame)>) -> Self {
+        frames.reverse();
+        Self {
+            frames,
+            page: 0,
+            stack: vec![],
+        }
+    }
+}
+
+impl<'a> Iterator for FlattenFrames<'a> {
+    type Item = PlacedItem<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(level) = self.stack.last_mut() else {
+                let (page, frame) = self.frames.pop()?;
+                self.page = page;
+                self.stack.push(Level {
+                    items: frame.items(),
+                    transform: Transform::identity(),
+                    clip: None,
+                });
+                continue;
+            };
+            let Some((pos, item)) = level.items.next() else {
+                self.stack.pop();
+                continue;
+            };
+
+            let transform = (level.transform).pre_concat(Transform::translate(pos.x, pos.y));
+            let clip = level.clip;
+            let FrameItem::Group(group) = item else {
+                return Some(place(self.page, transform, clip, item));
+            };
+
+            let transform = transform.pre_concat(group.transform);
+            let clip = match &group.clip_path {
+                Some(path) => {
+                    // An empty clip path hides the whole group.
+                    let bbox = path_bbox(path).map(|bbox| bbox.transform(transform));
+                    let bbox = bbox.unwrap_or_else(|| BBox::from_rect(Point::zero(), Size::zero()));
+                    Some(clip.map_or(bbox, |clip| clip.intersect(bbox)))
+                }
+                None => clip,
+            };
+            self.stack.push(Level {
+                items: group.frame.items(),
+                transform,
+                clip,
+            });
+        }
+    }
+}
+
+fn place(
+    page: usize,
+    transform: Transform,
+    clip: Option<BBox>,
+    item: &FrameItem,
+) -> PlacedItem<'_> {
+    let (local, span) = match item {
+        FrameItem::Text(text) => {
+            let span = (text.glyphs.iter())
+                .map(|glyph| glyph.span.0)
+                .find(|span| !span.is_detached())
+                .unwrap_or_else(Span::detached);
+            let origin = Point::with_y(-text.size);
+            (
+                BBox::from_rect(origin, Size::new(text.width(), text.size)),
+                span,
+            )
+        }
+        FrameItem::Shape(shape, span) => (shape_bbox(shape), *span),
+        FrameItem::Image(_, size, span) => (BBox::from_rect(Point::zero(), *size), *span),
+        FrameItem::Meta(_, size) => (BBox::from_rect(Point::zero(), *size), Span::detached()),
+        FrameItem::Group(group) => {
+            let size = group.frame.size();
+            (BBox::from_rect(Point::zero(), size), Span::detached())
+        }
+    };
+
+    PlacedItem {
+        page,
+        transform,
+        bbox: local.transform(transform),
+        clip,
+        item,
+        span,
+    }
+}
+
+/// Get the bounding box of the geometry of a shape, ignoring its stroke.
+fn shape_bbox(shape: &Shape) -> BBox {
+    let origin = BBox::from_rect(Point::zero(), Size::zero());
+    match &shape.geometry {
+        Geometry::Line(to) => BBox::from_points([Point::zero(), *to]).unwrap_or(origin),
+        Geometry::Rect(size) => BBox::from_rect(Point::zero(), *size),
+        Geometry::Path(path) => path_bbox(path).unwrap_or(origin),
+    }
+}
+
+/// Get the bounding box of the points of a path, including the control
+/// points of curves, which is `None` for an empty path.
+fn path_bbox(path: &Path) -> Option<BBox> {
+    BBox::from_points(path.0.iter().flat_map(|item| match item {
+        PathItem::MoveTo(p) | PathItem::LineTo(p) => vec![*p],
+        PathItem::CubicTo(p1, p2, p3) => vec![*p1, *p2, *p3],
+        PathItem::ClosePath => vec![],
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::layout::{Abs, Angle, GroupItem, Ratio};
+
+    use super::*;
+
+    /// A deterministic generator of pseudo-random numbers in `[0, 1)`.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+
+        fn abs(&mut self, max: f64) -> Abs {
+            Abs::pt(self.next() * max)
+        }
+    }
+
+    fn gen_frame(rng: &mut Lcg, depth: usize) -> Frame {
+        let mut frame = Frame::soft(Size::new(rng.abs(200.), rng.abs(200.)));
+        for _ in 0..1 + (rng.next() * 4.) as usize {
+            let pos = Point::new(rng.abs(100.), rng.abs(100.));
+            if depth > 0 && rng.next() < 0.5 {
+                let mut group = GroupItem::new(gen_frame(rng, depth - 1));
+                group.transform = match (rng.next() * 4.) as usize {
+                    0 => Transform::rotate(Angle::deg(rng.next() * 360.)),
+                    1 => {
+                        Transform::scale(Ratio::new(0.5 + rng.next()), Ratio::new(0.5 + rng.next()))
+                    }
+                    2 => Transform::rotate(Angle::deg(rng.next() * 90.))
+                        .pre_concat(Transform::scale(Ratio::new(2.), Ratio::new(0.5))),
+                    _ => Transform::identity(),
+                };
+                if rng.next() < 0.5 {
+                    group.clip_path = Some(Path::rect(Size::new(rng.abs(150.), rng.abs(150.))));
+                }
+                frame.push(pos, FrameItem::Group(group));
+            } else {
+                let size = Size::new(rng.abs(50.), rng.abs(50.));
+                let shape = Shape {
+                    geometry: Geometry::Rect(size),
+                    fill: None,
+                    stroke: None,
+                };
+                frame.push(pos, FrameItem::Shape(shape, Span::detached()));
+            }
+        }
+        frame
+    }
+
+    /// An affine matrix `[a, b, c, d, e, f]` mapping `(x, y)` to
+    /// `(a x + c y + e, b x + d y + f)`.
+    type Matrix = [f64; 6];
+
+    fn matrix_of(ts: Transform) -> Matrix {
+        let [a, b, c, d] = [ts.sx, ts.ky, ts.kx, ts.sy].map(|r| r.get());
+        [a, b, c, d, ts.tx.to_pt(), ts.ty.to_pt()]
+    }
+
+    fn mul(m: Matrix, n: Matrix) -> Matrix {
+        [
+            m[0] * n[0] + m[2] * n[1],
+            m[1] * n[0] + m[3] * n[1],
+            m[0] * n[2] + m[2] * n[3],
+            m[1] * n[2] + m[3] * n[3],
+            m[0] * n[4] + m[2] * n[5] + m[4],
+            m[1] * n[4] + m[3] * n[5] + m[5],
+        ]
+    }
+
+    fn apply(m: Matrix, (x, y): (f64, f64)) -> (f64, f64) {
+        (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+    }
+
+    /// The bounding box `(min_x, min_y, max_x, max_y)` of transformed points.
+    fn bounds(m: Matrix, points: &[(f64, f64)]) -> [f64; 4] {
+        let points: Vec<_> = points.iter().map(|p| apply(m, *p)).collect();
+        let xs = points.iter().map(|p| p.0);
+        let ys = points.iter().map(|p| p.1);
+        [
+            xs.clone().fold(f64::INFINITY, f64::min),
+            ys.clone().fold(f64::INFINITY, f64::min),
+            xs.fold(f64::NEG_INFINITY, f64::max),
+            ys.fold(f64::NEG_INFINITY, f64::max),
+        ]
+    }
+
+    fn rect_points(w: f64, h: f64) -> [(f64, f64); 4] {
+        [(0., 0.), (w, 0.), (0., h), (w, h)]
+    }
+
+    /// Walk a frame tree recursively, collecting the bounding boxes and the
+    /// clips of the shapes.
+    fn reference(
+        frame: &Frame,
+        m: Matrix,
+        clip: Option<[f64; 4]>,
+        out: &mut Vec<([f64; 4], Option<[f64; 4]>)>,
+    ) {
+        for (pos, item) in frame.items() {
+            let translate = [1., 0., 0., 1., pos.x.to_pt(), pos.y.to_pt()];
+            let m = mul(m, translate);
+            match item {
+                FrameItem::Group(group) => {
+                    let m = mul(m, matrix_of(group.transform));
+                    let clip = match &group.clip_path {
+                        Some(path) => {
+                            let points: Vec<_> = (path.0.iter())
+                                .filter_map(|item| match item {
+                                    PathItem::MoveTo(p) | PathItem::LineTo(p) => {
+                                        Some((p.x.to_pt(), p.y.to_pt()))
+                                    }
+                                    _ => None,
+                                })
+                                .collect();
+                            let b = bounds(m, &points);
+                            Some(match clip {
+                                Some(c) => {
+                                    let (x0, y0) = (c[0].max(b[0]), c[1].max(b[1]));
+                                    [x0, y0, c[2].min(b[2]).max(x0), c[3].min(b[3]).max(y0)]
+                                }
+                                None => b,
+                            })
+                        }
+                        None => clip,
+                    };
+                    reference(&group.frame, m, clip, out);
+                }
+                FrameItem::Shape(shape, _) => {
+                    let Geometry::Rect(size) = shape.geometry else {
+                        unreachable!()
+                    };
+                    let points = rect_points(size.x.to_pt(), size.y.to_pt());
+                    out.push((bounds(m, &points), clip));
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn assert_close(actual: BBox, expected: [f64; 4]) {
+        let actual = [actual.min.x, actual.min.y, actual.max.x, actual.max.y].map(Abs::to_pt);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn test_flatten_generated_frames() {
+        let mut rng = Lcg(42);
+        for _ in 0..200 {
+            let frame = gen_frame(&mut rng, 3);
+            let identity = [1., 0., 0., 1., 0., 0.];
+            let mut expected = vec![];
+            reference(&frame, identity, None, &mut expected);
+
+            let actual: Vec<_> = flatten_frame(&frame, 1).collect();
+            assert_eq!(actual.len(), expected.len());
+            for (placed, (bbox, clip)) in actual.iter().zip(expected) {
+                assert_eq!(placed.page, 1);
+                assert_close(placed.bbox, bbox);
+                assert_eq!(placed.clip.is_some(), clip.is_some());
+                if let (Some(actual), Some(clip)) = (placed.clip, clip) {
+                    assert_close(actual, clip);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_flatten_nested_clips() {
+        let shape = Shape {
+            geometry: Geometry::Rect(Size::new(Abs::pt(10.), Abs::pt(10.))),
+            fill: None,
+            stroke: None,
+        };
+        let mut inner = Frame::soft(Size::new(Abs::pt(100.), Abs::pt(100.)));
+        inner.push(Point::zero(), FrameItem::Shape(shape, Span::detached()));
+
+        // A scaled group clipped to 20x20, nested in a group clipped to its
+        // left half on the page.
+        let mut scaled = GroupItem::new(inner);
+        scaled.transform = Transform::scale(Ratio::new(2.), Ratio::new(2.));
+        scaled.clip_path = Some(Path::rect(Size::new(Abs::pt(20.), Abs::pt(20.))));
+        let mut middle = Frame::soft(Size::new(Abs::pt(100.), Abs::pt(100.)));
+        middle.push(
+            Point::new(Abs::pt(5.), Abs::pt(5.)),
+            FrameItem::Group(scaled),
+        );
+        let mut outer = GroupItem::new(middle);
+        outer.clip_path = Some(Path::rect(Size::new(Abs::pt(15.), Abs::pt(100.))));
+        let mut page = Frame::soft(Size::new(Abs::pt(100.), Abs::pt(100.)));
+        page.push(Point::zero(), FrameItem::Group(outer));
+
+        let items: Vec<_> = flatten_frame(&page, 3).collect();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].page, 3);
+        assert_close(items[0].bbox, [5., 5., 25., 25.]);
+        assert_close(items[0].clip.unwrap(), [5., 5., 15., 45.]);
+        assert_close(items[0].visible_bbox(), [5., 5., 15., 25.]);
+        assert_eq!(
+            items[0].to_page(Point::new(Abs::pt(1.), Abs::pt(1.))),
+            Point::new(Abs::pt(7.), Abs::pt(7.))
+        );
+    }
+}
@@ -6,11 +6,12 @@ use std::{
 
 use comemo::Prehashed;
 use reflexo::debug_loc::DataSource;
+use serde::Serialize;
 use typst::text::{Font, FontBook, FontInfo};
 
 use crate::{Bytes, FontSlot};
 
-use super::{BufferFontLoader, FontProfile, PartialFontBook};
+use super::{BufferFontLoader, FontOrigin, FontProfile, PartialFontBook};
 
 /// A FontResolver can resolve a font by index.
 /// It also reuse FontBook for font-related query.
@@ -35,6 +36,36 @@ pub trait FontResolver {
     fn get_by_info(&self, info: &FontInfo) -> Option<Font> {
         self.default_get_by_info(info)
     }
+
+    /// Get the total size of the loaded font data in bytes.
+    fn loaded_font_bytes(&self) -> usize {
+        0
+    }
+
+    /// Get the source of a loaded font if known.
+    fn describe_font(&self, _font: &Font) -> Option<Arc<DataSource>> {
+        None
+    }
+
+    /// Get where the data of a font in the book comes from if known.
+    fn font_origin(&self, _idx: usize) -> Option<FontOrigin> {
+        None
+    }
+
+    /// Get the fonts which are skipped since they fail to load.
+    fn load_errors(&self) -> Vec<FontLoadError> {
+        vec![]
+    }
+}
+
+/// A font which is skipped since it fails to load, e.g. a corrupt font
+/// uploaded by a user, see [`FontResolver::load_errors`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontLoadError {
+    /// The path of the font file, or `<memory>` for the data in memory.
+    pub source: String,
+    pub message: String,
 }
 
 #[derive(Debug)]
@@ -44,6 +75,7 @@ pub struct FontResolverImpl {
     partial_book: Arc<Mutex<PartialFontBook>>,
     fonts: Vec<FontSlot>,
     profile: FontProfile,
+    load_errors: Vec<FontLoadError>,
 }
 
 impl FontResolverImpl {
@@ -58,9 +90,17 @@ impl FontResolverImpl {
             partial_book,
             fonts,
             profile,
+            load_errors: vec![],
         }
     }
 
+    /// Record the fonts skipped when the fonts are searched, see
+    /// [`FontResolver::load_errors`].
+    pub fn with_load_errors(mut self, load_errors: Vec<FontLoadError>) -> Self {
+        self.load_errors = load_errors;
+        self
+    }
+
     pub fn len(&self) -> usize {
         self.fonts.len()
     }
@@ -181,6 +221,22 @@ impl FontResolver for FontResolverImpl {
     fn get_by_info(&self, info: &FontInfo) -> Option<Font> {
         FontResolver::default_get_by_info(self, info)
     }
+
+    fn loaded_font_bytes(&self) -> usize {
+        self.loaded_fonts().map(|(_, font)| font.data().len()).sum()
+    }
+
+    fn describe_font(&self, font: &Font) -> Option<Arc<DataSource>> {
+        FontResolverImpl::describe_font(self, font)
+    }
+
+    fn font_origin(&self, idx: usize) -> Option<FontOrigin> {
+        self.fonts.get(idx).map(|slot| slot.origin)
+    }
+
+    fn load_errors(&self) -> Vec<FontLoadError> {
+        self.load_errors.clone()
+    }
 }
 
 impl fmt::Display for FontResolverImpl {
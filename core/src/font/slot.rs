@@ -2,16 +2,31 @@ use core::fmt;
 use std::sync::Arc;
 
 use reflexo::debug_loc::DataSource;
+use serde::{Deserialize, Serialize};
 use typst::text::Font;
 
 use crate::{FontLoader, QueryRef};
 
 type FontSlotInner = QueryRef<Option<Font>, (), Box<dyn FontLoader + Send>>;
 
+/// Where the data of a font comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FontOrigin {
+    /// Embedded into the binary or added as data in memory.
+    #[default]
+    Embedded,
+    /// Found in a font path given by the user.
+    User,
+    /// Found in the font directories of the system.
+    System,
+}
+
 /// Lazy Font Reference, load as needed.
 pub struct FontSlot {
     inner: FontSlotInner,
     pub description: Option<Arc<DataSource>>,
+    pub origin: FontOrigin,
 }
 
 impl FontSlot {
@@ -19,6 +34,7 @@ impl FontSlot {
         Self {
             inner: FontSlotInner::with_value(f),
             description: None,
+            origin: FontOrigin::default(),
         }
     }
 
@@ -26,6 +42,7 @@ impl FontSlot {
         Self {
             inner: FontSlotInner::with_context(f),
             description: None,
+            origin: FontOrigin::default(),
         }
     }
 
@@ -37,9 +54,15 @@ impl FontSlot {
         Self {
             inner: self.inner,
             description: Some(Arc::new(desc)),
+            origin: self.origin,
         }
     }
 
+    pub fn with_origin(mut self, origin: FontOrigin) -> Self {
+        self.origin = origin;
+        self
+    }
+
     /// Gets the reference to the font load result (possible uninitialized).
     ///
     /// Returns `None` if the cell is empty, or being initialized. This
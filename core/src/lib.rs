@@ -8,15 +8,21 @@ pub use concepts::*;
 
 // Core data structures of typst-ts.
 // todo: move me to compiler
+pub mod artifact_cache;
 pub mod cache;
 pub mod config;
+pub mod content_hash;
 pub mod debug_loc;
 pub mod error;
+pub mod flatten;
 pub mod font;
 pub mod package;
+pub mod segment;
+pub mod slug;
 
 // Core mechanism of typst-ts.
 pub(crate) mod exporter;
+pub mod output;
 
 // Intermediate representation of typst-ts.
 pub mod vector;
@@ -0,0 +1,312 @@
+//! Write the outputs of exporters atomically, so that a reader, e.g. a PDF
+//! viewer reloading the output in watch mode, never sees a truncated file,
+//! and a failed export leaves the previous output untouched.
+//!
+//! An output is written to a temporary file in the directory of the target,
+//! which is renamed over the target once it is complete, see [`AtomicFile`].
+//!
+//! With [`OutputRevisions`], each export is written to a new revision of the
+//! output instead, e.g. `out.r42.pdf`, while the target `out.pdf` links to
+//! the latest revision. The link is a symbolic link on unix, and a copy on
+//! the other platforms. Only the last revisions are kept.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use parking_lot::Mutex;
+
+/// A file written in place of a target, see the [module docs](self).
+///
+/// The temporary file is removed if the file is dropped without
+/// [`AtomicFile::commit`].
+#[derive(Debug)]
+pub struct AtomicFile {
+    target: PathBuf,
+    temp: PathBuf,
+    file: Option<File>,
+}
+
+impl AtomicFile {
+    pub fn create(target: impl Into<PathBuf>) -> io::Result<Self> {
+        let target = target.into();
+        let temp = temp_path(&target);
+        let file = File::create(&temp)?;
+        Ok(Self {
+            target,
+            temp,
+            file: Some(file),
+        })
+    }
+
+    /// The path which the file is renamed to once it is committed.
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    /// The temporary file being written.
+    pub fn file(&self) -> &File {
+        self.file.as_ref().unwrap()
+    }
+
+    /// Rename the file over the target.
+    pub fn commit(mut self) -> io::Result<()> {
+        // The file is closed before it is renamed, which is required on
+        // windows.
+        drop(self.file.take());
+        std::fs::rename(&self.temp, &self.target)
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().unwrap().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // The file is removed if it isn't renamed, including if the renaming
+        // fails.
+        drop(self.file.take());
+        let _ = std::fs::remove_file(&self.temp);
+    }
+}
+
+/// Write the data to a file atomically, see the [module docs](self).
+pub fn write_atomic(path: impl AsRef<Path>, data: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut file = AtomicFile::create(path.as_ref())?;
+    file.write_all(data.as_ref())?;
+    file.commit()
+}
+
+/// Get a unique hidden path in the directory of the target, e.g.
+/// `.out.pdf.1234.0.tmp` of `out.pdf`.
+fn temp_path(target: &Path) -> PathBuf {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{name}.{}.{id}.tmp", std::process::id()))
+}
+
+/// The revisions of an output, see the [module docs](self).
+#[derive(Debug)]
+pub struct OutputRevisions {
+    extension: Option<String>,
+    retention: usize,
+    /// The last revision, which is looked up in the directory of the output at
+    /// the first export.
+    last: Mutex<Option<u64>>,
+}
+
+impl OutputRevisions {
+    /// Keep the last `retention` revisions, at least the latest one.
+    pub fn new(retention: usize) -> Self {
+        Self {
+            extension: None,
+            retention: retention.max(1),
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Set the extension of the outputs, before which the revision is
+    /// inserted, e.g. `artifact.sir.in` for `out.r42.artifact.sir.in`. It is
+    /// the last extension of an output by default.
+    pub fn with_extension(mut self, extension: impl Into<String>) -> Self {
+        self.extension = Some(extension.into());
+        self
+    }
+
+    /// Get the path of a revision of the target, e.g. `out.r42.pdf` of
+    /// `out.pdf`.
+    pub fn revision_path(&self, target: &Path, revision: u64) -> PathBuf {
+        let (stem, suffix) = self.split_name(target);
+        target.with_file_name(format!("{stem}.r{revision}{suffix}"))
+    }
+
+    /// Create the file of a new revision of the target.
+    pub fn create(&self, target: &Path) -> io::Result<AtomicFile> {
+        let revision = {
+            let mut last = self.last.lock();
+            let revision = match *last {
+                Some(last) => last,
+                None => self.revisions(target)?.last().copied().unwrap_or(0),
+            } + 1;
+            *last = Some(revision);
+            revision
+        };
+        AtomicFile::create(self.revision_path(target, revision))
+    }
+
+    /// Commit the file of a revision, link the target to it, and then remove
+    /// the revisions beyond the retention.
+    ///
+    /// The export has succeeded once the target is linked, hence a failure of
+    /// removing the outdated revisions is only logged, and retried by the
+    /// next commit.
+    pub fn commit(&self, target: &Path, file: AtomicFile) -> io::Result<()> {
+        let revision = file.target().to_owned();
+        file.commit()?;
+        link_latest(target, &revision)?;
+
+        let revisions = match self.revisions(target) {
+            Ok(revisions) => revisions,
+            Err(err) => {
+                log::warn!("failed to list the revisions of {target:?}: {err}");
+                return Ok(());
+            }
+        };
+        let outdated = revisions.len().saturating_sub(self.retention);
+        for revision in &revisions[..outdated] {
+            let path = self.revision_path(target, *revision);
+            if let Err(err) = std::fs::remove_file(&path) {
+                log::warn!("failed to remove the outdated revision {path:?}: {err}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the existing revisions of the target in ascending order.
+    pub fn revisions(&self, target: &Path) -> io::Result<Vec<u64>> {
+        let dir = match target.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+
+        let (stem, suffix) = self.split_name(target);
+        let prefix = format!("{stem}.r");
+        let mut revisions = vec![];
+        for entry in entries {
+            let name = entry?.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            let revision = (name.strip_prefix(&prefix))
+                .and_then(|name| name.strip_suffix(&suffix))
+                .filter(|revision| revision.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|revision| revision.parse::<u64>().ok());
+            revisions.extend(revision);
+        }
+        revisions.sort_unstable();
+        Ok(revisions)
+    }
+
+    /// Split the name of the target into the stem and the extension with its
+    /// leading dot.
+    fn split_name(&self, target: &Path) -> (String, String) {
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        let suffix = match &self.extension {
+            Some(ext) => Some(format!(".{ext}")).filter(|suffix| name.ends_with(suffix)),
+            None => (target.extension()).map(|ext| format!(".{}", ext.to_string_lossy())),
+        };
+        match suffix {
+            Some(suffix) => (name[..name.len() - suffix.len()].to_owned(), suffix),
+            None => (name.into_owned(), String::new()),
+        }
+    }
+}
+
+/// Link the target to the latest revision atomically.
+fn link_latest(target: &Path, revision: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        // The link is relative so that the directory can be moved.
+        let temp = temp_path(target);
+        std::os::unix::fs::symlink(revision.file_name().unwrap(), &temp)?;
+        std::fs::rename(&temp, target).inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp);
+        })
+    }
+
+    #[cfg(not(unix))]
+    {
+        let mut file = AtomicFile::create(target)?;
+        io::copy(&mut File::open(revision)?, &mut file)?;
+        file.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = (std::fs::read_dir(dir).unwrap())
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let out = dir.join("out.pdf");
+        write_atomic(&out, b"old").unwrap();
+
+        // An export killed mid-write leaves the previous output untouched.
+        let mut file = AtomicFile::create(&out).unwrap();
+        file.write_all(b"trunc").unwrap();
+        drop(file);
+        assert_eq!(std::fs::read(&out).unwrap(), b"old");
+        assert_eq!(dir_names(dir), ["out.pdf"]);
+
+        write_atomic(&out, b"new").unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), b"new");
+        assert_eq!(dir_names(dir), ["out.pdf"]);
+    }
+
+    #[test]
+    fn test_output_revisions() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let out = dir.join("out.pdf");
+        let write = |revisions: &OutputRevisions, data: &[u8]| {
+            let mut file = revisions.create(&out).unwrap();
+            file.write_all(data).unwrap();
+            revisions.commit(&out, file).unwrap();
+        };
+
+        let revisions = OutputRevisions::new(2);
+        assert_eq!(revisions.revision_path(&out, 42), dir.join("out.r42.pdf"));
+        write(&revisions, b"1");
+        write(&revisions, b"2");
+        assert_eq!(std::fs::read(&out).unwrap(), b"2");
+        assert_eq!(dir_names(dir), ["out.pdf", "out.r1.pdf", "out.r2.pdf"]);
+
+        // A failed export leaves the latest revision untouched.
+        let mut file = revisions.create(&out).unwrap();
+        file.write_all(b"trunc").unwrap();
+        drop(file);
+        assert_eq!(std::fs::read(&out).unwrap(), b"2");
+        assert_eq!(dir_names(dir), ["out.pdf", "out.r1.pdf", "out.r2.pdf"]);
+
+        // The revisions continue from the existing ones, and the outdated
+        // ones are removed.
+        let revisions = OutputRevisions::new(2);
+        write(&revisions, b"3");
+        assert_eq!(std::fs::read(&out).unwrap(), b"3");
+        assert_eq!(dir_names(dir), ["out.pdf", "out.r2.pdf", "out.r3.pdf"]);
+        #[cfg(unix)]
+        assert_eq!(std::fs::read_link(&out).unwrap(), Path::new("out.r3.pdf"));
+
+        let revisions = OutputRevisions::new(1).with_extension("artifact.sir.in");
+        assert_eq!(
+            revisions.revision_path(&dir.join("out.artifact.sir.in"), 42),
+            dir.join("out.r42.artifact.sir.in")
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! Segment the text of documents into lines and words, shared by the text
+//! exporter, the word counts and the search in documents, so that their
+//! numbers agree.
+//!
+//! The text runs of a document, see [`text_runs`], are grouped into lines by
+//! their baselines, see [`reflow_lines`], which are joined into the plain text
+//! of the document, see [`join_lines`]. A word broken at the end of a line,
+//! either hyphenated by the layouter or at a hyphen of the text, is rejoined
+//! with its rest on the next line, and soft hyphens are dropped.
+//!
+//! The words of a line, see [`segment_words`], are the words of the Unicode
+//! word boundaries (UAX #29), where
+//! - segments without letters or digits, e.g. spaces and punctuation, aren't
+//!   words,
+//! - segments joined by hyphens are a single word, e.g. `state-of-the-art`,
+//! - each ideograph is a word, e.g. in Chinese,
+//! - in French, Italian and Catalan, an elided word is a word by itself, e.g.
+//!   the `l'` of `l'homme`.
+//!
+//! The scripts written without spaces but not ideographic, e.g. Thai, aren't
+//! segmented by dictionaries, hence a phrase between spaces is a word.
+
+use std::ops::Range;
+
+#[cfg(not(feature = "no-content-hint"))]
+use typst::introspection::Meta;
+use typst::{
+    layout::{Abs, FrameItem, Point},
+    text::Lang,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{flatten, TypstDocument};
+
+/// A run of text in a document, see [`text_runs`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextRun {
+    /// The text of the run, followed by the content hints painted after it,
+    /// e.g. the spaces collapsed by the layouter.
+    pub text: String,
+    pub lang: Lang,
+    /// The 1-based page number.
+    pub page: usize,
+    /// The start of the baseline of the run on the page.
+    pub point: Point,
+    /// The font size of the run.
+    pub size: Abs,
+    /// The raw span of the first attached glyph in the run, which can be
+    /// converted back by [`typst::syntax::Span::from_raw`].
+    pub span_id: u64,
+    /// Whether the layouter hyphenated the last word of the run, which
+    /// continues at the next line.
+    pub hyphenated: bool,
+}
+
+/// A line of text, see [`reflow_lines`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLine {
+    pub text: String,
+    /// The language of the first run of the line.
+    pub lang: Lang,
+    /// The 1-based page number.
+    pub page: usize,
+    /// The vertical position of the baseline of the first run of the line.
+    pub baseline: Abs,
+    /// The largest font size of the runs of the line.
+    pub size: Abs,
+}
+
+/// Collect the text runs of a document in the order of painting, e.g. for
+/// searching in the document.
+///
+/// The content hints are appended to the last run on their page, or
+/// prepended to the next run if none.
+pub fn text_runs(doc: &TypstDocument) -> Vec<TextRun> {
+    let mut runs: Vec<TextRun> = vec![];
+    // The content hints before the first run of a page.
+    let mut pending = String::new();
+    for placed in flatten::flatten_frames(doc) {
+        match placed.item {
+            FrameItem::Text(text) => {
+                runs.push(TextRun {
+                    text: std::mem::take(&mut pending) + text.text.as_str(),
+                    lang: text.lang,
+                    page: placed.page,
+                    point: placed.to_page(Point::zero()),
+                    size: text.size,
+                    span_id: placed.span.into_raw().get(),
+                    // The hyphen inserted by the layouter covers no text.
+                    hyphenated: (text.glyphs.last()).is_some_and(|glyph| glyph.range().is_empty()),
+                });
+            }
+            #[cfg(not(feature = "no-content-hint"))]
+            FrameItem::Meta(Meta::ContentHint(c), _) => match runs.last_mut() {
+                Some(run) if run.page == placed.page => run.text.push(*c),
+                _ => pending.push(*c),
+            },
+            _ => {}
+        }
+    }
+    runs
+}
+
+/// Group the runs into lines, see the [module docs](self).
+///
+/// The runs whose baselines are within half of the font size are on the same
+/// line, e.g. sub- and superscripts.
+pub fn reflow_lines(runs: &[TextRun]) -> Vec<TextLine> {
+    // The lines along with whether their last words continue at the next line.
+    let mut lines: Vec<(TextLine, bool)> = vec![];
+    let mut last: Option<&TextRun> = None;
+    for run in runs {
+        let same_line = last.is_some_and(|last| {
+            let size = last.size.max(run.size).to_pt();
+            let gap = (run.point.y - last.point.y).to_pt().abs();
+            last.page == run.page && gap <= size * 0.5
+        });
+        let text = run.text.replace('\u{ad}', "");
+        if same_line {
+            let (line, hyphenated) = lines.last_mut().unwrap();
+            line.text.push_str(&text);
+            line.size = line.size.max(run.size);
+            *hyphenated = run.hyphenated;
+        } else {
+            let line = TextLine {
+                text,
+                lang: run.lang,
+                page: run.page,
+                baseline: run.point.y,
+                size: run.size,
+            };
+            lines.push((line, run.hyphenated));
+        }
+        last = Some(run);
+    }
+
+    for i in 1..lines.len() {
+        let (prev, rest) = lines.split_at_mut(i);
+        let (prev, hyphenated) = &mut prev[i - 1];
+        let next = &mut rest[0].0;
+        prev.text.truncate(prev.text.trim_end().len());
+        let broken = *hyphenated || prev.text.ends_with(is_hyphen);
+        if !broken || prev.page != next.page {
+            continue;
+        }
+
+        // Move the rest of the broken word to the previous line.
+        let start = next.text.len() - next.text.trim_start().len();
+        let end = (next.text[start..].find(char::is_whitespace))
+            .map_or(next.text.len(), |end| start + end);
+        prev.text.push_str(&next.text[start..end]);
+        next.text.replace_range(..end, "");
+        next.text = next.text.trim_start().to_owned();
+        // The next line only holds the middle of a word broken across more
+        // lines, hence the word continues after it.
+        if next.text.is_empty() {
+            std::mem::swap(prev, next);
+        }
+    }
+
+    (lines.into_iter())
+        .map(|(line, _)| line)
+        .filter(|line| !line.text.trim().is_empty())
+        .collect()
+}
+
+/// The vertical gap between baselines, in the unit of the font size, above
+/// which the lines are considered in different paragraphs.
+const PARAGRAPH_GAP: f64 = 1.6;
+
+/// Join the lines into the text of a document, where the paragraphs are
+/// separated by blank lines and the pages by the delimiter.
+///
+/// The paragraphs are guessed by the vertical gaps between the lines, since
+/// the frames don't retain the structure of the document.
+pub fn join_lines(lines: &[TextLine], page_delimiter: &str) -> String {
+    let mut text = String::new();
+    let mut last: Option<&TextLine> = None;
+    for line in lines {
+        if let Some(last) = last {
+            if last.page != line.page {
+                text.push_str(page_delimiter);
+            } else {
+                let size = last.size.max(line.size).to_pt();
+                let gap = (line.baseline - last.baseline).to_pt().abs();
+                text.push_str(if gap > size * PARAGRAPH_GAP {
+                    "\n\n"
+                } else {
+                    "\n"
+                });
+            }
+        }
+        text.push_str(&line.text);
+        last = Some(line);
+    }
+    text
+}
+
+/// Split a text into its words, see the [module docs](self).
+pub fn segment_words(text: &str, lang: Lang) -> Vec<&str> {
+    let elides = matches!(lang.as_str(), "fr" | "it" | "ca");
+
+    let mut words: Vec<Range<usize>> = vec![];
+    // The end of a hyphen right after the last word, which joins the word to
+    // a word starting there.
+    let mut joint = None;
+    for (start, segment) in text.split_word_bound_indices() {
+        let end = start + segment.len();
+        if segment.chars().all(is_hyphen) {
+            joint = words.last().filter(|word| word.end == start).map(|_| end);
+            continue;
+        }
+        if !segment.chars().any(char::is_alphanumeric) {
+            joint = None;
+            continue;
+        }
+
+        let mut start = start;
+        if elides {
+            if let Some(elided) = elided_len(segment) {
+                words.push(start..start + elided);
+                start += elided;
+            }
+        }
+        match words.last_mut() {
+            Some(word) if joint == Some(start) => word.end = end,
+            _ => words.push(start..end),
+        }
+        joint = None;
+    }
+
+    words.into_iter().map(|word| &text[word]).collect()
+}
+
+/// Count the words of the lines, see [`segment_words`].
+pub fn count_words(lines: &[TextLine]) -> usize {
+    (lines.iter())
+        .map(|line| segment_words(&line.text, line.lang).len())
+        .sum()
+}
+
+fn is_hyphen(c: char) -> bool {
+    matches!(c, '-' | '\u{2010}')
+}
+
+/// Get the length of an elided word at the start of a segment, including its
+/// apostrophe, e.g. `l'` or `qu'`.
+fn elided_len(segment: &str) -> Option<usize> {
+    let (idx, apostrophe) = segment
+        .char_indices()
+        .find(|(_, c)| matches!(c, '\'' | '’'))?;
+    let elided = idx + apostrophe.len_utf8();
+    // Longer words before apostrophes are not elided, e.g. `aujourd'hui`.
+    let short = (1..=5).contains(&segment[..idx].chars().count());
+    (short && elided < segment.len()).then_some(elided)
+}
+
+#[cfg(test)]
+mod tests {
+    use typst::syntax::Span;
+
+    use super::*;
+
+    fn run(text: &str, line: usize, hyphenated: bool) -> TextRun {
+        TextRun {
+            text: text.into(),
+            lang: Lang::ENGLISH,
+            page: 1,
+            point: Point::new(Abs::zero(), Abs::pt(12. * line as f64)),
+            size: Abs::pt(10.),
+            span_id: Span::detached().into_raw().get(),
+            hyphenated,
+        }
+    }
+
+    fn line_texts(lines: &[TextLine]) -> Vec<&str> {
+        lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_reflow_lines() {
+        // The runs of a line are joined, and the words broken by the layouter
+        // or at hyphens are rejoined.
+        let runs = [
+            run("The hy", 0, false),
+            run("phen", 0, true),
+            run("ation of soft\u{ad}", 1, true),
+            run("hyphens in state-of-", 2, false),
+            run("the-art text.", 3, false),
+        ];
+        let lines = reflow_lines(&runs);
+        assert_eq!(
+            line_texts(&lines),
+            [
+                "The hyphenation",
+                "of softhyphens",
+                "in state-of-the-art",
+                "text."
+            ]
+        );
+        assert_eq!(count_words(&lines), 7);
+        assert_eq!(
+            segment_words(&lines[2].text, Lang::ENGLISH),
+            ["in", "state-of-the-art"]
+        );
+
+        // A word is rejoined across more than two lines.
+        let runs = [
+            run("The ex", 0, true),
+            run("traordi", 1, true),
+            run("narily long", 2, false),
+        ];
+        let lines = reflow_lines(&runs);
+        assert_eq!(line_texts(&lines), ["The extraordinarily", "long"]);
+        assert_eq!(lines[0].baseline, Abs::zero());
+
+        // A superscript is on the same line.
+        let mut sup = run("2", 0, false);
+        sup.point.y = Abs::pt(-3.);
+        let lines = reflow_lines(&[run("x", 0, false), sup, run("y", 1, false)]);
+        assert_eq!(line_texts(&lines), ["x2", "y"]);
+    }
+
+    #[test]
+    fn test_join_lines() {
+        let mut runs = vec![run("First", 0, false), run("line.", 1, false)];
+        let mut next = run("Next paragraph.", 3, false);
+        runs.push(next.clone());
+        next.page = 2;
+        runs.push(next);
+        let lines = reflow_lines(&runs);
+        assert_eq!(
+            join_lines(&lines, "\u{c}"),
+            "First\nline.\n\nNext paragraph.\u{c}Next paragraph."
+        );
+    }
+
+    #[test]
+    fn test_segment_words() {
+        let words = segment_words("Hello, world! It's 3.14.", Lang::ENGLISH);
+        assert_eq!(words, ["Hello", "world", "It's", "3.14"]);
+
+        // Each ideograph is a word, unlike the punctuation.
+        let words = segment_words("你好，世界。", Lang::CHINESE);
+        assert_eq!(words, ["你", "好", "世", "界"]);
+
+        // The scripts are segmented by themselves in mixed lines.
+        let words = segment_words("Typst 排版 engine 2024年", Lang::ENGLISH);
+        assert_eq!(words, ["Typst", "排", "版", "engine", "2024", "年"]);
+
+        // Elided words are words in French.
+        let words = segment_words("l'homme aujourd'hui", Lang::FRENCH);
+        assert_eq!(words, ["l'", "homme", "aujourd'hui"]);
+        let words = segment_words("l'homme", Lang::ENGLISH);
+        assert_eq!(words, ["l'homme"]);
+    }
+}
@@ -0,0 +1,106 @@
+//! Generate the anchors of headings, which are shared by the output formats,
+//! e.g. the `id` attributes of HTML and the outline of vector artifacts, so
+//! that a URL fragment like `#sec-installation` works for all of them.
+//!
+//! A slug is derived from the plain text of a heading only, hence it stays
+//! stable as long as the text doesn't change. Duplicate texts are suffixed by
+//! numbers in the order of the document, i.e. `sec-usage`, `sec-usage-1`,
+//! `sec-usage-2`, etc.
+
+use std::collections::HashSet;
+
+/// The prefix of the slugs of headings, which keeps them apart from the
+/// labels used as anchors.
+pub const HEADING_PREFIX: &str = "sec";
+
+/// Convert a text into a slug, i.e. lowercase alphanumeric words separated
+/// by single dashes.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    let mut dash = false;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            if dash && !slug.is_empty() {
+                slug.push('-');
+            }
+            dash = false;
+            slug.extend(c.to_lowercase());
+        } else {
+            dash = true;
+        }
+    }
+    slug
+}
+
+/// Allocate distinct slugs in the order of a document, see the
+/// [module docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct SlugAllocator {
+    used: HashSet<String>,
+}
+
+impl SlugAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocate the slug of a heading of the given plain text.
+    pub fn heading(&mut self, text: &str) -> String {
+        let slug = slugify(text);
+        if slug.is_empty() {
+            self.allocate(HEADING_PREFIX.to_owned())
+        } else {
+            self.allocate(format!("{HEADING_PREFIX}-{slug}"))
+        }
+    }
+
+    /// Allocate a slug, suffixing it by the first number making it distinct
+    /// from the slugs allocated before.
+    pub fn allocate(&mut self, base: String) -> String {
+        if !self.used.contains(&base) {
+            self.used.insert(base.clone());
+            return base;
+        }
+
+        (1..)
+            .map(|n| format!("{base}-{n}"))
+            .find(|slug| self.used.insert(slug.clone()))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Installation"), "installation");
+        assert_eq!(
+            slugify("  Getting Started: A Tour!"),
+            "getting-started-a-tour"
+        );
+        assert_eq!(slugify("Über C++ & Rust"), "über-c-rust");
+        assert_eq!(slugify("--"), "");
+    }
+
+    #[test]
+    fn test_duplicate_slugs() {
+        let mut slugs = SlugAllocator::new();
+        let allocated: Vec<_> = ["Usage", "Usage", "Usage 1", "usage", "", ""]
+            .into_iter()
+            .map(|text| slugs.heading(text))
+            .collect();
+        assert_eq!(
+            allocated,
+            [
+                "sec-usage",
+                "sec-usage-1",
+                "sec-usage-1-1",
+                "sec-usage-2",
+                "sec",
+                "sec-1"
+            ]
+        );
+    }
+}
@@ -0,0 +1,46 @@
+//! Utilities for exported vector artifacts.
+
+use super::ir::{ModuleMetadata, MultiVecDocument, Page};
+use super::paged;
+use super::stream::BytesModuleStream;
+use crate::error::prelude::*;
+
+/// Load an artifact in either the monolithic or the paged format.
+pub fn load_artifact(bytes: &[u8]) -> ZResult<MultiVecDocument> {
+    if paged::is_paged_artifact(bytes) {
+        return paged::decode_document(bytes);
+    }
+
+    MultiVecDocument::try_from_slice(bytes)
+}
+
+/// Load an artifact like [`load_artifact`] along with the metadata of the
+/// module, e.g. [`ModuleMetadata::Outline`].
+pub fn load_artifact_with_metadata(
+    bytes: &[u8],
+) -> ZResult<(MultiVecDocument, Vec<ModuleMetadata>)> {
+    if paged::is_paged_artifact(bytes) {
+        return paged::decode_document_with_metadata(bytes);
+    }
+
+    let module = BytesModuleStream::from_slice(bytes).try_checkout_owned()?;
+    let mut doc = MultiVecDocument::default();
+    doc.try_merge_delta(&module)?;
+    Ok((doc, module.metadata))
+}
+
+/// Get the pages of the first layout of a document.
+pub fn artifact_pages(doc: &MultiVecDocument) -> Vec<Page> {
+    let Some(layout) = doc.layouts.first() else {
+        return vec![];
+    };
+    if layout.is_empty() {
+        return vec![];
+    }
+
+    layout
+        .unwrap_single()
+        .pages_meta()
+        .map(<[Page]>::to_vec)
+        .unwrap_or_default()
+}
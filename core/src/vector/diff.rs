@@ -0,0 +1,371 @@
+//! Compare two exported artifacts page by page.
+//!
+//! Pages are compared by flattening them into leaf items placed in page
+//! coordinates. A leaf that only exists in the left artifact is removed, one
+//! that only exists in the right artifact is added, and a removed leaf and an
+//! added leaf at the same origin are counted as a single changed leaf.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::artifact::{artifact_pages, load_artifact};
+use super::ir::{Module, MultiVecDocument, Page, Point, Rect, Scalar, Transform, TransformItem};
+use crate::{error::prelude::*, hash::Fingerprint, vector::ir::VecItem};
+
+/// Options for [`diff`].
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Ignore items that are not visible, e.g. links and content hints.
+    pub ignore_metadata: bool,
+}
+
+/// A region of a page in page coordinates, in pt.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DiffRegion {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl From<Rect> for DiffRegion {
+    fn from(rect: Rect) -> Self {
+        Self {
+            x: rect.lo.x.0,
+            y: rect.lo.y.0,
+            width: rect.hi.x.0 - rect.lo.x.0,
+            height: rect.hi.y.0 - rect.lo.y.0,
+        }
+    }
+}
+
+/// The result of comparing a page.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PageDiff {
+    /// The page is the same in both artifacts.
+    Identical,
+    /// The page differs.
+    #[serde(rename_all = "camelCase")]
+    Differing {
+        added: usize,
+        removed: usize,
+        changed: usize,
+        /// Bounding boxes of the changed items.
+        regions: Vec<DiffRegion>,
+    },
+    /// The page only exists in the left artifact.
+    OnlyInLeft,
+    /// The page only exists in the right artifact.
+    OnlyInRight,
+}
+
+/// The result of comparing two artifacts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArtifactDiff {
+    pub pages: Vec<PageDiff>,
+}
+
+impl ArtifactDiff {
+    /// Whether the artifacts are visually the same.
+    pub fn is_identical(&self) -> bool {
+        self.pages.iter().all(|p| matches!(p, PageDiff::Identical))
+    }
+}
+
+/// Compare two artifacts in either the monolithic or the paged format.
+pub fn diff(left: &[u8], right: &[u8], opts: &DiffOptions) -> ZResult<ArtifactDiff> {
+    let left = load_artifact(left)?;
+    let right = load_artifact(right)?;
+    Ok(diff_documents(&left, &right, opts))
+}
+
+/// Compare two loaded documents.
+pub fn diff_documents(
+    left: &MultiVecDocument,
+    right: &MultiVecDocument,
+    opts: &DiffOptions,
+) -> ArtifactDiff {
+    let left_pages = artifact_pages(left);
+    let right_pages = artifact_pages(right);
+
+    let pages = (0..left_pages.len().max(right_pages.len()))
+        .map(|i| match (left_pages.get(i), right_pages.get(i)) {
+            (Some(l), Some(r)) => diff_page(&left.module, l, &right.module, r, opts),
+            (Some(..), None) => PageDiff::OnlyInLeft,
+            (None, Some(..)) => PageDiff::OnlyInRight,
+            (None, None) => unreachable!(),
+        })
+        .collect();
+
+    ArtifactDiff { pages }
+}
+
+fn diff_page(
+    left_module: &Module,
+    left: &Page,
+    right_module: &Module,
+    right: &Page,
+    opts: &DiffOptions,
+) -> PageDiff {
+    if left == right {
+        return PageDiff::Identical;
+    }
+
+    let mut removed = leaves_of(left_module, left, opts);
+    let mut added = leaves_of(right_module, right, opts);
+
+    // Cancel out the leaves existing in both pages.
+    let mut counts = HashMap::<LeafKey, isize>::new();
+    for leaf in &removed {
+        *counts.entry(leaf.key).or_default() += 1;
+    }
+    added.retain(|leaf| match counts.get_mut(&leaf.key) {
+        Some(c) if *c > 0 => {
+            *c -= 1;
+            false
+        }
+        _ => true,
+    });
+    removed.retain(|leaf| match counts.get_mut(&leaf.key) {
+        Some(c) if *c > 0 => {
+            *c -= 1;
+            true
+        }
+        _ => false,
+    });
+
+    if added.is_empty() && removed.is_empty() && left.size == right.size {
+        return PageDiff::Identical;
+    }
+
+    // Pair removed and added leaves at the same origin as changed leaves.
+    let mut removed_at = HashMap::<(i64, i64), usize>::new();
+    for leaf in &removed {
+        *removed_at.entry(leaf.key.origin).or_default() += 1;
+    }
+    let mut changed = 0;
+    for leaf in &added {
+        if let Some(c) = removed_at.get_mut(&leaf.key.origin).filter(|c| **c > 0) {
+            *c -= 1;
+            changed += 1;
+        }
+    }
+
+    let regions = removed
+        .iter()
+        .chain(added.iter())
+        .map(|leaf| leaf.bbox.into())
+        .collect();
+
+    PageDiff::Differing {
+        added: added.len() - changed,
+        removed: removed.len() - changed,
+        changed,
+        regions,
+    }
+}
+
+/// Identifies a leaf item placed on a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct LeafKey {
+    item: Fingerprint,
+    /// The origin of the item in 1/100 pt.
+    origin: (i64, i64),
+}
+
+/// A leaf item placed on a page.
+struct Leaf {
+    key: LeafKey,
+    bbox: Rect,
+}
+
+fn leaves_of(module: &Module, page: &Page, opts: &DiffOptions) -> Vec<Leaf> {
+    let mut leaves = vec![];
+    collect_leaves(
+        module,
+        &page.content,
+        Transform::identity(),
+        opts,
+        &mut leaves,
+    );
+    leaves
+}
+
+fn collect_leaves(
+    module: &Module,
+    fg: &Fingerprint,
+    ts: Transform,
+    opts: &DiffOptions,
+    leaves: &mut Vec<Leaf>,
+) {
+    let Some(item) = module.get_item(fg) else {
+        return;
+    };
+
+    let size = match item {
+        VecItem::Group(group) => {
+            for (pos, child) in group.0.iter() {
+                let ts = ts.pre_translate(pos.x.0, pos.y.0);
+                collect_leaves(module, child, ts, opts, leaves);
+            }
+            return;
+        }
+        VecItem::Item(transformed) => {
+            let ts = ts.pre_concat(to_transform(&transformed.0));
+            collect_leaves(module, &transformed.1, ts, opts, leaves);
+            return;
+        }
+        VecItem::Link(..) | VecItem::ContentHint(..) | VecItem::None if opts.ignore_metadata => {
+            return;
+        }
+        VecItem::Path(path) => path.size.map(|s| rect(0., 0., s.x.0, s.y.0)),
+        VecItem::Image(image) => Some(rect(0., 0., image.size.x.0, image.size.y.0)),
+        VecItem::Link(link) => Some(rect(0., 0., link.size.x.0, link.size.y.0)),
+        VecItem::Text(text) => Some(rect(0., -text.shape.size.0, text.width().0, 0.)),
+        _ => None,
+    };
+
+    let origin = apply(&ts, Point::new(Scalar(0.), Scalar(0.)));
+    let bbox = match size {
+        Some(local) => {
+            let corners = [
+                local.lo,
+                local.hi,
+                Point::new(local.lo.x, local.hi.y),
+                Point::new(local.hi.x, local.lo.y),
+            ]
+            .map(|p| apply(&ts, p));
+            corners.iter().fold(
+                Rect {
+                    lo: corners[0],
+                    hi: corners[0],
+                },
+                |acc, p| Rect {
+                    lo: Point::new(Scalar(acc.lo.x.0.min(p.x.0)), Scalar(acc.lo.y.0.min(p.y.0))),
+                    hi: Point::new(Scalar(acc.hi.x.0.max(p.x.0)), Scalar(acc.hi.y.0.max(p.y.0))),
+                },
+            )
+        }
+        None => Rect {
+            lo: origin,
+            hi: origin,
+        },
+    };
+
+    let quantize = |v: Scalar| (v.0 * 100.).round() as i64;
+    leaves.push(Leaf {
+        key: LeafKey {
+            item: *fg,
+            origin: (quantize(origin.x), quantize(origin.y)),
+        },
+        bbox,
+    });
+}
+
+fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> Rect {
+    Rect {
+        lo: Point::new(Scalar(x0), Scalar(y0)),
+        hi: Point::new(Scalar(x1), Scalar(y1)),
+    }
+}
+
+fn apply(ts: &Transform, p: Point) -> Point {
+    Point::new(
+        Scalar(ts.sx.0 * p.x.0 + ts.kx.0 * p.y.0 + ts.tx.0),
+        Scalar(ts.ky.0 * p.x.0 + ts.sy.0 * p.y.0 + ts.ty.0),
+    )
+}
+
+fn to_transform(item: &TransformItem) -> Transform {
+    match item {
+        TransformItem::Rotate(angle) => {
+            let (sin, cos) = angle.0.sin_cos();
+            Transform {
+                sx: Scalar(cos),
+                ky: Scalar(sin),
+                kx: Scalar(-sin),
+                sy: Scalar(cos),
+                tx: Scalar(0.),
+                ty: Scalar(0.),
+            }
+        }
+        item => item.clone().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::vector::ir::{GroupRef, PathItem, Size, VecDocument};
+
+    fn fg(v: u64) -> Fingerprint {
+        Fingerprint::from_pair(v, 0)
+    }
+
+    fn doc(children: &[(f32, u64)]) -> MultiVecDocument {
+        let mut module = Module::default();
+        for (_, c) in children {
+            module.items.insert(
+                fg(*c),
+                VecItem::Path(PathItem {
+                    d: format!("M 0 0 L {c} {c}").into(),
+                    size: Some(Size::new(Scalar(10.), Scalar(10.))),
+                    styles: vec![],
+                }),
+            );
+        }
+        let group = VecItem::Group(GroupRef(
+            children
+                .iter()
+                .map(|(y, c)| (Point::new(Scalar(0.), Scalar(*y)), fg(*c)))
+                .collect::<Arc<[_]>>(),
+        ));
+        let root = Fingerprint::from_pair(children.iter().map(|(_, c)| *c).sum::<u64>(), 1);
+        module.items.insert(root, group);
+
+        VecDocument {
+            module,
+            pages: vec![Page {
+                content: root,
+                size: Size::new(Scalar(100.), Scalar(100.)),
+            }],
+        }
+        .to_multi()
+    }
+
+    #[test]
+    fn test_diff_changed_item() {
+        let left = doc(&[(0., 1), (20., 2)]);
+        let right = doc(&[(0., 1), (20., 3)]);
+
+        let res = diff_documents(&left, &right, &DiffOptions::default());
+        assert_eq!(
+            res.pages,
+            vec![PageDiff::Differing {
+                added: 0,
+                removed: 0,
+                changed: 1,
+                regions: vec![
+                    DiffRegion {
+                        x: 0.,
+                        y: 20.,
+                        width: 10.,
+                        height: 10.
+                    };
+                    2
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let left = doc(&[(0., 1), (20., 2)]);
+        let res = diff_documents(&left, &doc(&[(0., 1), (20., 2)]), &DiffOptions::default());
+        assert!(res.is_identical());
+    }
+}
@@ -11,7 +11,7 @@ use crate::debug_loc::ElementPoint;
 use crate::{debug_loc::SourceSpanOffset, error::prelude::*};
 
 /// Client side implementation is free from typst details.
-pub use reflexo::vector::incr::{IncrDocClient, IncrDocClientKern};
+pub use reflexo::vector::incr::{IncrDocClient, IncrDocClientKern, TextIndex};
 
 /// maintains the data of the incremental rendering at server side
 #[derive(Default)]
@@ -107,6 +107,18 @@ impl IncrDocServer {
         [b"diff-v1,", delta.as_slice()].concat()
     }
 
+    /// Pack the delta from the `base` document to the `output` document into
+    /// a binary blob, regardless of the documents packed before.
+    ///
+    /// Items are identified by their fingerprints, hence a client which has
+    /// the items of `base`, e.g. by rendering it, can apply the delta to
+    /// render `output`.
+    pub fn pack_delta_between(base: Arc<Document>, output: Arc<Document>) -> Vec<u8> {
+        let mut server = Self::default();
+        server.pack_delta(base);
+        server.pack_delta(output)
+    }
+
     /// Pack the current entirely into a binary blob.
     pub fn pack_current(&mut self) -> Option<Vec<u8>> {
         let doc = self.doc_view.as_ref()?;
@@ -1,4 +1,8 @@
+#[cfg(feature = "flat-vector")]
+pub mod artifact;
 pub mod convert;
+#[cfg(feature = "flat-vector")]
+pub mod diff;
 pub mod incr;
 pub mod ir;
 pub mod pass;
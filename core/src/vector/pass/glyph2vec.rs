@@ -79,7 +79,8 @@ impl<const ENABLE_REF_CNT: bool> TGlyph2VecPass<ENABLE_REF_CNT> {
         fonts.sort_by(|(_, a), (_, b)| a.idx.cmp(&b.idx));
         let fonts = fonts.into_iter().map(|(a, _)| a.into_typst()).collect();
 
-        let glyphs = self.glyph_defs.clone().into_iter().collect::<Vec<_>>();
+        let mut glyphs = self.glyph_defs.clone().into_iter().collect::<Vec<_>>();
+        glyphs.sort_by_key(|(_, (id, _))| (id.font_hash, id.glyph_idx));
         let glyphs = glyphs
             .into_par_iter()
             .flat_map(|(a, b)| {
@@ -186,7 +187,8 @@ impl<const ENABLE_REF_CNT: bool> TGlyph2VecPass<ENABLE_REF_CNT> {
 impl IncrGlyph2VecPass {
     pub fn finalize_delta(&self) -> (FontPack, Vec<(GlyphRef, FlatGlyphItem)>) {
         let fonts = std::mem::take(self.new_fonts.lock().deref_mut());
-        let glyphs = std::mem::take(self.new_glyphs.lock().deref_mut());
+        let mut glyphs = std::mem::take(self.new_glyphs.lock().deref_mut());
+        glyphs.sort_by_key(|(id, _)| (id.font_hash, id.glyph_idx));
         let glyphs = glyphs
             .into_par_iter()
             .flat_map(|(id, glyph)| {
@@ -1,5 +1,6 @@
 use std::{
     borrow::Cow,
+    collections::HashMap,
     hash::Hash,
     ops::DerefMut,
     sync::{atomic::AtomicU64, Arc},
@@ -96,6 +97,8 @@ pub struct Typst2VecPassImpl<const ENABLE_REF_CNT: bool = false> {
     pub cache_items: RefItemMapT<(AtomicU64, Fingerprint, VecItem)>,
     pub items: RefItemMapSync,
     pub new_items: Mutex<Vec<(Fingerprint, VecItem)>>,
+    /// The interned data of images by their hashes, see [`Self::image`].
+    images: Mutex<HashMap<Fingerprint, Arc<Image>>>,
 
     fingerprint_builder: FingerprintBuilder,
 
@@ -117,6 +120,7 @@ impl<const ENABLE_REF_CNT: bool> Default for Typst2VecPassImpl<ENABLE_REF_CNT> {
             cache_items: Default::default(),
             items: Default::default(),
             new_items: Default::default(),
+            images: Default::default(),
             fingerprint_builder: Default::default(),
         }
     }
@@ -182,6 +186,12 @@ impl<const ENABLE_REF_CNT: bool> Typst2VecPassImpl<ENABLE_REF_CNT> {
     pub fn doc(&self, introspector: &Introspector, doc: &TypstDocument) -> Vec<Page> {
         let doc_reg = self.spans.start();
 
+        // The pages are converted in parallel, hence the fonts are indexed in
+        // the order of the document beforehand for reproducible artifacts.
+        for page in &doc.pages {
+            self.register_fonts(&page.frame);
+        }
+
         let pages = doc
             .pages
             .par_iter()
@@ -213,6 +223,31 @@ impl<const ENABLE_REF_CNT: bool> Typst2VecPassImpl<ENABLE_REF_CNT> {
         pages
     }
 
+    /// Index the fonts of a frame in the order of its items.
+    fn register_fonts(&self, frame: &Frame) {
+        let paint = |paint: &Paint| {
+            if let Paint::Pattern(pattern) = paint {
+                self.register_fonts(pattern.frame());
+            }
+        };
+
+        for (_, item) in frame.items() {
+            match item {
+                FrameItem::Group(group) => self.register_fonts(&group.frame),
+                FrameItem::Text(text) => {
+                    self.glyphs.build_font(&text.font);
+                    paint(&text.fill);
+                    text.stroke.iter().for_each(|stroke| paint(&stroke.paint));
+                }
+                FrameItem::Shape(shape, _) => {
+                    shape.fill.iter().for_each(paint);
+                    shape.stroke.iter().for_each(|stroke| paint(&stroke.paint));
+                }
+                _ => {}
+            }
+        }
+    }
+
     fn frame(&self, mut state: State, frame: &Frame, parent: usize, index: usize) -> Fingerprint {
         let src_reg = self.spans.start();
 
@@ -774,12 +809,28 @@ impl<const ENABLE_REF_CNT: bool> Typst2VecPassImpl<ENABLE_REF_CNT> {
 
         self.store_cached(&cond, || {
             VecItem::Image(ImageItem {
-                image: Arc::new(image.clone().into_typst()),
+                image: self.intern_image(image),
                 size: size.into_typst(),
             })
         })
     }
 
+    /// Get the data of an image shared by all its items, e.g. a logo placed
+    /// at different sizes, which is then serialized once in an artifact.
+    ///
+    /// The images are identified by their encoded data, hence the images
+    /// loaded from different files collapse if they are identical, e.g. after
+    /// being downscaled to the same image by the image limits of a compiler.
+    fn intern_image(&self, image: &TypstImage) -> Arc<Image> {
+        let content = (image.data(), image.format(), image.alt());
+        let hash = Fingerprint::from_u128(crate::hash::typst_affinite_hash(&content));
+        let mut images = self.images.lock();
+        let interned = images.entry(hash);
+        interned
+            .or_insert_with(|| Arc::new(image.clone().into_typst()))
+            .clone()
+    }
+
     // /// Convert a link into vector item.
     fn link(&self, url: &str, size: Size) -> VecItem {
         VecItem::Link(LinkItem {
@@ -1004,6 +1055,9 @@ impl IncrTypst2VecPass {
                 });
             });
 
+        // The images are only kept by the remaining items.
+        (self.images.get_mut()).retain(|_, image| Arc::strong_count(image) > 1);
+
         Arc::try_unwrap(gc_items).unwrap().into_inner()
     }
 
@@ -63,6 +63,16 @@ impl IncrVec2CanvasPass {
         self.pages = pages;
     }
 
+    /// Prepares a page to be flushed with the given transform, see
+    /// [`CanvasFlush`].
+    pub fn prepare_page(&self, idx: usize, ts: sk::Transform) -> CanvasFlush {
+        CanvasFlush {
+            page: self.pages[idx].clone(),
+            fill: self.fill.clone(),
+            ts,
+        }
+    }
+
     /// Flushes a page to the canvas with the given transform.
     pub async fn flush_page(
         &mut self,
@@ -70,13 +80,29 @@ impl IncrVec2CanvasPass {
         canvas: &web_sys::CanvasRenderingContext2d,
         ts: sk::Transform,
     ) {
-        let pg = &self.pages[idx];
+        self.prepare_page(idx, ts).flush(canvas).await;
+    }
+}
+
+/// A rendered page to flush to a canvas, which doesn't borrow the client, so
+/// that a client behind a lock is released before awaiting the flush.
+#[derive(Clone)]
+pub struct CanvasFlush {
+    page: CanvasPage,
+    fill: ImmutStr,
+    ts: sk::Transform,
+}
+
+impl CanvasFlush {
+    /// Flushes the page to the canvas.
+    pub async fn flush(&self, canvas: &web_sys::CanvasRenderingContext2d) {
+        let pg = &self.page;
 
-        set_transform(canvas, ts);
+        set_transform(canvas, self.ts);
         canvas.set_fill_style(&self.fill.as_ref().into());
         canvas.fill_rect(0., 0., pg.size.x.0 as f64, pg.size.y.0 as f64);
 
-        pg.elem.realize(ts, canvas).await;
+        pg.elem.realize(self.ts, canvas).await;
     }
 }
 
@@ -122,6 +148,14 @@ impl IncrCanvasDocClient {
         canvas: &web_sys::CanvasRenderingContext2d,
         rect: Rect,
     ) {
+        for flush in self.prepare_in_window(kern, rect) {
+            flush.flush(canvas).await;
+        }
+    }
+
+    /// Prepares the pages to flush for rendering the document in the given
+    /// window, see [`Self::render_in_window`].
+    pub fn prepare_in_window(&mut self, kern: &mut IncrDocClient, rect: Rect) -> Vec<CanvasFlush> {
         const NULL_PAGE: Fingerprint = Fingerprint::from_u128(1);
 
         self.patch_delta(kern);
@@ -164,14 +198,16 @@ impl IncrCanvasDocClient {
 
         // accumulate offset_y
         let mut offset_y = 0.;
+        let mut flushes = vec![];
         for (idx, y) in next_doc_view.iter().enumerate() {
             let x = prev_doc_view.get(idx);
             if x.is_none() || (x.unwrap() != y && y.content != NULL_PAGE) {
                 let ts = ts.pre_translate(0., offset_y);
-                self.vec2canvas.flush_page(idx, canvas, ts).await;
+                flushes.push(self.vec2canvas.prepare_page(idx, ts));
             }
             offset_y += y.size.y.0;
         }
+        flushes
     }
 
     /// Render a specific page of the document in the given window.
@@ -180,8 +216,22 @@ impl IncrCanvasDocClient {
         kern: &mut IncrDocClient,
         canvas: &web_sys::CanvasRenderingContext2d,
         idx: usize,
-        _rect: Rect,
+        rect: Rect,
     ) -> ZResult<()> {
+        let flush = self.prepare_page_in_window(kern, idx, rect)?;
+        flush.flush(canvas).await;
+
+        Ok(())
+    }
+
+    /// Prepares a specific page to flush, see
+    /// [`Self::render_page_in_window`].
+    pub fn prepare_page_in_window(
+        &mut self,
+        kern: &mut IncrDocClient,
+        idx: usize,
+        _rect: Rect,
+    ) -> ZResult<CanvasFlush> {
         self.patch_delta(kern);
 
         if idx >= self.vec2canvas.pages.len() {
@@ -190,8 +240,6 @@ impl IncrCanvasDocClient {
 
         let s = self.vec2canvas.pixel_per_pt;
         let ts = sk::Transform::from_scale(s, s);
-        self.vec2canvas.flush_page(idx, canvas, ts).await;
-
-        Ok(())
+        Ok(self.vec2canvas.prepare_page(idx, ts))
     }
 }
@@ -15,6 +15,9 @@ pub use error::{ErrKind, Error};
 pub mod vector {
     pub mod incr;
     pub mod ir;
+    pub mod lazy;
+    pub mod paged;
+    pub mod stats;
     pub mod stream;
     pub mod vm;
 
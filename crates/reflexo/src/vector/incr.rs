@@ -1,8 +1,14 @@
+use std::sync::Arc;
+
+#[cfg(feature = "rkyv")]
+use rkyv::{Archive, Deserialize as rDeser, Serialize as rSer};
+
 use super::ir::{
-    FlatGlyphItem, FlatModule, GlyphRef, LayoutRegionNode, LayoutSourceMapping, Module,
-    ModuleMetadata, MultiVecDocument, Page, SourceMappingNode,
+    FlatGlyphItem, FlatModule, FontItem, GlyphRef, ImmutStr, LayoutRegion, LayoutRegionNode,
+    LayoutSourceMapping, MetadataAnchorItem, Module, ModuleMetadata, MultiVecDocument, OutlineItem,
+    Page, PageMetaItem, SourceMappingNode, VecItem,
 };
-use crate::{error::prelude::*, TakeAs};
+use crate::{error::prelude::*, hash::Fingerprint, TakeAs};
 
 /// maintains the data of the incremental rendering at client side
 #[derive(Default)]
@@ -18,13 +24,41 @@ pub struct IncrDocClient {
     pub source_mapping_data: Vec<SourceMappingNode>,
     /// Optional page source mapping references.
     pub page_source_mappping: LayoutSourceMapping,
+    /// Optional geometry of the pages, which is kept until a delta carries a
+    /// new one.
+    pub page_meta: Option<Arc<Vec<PageMetaItem>>>,
+    /// Optional headings of the latest delta.
+    pub outline: Option<Arc<Vec<OutlineItem>>>,
+    /// Optional metadata elements of the latest delta.
+    pub metadata_anchors: Option<Arc<Vec<MetadataAnchorItem>>>,
+    /// Texts of the current pages, which is built on the first search, see
+    /// [`Self::text_index`].
+    pub text_index: Option<Arc<TextIndex>>,
 }
 
 impl IncrDocClient {
     /// Merge the delta from server.
     pub fn merge_delta(&mut self, delta: FlatModule) {
         self.doc.merge_delta(&delta);
-        for metadata in delta.metadata {
+        self.merge_metadata(delta.metadata);
+    }
+
+    /// Merge the delta from server, or report why it cannot be merged.
+    pub fn try_merge_delta(&mut self, delta: FlatModule) -> ZResult<()> {
+        self.doc.try_merge_delta(&delta)?;
+        self.merge_metadata(delta.metadata);
+        Ok(())
+    }
+
+    /// Merge the metadata of a delta, e.g. of the index of a paged artifact,
+    /// see [`crate::vector::paged::PagedArtifactHeader::metadata`].
+    pub fn merge_metadata(&mut self, metadata: Vec<ModuleMetadata>) {
+        // The headings and the metadata describe a single delta, while the
+        // geometry of the pages is kept by the deltas not carrying it.
+        self.outline = None;
+        self.metadata_anchors = None;
+        self.text_index = None;
+        for metadata in metadata {
             match metadata {
                 ModuleMetadata::Glyph(data) => {
                     self.glyphs.extend(data.take().items.into_iter());
@@ -35,17 +69,143 @@ impl IncrDocClient {
                 ModuleMetadata::PageSourceMapping(data) => {
                     self.page_source_mappping = data.take();
                 }
+                ModuleMetadata::PageMeta(data) => {
+                    self.page_meta = Some(data);
+                }
+                ModuleMetadata::Outline(data) => {
+                    self.outline = Some(data);
+                }
+                ModuleMetadata::MetadataAnchors(data) => {
+                    self.metadata_anchors = Some(data);
+                }
                 _ => {}
             }
         }
     }
 
+    /// Serialize the merged state, from which [`Self::restore`] rebuilds the
+    /// same state without merging it again.
+    ///
+    /// The fonts keep their glyphs and coverage, hence the glyphs are neither
+    /// validated nor distributed to the fonts again on restoring. The blob
+    /// carries the items and glyphs of the current document once, regardless
+    /// of the deltas merged so far, along with the text index.
+    #[cfg(feature = "rkyv")]
+    pub fn snapshot(&mut self) -> Vec<u8> {
+        let text_index = self.text_index().clone();
+        let module = &self.doc.module;
+        let glyph_cov = (module.fonts.iter())
+            .map(|font| font.glyph_cov.iter_ones().map(|idx| idx as u32).collect())
+            .collect();
+        let snapshot = IncrDocSnapshot {
+            fonts: module.fonts.clone(),
+            glyph_cov,
+            items: module
+                .items
+                .iter()
+                .map(|(fg, item)| (*fg, item.clone()))
+                .collect(),
+            layouts: self.doc.layouts.clone(),
+            source_mapping_data: self.source_mapping_data.clone(),
+            page_source_mappping: self.page_source_mappping.clone(),
+            page_meta: self.page_meta.clone(),
+            outline: self.outline.clone(),
+            metadata_anchors: self.metadata_anchors.clone(),
+            text_index,
+        };
+
+        use rkyv::ser::{serializers::AllocSerializer, Serializer};
+        let mut serializer = AllocSerializer::<0>::default();
+        serializer.serialize_value(&snapshot).unwrap();
+        serializer.into_serializer().into_inner().into_vec()
+    }
+
+    /// Restore a client from a blob of [`Self::snapshot`].
+    ///
+    /// The layout is not checked out, see [`Self::set_layout`].
+    #[cfg(all(feature = "rkyv", feature = "rkyv-validation"))]
+    pub fn restore(snapshot: &[u8]) -> ZResult<Self> {
+        use rkyv::{de::deserializers::SharedDeserializeMap, AlignedVec, Deserialize};
+
+        let mut aligned;
+        let mut data = snapshot;
+        if !(data.as_ptr() as usize).is_multiple_of(AlignedVec::ALIGNMENT) {
+            aligned = AlignedVec::with_capacity(data.len());
+            aligned.extend_from_slice(data);
+            data = aligned.as_slice();
+        }
+        let archived = rkyv::check_archived_root::<IncrDocSnapshot>(data)
+            .map_err(|err| error_once!("snapshot: invalid state", err: err.to_string()))?;
+        let snapshot: IncrDocSnapshot = archived
+            .deserialize(&mut SharedDeserializeMap::default())
+            .map_err(|err| error_once!("snapshot: deserialize state", err: format!("{err:?}")))?;
+
+        let IncrDocSnapshot {
+            mut fonts,
+            glyph_cov,
+            items,
+            layouts,
+            source_mapping_data,
+            page_source_mappping,
+            page_meta,
+            outline,
+            metadata_anchors,
+            text_index,
+        } = snapshot;
+        if glyph_cov.len() != fonts.len() {
+            return Err(error_once!("snapshot: coverage does not match fonts"));
+        }
+        for (font, cov) in fonts.iter_mut().zip(glyph_cov) {
+            if cov.is_empty() {
+                continue;
+            }
+            font.glyph_cov = bitvec::vec::BitVec::repeat(false, 65536);
+            for idx in cov {
+                if idx as usize >= font.glyphs.len() {
+                    return Err(error_once!("snapshot: glyph out of range", glyph: idx));
+                }
+                font.glyph_cov.set(idx as usize, true);
+            }
+        }
+
+        let module = Module {
+            fonts,
+            glyphs: vec![],
+            // The items are serialized in order, which are bulk built.
+            items: items.into_iter().collect(),
+        };
+        let glyphs = (module.glyphs_all())
+            .map(|(id, glyph)| (id, glyph.clone()))
+            .collect();
+        Ok(Self {
+            doc: MultiVecDocument { module, layouts },
+            glyphs,
+            layout: None,
+            source_mapping_data,
+            page_source_mappping,
+            page_meta,
+            outline,
+            metadata_anchors,
+            text_index: Some(text_index),
+        })
+    }
+
+    /// Get the texts of the current pages, which are built once per delta.
+    pub fn text_index(&mut self) -> &Arc<TextIndex> {
+        let (module, layout) = (&self.doc.module, &self.layout);
+        self.text_index.get_or_insert_with(|| {
+            let pages = layout.as_ref().and_then(LayoutRegionNode::pages_meta);
+            Arc::new(TextIndex::new(module, pages.unwrap_or_default()))
+        })
+    }
+
     /// Set the current layout of the document.
     /// This is so bare-bone that stupidly takes a selected layout.
     ///
     /// Please wrap this for your own use case.
     pub fn set_layout(&mut self, layout: LayoutRegionNode) {
         self.layout = Some(layout);
+        self.text_index = None;
     }
 
     /// Kern of the client without leaking abstraction.
@@ -62,6 +222,84 @@ impl IncrDocClient {
     }
 }
 
+/// The merged state of a client, see [`IncrDocClient::snapshot`].
+#[cfg(feature = "rkyv")]
+#[derive(Archive, rDeser, rSer)]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+struct IncrDocSnapshot {
+    /// The fonts along with their glyphs.
+    fonts: Vec<FontItem>,
+    /// The covered glyphs of each font, which are skipped in serialization of
+    /// fonts.
+    glyph_cov: Vec<Vec<u32>>,
+    items: Vec<(Fingerprint, VecItem)>,
+    layouts: Vec<LayoutRegion>,
+    source_mapping_data: Vec<SourceMappingNode>,
+    page_source_mappping: LayoutSourceMapping,
+    page_meta: Option<Arc<Vec<PageMetaItem>>>,
+    outline: Option<Arc<Vec<OutlineItem>>>,
+    metadata_anchors: Option<Arc<Vec<MetadataAnchorItem>>>,
+    text_index: Arc<TextIndex>,
+}
+
+/// The texts of pages, which are the contents of the text items and the
+/// content hints in the order of painting.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct TextIndex {
+    pub pages: Vec<ImmutStr>,
+}
+
+impl TextIndex {
+    pub fn new(module: &Module, pages: &[Page]) -> Self {
+        fn collect(module: &Module, fg: &Fingerprint, text: &mut String) {
+            match module.get_item(fg) {
+                Some(VecItem::Text(t)) => text.push_str(&t.content.content),
+                Some(VecItem::ContentHint(c)) => text.push(*c),
+                Some(VecItem::Item(t)) => collect(module, &t.1, text),
+                Some(VecItem::ColorTransform(t)) => collect(module, &t.item, text),
+                Some(VecItem::Group(g)) => {
+                    for (_, fg) in g.0.iter() {
+                        collect(module, fg, text);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let pages = pages.iter().map(|page| {
+            let mut text = String::new();
+            collect(module, &page.content, &mut text);
+            text.into()
+        });
+        Self {
+            pages: pages.collect(),
+        }
+    }
+
+    /// Find the occurrences of the query ignoring ASCII case, i.e. pairs of
+    /// the index of the page and the offset in chars in the text of the page.
+    pub fn search(&self, query: &str) -> Vec<(usize, usize)> {
+        let query = query.as_bytes();
+        if query.is_empty() {
+            return vec![];
+        }
+
+        let mut res = vec![];
+        for (page, text) in self.pages.iter().enumerate() {
+            let bytes = text.as_bytes();
+            for (offset, (idx, _)) in text.char_indices().enumerate() {
+                let rest = &bytes[idx..];
+                if rest.len() >= query.len() && rest[..query.len()].eq_ignore_ascii_case(query) {
+                    res.push((page, offset));
+                }
+            }
+        }
+        res
+    }
+}
+
 fn access_slice<'a, T>(v: &'a [T], idx: usize, kind: &'static str, pos: usize) -> ZResult<&'a T> {
     v.get(idx).ok_or_else(
         || error_once!("out of bound access", pos: pos, kind: kind, idx: idx, actual: v.len()),
@@ -81,6 +319,22 @@ impl<'a> IncrDocClientKern<'a> {
         layout.and_then(LayoutRegionNode::pages_meta)
     }
 
+    /// Get the geometry of the pages, if any delta carried it.
+    pub fn page_meta(&self) -> Option<&[PageMetaItem]> {
+        self.0.page_meta.as_deref().map(Vec::as_slice)
+    }
+
+    /// Get the headings of the document, if the latest delta carries them.
+    pub fn outline(&self) -> Option<&[OutlineItem]> {
+        self.0.outline.as_deref().map(Vec::as_slice)
+    }
+
+    /// Get the metadata elements of the document, if the latest delta
+    /// carries them.
+    pub fn metadata_anchors(&self) -> Option<&[MetadataAnchorItem]> {
+        self.0.metadata_anchors.as_deref().map(Vec::as_slice)
+    }
+
     /// Get estimated width of the document (in flavor of PDF Viewer).
     pub fn doc_width(&self) -> Option<f32> {
         let view = self.pages_meta()?.iter();
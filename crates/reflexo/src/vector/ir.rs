@@ -100,6 +100,20 @@ impl VecDocument {
     pub fn to_bytes(self) -> Vec<u8> {
         self.to_multi().to_bytes()
     }
+
+    /// Serialize the document with a version header, which is suitable for
+    /// writing to files, see [`super::stream::ARTIFACT_MAGIC`].
+    pub fn to_artifact_bytes(self) -> Vec<u8> {
+        super::stream::with_artifact_header(&self.to_bytes())
+    }
+
+    /// Serialize the document with a version header like
+    /// [`Self::to_artifact_bytes`], attaching extra metadata, e.g.
+    /// [`ModuleMetadata::Links`].
+    pub fn to_artifact_bytes_with(self, metadata: Vec<ModuleMetadata>) -> Vec<u8> {
+        let bytes = self.to_multi().to_bytes_with(metadata);
+        super::stream::with_artifact_header(&bytes)
+    }
 }
 
 /// Module with multiple documents, corresponding to multiple
@@ -127,27 +141,46 @@ impl MultiVecDocument {
         self.layouts = v.layouts().take();
         self.module.merge_delta(v);
     }
+
+    /// See [`Module::try_merge_delta`].
+    pub fn try_merge_delta(&mut self, v: impl ModuleStream) -> crate::error::prelude::ZResult<()> {
+        let layouts = v.layouts().take();
+        self.module.try_merge_delta(v)?;
+        self.layouts = layouts;
+        Ok(())
+    }
 }
 
 #[cfg(feature = "rkyv")]
 impl MultiVecDocument {
     pub fn from_slice(v: &[u8]) -> Self {
+        Self::try_from_slice(v).unwrap()
+    }
+
+    /// Validate and load a document from bytes.
+    pub fn try_from_slice(v: &[u8]) -> crate::error::prelude::ZResult<Self> {
         type DocStream<'a> = super::stream::BytesModuleStream<'a>;
 
         let mut res = Self::default();
-        res.merge_delta(&DocStream::from_slice(v).checkout_owned());
-        res
+        res.try_merge_delta(&DocStream::from_slice(v).try_checkout_owned()?)?;
+        Ok(res)
     }
 
     pub fn to_bytes(self) -> Vec<u8> {
-        let flatten_module = FlatModule::new(vec![
+        self.to_bytes_with(vec![])
+    }
+
+    /// Serialize the document, attaching extra metadata.
+    pub fn to_bytes_with(self, extra: Vec<ModuleMetadata>) -> Vec<u8> {
+        let mut metadata = vec![
             ModuleMetadata::Item(ItemPack(self.module.items.into_iter().collect())),
             ModuleMetadata::Font(Arc::new(self.module.fonts.into())),
             ModuleMetadata::Glyph(Arc::new(self.module.glyphs.into())),
             ModuleMetadata::Layout(Arc::new(self.layouts)),
-        ]);
+        ];
+        metadata.extend(extra);
 
-        flatten_module.to_bytes()
+        FlatModule::new(metadata).to_bytes()
     }
 }
 
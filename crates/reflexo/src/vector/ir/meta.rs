@@ -22,3 +22,124 @@ pub enum SourceMappingNode {
     Shape(SpanId),
     Page(u64),
 }
+
+/// The target of a link in a [`LinkTableItem`].
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub enum LinkTarget {
+    /// An external URL.
+    Url(ImmutStr),
+    /// A position in the document, where the page number is 1-based.
+    Position { page: u32, point: Point },
+    /// A labelled element in the document, where the page number is 1-based.
+    Label {
+        label: ImmutStr,
+        page: u32,
+        point: Point,
+    },
+    /// An element which is not in the document.
+    Broken,
+}
+
+/// A rectangle covered by a link in page coordinates.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct LinkRect {
+    pub origin: Point,
+    pub size: Size,
+}
+
+/// A link in the document, which is collected into
+/// [`super::ModuleMetadata::Links`] for static viewers to navigate.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct LinkTableItem {
+    pub target: LinkTarget,
+    /// The 1-based page number of the link.
+    pub page: u32,
+    /// The rectangles covered by the link, e.g. one per line.
+    pub rects: Vec<LinkRect>,
+    /// The span of the link text.
+    pub span: SpanId,
+}
+
+/// The margins of a page in pt.
+#[derive(Debug, Clone, Copy, Default, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct PageMargins {
+    pub left: Scalar,
+    pub top: Scalar,
+    pub right: Scalar,
+    pub bottom: Scalar,
+}
+
+/// The geometry of a page, which is collected into
+/// [`super::ModuleMetadata::PageMeta`] for static viewers to lay out pages
+/// before rendering them.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct PageMetaItem {
+    /// The size of the page in pt.
+    pub size: Size,
+    pub margins: PageMargins,
+    /// Whether the size differs from the size shared by most pages.
+    pub differs_from_default: bool,
+}
+
+/// A heading in the document, which is collected into
+/// [`super::ModuleMetadata::Outline`] for static viewers to link to.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct OutlineItem {
+    /// The 1-based level of the heading.
+    pub level: u32,
+    /// The plain text of the heading.
+    pub text: ImmutStr,
+    /// The resolved numbering, e.g. `2.3.1`, if the heading is numbered.
+    pub numbering: Option<ImmutStr>,
+    /// The 1-based page number of the heading.
+    pub page: u32,
+    /// The top of the heading on the page in pt.
+    pub y: Scalar,
+    /// The anchor of the heading, which is unique in the document, e.g.
+    /// `sec-installation`.
+    pub slug: ImmutStr,
+}
+
+/// The source range of a [`MetadataAnchorItem`], where the lines and the
+/// columns are 0-based.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct SourceRangeItem {
+    pub filepath: ImmutStr,
+    pub start_line: u32,
+    pub start_column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+}
+
+/// A `metadata` element in the document, which is collected into
+/// [`super::ModuleMetadata::MetadataAnchors`] for static viewers to navigate
+/// by, e.g. between slides.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct MetadataAnchorItem {
+    /// The value of the element serialized as JSON.
+    pub value: ImmutStr,
+    /// The label attached to the element, if any.
+    pub label: Option<ImmutStr>,
+    /// The 1-based page number of the element.
+    pub page: u32,
+    /// The position of the element on the page in pt.
+    pub y: Scalar,
+    /// The source of the element, which is only embedded on request.
+    pub source: Option<SourceRangeItem>,
+}
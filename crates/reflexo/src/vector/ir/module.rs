@@ -1,6 +1,6 @@
 use std::{
     borrow::Borrow,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     hash::Hash,
     ops::Deref,
     sync::{atomic::AtomicU64, Arc},
@@ -8,7 +8,11 @@ use std::{
 
 use comemo::Prehashed;
 
-use crate::{hash::Fingerprint, ImmutStr, TakeAs};
+use crate::{
+    error::prelude::*,
+    hash::{Fingerprint, FingerprintBuilder},
+    ImmutStr, TakeAs,
+};
 
 use super::{preludes::*, *};
 
@@ -99,6 +103,17 @@ impl Module {
         self.items.get(id)
     }
 
+    /// Merge a delta after checking that it can be merged without panicking.
+    pub fn try_merge_delta(&mut self, v: impl ModuleStream) -> ZResult<()> {
+        let fonts = v.fonts();
+        validate_glyphs(
+            self.fonts.iter().chain(fonts.items.iter()),
+            &v.glyphs().items,
+        )?;
+        self.merge_delta(v);
+        Ok(())
+    }
+
     pub fn merge_delta(&mut self, v: impl ModuleStream) {
         let item_pack: ItemPack = v.items();
         if let Some(gc_items) = v.gc_items() {
@@ -118,6 +133,39 @@ impl Module {
         }
     }
 
+    /// Visit the items reachable from the given item, including itself.
+    ///
+    /// Each item is visited at most once. Patterns and gradients referenced
+    /// by path styles are followed as well.
+    pub fn visit_reachable(&self, root: &Fingerprint, f: &mut impl FnMut(&Fingerprint, &VecItem)) {
+        let mut visited = HashSet::new();
+        let mut stack = vec![*root];
+        while let Some(fg) = stack.pop() {
+            if !visited.insert(fg) {
+                continue;
+            }
+            let Some(item) = self.items.get(&fg) else {
+                continue;
+            };
+            f(&fg, item);
+
+            match item {
+                VecItem::Item(t) => stack.push(t.1),
+                VecItem::Group(g) => stack.extend(g.0.iter().map(|(_, fg)| *fg)),
+                VecItem::Pattern(p) => stack.push(p.frame),
+                VecItem::ColorTransform(c) => stack.push(c.item),
+                VecItem::Path(p) => stack.extend(paint_refs(&p.styles)),
+                VecItem::Text(t) => stack.extend(paint_refs(&t.shape.styles)),
+                VecItem::None
+                | VecItem::Image(..)
+                | VecItem::Link(..)
+                | VecItem::Color32(..)
+                | VecItem::Gradient(..)
+                | VecItem::ContentHint(..) => {}
+            }
+        }
+    }
+
     pub fn glyphs_all(&self) -> impl Iterator<Item = (GlyphRef, &FlatGlyphItem)> {
         self.fonts.iter().flat_map(|font| {
             font.glyph_cov.iter_ones().map(move |glyph_idx| {
@@ -131,6 +179,114 @@ impl Module {
             })
         })
     }
+
+    /// Replace the text items by the outlines of their glyphs, so that the
+    /// module renders without fonts, and drop the fonts and the glyphs.
+    ///
+    /// The replaced items keep their fingerprints, hence the references to
+    /// them stay valid. The flattened text is not selectable anymore.
+    pub fn flatten_text(&mut self) {
+        let fonts = std::mem::take(&mut self.fonts);
+        let glyphs: HashMap<_, _> = (std::mem::take(&mut self.glyphs).into_iter())
+            .map(|(id, item)| ((id.font_hash, id.glyph_idx), item))
+            .collect();
+
+        let builder = FingerprintBuilder::default();
+        let mut flattened = vec![];
+        let mut texts = vec![];
+        let mut store = |item: VecItem| {
+            let fg = builder.resolve(&item);
+            flattened.push((fg, item));
+            fg
+        };
+        for (fg, item) in &self.items {
+            let VecItem::Text(text) = item else {
+                continue;
+            };
+            let Some(font) = fonts.get(text.shape.font.idx as usize) else {
+                continue;
+            };
+
+            // The outlines are in font units, scaled like the renderers do,
+            // see [`TextShape::add_transform`].
+            let ppem = text.shape.ppem(font.units_per_em.0).0;
+            let styles = scale_styles(&text.shape.styles, 1. / ppem);
+            let mut width = 0f32;
+            let mut children = vec![];
+            let glyph_iter = text.content.glyphs.iter();
+            let glyph_iter = (text.shape).render_glyphs(font.units_per_em, glyph_iter, &mut width);
+            for (x, glyph) in glyph_iter {
+                let item = (glyphs.get(&(font.hash, glyph)))
+                    .or_else(|| font.get_glyph(glyph).map(Arc::as_ref));
+                let glyph = match item {
+                    Some(FlatGlyphItem::Outline(outline)) => {
+                        let path = store(VecItem::Path(PathItem {
+                            d: outline.d.clone(),
+                            size: None,
+                            styles: styles.clone(),
+                        }));
+                        match &outline.ts {
+                            Some(ts) => store(VecItem::Item(TransformedRef(
+                                TransformItem::Matrix(Arc::new(**ts)),
+                                path,
+                            ))),
+                            None => path,
+                        }
+                    }
+                    Some(FlatGlyphItem::Image(glyph)) => {
+                        let image = store(VecItem::Image(glyph.image.clone()));
+                        store(VecItem::Item(TransformedRef(
+                            TransformItem::Matrix(Arc::new(glyph.ts)),
+                            image,
+                        )))
+                    }
+                    Some(FlatGlyphItem::None) | None => continue,
+                };
+                children.push((Point::new(x, Scalar(0.)), glyph));
+            }
+
+            let group = store(VecItem::Group(GroupRef(children.into())));
+            let ts = Transform::from_scale(Scalar(ppem), Scalar(-ppem));
+            let item = VecItem::Item(TransformedRef(TransformItem::Matrix(Arc::new(ts)), group));
+            texts.push((*fg, item));
+        }
+        self.items.extend(flattened);
+        self.items.extend(texts);
+    }
+}
+
+/// Scale the lengths of path styles, e.g. the stroke width of a text whose
+/// outlines are scaled by the inverse, see [`Module::flatten_text`].
+fn scale_styles(styles: &[PathStyle], scale: f32) -> Vec<PathStyle> {
+    let scale_abs = |abs: &Abs| Scalar(abs.0 * scale);
+    (styles.iter())
+        .map(|style| match style {
+            PathStyle::StrokeWidth(width) => PathStyle::StrokeWidth(scale_abs(width)),
+            PathStyle::StrokeDashOffset(offset) => PathStyle::StrokeDashOffset(scale_abs(offset)),
+            PathStyle::StrokeDashArray(array) => {
+                PathStyle::StrokeDashArray(array.iter().map(scale_abs).collect())
+            }
+            style => style.clone(),
+        })
+        .collect()
+}
+
+/// Extract the items referenced by paint styles, which are in form of
+/// `@p<svg id>` (patterns) or `@g<svg id>` (gradients).
+fn paint_refs(styles: &[PathStyle]) -> impl Iterator<Item = Fingerprint> + '_ {
+    styles.iter().filter_map(|style| {
+        let (PathStyle::Fill(paint) | PathStyle::Stroke(paint)) = style else {
+            return None;
+        };
+        let id = paint
+            .strip_prefix("@p")
+            .or_else(|| paint.strip_prefix("@g"))?;
+        // An svg id is at least 11 base64 characters long.
+        if id.len() < 11 {
+            return None;
+        }
+        Fingerprint::try_from_str(id).ok()
+    })
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -261,6 +417,10 @@ pub enum ModuleMetadata {
     Font(Arc<IncrFontPack>),
     Glyph(Arc<IncrGlyphPack>),
     Layout(Arc<Vec<LayoutRegion>>),
+    Links(Arc<Vec<LinkTableItem>>),
+    PageMeta(Arc<Vec<PageMetaItem>>),
+    Outline(Arc<Vec<OutlineItem>>),
+    MetadataAnchors(Arc<Vec<MetadataAnchorItem>>),
 }
 
 const _: () = assert!(core::mem::size_of::<ModuleMetadata>() == 32);
@@ -325,6 +485,50 @@ impl FlatModule {
     }
 }
 
+impl FlatModule {
+    /// Check that the module carries the metadata required by
+    /// [`ModuleStream`].
+    pub fn validate(&self) -> ZResult<()> {
+        if self.magic != *b"tsvr\x00\x00\x00\x00" {
+            return Err(error_once!("artifact: bad magic"));
+        }
+
+        let mut found = [false; 4];
+        for meta in &self.metadata {
+            match meta {
+                ModuleMetadata::Item(..) => found[0] = true,
+                ModuleMetadata::Font(..) => found[1] = true,
+                ModuleMetadata::Glyph(..) => found[2] = true,
+                ModuleMetadata::Layout(..) => found[3] = true,
+                _ => {}
+            }
+        }
+        if found.contains(&false) {
+            return Err(error_once!("artifact: missing module metadata"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Check that glyphs reference known fonts and fit in the glyph coverage,
+/// see [`Module::prepare_glyphs`].
+pub(crate) fn validate_glyphs<'a>(
+    fonts: impl Iterator<Item = &'a FontItem> + Clone,
+    glyphs: &[(GlyphRef, FlatGlyphItem)],
+) -> ZResult<()> {
+    for (id, _) in glyphs {
+        if !fonts.clone().any(|f| f.hash == id.font_hash) {
+            return Err(error_once!("artifact: glyph of unknown font", font: id.font_hash));
+        }
+        if id.glyph_idx >= 65536 {
+            return Err(error_once!("artifact: glyph index out of range", glyph: id.glyph_idx));
+        }
+    }
+
+    Ok(())
+}
+
 // todo: for archived module.
 // todo: zero copy
 #[cfg(feature = "rkyv")]
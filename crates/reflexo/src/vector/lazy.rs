@@ -0,0 +1,209 @@
+//! Random access to pages of a paged artifact.
+//!
+//! [`LazyArtifact`] only reads the index of an artifact when it is opened.
+//! Page blocks and resource blocks are decoded on first access and cached,
+//! so that rendering a single page of a large artifact only decodes that
+//! page and the resource blocks written before it.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use once_cell::sync::OnceCell;
+
+use super::ir::{Module, Page, Size};
+use super::paged::{
+    self, check_references, PageBlock, PagedArtifactHeader, PagedBlock, PagedDocument,
+    ResourceBlock,
+};
+use crate::error::prelude::*;
+
+/// A paged artifact borrowing its bytes, whose blocks are decoded on demand.
+pub struct LazyArtifact<'a> {
+    bytes: &'a [u8],
+    header: PagedArtifactHeader,
+    pages: Vec<OnceCell<PageBlock>>,
+    resources: Vec<OnceCell<ResourceBlock>>,
+    /// The number of blocks decoded so far.
+    decoded: AtomicUsize,
+}
+
+impl<'a> LazyArtifact<'a> {
+    /// Open a paged artifact, reading only its index.
+    pub fn open(bytes: &'a [u8]) -> ZResult<Self> {
+        let header = paged::read_header(bytes)?;
+        let pages = header.pages.iter().map(|_| OnceCell::new()).collect();
+        let resources = header.resources.iter().map(|_| OnceCell::new()).collect();
+
+        Ok(Self {
+            bytes,
+            header,
+            pages,
+            resources,
+            decoded: AtomicUsize::new(0),
+        })
+    }
+
+    /// Get the index of the artifact.
+    pub fn header(&self) -> &PagedArtifactHeader {
+        &self.header
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.header.pages.len()
+    }
+
+    /// Get the size of page `k` without decoding it.
+    pub fn page_size(&self, k: usize) -> Option<Size> {
+        self.header.pages.get(k).map(|entry| entry.page.size)
+    }
+
+    /// Get the block of page `k`, decoding it on first access.
+    pub fn page(&self, k: usize) -> ZResult<&PageBlock> {
+        let entry = self
+            .header
+            .pages
+            .get(k)
+            .ok_or_else(|| error_once!("paged artifact: page out of range", page: k))?;
+
+        self.pages[k].get_or_try_init(|| {
+            self.decoded.fetch_add(1, Ordering::Relaxed);
+            paged::decode_page(&self.header, k, entry.block.slice(self.bytes)?)
+        })
+    }
+
+    /// Get resource block `k`, decoding it on first access.
+    pub fn resource(&self, k: usize) -> ZResult<&ResourceBlock> {
+        let range = self
+            .header
+            .resources
+            .get(k)
+            .ok_or_else(|| error_once!("paged artifact: resource out of range", resource: k))?;
+
+        self.resources[k].get_or_try_init(|| {
+            self.decoded.fetch_add(1, Ordering::Relaxed);
+            match paged::decode_block_at(range.slice(self.bytes)?, range.offset)? {
+                PagedBlock::Resource(res) => Ok(res),
+                _ => Err(error_once!("paged artifact: not a resource block", offset: range.offset)),
+            }
+        })
+    }
+
+    /// Assemble a module which is able to render page `k`.
+    ///
+    /// The module contains the items of the page and the resources it
+    /// depends on, which are checked to be complete.
+    pub fn page_module(&self, k: usize) -> ZResult<(Module, Page)> {
+        let page = self.page(k)?;
+
+        let mut doc = PagedDocument::default();
+        for r in 0..self.header.pages[k].resources as usize {
+            doc.merge_block(PagedBlock::Resource(self.resource(r)?.clone()))?;
+        }
+        let mut module = doc.module;
+        module.items.extend(page.items.0.iter().cloned());
+
+        if !module.items.contains_key(&page.page.content) {
+            return Err(error_once!("paged artifact: missing page content", page: k));
+        }
+        check_references(&module)?;
+
+        Ok((module, page.page.clone()))
+    }
+
+    /// Whether page `k` has been decoded.
+    pub fn is_page_decoded(&self, k: usize) -> bool {
+        self.pages.get(k).is_some_and(|cell| cell.get().is_some())
+    }
+
+    /// The number of blocks decoded so far.
+    pub fn decoded_blocks(&self) -> usize {
+        self.decoded.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::hash::Fingerprint;
+    use crate::vector::ir::{
+        Axes, GroupRef, Image, ImageItem, PathItem, Scalar, VecDocument, VecItem,
+    };
+
+    fn fg(v: u64) -> Fingerprint {
+        Fingerprint::from_pair(v, 0)
+    }
+
+    fn fixture(pages: u64) -> VecDocument {
+        let mut module = Module::default();
+        module.items.insert(
+            fg(0),
+            VecItem::Image(ImageItem {
+                image: Arc::new(Image {
+                    data: vec![0; 256],
+                    format: "png".into(),
+                    size: Axes::new(8, 8),
+                    alt: None,
+                    hash: fg(0),
+                }),
+                size: Size::new(Scalar(8.), Scalar(8.)),
+            }),
+        );
+
+        let pages = (1..=pages)
+            .map(|k| {
+                module.items.insert(
+                    fg(k),
+                    VecItem::Path(PathItem {
+                        d: format!("M 0 0 L {k} {k}").into(),
+                        size: None,
+                        styles: vec![],
+                    }),
+                );
+                let content = Fingerprint::from_pair(k, 1);
+                module.items.insert(
+                    content,
+                    VecItem::Group(GroupRef(
+                        [fg(0), fg(k)]
+                            .map(|c| (Default::default(), c))
+                            .into_iter()
+                            .collect(),
+                    )),
+                );
+                Page {
+                    content,
+                    size: Size::new(Scalar(100.), Scalar(k as f32)),
+                }
+            })
+            .collect();
+
+        VecDocument { module, pages }
+    }
+
+    #[test]
+    fn test_lazy_page_access() {
+        let bytes = fixture(200).to_paged_bytes().unwrap();
+        let artifact = LazyArtifact::open(&bytes).unwrap();
+        assert_eq!(artifact.page_count(), 200);
+        assert_eq!(artifact.page_size(199).unwrap().y, Scalar(200.));
+        assert_eq!(artifact.decoded_blocks(), 0);
+
+        let (module, page) = artifact.page_module(0).unwrap();
+        assert_eq!(page.size.y, Scalar(1.));
+        assert!(module.items.contains_key(&fg(0)));
+        assert!(module.items.contains_key(&fg(1)));
+        assert!(!module.items.contains_key(&fg(200)));
+
+        assert!(!artifact.is_page_decoded(199));
+        let resources = artifact.header().pages[0].resources as usize;
+        assert_eq!(artifact.decoded_blocks(), resources + 1);
+
+        // Decoded blocks are cached.
+        artifact.page_module(0).unwrap();
+        assert_eq!(artifact.decoded_blocks(), resources + 1);
+
+        assert!(artifact.page(200).is_err());
+        assert!(artifact.page(199).is_ok());
+        assert!(artifact.is_page_decoded(199));
+    }
+}
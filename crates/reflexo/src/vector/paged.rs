@@ -0,0 +1,988 @@
+//! Page-wise artifact format.
+//!
+//! Unlike [`super::ir::FlatModule`], which is serialized as a whole, a paged
+//! artifact is a sequence of independently decodable blocks:
+//!
+//! ```text
+//! ┌───────┬──────────┬────────┬──────────┬────────┬─────┬───────┬─────────┐
+//! │ magic │ resource │ page 0 │ resource │ page 1 │ ... │ index │ trailer │
+//! └───────┴──────────┴────────┴──────────┴────────┴─────┴───────┴─────────┘
+//! ```
+//!
+//! + Each block is framed by a 16-byte block header carrying its kind,
+//!   payload checksum and payload length, so that a reader can split a byte
+//!   stream into blocks without knowing the index, and detect corrupted
+//!   blocks before deserializing them.
+//! + A page block carries the items reachable from the page. Fonts, glyphs,
+//!   images and gradients are shared by pages and interned in resource
+//!   blocks by their content hash, which are always written before the first
+//!   page that uses them.
+//! + The index block ([`PagedArtifactHeader`]) maps pages to their blocks
+//!   and carries the metadata of the document, e.g. the links, and the
+//!   trailer stores the offset of the index block.
+//!
+//! Since the index is written last, the writer is able to emit pages as soon
+//! as they finish layout. A reader may decode page `k` given only the index
+//! and the bytes of that page, or decode blocks as they arrive with
+//! [`PagedStreamDecoder`].
+//!
+//! Artifacts may be truncated or corrupted, so the reader never trusts an
+//! offset or index read from the bytes. Use [`validate`] to check an entire
+//! artifact before handing it to a renderer.
+
+use std::{collections::HashSet, io::Write, sync::Arc};
+
+use rkyv::{de::deserializers::SharedDeserializeMap, AlignedVec};
+use rkyv::{Archive, Deserialize as rDeser, Serialize as rSer};
+
+use super::ir::{
+    module::validate_glyphs, FlatGlyphItem, FontItem, GlyphRef, IncrFontPack, IncrGlyphPack,
+    ItemPack, LayoutRegion, Module, ModuleMetadata, MultiVecDocument, Page, VecDocument, VecItem,
+};
+use crate::{
+    error::prelude::*,
+    hash::{hash128, Fingerprint},
+    TakeAs,
+};
+
+/// Magic of paged artifacts, the last byte is the format version.
+pub const PAGED_MAGIC: [u8; 8] = *b"tsvp\x00\x00\x00\x04";
+
+/// Size of a block header, it keeps the payload aligned.
+const BLOCK_HEADER_SIZE: usize = 16;
+/// Size of the trailer, which stores the offset of the index block.
+pub(crate) const TRAILER_SIZE: usize = 8;
+
+/// The kind of a block in a paged artifact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BlockKind {
+    Resource = 1,
+    Page = 2,
+    Index = 3,
+}
+
+impl BlockKind {
+    fn from_u8(v: u8) -> ZResult<Self> {
+        Ok(match v {
+            1 => Self::Resource,
+            2 => Self::Page,
+            3 => Self::Index,
+            _ => return Err(error_once!("paged artifact: unknown block kind", kind: v)),
+        })
+    }
+}
+
+/// A range of bytes in a paged artifact, including the block header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct BlockRange {
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl BlockRange {
+    /// Get the bytes of the block from the entire artifact.
+    pub fn slice<'a>(&self, artifact: &'a [u8]) -> ZResult<&'a [u8]> {
+        let start = usize::try_from(self.offset).ok();
+        let end = start.and_then(|start| start.checked_add(usize::try_from(self.len).ok()?));
+        start
+            .zip(end)
+            .and_then(|(start, end)| artifact.get(start..end))
+            .ok_or_else(|| {
+                error_once!("paged artifact: block out of range", offset: self.offset, len: self.len)
+            })
+    }
+}
+
+/// An entry of the page offset table.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct PageEntry {
+    /// The page reference.
+    pub page: Page,
+    /// The page block.
+    pub block: BlockRange,
+    /// The number of resource blocks that must be merged before rendering
+    /// this page.
+    pub resources: u32,
+}
+
+/// The index of a paged artifact.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct PagedArtifactHeader {
+    /// The page offset table.
+    pub pages: Vec<PageEntry>,
+    /// The resource blocks in the order of writing.
+    pub resources: Vec<BlockRange>,
+    /// The metadata of the document, which is only known when all the pages
+    /// are written, see [`PagedArtifactWriter::finish_with`].
+    pub metadata: Vec<ModuleMetadata>,
+}
+
+/// Resources shared by pages.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct ResourceBlock {
+    pub fonts: Arc<IncrFontPack>,
+    pub glyphs: Arc<IncrGlyphPack>,
+    /// Interned items, see [`is_shared_item`].
+    pub items: ItemPack,
+}
+
+/// Items of a single page.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "rkyv", derive(Archive, rDeser, rSer))]
+#[cfg_attr(feature = "rkyv-validation", archive(check_bytes))]
+pub struct PageBlock {
+    /// The index of the page.
+    pub index: u32,
+    /// The page reference.
+    pub page: Page,
+    /// Items reachable from the page, except the interned ones.
+    pub items: ItemPack,
+}
+
+/// A decoded block.
+#[derive(Debug, Clone)]
+pub enum PagedBlock {
+    Resource(ResourceBlock),
+    Page(PageBlock),
+    Index(PagedArtifactHeader),
+}
+
+/// Writes a paged artifact page by page.
+pub struct PagedArtifactWriter<W: Write> {
+    out: W,
+    /// Bytes written so far.
+    offset: u64,
+    header: PagedArtifactHeader,
+    /// Number of fonts already written.
+    fonts_written: usize,
+    /// Glyphs already written.
+    glyphs_written: HashSet<GlyphRef>,
+    /// Interned items already written.
+    items_written: HashSet<Fingerprint>,
+}
+
+impl<W: Write> PagedArtifactWriter<W> {
+    /// Create a writer and write the magic.
+    pub fn new(mut out: W) -> ZResult<Self> {
+        out.write_all(&PAGED_MAGIC)
+            .map_err(map_string_err("paged artifact: write magic"))?;
+        Ok(Self {
+            out,
+            offset: PAGED_MAGIC.len() as u64,
+            header: PagedArtifactHeader::default(),
+            fonts_written: 0,
+            glyphs_written: HashSet::new(),
+            items_written: HashSet::new(),
+        })
+    }
+
+    /// Write the next page of the document.
+    ///
+    /// The module may grow between calls, as long as existing fonts are not
+    /// reordered.
+    pub fn write_page(&mut self, module: &Module, page: &Page) -> ZResult<()> {
+        let mut items = vec![];
+        let mut shared_items = vec![];
+        let mut used_glyphs = HashSet::new();
+        module.visit_reachable(&page.content, &mut |fg, item| {
+            if let VecItem::Text(text) = item {
+                let font_hash = text.shape.font.hash;
+                used_glyphs.extend(
+                    text.content
+                        .glyphs
+                        .iter()
+                        .map(|(_, _, glyph_idx)| GlyphRef {
+                            font_hash,
+                            glyph_idx: *glyph_idx,
+                        }),
+                );
+            }
+            if !is_shared_item(item) {
+                items.push((*fg, item.clone()));
+            } else if !self.items_written.contains(fg) {
+                shared_items.push((*fg, item.clone()));
+            }
+        });
+        // Keep the block content independent of the traversal order, so that
+        // identical documents produce identical artifacts.
+        items.sort_by_key(|(fg, _)| *fg);
+        shared_items.sort_by_key(|(fg, _)| *fg);
+
+        // Write resources which are used by this page for the first time.
+        //
+        // Glyphs are either pending in `module.glyphs` or already attached to
+        // fonts by `Module::prepare_glyphs`, so they are written separately
+        // from the fonts.
+        let fonts: Vec<FontItem> = module.fonts[self.fonts_written.min(module.fonts.len())..]
+            .iter()
+            .map(|font| FontItem {
+                glyphs: vec![],
+                glyph_cov: Default::default(),
+                ..font.clone()
+            })
+            .collect();
+        let mut seen = HashSet::new();
+        let glyphs: Vec<(GlyphRef, FlatGlyphItem)> = module
+            .glyphs
+            .iter()
+            .map(|(id, glyph)| (*id, glyph))
+            .chain(module.glyphs_all())
+            .filter(|(id, _)| {
+                used_glyphs.contains(id) && !self.glyphs_written.contains(id) && seen.insert(*id)
+            })
+            .map(|(id, glyph)| (id, glyph.clone()))
+            .collect();
+        if !fonts.is_empty() || !glyphs.is_empty() || !shared_items.is_empty() {
+            self.items_written
+                .extend(shared_items.iter().map(|(fg, _)| *fg));
+            let block = ResourceBlock {
+                fonts: Arc::new(IncrFontPack {
+                    incremental_base: self.fonts_written,
+                    items: fonts,
+                }),
+                glyphs: Arc::new(glyphs.into()),
+                items: ItemPack(shared_items),
+            };
+            self.fonts_written = module.fonts.len();
+            self.glyphs_written
+                .extend(block.glyphs.items.iter().map(|(id, _)| *id));
+
+            let range = self.write_block(BlockKind::Resource, to_bytes(&block))?;
+            self.header.resources.push(range);
+        }
+
+        let block = PageBlock {
+            index: self.header.pages.len() as u32,
+            page: page.clone(),
+            items: ItemPack(items),
+        };
+        let range = self.write_block(BlockKind::Page, to_bytes(&block))?;
+        self.header.pages.push(PageEntry {
+            page: page.clone(),
+            block: range,
+            resources: self.header.resources.len() as u32,
+        });
+
+        Ok(())
+    }
+
+    /// Write the index and the trailer, and return the underlying writer.
+    pub fn finish(self) -> ZResult<W> {
+        self.finish_with(vec![])
+    }
+
+    /// Write the index like [`Self::finish`], attaching the metadata of the
+    /// document, e.g. [`ModuleMetadata::Links`].
+    pub fn finish_with(mut self, metadata: Vec<ModuleMetadata>) -> ZResult<W> {
+        let mut header = std::mem::take(&mut self.header);
+        header.metadata = metadata;
+        let range = self.write_block(BlockKind::Index, to_bytes(&header))?;
+        self.out
+            .write_all(&range.offset.to_le_bytes())
+            .map_err(map_string_err("paged artifact: write trailer"))?;
+        self.out
+            .flush()
+            .map_err(map_string_err("paged artifact: flush"))?;
+        Ok(self.out)
+    }
+
+    fn write_block(&mut self, kind: BlockKind, mut payload: Vec<u8>) -> ZResult<BlockRange> {
+        let payload_len = payload.len() as u64;
+        // Pad the payload so that the next block header is aligned as well.
+        payload.resize(payload.len().next_multiple_of(BLOCK_HEADER_SIZE), 0);
+
+        let mut head = [0u8; BLOCK_HEADER_SIZE];
+        head[0] = kind as u8;
+        head[4..8].copy_from_slice(&checksum(&payload[..payload_len as usize]).to_le_bytes());
+        head[8..].copy_from_slice(&payload_len.to_le_bytes());
+
+        self.out
+            .write_all(&head)
+            .and_then(|_| self.out.write_all(&payload))
+            .map_err(map_string_err("paged artifact: write block"))?;
+
+        let range = BlockRange {
+            offset: self.offset,
+            len: (BLOCK_HEADER_SIZE + payload.len()) as u64,
+        };
+        self.offset += range.len;
+        Ok(range)
+    }
+}
+
+impl VecDocument {
+    /// Serialize the document into a paged artifact.
+    pub fn to_paged_bytes(&self) -> ZResult<Vec<u8>> {
+        self.to_paged_bytes_with(vec![])
+    }
+
+    /// Serialize the document into a paged artifact like
+    /// [`Self::to_paged_bytes`], attaching extra metadata.
+    pub fn to_paged_bytes_with(&self, metadata: Vec<ModuleMetadata>) -> ZResult<Vec<u8>> {
+        let mut writer = PagedArtifactWriter::new(vec![])?;
+        for page in &self.pages {
+            writer.write_page(&self.module, page)?;
+        }
+        writer.finish_with(metadata)
+    }
+}
+
+/// Whether the item is interned in resource blocks instead of being stored in
+/// every page using it.
+///
+/// Images and gradients are usually repeated across pages, e.g. a logo in the
+/// header, and they are leaves which don't reference other items.
+pub fn is_shared_item(item: &VecItem) -> bool {
+    matches!(item, VecItem::Image(..) | VecItem::Gradient(..))
+}
+
+/// Check whether the bytes are a paged artifact.
+pub fn is_paged_artifact(bytes: &[u8]) -> bool {
+    bytes.starts_with(&PAGED_MAGIC)
+}
+
+/// Read the index of an entire paged artifact.
+pub fn read_header(artifact: &[u8]) -> ZResult<PagedArtifactHeader> {
+    if !is_paged_artifact(artifact) || artifact.len() < PAGED_MAGIC.len() + TRAILER_SIZE {
+        return Err(error_once!("paged artifact: bad magic or truncated"));
+    }
+
+    let (rest, trailer) = artifact.split_at(artifact.len() - TRAILER_SIZE);
+    let offset = u64::from_le_bytes(trailer.try_into().unwrap());
+    let block = usize::try_from(offset)
+        .ok()
+        .filter(|offset| *offset >= PAGED_MAGIC.len())
+        .and_then(|offset| rest.get(offset..))
+        .ok_or_else(|| error_once!("paged artifact: index out of range", offset: offset))?;
+
+    match decode_block_at(block, offset)? {
+        PagedBlock::Index(header) => Ok(header),
+        _ => Err(error_once!(
+            "paged artifact: trailer does not point to index"
+        )),
+    }
+}
+
+/// Decode page `k` given the index and the bytes of that page block.
+///
+/// Interned items used by the page are stored in the resource blocks written
+/// before it, see [`PageEntry::resources`].
+pub fn decode_page(header: &PagedArtifactHeader, k: usize, block: &[u8]) -> ZResult<PageBlock> {
+    let entry = header
+        .pages
+        .get(k)
+        .ok_or_else(|| error_once!("paged artifact: page out of range", page: k))?;
+    if block.len() as u64 != entry.block.len {
+        return Err(error_once!("paged artifact: page block length mismatch", page: k));
+    }
+
+    match decode_block_at(block, entry.block.offset)? {
+        PagedBlock::Page(page) if page.index as usize == k => Ok(page),
+        _ => Err(error_once!("paged artifact: not the requested page block", page: k)),
+    }
+}
+
+/// Decode a single framed block.
+pub fn decode_block(block: &[u8]) -> ZResult<PagedBlock> {
+    decode_block_at(block, 0)
+}
+
+/// Decode a single framed block, which starts at `offset` of the artifact.
+///
+/// The offset is only used for error messages.
+pub(crate) fn decode_block_at(block: &[u8], offset: u64) -> ZResult<PagedBlock> {
+    let (kind, payload) = split_block(block, offset)?.ok_or_else(
+        || error_once!("paged artifact: truncated block", offset: offset, len: block.len()),
+    )?;
+    let expected = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    if checksum(payload) != expected {
+        return Err(error_once!("paged artifact: checksum mismatch", offset: offset));
+    }
+
+    let mut aligned = AlignedVec::with_capacity(payload.len());
+    aligned.extend_from_slice(payload);
+    let payload = aligned.as_slice();
+
+    Ok(match kind {
+        BlockKind::Resource => PagedBlock::Resource(from_bytes::<ResourceBlock>(payload, offset)?),
+        BlockKind::Page => PagedBlock::Page(from_bytes::<PageBlock>(payload, offset)?),
+        BlockKind::Index => PagedBlock::Index(from_bytes::<PagedArtifactHeader>(payload, offset)?),
+    })
+}
+
+/// Decode an entire paged artifact into a document.
+///
+/// The artifact is checked by [`validate`] as a whole before it is returned.
+pub fn decode_document(artifact: &[u8]) -> ZResult<MultiVecDocument> {
+    Ok(decode_checked(artifact)?.into_multi())
+}
+
+/// Decode an entire paged artifact like [`decode_document`], along with the
+/// metadata stored in its index.
+pub fn decode_document_with_metadata(
+    artifact: &[u8],
+) -> ZResult<(MultiVecDocument, Vec<ModuleMetadata>)> {
+    let mut doc = decode_checked(artifact)?;
+    let metadata = std::mem::take(&mut doc.metadata);
+    Ok((doc.into_multi(), metadata))
+}
+
+/// Check that an entire paged artifact can be decoded and rendered.
+///
+/// This checks the magic, the trailer, the bounds of the offset table, the
+/// checksum of every block, and that every referenced resource, item and
+/// font is present in the artifact.
+pub fn validate(artifact: &[u8]) -> ZResult<()> {
+    decode_checked(artifact).map(|_| ())
+}
+
+fn decode_checked(artifact: &[u8]) -> ZResult<PagedDocument> {
+    let header = read_header(artifact)?;
+
+    for (k, entry) in header.pages.iter().enumerate() {
+        entry.block.slice(artifact)?;
+        if entry.resources as usize > header.resources.len() {
+            return Err(
+                error_once!("paged artifact: page references unknown resources", page: k, resources: entry.resources),
+            );
+        }
+    }
+    for range in &header.resources {
+        range.slice(artifact)?;
+    }
+
+    let mut doc = PagedDocument::default();
+    let mut decoder = PagedStreamDecoder::default();
+    for block in decoder.feed(artifact)? {
+        doc.merge_block(block)?;
+    }
+    if !decoder.finished {
+        return Err(error_once!("paged artifact: missing index block"));
+    }
+
+    for (k, (entry, page)) in header.pages.iter().zip(&doc.pages).enumerate() {
+        if page.as_ref() != Some(&entry.page) {
+            return Err(error_once!("paged artifact: page does not match index", page: k));
+        }
+        if !doc.module.items.contains_key(&entry.page.content) {
+            return Err(error_once!("paged artifact: missing page content", page: k));
+        }
+    }
+    check_references(&doc.module)?;
+
+    Ok(doc)
+}
+
+/// Check that items only reference present items and fonts.
+pub(crate) fn check_references(module: &Module) -> ZResult<()> {
+    let check = |fg: &Fingerprint, child: &Fingerprint| {
+        if module.items.contains_key(child) {
+            Ok(())
+        } else {
+            Err(
+                error_once!("paged artifact: missing item", item: fg.as_svg_id("g"), child: child.as_svg_id("g")),
+            )
+        }
+    };
+
+    for (fg, item) in &module.items {
+        match item {
+            VecItem::Item(t) => check(fg, &t.1)?,
+            VecItem::Group(g) => {
+                for (_, child) in g.0.iter() {
+                    check(fg, child)?;
+                }
+            }
+            VecItem::Pattern(p) => check(fg, &p.frame)?,
+            VecItem::ColorTransform(c) => check(fg, &c.item)?,
+            VecItem::Text(t) => {
+                let font = &t.shape.font;
+                if !matches!(module.get_font(font), Some(f) if f.hash == font.hash) {
+                    return Err(
+                        error_once!("paged artifact: missing font", item: fg.as_svg_id("g"), font: font.idx),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Split a block which starts at `offset` into its kind and unpadded payload.
+///
+/// Returns `None` if the block is not complete yet.
+fn split_block(block: &[u8], offset: u64) -> ZResult<Option<(BlockKind, &[u8])>> {
+    if block.len() < BLOCK_HEADER_SIZE {
+        return Ok(None);
+    }
+    let kind = BlockKind::from_u8(block[0]).map_err(
+        |_| error_once!("paged artifact: unknown block kind", kind: block[0], offset: offset),
+    )?;
+    let len = u64::from_le_bytes(block[8..BLOCK_HEADER_SIZE].try_into().unwrap());
+    let end = usize::try_from(len)
+        .ok()
+        .and_then(block_size)
+        .ok_or_else(|| error_once!("paged artifact: block too large", offset: offset, len: len))?;
+    if block.len() < end {
+        return Ok(None);
+    }
+
+    let payload = &block[BLOCK_HEADER_SIZE..BLOCK_HEADER_SIZE + len as usize];
+    Ok(Some((kind, payload)))
+}
+
+/// Padded size of a block which has a payload of the given length.
+fn block_size(payload_len: usize) -> Option<usize> {
+    payload_len
+        .checked_next_multiple_of(BLOCK_HEADER_SIZE)?
+        .checked_add(BLOCK_HEADER_SIZE)
+}
+
+/// Checksum of a block payload.
+fn checksum(payload: &[u8]) -> u32 {
+    hash128(&payload) as u32
+}
+
+/// Decodes blocks of a paged artifact as its bytes arrive.
+#[derive(Default)]
+pub struct PagedStreamDecoder {
+    buffer: Vec<u8>,
+    magic_checked: bool,
+    /// Offset of the buffer in the artifact.
+    offset: u64,
+    /// The index of the next page block.
+    next_page: u32,
+    /// Whether the index block has been decoded.
+    finished: bool,
+}
+
+impl PagedStreamDecoder {
+    /// Whether the index block has been decoded, after which the remaining
+    /// bytes are ignored.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Feed the next chunk of bytes and get the blocks completed by it.
+    pub fn feed(&mut self, chunk: &[u8]) -> ZResult<Vec<PagedBlock>> {
+        if self.finished {
+            // Only the trailer follows the index.
+            return Ok(vec![]);
+        }
+        self.buffer.extend_from_slice(chunk);
+
+        if !self.magic_checked {
+            if self.buffer.len() < PAGED_MAGIC.len() {
+                return Ok(vec![]);
+            }
+            if !is_paged_artifact(&self.buffer) {
+                return Err(error_once!("paged artifact: bad magic"));
+            }
+            self.buffer.drain(..PAGED_MAGIC.len());
+            self.magic_checked = true;
+            self.offset = PAGED_MAGIC.len() as u64;
+        }
+
+        let mut blocks = vec![];
+        let mut consumed = 0;
+        while let Some((_, payload)) = split_block(&self.buffer[consumed..], self.offset)? {
+            // Checked by `split_block`.
+            let size = block_size(payload.len()).unwrap();
+            let block = decode_block_at(&self.buffer[consumed..consumed + size], self.offset)?;
+            consumed += size;
+            self.offset += size as u64;
+
+            match &block {
+                PagedBlock::Page(page) if page.index != self.next_page => {
+                    return Err(
+                        error_once!("paged artifact: page out of order", page: page.index, expected: self.next_page, offset: self.offset - size as u64),
+                    );
+                }
+                PagedBlock::Page(..) => self.next_page += 1,
+                PagedBlock::Index(..) => self.finished = true,
+                PagedBlock::Resource(..) => {}
+            }
+            blocks.push(block);
+            if self.finished {
+                consumed = self.buffer.len();
+                break;
+            }
+        }
+        self.buffer.drain(..consumed);
+
+        Ok(blocks)
+    }
+}
+
+/// A document assembled from blocks of a paged artifact.
+#[derive(Default)]
+pub struct PagedDocument {
+    pub module: Module,
+    pub pages: Vec<Option<Page>>,
+    /// The metadata of the document, which arrives with the index.
+    pub metadata: Vec<ModuleMetadata>,
+}
+
+impl PagedDocument {
+    /// Merge a decoded block into the document.
+    ///
+    /// Blocks must be merged in the order of the artifact, as done by
+    /// [`PagedStreamDecoder`].
+    pub fn merge_block(&mut self, block: PagedBlock) -> ZResult<()> {
+        match block {
+            PagedBlock::Resource(res) => {
+                if res.fonts.incremental_base != self.module.fonts.len() {
+                    return Err(
+                        error_once!("paged artifact: resource block out of order", base: res.fonts.incremental_base, fonts: self.module.fonts.len()),
+                    );
+                }
+                validate_glyphs(
+                    self.module.fonts.iter().chain(res.fonts.items.iter()),
+                    &res.glyphs.items,
+                )?;
+
+                self.module.items.extend(res.items.0);
+                self.module.fonts.extend(res.fonts.take().items);
+                let glyphs = res.glyphs.take().items;
+                if !glyphs.is_empty() {
+                    self.module.glyphs.extend(glyphs);
+                    self.module.prepare_glyphs();
+                }
+            }
+            PagedBlock::Page(page) => {
+                let index = page.index as usize;
+                if index > self.pages.len() {
+                    return Err(
+                        error_once!("paged artifact: page out of order", page: index, pages: self.pages.len()),
+                    );
+                }
+                self.module.items.extend(page.items.0);
+                if self.pages.len() == index {
+                    self.pages.push(None);
+                }
+                self.pages[index] = Some(page.page);
+            }
+            PagedBlock::Index(header) => {
+                if header.pages.len() < self.pages.len() {
+                    return Err(
+                        error_once!("paged artifact: index does not match pages", index: header.pages.len(), pages: self.pages.len()),
+                    );
+                }
+                self.pages.resize(header.pages.len(), None);
+                self.metadata = header.metadata;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the page at the given index is ready to be rendered.
+    pub fn is_page_ready(&self, index: usize) -> bool {
+        matches!(self.pages.get(index), Some(Some(..)))
+    }
+
+    /// Get the layouts of the pages which are ready, i.e. of the pages before
+    /// the first one that has not arrived, as they arrive in order.
+    pub fn ready_layouts(&self) -> Vec<LayoutRegion> {
+        let pages = self.pages.iter().map_while(Clone::clone).collect();
+        let module = Module::default();
+        VecDocument { module, pages }.to_multi().layouts
+    }
+
+    /// Convert to a document, skipping the pages that have not arrived.
+    pub fn into_multi(self) -> MultiVecDocument {
+        VecDocument {
+            module: self.module,
+            pages: self.pages.into_iter().flatten().collect(),
+        }
+        .to_multi()
+    }
+}
+
+pub(crate) fn to_bytes<T>(v: &T) -> Vec<u8>
+where
+    T: rkyv::Serialize<rkyv::ser::serializers::AllocSerializer<0>>,
+{
+    use rkyv::ser::{serializers::AllocSerializer, Serializer};
+
+    let mut serializer = AllocSerializer::<0>::default();
+    serializer.serialize_value(v).unwrap();
+    serializer.into_serializer().into_inner().into_vec()
+}
+
+fn from_bytes<T>(payload: &[u8], offset: u64) -> ZResult<T>
+where
+    T: rkyv::Archive,
+    for<'a> T::Archived: rkyv::CheckBytes<rkyv::validation::validators::DefaultValidator<'a>>
+        + rDeser<T, SharedDeserializeMap>,
+{
+    let archived = rkyv::check_archived_root::<T>(payload).map_err(
+        |err| error_once!("paged artifact: invalid block", offset: offset, err: err.to_string()),
+    )?;
+    let mut dmap = SharedDeserializeMap::default();
+    archived.deserialize(&mut dmap).map_err(|err| {
+        error_once!("paged artifact: deserialize block", offset: offset, err: format!("{err:?}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::ir::{
+        Axes, FontRef, GroupRef, Image, ImageItem, OutlineGlyphItem, PathItem, PathStyle, Scalar,
+        Size, TextItem, TextItemContent, TextShape,
+    };
+
+    fn fg(v: u64) -> Fingerprint {
+        Fingerprint::from_pair(v, 0)
+    }
+
+    fn path(d: &str) -> VecItem {
+        VecItem::Path(PathItem {
+            d: d.into(),
+            size: None,
+            styles: vec![PathStyle::Fill("#000".into())],
+        })
+    }
+
+    fn fixture() -> VecDocument {
+        let mut module = Module::default();
+        module.items.insert(fg(1), path("M 0 0 L 1 1"));
+        module.items.insert(fg(2), path("M 1 1 L 2 2"));
+        module.items.insert(fg(3), path("M 2 2 L 3 3"));
+        let group = |children: &[u64]| {
+            VecItem::Group(GroupRef(
+                children
+                    .iter()
+                    .map(|c| (Default::default(), fg(*c)))
+                    .collect(),
+            ))
+        };
+        module.items.insert(
+            fg(4),
+            VecItem::Image(ImageItem {
+                image: Arc::new(Image {
+                    data: vec![0; 1024],
+                    format: "png".into(),
+                    size: Axes::new(32, 32),
+                    alt: None,
+                    hash: fg(4),
+                }),
+                size: Size::new(Scalar(32.), Scalar(32.)),
+            }),
+        );
+        module.items.insert(fg(10), group(&[1, 2, 4]));
+        module.items.insert(fg(11), group(&[2, 3, 4]));
+
+        let size = Size::new(Scalar(100.), Scalar(200.));
+        VecDocument {
+            module,
+            pages: vec![
+                Page {
+                    content: fg(10),
+                    size,
+                },
+                Page {
+                    content: fg(11),
+                    size,
+                },
+            ],
+        }
+    }
+
+    fn pages_of(doc: &MultiVecDocument) -> Vec<Page> {
+        let layout = doc.layouts[0].unwrap_single();
+        layout.pages_meta().unwrap().to_vec()
+    }
+
+    #[test]
+    fn test_paged_round_trip() {
+        let paged = fixture().to_paged_bytes().unwrap();
+        let monolithic = MultiVecDocument::from_slice(&fixture().to_bytes());
+
+        let doc = decode_document(&paged).unwrap();
+        assert_eq!(doc.module.items, monolithic.module.items);
+        assert_eq!(pages_of(&doc), pages_of(&monolithic));
+    }
+
+    #[test]
+    fn test_decode_single_page() {
+        let paged = fixture().to_paged_bytes().unwrap();
+        let header = read_header(&paged).unwrap();
+        assert_eq!(header.pages.len(), 2);
+
+        let block = header.pages[1].block.slice(&paged).unwrap();
+        let page = decode_page(&header, 1, block).unwrap();
+        assert_eq!(page.page.content, fg(11));
+        let mut items: Vec<_> = page.items.0.iter().map(|(fg, _)| *fg).collect();
+        items.sort();
+        assert_eq!(items, vec![fg(2), fg(3), fg(11)]);
+    }
+
+    #[test]
+    fn test_shared_items_interned() {
+        let paged = fixture().to_paged_bytes().unwrap();
+        assert_eq!(paged, fixture().to_paged_bytes().unwrap());
+
+        let header = read_header(&paged).unwrap();
+        let mut images = 0;
+        for range in header
+            .resources
+            .iter()
+            .chain(header.pages.iter().map(|p| &p.block))
+        {
+            let items = match decode_block(range.slice(&paged).unwrap()).unwrap() {
+                PagedBlock::Resource(res) => res.items,
+                PagedBlock::Page(page) => page.items,
+                PagedBlock::Index(..) => unreachable!(),
+            };
+            images += items
+                .0
+                .iter()
+                .filter(|(_, item)| is_shared_item(item))
+                .count();
+        }
+        assert_eq!(images, 1);
+    }
+
+    #[test]
+    fn test_glyphs_of_resource_blocks() {
+        let glyph = |d: &str| {
+            FlatGlyphItem::Outline(Arc::new(OutlineGlyphItem {
+                ts: None,
+                d: d.into(),
+                ligature_len: 0,
+            }))
+        };
+        let text = |glyph_idx: u32| {
+            VecItem::Text(TextItem {
+                shape: Arc::new(TextShape {
+                    font: FontRef { hash: 7, idx: 0 },
+                    dir: "ltr".into(),
+                    size: Scalar(10.),
+                    styles: vec![],
+                }),
+                content: Arc::new(TextItemContent {
+                    content: "a".into(),
+                    glyphs: Arc::new([(Scalar(0.), Scalar(5.), glyph_idx)]),
+                }),
+            })
+        };
+
+        let mut module = Module::default();
+        module.fonts.push(FontItem {
+            fingerprint: fg(7),
+            family: "Sans".into(),
+            hash: 7,
+            cap_height: Scalar(0.7),
+            ascender: Scalar(0.8),
+            descender: Scalar(-0.2),
+            units_per_em: Scalar(1000.),
+            vertical: false,
+            glyphs: vec![],
+            glyph_cov: Default::default(),
+        });
+        module.glyphs = [(1, "M 0 0 L 1 1"), (2, "M 0 0 L 2 2")]
+            .map(|(glyph_idx, d)| {
+                (
+                    GlyphRef {
+                        font_hash: 7,
+                        glyph_idx,
+                    },
+                    glyph(d),
+                )
+            })
+            .to_vec();
+        module.items.insert(fg(1), text(1));
+        module.items.insert(fg(2), text(2));
+        let size = Size::new(Scalar(100.), Scalar(200.));
+        let pages = [1, 2]
+            .map(|k| Page {
+                content: fg(k),
+                size,
+            })
+            .to_vec();
+
+        // Each page uses a glyph for the first time, hence the glyphs are
+        // written in two resource blocks.
+        let paged = VecDocument { module, pages }.to_paged_bytes().unwrap();
+        assert_eq!(read_header(&paged).unwrap().resources.len(), 2);
+
+        let doc = decode_document(&paged).unwrap();
+        let glyphs: Vec<_> = (doc.module.glyphs_all())
+            .map(|(id, glyph)| (id.glyph_idx, glyph.clone()))
+            .collect();
+        assert_eq!(
+            glyphs,
+            vec![(1, glyph("M 0 0 L 1 1")), (2, glyph("M 0 0 L 2 2"))]
+        );
+    }
+
+    #[test]
+    fn test_stream_decode_by_byte() {
+        let paged = fixture().to_paged_bytes().unwrap();
+
+        let mut decoder = PagedStreamDecoder::default();
+        let mut doc = PagedDocument::default();
+        for byte in paged.chunks(1) {
+            for block in decoder.feed(byte).unwrap() {
+                doc.merge_block(block).unwrap();
+            }
+        }
+        assert!(doc.is_page_ready(0));
+        assert!(doc.is_page_ready(1));
+    }
+
+    #[test]
+    fn test_corrupted_artifact() {
+        let paged = fixture().to_paged_bytes().unwrap();
+        validate(&paged).unwrap();
+
+        for len in 0..paged.len() {
+            assert!(validate(&paged[..len]).is_err(), "truncated at {len}");
+        }
+
+        // A simple xorshift, to mutate bytes deterministically.
+        let mut state = 0x2545_f491_u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize
+        };
+        for _ in 0..2000 {
+            let mut mutated = paged.clone();
+            for _ in 0..1 + next() % 4 {
+                let at = next() % mutated.len();
+                mutated[at] = next() as u8;
+            }
+            let _ = validate(&mutated);
+            let _ = decode_document(&mutated);
+
+            let mut decoder = PagedStreamDecoder::default();
+            let mut doc = PagedDocument::default();
+            for chunk in mutated.chunks(7) {
+                let Ok(blocks) = decoder.feed(chunk) else {
+                    break;
+                };
+                if blocks
+                    .into_iter()
+                    .any(|block| doc.merge_block(block).is_err())
+                {
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,552 @@
+//! Size breakdown of exported artifacts.
+//!
+//! All sizes are measured on the serialized form of an artifact. Sections
+//! and pages of a paged artifact are the byte spans of their blocks. A single
+//! resource or item is measured by its own encoding, which is the span it
+//! occupies in its block or section, up to alignment padding.
+//!
+//! The items of an image placed at different sizes share its data, which is
+//! serialized once in a block or a monolithic artifact, hence the images are
+//! reported by their contents along with the pages using them.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use super::ir::{
+    FlatGlyphItem, FontItem, GlyphRef, ModuleMetadata, ModuleStream, MultiVecDocument, Page,
+    VecItem,
+};
+use super::paged::{self, to_bytes, PagedBlock, PAGED_MAGIC, TRAILER_SIZE};
+use super::stream::BytesModuleStream;
+use crate::{error::prelude::*, hash::Fingerprint};
+
+/// A size breakdown of an artifact.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactStats {
+    /// The size of the entire artifact.
+    pub total_bytes: usize,
+    /// Sections of the artifact in the order of serialization.
+    pub sections: Vec<SectionStats>,
+    /// Fonts, including their glyphs.
+    pub fonts: Vec<FontStats>,
+    pub images: Vec<ImageStats>,
+    pub pages: Vec<PageStats>,
+    /// The size of all text items.
+    pub text_bytes: usize,
+    /// The largest resources in descending order of size.
+    pub largest: Vec<ResourceStats>,
+    /// The decisions of the image limits of the compilation producing the
+    /// artifact, see [`Self::with_image_decisions`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub image_decisions: Vec<ImageDecision>,
+}
+
+impl ArtifactStats {
+    /// Attach the decisions of the image limits, which are not stored in the
+    /// artifact, hence only known by the compiler producing it.
+    pub fn with_image_decisions(mut self, decisions: Vec<ImageDecision>) -> Self {
+        self.image_decisions = decisions;
+        self
+    }
+}
+
+/// A section of an artifact, e.g. the resource blocks of a paged artifact or
+/// a metadata entry of a monolithic artifact.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionStats {
+    pub name: String,
+    pub bytes: usize,
+    /// The number of entries in the section.
+    pub count: usize,
+}
+
+/// A font face.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontStats {
+    pub family: String,
+    /// The number of glyphs stored for this face.
+    pub glyphs: usize,
+    /// The size of the font item and its glyphs.
+    pub bytes: usize,
+}
+
+/// An image, which is embedded once however many items place it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageStats {
+    /// The id of the first item placing the image.
+    pub id: String,
+    /// The hash of the content of the image.
+    pub hash: String,
+    pub width: u32,
+    pub height: u32,
+    /// The encoding of the image, e.g. `png`.
+    pub format: String,
+    /// The size of the largest item placing the image, which embeds its data.
+    pub bytes: usize,
+    /// The number of items placing the image, e.g. at different sizes.
+    pub items: usize,
+    /// The number of pages using the image.
+    pub pages: usize,
+}
+
+/// A decision of the image limits of a compiler on an image file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageDecision {
+    pub path: String,
+    /// The width of the image file in pixels.
+    pub width: u32,
+    /// The height of the image file in pixels.
+    pub height: u32,
+    pub action: ImageAction,
+}
+
+/// The action taken on an image file, see [`ImageDecision`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum ImageAction {
+    /// The image is used as is.
+    Kept,
+    /// The image is downscaled to the given size.
+    Downscaled { width: u32, height: u32 },
+    /// Only the first frame of the animated image is used, in the given size.
+    FirstFrame { width: u32, height: u32 },
+    /// The image is rejected.
+    Rejected { reason: String },
+}
+
+/// A page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageStats {
+    pub index: usize,
+    /// The size of the items of the page.
+    ///
+    /// For a paged artifact, this is the size of the page block. Otherwise,
+    /// this is the size of the items reachable from the page, so items shared
+    /// by pages are counted for each page.
+    pub bytes: usize,
+    pub items: usize,
+}
+
+/// The kind of a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResourceKind {
+    Font,
+    Image,
+    Gradient,
+}
+
+/// A single resource.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceStats {
+    pub kind: ResourceKind,
+    /// The family of a font, or the id of an item.
+    pub id: String,
+    pub bytes: usize,
+}
+
+/// Get a size breakdown of an artifact in either the monolithic or the paged
+/// format, keeping the `top_n` largest resources.
+pub fn stats(artifact: &[u8], top_n: usize) -> ZResult<ArtifactStats> {
+    let mut builder = StatsBuilder::default();
+    builder.stats.total_bytes = artifact.len();
+    if paged::is_paged_artifact(artifact) {
+        builder.paged(artifact)?;
+    } else {
+        builder.monolithic(artifact)?;
+    }
+
+    Ok(builder.finish(top_n))
+}
+
+#[derive(Default)]
+struct StatsBuilder {
+    stats: ArtifactStats,
+    /// Indices of fonts in `stats.fonts` by their hash.
+    fonts: HashMap<u32, usize>,
+    /// Encoded sizes of items.
+    items: HashMap<Fingerprint, usize>,
+    /// Indices of images in `stats.images` by the fingerprints of their items.
+    image_items: HashMap<Fingerprint, usize>,
+    /// Indices of images in `stats.images` by the hashes of their contents.
+    images: HashMap<Fingerprint, usize>,
+    resources: Vec<ResourceStats>,
+}
+
+impl StatsBuilder {
+    fn paged(&mut self, artifact: &[u8]) -> ZResult<()> {
+        let header = paged::read_header(artifact)?;
+
+        let resource_bytes: usize = header.resources.iter().map(|r| r.len as usize).sum();
+        let page_bytes: usize = header.pages.iter().map(|p| p.block.len as usize).sum();
+        // Blocks are contiguous and the index block is the last one.
+        let index_bytes = artifact
+            .len()
+            .checked_sub(PAGED_MAGIC.len() + TRAILER_SIZE + resource_bytes + page_bytes)
+            .ok_or_else(|| error_once!("paged artifact: blocks exceed the artifact"))?;
+        self.section("magic", PAGED_MAGIC.len(), 1);
+        self.section("resources", resource_bytes, header.resources.len());
+        self.section("pages", page_bytes, header.pages.len());
+        self.section("index", index_bytes, 1);
+        self.section("trailer", TRAILER_SIZE, 1);
+
+        for range in &header.resources {
+            let PagedBlock::Resource(res) = paged::decode_block(range.slice(artifact)?)? else {
+                return Err(
+                    error_once!("paged artifact: not a resource block", offset: range.offset),
+                );
+            };
+            res.fonts.items.iter().for_each(|font| self.font(font));
+            res.glyphs.items.iter().for_each(|glyph| self.glyph(glyph));
+            for (fg, item) in &res.items.0 {
+                self.item(fg, item);
+            }
+        }
+
+        for (k, entry) in header.pages.iter().enumerate() {
+            let page = paged::decode_page(&header, k, entry.block.slice(artifact)?)?;
+            // The images are interned in resource blocks, hence only referred
+            // to by the items of the page.
+            let mut images = HashSet::new();
+            for (fg, item) in &page.items.0 {
+                self.item(fg, item);
+                for child in children(item) {
+                    images.extend(self.image_items.get(&child).copied());
+                }
+            }
+            self.page_images(images);
+            self.stats.pages.push(PageStats {
+                index: k,
+                bytes: entry.block.len as usize,
+                items: page.items.0.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn monolithic(&mut self, artifact: &[u8]) -> ZResult<()> {
+        let module = BytesModuleStream::from_slice(artifact).try_checkout_owned()?;
+        for meta in &module.metadata {
+            let (name, count) = match meta {
+                ModuleMetadata::BuildVersion(..) => ("buildVersion", 1),
+                ModuleMetadata::SourceMappingData(v) => ("sourceMapping", v.len()),
+                ModuleMetadata::PageSourceMapping(..) => ("pageSourceMapping", 1),
+                ModuleMetadata::GarbageCollection(v) => ("garbageCollection", v.len()),
+                ModuleMetadata::Item(v) => ("items", v.0.len()),
+                ModuleMetadata::Font(v) => ("fonts", v.items.len()),
+                ModuleMetadata::Glyph(v) => ("glyphs", v.items.len()),
+                ModuleMetadata::Layout(v) => ("layouts", v.len()),
+                ModuleMetadata::Links(v) => ("links", v.len()),
+                ModuleMetadata::PageMeta(v) => ("pageMeta", v.len()),
+                ModuleMetadata::Outline(v) => ("outline", v.len()),
+                ModuleMetadata::MetadataAnchors(v) => ("metadataAnchors", v.len()),
+            };
+            self.section(name, to_bytes(meta).len(), count);
+        }
+
+        let stream = &module;
+        stream.fonts().items.iter().for_each(|font| self.font(font));
+        stream
+            .glyphs()
+            .items
+            .iter()
+            .for_each(|glyph| self.glyph(glyph));
+
+        let mut doc = MultiVecDocument::default();
+        doc.try_merge_delta(stream)?;
+        for (fg, item) in &doc.module.items {
+            self.item(fg, item);
+        }
+
+        for (k, page) in first_layout_pages(&doc).iter().enumerate() {
+            let (mut bytes, mut items) = (0, 0);
+            let mut images = HashSet::new();
+            doc.module.visit_reachable(&page.content, &mut |fg, _| {
+                bytes += self.items.get(fg).copied().unwrap_or_default();
+                items += 1;
+                images.extend(self.image_items.get(fg).copied());
+            });
+            self.page_images(images);
+            self.stats.pages.push(PageStats {
+                index: k,
+                bytes,
+                items,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn section(&mut self, name: &str, bytes: usize, count: usize) {
+        self.stats.sections.push(SectionStats {
+            name: name.to_owned(),
+            bytes,
+            count,
+        });
+    }
+
+    fn font(&mut self, font: &FontItem) {
+        self.fonts.insert(font.hash, self.stats.fonts.len());
+        self.stats.fonts.push(FontStats {
+            family: font.family.to_string(),
+            glyphs: 0,
+            bytes: to_bytes(font).len(),
+        });
+    }
+
+    fn glyph(&mut self, glyph: &(GlyphRef, FlatGlyphItem)) {
+        let bytes = to_bytes(glyph).len();
+        if let Some(font) = self.fonts.get(&glyph.0.font_hash) {
+            let font = &mut self.stats.fonts[*font];
+            font.glyphs += 1;
+            font.bytes += bytes;
+        }
+    }
+
+    fn item(&mut self, fg: &Fingerprint, item: &VecItem) {
+        let bytes = to_bytes(item).len();
+        self.items.insert(*fg, bytes);
+
+        let id = fg.as_svg_id("g");
+        match item {
+            VecItem::Image(image) => {
+                if self.image_items.contains_key(fg) {
+                    return;
+                }
+
+                let image = &image.image;
+                let idx = *self.images.entry(image.hash).or_insert_with(|| {
+                    self.stats.images.push(ImageStats {
+                        id,
+                        hash: image.hash.as_svg_id("i"),
+                        width: image.width(),
+                        height: image.height(),
+                        format: image.format.to_string(),
+                        bytes: 0,
+                        items: 0,
+                        pages: 0,
+                    });
+                    self.stats.images.len() - 1
+                });
+                self.image_items.insert(*fg, idx);
+                let stats = &mut self.stats.images[idx];
+                stats.items += 1;
+                stats.bytes = stats.bytes.max(bytes);
+            }
+            VecItem::Gradient(..) => self.resources.push(ResourceStats {
+                kind: ResourceKind::Gradient,
+                id,
+                bytes,
+            }),
+            VecItem::Text(..) => self.stats.text_bytes += bytes,
+            _ => {}
+        }
+    }
+
+    fn page_images(&mut self, images: HashSet<usize>) {
+        for idx in images {
+            self.stats.images[idx].pages += 1;
+        }
+    }
+
+    fn finish(mut self, top_n: usize) -> ArtifactStats {
+        self.resources
+            .extend(self.stats.images.iter().map(|image| ResourceStats {
+                kind: ResourceKind::Image,
+                id: image.id.clone(),
+                bytes: image.bytes,
+            }));
+        self.resources
+            .extend(self.stats.fonts.iter().map(|font| ResourceStats {
+                kind: ResourceKind::Font,
+                id: font.family.clone(),
+                bytes: font.bytes,
+            }));
+        self.resources
+            .sort_by_key(|resource| std::cmp::Reverse(resource.bytes));
+        self.resources.truncate(top_n);
+
+        self.stats.largest = self.resources;
+        self.stats
+    }
+}
+
+/// Get the items referred to by an item, see [`super::ir::Module::visit_reachable`].
+fn children(item: &VecItem) -> Vec<Fingerprint> {
+    match item {
+        VecItem::Item(t) => vec![t.1],
+        VecItem::Group(g) => g.0.iter().map(|(_, fg)| *fg).collect(),
+        VecItem::Pattern(p) => vec![p.frame],
+        VecItem::ColorTransform(c) => vec![c.item],
+        _ => vec![],
+    }
+}
+
+fn first_layout_pages(doc: &MultiVecDocument) -> Vec<Page> {
+    let Some(layout) = doc.layouts.first() else {
+        return vec![];
+    };
+    if layout.is_empty() {
+        return vec![];
+    }
+
+    layout
+        .unwrap_single()
+        .pages_meta()
+        .map(<[Page]>::to_vec)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::vector::ir::{
+        Axes, GroupRef, Image, ImageItem, Module, PathItem, Scalar, Size, VecDocument,
+    };
+
+    fn fixture() -> VecDocument {
+        let fg = |v| Fingerprint::from_pair(v, 0);
+        let mut module = Module::default();
+        module.items.insert(
+            fg(1),
+            VecItem::Path(PathItem {
+                d: "M 0 0 L 1 1".into(),
+                size: None,
+                styles: vec![],
+            }),
+        );
+        module.items.insert(
+            fg(2),
+            VecItem::Image(ImageItem {
+                image: Arc::new(Image {
+                    data: vec![0; 4096],
+                    format: "jpeg".into(),
+                    size: Axes::new(64, 48),
+                    alt: None,
+                    hash: fg(2),
+                }),
+                size: Size::new(Scalar(64.), Scalar(48.)),
+            }),
+        );
+        module.items.insert(
+            fg(10),
+            VecItem::Group(GroupRef(
+                [fg(1), fg(2)]
+                    .map(|c| (Default::default(), c))
+                    .into_iter()
+                    .collect(),
+            )),
+        );
+
+        let page = Page {
+            content: fg(10),
+            size: Size::new(Scalar(100.), Scalar(100.)),
+        };
+        VecDocument {
+            module,
+            pages: vec![page.clone(), page],
+        }
+    }
+
+    #[test]
+    fn test_artifact_stats() {
+        for artifact in [fixture().to_paged_bytes().unwrap(), fixture().to_bytes()] {
+            let res = stats(&artifact, 1).unwrap();
+            assert_eq!(res.total_bytes, artifact.len());
+            assert_eq!(res.pages.len(), 2);
+
+            assert_eq!(res.images.len(), 1);
+            let image = &res.images[0];
+            assert_eq!((image.width, image.height), (64, 48));
+            assert_eq!(image.format, "jpeg");
+            assert!(image.bytes >= 4096);
+            assert_eq!((image.items, image.pages), (1, 2));
+
+            assert_eq!(res.largest.len(), 1);
+            assert_eq!(res.largest[0].kind, ResourceKind::Image);
+        }
+
+        let paged = fixture().to_paged_bytes().unwrap();
+        let res = stats(&paged, 10).unwrap();
+        let sections: usize = res.sections.iter().map(|s| s.bytes).sum();
+        assert_eq!(sections, paged.len());
+    }
+
+    /// A document with a logo in the header of each page, which is smaller on
+    /// the last page.
+    fn header_logo(shared: bool) -> VecDocument {
+        let fg = |v| Fingerprint::from_pair(v, 0);
+        let logo = Arc::new(Image {
+            data: (0..8192u32).map(|i| (i * 7919 % 251) as u8).collect(),
+            format: "png".into(),
+            size: Axes::new(128, 64),
+            alt: None,
+            hash: fg(1),
+        });
+        let mut module = Module::default();
+        for (id, width) in [(2, 64.), (3, 32.)] {
+            let image = match shared {
+                true => logo.clone(),
+                false => Arc::new(logo.as_ref().clone()),
+            };
+            let size = Size::new(Scalar(width), Scalar(width / 2.));
+            module
+                .items
+                .insert(fg(id), VecItem::Image(ImageItem { image, size }));
+        }
+
+        let mut pages = vec![];
+        for k in 0..3 {
+            let path = VecItem::Path(PathItem {
+                d: format!("M 0 {k} L 1 1").into(),
+                size: None,
+                styles: vec![],
+            });
+            module.items.insert(fg(10 + k), path);
+            let logo = if k == 2 { fg(3) } else { fg(2) };
+            let children = [fg(10 + k), logo].map(|c| (Default::default(), c));
+            module.items.insert(
+                fg(20 + k),
+                VecItem::Group(GroupRef(children.into_iter().collect())),
+            );
+            pages.push(Page {
+                content: fg(20 + k),
+                size: Size::new(Scalar(100.), Scalar(100.)),
+            });
+        }
+        VecDocument { module, pages }
+    }
+
+    #[test]
+    fn test_shared_image_stats() {
+        let artifacts = [
+            header_logo(true).to_paged_bytes().unwrap(),
+            header_logo(true).to_bytes(),
+        ];
+        for artifact in artifacts {
+            let res = stats(&artifact, 10).unwrap();
+            assert_eq!(res.images.len(), 1, "{:?}", res.images);
+            let image = &res.images[0];
+            assert_eq!((image.items, image.pages), (2, 3));
+            assert_eq!(image.hash, Fingerprint::from_pair(1, 0).as_svg_id("i"));
+            let images = res.largest.iter().filter(|r| r.kind == ResourceKind::Image);
+            assert_eq!(images.count(), 1);
+        }
+
+        // The data shared by the items of the logo is serialized once.
+        let shared = header_logo(true).to_bytes().len();
+        let copied = header_logo(false).to_bytes().len();
+        assert!(shared + 8192 <= copied, "{shared} vs {copied}");
+    }
+}
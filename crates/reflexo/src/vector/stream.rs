@@ -2,6 +2,21 @@ use super::ir::{ArchivedFlatModule, FlatModule};
 use rkyv::de::deserializers::SharedDeserializeMap;
 use rkyv::{AlignedVec, Deserialize};
 
+use crate::error::prelude::*;
+
+/// Magic of the header of vector artifacts written to files, the last byte is
+/// the format version.
+///
+/// The header is optional, e.g. deltas sent to the renderer don't carry it,
+/// but an artifact with a header of unsupported version is rejected on
+/// checkout.
+pub const ARTIFACT_MAGIC: [u8; 8] = *b"tsva\x00\x00\x00\x01";
+
+/// Prepend the header to the bytes of a module, see [`ARTIFACT_MAGIC`].
+pub fn with_artifact_header(module: &[u8]) -> Vec<u8> {
+    [ARTIFACT_MAGIC.as_slice(), module].concat()
+}
+
 enum RkyvStreamData<'a> {
     Aligned(&'a [u8]),
     Unaligned(AlignedVec),
@@ -19,10 +34,19 @@ impl<'a> AsRef<[u8]> for RkyvStreamData<'a> {
 
 pub struct BytesModuleStream<'a> {
     data: RkyvStreamData<'a>,
+    /// The header of the artifact if any, see [`ARTIFACT_MAGIC`].
+    header: Option<&'a [u8]>,
 }
 
 impl<'a> BytesModuleStream<'a> {
     pub fn from_slice(v: &'a [u8]) -> Self {
+        let (header, v) = match v.get(..ARTIFACT_MAGIC.len()) {
+            Some(header) if header.starts_with(&ARTIFACT_MAGIC[..4]) => {
+                (Some(header), &v[ARTIFACT_MAGIC.len()..])
+            }
+            _ => (None, v),
+        };
+
         let v = if (v.as_ptr() as usize) % AlignedVec::ALIGNMENT != 0 {
             let mut aligned = AlignedVec::with_capacity(v.len());
             aligned.extend_from_slice(v);
@@ -31,16 +55,37 @@ impl<'a> BytesModuleStream<'a> {
             RkyvStreamData::Aligned(v)
         };
 
-        Self { data: v }
+        Self { data: v, header }
     }
 
     pub fn checkout(&self) -> &ArchivedFlatModule {
-        rkyv::check_archived_root::<FlatModule>(self.data.as_ref()).unwrap()
+        self.try_checkout().unwrap()
     }
 
     pub fn checkout_owned(&self) -> FlatModule {
-        let v = self.checkout();
+        self.try_checkout_owned().unwrap()
+    }
+
+    /// Validate and checkout the archived module.
+    pub fn try_checkout(&self) -> ZResult<&ArchivedFlatModule> {
+        if let Some(header) = self.header.filter(|header| *header != ARTIFACT_MAGIC) {
+            let version = header[ARTIFACT_MAGIC.len() - 1];
+            return Err(error_once!("artifact: unsupported version", version: version));
+        }
+
+        rkyv::check_archived_root::<FlatModule>(self.data.as_ref())
+            .map_err(|err| error_once!("artifact: invalid module", err: err.to_string()))
+    }
+
+    /// Validate and checkout the module, which is also checked by
+    /// [`FlatModule::validate`].
+    pub fn try_checkout_owned(&self) -> ZResult<FlatModule> {
+        let v = self.try_checkout()?;
         let mut dmap = SharedDeserializeMap::default();
-        v.deserialize(&mut dmap).unwrap()
+        let module: FlatModule = v
+            .deserialize(&mut dmap)
+            .map_err(|err| error_once!("artifact: deserialize module", err: format!("{err:?}")))?;
+        module.validate()?;
+        Ok(module)
     }
 }
@@ -0,0 +1,716 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use base64::Engine as _;
+use comemo::{Track, Tracked};
+use typst::{
+    diag::SourceResult,
+    engine::{Engine, Route},
+    eval::Tracer,
+    foundations::{
+        Bytes, Content, Context, Dict, RecipeIndex, Selector, Style, StyleChain, StyledElem,
+        Transformation, Value,
+    },
+    introspection::{Introspector, Location, Locator, Meta},
+    layout::{Frame, FrameItem, Point, Size},
+    model::{Document, EnumItem, ListItem, TermItem},
+    World,
+};
+use typst_ts_core::{exporter_utils::map_err, output::write_atomic, slug::SlugAllocator, Exporter};
+
+/// The elements rendered as inline SVG, since they have no HTML counterpart.
+const SVG_FALLBACKS: &[&str] = &["equation", "context", "ref"];
+
+/// Where the images of a document are stored.
+#[derive(Debug, Clone, Default)]
+pub enum HtmlImages {
+    /// Embed the images as data URIs.
+    #[default]
+    DataUri,
+    /// Write the images into the directory.
+    ///
+    /// The images are referred by the directory joined with their file names,
+    /// so a relative directory should be relative to the HTML file.
+    Files(PathBuf),
+}
+
+/// Export a document into semantic HTML.
+///
+/// See the README of the crate for the constructs falling back to inline SVG.
+#[derive(Debug, Clone, Default)]
+pub struct HtmlExporter {
+    images: HtmlImages,
+}
+
+impl HtmlExporter {
+    pub fn with_images(mut self, images: HtmlImages) -> Self {
+        self.images = images;
+        self
+    }
+}
+
+impl Exporter<Document, String> for HtmlExporter {
+    fn export(&self, world: &dyn World, output: Arc<Document>) -> SourceResult<String> {
+        // The document keeps no structure, so evaluate the main source again,
+        // whose show rules are applied while exporting it.
+        let world = world.track();
+        let mut tracer = Tracer::new();
+        let module = typst::eval::eval(
+            world,
+            Route::default().track(),
+            tracer.track_mut(),
+            &world.main(),
+        )?;
+
+        let mut html = HtmlBuilder::new(&self.images, &output, world);
+        html.out
+            .push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        if let Some(title) = &output.title {
+            let _ = writeln!(html.out, "<title>{}</title>", Escape(title));
+        }
+        html.out.push_str("</head>\n<body>\n");
+        let styles = StyleChain::new(&world.library().styles);
+        html.content(&module.content(), styles)?;
+        html.close_par();
+        html.out.push_str("</body>\n</html>\n");
+
+        Ok(html.out)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ListKind {
+    Bullet,
+    Numbered,
+    Terms,
+}
+
+impl ListKind {
+    fn of(content: &Content) -> Option<Self> {
+        if content.is::<ListItem>() {
+            Some(Self::Bullet)
+        } else if content.is::<EnumItem>() {
+            Some(Self::Numbered)
+        } else if content.is::<TermItem>() {
+            Some(Self::Terms)
+        } else {
+            None
+        }
+    }
+
+    fn tag(self) -> &'static str {
+        match self {
+            Self::Bullet => "ul",
+            Self::Numbered => "ol",
+            Self::Terms => "dl",
+        }
+    }
+}
+
+struct HtmlBuilder<'a> {
+    images: &'a HtmlImages,
+    world: Tracked<'a, dyn World + 'a>,
+    introspector: &'a Introspector,
+    /// The laid out frames of the fallback elements, by element name in the
+    /// order of the document.
+    frames: HashMap<&'static str, Vec<Frame>>,
+    /// The number of fallback elements visited, by element name.
+    visited: HashMap<&'static str, usize>,
+    image_count: usize,
+    /// The anchors of the headings, shared with the outline of the vector
+    /// artifacts.
+    slugs: SlugAllocator,
+    /// Whether inline content is wrapped into paragraphs.
+    in_block: bool,
+    /// Whether a paragraph is open.
+    par: bool,
+    out: String,
+}
+
+impl<'a> HtmlBuilder<'a> {
+    fn new(images: &'a HtmlImages, doc: &'a Document, world: Tracked<'a, dyn World + 'a>) -> Self {
+        let mut frames = HashMap::new();
+        let mut seen = HashSet::new();
+        for page in &doc.pages {
+            collect_frames(&page.frame, &mut seen, &mut frames);
+        }
+
+        Self {
+            images,
+            world,
+            introspector: &doc.introspector,
+            frames,
+            visited: HashMap::new(),
+            image_count: 0,
+            slugs: SlugAllocator::new(),
+            in_block: true,
+            par: false,
+            out: String::new(),
+        }
+    }
+
+    fn content(&mut self, content: &Content, styles: StyleChain) -> SourceResult<()> {
+        if let Some(shown) = self.show(content, styles)? {
+            return self.content(&shown, styles);
+        }
+
+        let fields = content.fields();
+        let text = |name: &str| match fields.get(name) {
+            Ok(Value::Str(s)) => Some(s.as_str().to_owned()),
+            _ => None,
+        };
+
+        let name = content.func().name();
+        match name {
+            "sequence" => self.sequence(&contents(fields.get("children").ok()), styles)?,
+            "styled" => {
+                let styled = content.to_packed::<StyledElem>().unwrap();
+                self.content(&styled.child, styles.chain(&styled.styles))?;
+            }
+            "text" => {
+                self.inline();
+                let _ = write!(self.out, "{}", Escape(&text("text").unwrap_or_default()));
+            }
+            "space" => {
+                // Spaces never open a paragraph.
+                if self.par || !self.in_block {
+                    self.out.push(' ');
+                }
+            }
+            "linebreak" => {
+                self.inline();
+                self.out.push_str("<br>");
+            }
+            "parbreak" => self.close_par(),
+            "smartquote" => {
+                self.inline();
+                let double = !matches!(fields.get("double"), Ok(Value::Bool(false)));
+                self.out.push_str(if double { "&quot;" } else { "'" });
+            }
+            "strong" | "emph" => {
+                let tag = if name == "strong" { "strong" } else { "em" };
+                self.inline();
+                let _ = write!(self.out, "<{tag}>");
+                self.inline_body(&fields, styles)?;
+                let _ = write!(self.out, "</{tag}>");
+            }
+            "raw" => {
+                let code = match fields.get("text") {
+                    Ok(Value::Array(lines)) => (lines.iter())
+                        .filter_map(|line| match line {
+                            Value::Str(s) => Some(s.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    _ => text("text").unwrap_or_default(),
+                };
+                if matches!(fields.get("block"), Ok(Value::Bool(true))) {
+                    self.close_par();
+                    let _ = writeln!(self.out, "<pre><code>{}</code></pre>", Escape(&code));
+                } else {
+                    self.inline();
+                    let _ = write!(self.out, "<code>{}</code>", Escape(&code));
+                }
+            }
+            "heading" => {
+                let depth = match fields.get("depth") {
+                    Ok(Value::Int(depth)) => (*depth).clamp(1, 6),
+                    _ => 1,
+                };
+                let body = match fields.get("body") {
+                    Ok(Value::Content(body)) => body.plain_text().to_string(),
+                    _ => String::new(),
+                };
+                let slug = self.slugs.heading(&body);
+                self.close_par();
+                let _ = write!(self.out, "<h{depth} id=\"{}\">", Escape(&slug));
+                // Keep the label linkable besides the slug.
+                if let Some(label) = content.label() {
+                    let _ = write!(self.out, "<a id=\"{}\"></a>", Escape(label.as_str()));
+                }
+                self.inline_body(&fields, styles)?;
+                let _ = writeln!(self.out, "</h{depth}>");
+            }
+            "link" => {
+                let href = match fields.get("dest") {
+                    Ok(Value::Str(url)) => Some(url.as_str().to_owned()),
+                    Ok(Value::Label(label)) => Some(format!("#{}", label.as_str())),
+                    _ => None,
+                };
+                self.inline();
+                match href {
+                    Some(href) => {
+                        let _ = write!(self.out, "<a href=\"{}\">", Escape(&href));
+                        self.inline_body(&fields, styles)?;
+                        self.out.push_str("</a>");
+                    }
+                    None => self.inline_body(&fields, styles)?,
+                }
+            }
+            "list" | "enum" | "terms" => {
+                let items = contents(fields.get("children").ok());
+                let kind = match name {
+                    "list" => ListKind::Bullet,
+                    "enum" => ListKind::Numbered,
+                    _ => ListKind::Terms,
+                };
+                self.list(kind, &items, styles)?;
+            }
+            "figure" => {
+                self.close_par();
+                let _ = writeln!(self.out, "<figure{}>", Id(content));
+                self.scoped(|html| {
+                    if let Ok(Value::Content(body)) = fields.get("body") {
+                        html.content(body, styles)?;
+                    }
+                    if let Ok(Value::Content(caption)) = fields.get("caption") {
+                        html.out.push_str("\n<figcaption>");
+                        html.content(caption, styles)?;
+                        html.out.push_str("</figcaption>");
+                    }
+                    Ok(())
+                })?;
+                self.out.push_str("\n</figure>\n");
+            }
+            "image" => {
+                if let Some(src) = self.image_src(&fields)? {
+                    self.inline();
+                    let alt = text("alt").unwrap_or_default();
+                    let _ = write!(
+                        self.out,
+                        "<img src=\"{}\" alt=\"{}\">",
+                        Escape(&src),
+                        Escape(&alt)
+                    );
+                }
+            }
+            _ if SVG_FALLBACKS.contains(&name) => self.fallback(content, &fields),
+            _ => match ListKind::of(content) {
+                Some(kind) => self.list(kind, std::slice::from_ref(content), styles)?,
+                // Layout elements are flattened into their contents.
+                None => self.children(&fields, styles)?,
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Apply the innermost user-defined show rule matching an element, like
+    /// realization during layout, or get `None` if no rule applies.
+    ///
+    /// Show-set rules only style the element, and regex show rules, which
+    /// split texts, are not applied.
+    fn show(&mut self, content: &Content, styles: StyleChain) -> SourceResult<Option<Content>> {
+        // The rules are indexed from the top of the chain, see
+        // `typst::realize`.
+        let depth = styles.entries().filter_map(Style::recipe).count();
+        let mut revoked = HashSet::new();
+        let mut r = 0;
+        for entry in styles.entries() {
+            let recipe = match entry {
+                Style::Recipe(recipe) => recipe,
+                Style::Property(..) => continue,
+                Style::Revocation(index) => {
+                    revoked.insert(index.0);
+                    continue;
+                }
+            };
+            let index = RecipeIndex(depth - r);
+            r += 1;
+
+            let transforms = !matches!(recipe.transform, Transformation::Style(..));
+            let regex = matches!(recipe.selector, Some(Selector::Regex(..)));
+            if !transforms
+                || regex
+                || revoked.contains(&index.0)
+                || content.is_guarded(index)
+                || !recipe.applicable(content, styles)
+            {
+                continue;
+            }
+
+            let mut locator = Locator::new();
+            let mut tracer = Tracer::new();
+            let mut engine = Engine {
+                world: self.world,
+                introspector: self.introspector.track(),
+                route: Route::default(),
+                locator: &mut locator,
+                tracer: tracer.track_mut(),
+            };
+            let context = Context::new(content.location(), Some(styles));
+            let target = content.clone().guarded(index);
+            return recipe.apply(&mut engine, context.track(), target).map(Some);
+        }
+
+        Ok(None)
+    }
+
+    /// Export the content fields of an element in order.
+    fn children(&mut self, fields: &Dict, styles: StyleChain) -> SourceResult<()> {
+        for (key, value) in fields.iter() {
+            if key.as_str() == "label" {
+                continue;
+            }
+            for child in contents(Some(value)) {
+                self.content(&child, styles)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Export the body of an element without paragraphs.
+    fn inline_body(&mut self, fields: &Dict, styles: StyleChain) -> SourceResult<()> {
+        let body = contents(fields.get("body").ok());
+        self.scoped(|html| {
+            body.iter()
+                .try_for_each(|child| html.content(child, styles))
+        })
+    }
+
+    fn sequence(&mut self, children: &[Content], styles: StyleChain) -> SourceResult<()> {
+        let mut idx = 0;
+        while idx < children.len() {
+            let child = &children[idx];
+            idx += 1;
+            let Some(kind) = ListKind::of(child) else {
+                self.content(child, styles)?;
+                continue;
+            };
+
+            // Group the adjacent items, which may be separated by spaces and
+            // paragraph breaks, into one list.
+            let mut items = vec![child.clone()];
+            let mut next = idx;
+            while next < children.len() {
+                let sibling = &children[next];
+                next += 1;
+                if ListKind::of(sibling) == Some(kind) {
+                    items.push(sibling.clone());
+                    idx = next;
+                } else if !matches!(sibling.func().name(), "space" | "parbreak") {
+                    break;
+                }
+            }
+            self.list(kind, &items, styles)?;
+        }
+
+        Ok(())
+    }
+
+    fn list(&mut self, kind: ListKind, items: &[Content], styles: StyleChain) -> SourceResult<()> {
+        self.close_par();
+        let _ = writeln!(self.out, "<{}>", kind.tag());
+        self.scoped(|html| {
+            for item in items {
+                let fields = item.fields();
+                let body = |name: &str| match fields.get(name) {
+                    Ok(Value::Content(body)) => Some(body.clone()),
+                    _ => None,
+                };
+
+                if kind == ListKind::Terms {
+                    html.out.push_str("<dt>");
+                    if let Some(term) = body("term") {
+                        html.content(&term, styles)?;
+                    }
+                    html.out.push_str("</dt>\n<dd>");
+                    if let Some(description) = body("description") {
+                        html.content(&description, styles)?;
+                    }
+                    html.out.push_str("</dd>\n");
+                    continue;
+                }
+
+                match fields.get("number") {
+                    Ok(Value::Int(number)) => {
+                        let _ = write!(html.out, "<li value=\"{number}\">");
+                    }
+                    _ => html.out.push_str("<li>"),
+                }
+                match body("body") {
+                    Some(body) => html.content(&body, styles)?,
+                    // An explicit child of a list call may be plain content.
+                    None if ListKind::of(item).is_none() => html.content(item, styles)?,
+                    None => {}
+                }
+                html.out.push_str("</li>\n");
+            }
+            Ok(())
+        })?;
+        let _ = writeln!(self.out, "</{}>", kind.tag());
+
+        Ok(())
+    }
+
+    /// Export an element as the SVG of its laid out frame.
+    fn fallback(&mut self, content: &Content, fields: &Dict) {
+        let name = content.func().name();
+        let visited = self.visited.entry(name).or_default();
+        let frame = self
+            .frames
+            .get(name)
+            .and_then(|frames| frames.get(*visited));
+        *visited += 1;
+
+        let block = matches!(fields.get("block"), Ok(Value::Bool(true)));
+        let tag = if block { "div" } else { "span" };
+        let class = if name == "equation" { "math" } else { "frame" };
+        let href = match fields.get("target") {
+            Ok(Value::Label(label)) => Some(format!("#{}", label.as_str())),
+            _ => None,
+        };
+
+        let mut inner = match frame {
+            Some(frame) => typst_svg::svg(frame),
+            // The element is not laid out, e.g. in hidden content.
+            None => Escape(&content.plain_text()).to_string(),
+        };
+        if let Some(href) = href {
+            inner = format!("<a href=\"{}\">{inner}</a>", Escape(&href));
+        }
+
+        if block {
+            self.close_par();
+        } else {
+            self.inline();
+        }
+        let _ = write!(self.out, "<{tag} class=\"{class}\">{inner}</{tag}>");
+        if block {
+            self.out.push('\n');
+        }
+    }
+
+    fn image_src(&mut self, fields: &Dict) -> SourceResult<Option<String>> {
+        let data = match fields.get("data") {
+            Ok(Value::Bytes(data)) => data.clone(),
+            Ok(Value::Str(data)) => Bytes::from(data.as_str().as_bytes()),
+            _ => return Ok(None),
+        };
+        let ext = match fields.get("path") {
+            Ok(Value::Str(path)) => (path.rsplit_once('.'))
+                .map(|(_, ext)| ext.to_lowercase())
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+
+        let src = match self.images {
+            HtmlImages::DataUri => {
+                let mime = match ext.as_str() {
+                    "png" => "image/png",
+                    "jpg" | "jpeg" => "image/jpeg",
+                    "gif" => "image/gif",
+                    "svg" => "image/svg+xml",
+                    _ => "application/octet-stream",
+                };
+                let data = base64::engine::general_purpose::STANDARD.encode(&data);
+                format!("data:{mime};base64,{data}")
+            }
+            HtmlImages::Files(dir) => {
+                self.image_count += 1;
+                let file_name = format!("image-{:03}.{ext}", self.image_count);
+                std::fs::create_dir_all(dir).map_err(map_err)?;
+                let path = dir.join(file_name);
+                write_atomic(&path, &data).map_err(map_err)?;
+                path.to_string_lossy().replace('\\', "/")
+            }
+        };
+
+        Ok(Some(src))
+    }
+
+    /// Run `f` without wrapping inline content into paragraphs.
+    fn scoped(&mut self, f: impl FnOnce(&mut Self) -> SourceResult<()>) -> SourceResult<()> {
+        let in_block = std::mem::replace(&mut self.in_block, false);
+        let res = f(self);
+        self.in_block = in_block;
+        res
+    }
+
+    fn inline(&mut self) {
+        if self.in_block && !self.par {
+            self.out.push_str("<p>");
+            self.par = true;
+        }
+    }
+
+    fn close_par(&mut self) {
+        if self.par {
+            self.out.push_str("</p>\n");
+            self.par = false;
+        }
+    }
+}
+
+/// Collect the frames of the fallback elements, see [`SVG_FALLBACKS`].
+fn collect_frames(
+    frame: &Frame,
+    seen: &mut HashSet<Location>,
+    frames: &mut HashMap<&'static str, Vec<Frame>>,
+) {
+    for (idx, (pos, item)) in frame.items().enumerate() {
+        match item {
+            FrameItem::Group(group) => collect_frames(&group.frame, seen, frames),
+            FrameItem::Meta(Meta::Elem(elem), size) => {
+                let name = elem.func().name();
+                if !SVG_FALLBACKS.contains(&name) {
+                    continue;
+                }
+                // An element broken across regions is marked in each region.
+                if elem.location().is_some_and(|loc| !seen.insert(loc)) {
+                    continue;
+                }
+                let region = element_region(frame, idx, *pos, *size);
+                frames.entry(name).or_default().push(region);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Cut the items following the marker of an element out of its frame.
+///
+/// The marker spans the layout of the element, which may be inlined into the
+/// frame along with its siblings.
+fn element_region(frame: &Frame, marker: usize, origin: Point, size: Size) -> Frame {
+    let end = origin + size.to_point();
+    let mut region = Frame::soft(size);
+    for (pos, item) in frame.items().skip(marker + 1) {
+        if matches!(item, FrameItem::Meta(..)) {
+            continue;
+        }
+        let inside = origin.x <= pos.x && pos.x <= end.x && origin.y <= pos.y && pos.y <= end.y;
+        if inside {
+            region.push(*pos - origin, item.clone());
+        }
+    }
+
+    region
+}
+
+/// The contents in a field value.
+fn contents(value: Option<&Value>) -> Vec<Content> {
+    match value {
+        Some(Value::Content(content)) => vec![content.clone()],
+        Some(Value::Array(array)) => (array.iter())
+            .filter_map(|value| match value {
+                Value::Content(content) => Some(content.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// The `id` attribute of a labelled element.
+struct Id<'a>(&'a Content);
+
+impl std::fmt::Display for Id<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.label() {
+            Some(label) => write!(f, " id=\"{}\"", Escape(label.as_str())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Escape text for HTML contents and attributes.
+struct Escape<'a>(&'a str);
+
+impl std::fmt::Display for Escape<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, path::Path};
+
+    use typst_ts_compiler::{
+        service::{CompileEnv, EnvWorld},
+        TypstSystemWorld,
+    };
+    use typst_ts_core::config::{compiler::EntryOpts, CompileOpts};
+
+    use super::*;
+
+    /// Compile a fixture of `fixtures/html` and export it into HTML.
+    fn export_fixture(name: &str) -> String {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fixtures/html");
+        let mut world = TypstSystemWorld::new(CompileOpts {
+            entry: EntryOpts::new_rooted(root.canonicalize().unwrap(), Some(name.into())),
+            no_system_fonts: true,
+            with_embedded_fonts: typst_assets::fonts().map(Cow::Borrowed).collect(),
+            ..CompileOpts::default()
+        })
+        .unwrap();
+        world.prepare_env(&mut CompileEnv::default()).unwrap();
+
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        HtmlExporter::default()
+            .export(&world, Arc::new(doc))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_export_structure() {
+        let html = export_fixture("structure.typ");
+
+        let heading = "<h1 id=\"sec-introduction\"><a id=\"intro\"></a>Introduction</h1>";
+        assert!(html.contains(heading), "{html}");
+        assert!(html.contains("<strong>strong</strong>"), "{html}");
+        assert!(html.contains("<em>emphasized</em>"), "{html}");
+        assert!(
+            html.contains("<a href=\"https://typst.app\">link</a>"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<ul>\n<li>First</li>\n<li>Second</li>\n</ul>"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<ol>\n<li>One</li>\n<li>Two</li>\n</ol>"),
+            "{html}"
+        );
+        assert!(html.contains("<figcaption>"), "{html}");
+        assert!(html.contains("A square</figcaption>"), "{html}");
+
+        // The reference and the equation fall back to their laid out frames.
+        assert!(
+            html.contains("<span class=\"frame\"><a href=\"#intro\"><svg"),
+            "{html}"
+        );
+        assert!(html.contains("<span class=\"math\"><svg"), "{html}");
+    }
+
+    #[test]
+    fn test_export_show_rules() {
+        let html = export_fixture("show-rule.typ");
+
+        // The heading is replaced by its show rule.
+        assert!(!html.contains("<h1"), "{html}");
+        assert!(html.contains("Chapter: Overview"), "{html}");
+
+        // The rules apply to their outputs, but not to the elements they
+        // wrap again.
+        assert!(!html.contains("<em>"), "{html}");
+        assert!(html.contains("<strong>important</strong>!"), "{html}");
+        assert!(html.contains("<strong>bold</strong>!"), "{html}");
+    }
+}
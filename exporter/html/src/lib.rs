@@ -0,0 +1,2 @@
+pub(crate) mod html;
+pub use html::{HtmlExporter, HtmlImages};
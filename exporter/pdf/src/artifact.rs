@@ -0,0 +1,2131 @@
+//! Convert exported vector artifacts to PDF without recompiling the sources.
+//!
+//! The pages are drawn from the items of the artifact: texts are drawn by the
+//! fonts of the artifact, which are subsetted and embedded if they are
+//! resolved by [`artifact_to_pdf_with_fonts`], e.g. among the fonts of a world
+//! by [`world_fonts`]. The artifact carries only the outlines of the glyphs,
+//! hence the texts of the fonts which are not resolved are drawn by Type 3
+//! fonts built from the outlines. Shapes are drawn by path operators,
+//! gradients become shading patterns and images are embedded again, once per
+//! content however many times and sizes they are placed at. Links become link
+//! annotations and the outline of a monolithic artifact becomes the bookmarks
+//! of the PDF.
+//!
+//! A link annotation covers a single rectangle, hence a link broken across
+//! lines gets an annotation per line. Internal links and bookmarks go to named
+//! destinations, which are named by the page and the position of their
+//! targets, e.g. `/loc-2-0-120`. The PDFs exported by typst itself link to
+//! explicit destinations instead, so the names are only stable across the
+//! PDFs converted from artifacts.
+//!
+//! Items without a counterpart in the converter are dropped or approximated,
+//! e.g. patterns are painted in black, and they are reported by
+//! [`ArtifactPdf::warnings`].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    f32::consts::{PI, TAU},
+    fmt,
+    io::Write as _,
+};
+
+use flate2::{write::ZlibEncoder, Compression};
+use image::{ColorType, ImageFormat};
+use pdf_writer::{
+    types::{
+        ActionType, AnnotationType, CidFontType, ColorSpaceOperand, FontFlags, FunctionShadingType,
+        LineCapStyle, LineJoinStyle, PageMode, SystemInfo, UnicodeCmap,
+    },
+    writers::{Resources, StreamShadingType},
+    Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr,
+};
+use ttf_parser::{name_id, GlyphId, Tag};
+use typst::{
+    layout::{Angle, Quadrant, Ratio},
+    text::Font,
+    visualize::{Color, ColorSpace, Gradient, WeightedColor},
+    World,
+};
+use typst_ts_core::{
+    error::prelude::*,
+    hash::{hash128, Fingerprint},
+    vector::{
+        artifact::{artifact_pages, load_artifact_with_metadata},
+        ir::{
+            FlatGlyphItem, FontItem, FontRef, GradientItem, GradientKind, GradientStyle, Image,
+            ImageItem, LinkItem, Module, ModuleMetadata, OutlineItem, PathItem, PathStyle,
+            Rgba8Item, Scalar, TextItem, Transform, TransformItem, VecItem,
+        },
+    },
+    TryFromTypst,
+};
+
+/// A PDF converted from an artifact, see [`artifact_to_pdf`].
+#[derive(Debug, Clone)]
+pub struct ArtifactPdf {
+    pub pdf: Vec<u8>,
+    /// The features of the artifact which are dropped or approximated, e.g.
+    /// `pattern paints are painted in black (3 times)`.
+    pub warnings: Vec<String>,
+}
+
+/// Convert an artifact in either the monolithic or the paged format to PDF,
+/// drawing the texts by Type 3 fonts, see the [module docs](self).
+pub fn artifact_to_pdf(artifact: &[u8]) -> ZResult<ArtifactPdf> {
+    artifact_to_pdf_with_fonts(artifact, |_| None)
+}
+
+/// Convert an artifact like [`artifact_to_pdf`], embedding the fonts of the
+/// artifact resolved by `resolve`.
+pub fn artifact_to_pdf_with_fonts(
+    artifact: &[u8],
+    mut resolve: impl FnMut(&FontItem) -> Option<Font>,
+) -> ZResult<ArtifactPdf> {
+    let (doc, metadata) = load_artifact_with_metadata(artifact)?;
+    let pages = artifact_pages(&doc);
+    if pages.is_empty() {
+        return Err(error_once!("artifact: no pages to convert"));
+    }
+    let outline = metadata.iter().find_map(|meta| match meta {
+        ModuleMetadata::Outline(outline) => Some(outline.as_slice()),
+        _ => None,
+    });
+
+    let mut alloc = Ref::new(1);
+    let catalog = alloc.bump();
+    let page_tree = alloc.bump();
+    let resources = alloc.bump();
+    let page_refs: Vec<_> = pages.iter().map(|_| alloc.bump()).collect();
+
+    let mut converter = Converter {
+        module: &doc.module,
+        resolve: &mut resolve,
+        pdf: Pdf::new(),
+        alloc,
+        page_refs,
+        page_heights: pages.iter().map(|page| page.size.y.0).collect(),
+        page_height: 0.,
+        base: Transform::identity(),
+        fonts: HashMap::new(),
+        embedded: vec![],
+        type3_fonts: vec![],
+        open_fonts: HashMap::new(),
+        glyphs: HashMap::new(),
+        images: HashMap::new(),
+        image_data: HashMap::new(),
+        image_refs: vec![],
+        alphas: vec![],
+        gradients: HashMap::new(),
+        gradient_refs: vec![],
+        dests: BTreeMap::new(),
+        warnings: BTreeMap::new(),
+    };
+
+    for (i, page) in pages.iter().enumerate() {
+        converter.page(
+            i,
+            page.size.x.0,
+            page.size.y.0,
+            &page.content,
+            page_tree,
+            resources,
+        );
+    }
+    converter.resources(resources);
+    let outline = outline.and_then(|outline| converter.outline(outline));
+    let dests = converter.dests();
+
+    let pdf = &mut converter.pdf;
+    let kids = converter.page_refs.iter().copied();
+    (pdf.pages(page_tree).kids(kids)).count(converter.page_refs.len() as i32);
+    let mut catalog = pdf.catalog(catalog);
+    catalog.pages(page_tree);
+    if let Some(dests) = dests {
+        catalog.destinations(dests);
+    }
+    if let Some(outline) = outline {
+        catalog.outlines(outline).page_mode(PageMode::UseOutlines);
+    }
+    catalog.finish();
+
+    let warnings = (converter.warnings.iter())
+        .map(|(what, &count)| match count {
+            1 => what.to_string(),
+            count => format!("{what} ({count} times)"),
+        })
+        .collect();
+    Ok(ArtifactPdf {
+        pdf: converter.pdf.finish(),
+        warnings,
+    })
+}
+
+/// Resolve the fonts of an artifact among the fonts of a world, by their
+/// families and hashes, see [`artifact_to_pdf_with_fonts`].
+pub fn world_fonts(world: &dyn World) -> impl FnMut(&FontItem) -> Option<Font> + '_ {
+    move |item| {
+        let family = item.family.to_lowercase();
+        (world.book().select_family(&family))
+            .filter_map(|idx| world.font(idx))
+            .find(|font| fxhash::hash32(font) == item.hash)
+    }
+}
+
+const PAINT_WARNING: &str = "pattern paints are painted in black";
+const TYPE3_WARNING: &str = "texts of unresolved fonts are drawn by Type 3 fonts";
+const PATH_WARNING: &str = "paths with unsupported commands are dropped";
+const TEXT_STROKE_WARNING: &str = "strokes of texts are dropped";
+const IMAGE_GLYPH_WARNING: &str = "image glyphs, e.g. emojis, are dropped";
+const SVG_IMAGE_WARNING: &str = "SVG images are dropped";
+const IMAGE_WARNING: &str = "images of unsupported formats are dropped";
+const LINK_WARNING: &str = "links of unsupported targets are dropped";
+const DEST_WARNING: &str = "links to removed destinations are dropped";
+
+const CFF: Tag = Tag::from_bytes(b"CFF ");
+const CFF2: Tag = Tag::from_bytes(b"CFF2");
+const CMAP_NAME: Name = Name(b"Custom");
+const SYSTEM_INFO: SystemInfo = SystemInfo {
+    registry: Str(b"Adobe"),
+    ordering: Str(b"Identity"),
+    supplement: 0,
+};
+
+/// The number of segments of the meshes drawing conic gradients.
+const CONIC_SEGMENTS: usize = 360;
+
+/// A font of the artifact which is embedded as a CID font.
+struct EmbeddedFont {
+    font: Font,
+    /// The glyph ids drawn so far and their texts.
+    glyphs: BTreeMap<u16, String>,
+}
+
+/// A Type 3 font covering up to 256 glyphs of a font in the artifact.
+struct Type3Font {
+    font: FontRef,
+    /// The glyph ids, the advances in font units and the texts of the codes.
+    glyphs: Vec<(u32, f32, Option<String>)>,
+}
+
+/// A link annotation of a page.
+struct Link {
+    rect: Rect,
+    target: LinkTarget,
+}
+
+enum LinkTarget {
+    Uri(String),
+    /// The name of a destination.
+    Dest(String),
+}
+
+struct Converter<'a> {
+    module: &'a Module,
+    resolve: &'a mut dyn FnMut(&FontItem) -> Option<Font>,
+    pdf: Pdf,
+    alloc: Ref,
+    page_refs: Vec<Ref>,
+    page_heights: Vec<f32>,
+    /// The height of the page being drawn.
+    page_height: f32,
+    /// The transform from the items of the page being drawn to the default
+    /// space of the page, which the paints are placed in.
+    base: Transform,
+    /// The embedded fonts of the fonts in the artifact, or `None` if a font
+    /// isn't resolved.
+    fonts: HashMap<FontRef, Option<usize>>,
+    embedded: Vec<EmbeddedFont>,
+    type3_fonts: Vec<Type3Font>,
+    /// The Type 3 font which new glyphs of a font are added to.
+    open_fonts: HashMap<FontRef, usize>,
+    /// The Type 3 fonts and the codes of the glyphs drawn so far.
+    glyphs: HashMap<(FontRef, u32), (usize, u8)>,
+    /// The indices of the embedded images, or `None` if an image is dropped.
+    images: HashMap<Fingerprint, Option<usize>>,
+    /// The indices of the embedded images by the hashes of their data, which
+    /// are shared by the images differing only in their alt texts.
+    image_data: HashMap<u128, Option<usize>>,
+    image_refs: Vec<Ref>,
+    /// The fill and stroke alphas of the graphics states.
+    alphas: Vec<(u8, u8)>,
+    /// The indices of the shading patterns by the gradients and the matrices
+    /// placing them.
+    gradients: HashMap<(Fingerprint, [u32; 6]), usize>,
+    gradient_refs: Vec<Ref>,
+    /// The named destinations by their names, i.e. their pages and positions
+    /// in the default space of the pages.
+    dests: BTreeMap<String, (Ref, f32, f32)>,
+    warnings: BTreeMap<&'static str, usize>,
+}
+
+impl Converter<'_> {
+    fn warn(&mut self, what: &'static str) {
+        *self.warnings.entry(what).or_default() += 1;
+    }
+
+    fn alloc(&mut self) -> Ref {
+        self.alloc.bump()
+    }
+
+    fn page(
+        &mut self,
+        i: usize,
+        width: f32,
+        height: f32,
+        content: &Fingerprint,
+        page_tree: Ref,
+        resources: Ref,
+    ) {
+        self.page_height = height;
+
+        // The items are placed in a coordinate system with the origin at the
+        // top left corner, like the renderers.
+        let flip = [1., 0., 0., -1., 0., height];
+        self.base = from_array(flip);
+        let mut out = Content::new();
+        out.transform(flip);
+        let mut links = vec![];
+        self.item(&mut out, &mut links, content, Transform::identity());
+
+        let contents = self.alloc();
+        let data = deflate(&out.finish());
+        self.pdf.stream(contents, &data).filter(Filter::FlateDecode);
+
+        let page_ref = self.page_refs[i];
+        let mut page = self.pdf.page(page_ref);
+        page.parent(page_tree)
+            .media_box(Rect::new(0., 0., width, height))
+            .contents(contents);
+        page.pair(Name(b"Resources"), resources);
+        let mut annots = page.annotations();
+        for link in &links {
+            let mut annot = annots.push();
+            annot
+                .subtype(AnnotationType::Link)
+                .rect(link.rect)
+                .border(0., 0., 0., None);
+            let mut action = annot.action();
+            match &link.target {
+                LinkTarget::Uri(uri) => {
+                    action.action_type(ActionType::Uri).uri(Str(uri.as_bytes()));
+                }
+                LinkTarget::Dest(name) => {
+                    (action.action_type(ActionType::GoTo)).destination_named(Name(name.as_bytes()));
+                }
+            }
+        }
+    }
+
+    fn item(&mut self, out: &mut Content, links: &mut Vec<Link>, fg: &Fingerprint, ts: Transform) {
+        let module = self.module;
+        let Some(item) = module.get_item(fg) else {
+            return;
+        };
+
+        match item {
+            VecItem::Group(group) => {
+                for (pos, child) in group.0.iter() {
+                    out.save_state();
+                    if pos.x.0 != 0. || pos.y.0 != 0. {
+                        out.transform([1., 0., 0., 1., pos.x.0, pos.y.0]);
+                    }
+                    let ts = ts.pre_translate(pos.x.0, pos.y.0);
+                    self.item(out, links, child, ts);
+                    out.restore_state();
+                }
+            }
+            VecItem::Item(transformed) => {
+                out.save_state();
+                let ts = match &transformed.0 {
+                    TransformItem::Clip(path) => {
+                        match parse_path(&path.d) {
+                            Some(segments) => {
+                                write_segments(out, &segments, None);
+                                out.clip_nonzero();
+                                out.end_path();
+                            }
+                            None => self.warn(PATH_WARNING),
+                        }
+                        ts
+                    }
+                    item => {
+                        let m = to_transform(item);
+                        out.transform(to_array(m));
+                        ts.pre_concat(m)
+                    }
+                };
+                self.item(out, links, &transformed.1, ts);
+                out.restore_state();
+            }
+            VecItem::Path(path) => self.path(out, path, ts),
+            VecItem::Text(text) => self.text(out, text, ts),
+            VecItem::Image(image) => self.image(out, image),
+            VecItem::Link(link) => {
+                if let Some(link) = self.link(link, ts) {
+                    links.push(link);
+                }
+            }
+            // Colors, gradients and patterns are only referenced by paints.
+            VecItem::None
+            | VecItem::ContentHint(..)
+            | VecItem::Color32(..)
+            | VecItem::Gradient(..)
+            | VecItem::Pattern(..)
+            | VecItem::ColorTransform(..) => {}
+        }
+    }
+
+    fn path(&mut self, out: &mut Content, path: &PathItem, ts: Transform) {
+        let mut fill = None;
+        let mut stroke = None;
+        let (mut width, mut cap, mut join, mut limit) = (None, None, None, None);
+        let (mut dashes, mut offset) = (None, None);
+        for style in &path.styles {
+            match style {
+                PathStyle::Fill(paint) => fill = Some(paint),
+                PathStyle::Stroke(paint) => stroke = Some(paint),
+                PathStyle::StrokeWidth(w) => width = Some(w.0),
+                PathStyle::StrokeLineCap(c) => {
+                    cap = Some(match c.as_ref() {
+                        "round" => LineCapStyle::RoundCap,
+                        "square" => LineCapStyle::ProjectingSquareCap,
+                        _ => LineCapStyle::ButtCap,
+                    });
+                }
+                PathStyle::StrokeLineJoin(j) => {
+                    join = Some(match j.as_ref() {
+                        "round" => LineJoinStyle::RoundJoin,
+                        "bevel" => LineJoinStyle::BevelJoin,
+                        _ => LineJoinStyle::MiterJoin,
+                    });
+                }
+                PathStyle::StrokeMitterLimit(l) => limit = Some(l.0),
+                PathStyle::StrokeDashArray(d) => dashes = Some(d),
+                PathStyle::StrokeDashOffset(o) => offset = Some(o.0),
+            }
+        }
+        if fill.is_none() && stroke.is_none() {
+            return;
+        }
+        let Some(segments) = parse_path(&path.d) else {
+            self.warn(PATH_WARNING);
+            return;
+        };
+
+        out.save_state();
+        let fill_alpha = fill.map(|paint| self.paint(out, paint, ts, false));
+        let stroke_alpha = stroke.map(|paint| self.paint(out, paint, ts, true));
+        self.alpha(
+            out,
+            fill_alpha.unwrap_or(u8::MAX),
+            stroke_alpha.unwrap_or(u8::MAX),
+        );
+        if stroke.is_some() {
+            if let Some(width) = width {
+                out.set_line_width(width);
+            }
+            if let Some(cap) = cap {
+                out.set_line_cap(cap);
+            }
+            if let Some(join) = join {
+                out.set_line_join(join);
+            }
+            if let Some(limit) = limit {
+                out.set_miter_limit(limit);
+            }
+            if let Some(dashes) = dashes {
+                let dashes = dashes.iter().map(|dash| dash.0);
+                out.set_dash_pattern(dashes, offset.unwrap_or_default());
+            }
+        }
+        write_segments(out, &segments, None);
+        match (fill, stroke) {
+            (Some(..), Some(..)) => out.fill_nonzero_and_stroke(),
+            (Some(..), None) => out.fill_nonzero(),
+            _ => out.stroke(),
+        };
+        out.restore_state();
+    }
+
+    /// Set a paint as the fill or the stroke color, returning the alpha of
+    /// the paint.
+    ///
+    /// The paint is placed by `ts`, the transform of the item being painted.
+    fn paint(&mut self, out: &mut Content, paint: &str, ts: Transform, stroke: bool) -> u8 {
+        if let Some(id) = paint.strip_prefix("@g") {
+            if let Some(idx) = self.gradient(id, ts) {
+                let name = format!("Sh{idx}");
+                if stroke {
+                    out.set_stroke_color_space(ColorSpaceOperand::Pattern);
+                    out.set_stroke_pattern(None, Name(name.as_bytes()));
+                } else {
+                    out.set_fill_color_space(ColorSpaceOperand::Pattern);
+                    out.set_fill_pattern(None, Name(name.as_bytes()));
+                }
+                return u8::MAX;
+            }
+        }
+
+        let (rgb, alpha) = parse_color(paint).unwrap_or_else(|| {
+            self.warn(PAINT_WARNING);
+            ([0; 3], u8::MAX)
+        });
+        let [r, g, b] = rgb.map(|c| c as f32 / 255.);
+        if stroke {
+            out.set_stroke_rgb(r, g, b);
+        } else {
+            out.set_fill_rgb(r, g, b);
+        }
+        alpha
+    }
+
+    fn alpha(&mut self, out: &mut Content, fill: u8, stroke: u8) {
+        if fill == u8::MAX && stroke == u8::MAX {
+            return;
+        }
+        let idx = match self
+            .alphas
+            .iter()
+            .position(|&alphas| alphas == (fill, stroke))
+        {
+            Some(idx) => idx,
+            None => {
+                self.alphas.push((fill, stroke));
+                self.alphas.len() - 1
+            }
+        };
+        out.set_parameters(Name(format!("Gs{idx}").as_bytes()));
+    }
+
+    fn text(&mut self, out: &mut Content, text: &TextItem, ts: Transform) {
+        let module = self.module;
+        let shape = &text.shape;
+        let Some(font) = module.get_font(&shape.font) else {
+            return;
+        };
+        if (shape.styles.iter()).any(|style| matches!(style, PathStyle::Stroke(..))) {
+            self.warn(TEXT_STROKE_WARNING);
+        }
+        let fill = shape.styles.iter().find_map(|style| match style {
+            PathStyle::Fill(paint) => Some(paint),
+            _ => None,
+        });
+
+        // The texts of the glyphs are only known if they map one to one.
+        let chars: Vec<_> = text.content.content.chars().collect();
+        let one_to_one = chars.len() == text.content.glyphs.len();
+
+        let (upem, size) = (font.units_per_em.0, shape.size.0);
+        // The paints of texts are placed relative to the glyphs, which are
+        // drawn upwards in font units.
+        let glyph_ts = Transform::from_scale(Scalar(size / upem), Scalar(-size / upem));
+        out.save_state();
+        let alpha = fill.map_or(u8::MAX, |paint| {
+            self.paint(out, paint, ts.pre_concat(glyph_ts), false)
+        });
+        self.alpha(out, alpha, u8::MAX);
+        out.begin_text();
+        let embedded = self.embedded_font(shape.font, font);
+        let (mut x, mut current) = (0., None);
+        for (i, (offset, advance, glyph)) in text.content.glyphs.iter().enumerate() {
+            let pos = x + offset.0;
+            x += advance.0;
+
+            if let Some(FlatGlyphItem::Image(..)) = font.get_glyph(*glyph).map(AsRef::as_ref) {
+                self.warn(IMAGE_GLYPH_WARNING);
+                continue;
+            }
+            let unicode = one_to_one.then(|| chars[i].to_string());
+            let (name, code) = match embedded {
+                Some(idx) => {
+                    let cid = self.embedded_glyph(idx, *glyph as u16, unicode);
+                    (format!("E{idx}"), cid.to_be_bytes().to_vec())
+                }
+                None => {
+                    let advance = advance.0 * upem / size;
+                    let (idx, code) = self.type3_glyph(shape.font, *glyph, advance, unicode);
+                    (format!("F{idx}"), vec![code])
+                }
+            };
+            if current.as_ref() != Some(&name) {
+                out.set_font(Name(name.as_bytes()), size);
+                current = Some(name);
+            }
+            // Flip the glyphs back, which are drawn upwards.
+            out.set_text_matrix([1., 0., 0., -1., pos, 0.]);
+            out.show(Str(&code));
+        }
+        out.end_text();
+        out.restore_state();
+    }
+
+    /// Get the embedded font of a font in the artifact, resolving it at its
+    /// first use.
+    fn embedded_font(&mut self, font_ref: FontRef, font: &FontItem) -> Option<usize> {
+        if let Some(&idx) = self.fonts.get(&font_ref) {
+            return idx;
+        }
+
+        let idx = (self.resolve)(font).map(|font| {
+            self.embedded.push(EmbeddedFont {
+                font,
+                glyphs: BTreeMap::new(),
+            });
+            self.embedded.len() - 1
+        });
+        if idx.is_none() {
+            self.warn(TYPE3_WARNING);
+        }
+        self.fonts.insert(font_ref, idx);
+        idx
+    }
+
+    /// Add a glyph to an embedded font, returning the CID of the glyph.
+    fn embedded_glyph(&mut self, idx: usize, glyph: u16, unicode: Option<String>) -> u16 {
+        let font = &mut self.embedded[idx];
+        let text = font.glyphs.entry(glyph).or_default();
+        if text.is_empty() {
+            *text = unicode.unwrap_or_default();
+        }
+        glyph_cid(&font.font, glyph)
+    }
+
+    fn type3_glyph(
+        &mut self,
+        font_ref: FontRef,
+        glyph: u32,
+        advance: f32,
+        unicode: Option<String>,
+    ) -> (usize, u8) {
+        if let Some(&code) = self.glyphs.get(&(font_ref, glyph)) {
+            return code;
+        }
+
+        let idx = match self.open_fonts.get(&font_ref) {
+            Some(&idx) if self.type3_fonts[idx].glyphs.len() < 256 => idx,
+            _ => {
+                self.type3_fonts.push(Type3Font {
+                    font: font_ref,
+                    glyphs: vec![],
+                });
+                self.open_fonts.insert(font_ref, self.type3_fonts.len() - 1);
+                self.type3_fonts.len() - 1
+            }
+        };
+        let glyphs = &mut self.type3_fonts[idx].glyphs;
+        let code = glyphs.len() as u8;
+        glyphs.push((glyph, advance, unicode));
+        self.glyphs.insert((font_ref, glyph), (idx, code));
+        (idx, code)
+    }
+
+    /// Get the shading pattern of a gradient placed by `ts`, or `None` if the
+    /// gradient doesn't exist.
+    fn gradient(&mut self, id: &str, ts: Transform) -> Option<usize> {
+        let module = self.module;
+        // The fingerprints are at least 11 characters long.
+        let mut fg = Fingerprint::try_from_str(id.get(..11).map(|_| id)?).ok()?;
+        let mut transform = Transform::identity();
+        if let Some(VecItem::ColorTransform(color)) = module.get_item(&fg) {
+            (fg, transform) = (color.item, color.transform);
+        }
+        let Some(VecItem::Gradient(gradient)) = module.get_item(&fg) else {
+            return None;
+        };
+
+        // A shading pattern is placed in the default space of the page rather
+        // than the space of the item.
+        let matrix = to_array(self.base.pre_concat(ts).pre_concat(transform));
+        let key = (fg, matrix.map(f32::to_bits));
+        if let Some(&idx) = self.gradients.get(&key) {
+            return Some(idx);
+        }
+
+        // The angles are corrected by the aspect ratio of the gradient like
+        // the other exporters.
+        let (width, height) = (
+            transform.sx.0.hypot(transform.ky.0),
+            transform.kx.0.hypot(transform.sy.0),
+        );
+        let aspect_ratio = if height > 0. { width / height } else { 1. };
+        let pattern = self.shading_pattern(gradient, matrix, aspect_ratio);
+        self.gradient_refs.push(pattern);
+        let idx = self.gradient_refs.len() - 1;
+        self.gradients.insert(key, idx);
+        Some(idx)
+    }
+
+    fn shading_pattern(&mut self, gradient: &GradientItem, matrix: [f32; 6], aspect: f32) -> Ref {
+        let stops = rgb_stops(gradient);
+        let corrected = |angle: Scalar| {
+            let angle = Angle::rad(angle.0 as f64);
+            Gradient::correct_aspect_ratio(angle, Ratio::new(aspect as f64))
+        };
+        let mut center = (0.5, 0.5);
+        let mut focal_center = None;
+        let mut focal_radius = 0.;
+        for style in &gradient.styles {
+            match style {
+                GradientStyle::Center(c) => center = (c.x.0, c.y.0),
+                GradientStyle::FocalCenter(c) => focal_center = Some((c.x.0, c.y.0)),
+                GradientStyle::FocalRadius(r) => focal_radius = r.0,
+            }
+        }
+
+        let id = self.alloc();
+        match gradient.kind {
+            GradientKind::Linear(angle) => {
+                let angle = corrected(angle);
+                let function = self.shading_function(&stops);
+                let (sin, cos) = (angle.sin() as f32, angle.cos() as f32);
+                // Scale to the edges of the unit square.
+                let factor = cos.abs() + sin.abs();
+                let (sin, cos) = (sin * factor, cos * factor);
+                let coords = match angle.quadrant() {
+                    Quadrant::First => [0., 0., cos, sin],
+                    Quadrant::Second => [1., 0., cos + 1., sin],
+                    Quadrant::Third => [1., 1., cos + 1., sin + 1.],
+                    Quadrant::Fourth => [0., 1., cos, sin + 1.],
+                };
+
+                let mut pattern = self.pdf.shading_pattern(id);
+                let mut shading = pattern.function_shading();
+                shading.shading_type(FunctionShadingType::Axial);
+                shading.color_space().device_rgb();
+                (shading.anti_alias(gradient.anti_alias).function(function))
+                    .coords(coords)
+                    .extend([true; 2]);
+                shading.finish();
+                pattern.matrix(matrix);
+            }
+            GradientKind::Radial(radius) => {
+                let function = self.shading_function(&stops);
+                let (fx, fy) = focal_center.unwrap_or(center);
+
+                let mut pattern = self.pdf.shading_pattern(id);
+                let mut shading = pattern.function_shading();
+                shading.shading_type(FunctionShadingType::Radial);
+                shading.color_space().device_rgb();
+                (shading.anti_alias(gradient.anti_alias).function(function))
+                    .coords([fx, fy, focal_radius, center.0, center.1, radius.0])
+                    .extend([true; 2]);
+                shading.finish();
+                pattern.matrix(matrix);
+            }
+            GradientKind::Conic(angle) => {
+                let angle = corrected(angle).to_rad() as f32;
+                let mesh = self.alloc();
+                let vertices = deflate(&conic_mesh(&stops, center, angle));
+                let mut shading = self.pdf.stream_shading(mesh, &vertices);
+                shading.shading_type(StreamShadingType::FreeformGouraud);
+                shading.color_space().device_rgb();
+                (shading.bits_per_coordinate(16).bits_per_component(8))
+                    .bits_per_flag(8)
+                    .decode([-2., 3., -2., 3., 0., 1., 0., 1., 0., 1.])
+                    .anti_alias(gradient.anti_alias)
+                    .filter(Filter::FlateDecode);
+                shading.finish();
+
+                (self.pdf.shading_pattern(id))
+                    .shading_ref(mesh)
+                    .matrix(matrix);
+            }
+        }
+        id
+    }
+
+    /// Write a function interpolating the stops of a gradient.
+    fn shading_function(&mut self, stops: &[([f32; 3], f32)]) -> Ref {
+        let mut functions = vec![];
+        let mut bounds = vec![];
+        let mut encode = vec![];
+        for window in stops.windows(2) {
+            let ((c0, _), (c1, t1)) = (window[0], window[1]);
+            let id = self.alloc();
+            (self.pdf.exponential_function(id))
+                .domain([0., 1.])
+                .range([0., 1., 0., 1., 0., 1.])
+                .c0(c0)
+                .c1(c1)
+                .n(1.);
+            functions.push(id);
+            bounds.push(t1);
+            encode.extend([0., 1.]);
+        }
+        if functions.len() == 1 {
+            return functions[0];
+        }
+
+        // The last bound is the end of the domain.
+        bounds.pop();
+        let id = self.alloc();
+        (self.pdf.stitching_function(id))
+            .domain([0., 1.])
+            .range([0., 1., 0., 1., 0., 1.])
+            .functions(functions)
+            .bounds(bounds)
+            .encode(encode);
+        id
+    }
+
+    fn image(&mut self, out: &mut Content, image: &ImageItem) {
+        let idx = match self.images.get(&image.image.hash) {
+            Some(&idx) => idx,
+            None => {
+                let data = hash128(&image.image.data);
+                let idx = match self.image_data.get(&data) {
+                    Some(&idx) => idx,
+                    None => {
+                        let idx = self.embed_image(&image.image);
+                        self.image_data.insert(data, idx);
+                        idx
+                    }
+                };
+                self.images.insert(image.image.hash, idx);
+                idx
+            }
+        };
+        let Some(idx) = idx else {
+            return;
+        };
+
+        let (width, height) = (image.size.x.0, image.size.y.0);
+        out.save_state();
+        out.transform([width, 0., 0., -height, 0., height]);
+        out.x_object(Name(format!("Im{idx}").as_bytes()));
+        out.restore_state();
+    }
+
+    fn embed_image(&mut self, source: &Image) -> Option<usize> {
+        let Ok(decoded) = image::load_from_memory(&source.data) else {
+            let is_svg = source.format.contains("svg");
+            self.warn(if is_svg {
+                SVG_IMAGE_WARNING
+            } else {
+                IMAGE_WARNING
+            });
+            return None;
+        };
+
+        let id = self.alloc();
+        let (width, height) = (decoded.width() as i32, decoded.height() as i32);
+        let is_jpeg = image::guess_format(&source.data).ok() == Some(ImageFormat::Jpeg);
+        let space = match decoded.color() {
+            ColorType::L8 if is_jpeg => Some(Name(b"DeviceGray")),
+            ColorType::Rgb8 if is_jpeg => Some(Name(b"DeviceRGB")),
+            _ => None,
+        };
+        match space {
+            Some(space) => {
+                let mut image = self.pdf.image_xobject(id, &source.data);
+                image.width(width).height(height);
+                image.color_space_name(space).bits_per_component(8);
+                image.filter(Filter::DctDecode);
+            }
+            None => {
+                let rgba = decoded.to_rgba8();
+                let (mut rgb, mut alpha) = (vec![], vec![]);
+                for pixel in rgba.as_raw().chunks_exact(4) {
+                    rgb.extend_from_slice(&pixel[..3]);
+                    alpha.push(pixel[3]);
+                }
+
+                let mask = alpha.iter().any(|&a| a != u8::MAX).then(|| {
+                    let mask = self.alloc();
+                    let data = deflate(&alpha);
+                    let mut image = self.pdf.image_xobject(mask, &data);
+                    image.width(width).height(height);
+                    image
+                        .color_space_name(Name(b"DeviceGray"))
+                        .bits_per_component(8);
+                    image.filter(Filter::FlateDecode);
+                    mask
+                });
+                let data = deflate(&rgb);
+                let mut image = self.pdf.image_xobject(id, &data);
+                image.width(width).height(height);
+                image
+                    .color_space_name(Name(b"DeviceRGB"))
+                    .bits_per_component(8);
+                if let Some(mask) = mask {
+                    image.s_mask(mask);
+                }
+                image.filter(Filter::FlateDecode);
+            }
+        }
+
+        self.image_refs.push(id);
+        Some(self.image_refs.len() - 1)
+    }
+
+    fn link(&mut self, link: &LinkItem, ts: Transform) -> Option<Link> {
+        let (width, height) = (link.size.x.0, link.size.y.0);
+        let corners = [(0., 0.), (width, 0.), (0., height), (width, height)].map(|(x, y)| {
+            (
+                ts.sx.0 * x + ts.kx.0 * y + ts.tx.0,
+                ts.ky.0 * x + ts.sy.0 * y + ts.ty.0,
+            )
+        });
+        let (mut x0, mut y0, mut x1, mut y1) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for (x, y) in corners {
+            (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+        }
+
+        let target = match link.href.strip_prefix("@typst:handleTypstLocation(") {
+            Some(args) => {
+                let Some((page, x, y)) = parse_location(args) else {
+                    self.warn(LINK_WARNING);
+                    return None;
+                };
+                // The target may be on a page which is not exported.
+                let Some(dest) = self.dest(page, x, y) else {
+                    self.warn(DEST_WARNING);
+                    return None;
+                };
+                LinkTarget::Dest(dest)
+            }
+            None if link.href.starts_with('@') => {
+                self.warn(LINK_WARNING);
+                return None;
+            }
+            None => LinkTarget::Uri(link.href.to_string()),
+        };
+
+        let h = self.page_height;
+        Some(Link {
+            rect: Rect::new(x0, h - y1, x1, h - y0),
+            target,
+        })
+    }
+
+    /// Get the name of the destination of a point on a 1-based page, or
+    /// `None` if the page doesn't exist.
+    fn dest(&mut self, page: usize, x: f32, y: f32) -> Option<String> {
+        let page_ref = *self.page_refs.get(page.checked_sub(1)?)?;
+        let h = self.page_heights[page - 1];
+        let name = format!("loc-{page}-{}-{}", Num(x), Num(y));
+        (self.dests.entry(name.clone())).or_insert((page_ref, x, h - y));
+        Some(name)
+    }
+
+    /// Write the named destinations, returning the dictionary of them.
+    fn dests(&mut self) -> Option<Ref> {
+        if self.dests.is_empty() {
+            return None;
+        }
+
+        let id = self.alloc();
+        let mut dests = self.pdf.destinations(id);
+        for (name, &(page, x, y)) in &self.dests {
+            dests
+                .insert(Name(name.as_bytes()))
+                .page(page)
+                .xyz(x, y, None);
+        }
+        Some(id)
+    }
+
+    /// Write the resources shared by all of the pages along with the fonts.
+    fn resources(&mut self, resources: Ref) {
+        let embedded = std::mem::take(&mut self.embedded);
+        let embedded: Vec<_> = embedded.iter().map(|font| self.embed_font(font)).collect();
+        let type3_fonts = std::mem::take(&mut self.type3_fonts);
+        let type3_fonts: Vec<_> = type3_fonts
+            .iter()
+            .map(|font| self.type3_font(font))
+            .collect();
+        let states: Vec<_> = (self.alphas.clone().into_iter())
+            .map(|(fill, stroke)| {
+                let id = self.alloc();
+                (self.pdf.ext_graphics(id))
+                    .non_stroking_alpha(fill as f32 / 255.)
+                    .stroking_alpha(stroke as f32 / 255.);
+                id
+            })
+            .collect();
+
+        let named = |prefix: &str, refs: &[Ref]| -> Vec<(String, Ref)> {
+            (refs.iter().enumerate())
+                .map(|(idx, id)| (format!("{prefix}{idx}"), *id))
+                .collect()
+        };
+        let mut dict = self.pdf.indirect(resources).start::<Resources>();
+        let mut fonts = dict.fonts();
+        for (name, id) in named("E", &embedded)
+            .iter()
+            .chain(&named("F", &type3_fonts))
+        {
+            fonts.pair(Name(name.as_bytes()), *id);
+        }
+        fonts.finish();
+        let mut images = dict.x_objects();
+        for (name, id) in named("Im", &self.image_refs) {
+            images.pair(Name(name.as_bytes()), id);
+        }
+        images.finish();
+        let mut patterns = dict.patterns();
+        for (name, id) in named("Sh", &self.gradient_refs) {
+            patterns.pair(Name(name.as_bytes()), id);
+        }
+        patterns.finish();
+        let mut ext_states = dict.ext_g_states();
+        for (name, id) in named("Gs", &states) {
+            ext_states.pair(Name(name.as_bytes()), id);
+        }
+        ext_states.finish();
+    }
+
+    /// Embed a font as a CID font with the glyphs drawn by the pages, like
+    /// typst-pdf.
+    fn embed_font(&mut self, embedded: &EmbeddedFont) -> Ref {
+        let [type0, cid, descriptor, cmap, data] = [(); 5].map(|_| self.alloc());
+        let font = &embedded.font;
+        let ttf = font.ttf();
+
+        // CFF2 fonts are embedded like CFF fonts, which is what typst-pdf
+        // does.
+        let raw = ttf.raw_face();
+        let is_cff = raw.table(CFF).or_else(|| raw.table(CFF2)).is_some();
+        let postscript_name = font
+            .find_name(name_id::POST_SCRIPT_NAME)
+            .unwrap_or_else(|| "unknown".to_string());
+        let base_font = format!("{}+{postscript_name}", subset_tag(&embedded.glyphs));
+        let base_font_type0 = if is_cff {
+            format!("{base_font}-Identity-H")
+        } else {
+            base_font.clone()
+        };
+
+        (self.pdf.type0_font(type0))
+            .base_font(Name(base_font_type0.as_bytes()))
+            .encoding_predefined(Name(b"Identity-H"))
+            .descendant_font(cid)
+            .to_unicode(cmap);
+
+        let upem = font.units_per_em() as f32;
+        let to_units = |v: f32| v * 1000. / upem;
+        let mut cid_font = self.pdf.cid_font(cid);
+        cid_font.subtype(if is_cff {
+            CidFontType::Type0
+        } else {
+            CidFontType::Type2
+        });
+        cid_font.base_font(Name(base_font.as_bytes()));
+        cid_font.system_info(SYSTEM_INFO);
+        cid_font.font_descriptor(descriptor);
+        cid_font.default_width(0.);
+        if !is_cff {
+            cid_font.cid_to_gid_map_predefined(Name(b"Identity"));
+        }
+        let mut widths = cid_font.widths();
+        for &glyph in embedded.glyphs.keys() {
+            let width = ttf.glyph_hor_advance(GlyphId(glyph)).unwrap_or(0);
+            widths.consecutive(glyph_cid(font, glyph), [to_units(width as f32)]);
+        }
+        widths.finish();
+        cid_font.finish();
+
+        let mut flags = FontFlags::empty();
+        flags.set(FontFlags::SERIF, postscript_name.contains("Serif"));
+        flags.set(FontFlags::FIXED_PITCH, ttf.is_monospaced());
+        flags.set(FontFlags::ITALIC, ttf.is_italic());
+        flags.insert(FontFlags::SYMBOLIC);
+        flags.insert(FontFlags::SMALL_CAP);
+
+        let bbox = ttf.global_bounding_box();
+        let metrics = font.metrics();
+        let em = |v: typst::layout::Em| v.get() as f32 * 1000.;
+        let stem_v = 10. + 0.244 * (f32::from(ttf.weight().to_number()) - 50.);
+        let mut font_descriptor = self.pdf.font_descriptor(descriptor);
+        (font_descriptor.name(Name(base_font.as_bytes())))
+            .flags(flags)
+            .bbox(Rect::new(
+                to_units(bbox.x_min as f32),
+                to_units(bbox.y_min as f32),
+                to_units(bbox.x_max as f32),
+                to_units(bbox.y_max as f32),
+            ))
+            .italic_angle(ttf.italic_angle().unwrap_or(0.))
+            .ascent(em(metrics.ascender))
+            .descent(em(metrics.descender))
+            .cap_height(em(metrics.cap_height))
+            .stem_v(stem_v);
+        if is_cff {
+            font_descriptor.font_file3(data);
+        } else {
+            font_descriptor.font_file2(data);
+        }
+        font_descriptor.finish();
+
+        let unicode_cmap = to_unicode_cmap(font, &embedded.glyphs);
+        self.pdf.cmap(cmap, &unicode_cmap.finish());
+
+        let glyphs: Vec<_> = embedded.glyphs.keys().copied().collect();
+        let program = subset_font(font, &glyphs);
+        let mut stream = self.pdf.stream(data, &program);
+        stream.filter(Filter::FlateDecode);
+        if is_cff {
+            stream.pair(Name(b"Subtype"), Name(b"CIDFontType0C"));
+        }
+        stream.finish();
+        type0
+    }
+
+    fn type3_font(&mut self, font: &Type3Font) -> Ref {
+        let module = self.module;
+        let item = module.get_font(&font.font);
+        let upem = item.map_or(1000., |item| item.units_per_em.0);
+
+        let mut procs = vec![];
+        let mut font_bbox = BBox::default();
+        for (glyph, advance, _) in &font.glyphs {
+            let outline =
+                item.and_then(|item| item.get_glyph(*glyph))
+                    .and_then(|glyph| match glyph.as_ref() {
+                        FlatGlyphItem::Outline(outline) => Some(outline),
+                        _ => None,
+                    });
+            let segments = outline
+                .and_then(|outline| parse_path(&outline.d))
+                .unwrap_or_default();
+            let ts = outline.and_then(|outline| outline.ts.as_deref());
+
+            let mut bbox = BBox::default();
+            for segment in &segments {
+                for (x, y) in segment.points() {
+                    bbox.add(apply(ts, (x, y)));
+                }
+            }
+            font_bbox.union(&bbox);
+
+            let mut content = Content::new();
+            let [x0, y0, x1, y1] = bbox.0.unwrap_or_default();
+            content.start_shape_glyph(*advance, x0, y0, x1, y1);
+            if !segments.is_empty() {
+                write_segments(&mut content, &segments, ts);
+                content.fill_nonzero();
+            }
+            let id = self.alloc();
+            let data = deflate(&content.finish());
+            self.pdf.stream(id, &data).filter(Filter::FlateDecode);
+            procs.push(id);
+        }
+
+        let to_unicode = self.alloc();
+        let mut cmap = UnicodeCmap::<u8>::new(CMAP_NAME, SYSTEM_INFO);
+        for (code, (_, _, unicode)) in font.glyphs.iter().enumerate() {
+            if let Some(unicode) = unicode {
+                cmap.pair_with_multiple(code as u8, unicode.chars());
+            }
+        }
+        self.pdf.cmap(to_unicode, &cmap.finish());
+
+        let names: Vec<_> = (0..font.glyphs.len())
+            .map(|code| format!("g{code}"))
+            .collect();
+        let id = self.alloc();
+        let scale = 1. / upem;
+        let [x0, y0, x1, y1] = font_bbox.0.unwrap_or_default();
+        let mut type3 = self.pdf.type3_font(id);
+        (type3.bbox(Rect::new(x0, y0, x1, y1))).matrix([scale, 0., 0., scale, 0., 0.]);
+        let mut char_procs = type3.char_procs();
+        for (name, proc) in names.iter().zip(procs) {
+            char_procs.pair(Name(name.as_bytes()), proc);
+        }
+        char_procs.finish();
+        (type3.encoding_custom().differences())
+            .consecutive(0, names.iter().map(|name| Name(name.as_bytes())));
+        (type3.first_char(0))
+            .last_char(font.glyphs.len().saturating_sub(1) as u8)
+            .widths(font.glyphs.iter().map(|(_, advance, _)| *advance))
+            .to_unicode(to_unicode);
+        type3.resources().finish();
+        id
+    }
+
+    /// Write the bookmarks of the outline, returning the outline dictionary.
+    fn outline(&mut self, outline: &[OutlineItem]) -> Option<Ref> {
+        if outline.is_empty() {
+            return None;
+        }
+
+        let root = self.alloc();
+        let ids: Vec<_> = outline.iter().map(|_| self.alloc()).collect();
+
+        // A heading is nested in the closest preceding heading of a lower
+        // level.
+        let mut parents = vec![None; outline.len()];
+        let mut stack: Vec<usize> = vec![];
+        for (i, item) in outline.iter().enumerate() {
+            while stack
+                .last()
+                .is_some_and(|&top| outline[top].level >= item.level)
+            {
+                stack.pop();
+            }
+            parents[i] = stack.last().copied();
+            stack.push(i);
+        }
+        let children_of = |parent: Option<usize>| -> Vec<usize> {
+            (0..outline.len())
+                .filter(|&i| parents[i] == parent)
+                .collect()
+        };
+        let mut descendants = vec![0; outline.len()];
+        for i in (0..outline.len()).rev() {
+            if let Some(parent) = parents[i] {
+                descendants[parent] += 1 + descendants[i];
+            }
+        }
+
+        for (i, item) in outline.iter().enumerate() {
+            let title = match &item.numbering {
+                Some(numbering) => format!("{numbering} {}", item.text),
+                None => item.text.to_string(),
+            };
+            let dest = self.dest(item.page as usize, 0., item.y.0);
+            let parent = parents[i].map_or(root, |parent| ids[parent]);
+            let mut writer = self.pdf.outline_item(ids[i]);
+            writer.title(TextStr(&title)).parent(parent);
+
+            let siblings = children_of(parents[i]);
+            let at = siblings
+                .iter()
+                .position(|&sibling| sibling == i)
+                .unwrap_or_default();
+            if let Some(prev) = at.checked_sub(1) {
+                writer.prev(ids[siblings[prev]]);
+            }
+            if let Some(next) = siblings.get(at + 1) {
+                writer.next(ids[*next]);
+            }
+            let children = children_of(Some(i));
+            if let (Some(first), Some(last)) = (children.first(), children.last()) {
+                (writer.first(ids[*first]).last(ids[*last])).count(descendants[i]);
+            }
+            if let Some(dest) = dest {
+                writer.dest_name(Name(dest.as_bytes()));
+            }
+        }
+
+        let top = children_of(None);
+        (self.pdf.outline(root))
+            .first(ids[top[0]])
+            .last(ids[top[top.len() - 1]])
+            .count(outline.len() as i32);
+        Some(root)
+    }
+}
+
+/// Parse the arguments of an internal link, i.e. `this, page, x, y)`.
+fn parse_location(args: &str) -> Option<(usize, f32, f32)> {
+    let mut args = args.trim_end_matches(')').split(',').map(str::trim).skip(1);
+    let page = args.next()?.parse::<usize>().ok()?;
+    let x = args.next()?.parse::<f32>().ok()?;
+    let y = args.next()?.parse::<f32>().ok()?;
+    Some((page, x, y))
+}
+
+/// Get the CID of a glyph, which differs from the glyph id only in CID-keyed
+/// CFF fonts.
+fn glyph_cid(font: &Font, glyph: u16) -> u16 {
+    (font.ttf().tables().cff)
+        .and_then(|cff| cff.glyph_cid(GlyphId(glyph)))
+        .unwrap_or(glyph)
+}
+
+/// Subset a font to the glyphs, returning the compressed font program, which
+/// is the bare CFF table of a CFF font.
+fn subset_font(font: &Font, glyphs: &[u16]) -> Vec<u8> {
+    let data = font.data();
+    let subsetted = subsetter::subset(data, font.index(), subsetter::Profile::pdf(glyphs));
+    let mut data = subsetted.as_deref().unwrap_or(data);
+    if let Some(cff) = ttf_parser::RawFace::parse(data, 0)
+        .ok()
+        .and_then(|raw| raw.table(CFF))
+    {
+        data = cff;
+    }
+    deflate(data)
+}
+
+/// Get the six letter tag of a subset, e.g. `ABCDEF` in `ABCDEF+Font`.
+fn subset_tag(glyphs: &BTreeMap<u16, String>) -> String {
+    let mut hash = hash128(glyphs);
+    (0..6)
+        .map(|_| {
+            let letter = (b'A' + (hash % 26) as u8) as char;
+            hash /= 26;
+            letter
+        })
+        .collect()
+}
+
+/// Write the ToUnicode CMap of an embedded font, preferring the code points
+/// of the font over the texts of the glyphs in the artifact.
+fn to_unicode_cmap(font: &Font, glyphs: &BTreeMap<u16, String>) -> UnicodeCmap {
+    let ttf = font.ttf();
+    let mut texts = glyphs.clone();
+    let subtables = ttf
+        .tables()
+        .cmap
+        .into_iter()
+        .flat_map(|cmap| cmap.subtables);
+    for subtable in subtables.filter(|subtable| subtable.is_unicode()) {
+        subtable.codepoints(|n| {
+            let Some(c) = char::from_u32(n).filter(|c| !is_private_use(*c)) else {
+                return;
+            };
+            if let Some(GlyphId(glyph)) = ttf.glyph_index(c) {
+                if let Some(text) = texts.get_mut(&glyph) {
+                    *text = c.to_string();
+                }
+            }
+        });
+    }
+
+    let mut cmap = UnicodeCmap::new(CMAP_NAME, SYSTEM_INFO);
+    for (&glyph, text) in texts.iter().filter(|(_, text)| !text.is_empty()) {
+        cmap.pair_with_multiple(glyph_cid(font, glyph), text.chars());
+    }
+    cmap
+}
+
+fn is_private_use(c: char) -> bool {
+    matches!(c, '\u{e000}'..='\u{f8ff}' | '\u{f0000}'..='\u{ffffd}' | '\u{100000}'..='\u{10fffd}')
+}
+
+/// Get the stops of a gradient in RGB, which are subdivided if the gradient
+/// is interpolated in another color space.
+fn rgb_stops(gradient: &GradientItem) -> Vec<([f32; 3], f32)> {
+    const SUBDIVISIONS: usize = 16;
+
+    let space = ColorSpace::try_from_typst(gradient.space).unwrap_or(ColorSpace::Srgb);
+    let color = |c: &Rgba8Item| Color::from_u8(c.r, c.g, c.b, c.a);
+    let rgb = |color: Color| {
+        let [r, g, b, _] = color.to_rgb().to_vec4();
+        [r, g, b]
+    };
+
+    let mut stops = vec![];
+    for window in gradient.stops.windows(2) {
+        let ((c0, t0), (c1, t1)) = (window[0], window[1]);
+        stops.push((rgb(color(&c0)), t0.0));
+        if space == ColorSpace::Srgb || c0 == c1 {
+            continue;
+        }
+        for step in 1..SUBDIVISIONS {
+            let w = step as f64 / SUBDIVISIONS as f64;
+            let mixed = Color::mix_iter(
+                [
+                    WeightedColor::new(color(&c0), 1. - w),
+                    WeightedColor::new(color(&c1), w),
+                ],
+                space,
+            );
+            if let Ok(mixed) = mixed {
+                stops.push((rgb(mixed), t0.0 + (t1.0 - t0.0) * w as f32));
+            }
+        }
+    }
+    if let Some((c, t)) = gradient.stops.last() {
+        stops.push((rgb(color(c)), t.0));
+    }
+    // A shading function interpolates at least two stops.
+    if stops.len() == 1 {
+        stops.push(stops[0]);
+    }
+    stops
+}
+
+/// Sample the RGB stops of a gradient at `t`.
+fn sample_stops(stops: &[([f32; 3], f32)], t: f32) -> [f32; 3] {
+    let Some(end) = stops.iter().position(|(_, stop)| *stop >= t) else {
+        return stops.last().map_or([0.; 3], |(c, _)| *c);
+    };
+    let Some(start) = end.checked_sub(1) else {
+        return stops[0].0;
+    };
+    let ((c0, t0), (c1, t1)) = (stops[start], stops[end]);
+    let w = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1. };
+    [0, 1, 2].map(|i| c0[i] + (c1[i] - c0[i]) * w)
+}
+
+/// Write the vertices of a free-form Gouraud-shaded mesh drawing a conic
+/// gradient in its unit square, as a fan of triangles around the center.
+fn conic_mesh(stops: &[([f32; 3], f32)], (cx, cy): (f32, f32), angle: f32) -> Vec<u8> {
+    // The fan covers the unit square from any center in it, see the decode
+    // array of the shading.
+    const RADIUS: f32 = 1.5;
+
+    let coord = |v: f32| (((v + 2.) / 5.).clamp(0., 1.) * u16::MAX as f32).round() as u16;
+    let mut data = vec![];
+    let mut vertex = |(x, y): (f32, f32), color: [f32; 3]| {
+        data.push(0);
+        data.extend(coord(x).to_be_bytes());
+        data.extend(coord(y).to_be_bytes());
+        data.extend(color.map(|c| (c.clamp(0., 1.) * 255.).round() as u8));
+    };
+
+    // The direction of the color at `t` like typst, which samples
+    // `(π - atan2(y, x) + angle) % τ / τ`.
+    let point = |t: f32| {
+        let phi = PI + angle - TAU * t;
+        (cx + RADIUS * phi.cos(), cy + RADIUS * phi.sin())
+    };
+    for i in 0..CONIC_SEGMENTS {
+        let (t0, t1) = (
+            i as f32 / CONIC_SEGMENTS as f32,
+            (i + 1) as f32 / CONIC_SEGMENTS as f32,
+        );
+        vertex((cx, cy), sample_stops(stops, (t0 + t1) / 2.));
+        vertex(point(t0), sample_stops(stops, t0));
+        vertex(point(t1), sample_stops(stops, t1));
+    }
+    data
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing to a vector never fails.
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// A number in a destination name, which is written without trailing zeros.
+struct Num(f32);
+
+impl fmt::Display for Num {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = format!("{:.4}", self.0);
+        let s = s.trim_end_matches('0').trim_end_matches('.');
+        f.write_str(if s == "-0" { "0" } else { s })
+    }
+}
+
+/// A bounding box of a glyph, which is empty until a point is added.
+#[derive(Debug, Clone, Copy, Default)]
+struct BBox(Option<[f32; 4]>);
+
+impl BBox {
+    fn add(&mut self, (x, y): (f32, f32)) {
+        self.0 = Some(match self.0 {
+            Some([x0, y0, x1, y1]) => [x0.min(x), y0.min(y), x1.max(x), y1.max(y)],
+            None => [x, y, x, y],
+        });
+    }
+
+    fn union(&mut self, other: &BBox) {
+        if let Some([x0, y0, x1, y1]) = other.0 {
+            self.add((x0, y0));
+            self.add((x1, y1));
+        }
+    }
+}
+
+/// Parse a color of a paint, i.e. `#rgb`, `#rrggbb` or `#rrggbbaa`, returning
+/// the components and the alpha.
+fn parse_color(paint: &str) -> Option<([u8; 3], u8)> {
+    let hex = paint.strip_prefix('#')?;
+    let digit = |i: usize| u8::from_str_radix(hex.get(i..i + 1)?, 16).ok();
+    let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    match hex.len() {
+        3 => Some(([digit(0)? * 17, digit(1)? * 17, digit(2)? * 17], u8::MAX)),
+        6 => Some(([byte(0)?, byte(2)?, byte(4)?], u8::MAX)),
+        8 => Some(([byte(0)?, byte(2)?, byte(4)?], byte(6)?)),
+        _ => None,
+    }
+}
+
+fn to_transform(item: &TransformItem) -> Transform {
+    match item {
+        TransformItem::Rotate(angle) => {
+            let (sin, cos) = angle.0.sin_cos();
+            Transform {
+                sx: Scalar(cos),
+                ky: Scalar(sin),
+                kx: Scalar(-sin),
+                sy: Scalar(cos),
+                tx: Scalar(0.),
+                ty: Scalar(0.),
+            }
+        }
+        item => item.clone().into(),
+    }
+}
+
+fn to_array(ts: Transform) -> [f32; 6] {
+    [ts.sx.0, ts.ky.0, ts.kx.0, ts.sy.0, ts.tx.0, ts.ty.0]
+}
+
+fn from_array([sx, ky, kx, sy, tx, ty]: [f32; 6]) -> Transform {
+    Transform {
+        sx: Scalar(sx),
+        ky: Scalar(ky),
+        kx: Scalar(kx),
+        sy: Scalar(sy),
+        tx: Scalar(tx),
+        ty: Scalar(ty),
+    }
+}
+
+fn apply(ts: Option<&Transform>, (x, y): (f32, f32)) -> (f32, f32) {
+    match ts {
+        Some(ts) => (
+            ts.sx.0 * x + ts.kx.0 * y + ts.tx.0,
+            ts.ky.0 * x + ts.sy.0 * y + ts.ty.0,
+        ),
+        None => (x, y),
+    }
+}
+
+/// A segment of a path in absolute coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Segment {
+    Move(f32, f32),
+    Line(f32, f32),
+    Cubic([f32; 6]),
+    Close,
+}
+
+impl Segment {
+    fn points(&self) -> Vec<(f32, f32)> {
+        match *self {
+            Segment::Move(x, y) | Segment::Line(x, y) => vec![(x, y)],
+            Segment::Cubic([x1, y1, x2, y2, x, y]) => vec![(x1, y1), (x2, y2), (x, y)],
+            Segment::Close => vec![],
+        }
+    }
+}
+
+fn write_segments(out: &mut Content, segments: &[Segment], ts: Option<&Transform>) {
+    let p = |x: f32, y: f32| apply(ts, (x, y));
+    for segment in segments {
+        match *segment {
+            Segment::Move(x, y) => {
+                let (x, y) = p(x, y);
+                out.move_to(x, y);
+            }
+            Segment::Line(x, y) => {
+                let (x, y) = p(x, y);
+                out.line_to(x, y);
+            }
+            Segment::Cubic([x1, y1, x2, y2, x, y]) => {
+                let ((x1, y1), (x2, y2), (x, y)) = (p(x1, y1), p(x2, y2), p(x, y));
+                out.cubic_to(x1, y1, x2, y2, x, y);
+            }
+            Segment::Close => {
+                out.close_path();
+            }
+        }
+    }
+}
+
+/// Parse the `d` attribute of a path with the commands `M`, `L`, `H`, `V`,
+/// `C`, `Q` and `Z`, in either the absolute or the relative form.
+///
+/// Returns `None` if a command is not supported or malformed.
+fn parse_path(d: &str) -> Option<Vec<Segment>> {
+    let mut tokens = PathTokens { s: d.as_bytes() };
+    let mut segments = vec![];
+    let (mut cur, mut start) = ((0f32, 0f32), (0f32, 0f32));
+    let mut cmd = None;
+    loop {
+        tokens.skip_separators();
+        if tokens.s.is_empty() {
+            break;
+        }
+        if let Some(c) = tokens.command() {
+            cmd = Some(c);
+        }
+        let c = cmd?;
+        let base = if c.is_ascii_lowercase() {
+            cur
+        } else {
+            (0., 0.)
+        };
+        let point = |tokens: &mut PathTokens| {
+            let (x, y) = (tokens.number()?, tokens.number()?);
+            Some((base.0 + x, base.1 + y))
+        };
+        match c.to_ascii_uppercase() {
+            b'M' => {
+                cur = point(&mut tokens)?;
+                start = cur;
+                segments.push(Segment::Move(cur.0, cur.1));
+                // The following pairs are implicit line commands.
+                cmd = Some(if c == b'm' { b'l' } else { b'L' });
+            }
+            b'L' => {
+                cur = point(&mut tokens)?;
+                segments.push(Segment::Line(cur.0, cur.1));
+            }
+            b'H' => {
+                cur.0 = base.0 + tokens.number()?;
+                segments.push(Segment::Line(cur.0, cur.1));
+            }
+            b'V' => {
+                cur.1 = base.1 + tokens.number()?;
+                segments.push(Segment::Line(cur.0, cur.1));
+            }
+            b'C' => {
+                let (c1, c2, end) = (
+                    point(&mut tokens)?,
+                    point(&mut tokens)?,
+                    point(&mut tokens)?,
+                );
+                segments.push(Segment::Cubic([c1.0, c1.1, c2.0, c2.1, end.0, end.1]));
+                cur = end;
+            }
+            b'Q' => {
+                let (q, end) = (point(&mut tokens)?, point(&mut tokens)?);
+                let c1 = (
+                    cur.0 + 2. / 3. * (q.0 - cur.0),
+                    cur.1 + 2. / 3. * (q.1 - cur.1),
+                );
+                let c2 = (
+                    end.0 + 2. / 3. * (q.0 - end.0),
+                    end.1 + 2. / 3. * (q.1 - end.1),
+                );
+                segments.push(Segment::Cubic([c1.0, c1.1, c2.0, c2.1, end.0, end.1]));
+                cur = end;
+            }
+            b'Z' => {
+                segments.push(Segment::Close);
+                cur = start;
+                // A close command takes no arguments.
+                cmd = None;
+            }
+            _ => return None,
+        }
+    }
+    Some(segments)
+}
+
+struct PathTokens<'a> {
+    s: &'a [u8],
+}
+
+impl PathTokens<'_> {
+    fn skip_separators(&mut self) {
+        while let [b' ' | b',' | b'\t' | b'\n' | b'\r', rest @ ..] = self.s {
+            self.s = rest;
+        }
+    }
+
+    fn command(&mut self) -> Option<u8> {
+        match self.s {
+            [c, rest @ ..] if c.is_ascii_alphabetic() => {
+                self.s = rest;
+                Some(*c)
+            }
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.skip_separators();
+        let s = self.s;
+        let mut len = 0;
+        let digits = |len: &mut usize| {
+            while s.get(*len).is_some_and(u8::is_ascii_digit) {
+                *len += 1;
+            }
+        };
+        if matches!(s.first(), Some(b'+' | b'-')) {
+            len += 1;
+        }
+        digits(&mut len);
+        if s.get(len) == Some(&b'.') {
+            len += 1;
+            digits(&mut len);
+        }
+        if matches!(s.get(len), Some(b'e' | b'E')) {
+            let mut exp = len + 1;
+            if matches!(s.get(exp), Some(b'+' | b'-')) {
+                exp += 1;
+            }
+            if s.get(exp).is_some_and(u8::is_ascii_digit) {
+                len = exp;
+                digits(&mut len);
+            }
+        }
+
+        let number = std::str::from_utf8(&s[..len]).ok()?.parse().ok()?;
+        self.s = &s[len..];
+        Some(number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, path::Path, sync::Arc};
+
+    use typst::{
+        eval::Tracer,
+        foundations::{Label, Smart},
+    };
+    use typst_ts_compiler::{
+        service::{
+            outline::document_outline, vector_artifact, vector_artifact_with, ArtifactOptions,
+            CompileEnv, EnvWorld,
+        },
+        TypstSystemWorld,
+    };
+    use typst_ts_core::{
+        config::{compiler::EntryOpts, CompileOpts},
+        vector::ir::{
+            Axes, ColorTransform, GlyphRef, GroupRef, OutlineGlyphItem, Page, Point, Size,
+            TextItemContent, TextShape, VecDocument,
+        },
+    };
+
+    use super::*;
+
+    fn fg(v: u64) -> Fingerprint {
+        Fingerprint::from_pair(v, 0)
+    }
+
+    /// Collapse the whitespace of a PDF, which pdf-writer writes a dictionary
+    /// entry per line.
+    fn flat(pdf: &[u8]) -> String {
+        let pdf = String::from_utf8_lossy(pdf);
+        pdf.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn artifact() -> Vec<u8> {
+        let mut module = Module::default();
+        let font = FontRef { hash: 7, idx: 0 };
+        module.fonts.push(FontItem {
+            fingerprint: fg(7),
+            family: "Test".into(),
+            hash: 7,
+            cap_height: Scalar(700.),
+            ascender: Scalar(800.),
+            descender: Scalar(-200.),
+            units_per_em: Scalar(1000.),
+            vertical: false,
+            glyphs: vec![],
+            glyph_cov: Default::default(),
+        });
+        let outline = OutlineGlyphItem {
+            ts: None,
+            d: "M 0 0 L 500 0 L 500 700 Z".into(),
+            ligature_len: 0,
+        };
+        module.glyphs.push((
+            GlyphRef {
+                font_hash: 7,
+                glyph_idx: 1,
+            },
+            FlatGlyphItem::Outline(Arc::new(outline)),
+        ));
+
+        // A linear gradient from red to blue, which is placed on a 10pt
+        // square.
+        let rgba = |r, g, b| Rgba8Item { r, g, b, a: 255 };
+        let gradient = GradientItem {
+            stops: vec![(rgba(255, 0, 0), Scalar(0.)), (rgba(0, 0, 255), Scalar(1.))],
+            anti_alias: true,
+            space: typst_ts_core::vector::ir::ColorSpace::Srgb,
+            kind: GradientKind::Linear(Scalar(0.)),
+            styles: vec![],
+        };
+        module
+            .items
+            .insert(fg(20), VecItem::Gradient(Arc::new(gradient)));
+        let color = ColorTransform {
+            transform: Transform::from_scale(Scalar(10.), Scalar(10.)),
+            item: fg(20),
+        };
+        module
+            .items
+            .insert(fg(21), VecItem::ColorTransform(Arc::new(color)));
+
+        let items = [
+            VecItem::Path(PathItem {
+                d: "M 0 0 h 100 v 50 H 0 Z".into(),
+                size: Some(Size::new(Scalar(100.), Scalar(50.))),
+                styles: vec![PathStyle::Fill("#ff000080".into())],
+            }),
+            VecItem::Path(PathItem {
+                d: "M 0 0 L 10 10".into(),
+                size: None,
+                styles: vec![PathStyle::Stroke(
+                    format!("@{}", fg(21).as_svg_id("g")).into(),
+                )],
+            }),
+            VecItem::Text(TextItem {
+                shape: Arc::new(TextShape {
+                    font,
+                    dir: "ltr".into(),
+                    size: Scalar(10.),
+                    styles: vec![PathStyle::Fill("#000".into())],
+                }),
+                content: Arc::new(TextItemContent {
+                    content: "AA".into(),
+                    glyphs: vec![(Scalar(0.), Scalar(5.), 1), (Scalar(0.), Scalar(5.), 1)].into(),
+                }),
+            }),
+            VecItem::Link(LinkItem {
+                href: "https://example.com".into(),
+                size: Size::new(Scalar(20.), Scalar(10.)),
+            }),
+            VecItem::Link(LinkItem {
+                href: "@typst:handleTypstLocation(this, 1, 0, 20)".into(),
+                size: Size::new(Scalar(20.), Scalar(10.)),
+            }),
+            VecItem::Path(PathItem {
+                d: "M 0 0 h 10 v 10 Z".into(),
+                size: None,
+                styles: vec![PathStyle::Fill("@p1234".into())],
+            }),
+        ];
+        let mut children = vec![];
+        for (i, item) in items.into_iter().enumerate() {
+            module.items.insert(fg(i as u64 + 1), item);
+            let pos = Point::new(Scalar(10.), Scalar(10. * i as f32));
+            children.push((pos, fg(i as u64 + 1)));
+        }
+        module
+            .items
+            .insert(fg(100), VecItem::Group(GroupRef(children.into())));
+
+        let doc = VecDocument {
+            module,
+            pages: vec![Page {
+                content: fg(100),
+                size: Size::new(Scalar(200.), Scalar(300.)),
+            }],
+        };
+        let outline = vec![
+            OutlineItem {
+                level: 1,
+                text: "Intro".into(),
+                numbering: Some("1".into()),
+                page: 1,
+                y: Scalar(20.),
+                slug: "sec-intro".into(),
+            },
+            OutlineItem {
+                level: 2,
+                text: "Überblick".into(),
+                numbering: None,
+                page: 1,
+                y: Scalar(40.),
+                slug: "sec-überblick".into(),
+            },
+        ];
+        doc.to_artifact_bytes_with(vec![ModuleMetadata::Outline(Arc::new(outline))])
+    }
+
+    #[test]
+    fn test_artifact_to_pdf() {
+        let res = artifact_to_pdf(&artifact()).unwrap();
+        assert_eq!(
+            res.warnings,
+            [
+                "pattern paints are painted in black",
+                "texts of unresolved fonts are drawn by Type 3 fonts"
+            ]
+        );
+
+        assert!(res.pdf.starts_with(b"%PDF-1.7"));
+        assert!(res.pdf.ends_with(b"%%EOF"));
+        let pdf = flat(&res.pdf);
+        assert!(pdf.contains("/Subtype /Type3"));
+        // Both glyphs share a single code.
+        assert!(pdf.contains("/FirstChar 0 /LastChar 0 /Widths [500]"));
+        // The gradient is placed on the stroked path in the default space of
+        // the page.
+        assert!(pdf.contains("/ShadingType 2"));
+        assert!(pdf.contains("/Matrix [10 0 0 -10 10 290]"));
+        assert!(pdf.contains("/S /URI /URI (https://example.com)"));
+        assert!(pdf.contains("/S /GoTo /D /loc-1-0-20"));
+        assert!(pdf.contains("/Dests "));
+        assert!(pdf.contains("/loc-1-0-20 [4 0 R /XYZ 0 280 0] /loc-1-0-40 [4 0 R /XYZ 0 260 0]"));
+        assert!(pdf.contains("/Type /ExtGState /ca 0.50"));
+        assert!(pdf.contains("/Type /Outlines"));
+        assert!(pdf.contains("/Title (1 Intro)"));
+        assert!(pdf.contains("/Count 1 /Dest /loc-1-0-20"));
+    }
+
+    /// Resolve a named destination to its 1-based page by the objects of a
+    /// PDF.
+    fn dest_page(pdf: &str, name: &str) -> Option<usize> {
+        let kids = pdf.split("/Kids [").nth(1)?.split(']').next()?;
+        let kids: Vec<_> = (kids.split(" R").map(str::trim))
+            .filter(|kid| !kid.is_empty())
+            .collect();
+        let dest = pdf.split(&format!("{name} [")).nth(1)?;
+        let page = dest.split(" R").next()?.trim();
+        kids.iter().position(|kid| *kid == page).map(|idx| idx + 1)
+    }
+
+    #[test]
+    fn test_cross_reference_links() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fixtures/pdf");
+        let mut world = TypstSystemWorld::new(CompileOpts {
+            entry: EntryOpts::new_rooted(
+                root.canonicalize().unwrap(),
+                Some("cross-references.typ".into()),
+            ),
+            no_system_fonts: true,
+            with_embedded_fonts: typst_assets::fonts().map(Cow::Borrowed).collect(),
+            ..CompileOpts::default()
+        })
+        .unwrap();
+        world.prepare_env(&mut CompileEnv::default()).unwrap();
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+        assert_eq!(doc.pages.len(), 2);
+
+        // The destination of a heading, named by its position like the links
+        // of the artifact.
+        let dest = |label: &str| {
+            let heading = doc.introspector.query_label(Label::new(label)).unwrap();
+            let pos = doc.introspector.position(heading.location().unwrap());
+            let (x, y) = (pos.point.x.to_pt() as f32, pos.point.y.to_pt() as f32);
+            format!("/loc-{}-{}-{}", pos.page, Num(x), Num(y))
+        };
+        let (intro, usage) = (dest("sec-intro"), dest("sec-usage"));
+        assert!(intro.starts_with("/loc-1-"), "{intro}");
+        assert!(usage.starts_with("/loc-2-"), "{usage}");
+
+        let options = ArtifactOptions {
+            outline: Some(document_outline(&world, &doc)),
+            ..ArtifactOptions::default()
+        };
+        let artifact = vector_artifact_with(&doc, &options);
+        let res = artifact_to_pdf_with_fonts(&artifact, world_fonts(&world)).unwrap();
+        assert!(res.warnings.is_empty(), "{:?}", res.warnings);
+        let pdf = flat(&res.pdf);
+        assert_eq!(pdf.matches("/S /URI /URI (https://typst.app)").count(), 1);
+        assert_eq!(pdf.matches(&format!("/S /GoTo /D {usage}")).count(), 1);
+        // The reference on the second page, and the link broken across lines
+        // with an annotation per line.
+        let to_intro = pdf.matches(&format!("/S /GoTo /D {intro}")).count();
+        assert!(to_intro >= 3, "{to_intro}");
+        assert_eq!(pdf.matches("/Subtype /Link").count(), 2 + to_intro);
+        assert_eq!(dest_page(&pdf, &intro), Some(1));
+        assert_eq!(dest_page(&pdf, &usage), Some(2));
+        // The headings are the bookmarks.
+        assert!(pdf.contains("/Type /Outlines"));
+        assert!(pdf.contains("Introduction)"));
+        assert!(pdf.contains("Usage)"));
+
+        // The links to a page which is not exported are dropped.
+        let (vec_doc, metadata) = load_artifact_with_metadata(&artifact).unwrap();
+        let mut pages = artifact_pages(&vec_doc);
+        pages.truncate(1);
+        let first_page = VecDocument {
+            module: vec_doc.module,
+            pages,
+        };
+        let artifact = first_page.to_artifact_bytes_with(metadata);
+        let res = artifact_to_pdf_with_fonts(&artifact, world_fonts(&world)).unwrap();
+        assert_eq!(res.warnings, ["links to removed destinations are dropped"]);
+        let pdf = flat(&res.pdf);
+        assert_eq!(pdf.matches("/Subtype /Link").count(), 1);
+        assert_eq!(dest_page(&pdf, &usage), None);
+    }
+
+    /// Parse the objects of a PDF by its cross-reference table, checking that
+    /// each entry points to its object.
+    fn parse_objects(pdf: &[u8]) -> Vec<&[u8]> {
+        let find = |hay: &[u8], needle: &[u8]| hay.windows(needle.len()).position(|w| w == needle);
+        let start = find(pdf, b"startxref\n").unwrap() + b"startxref\n".len();
+        let xref = std::str::from_utf8(&pdf[start..]).unwrap();
+        let xref: usize = xref.lines().next().unwrap().parse().unwrap();
+
+        let mut table = std::str::from_utf8(&pdf[xref..]).unwrap().lines();
+        assert_eq!(table.next(), Some("xref"));
+        let count: usize = table.next().unwrap()[2..].parse().unwrap();
+        assert_eq!(table.next(), Some("0000000000 65535 f"));
+        (1..count)
+            .map(|n| {
+                let offset: usize = table.next().unwrap()[..10].parse().unwrap();
+                let object = &pdf[offset..];
+                assert!(object.starts_with(format!("{n} 0 obj\n").as_bytes()));
+                &object[..find(object, b"\nendobj\n").unwrap()]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_shared_images() {
+        let mut logo = vec![];
+        // Noise, which is hardly compressed.
+        let pixels = image::RgbImage::from_fn(64, 32, |x, y| {
+            let noise = (x * 64 + y).wrapping_mul(2654435761);
+            image::Rgb([(noise >> 24) as u8, (noise >> 16) as u8, (noise >> 8) as u8])
+        });
+        (pixels.write_to(&mut std::io::Cursor::new(&mut logo), ImageFormat::Png)).unwrap();
+
+        // The logo is in the header of each page, smaller on the last page and
+        // with a different alt text.
+        let mut module = Module::default();
+        for (idx, width, alt) in [(1, 64., None), (2, 32., Some("Logo".into()))] {
+            let image = Arc::new(Image {
+                data: logo.clone(),
+                format: "png".into(),
+                size: Axes::new(64, 32),
+                alt,
+                hash: fg(idx),
+            });
+            let size = Size::new(Scalar(width), Scalar(width / 2.));
+            (module.items).insert(fg(10 + idx), VecItem::Image(ImageItem { image, size }));
+        }
+        let mut pages = vec![];
+        for k in 0..3 {
+            let logo = if k == 2 { fg(12) } else { fg(11) };
+            let children = [(Point::new(Scalar(10.), Scalar(10.)), logo)];
+            (module.items).insert(fg(100 + k), VecItem::Group(GroupRef(children.into())));
+            pages.push(Page {
+                content: fg(100 + k),
+                size: Size::new(Scalar(200.), Scalar(300.)),
+            });
+        }
+        let artifact = VecDocument { module, pages }.to_artifact_bytes_with(vec![]);
+
+        let res = artifact_to_pdf(&artifact).unwrap();
+        assert!(res.warnings.is_empty(), "{:?}", res.warnings);
+        let objects = parse_objects(&res.pdf);
+        let images: Vec<_> = (objects.iter().enumerate())
+            .filter(|(_, object)| flat(object).contains("/Subtype /Image"))
+            .map(|(idx, object)| (idx + 1, object.len()))
+            .collect();
+        assert_eq!(images.len(), 1);
+        let (image, image_len) = images[0];
+
+        // Every page draws the single image of the shared resources.
+        let xobjects = format!("/XObject << /Im0 {image} 0 R >>");
+        assert!(objects
+            .iter()
+            .any(|object| flat(object).contains(&xobjects)));
+        let pages: Vec<_> = (objects.iter().map(|object| flat(object)))
+            .filter(|object| object.contains("/Type /Page /"))
+            .collect();
+        assert_eq!(pages.len(), 3);
+        for page in pages {
+            let contents = page.split("/Contents ").nth(1).unwrap();
+            let contents: usize = contents.split(' ').next().unwrap().parse().unwrap();
+            let stream = objects[contents - 1];
+            let start = stream.windows(7).position(|w| w == b"stream\n").unwrap() + 7;
+            let end = stream.len() - b"\nendstream".len();
+            let mut content = String::new();
+            let mut decoder = flate2::read::ZlibDecoder::new(&stream[start..end]);
+            std::io::Read::read_to_string(&mut decoder, &mut content).unwrap();
+            assert!(content.contains("/Im0 Do"), "{content}");
+        }
+
+        // The image is embedded once rather than per page.
+        assert!(res.pdf.len() < 2 * image_len, "{}", res.pdf.len());
+    }
+
+    /// Rasterize a PDF by `pdftoppm` at 96 DPI, returning the size and the
+    /// RGB pixels of its first page, or `None` if poppler is not installed.
+    fn rasterize(pdf: &[u8], name: &str) -> Option<(usize, usize, Vec<u8>)> {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let input = dir.join(format!("{name}.pdf"));
+        std::fs::write(&input, pdf).unwrap();
+        let output = dir.join(name);
+        let status = std::process::Command::new("pdftoppm")
+            .args(["-r", "96", "-singlefile"])
+            .arg(&input)
+            .arg(&output)
+            .status();
+        let ppm = status.ok().map(|status| {
+            assert!(status.success(), "pdftoppm failed on {name}.pdf");
+            std::fs::read(output.with_extension("ppm")).unwrap()
+        });
+
+        // A binary PPM, i.e. `P6 <width> <height> 255` followed by the pixels.
+        let ppm = ppm?;
+        let mut fields = ppm.splitn(5, u8::is_ascii_whitespace);
+        assert_eq!(fields.next(), Some(&b"P6"[..]));
+        let mut number = || -> usize {
+            let field = fields.next().unwrap();
+            std::str::from_utf8(field).unwrap().parse().unwrap()
+        };
+        let (width, height, _) = (number(), number(), number());
+        let pixels = fields.next().unwrap().to_vec();
+        assert_eq!(pixels.len(), width * height * 3);
+        Some((width, height, pixels))
+    }
+
+    #[test]
+    fn test_fidelity_to_typst_pdf() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fixtures/pdf");
+        let mut world = TypstSystemWorld::new(CompileOpts {
+            entry: EntryOpts::new_rooted(root.canonicalize().unwrap(), Some("fidelity.typ".into())),
+            no_system_fonts: true,
+            with_embedded_fonts: typst_assets::fonts().map(Cow::Borrowed).collect(),
+            ..CompileOpts::default()
+        })
+        .unwrap();
+        world.prepare_env(&mut CompileEnv::default()).unwrap();
+        let doc = typst::compile(&world, &mut Tracer::new()).unwrap();
+
+        let artifact = vector_artifact(&doc);
+        let res = artifact_to_pdf_with_fonts(&artifact, world_fonts(&world)).unwrap();
+        assert!(res.warnings.is_empty(), "{:?}", res.warnings);
+        let pdf = flat(&res.pdf);
+        // The TrueType and the CFF fonts of the fixture are embedded.
+        assert!(!pdf.contains("/Subtype /Type3"));
+        assert!(pdf.contains("/FontFile2"));
+        assert!(pdf.contains("/FontFile3"));
+        for ty in [2, 3, 4] {
+            assert!(pdf.contains(&format!("/ShadingType {ty}")), "{ty}");
+        }
+
+        let expected = typst_pdf::pdf(&doc, Smart::Auto, None);
+        let (Some(expected), Some(actual)) = (
+            rasterize(&expected, "typst"),
+            rasterize(&res.pdf, "artifact"),
+        ) else {
+            eprintln!("skipping the raster comparison, pdftoppm is not installed");
+            return;
+        };
+        assert_eq!((expected.0, expected.1), (actual.0, actual.1));
+
+        // The rasters differ only by anti-aliasing.
+        let diffs: Vec<_> = (expected.2.iter().zip(&actual.2))
+            .map(|(a, b)| a.abs_diff(*b))
+            .collect();
+        let mean = diffs.iter().map(|&d| d as f64).sum::<f64>() / diffs.len() as f64;
+        let pixels = diffs.len() / 3;
+        let differing = (diffs.chunks(3))
+            .filter(|pixel| pixel.iter().any(|&d| d > 64))
+            .count();
+        assert!(mean < 2., "mean difference {mean}");
+        assert!(
+            differing * 100 < pixels,
+            "{differing} of {pixels} pixels differ"
+        );
+    }
+
+    #[test]
+    fn test_parse_path() {
+        let segments = parse_path("M1 2l3-4Q 5,6 7,8 z m.5.5 1e1 0").unwrap();
+        assert_eq!(
+            segments,
+            [
+                Segment::Move(1., 2.),
+                Segment::Line(4., -2.),
+                Segment::Cubic([
+                    4. + 2. / 3. * 1.,
+                    -2. + 2. / 3. * 8.,
+                    7. + 2. / 3. * -2.,
+                    8. + 2. / 3. * -2.,
+                    7.,
+                    8.
+                ]),
+                Segment::Close,
+                Segment::Move(1.5, 2.5),
+                Segment::Line(11.5, 2.5),
+            ]
+        );
+        assert_eq!(parse_path("M 0 0 A 1 1 0 0 0 2 2"), None);
+    }
+}
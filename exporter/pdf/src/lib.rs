@@ -1,3 +1,5 @@
+pub mod artifact;
+
 use std::sync::Arc;
 
 pub use typst_pdf::pdf;
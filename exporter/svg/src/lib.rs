@@ -7,6 +7,9 @@ use std::sync::Arc;
 
 use typst::{diag::SourceResult, World};
 
+use typst_ts_core::error::prelude::*;
+use typst_ts_core::vector::artifact::{artifact_pages, load_artifact};
+use typst_ts_core::vector::{lazy::LazyArtifact, paged::is_paged_artifact};
 use typst_ts_core::Exporter;
 use typst_ts_core::TypstDocument;
 
@@ -143,6 +146,41 @@ pub fn render_svg(output: &TypstDocument) -> String {
     generate_text(transform::minify(svg_text))
 }
 
+/// Render SVG for each page of [`TypstDocument`].
+///
+/// The pages share the module of the document, hence every SVG embeds the
+/// same glyph definitions, i.e. the glyphs of the whole document.
+pub fn render_svg_pages(output: &TypstDocument) -> Vec<String> {
+    type UsingExporter = SvgExporter<SvgExportFeature>;
+    let mut doc = UsingExporter::svg_doc(output);
+    doc.module.prepare_glyphs();
+    (doc.pages.iter())
+        .map(|page| {
+            let svg_text = UsingExporter::render(&doc.module, std::slice::from_ref(page), None);
+            generate_text(transform::minify(svg_text))
+        })
+        .collect()
+}
+
+/// Render SVG for a single page of an artifact.
+///
+/// Only the page and the resources it depends on are decoded from a paged
+/// artifact, while a monolithic artifact is decoded as a whole.
+pub fn render_artifact_page(artifact: &[u8], page: usize) -> ZResult<String> {
+    type UsingExporter = SvgExporter<SvgExportFeature>;
+    let (module, page) = if is_paged_artifact(artifact) {
+        LazyArtifact::open(artifact)?.page_module(page)?
+    } else {
+        let doc = load_artifact(artifact)?;
+        let pages = artifact_pages(&doc);
+        let page = (pages.get(page).cloned())
+            .ok_or_else(|| error_once!("artifact: page out of range", page: page))?;
+        (doc.module, page)
+    };
+    let svg_text = UsingExporter::render(&module, std::slice::from_ref(&page), None);
+    Ok(generate_text(transform::minify(svg_text)))
+}
+
 impl<Feat: ExportFeature> Exporter<TypstDocument, String> for SvgExporter<Feat> {
     fn export(&self, _world: &dyn World, output: Arc<TypstDocument>) -> SourceResult<String> {
         // html wrap
@@ -161,11 +199,27 @@ impl Exporter<TypstDocument, String> for PureSvgExporter {
 }
 
 #[derive(Default)]
-pub struct SvgModuleExporter {}
+pub struct SvgModuleExporter {
+    paged: bool,
+}
+
+impl SvgModuleExporter {
+    /// Write the module in the page-wise format, see
+    /// [`typst_ts_core::vector::paged`].
+    pub fn with_paged(mut self, enable: bool) -> Self {
+        self.paged = enable;
+        self
+    }
+}
 
 impl Exporter<TypstDocument, Vec<u8>> for SvgModuleExporter {
     fn export(&self, _world: &dyn World, output: Arc<TypstDocument>) -> SourceResult<Vec<u8>> {
         type UsingExporter = SvgExporter<DefaultExportFeature>;
-        Ok(UsingExporter::svg_doc(&output).to_bytes())
+        let doc = UsingExporter::svg_doc(&output);
+        if self.paged {
+            // Writing into memory never fails.
+            return Ok(doc.to_paged_bytes().unwrap());
+        }
+        Ok(doc.to_bytes())
     }
 }
@@ -3,8 +3,17 @@ use std::io::Write;
 use std::sync::Arc;
 
 use typst_ts_core::exporter_utils::map_err;
+use typst_ts_core::segment::{reflow_lines, text_runs};
 use typst_ts_core::{Transformer, TypstDocument};
 
+/// Export the text of a document line by line, where the pages are separated
+/// by form feeds, see [`typst_ts_core::segment`].
+///
+/// Note: the exporter used to write the texts and the content hints of the
+/// pages as painted, without separators. The lines are now reflowed, i.e. a
+/// line ends with a newline, the words hyphenated at the ends of lines are
+/// rejoined and the soft hyphens are dropped, while the content hints are
+/// kept within the lines.
 #[derive(Debug, Clone, Default)]
 pub struct TextExporter {}
 
@@ -28,32 +37,15 @@ where
 
 struct FullTextDigest(Arc<TypstDocument>);
 
-impl FullTextDigest {
-    fn export_frame(f: &mut fmt::Formatter<'_>, doc: &typst::layout::Frame) -> fmt::Result {
-        for (_, item) in doc.items() {
-            Self::export_item(f, item)?;
-        }
-
-        Ok(())
-    }
-
-    fn export_item(f: &mut fmt::Formatter<'_>, item: &typst::layout::FrameItem) -> fmt::Result {
-        use std::fmt::Write;
-        use typst::introspection::Meta::*;
-        use typst::layout::FrameItem::*;
-        match item {
-            Group(g) => Self::export_frame(f, &g.frame),
-            Text(t) => f.write_str(t.text.as_str()),
-            Meta(ContentHint(c), _) => f.write_char(*c),
-            Meta(Link(..) | Elem(..) | Hide, _) | Shape(..) | Image(..) => Ok(()),
-        }
-    }
-}
-
 impl fmt::Display for FullTextDigest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for page in self.0.pages.iter() {
-            Self::export_frame(f, &page.frame)?;
+        let mut page = None;
+        for line in reflow_lines(&text_runs(&self.0)) {
+            if page.is_some_and(|page| page != line.page) {
+                f.write_str("\u{c}")?;
+            }
+            writeln!(f, "{}", line.text)?;
+            page = Some(line.page);
         }
         Ok(())
     }
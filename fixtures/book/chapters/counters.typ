@@ -0,0 +1,8 @@
+= Counters <counters>
+
+#for i in range(10) [
+  #figure(rect(width: 60%, height: 2em), caption: [Figure #(i + 1)])
+  #lorem(40)
+]
+
+There are #context counter(figure).final().first() figures in total.
@@ -0,0 +1,9 @@
+= Introduction <intro>
+
+#lorem(300)
+
+== Motivation
+
+#lorem(200)
+
+See @counters for the numbered figures.
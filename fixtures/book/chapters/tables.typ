@@ -0,0 +1,8 @@
+= Tables
+
+#table(
+  columns: 4,
+  ..range(80).map(i => [#i])
+)
+
+#lorem(200)
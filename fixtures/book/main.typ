@@ -0,0 +1,11 @@
+// A small book measured by `cargo run -p typst-ts-compiler --bin bench`.
+
+#set document(title: "Bench Book")
+#set page(numbering: "1")
+#set heading(numbering: "1.1")
+
+#outline()
+
+#include "chapters/intro.typ"
+#include "chapters/counters.typ"
+#include "chapters/tables.typ"
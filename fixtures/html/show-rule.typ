@@ -0,0 +1,7 @@
+#show heading: it => [Chapter: #it.body]
+#show strong: it => [#it!]
+#show emph: it => strong(it.body)
+
+= Overview
+
+This is _important_ and *bold*.
@@ -0,0 +1,15 @@
+#set heading(numbering: "1.")
+
+= Introduction <intro>
+
+Some *strong* and _emphasized_ text with a #link("https://typst.app")[link].
+
+- First
+- Second
+
++ One
++ Two
+
+#figure(rect(width: 1cm, height: 1cm), caption: [A square])
+
+See @intro for $x^2$.
@@ -0,0 +1,15 @@
+#set page(width: 200pt, height: 200pt, margin: 12pt)
+#set text(font: "Libertinus Serif", size: 10pt)
+#set heading(numbering: "1.")
+
+= Introduction <sec-intro>
+
+@sec-usage describes the usage of #link("https://typst.app")[Typst].
+
+#pagebreak()
+
+= Usage <sec-usage>
+
+Back to @sec-intro.
+
+#link(<sec-intro>)[This link to the introduction is long enough to be broken across lines.]
@@ -0,0 +1,21 @@
+#set page(width: 240pt, height: 200pt, margin: 12pt)
+#set text(font: "Linux Libertine", size: 11pt)
+
+= Fidelity
+
+Text in _Linux Libertine_, which has TrueType outlines.
+
+#text(font: "New Computer Modern")[Text in New Computer Modern, which has CFF outlines.]
+
+#text(font: "DejaVu Sans Mono")[fn main() {}]
+
+#stack(
+  dir: ltr,
+  spacing: 8pt,
+  rect(width: 40pt, height: 30pt, fill: gradient.linear(red, blue, angle: 30deg)),
+  rect(width: 40pt, height: 30pt, fill: gradient.radial(yellow, green, space: oklab)),
+  rect(width: 40pt, height: 30pt, fill: gradient.conic(red, yellow, blue, red)),
+  rect(width: 40pt, height: 30pt, stroke: 2pt + purple),
+)
+
+#text(size: 16pt, fill: gradient.linear(red, blue))[*Gradient text*]
@@ -10,7 +10,10 @@ use typst_ts_compiler::{
     font::web::BrowserFontSearcher,
     package::browser::ProxyRegistry,
     parser::OffsetEncoding,
-    service::{CompileDriverImpl, Compiler},
+    service::{
+        fonts::{font_coverage, list_fonts},
+        CompileDriverImpl, Compiler,
+    },
     vfs::browser::ProxyAccessModel,
     world::WorldSnapshot,
 };
@@ -19,8 +22,8 @@ use typst_ts_core::{
     diag::SourceDiagnostic,
     error::{long_diag_from_std, prelude::*, DiagMessage},
     typst::{self, foundations::IntoValue, prelude::EcoVec},
-    DynExporter, Exporter, FontLoader, FontSlot, TypstDocument, TypstFileId, TypstFont, TypstFrame,
-    TypstTransform, TypstWorld,
+    DynExporter, Exporter, FontLoader, FontResolver, FontSlot, TypstDocument, TypstFileId,
+    TypstFont, TypstFrame, TypstTransform, TypstWorld,
 };
 use wasm_bindgen::prelude::*;
 
@@ -272,6 +275,21 @@ impl TypstCompiler {
         }
     }
 
+    /// List the font families known to the compiler, see
+    /// [`typst_ts_compiler::service::fonts::list_fonts`].
+    pub fn list_fonts(&mut self) -> Result<JsValue, JsValue> {
+        let fonts = list_fonts(&self.compiler.world().font_resolver);
+        Ok(serde_wasm_bindgen::to_value(&fonts)?)
+    }
+
+    /// Look up the characters of a text which a font family doesn't cover,
+    /// see [`typst_ts_compiler::service::fonts::font_coverage`].
+    pub fn font_coverage(&mut self, family: String, text: String) -> Result<JsValue, JsValue> {
+        let book = self.compiler.world().font_resolver.font_book();
+        let coverage = font_coverage(book, &family, &text).map_err(|e| format!("{e:?}"))?;
+        Ok(serde_wasm_bindgen::to_value(&coverage)?)
+    }
+
     pub fn get_loaded_fonts(&mut self) -> Vec<JsString> {
         self.compiler
             .world_mut()
@@ -171,6 +171,7 @@ impl TypstRenderer {
         match action {
             "reset" => session.reset_current(data),
             "merge" => session.merge_delta(data),
+            "stream" => session.feed_stream(data),
             _ => Err(error_once!("Renderer.UnsupportedAction", action: action)),
         }
     }
@@ -191,6 +192,14 @@ impl TypstRenderer {
         Err(error_once!("Renderer.UnsupportedDecoder", decoder: decoder))
     }
 
+    /// Get a size breakdown of an artifact, keeping the `top_n` largest
+    /// resources.
+    pub fn artifact_stats(&self, artifact_content: &[u8], top_n: usize) -> ZResult<JsValue> {
+        let stats = typst_ts_core::vector::stats::stats(artifact_content, top_n)?;
+        serde_wasm_bindgen::to_value(&stats)
+            .map_err(map_into_err::<JsValue, _>("Renderer.EncodeArtifactStats"))
+    }
+
     fn session_from_vector_artifact(&self, artifact_content: &[u8]) -> ZResult<RenderSession> {
         let mut session = RenderSession::default();
         session.reset_current(artifact_content)?;
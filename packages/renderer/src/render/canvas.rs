@@ -10,10 +10,14 @@ use typst_ts_core::{
     vector::ir::{Axes, LayoutRegionNode, Rect, Scalar},
     TextContent,
 };
-use wasm_bindgen::prelude::*;
+use wasm_bindgen::{prelude::*, JsCast};
 
 use crate::{RenderPageImageOptions, RenderSession, TypstRenderer};
 
+/// The time in milliseconds to render thumbnails before yielding to the event
+/// loop, which is about a frame.
+const THUMBNAIL_FRAME_BUDGET: f64 = 16.;
+
 #[derive(Default)]
 pub struct CanvasDataSelection {
     pub body: bool,
@@ -43,12 +47,95 @@ impl TypstRenderer {
         err.map_err(map_into_err::<JsValue, _>("Renderer.SetAnnotationContent"))?;
         Ok(res.into())
     }
+
+    /// Render thumbnails of the pages of a session, where the longer side of
+    /// each page fits into `max_dim` pixels.
+    ///
+    /// `on_thumbnail` is called with the page offset and a new canvas holding
+    /// the thumbnail, for each page in `pages` or all the pages if it is
+    /// absent. The rendering yields to the event loop whenever it has taken
+    /// a frame, so that a long document doesn't block the main thread.
+    pub async fn render_thumbnails(
+        &mut self,
+        ses: &RenderSession,
+        max_dim: u32,
+        pages: Option<Vec<usize>>,
+        on_thumbnail: js_sys::Function,
+    ) -> ZResult<()> {
+        let document = web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or_else(|| error_once!("Renderer.MissingDocument"))?;
+        let rect = Rect {
+            lo: Axes::new(Scalar(-1.), Scalar(-1.)),
+            hi: Axes::new(Scalar(1e30), Scalar(1e30)),
+        };
+
+        let pages = pages.unwrap_or_else(|| (0..ses.pages_info.page_count()).collect());
+        let mut frame_start = js_sys::Date::now();
+        for page_off in pages {
+            let info = (ses.pages_info.pages.get(page_off))
+                .ok_or_else(|| error_once!("Renderer.PageNotFound", page_off: page_off))?;
+            let longer = info.width.max(info.height);
+            let pixel_per_pt = match longer {
+                longer if longer > 0. => max_dim.max(1) as f64 / longer,
+                _ => 1.,
+            };
+
+            let canvas = document
+                .create_element("canvas")
+                .map_err(map_into_err::<JsValue, _>("Renderer.CreateThumbnailCanvas"))?
+                .unchecked_into::<web_sys::HtmlCanvasElement>();
+            canvas.set_width((info.width * pixel_per_pt).round().max(1.) as u32);
+            canvas.set_height((info.height * pixel_per_pt).round().max(1.) as u32);
+            let context = canvas
+                .get_context("2d")
+                .map_err(map_into_err::<JsValue, _>("Renderer.GetThumbnailContext"))?
+                .ok_or_else(|| error_once!("Renderer.MissingThumbnailContext"))?
+                .unchecked_into::<web_sys::CanvasRenderingContext2d>();
+
+            // The page is flushed after releasing the locks, so that the
+            // session can be used while the thumbnails are rendered.
+            let flush = {
+                let mut kern = ses.client.lock().unwrap();
+                let mut client = ses.canvas_kern.lock().unwrap();
+                client.set_pixel_per_pt(pixel_per_pt as f32);
+                client.set_fill(ses.background_color.as_deref().unwrap_or("ffffff").into());
+                client.prepare_page_in_window(&mut kern, page_off, rect)?
+            };
+            flush.flush(&context).await;
+
+            on_thumbnail
+                .call2(&JsValue::NULL, &(page_off as u32).into(), &canvas)
+                .map_err(map_into_err::<JsValue, _>("Renderer.ThumbnailCallback"))?;
+
+            if js_sys::Date::now() - frame_start > THUMBNAIL_FRAME_BUDGET {
+                yield_to_event_loop().await?;
+                frame_start = js_sys::Date::now();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Wait for a task of the event loop, which lets the browser paint a frame.
+async fn yield_to_event_loop() -> ZResult<()> {
+    let mut scheduled = Ok(0);
+    let timeout = js_sys::Promise::new(&mut |resolve, _| {
+        scheduled = web_sys::window()
+            .ok_or_else(|| JsValue::from_str("no window"))
+            .and_then(|window| window.set_timeout_with_callback(&resolve));
+    });
+    scheduled.map_err(map_into_err::<JsValue, _>("Renderer.ScheduleThumbnails"))?;
+    wasm_bindgen_futures::JsFuture::from(timeout)
+        .await
+        .map_err(map_into_err::<JsValue, _>("Renderer.ScheduleThumbnails"))?;
+    Ok(())
 }
 
 impl TypstRenderer {
-    #[allow(clippy::await_holding_lock)]
     pub async fn render_page_to_canvas_internal<Feat: ExportFeature>(
-        &mut self,
+        &self,
         ses: &RenderSession,
         canvas: Option<web_sys::CanvasRenderingContext2d>,
         options: Option<RenderPageImageOptions>,
@@ -62,11 +149,6 @@ impl TypstRenderer {
             hi: Axes::new(Scalar(rect_hi_x), Scalar(rect_hi_y)),
         };
 
-        let mut kern = ses.client.lock().unwrap();
-        let mut client = ses.canvas_kern.lock().unwrap();
-        client.set_pixel_per_pt(ses.pixel_per_pt.unwrap_or(3.));
-        client.set_fill(ses.background_color.as_deref().unwrap_or("ffffff").into());
-
         let data_selection = options
             .as_ref()
             .and_then(|o| o.data_selection)
@@ -94,83 +176,100 @@ impl TypstRenderer {
         //     worker.set_perf_events(perf_events)
         // };
 
-        // todo: reuse
-        let Some(t) = &kern.layout else {
-            todo!();
-        };
-        let pages = t.pages(kern.module()).unwrap().pages();
-
-        let (page_num, fingerprint) = if let Some(RenderPageImageOptions {
-            page_off: Some(c),
-            ..
-        }) = options
-        {
-            (Some(c), pages[c].content)
-        } else {
-            let mut f = FingerprintSipHasher::default();
-            for page in pages.iter() {
-                page.content.hash(&mut f);
-            }
-            (None, f.finish_fingerprint().0)
-        };
+        // The pages are flushed after releasing the locks, so that the
+        // session isn't locked while awaiting.
+        let (fingerprint, flush) = {
+            let mut kern = ses.client.lock().unwrap();
+            let mut client = ses.canvas_kern.lock().unwrap();
+            client.set_pixel_per_pt(ses.pixel_per_pt.unwrap_or(3.));
+            client.set_fill(ses.background_color.as_deref().unwrap_or("ffffff").into());
+
+            // todo: reuse
+            let Some(t) = &kern.layout else {
+                todo!();
+            };
+            let pages = t.pages(kern.module()).unwrap().pages();
+
+            let (page_num, fingerprint) = if let Some(RenderPageImageOptions {
+                page_off: Some(c),
+                ..
+            }) = options
+            {
+                (Some(c), pages[c].content)
+            } else {
+                let mut f = FingerprintSipHasher::default();
+                for page in pages.iter() {
+                    page.content.hash(&mut f);
+                }
+                (None, f.finish_fingerprint().0)
+            };
 
-        if should_render_body {
-            let cached = options
-                .and_then(|o| o.cache_key)
-                .map(|c| c == fingerprint.as_svg_id("c"))
-                .unwrap_or(false);
-
-            let canvas = &canvas.ok_or_else(|| error_once!("Renderer.MissingCanvasForBody"))?;
-
-            if !cached {
-                if let Some(page_num) = page_num {
-                    client
-                        .render_page_in_window(&mut kern, canvas, page_num, rect)
-                        .await?;
-                } else {
-                    client.render_in_window(&mut kern, canvas, rect).await;
+            let mut flush = None;
+            if should_render_body {
+                let cached = options
+                    .and_then(|o| o.cache_key)
+                    .map(|c| c == fingerprint.as_svg_id("c"))
+                    .unwrap_or(false);
+
+                let canvas = canvas.ok_or_else(|| error_once!("Renderer.MissingCanvasForBody"))?;
+
+                if !cached {
+                    let pages = if let Some(page_num) = page_num {
+                        vec![client.prepare_page_in_window(&mut kern, page_num, rect)?]
+                    } else {
+                        client.prepare_in_window(&mut kern, rect)
+                    };
+                    flush = Some((canvas, pages));
                 }
             }
-        }
 
-        // todo: leaking abstraction
-        let mut worker = tc
-            .as_mut()
-            .map(|tc| TextContentTask::new(&kern.doc.module, tc));
-        let mut annotation_list_worker = annotations
-            .as_mut()
-            .map(|annotations| AnnotationListTask::new(&kern.doc.module, annotations));
-        // todo: reuse
-        if let Some(t) = &kern.layout {
-            let pages = match t {
-                LayoutRegionNode::Pages(a) => {
-                    let (_, pages) = a.deref();
-                    pages
-                }
-                _ => todo!(),
-            };
-            let mut page_off = 0.;
-            for (idx, page) in pages.iter().enumerate() {
-                if page_num.map_or(false, |p| p != idx) {
+            // todo: leaking abstraction
+            let mut worker = tc
+                .as_mut()
+                .map(|tc| TextContentTask::new(&kern.doc.module, tc));
+            let mut annotation_list_worker = annotations
+                .as_mut()
+                .map(|annotations| AnnotationListTask::new(&kern.doc.module, annotations));
+            // todo: reuse
+            if let Some(t) = &kern.layout {
+                let pages = match t {
+                    LayoutRegionNode::Pages(a) => {
+                        let (_, pages) = a.deref();
+                        pages
+                    }
+                    _ => todo!(),
+                };
+                let mut page_off = 0.;
+                for (idx, page) in pages.iter().enumerate() {
+                    if page_num.is_some_and(|p| p != idx) {
+                        page_off += page.size.y.0;
+                        continue;
+                    }
+                    let partial_page_off = if page_num.is_some() { 0. } else { page_off };
+                    if let Some(worker) = worker.as_mut() {
+                        worker.page_height = partial_page_off + page.size.y.0;
+                        worker.process_flat_item(
+                            tiny_skia::Transform::from_translate(partial_page_off, 0.),
+                            &page.content,
+                        );
+                    }
+                    if let Some(worker) = annotation_list_worker.as_mut() {
+                        worker.page_num = idx as u32;
+                        worker.process_flat_item(
+                            tiny_skia::Transform::from_translate(partial_page_off, 0.),
+                            &page.content,
+                        );
+                    }
                     page_off += page.size.y.0;
-                    continue;
                 }
-                let partial_page_off = if page_num.is_some() { 0. } else { page_off };
-                if let Some(worker) = worker.as_mut() {
-                    worker.page_height = partial_page_off + page.size.y.0;
-                    worker.process_flat_item(
-                        tiny_skia::Transform::from_translate(partial_page_off, 0.),
-                        &page.content,
-                    );
-                }
-                if let Some(worker) = annotation_list_worker.as_mut() {
-                    worker.page_num = idx as u32;
-                    worker.process_flat_item(
-                        tiny_skia::Transform::from_translate(partial_page_off, 0.),
-                        &page.content,
-                    );
-                }
-                page_off += page.size.y.0;
+            }
+
+            (fingerprint, flush)
+        };
+
+        if let Some((canvas, pages)) = flush {
+            for page in pages {
+                page.flush(&canvas).await;
             }
         }
 
@@ -195,12 +294,9 @@ impl TypstRenderer {
 #[cfg(test)]
 #[cfg(target_arch = "wasm32")]
 mod tests {
-    #![allow(clippy::await_holding_lock)]
-
-    use std::{collections::HashMap, sync::Mutex};
+    use std::{collections::HashMap, rc::Rc};
 
     use reflexo_vec2canvas::ExportFeature;
-    use send_wrapper::SendWrapper;
     use serde::{Deserialize, Serialize};
     use sha2::Digest;
     // use typst_ts_test_common::std_artifact::STD_TEST_FILES;
@@ -237,8 +333,15 @@ mod tests {
         const SHOULD_RENDER_TEXT_ELEMENT: bool = true;
     }
 
-    static RENDERER: Mutex<once_cell::sync::OnceCell<SendWrapper<Mutex<TypstRenderer>>>> =
-        Mutex::new(once_cell::sync::OnceCell::new());
+    thread_local! {
+        /// The renderer shared by the tests, which run on the main thread of
+        /// the browser.
+        static RENDERER: Rc<TypstRenderer> = Rc::new(crate::tests::get_renderer());
+    }
+
+    fn renderer() -> Rc<TypstRenderer> {
+        RENDERER.with(Rc::clone)
+    }
 
     async fn render_test_template(point: &str, artifact: &[u8], format: &str) {
         let window = web_sys::window().expect("should have a window in this context");
@@ -257,10 +360,7 @@ mod tests {
         let (time_used, perf_events, data_content_hash, ..) = {
             let create = performance.now();
 
-            let renderer = RENDERER.lock().unwrap();
-            let renderer =
-                renderer.get_or_init(|| SendWrapper::new(Mutex::new(crate::tests::get_renderer())));
-            let renderer = &mut renderer.lock().unwrap();
+            let renderer = renderer();
 
             let start = performance.now();
             let mut session = renderer
@@ -502,4 +602,155 @@ mod tests {
     //     // "text/emoji_00",
     //     "text/emoji_01"
     // );
+
+    /// Render the pages of a session to a new canvas and hash the image.
+    async fn render_session_hash(
+        renderer: &TypstRenderer,
+        session: &mut crate::RenderSession,
+    ) -> String {
+        let canvas = web_sys::window()
+            .unwrap()
+            .document()
+            .unwrap()
+            .create_element("canvas")
+            .unwrap()
+            .dyn_into::<web_sys::HtmlCanvasElement>()
+            .unwrap();
+        session.set_background_color("#ffffff".to_string());
+        session.set_pixel_per_pt(3.);
+        let sizes = &session.pages_info;
+        canvas.set_width((sizes.width() * 3.).ceil() as u32);
+        canvas.set_height((sizes.height() * 3.).ceil() as u32);
+
+        let context = canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<web_sys::CanvasRenderingContext2d>()
+            .unwrap();
+        renderer
+            .render_page_to_canvas_internal::<CIRenderFeature>(session, Some(context), None)
+            .await
+            .unwrap();
+        hash_bytes(canvas.to_data_url_with_type("image/png").unwrap())
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_restore_session() {
+        let renderer = renderer();
+        let renderer = renderer.as_ref();
+
+        let artifact = get_ir_artifact("layout/transform_00").await;
+        let load = |renderer: &TypstRenderer| {
+            renderer
+                .create_session(Some(CreateSessionOptions {
+                    format: Some("vector".to_string()),
+                    artifact_content: Some(artifact.clone()),
+                }))
+                .unwrap()
+        };
+        let mut session = load(renderer);
+        let fresh = render_session_hash(renderer, &mut session).await;
+
+        // The blob is stored as a `Uint8Array`, e.g. in IndexedDB.
+        let snapshot = session.snapshot().unwrap();
+        let snapshot = js_sys::Uint8Array::from(snapshot.as_slice()).to_vec();
+        let hash = session.artifact_hash();
+        let mut restored = crate::RenderSession::restore(&snapshot, hash.clone()).unwrap();
+        assert_eq!(restored.artifact_hash(), session.artifact_hash());
+        assert_eq!(render_session_hash(renderer, &mut restored).await, fresh);
+
+        // The text index is restored along with the decoded state.
+        let text = session.page_text(1).unwrap();
+        assert!(!text.is_empty());
+        assert_eq!(restored.page_text(1), Some(text.clone()));
+        let query = text.split_whitespace().next().unwrap();
+        let stringify = |res: js_sys::Array| js_sys::JSON::stringify(&res).unwrap();
+        let found = stringify(session.search(query));
+        assert_ne!(found, "[]");
+        assert_eq!(stringify(restored.search(query)), found);
+        assert_eq!(stringify(restored.search(&query.to_uppercase())), found);
+
+        // Measure a fresh load against a restore, both including the first
+        // search.
+        const ROUNDS: u32 = 20;
+        let performance = web_sys::window().unwrap().performance().unwrap();
+        let start = performance.now();
+        for _ in 0..ROUNDS {
+            load(renderer).search(query);
+        }
+        let loaded = performance.now();
+        for _ in 0..ROUNDS {
+            crate::RenderSession::restore(&snapshot, hash.clone())
+                .unwrap()
+                .search(query);
+        }
+        let restored_time = performance.now() - loaded;
+        web_sys::console::log_1(
+            &format!(
+                "restore session: artifact {} bytes, snapshot {} bytes, load {:.3}ms, restore {:.3}ms",
+                artifact.len(),
+                snapshot.len(),
+                (loaded - start) / ROUNDS as f64,
+                restored_time / ROUNDS as f64,
+            )
+            .into(),
+        );
+
+        // A blob of another artifact is rejected.
+        let stale = crate::RenderSession::restore(&snapshot, Some("0".repeat(32)));
+        assert!(stale.is_err());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_stream_paged_artifact() {
+        use typst_ts_core::vector::{
+            artifact::{artifact_pages, load_artifact},
+            ir::VecDocument,
+        };
+
+        let renderer = renderer();
+        let renderer = renderer.as_ref();
+
+        let artifact = get_ir_artifact("layout/pagebreak-parity_00").await;
+        let load = |artifact: &[u8]| {
+            renderer
+                .create_session(Some(CreateSessionOptions {
+                    format: Some("vector".to_string()),
+                    artifact_content: Some(artifact.to_vec()),
+                }))
+                .unwrap()
+        };
+        let monolithic = render_session_hash(renderer, &mut load(&artifact)).await;
+
+        let doc = load_artifact(&artifact).unwrap();
+        let pages = artifact_pages(&doc);
+        let page_count = pages.len();
+        let module = doc.module;
+        let paged = VecDocument { module, pages }.to_paged_bytes().unwrap();
+
+        // The pages are ready in order as the chunks arrive.
+        let mut streamed = crate::RenderSession::default();
+        let mut ready = vec![];
+        for chunk in paged.chunks(64) {
+            streamed.feed_stream(chunk).unwrap();
+            ready.push(streamed.pages_info.page_count());
+        }
+        assert!(ready.windows(2).all(|w| w[0] <= w[1]), "{ready:?}");
+        assert!(
+            ready.iter().any(|n| (1..page_count).contains(n)),
+            "{ready:?}"
+        );
+        assert_eq!(ready.last(), Some(&page_count));
+        assert_eq!(
+            render_session_hash(renderer, &mut streamed).await,
+            monolithic
+        );
+
+        // A paged artifact is also loaded as a whole.
+        assert_eq!(
+            render_session_hash(renderer, &mut load(&paged)).await,
+            monolithic
+        );
+    }
 }
@@ -30,6 +30,16 @@ pub mod canvas_stub {
         ) -> ZResult<JsValue> {
             Err(error_once!("Renderer.CanvasFeatureNotEnabled"))
         }
+
+        pub async fn render_thumbnails(
+            &mut self,
+            _ses: &RenderSession,
+            _max_dim: u32,
+            _pages: Option<Vec<usize>>,
+            _on_thumbnail: js_sys::Function,
+        ) -> ZResult<()> {
+            Err(error_once!("Renderer.CanvasFeatureNotEnabled"))
+        }
     }
 }
 #[cfg(not(feature = "render_canvas"))]
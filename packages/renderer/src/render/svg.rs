@@ -67,6 +67,18 @@ impl TypstRenderer {
         Ok(svg)
     }
 
+    /// Render a single page of an artifact without creating a session.
+    ///
+    /// A paged artifact is only decoded for the page, see
+    /// [`typst_ts_svg_exporter::render_artifact_page`].
+    pub fn render_artifact_page_svg(
+        &self,
+        artifact_content: &[u8],
+        page: usize,
+    ) -> ZResult<String> {
+        typst_ts_svg_exporter::render_artifact_page(artifact_content, page)
+    }
+
     pub fn get_customs(&self, session: &RenderSession) -> Option<js_sys::Array> {
         let client = session.client.lock().unwrap();
         let layout = client.layout.clone();
@@ -4,9 +4,11 @@ use std::sync::{Arc, Mutex};
 use reflexo_vec2canvas::IncrCanvasDocClient;
 use typst_ts_core::{
     error::prelude::*,
+    hash::hash128,
     vector::{
         incr::IncrDocClient,
         ir::{Page, Scalar},
+        paged::{self, PagedDocument, PagedStreamDecoder},
     },
 };
 #[cfg(feature = "render_svg")]
@@ -157,6 +159,10 @@ impl PagesInfo {
     }
 }
 
+/// Magic of the snapshots of sessions, the last byte is the format version,
+/// see [`RenderSession::snapshot`].
+const SESSION_MAGIC: [u8; 8] = *b"tsrs\x00\x00\x00\x01";
+
 #[derive(Default)]
 #[wasm_bindgen]
 pub struct RenderSession {
@@ -171,6 +177,14 @@ pub struct RenderSession {
     /// stored pages info
     pub(crate) pages_info: PagesInfo,
 
+    /// hash of the artifact and the deltas merged since, see
+    /// [`RenderSession::artifact_hash`]
+    pub(crate) artifact_hash: Option<u128>,
+
+    /// the paged artifact being loaded chunk by chunk, see
+    /// [`RenderSession::feed_stream`]
+    pub(crate) stream: Option<PagedStream>,
+
     /// underlying communication client model
     pub(crate) client: Arc<Mutex<IncrDocClient>>,
     /// underlying incremental state of canvas rendering
@@ -207,6 +221,96 @@ impl RenderSession {
     pub fn pages_info(&self) -> PagesInfo {
         self.pages_info.clone()
     }
+
+    /// Get the hash of the artifact, chained with the deltas merged since, in
+    /// hex.
+    ///
+    /// Returns `undefined` if no artifact is loaded.
+    #[wasm_bindgen(getter)]
+    pub fn artifact_hash(&self) -> Option<String> {
+        self.artifact_hash.map(|hash| format!("{hash:032x}"))
+    }
+
+    /// Serialize the decoded state of the session into a binary blob, e.g. to
+    /// be stored in IndexedDB and restored by [`RenderSession::restore`]
+    /// after reloading the page.
+    ///
+    /// The blob contains the items, fonts, glyphs and layouts of the current
+    /// document along with its source mapping and text index, but not the
+    /// resources of canvases, which are recreated on rendering. Regardless of
+    /// the deltas merged so far, it is about as large as a full artifact of
+    /// the current document plus a header of 24 bytes, the text of the pages
+    /// and 4 bytes per used glyph.
+    pub fn snapshot(&self) -> ZResult<Vec<u8>> {
+        let hash = self
+            .artifact_hash
+            .ok_or_else(|| error_once!("Renderer.EmptySession"))?;
+        let state = self.client().snapshot();
+        Ok([&SESSION_MAGIC[..], &hash.to_le_bytes()[..], &state[..]].concat())
+    }
+
+    /// Restore a session from a blob of [`RenderSession::snapshot`], which
+    /// renders the same as the session taking the snapshot.
+    ///
+    /// The decoded state is restored as is, hence the glyphs are neither
+    /// validated nor distributed to the fonts, the metadata of the artifact
+    /// is not merged and the text index is not rebuilt, which a fresh load of
+    /// the artifact does. See `test_restore_session` for the timings.
+    ///
+    /// If `artifact_hash` is given, the blob is rejected unless it is taken
+    /// from a session of the same [`RenderSession::artifact_hash`], so that a
+    /// blob of a stale artifact is never rendered.
+    pub fn restore(snapshot: &[u8], artifact_hash: Option<String>) -> ZResult<RenderSession> {
+        let header_len = SESSION_MAGIC.len() + 16;
+        if snapshot.len() < header_len || snapshot[..SESSION_MAGIC.len()] != SESSION_MAGIC {
+            return Err(error_once!("Renderer.UnsupportedSession"));
+        }
+        let hash = &snapshot[SESSION_MAGIC.len()..header_len];
+        let hash = u128::from_le_bytes(hash.try_into().unwrap());
+        if let Some(expected) = artifact_hash {
+            let actual = format!("{hash:032x}");
+            if !expected.eq_ignore_ascii_case(&actual) {
+                return Err(
+                    error_once!("Renderer.StaleSession", expected: expected, actual: actual),
+                );
+            }
+        }
+
+        let mut client = IncrDocClient::restore(&snapshot[header_len..])?;
+        let mut session = RenderSession::default();
+        Self::checkout_pages(&mut session.pages_info, &mut client);
+        session.client = Arc::new(Mutex::new(client));
+        session.artifact_hash = Some(hash);
+        Ok(session)
+    }
+
+    /// Search the texts of the pages ignoring ASCII case, i.e. an array of
+    /// `{ page, offset }` where `page` is 1-based and `offset` is the offset
+    /// in chars in [`RenderSession::page_text`].
+    pub fn search(&self, query: &str) -> js_sys::Array {
+        let mut client = self.client();
+        let matches = client.text_index().search(query).into_iter();
+        let matches = matches.map(|(page, offset)| {
+            let obj = js_sys::Object::new();
+            let set = |key: &str, value: usize| {
+                let value = JsValue::from_f64(value as f64);
+                js_sys::Reflect::set(&obj, &JsValue::from_str(key), &value).unwrap();
+            };
+            set("page", page + 1);
+            set("offset", offset);
+            JsValue::from(obj)
+        });
+        matches.collect()
+    }
+
+    /// Get the text of the 1-based page, i.e. the contents of its texts in the
+    /// order of painting.
+    #[wasm_bindgen(js_name = pageText)]
+    pub fn page_text(&self, page: usize) -> Option<String> {
+        let mut client = self.client();
+        let text = client.text_index().pages.get(page.checked_sub(1)?)?;
+        Some(text.to_string())
+    }
 }
 
 #[wasm_bindgen]
@@ -229,7 +333,123 @@ impl RenderSession {
         self.client().kern().source_span(path)
     }
 
+    /// Get the geometry of the pages embedded in the artifact, i.e. an array
+    /// of `{ width, height, margins: { left, top, right, bottom },
+    /// differsFromDefault }` in pt.
+    ///
+    /// Returns `undefined` if the artifact doesn't embed it.
+    #[wasm_bindgen(js_name = pageMetadata)]
+    pub fn page_metadata(&self) -> Option<js_sys::Array> {
+        let set = |obj: &js_sys::Object, key: &str, value: JsValue| {
+            js_sys::Reflect::set(obj, &JsValue::from_str(key), &value).unwrap();
+        };
+        let scalar = |value: Scalar| JsValue::from_f64(value.0 as f64);
+
+        let client = self.client();
+        let kern = client.kern();
+        let pages = kern.page_meta()?.iter().map(|page| {
+            let margins = js_sys::Object::new();
+            set(&margins, "left", scalar(page.margins.left));
+            set(&margins, "top", scalar(page.margins.top));
+            set(&margins, "right", scalar(page.margins.right));
+            set(&margins, "bottom", scalar(page.margins.bottom));
+
+            let obj = js_sys::Object::new();
+            set(&obj, "width", scalar(page.size.x));
+            set(&obj, "height", scalar(page.size.y));
+            set(&obj, "margins", margins.into());
+            set(
+                &obj,
+                "differsFromDefault",
+                JsValue::from_bool(page.differs_from_default),
+            );
+            JsValue::from(obj)
+        });
+        Some(pages.collect())
+    }
+
+    /// Locate the heading of the anchor slug embedded in the artifact, e.g.
+    /// `sec-installation`, i.e. `{ page, y, offset }` where `page` is
+    /// 1-based, `y` is the top of the heading on the page and `offset` is the
+    /// top of the heading in the stacked pages, both in pt.
+    ///
+    /// Returns `undefined` if the artifact doesn't embed the outline or no
+    /// heading has the slug.
+    #[wasm_bindgen(js_name = scrollToAnchor)]
+    pub fn scroll_to_anchor(&self, slug: &str) -> Option<js_sys::Object> {
+        let client = self.client();
+        let kern = client.kern();
+        let outline = kern.outline()?;
+        let heading = outline.iter().find(|item| item.slug.as_ref() == slug)?;
+
+        let page = heading.page as usize;
+        let y = heading.y.0 as f64;
+        let above: f64 = (self.pages_info.pages.iter())
+            .filter(|info| info.page_off + 1 < page)
+            .map(|info| info.height)
+            .sum();
+
+        let obj = js_sys::Object::new();
+        let set = |key: &str, value: JsValue| {
+            js_sys::Reflect::set(&obj, &JsValue::from_str(key), &value).unwrap();
+        };
+        set("page", JsValue::from_f64(page as f64));
+        set("y", JsValue::from_f64(y));
+        set("offset", JsValue::from_f64(above + y));
+        Some(obj)
+    }
+
+    /// Get the metadata elements embedded in the artifact, e.g. the slides of
+    /// a presentation, i.e. an array of `{ value, label, page, y, offset,
+    /// source }` in the order of the pages, where `page` is 1-based, `y` is
+    /// the position on the page and `offset` is the position in the stacked
+    /// pages, both in pt. The `source` is `{ filepath, startLine,
+    /// startColumn, endLine, endColumn }` if the artifact embeds it.
+    ///
+    /// Returns `undefined` if the artifact doesn't embed the metadata.
+    #[wasm_bindgen(js_name = metadataAnchors)]
+    pub fn metadata_anchors(&self) -> Option<js_sys::Array> {
+        let set = |obj: &js_sys::Object, key: &str, value: JsValue| {
+            js_sys::Reflect::set(obj, &JsValue::from_str(key), &value).unwrap();
+        };
+        let num = |value: u32| JsValue::from_f64(value as f64);
+
+        let client = self.client();
+        let kern = client.kern();
+        let anchors = kern.metadata_anchors()?.iter().map(|anchor| {
+            let page = anchor.page as usize;
+            let y = anchor.y.0 as f64;
+            let above: f64 = (self.pages_info.pages.iter())
+                .filter(|info| info.page_off + 1 < page)
+                .map(|info| info.height)
+                .sum();
+
+            let obj = js_sys::Object::new();
+            let value = js_sys::JSON::parse(&anchor.value).unwrap_or(JsValue::NULL);
+            set(&obj, "value", value);
+            let label = anchor.label.as_deref().map(JsValue::from_str);
+            set(&obj, "label", label.unwrap_or(JsValue::NULL));
+            set(&obj, "page", num(anchor.page));
+            set(&obj, "y", JsValue::from_f64(y));
+            set(&obj, "offset", JsValue::from_f64(above + y));
+            let source = anchor.source.as_ref().map(|source| {
+                let obj = js_sys::Object::new();
+                set(&obj, "filepath", JsValue::from_str(&source.filepath));
+                set(&obj, "startLine", num(source.start_line));
+                set(&obj, "startColumn", num(source.start_column));
+                set(&obj, "endLine", num(source.end_line));
+                set(&obj, "endColumn", num(source.end_column));
+                JsValue::from(obj)
+            });
+            set(&obj, "source", source.unwrap_or(JsValue::NULL));
+            JsValue::from(obj)
+        });
+        Some(anchors.collect())
+    }
+
     pub(crate) fn reset(&mut self) {
+        self.artifact_hash = None;
+        self.stream = None;
         let mut client = self.client.lock().unwrap();
         *client = IncrDocClient::default();
         if cfg!(feature = "render_canvas") {
@@ -243,6 +463,17 @@ impl RenderSession {
     }
 
     pub(crate) fn reset_current(&mut self, delta: &[u8]) -> ZResult<()> {
+        if paged::is_paged_artifact(delta) {
+            self.reset();
+            self.feed_stream(delta)?;
+            if !self.stream.as_ref().is_some_and(PagedStream::is_finished) {
+                return Err(error_once!("Renderer.TruncatedArtifact"));
+            }
+            self.artifact_hash = Some(hash128(&delta));
+            return Ok(());
+        }
+
+        self.stream = None;
         let mut client = self.client.lock().unwrap();
         *client = IncrDocClient::default();
         if cfg!(feature = "render_canvas") {
@@ -253,12 +484,52 @@ impl RenderSession {
             let mut svg_kern = self.svg_kern.lock().unwrap();
             svg_kern.reset();
         }
-        Self::merge_delta_inner(&mut self.pages_info, &mut client, delta)
+        self.artifact_hash = None;
+        Self::merge_delta_inner(&mut self.pages_info, &mut client, delta)?;
+        self.artifact_hash = Some(hash128(&delta));
+        Ok(())
     }
 
     pub(crate) fn merge_delta(&mut self, delta: &[u8]) -> ZResult<()> {
         let mut client = self.client.lock().unwrap();
-        Self::merge_delta_inner(&mut self.pages_info, &mut client, delta)
+        Self::merge_delta_inner(&mut self.pages_info, &mut client, delta)?;
+        self.artifact_hash = Some(hash128(&(self.artifact_hash, delta)));
+        Ok(())
+    }
+
+    /// Feed the next chunk of a paged artifact, so that its pages are
+    /// rendered as soon as they arrive, see
+    /// [`typst_ts_core::vector::paged::PagedStreamDecoder`].
+    ///
+    /// The first chunk, or the first chunk after an artifact is complete,
+    /// starts a new artifact. The hash of the artifact is chained over the
+    /// chunks like the deltas, see [`RenderSession::artifact_hash`].
+    pub(crate) fn feed_stream(&mut self, chunk: &[u8]) -> ZResult<()> {
+        if self.stream.as_ref().is_none_or(PagedStream::is_finished) {
+            self.reset();
+            self.stream = Some(PagedStream::default());
+        }
+        let stream = self.stream.as_mut().unwrap();
+        let blocks = stream.decoder.feed(chunk)?;
+        self.artifact_hash = Some(hash128(&(self.artifact_hash, chunk)));
+        if blocks.is_empty() {
+            return Ok(());
+        }
+
+        let mut client = self.client.lock().unwrap();
+        // The client keeps the module between chunks, so that the ready pages
+        // are rendered from it.
+        std::mem::swap(&mut stream.doc.module, &mut client.doc.module);
+        let merged = (blocks.into_iter()).try_for_each(|block| stream.doc.merge_block(block));
+        std::mem::swap(&mut stream.doc.module, &mut client.doc.module);
+        merged?;
+
+        client.doc.layouts = stream.doc.ready_layouts();
+        if stream.is_finished() {
+            client.merge_metadata(std::mem::take(&mut stream.doc.metadata));
+        }
+        Self::checkout_pages(&mut self.pages_info, &mut client);
+        Ok(())
     }
 
     pub(crate) fn merge_delta_inner(
@@ -268,7 +539,7 @@ impl RenderSession {
     ) -> ZResult<()> {
         use typst_ts_core::vector::stream::BytesModuleStream;
 
-        let delta = BytesModuleStream::from_slice(delta).checkout_owned();
+        let delta = BytesModuleStream::from_slice(delta).try_checkout_owned()?;
         let _delta_ref = &delta;
 
         #[cfg(feature = "debug_delta_update")]
@@ -283,7 +554,12 @@ impl RenderSession {
             _delta_ref.gc_items().map(|s| s.len()),
         );
 
-        client.merge_delta(delta);
+        client.try_merge_delta(delta)?;
+        Self::checkout_pages(pages_info, client);
+        Ok(())
+    }
+
+    fn checkout_pages(pages_info: &mut PagesInfo, client: &mut IncrDocClient) {
         // checkout the current layout
         // todo: multiple layout
         let layouts = &client.doc.layouts[0];
@@ -314,6 +590,20 @@ impl RenderSession {
         };
 
         *pages_info = PagesInfo { pages };
-        Ok(())
+    }
+}
+
+/// A paged artifact being loaded, see [`RenderSession::feed_stream`].
+#[derive(Default)]
+pub(crate) struct PagedStream {
+    decoder: PagedStreamDecoder,
+    /// The pages and the metadata decoded so far, while the module is kept by
+    /// the client.
+    doc: PagedDocument,
+}
+
+impl PagedStream {
+    fn is_finished(&self) -> bool {
+        self.decoder.is_finished()
     }
 }
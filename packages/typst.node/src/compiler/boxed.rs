@@ -13,7 +13,7 @@ use typst_ts_core::{
     config::compiler::{EntryState, MEMORY_MAIN_ENTRY},
     error::{prelude::*, TypstFileError, TypstSourceDiagnostic},
     error_once,
-    foundations::Content,
+    foundations::{Content, Module},
     typst::prelude::*,
     Bytes, ImmutPath, TypstDocument, TypstFileId,
 };
@@ -159,6 +159,11 @@ impl Compiler for BoxedCompiler {
         self.0.pure_compile(env)
     }
 
+    #[inline]
+    fn pure_evaluate(&mut self, env: &mut CompileEnv) -> SourceResult<Module> {
+        self.0.pure_evaluate(env)
+    }
+
     #[inline]
     fn pure_query(
         &mut self,
@@ -173,6 +178,11 @@ impl Compiler for BoxedCompiler {
         self.0.compile(env)
     }
 
+    #[inline]
+    fn evaluate(&mut self, env: &mut CompileEnv) -> SourceResult<Module> {
+        self.0.evaluate(env)
+    }
+
     #[inline]
     fn query(&mut self, selector: String, document: &TypstDocument) -> SourceResult<Vec<Content>> {
         self.0.query(selector, document)
@@ -190,6 +200,16 @@ impl Compiler for BoxedCompiler {
     fn notify_fs_event(&mut self, event: typst_ts_compiler::vfs::notify::FilesystemEvent) {
         self.0.notify_fs_event(event)
     }
+
+    #[inline]
+    fn evict_files(&mut self, paths: &[ImmutPath]) {
+        self.0.evict_files(paths)
+    }
+
+    #[inline]
+    fn evict_stale_files(&mut self, age: std::time::Duration) {
+        self.0.evict_stale_files(age)
+    }
 }
 
 impl ShadowApi for BoxedCompiler {
@@ -217,4 +237,14 @@ impl ShadowApi for BoxedCompiler {
     fn unmap_shadow(&self, path: &Path) -> FileResult<()> {
         self.0.unmap_shadow(path)
     }
+
+    #[inline]
+    fn edit_shadow(
+        &self,
+        path: &Path,
+        range: std::ops::Range<usize>,
+        text: &str,
+    ) -> FileResult<usize> {
+        self.0.edit_shadow(path, range, text)
+    }
 }
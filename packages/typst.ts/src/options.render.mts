@@ -124,15 +124,33 @@ export interface ManipulateDataOptions {
    * The action to manipulate the data.
    * @description `reset`: reset the data to the initial state.
    * @description `merge`: merge the data to the current state.
+   * @description `stream`: feed the next chunk of a paged artifact, see
+   * {@link LoadStreamOptions}.
    * @default 'reset'
    */
-  action?: 'reset' | 'merge';
+  action?: 'reset' | 'merge' | 'stream';
   /**
    * Opaque data to manipulate the Typst document from server.
    */
   data: Uint8Array;
 }
 
+/**
+ * The options for loading a paged artifact from a stream into the session,
+ * e.g. the body of a `fetch` response.
+ */
+export interface LoadStreamOptions {
+  /**
+   * The chunks of the artifact.
+   */
+  stream: ReadableStream<Uint8Array>;
+  /**
+   * Called whenever more pages are ready to be rendered, with the number of
+   * the ready pages, e.g. to render the first page before the others arrive.
+   */
+  onPages?: (pageCount: number) => void | Promise<void>;
+}
+
 /**
  * The options for rendering a page to a canvas.
  * @property {number} page_off - The page offset to render.
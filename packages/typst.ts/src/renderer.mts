@@ -18,6 +18,7 @@ import {
   RenderCanvasOptions,
   RenderToSvgOptions,
   ManipulateDataOptions,
+  LoadStreamOptions,
   RenderSvgOptions,
   RenderInSessionOptions,
   MountDomOptions,
@@ -207,6 +208,16 @@ export class RenderSession {
     });
   }
 
+  /**
+   * See {@link TypstRenderer#loadStream} for more details.
+   */
+  loadStream(opts: LoadStreamOptions): Promise<void> {
+    return this.plugin.loadStream({
+      renderSession: this,
+      ...opts,
+    });
+  }
+
   /**
    * See {@link TypstRenderer#renderSvgDiff} for more details.
    */
@@ -371,6 +382,29 @@ export interface TypstRenderer extends TypstSvgRenderer {
    */
   manipulateData(opts: RenderInSessionOptions<ManipulateDataOptions>): void;
 
+  /**
+   * Load a paged artifact from a stream into the session, so that the pages
+   * are rendered as soon as they arrive, instead of after the whole artifact
+   * is downloaded.
+   * @param {LoadStreamOptions} opts - The stream and the callback on the
+   * arrival of pages.
+   * @returns {Promise<void>} - Resolved when the whole artifact is loaded.
+   *
+   * @example
+   * render the first page as soon as it arrives.
+   * ```typescript
+   * const response = await fetch('main.artifact.sir.in');
+   * await renderer.loadStream({
+   *   renderSession: session,
+   *   stream: response.body!,
+   *   onPages: async () => {
+   *     await renderer.renderToCanvas({ renderSession: session, container });
+   *   },
+   * });
+   * ```
+   */
+  loadStream(opts: RenderInSessionOptions<LoadStreamOptions>): Promise<void>;
+
   /**
    * Run a function with a session, and the sesssion is only available during
    * the function call.
@@ -955,6 +989,25 @@ export class TypstRendererDriver {
     );
   }
 
+  async loadStream(opts: RenderInSessionOptions<LoadStreamOptions>): Promise<void> {
+    const session = (opts.renderSession as any)[kObject] as typst.RenderSession;
+    const reader = opts.stream.getReader();
+    let pageCount = 0;
+    for (;;) {
+      const { done, value } = await reader.read();
+      if (done) {
+        break;
+      }
+      this.renderer.manipulate_data(session, 'stream', value);
+
+      const readyCount = session.pages_info.page_count;
+      if (readyCount > pageCount) {
+        pageCount = readyCount;
+        await opts.onPages?.(pageCount);
+      }
+    }
+  }
+
   private withinOptionSession<T>(
     options: RenderOptions<any>,
     fn: (session: RenderSession) => Promise<T>,
@@ -1,11 +1,10 @@
 use std::path::Path;
 
 use typst_ts_compiler::{
-    service::{CompileDriver, CompileExporter, Compiler},
-    ShadowApiExt, TypstSystemWorld,
+    service::{CompileDriver, CompileDriverBuilder, CompileExporter, Compiler},
+    ShadowApiExt,
 };
 use typst_ts_core::{
-    config::{compiler::EntryOpts, CompileOpts},
     exporter_builtins::GroupExporter,
     vector::{
         incr::{IncrDocClient, IncrDocServer},
@@ -24,15 +23,13 @@ fn get_driver(
     let project_base = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
     let w = project_base.join("fonts");
     let font_path = project_base.join("assets/fonts");
-    let world = TypstSystemWorld::new(CompileOpts {
-        entry: EntryOpts::new_workspace(workspace_dir.into()),
-        no_system_fonts: true,
-        font_paths: vec![w, font_path],
-        ..CompileOpts::default()
-    })
-    .unwrap();
-
-    let driver = CompileDriver::new(world).with_entry_file(entry_file_path.to_owned());
+    let driver = CompileDriverBuilder::new()
+        .root(workspace_dir)
+        .entry(entry_file_path)
+        .with_system_fonts(false)
+        .font_paths([w, font_path])
+        .build_driver()
+        .unwrap();
     CompileExporter::new(driver).with_exporter(exporter)
 }
 